@@ -11,11 +11,14 @@ use std::fs::File;
 use std::os::unix::net::UnixListener;
 
 use uffd_utils::{Runtime, UffdHandler};
+use vmm::vmm_config::snapshot::MemFileKey;
 
 fn main() {
     let mut args = std::env::args();
     let uffd_sock_path = args.nth(1).expect("No socket path given");
     let mem_file_path = args.next().expect("No memory file given");
+    // Key to decrypt an encrypted memory file.
+    let mem_file_key = args.next().map(MemFileKey);
 
     let file = File::open(mem_file_path).expect("Cannot open memfile");
 
@@ -23,7 +26,7 @@ fn main() {
     let listener = UnixListener::bind(uffd_sock_path).expect("Cannot bind to socket path");
     let (stream, _) = listener.accept().expect("Cannot listen on UDS socket");
 
-    let mut runtime = Runtime::new(stream, file);
+    let mut runtime = Runtime::new(stream, file, mem_file_key);
     runtime.install_panic_hook();
     runtime.run(|uffd_handler: &mut UffdHandler| {
         // !DISCLAIMER!
@@ -12,6 +12,8 @@
 use std::collections::{HashMap, HashSet};
 use std::ffi::c_void;
 use std::fs::File;
+use std::io::BufReader;
+use std::os::unix::fs::FileExt;
 use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
 use std::os::unix::net::UnixStream;
 use std::ptr;
@@ -19,6 +21,8 @@ use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 use userfaultfd::{Error, Event, Uffd};
+use vmm::snapshot::mem_file::{MEM_FILE_MAGIC, MemFileDecoder};
+use vmm::vmm_config::snapshot::MemFileKey;
 use vmm_sys_util::sock_ctrl_msg::ScmSocket;
 
 // This is the same with the one used in src/vmm.
@@ -213,7 +217,27 @@ pub struct Runtime {
 }
 
 impl Runtime {
-    pub fn new(stream: UnixStream, backing_file: File) -> Self {
+    /// Creates a runtime serving the guest memory of `backing_file`. Encoded memory files are
+    /// decoded in memory first, which requires `key` if they are encrypted.
+    pub fn new(stream: UnixStream, backing_file: File, key: Option<MemFileKey>) -> Self {
+        let mut magic = [0u8; MEM_FILE_MAGIC.len()];
+        let encoded = backing_file.read_exact_at(&mut magic, 0).is_ok() && magic == MEM_FILE_MAGIC;
+        let (backing_memory, backing_memory_size) = if encoded {
+            Self::decode_backing_file(&backing_file, key.as_ref())
+        } else {
+            Self::map_backing_file(&backing_file)
+        };
+
+        Self {
+            stream,
+            backing_file,
+            backing_memory,
+            backing_memory_size,
+            uffds: HashMap::default(),
+        }
+    }
+
+    fn map_backing_file(backing_file: &File) -> (*mut u8, usize) {
         let file_meta = backing_file
             .metadata()
             .expect("can not get backing file metadata");
@@ -233,14 +257,34 @@ impl Runtime {
         if ret == libc::MAP_FAILED {
             panic!("mmap on backing file failed");
         }
+        (ret.cast(), backing_memory_size)
+    }
 
-        Self {
-            stream,
-            backing_file,
-            backing_memory: ret.cast(),
-            backing_memory_size,
-            uffds: HashMap::default(),
+    fn decode_backing_file(backing_file: &File, key: Option<&MemFileKey>) -> (*mut u8, usize) {
+        let mut decoder = MemFileDecoder::new(BufReader::new(backing_file), key)
+            .expect("Cannot read encoded memfile header");
+        let backing_memory_size = decoder.header().mem_size as usize;
+        let ret = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                backing_memory_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ret == libc::MAP_FAILED {
+            panic!("mmap of decoded memory failed");
         }
+        // # Safety:
+        // The mapping is valid and of `backing_memory_size` bytes
+        let backing_memory =
+            unsafe { std::slice::from_raw_parts_mut(ret.cast::<u8>(), backing_memory_size) };
+        decoder
+            .read_exact(backing_memory)
+            .expect("Cannot decode memfile");
+        (ret.cast(), backing_memory_size)
     }
 
     fn peer_process_credentials(&self) -> libc::ucred {
@@ -371,7 +415,7 @@ mod tests {
                 UnixListener::bind(dummy_socket_path).expect("Cannot bind to socket path");
             let (stream, _) = listener.accept().expect("Cannot listen on UDS socket");
             // Update runtime with actual runtime
-            let runtime = uninit_runtime.write(Runtime::new(stream, file));
+            let runtime = uninit_runtime.write(Runtime::new(stream, file, None));
             runtime.run(|_: &mut UffdHandler| {});
         });
 
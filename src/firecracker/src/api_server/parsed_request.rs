@@ -3,7 +3,7 @@
 
 use std::fmt::Debug;
 
-use micro_http::{Body, Method, Request, Response, StatusCode, Version};
+use micro_http::{Body, MediaType, Method, Request, Response, StatusCode, Version};
 use serde::ser::Serialize;
 use serde_json::Value;
 use vmm::logger::{Level, error, info, log_enabled};
@@ -16,14 +16,19 @@ use super::request::boot_source::parse_put_boot_source;
 use super::request::cpu_configuration::parse_put_cpu_config;
 use super::request::drive::{parse_patch_drive, parse_put_drive};
 use super::request::entropy::parse_put_entropy;
+use super::request::hotplug::{parse_get_hotplug, parse_patch_hotplug, parse_put_hotplug};
 use super::request::instance_info::parse_get_instance_info;
 use super::request::logger::parse_put_logger;
 use super::request::machine_configuration::{
     parse_get_machine_config, parse_patch_machine_config, parse_put_machine_config,
 };
-use super::request::metrics::parse_put_metrics;
+use super::request::metrics::{parse_get_metrics, parse_put_metrics};
+use super::request::migration::{parse_get_migration, parse_put_migration};
 use super::request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
 use super::request::net::{parse_patch_net, parse_put_net};
+use super::request::rate_limiter_group::{
+    parse_patch_rate_limiter_group, parse_put_rate_limiter_group,
+};
 use super::request::snapshot::{parse_patch_vm_state, parse_put_snapshot};
 use super::request::version::parse_get_version;
 use super::request::vsock::parse_put_vsock;
@@ -78,11 +83,14 @@ impl TryFrom<&Request> for ParsedRequest {
         match (request.method(), path, request.body.as_ref()) {
             (Method::Get, "", None) => parse_get_instance_info(),
             (Method::Get, "balloon", None) => parse_get_balloon(path_tokens.next()),
+            (Method::Get, "hotplug", None) => parse_get_hotplug(path_tokens.next()),
             (Method::Get, "version", None) => parse_get_version(),
             (Method::Get, "vm", None) if path_tokens.next() == Some("config") => {
                 Ok(ParsedRequest::new_sync(VmmAction::GetFullVmConfig))
             }
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
+            (Method::Get, "metrics", None) => parse_get_metrics(),
+            (Method::Get, "migration", None) => parse_get_migration(path_tokens.next()),
             (Method::Get, "mmds", None) => parse_get_mmds(),
             (Method::Get, _, Some(_)) => method_to_error(Method::Get),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
@@ -90,25 +98,34 @@ impl TryFrom<&Request> for ParsedRequest {
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
             (Method::Put, "cpu-config", Some(body)) => parse_put_cpu_config(body),
             (Method::Put, "drives", Some(body)) => parse_put_drive(body, path_tokens.next()),
+            (Method::Put, "hotplug", Some(body)) => parse_put_hotplug(body, path_tokens.next()),
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
             (Method::Put, "serial", Some(body)) => parse_put_serial(body),
             (Method::Put, "machine-config", Some(body)) => parse_put_machine_config(body),
             (Method::Put, "metrics", Some(body)) => parse_put_metrics(body),
+            (Method::Put, "migration", Some(body)) => parse_put_migration(body, path_tokens.next()),
             (Method::Put, "mmds", Some(body)) => parse_put_mmds(body, path_tokens.next()),
             (Method::Put, "network-interfaces", Some(body)) => {
                 parse_put_net(body, path_tokens.next())
             }
+            (Method::Put, "rate-limiter-groups", Some(body)) => {
+                parse_put_rate_limiter_group(body, path_tokens.next())
+            }
             (Method::Put, "snapshot", Some(body)) => parse_put_snapshot(body, path_tokens.next()),
             (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
             (Method::Put, "entropy", Some(body)) => parse_put_entropy(body),
             (Method::Put, _, None) => method_to_error(Method::Put),
             (Method::Patch, "balloon", Some(body)) => parse_patch_balloon(body, path_tokens.next()),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.next()),
+            (Method::Patch, "hotplug", Some(body)) => parse_patch_hotplug(body, path_tokens.next()),
             (Method::Patch, "machine-config", Some(body)) => parse_patch_machine_config(body),
             (Method::Patch, "mmds", Some(body)) => parse_patch_mmds(body),
             (Method::Patch, "network-interfaces", Some(body)) => {
                 parse_patch_net(body, path_tokens.next())
             }
+            (Method::Patch, "rate-limiter-groups", Some(body)) => {
+                parse_patch_rate_limiter_group(body, path_tokens.next())
+            }
             (Method::Patch, "vm", Some(body)) => parse_patch_vm_state(body),
             (Method::Patch, _, None) => method_to_error(Method::Patch),
             (method, unknown_uri, _) => Err(RequestError::InvalidPathMethod(
@@ -156,6 +173,14 @@ impl ParsedRequest {
         response
     }
 
+    pub(crate) fn success_response_with_text(body_data: &str) -> Response {
+        info!("The request was executed successfully. Status code: 200 OK.");
+        let mut response = Response::new(Version::Http11, StatusCode::OK);
+        response.set_content_type(MediaType::PlainText);
+        response.set_body(Body::new(body_data));
+        response
+    }
+
     pub(crate) fn convert_to_response(
         request_outcome: &std::result::Result<VmmData, VmmActionError>,
     ) -> Response {
@@ -173,11 +198,15 @@ impl ParsedRequest {
                     Self::success_response_with_data(balloon_config)
                 }
                 VmmData::BalloonStats(stats) => Self::success_response_with_data(stats),
+                VmmData::BalloonHintingStatus(status) => Self::success_response_with_data(status),
                 VmmData::InstanceInformation(info) => Self::success_response_with_data(info),
                 VmmData::VmmVersion(version) => Self::success_response_with_data(
                     &serde_json::json!({ "firecracker_version": version.as_str() }),
                 ),
                 VmmData::FullVmConfig(config) => Self::success_response_with_data(config),
+                VmmData::MemoryHotplugStatus(status) => Self::success_response_with_data(status),
+                VmmData::Metrics(text) => Self::success_response_with_text(text),
+                VmmData::MigrationStatus(status) => Self::success_response_with_data(status),
             },
             Err(vmm_action_error) => {
                 let mut response = match vmm_action_error {
@@ -220,6 +249,10 @@ impl ParsedRequest {
 fn describe(method: Method, path: &str, body: Option<&Body>) -> String {
     match (path, body) {
         ("/mmds", Some(_)) | (_, None) => format!("{:?} request on {:?}", method, path),
+        // Snapshot requests may carry the encryption key of the memory file.
+        (_, Some(_)) if path.trim_start_matches('/').split('/').next() == Some("snapshot") => {
+            format!("{:?} request on {:?}", method, path)
+        }
         ("/cpu-config", Some(payload_value)) => {
             // If the log level is at Debug or higher, include the CPU template in
             // the log line.
@@ -325,9 +358,11 @@ pub mod tests {
     use vmm::cpu_config::templates::test_utils::build_test_template;
     use vmm::resources::VmmConfig;
     use vmm::rpc_interface::VmmActionError;
-    use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats};
+    use vmm::vmm_config::balloon::{BalloonDeviceConfig, BalloonStats, HintingStatus};
     use vmm::vmm_config::instance_info::InstanceInfo;
     use vmm::vmm_config::machine_config::MachineConfig;
+    use vmm::vmm_config::memory_hotplug::MemoryHotplugStatus;
+    use vmm::vmm_config::migration::MigrationStatus;
 
     use super::*;
 
@@ -543,6 +578,26 @@ pub mod tests {
             describe(Method::Put, "path", Some(&Body::new("body"))),
             "Put request on \"path\" with body \"body\""
         );
+
+        // The encryption key of snapshot requests is never described.
+        let key = "00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+        let bodies = [
+            format!(
+                "{{\"snapshot_path\": \"snap\", \"mem_file_path\": \"mem\", \
+                 \"mem_file_encoding\": {{\"encryption_key\": \"{key}\"}}}}"
+            ),
+            format!(
+                "{{\"snapshot_path\": \"snap\", \"mem_backend\": {{\"backend_path\": \"mem\", \
+                 \"backend_type\": \"File\"}}, \"mem_file_encryption_key\": \"{key}\"}}"
+            ),
+        ];
+        for path in ["/snapshot/create", "/snapshot/load", "//snapshot/load/"] {
+            for body in &bodies {
+                let description = describe(Method::Put, path, Some(&Body::new(body.as_str())));
+                assert_eq!(description, format!("Put request on {path:?}"));
+                assert!(!description.contains(key));
+            }
+        }
     }
 
     #[test]
@@ -557,6 +612,9 @@ pub mod tests {
                 VmmData::BalloonStats(stats) => {
                     http_response(&serde_json::to_string(stats).unwrap(), 200)
                 }
+                VmmData::BalloonHintingStatus(status) => {
+                    http_response(&serde_json::to_string(status).unwrap(), 200)
+                }
                 VmmData::Empty => http_response("", 204),
                 VmmData::FullVmConfig(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
@@ -564,6 +622,15 @@ pub mod tests {
                 VmmData::MachineConfiguration(cfg) => {
                     http_response(&serde_json::to_string(cfg).unwrap(), 200)
                 }
+                VmmData::MemoryHotplugStatus(status) => {
+                    http_response(&serde_json::to_string(status).unwrap(), 200)
+                }
+                VmmData::Metrics(text) => {
+                    http_response(text, 200).replace("application/json", "text/plain")
+                }
+                VmmData::MigrationStatus(status) => {
+                    http_response(&serde_json::to_string(status).unwrap(), 200)
+                }
                 VmmData::MmdsValue(value) => {
                     http_response(&serde_json::to_string(value).unwrap(), 200)
                 }
@@ -586,9 +653,20 @@ pub mod tests {
             swap_out: Some(1),
             ..Default::default()
         }));
+        verify_ok_response_with(VmmData::BalloonHintingStatus(HintingStatus::default()));
         verify_ok_response_with(VmmData::Empty);
         verify_ok_response_with(VmmData::FullVmConfig(VmmConfig::default()));
         verify_ok_response_with(VmmData::MachineConfiguration(MachineConfig::default()));
+        verify_ok_response_with(VmmData::MemoryHotplugStatus(MemoryHotplugStatus {
+            total_size_mib: 1024,
+            block_size_mib: 2,
+            plugged_size_mib: 256,
+            requested_size_mib: 512,
+        }));
+        verify_ok_response_with(VmmData::Metrics("# EOF\n".to_string()));
+        verify_ok_response_with(VmmData::MigrationStatus(MigrationStatus::Failed {
+            error: "The destination failed to restore the microVM.".to_string(),
+        }));
         verify_ok_response_with(VmmData::MmdsValue(serde_json::from_str("{}").unwrap()));
         verify_ok_response_with(VmmData::InstanceInformation(InstanceInfo::default()));
         verify_ok_response_with(VmmData::VmmVersion(String::default()));
@@ -640,6 +718,18 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_get_hotplug_memory() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/hotplug/memory", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_get_machine_config() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -664,6 +754,18 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_get_metrics() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/metrics", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_get_version() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -703,6 +805,32 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_put_hotplug_memory() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"total_size_mib\": 1024, \"block_size_mib\": 2 }";
+        sender
+            .write_all(http_request("PUT", "/hotplug/memory", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_hotplug_unplug() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"iface_id\": \"eth1\" }";
+        sender
+            .write_all(http_request("PUT", "/hotplug/unplug", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_put_entropy() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -871,6 +999,38 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_get_migration() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(http_request("GET", "/migration", None).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_put_migration() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"socket\": { \"socket_type\": \"Unix\", \"address\": \"foo\" } }";
+        sender
+            .write_all(http_request("PUT", "/migration/send", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+
+        sender
+            .write_all(http_request("PUT", "/migration/receive", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_patch_vm() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -915,6 +1075,39 @@ pub mod tests {
         connection.try_read().unwrap();
         let req = connection.pop_parsed_request().unwrap();
         ParsedRequest::try_from(&req).unwrap();
+        let body = "{ \"action\": \"start\" }";
+        sender
+            .write_all(http_request("PATCH", "/balloon/hinting", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_patch_hotplug_memory() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"requested_size_mib\": 512 }";
+        sender
+            .write_all(http_request("PATCH", "/hotplug/memory", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
+    #[test]
+    fn test_try_from_patch_hotplug_vcpus() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"vcpu_count\": 4 }";
+        sender
+            .write_all(http_request("PATCH", "/hotplug/vcpus", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
     }
 
     #[test]
@@ -987,6 +1180,26 @@ pub mod tests {
         ParsedRequest::try_from(&req).unwrap();
     }
 
+    #[test]
+    fn test_try_from_rate_limiter_groups() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        let body = "{ \"group_id\": \"string\" }";
+        sender
+            .write_all(http_request("PUT", "/rate-limiter-groups/string", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+
+        sender
+            .write_all(http_request("PATCH", "/rate-limiter-groups/string", Some(body)).as_bytes())
+            .unwrap();
+        connection.try_read().unwrap();
+        let req = connection.pop_parsed_request().unwrap();
+        ParsedRequest::try_from(&req).unwrap();
+    }
+
     #[test]
     fn test_try_from_patch_netif() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
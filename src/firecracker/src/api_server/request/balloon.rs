@@ -4,7 +4,7 @@
 use micro_http::StatusCode;
 use vmm::rpc_interface::VmmAction;
 use vmm::vmm_config::balloon::{
-    BalloonDeviceConfig, BalloonUpdateConfig, BalloonUpdateStatsConfig,
+    BalloonDeviceConfig, BalloonHintingUpdate, BalloonUpdateConfig, BalloonUpdateStatsConfig,
 };
 
 use super::super::parsed_request::{ParsedRequest, RequestError};
@@ -16,6 +16,7 @@ pub(crate) fn parse_get_balloon(
     match path_second_token {
         Some(stats_path) => match stats_path {
             "statistics" => Ok(ParsedRequest::new_sync(VmmAction::GetBalloonStats)),
+            "hinting" => Ok(ParsedRequest::new_sync(VmmAction::GetBalloonHintingStatus)),
             _ => Err(RequestError::Generic(
                 StatusCode::BadRequest,
                 format!("Unrecognized GET request path `{}`.", stats_path),
@@ -40,6 +41,9 @@ pub(crate) fn parse_patch_balloon(
             "statistics" => Ok(ParsedRequest::new_sync(VmmAction::UpdateBalloonStatistics(
                 serde_json::from_slice::<BalloonUpdateStatsConfig>(body.raw())?,
             ))),
+            "hinting" => Ok(ParsedRequest::new_sync(VmmAction::UpdateBalloonHinting(
+                serde_json::from_slice::<BalloonHintingUpdate>(body.raw())?,
+            ))),
             _ => Err(RequestError::Generic(
                 StatusCode::BadRequest,
                 format!("Unrecognized PATCH request path `{}`.", config_path),
@@ -53,6 +57,8 @@ pub(crate) fn parse_patch_balloon(
 
 #[cfg(test)]
 mod tests {
+    use vmm::vmm_config::balloon::BalloonHintingAction;
+
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
 
@@ -63,6 +69,8 @@ mod tests {
         parse_get_balloon(Some("unrelated")).unwrap_err();
 
         parse_get_balloon(Some("statistics")).unwrap();
+
+        parse_get_balloon(Some("hinting")).unwrap();
     }
 
     #[test]
@@ -147,6 +155,25 @@ mod tests {
             ),
             VmmAction::UpdateBalloonStatistics(expected_config)
         );
+
+        // PATCH with an unknown free page hinting action.
+        let body = r#"{
+            "action": "pause"
+        }"#;
+        parse_patch_balloon(&Body::new(body), Some("hinting")).unwrap_err();
+
+        let body = r#"{
+            "action": "stop"
+        }"#;
+        let expected_config = BalloonHintingUpdate {
+            action: BalloonHintingAction::Stop,
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_patch_balloon(&Body::new(body), Some("hinting")).unwrap()
+            ),
+            VmmAction::UpdateBalloonHinting(expected_config)
+        );
     }
 
     #[test]
@@ -167,5 +194,18 @@ mod tests {
             "stats_polling_interval_s": 0
         }"#;
         parse_put_balloon(&Body::new(body)).unwrap();
+
+        // PUT with a policy.
+        let body = r#"{
+            "amount_mib": 0,
+            "deflate_on_oom": true,
+            "stats_polling_interval_s": 1,
+            "policy": {
+                "target_free_mib": 128,
+                "max_mib": 1024,
+                "hysteresis_mib": 32
+            }
+        }"#;
+        parse_put_balloon(&Body::new(body)).unwrap();
     }
 }
@@ -229,6 +229,12 @@ mod tests {
             "is_read_only": true,
             "cache_type": "Unsafe",
             "io_engine": "Sync",
+            "format": "Qcow2",
+            "discard": {
+                "max_discard_sectors": 128,
+                "max_write_zeroes_sectors": 128,
+                "discard_sector_alignment": 8
+            },
             "rate_limiter": {
                 "bandwidth": {
                     "size": 0,
@@ -0,0 +1,199 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use micro_http::StatusCode;
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::device_hotplug::DeviceUnplugConfig;
+use vmm::vmm_config::machine_config::VcpuHotplugUpdate;
+use vmm::vmm_config::memory_hotplug::{MemoryHotplugConfig, MemoryHotplugSizeUpdate};
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::Body;
+
+fn check_hotplug_path<'a>(
+    method: &str,
+    path_second_token: Option<&'a str>,
+    resources: &[&str],
+) -> Result<&'a str, RequestError> {
+    match path_second_token {
+        Some(resource) if resources.contains(&resource) => Ok(resource),
+        Some(unknown_path) => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized {} request path `{}`.", method, unknown_path),
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Missing hotplug resource type.".to_string(),
+        )),
+    }
+}
+
+pub(crate) fn parse_get_hotplug(
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    check_hotplug_path("GET", path_second_token, &["memory"])?;
+    Ok(ParsedRequest::new_sync(VmmAction::GetMemoryHotplugStatus))
+}
+
+pub(crate) fn parse_put_hotplug(
+    body: &Body,
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let action = match check_hotplug_path("PUT", path_second_token, &["memory", "unplug"])? {
+        "unplug" => {
+            VmmAction::UnplugDevice(serde_json::from_slice::<DeviceUnplugConfig>(body.raw())?)
+        }
+        _ => VmmAction::SetMemoryHotplugDevice(serde_json::from_slice::<MemoryHotplugConfig>(
+            body.raw(),
+        )?),
+    };
+    Ok(ParsedRequest::new_sync(action))
+}
+
+pub(crate) fn parse_patch_hotplug(
+    body: &Body,
+    path_second_token: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    let action = match check_hotplug_path("PATCH", path_second_token, &["memory", "vcpus"])? {
+        "vcpus" => {
+            VmmAction::HotplugVcpus(serde_json::from_slice::<VcpuHotplugUpdate>(body.raw())?)
+        }
+        _ => VmmAction::UpdateMemoryHotplugSize(serde_json::from_slice::<MemoryHotplugSizeUpdate>(
+            body.raw(),
+        )?),
+    };
+    Ok(ParsedRequest::new_sync(action))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_server::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_get_hotplug_request() {
+        parse_get_hotplug(None).unwrap_err();
+        parse_get_hotplug(Some("cpu")).unwrap_err();
+        parse_get_hotplug(Some("vcpus")).unwrap_err();
+
+        assert_eq!(
+            vmm_action_from_request(parse_get_hotplug(Some("memory")).unwrap()),
+            VmmAction::GetMemoryHotplugStatus
+        );
+    }
+
+    #[test]
+    fn test_parse_put_hotplug_request() {
+        let body = r#"{
+            "total_size_mib": 2048
+        }"#;
+        parse_put_hotplug(&Body::new(body), None).unwrap_err();
+        parse_put_hotplug(&Body::new(body), Some("cpu")).unwrap_err();
+        parse_put_hotplug(&Body::new(body), Some("vcpus")).unwrap_err();
+        parse_put_hotplug(&Body::new("invalid_payload"), Some("memory")).unwrap_err();
+
+        // PUT with unknown fields.
+        let body = r#"{
+            "total_size_mib": 2048,
+            "foo": "bar"
+        }"#;
+        parse_put_hotplug(&Body::new(body), Some("memory")).unwrap_err();
+
+        let body = r#"{
+            "total_size_mib": 2048,
+            "block_size_mib": 128
+        }"#;
+        let expected_config = MemoryHotplugConfig {
+            total_size_mib: 2048,
+            block_size_mib: 128,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_hotplug(&Body::new(body), Some("memory")).unwrap()),
+            VmmAction::SetMemoryHotplugDevice(expected_config)
+        );
+    }
+
+    #[test]
+    fn test_parse_put_hotplug_unplug_request() {
+        parse_put_hotplug(&Body::new("invalid_payload"), Some("unplug")).unwrap_err();
+
+        // PUT with unknown fields.
+        let body = r#"{
+            "drive_id": "scratch",
+            "foo": "bar"
+        }"#;
+        parse_put_hotplug(&Body::new(body), Some("unplug")).unwrap_err();
+
+        let body = r#"{
+            "drive_id": "scratch"
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(parse_put_hotplug(&Body::new(body), Some("unplug")).unwrap()),
+            VmmAction::UnplugDevice(DeviceUnplugConfig {
+                drive_id: Some("scratch".to_string()),
+                iface_id: None,
+            })
+        );
+
+        let body = r#"{
+            "iface_id": "eth1"
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(parse_put_hotplug(&Body::new(body), Some("unplug")).unwrap()),
+            VmmAction::UnplugDevice(DeviceUnplugConfig {
+                drive_id: None,
+                iface_id: Some("eth1".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_patch_hotplug_request() {
+        let body = r#"{
+            "requested_size_mib": 512
+        }"#;
+        parse_patch_hotplug(&Body::new(body), None).unwrap_err();
+        parse_patch_hotplug(&Body::new(body), Some("cpu")).unwrap_err();
+
+        // PATCH with invalid types on fields.
+        let body = r#"{
+            "requested_size_mib": -512
+        }"#;
+        parse_patch_hotplug(&Body::new(body), Some("memory")).unwrap_err();
+
+        let body = r#"{
+            "requested_size_mib": 512
+        }"#;
+        let expected_config = MemoryHotplugSizeUpdate {
+            requested_size_mib: 512,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_patch_hotplug(&Body::new(body), Some("memory")).unwrap()),
+            VmmAction::UpdateMemoryHotplugSize(expected_config)
+        );
+    }
+
+    #[test]
+    fn test_parse_patch_hotplug_vcpus_request() {
+        // PATCH with unknown fields.
+        let body = r#"{
+            "vcpu_count": 4,
+            "foo": "bar"
+        }"#;
+        parse_patch_hotplug(&Body::new(body), Some("vcpus")).unwrap_err();
+
+        // PATCH with invalid types on fields.
+        let body = r#"{
+            "vcpu_count": 256
+        }"#;
+        parse_patch_hotplug(&Body::new(body), Some("vcpus")).unwrap_err();
+
+        let body = r#"{
+            "vcpu_count": 4
+        }"#;
+        assert_eq!(
+            vmm_action_from_request(parse_patch_hotplug(&Body::new(body), Some("vcpus")).unwrap()),
+            VmmAction::HotplugVcpus(VcpuHotplugUpdate { vcpu_count: 4 })
+        );
+    }
+}
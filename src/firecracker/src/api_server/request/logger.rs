@@ -20,7 +20,7 @@ pub(crate) fn parse_put_logger(body: &Body) -> Result<ParsedRequest, RequestErro
 mod tests {
     use std::path::PathBuf;
 
-    use vmm::logger::{LevelFilter, LoggerConfig};
+    use vmm::logger::{LevelFilter, LogOutputFormat, LoggerConfig, ModuleLevel};
 
     use super::*;
     use crate::api_server::parsed_request::tests::vmm_action_from_request;
@@ -40,6 +40,8 @@ mod tests {
             show_level: Some(false),
             show_log_origin: Some(false),
             module: None,
+            format: None,
+            module_levels: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_logger(&Body::new(body)).unwrap()),
@@ -59,6 +61,32 @@ mod tests {
             show_level: Some(false),
             show_log_origin: Some(false),
             module: None,
+            format: None,
+            module_levels: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_logger(&Body::new(body)).unwrap()),
+            VmmAction::ConfigureLogger(expected_config)
+        );
+
+        let body = r#"{
+                "format": "json",
+                "module_levels": [
+                    { "module": "vmm::devices", "level": "debug" }
+                ]
+              }"#;
+
+        let expected_config = LoggerConfig {
+            log_path: None,
+            level: None,
+            show_level: None,
+            show_log_origin: None,
+            module: None,
+            format: Some(LogOutputFormat::Json),
+            module_levels: Some(vec![ModuleLevel {
+                module: String::from("vmm::devices"),
+                level: LevelFilter::Debug,
+            }]),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_logger(&Body::new(body)).unwrap()),
@@ -118,6 +118,7 @@ mod tests {
             );
             let expected_config = MachineConfigUpdate {
                 vcpu_count: Some(8),
+                max_vcpus: None,
                 mem_size_mib: Some(1024),
                 smt: Some(false),
                 cpu_template: None,
@@ -139,6 +140,7 @@ mod tests {
         }"#;
         let expected_config = MachineConfigUpdate {
             vcpu_count: Some(8),
+            max_vcpus: None,
             mem_size_mib: Some(1024),
             smt: Some(false),
             cpu_template: Some(StaticCpuTemplate::None),
@@ -160,6 +162,7 @@ mod tests {
         }"#;
         let expected_config = MachineConfigUpdate {
             vcpu_count: Some(8),
+            max_vcpus: None,
             mem_size_mib: Some(1024),
             smt: Some(false),
             cpu_template: None,
@@ -185,6 +188,7 @@ mod tests {
         {
             let expected_config = MachineConfigUpdate {
                 vcpu_count: Some(8),
+                max_vcpus: None,
                 mem_size_mib: Some(1024),
                 smt: Some(false),
                 cpu_template: Some(StaticCpuTemplate::T2),
@@ -212,6 +216,7 @@ mod tests {
         }"#;
         let expected_config = MachineConfigUpdate {
             vcpu_count: Some(8),
+            max_vcpus: None,
             mem_size_mib: Some(1024),
             smt: Some(true),
             cpu_template: None,
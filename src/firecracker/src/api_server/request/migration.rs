@@ -0,0 +1,188 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::migration::{ReceiveMigrationParams, SendMigrationParams};
+
+use super::super::parsed_request::{ParsedRequest, RequestError};
+use super::super::request::{Body, Method, StatusCode};
+
+pub(crate) fn parse_get_migration(
+    request_type_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match request_type_from_path {
+        None => Ok(ParsedRequest::new_sync(VmmAction::GetMigrationStatus)),
+        Some(request_type) => Err(RequestError::InvalidPathMethod(
+            format!("/migration/{}", request_type),
+            Method::Get,
+        )),
+    }
+}
+
+pub(crate) fn parse_put_migration(
+    body: &Body,
+    request_type_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    match request_type_from_path {
+        Some("send") => {
+            let params = serde_json::from_slice::<SendMigrationParams>(body.raw())?;
+            Ok(ParsedRequest::new_sync(VmmAction::SendMigration(params)))
+        }
+        Some("receive") => {
+            let params = serde_json::from_slice::<ReceiveMigrationParams>(body.raw())?;
+            Ok(ParsedRequest::new_sync(VmmAction::ReceiveMigration(params)))
+        }
+        Some(request_type) => Err(RequestError::InvalidPathMethod(
+            format!("/migration/{}", request_type),
+            Method::Put,
+        )),
+        None => Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            "Missing migration operation type.".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::vmm_config::migration::{
+        DEFAULT_DIRTY_THRESHOLD_MIB, DEFAULT_MAX_ITERATIONS, MigrationSocketConfig,
+        MigrationSocketType,
+    };
+
+    use super::*;
+    use crate::api_server::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_get_migration() {
+        assert_eq!(
+            vmm_action_from_request(parse_get_migration(None).unwrap()),
+            VmmAction::GetMigrationStatus
+        );
+        parse_get_migration(Some("send")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_migration_send() {
+        let body = r#"{
+            "socket": {
+                "socket_type": "Tcp",
+                "address": "192.168.0.2:4242"
+            }
+        }"#;
+        let expected_params = SendMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Tcp,
+                address: "192.168.0.2:4242".to_string(),
+            },
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            dirty_threshold_mib: DEFAULT_DIRTY_THRESHOLD_MIB,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_migration(&Body::new(body), Some("send")).unwrap()),
+            VmmAction::SendMigration(expected_params)
+        );
+
+        let body = r#"{
+            "socket": {
+                "socket_type": "Unix",
+                "address": "/tmp/migration.sock"
+            },
+            "max_iterations": 3,
+            "dirty_threshold_mib": 64
+        }"#;
+        let expected_params = SendMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Unix,
+                address: "/tmp/migration.sock".to_string(),
+            },
+            max_iterations: 3,
+            dirty_threshold_mib: 64,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_migration(&Body::new(body), Some("send")).unwrap()),
+            VmmAction::SendMigration(expected_params)
+        );
+
+        // Unknown fields and socket types are rejected.
+        let body = r#"{
+            "socket": {
+                "socket_type": "Unix",
+                "address": "/tmp/migration.sock"
+            },
+            "resume_vm": true
+        }"#;
+        parse_put_migration(&Body::new(body), Some("send")).unwrap_err();
+        let body = r#"{
+            "socket": {
+                "socket_type": "Vsock",
+                "address": "3:4242"
+            }
+        }"#;
+        parse_put_migration(&Body::new(body), Some("send")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_migration_receive() {
+        let body = r#"{
+            "socket": {
+                "socket_type": "Unix",
+                "address": "/tmp/migration.sock"
+            }
+        }"#;
+        let expected_params = ReceiveMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Unix,
+                address: "/tmp/migration.sock".to_string(),
+            },
+            track_dirty_pages: false,
+            resume_vm: false,
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_migration(&Body::new(body), Some("receive")).unwrap()
+            ),
+            VmmAction::ReceiveMigration(expected_params)
+        );
+
+        let body = r#"{
+            "socket": {
+                "socket_type": "Tcp",
+                "address": "0.0.0.0:4242"
+            },
+            "track_dirty_pages": true,
+            "resume_vm": true
+        }"#;
+        let expected_params = ReceiveMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Tcp,
+                address: "0.0.0.0:4242".to_string(),
+            },
+            track_dirty_pages: true,
+            resume_vm: true,
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_migration(&Body::new(body), Some("receive")).unwrap()
+            ),
+            VmmAction::ReceiveMigration(expected_params)
+        );
+
+        let body = r#"{
+            "track_dirty_pages": true
+        }"#;
+        parse_put_migration(&Body::new(body), Some("receive")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_put_migration_invalid_path() {
+        let body = r#"{
+            "socket": {
+                "socket_type": "Unix",
+                "address": "/tmp/migration.sock"
+            }
+        }"#;
+        parse_put_migration(&Body::new(body), Some("start")).unwrap_err();
+        parse_put_migration(&Body::new(body), None).unwrap_err();
+    }
+}
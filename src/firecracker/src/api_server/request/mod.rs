@@ -7,12 +7,15 @@ pub mod boot_source;
 pub mod cpu_configuration;
 pub mod drive;
 pub mod entropy;
+pub mod hotplug;
 pub mod instance_info;
 pub mod logger;
 pub mod machine_configuration;
 pub mod metrics;
+pub mod migration;
 pub mod mmds;
 pub mod net;
+pub mod rate_limiter_group;
 pub mod serial;
 pub mod snapshot;
 pub mod version;
@@ -81,7 +81,8 @@ mod tests {
         let body = r#"{
             "iface_id": "foo",
             "host_dev_name": "bar",
-            "guest_mac": "12:34:56:78:9A:BC"
+            "guest_mac": "12:34:56:78:9A:BC",
+            "num_queues": 2
         }"#;
         // 1. Exercise infamous "The id from the path does not match id from the body!".
         parse_put_net(&Body::new(body), Some("bar")).unwrap_err();
@@ -0,0 +1,149 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use vmm::logger::{IncMetric, METRICS};
+use vmm::rpc_interface::VmmAction;
+use vmm::vmm_config::rate_limiter_group::RateLimiterGroupConfig;
+
+use super::super::parsed_request::{ParsedRequest, RequestError, checked_id};
+use super::{Body, StatusCode};
+
+pub(crate) fn parse_put_rate_limiter_group(
+    body: &Body,
+    id_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    METRICS.put_api_requests.rate_limiter_group_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        return Err(RequestError::EmptyID);
+    };
+
+    let group_cfg =
+        serde_json::from_slice::<RateLimiterGroupConfig>(body.raw()).inspect_err(|_| {
+            METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        })?;
+
+    if id != group_cfg.group_id {
+        METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            String::from("The id from the path does not match the id from the body!"),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::SetRateLimiterGroup(
+        group_cfg,
+    )))
+}
+
+pub(crate) fn parse_patch_rate_limiter_group(
+    body: &Body,
+    id_from_path: Option<&str>,
+) -> Result<ParsedRequest, RequestError> {
+    METRICS.patch_api_requests.rate_limiter_group_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.patch_api_requests.rate_limiter_group_fails.inc();
+        return Err(RequestError::EmptyID);
+    };
+
+    let group_cfg =
+        serde_json::from_slice::<RateLimiterGroupConfig>(body.raw()).inspect_err(|_| {
+            METRICS.patch_api_requests.rate_limiter_group_fails.inc();
+        })?;
+
+    if id != group_cfg.group_id {
+        METRICS.patch_api_requests.rate_limiter_group_fails.inc();
+        return Err(RequestError::Generic(
+            StatusCode::BadRequest,
+            String::from("The id from the path does not match the id from the body!"),
+        ));
+    }
+
+    Ok(ParsedRequest::new_sync(VmmAction::UpdateRateLimiterGroup(
+        group_cfg,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm::vmm_config::TokenBucketConfig;
+
+    use super::*;
+    use crate::api_server::parsed_request::tests::vmm_action_from_request;
+
+    #[test]
+    fn test_parse_put_rate_limiter_group_request() {
+        parse_put_rate_limiter_group(&Body::new("invalid_payload"), None).unwrap_err();
+        parse_put_rate_limiter_group(&Body::new("invalid_payload"), Some("id")).unwrap_err();
+
+        let body = r#"{
+            "group_id": "grp0",
+            "bandwidth": {
+                "size": 5000,
+                "refill_time": 100
+            }
+        }"#;
+        // Must fail since the group id differs from id_from_path.
+        parse_put_rate_limiter_group(&Body::new(body), Some("grp1")).unwrap_err();
+
+        let expected_config = RateLimiterGroupConfig {
+            group_id: String::from("grp0"),
+            bandwidth: Some(TokenBucketConfig {
+                size: 5000,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+            ops: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_put_rate_limiter_group(&Body::new(body), Some("grp0")).unwrap()
+            ),
+            VmmAction::SetRateLimiterGroup(expected_config)
+        );
+
+        // Unknown fields are rejected.
+        let body = r#"{
+            "group_id": "grp0",
+            "rate_limiter": {}
+        }"#;
+        parse_put_rate_limiter_group(&Body::new(body), Some("grp0")).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_patch_rate_limiter_group_request() {
+        parse_patch_rate_limiter_group(&Body::new("invalid_payload"), None).unwrap_err();
+        parse_patch_rate_limiter_group(&Body::new("invalid_payload"), Some("id")).unwrap_err();
+
+        let body = r#"{
+            "group_id": "grp0",
+            "ops": {
+                "size": 500,
+                "one_time_burst": 100,
+                "refill_time": 100
+            }
+        }"#;
+        // Must fail since the group id differs from id_from_path.
+        parse_patch_rate_limiter_group(&Body::new(body), Some("grp1")).unwrap_err();
+
+        let expected_config = RateLimiterGroupConfig {
+            group_id: String::from("grp0"),
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: 500,
+                one_time_burst: Some(100),
+                refill_time: 100,
+            }),
+        };
+        assert_eq!(
+            vmm_action_from_request(
+                parse_patch_rate_limiter_group(&Body::new(body), Some("grp0")).unwrap()
+            ),
+            VmmAction::UpdateRateLimiterGroup(expected_config)
+        );
+    }
+}
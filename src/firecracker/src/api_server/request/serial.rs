@@ -30,6 +30,17 @@ mod tests {
 
         let expected_config = SerialConfig {
             serial_out_path: Some(PathBuf::from("serial")),
+            serial_socket_path: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_serial(&Body::new(body)).unwrap()),
+            VmmAction::ConfigureSerial(expected_config)
+        );
+
+        let body = r#"{"serial_socket_path": "serial.sock"}"#;
+        let expected_config = SerialConfig {
+            serial_out_path: None,
+            serial_socket_path: Some(PathBuf::from("serial.sock")),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_serial(&Body::new(body)).unwrap()),
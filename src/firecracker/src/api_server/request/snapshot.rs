@@ -110,6 +110,8 @@ fn parse_put_snapshot_load(body: &Body) -> Result<ParsedRequest, RequestError> {
             || snapshot_config.track_dirty_pages,
         resume_vm: snapshot_config.resume_vm,
         network_overrides: snapshot_config.network_overrides,
+        parent_layers: snapshot_config.parent_layers,
+        mem_file_encryption_key: snapshot_config.mem_file_encryption_key,
     };
 
     // Construct the `ParsedRequest` object.
@@ -125,7 +127,10 @@ fn parse_put_snapshot_load(body: &Body) -> Result<ParsedRequest, RequestError> {
 
 #[cfg(test)]
 mod tests {
-    use vmm::vmm_config::snapshot::{MemBackendConfig, MemBackendType, NetworkOverride};
+    use vmm::vmm_config::snapshot::{
+        MemBackendConfig, MemBackendType, MemFileCompression, MemFileEncoding, MemFileKey,
+        NetworkOverride, SnapshotLayer,
+    };
 
     use super::*;
     use crate::api_server::parsed_request::tests::{depr_action_from_req, vmm_action_from_request};
@@ -145,6 +150,7 @@ mod tests {
             snapshot_type: SnapshotType::Diff,
             snapshot_path: PathBuf::from("foo"),
             mem_file_path: PathBuf::from("bar"),
+            mem_file_encoding: None,
         };
         assert_eq!(
             vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
@@ -159,6 +165,29 @@ mod tests {
             snapshot_type: SnapshotType::Full,
             snapshot_path: PathBuf::from("foo"),
             mem_file_path: PathBuf::from("bar"),
+            mem_file_encoding: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
+            VmmAction::CreateSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_file_path": "bar",
+            "mem_file_encoding": {
+                "compression": "Zstd",
+                "encryption_key": "a2V5"
+            }
+        }"#;
+        let expected_config = CreateSnapshotParams {
+            snapshot_type: SnapshotType::Full,
+            snapshot_path: PathBuf::from("foo"),
+            mem_file_path: PathBuf::from("bar"),
+            mem_file_encoding: Some(MemFileEncoding {
+                compression: Some(MemFileCompression::Zstd),
+                encryption_key: Some(MemFileKey("a2V5".to_string())),
+            }),
         };
         assert_eq!(
             vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("create")).unwrap()),
@@ -171,6 +200,15 @@ mod tests {
         }"#;
         parse_put_snapshot(&Body::new(invalid_body), Some("create")).unwrap_err();
 
+        let invalid_body = r#"{
+            "snapshot_path": "foo",
+            "mem_file_path": "bar",
+            "mem_file_encoding": {
+                "compression": "Gzip"
+            }
+        }"#;
+        parse_put_snapshot(&Body::new(invalid_body), Some("create")).unwrap_err();
+
         let body = r#"{
             "snapshot_path": "foo",
             "mem_backend": {
@@ -187,6 +225,8 @@ mod tests {
             track_dirty_pages: false,
             resume_vm: false,
             network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(
@@ -217,6 +257,8 @@ mod tests {
             track_dirty_pages: true,
             resume_vm: false,
             network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(
@@ -247,6 +289,8 @@ mod tests {
             track_dirty_pages: false,
             resume_vm: true,
             network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(
@@ -286,6 +330,8 @@ mod tests {
                 iface_id: String::from("eth0"),
                 host_dev_name: String::from("vmtap2"),
             }],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         };
         let mut parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert!(
@@ -299,6 +345,74 @@ mod tests {
             VmmAction::LoadSnapshot(expected_config)
         );
 
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "parent_layers": [
+                {
+                    "snapshot_path": "base_snap",
+                    "mem_file_path": "base_mem"
+                },
+                {
+                    "snapshot_path": "diff_snap",
+                    "mem_file_path": "diff_mem"
+                }
+            ]
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::File,
+            },
+            track_dirty_pages: false,
+            resume_vm: false,
+            network_overrides: vec![],
+            parent_layers: vec![
+                SnapshotLayer {
+                    snapshot_path: PathBuf::from("base_snap"),
+                    mem_file_path: PathBuf::from("base_mem"),
+                },
+                SnapshotLayer {
+                    snapshot_path: PathBuf::from("diff_snap"),
+                    mem_file_path: PathBuf::from("diff_mem"),
+                },
+            ],
+            mem_file_encryption_key: None,
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
+        let body = r#"{
+            "snapshot_path": "foo",
+            "mem_backend": {
+                "backend_path": "bar",
+                "backend_type": "File"
+            },
+            "mem_file_encryption_key": "a2V5"
+        }"#;
+        let expected_config = LoadSnapshotParams {
+            snapshot_path: PathBuf::from("foo"),
+            mem_backend: MemBackendConfig {
+                backend_path: PathBuf::from("bar"),
+                backend_type: MemBackendType::File,
+            },
+            track_dirty_pages: false,
+            resume_vm: false,
+            network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: Some(MemFileKey("a2V5".to_string())),
+        };
+        assert_eq!(
+            vmm_action_from_request(parse_put_snapshot(&Body::new(body), Some("load")).unwrap()),
+            VmmAction::LoadSnapshot(expected_config)
+        );
+
         let body = r#"{
             "snapshot_path": "foo",
             "mem_file_path": "bar",
@@ -313,6 +427,8 @@ mod tests {
             track_dirty_pages: false,
             resume_vm: true,
             network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         };
         let parsed_request = parse_put_snapshot(&Body::new(body), Some("load")).unwrap();
         assert_eq!(
@@ -67,7 +67,11 @@ impl ApiServerAdapter {
                 .run()
                 .expect("EventManager events driver fatal error");
 
-            match vmm.lock().unwrap().shutdown_exit_code() {
+            let mut locked_vmm = vmm.lock().unwrap();
+            // Devices hotplugged or unplugged while handling the events must be added to or
+            // removed from the event manager outside of its run loop.
+            locked_vmm.update_event_subscribers(event_manager);
+            match locked_vmm.shutdown_exit_code() {
                 Some(FcExitCode::Ok) => break,
                 Some(exit_code) => return Err(ApiServerError::MicroVMStoppedWithError(exit_code)),
                 None => continue,
@@ -8,7 +8,8 @@ mod metrics;
 mod seccomp;
 
 use std::fs::{self, File};
-use std::path::PathBuf;
+use std::os::fd::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
@@ -59,8 +60,12 @@ enum MainError {
     LoggerInitialization(vmm::logger::LoggerUpdateError),
     /// Could not initialize metrics: {0}
     MetricsInitialization(MetricsConfigError),
+    /// Invalid value for the vcpu cgroup fd: {0}
+    InvalidVcpuCgroupFd(String),
     /// Seccomp error: {0}
     SeccompFilter(FilterError),
+    /// Seccomp learning mode error: {0}
+    SeccompLearning(vmm::seccomp::learning::LearningError),
     /// Failed to resize fd table: {0}
     ResizeFdtable(ResizeFdTableError),
     /// RunWithApiError error: {0}
@@ -84,6 +89,7 @@ impl From<MainError> for FcExitCode {
         match value {
             MainError::ParseArguments(_) => FcExitCode::ArgParsing,
             MainError::InvalidLogLevel(_) => FcExitCode::BadConfiguration,
+            MainError::InvalidVcpuCgroupFd(_) => FcExitCode::BadConfiguration,
             MainError::RunWithApi(ApiServerError::MicroVMStoppedWithError(code)) => code,
             MainError::RunWithoutApiError(RunWithoutApiError::Shutdown(code)) => code,
             _ => FcExitCode::GenericError,
@@ -173,6 +179,16 @@ fn main_exec() -> Result<(), MainError> {
                          seccomp filtering. Not recommended.",
                     ),
             )
+            .arg(
+                Argument::new("seccomp-learning-log")
+                    .takes_value(true)
+                    .requires("seccomp-filter")
+                    .help(
+                        "Optional parameter which allows recording the syscalls not allowed by a \
+                         custom seccomp filter compiled with `seccompiler-bin --learning` to the \
+                         given file, instead of blocking them. For filter development only.",
+                    ),
+            )
             .arg(
                 Argument::new("start-time-us").takes_value(true).help(
                     "Process start time (wall clock, microseconds). This parameter is optional.",
@@ -265,7 +281,11 @@ fn main_exec() -> Result<(), MainError> {
                 Argument::new("enable-pci")
                     .takes_value(false)
                     .help("Enables PCIe support."),
-            );
+            )
+            .arg(Argument::new("vcpu-cgroup-fd").takes_value(true).help(
+                "File descriptor of the cgroup.threads file of the cgroup that the vCPU threads \
+                 move into. Set by the jailer when the vCPU cgroup is configured.",
+            ));
 
     arg_parser.parse_from_cmdline()?;
     let arguments = arg_parser.arguments();
@@ -315,6 +335,8 @@ fn main_exec() -> Result<(), MainError> {
             show_level,
             show_log_origin,
             module,
+            format: None,
+            module_levels: None,
         })
         .map_err(MainError::LoggerInitialization)?;
     info!("Running Firecracker v{FIRECRACKER_VERSION}");
@@ -356,6 +378,13 @@ fn main_exec() -> Result<(), MainError> {
         init_metrics(metrics_config).map_err(MainError::MetricsInitialization)?;
     }
 
+    if let Some(fd) = arguments.single_value("vcpu-cgroup-fd") {
+        let threads_file = vcpu_cgroup_threads_file(fd)?;
+        vmm::vstate::vcpu::VCPU_CGROUP_THREADS
+            .set(threads_file)
+            .unwrap();
+    }
+
     let mut seccomp_filters: BpfThreadMap = SeccompConfig::from_args(
         arguments.flag_present("no-seccomp"),
         arguments.single_value("seccomp-filter"),
@@ -363,6 +392,11 @@ fn main_exec() -> Result<(), MainError> {
     .and_then(seccomp::get_filters)
     .map_err(MainError::SeccompFilter)?;
 
+    // The learning mode has to be enabled before any thread installs its filter.
+    if let Some(log_path) = arguments.single_value("seccomp-learning-log") {
+        vmm::seccomp::learning::enable(Path::new(log_path)).map_err(MainError::SeccompLearning)?;
+    }
+
     let vmm_config_json = arguments
         .single_value("config-file")
         .map(fs::read_to_string)
@@ -500,6 +534,24 @@ fn resize_fdtable() -> Result<(), ResizeFdTableError> {
     Ok(())
 }
 
+// Takes ownership of the cgroup.threads file of the vCPU cgroup, inherited from the jailer.
+fn vcpu_cgroup_threads_file(fd: &str) -> Result<File, MainError> {
+    let raw_fd = fd
+        .parse::<RawFd>()
+        .map_err(|_| MainError::InvalidVcpuCgroupFd(fd.to_string()))?;
+    // The standard I/O file descriptors are not ours to take.
+    if raw_fd <= libc::STDERR_FILENO {
+        return Err(MainError::InvalidVcpuCgroupFd(fd.to_string()));
+    }
+    // Marking the file descriptor close-on-exec again fails if it is not open.
+    // SAFETY: Safe because fcntl doesn't access memory, and the return value is checked.
+    if unsafe { libc::fcntl(raw_fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return Err(MainError::InvalidVcpuCgroupFd(fd.to_string()));
+    }
+    // SAFETY: `raw_fd` is an open file descriptor inherited from the jailer, owned by nobody else.
+    Ok(unsafe { File::from_raw_fd(raw_fd) })
+}
+
 /// Enable SSBD mitigation through `prctl`.
 #[cfg(target_arch = "aarch64")]
 pub fn enable_ssbd_mitigation() {
@@ -640,7 +692,11 @@ fn run_without_api(
             .run()
             .expect("Failed to start the event manager");
 
-        match vmm.lock().unwrap().shutdown_exit_code() {
+        let mut locked_vmm = vmm.lock().unwrap();
+        // Devices unplugged while handling the events must be removed from the event manager
+        // outside of its run loop.
+        locked_vmm.update_event_subscribers(&mut event_manager);
+        match locked_vmm.shutdown_exit_code() {
             Some(FcExitCode::Ok) => break,
             Some(exit_code) => return Err(RunWithoutApiError::Shutdown(exit_code)),
             None => continue,
@@ -6,6 +6,7 @@ use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
 use std::path::{Path, PathBuf};
 use std::process;
 
@@ -13,6 +14,27 @@ use regex::Regex;
 
 use crate::{JailerError, readln_special, writeln_special};
 
+// Jailer arguments which set the cgroupsv2 file of the same name (e.g --cpu-max sets cpu.max),
+// after validating their value. The io-max argument can be used multiple times, once per block
+// device.
+pub const CGROUP_PRESET_ARGS: [&str; 5] = [
+    "cpu-max",
+    "cpu-weight",
+    "memory-max",
+    "memory-high",
+    "pids-max",
+];
+pub const CGROUP_IO_MAX_ARG: &str = "io-max";
+
+// Name of the child cgroup of the microVM cgroup in which the vcpu threads are placed.
+pub const VCPU_CGROUP_NAME: &str = "vcpus";
+
+// Controllers which can be enabled in a threaded cgroup.
+const THREADED_CONTROLLERS: [&str; 4] = ["cpu", "cpuset", "perf_event", "pids"];
+
+// Default period of cpu.max, in microseconds.
+const CPU_MAX_DEFAULT_PERIOD: u64 = 100_000;
+
 // Holds information on a cgroup mount point discovered on the system
 #[derive(Debug)]
 struct CgroupMountPoint {
@@ -135,6 +157,7 @@ impl CgroupHierarchies {
 pub struct CgroupConfigurationBuilder {
     hierarchies: CgroupHierarchies,
     cgroup_conf: CgroupConfiguration,
+    delegated: bool,
 }
 
 impl CgroupConfigurationBuilder {
@@ -148,9 +171,17 @@ impl CgroupConfigurationBuilder {
                 2 => Ok(CgroupConfiguration::V2(HashMap::new())),
                 _ => Err(JailerError::CgroupInvalidVersion(ver.to_string())),
             }?,
+            delegated: false,
         })
     }
 
+    // Restricts the cgroupsv2 configuration to the subtree of the parent cgroup, which has been
+    // delegated to the unprivileged user running the jailer. Controllers are then only enabled
+    // from the parent cgroup down, since the cgroups above it are not writable by this user.
+    pub fn set_delegated(&mut self, delegated: bool) {
+        self.delegated = delegated;
+    }
+
     // Adds a cgroup property to the configuration
     pub fn add_cgroup_property(
         &mut self,
@@ -173,13 +204,41 @@ impl CgroupConfigurationBuilder {
                 let path = self.hierarchies.get_v2_hierarchy_path()?;
                 let cgroup = cgroup_conf_v2
                     .entry(String::from("unified"))
-                    .or_insert(CgroupV2::new(id, parent_cg, path)?);
+                    .or_insert(CgroupV2::new(id, parent_cg, path, self.delegated)?);
                 cgroup.add_property(file, value)?;
                 Ok(())
             }
         }
     }
 
+    // Adds a property to the threaded child cgroup of the microVM cgroup, in which the vcpu
+    // threads are placed by Firecracker. The microVM cgroup is also created if there is no
+    // property for it, since threads can only move to a threaded cgroup of their own domain.
+    pub fn add_vcpu_cgroup_property(
+        &mut self,
+        file: String,
+        value: String,
+        id: &str,
+        parent_cg: &Path,
+    ) -> Result<(), JailerError> {
+        let CgroupConfiguration::V2(ref mut cgroup_conf_v2) = self.cgroup_conf else {
+            return Err(JailerError::CgroupV2Required("--vcpu-cgroup".to_string()));
+        };
+        let controller = get_controller_from_filename(&file)?;
+        if !THREADED_CONTROLLERS.contains(&controller) {
+            return Err(JailerError::VcpuCgroupController(controller.to_string()));
+        }
+
+        let path = self.hierarchies.get_v2_hierarchy_path()?;
+        if let Vacant(entry) = cgroup_conf_v2.entry(String::from("unified")) {
+            entry.insert(CgroupV2::new(id, parent_cg, path, self.delegated)?);
+        }
+        let cgroup = cgroup_conf_v2
+            .entry(String::from(VCPU_CGROUP_NAME))
+            .or_insert(CgroupV2::new_threaded(id, parent_cg, path, self.delegated)?);
+        cgroup.add_property(file, value)
+    }
+
     pub fn build(self) -> CgroupConfiguration {
         self.cgroup_conf
     }
@@ -212,6 +271,8 @@ pub struct CgroupV1 {
 pub struct CgroupV2 {
     base: CgroupBase,
     available_controllers: HashSet<String>,
+    subtree_root: PathBuf, // topmost cgroup in which controllers are enabled.
+    threaded: bool,        // threads are moved to the cgroup instead of the jailer process.
 }
 
 pub trait Cgroup: Debug {
@@ -238,6 +299,16 @@ impl CgroupConfiguration {
             Self::V2(conf) => setup_cgroup_conf(conf),
         }
     }
+
+    // Returns the path of the cgroup.threads file of the vcpu cgroup, if there is one.
+    pub fn vcpu_cgroup_threads_path(&self) -> Option<PathBuf> {
+        match self {
+            Self::V1(_) => None,
+            Self::V2(conf) => conf
+                .get(VCPU_CGROUP_NAME)
+                .map(|cgroup| cgroup.base.location.join("cgroup.threads")),
+        }
+    }
 }
 
 // If we call inherit_from_parent_aux(.../A/B/C, file, condition), the following will happen:
@@ -324,6 +395,108 @@ fn get_controller_from_filename(file: &str) -> Result<&str, JailerError> {
     Ok(v[0])
 }
 
+// Validates the value of a cgroup preset argument, and returns the cgroupsv2 file it sets along
+// with the value in the format of that file.
+pub fn parse_cgroup_preset(arg: &str, value: &str) -> Result<(String, String), JailerError> {
+    let parsed = match arg {
+        "cpu-max" => parse_cpu_max(value),
+        "cpu-weight" => value
+            .parse::<u64>()
+            .ok()
+            .filter(|weight| (1..=10_000).contains(weight))
+            .map(|weight| weight.to_string()),
+        "memory-max" | "memory-high" => parse_max_or(value, parse_bytes),
+        "pids-max" => parse_max_or(value, |v| v.parse::<u64>().ok()),
+        CGROUP_IO_MAX_ARG => parse_io_max(value),
+        _ => None,
+    };
+
+    match parsed {
+        // The file name is the argument name, with the controller separated by a dot.
+        Some(parsed) => Ok((arg.replacen('-', ".", 1), parsed)),
+        None => Err(JailerError::CgroupPreset(
+            arg.to_string(),
+            value.to_string(),
+        )),
+    }
+}
+
+// Parses a limit that is either "max" or a number.
+fn parse_max_or(value: &str, parse: impl Fn(&str) -> Option<u64>) -> Option<String> {
+    match value {
+        "max" => Some(value.to_string()),
+        _ => parse(value).map(|limit| limit.to_string()),
+    }
+}
+
+// Parses a number of bytes, with an optional K, M, G or T binary suffix (e.g 512M).
+fn parse_bytes(value: &str) -> Option<u64> {
+    let (number, shift) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 10),
+        (i, 'm' | 'M') => (&value[..i], 20),
+        (i, 'g' | 'G') => (&value[..i], 30),
+        (i, 't' | 'T') => (&value[..i], 40),
+        _ => (value, 0),
+    };
+    number.parse::<u64>().ok()?.checked_mul(1 << shift)
+}
+
+// cpu-max format: <quota>[,<period>], where the quota is either "max" or a number of
+// microseconds, and the period defaults to 100ms.
+fn parse_cpu_max(value: &str) -> Option<String> {
+    let (quota, period) = match value.split_once(',') {
+        Some((quota, period)) => (quota, period.parse::<u64>().ok()?),
+        None => (value, CPU_MAX_DEFAULT_PERIOD),
+    };
+    // These are the bounds enforced by the kernel.
+    if !(1_000..=1_000_000).contains(&period) {
+        return None;
+    }
+    let quota = parse_max_or(quota, |v| v.parse::<u64>().ok().filter(|q| *q >= 1_000))?;
+    Some(format!("{} {}", quota, period))
+}
+
+// io-max format: <device>,<key>=<limit>[,<key>=<limit>...], where the device is either the path
+// of a block device or its <major>:<minor> numbers, and the keys are rbps, wbps, riops and wiops.
+fn parse_io_max(value: &str) -> Option<String> {
+    let mut fields = value.split(',');
+    let mut io_max = parse_block_device(fields.next()?)?;
+
+    let mut has_limits = false;
+    for field in fields {
+        let (key, limit) = field.split_once('=')?;
+        if !["rbps", "wbps", "riops", "wiops"].contains(&key) {
+            return None;
+        }
+        let limit = parse_max_or(limit, |v| v.parse::<u64>().ok())?;
+        io_max.push_str(&format!(" {}={}", key, limit));
+        has_limits = true;
+    }
+
+    has_limits.then_some(io_max)
+}
+
+// Returns the <major>:<minor> numbers of a block device.
+fn parse_block_device(device: &str) -> Option<String> {
+    if let Some((major, minor)) = device.split_once(':') {
+        return Some(format!(
+            "{}:{}",
+            major.parse::<u32>().ok()?,
+            minor.parse::<u32>().ok()?
+        ));
+    }
+
+    let metadata = fs::metadata(device).ok()?;
+    if !metadata.file_type().is_block_device() {
+        return None;
+    }
+    Some(format!(
+        "{}:{}",
+        libc::major(metadata.rdev()),
+        libc::minor(metadata.rdev())
+    ))
+}
+
 impl CgroupV1 {
     // Create a new cgroupsv1 controller
     pub fn new(id: &str, parent_cg: &Path, controller_path: &Path) -> Result<Self, JailerError> {
@@ -380,8 +553,12 @@ impl CgroupV2 {
     // Enables the specified controller along the cgroup nested path.
     // To be able to use a leaf controller within a nested cgroup hierarchy,
     // the controller needs to be enabled by writing to the cgroup.subtree_control
-    // of it's parent. This rule applies recursively.
-    fn write_all_subtree_control<P>(path: P, controller: &str) -> Result<(), JailerError>
+    // of it's parent. This rule applies recursively, up to the `root` cgroup.
+    fn write_all_subtree_control<P>(
+        path: P,
+        controller: &str,
+        root: &Path,
+    ) -> Result<(), JailerError>
     where
         P: AsRef<Path> + Debug,
     {
@@ -390,14 +567,14 @@ impl CgroupV2 {
             return Ok(());
         }
         let parent = match path.as_ref().parent() {
-            Some(p) => p,
-            None => {
+            Some(p) if path.as_ref() != root => p,
+            _ => {
                 writeln_special(&cg_subtree_ctrl, format!("+{}", &controller))?;
                 return Ok(());
             }
         };
 
-        Self::write_all_subtree_control(parent, controller)?;
+        Self::write_all_subtree_control(parent, controller, root)?;
         writeln_special(&cg_subtree_ctrl, format!("+{}", &controller))
     }
 
@@ -423,19 +600,45 @@ impl CgroupV2 {
     }
 
     // Create a new cgroupsv2 controller
-    pub fn new(id: &str, parent_cg: &Path, unified_path: &Path) -> Result<Self, JailerError> {
+    // When the parent cgroup is delegated, only the controllers available in it can be used.
+    pub fn new(
+        id: &str,
+        parent_cg: &Path,
+        unified_path: &Path,
+        delegated: bool,
+    ) -> Result<Self, JailerError> {
         let mut path = unified_path.to_path_buf();
 
         path.push(parent_cg);
+        let subtree_root = match delegated {
+            true => path.clone(),
+            false => unified_path.to_path_buf(),
+        };
         path.push(id);
         Ok(CgroupV2 {
             base: CgroupBase {
                 properties: Vec::new(),
                 location: path,
             },
-            available_controllers: Self::detect_available_controllers(unified_path),
+            available_controllers: Self::detect_available_controllers(&subtree_root),
+            subtree_root,
+            threaded: false,
         })
     }
+
+    // Create the threaded child cgroup of the microVM cgroup, in which the vcpu threads are
+    // placed. Only the threaded controllers can be used in it.
+    pub fn new_threaded(
+        id: &str,
+        parent_cg: &Path,
+        unified_path: &Path,
+        delegated: bool,
+    ) -> Result<Self, JailerError> {
+        let mut cgroup = Self::new(id, parent_cg, unified_path, delegated)?;
+        cgroup.base.location.push(VCPU_CGROUP_NAME);
+        cgroup.threaded = true;
+        Ok(cgroup)
+    }
 }
 
 impl Cgroup for CgroupV2 {
@@ -458,6 +661,12 @@ impl Cgroup for CgroupV2 {
         fs::create_dir_all(&self.base.location)
             .map_err(|err| JailerError::CreateDir(self.base.location.clone(), err))?;
 
+        // Turning the cgroup into a threaded one also turns its parent into a threaded domain,
+        // in which processes can coexist with the threaded controllers of its children.
+        if self.threaded {
+            writeln_special(&self.base.location.join("cgroup.type"), "threaded")?;
+        }
+
         // Ok to unwrap since the path was just created.
         let parent = self.base.location.parent().unwrap();
 
@@ -466,7 +675,7 @@ impl Cgroup for CgroupV2 {
             // enable controllers only once
             if !enabled_controllers.contains(controller) {
                 // Enable the controller in all parent directories
-                CgroupV2::write_all_subtree_control(parent, controller)?;
+                CgroupV2::write_all_subtree_control(parent, controller, &self.subtree_root)?;
                 enabled_controllers.insert(controller);
             }
             writeln_special(&self.base.location.join(&property.file), &property.value)?;
@@ -476,6 +685,11 @@ impl Cgroup for CgroupV2 {
     }
 
     fn attach_pid(&self) -> Result<(), JailerError> {
+        // The vcpu threads move themselves to the threaded cgroup, once they are started.
+        if self.threaded {
+            return Ok(());
+        }
+
         let pid = process::id();
         let location = &self.base.location.join("cgroup.procs");
 
@@ -834,6 +1048,206 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cgroup_conf_v2_delegated_write_value() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v2_mounts().unwrap();
+
+        // with real cgroups the delegated parent cgroup is set up by the system administrator
+        let cg_root = mock_cgroups.sys_cgroups_path.join("unified");
+        fs::create_dir_all(cg_root.join("fc_test_delegated/101")).unwrap();
+        MockCgroupFs::create_file_with_contents(
+            cg_root.join("fc_test_delegated/cgroup.controllers"),
+            "cpuset memory",
+        )
+        .unwrap();
+        MockCgroupFs::create_file_with_contents(
+            cg_root.join("fc_test_delegated/cgroup.subtree_control"),
+            "",
+        )
+        .unwrap();
+
+        let mut builder =
+            CgroupConfigurationBuilder::new(2, mock_cgroups.proc_mounts_path.to_str().unwrap())
+                .unwrap();
+        builder.set_delegated(true);
+
+        // the cpu controller is available on the system, but not in the delegated subtree
+        assert!(matches!(
+            builder.add_cgroup_property(
+                "cpu.weight".to_string(),
+                "100".to_string(),
+                "101",
+                Path::new("fc_test_delegated"),
+            ),
+            Err(JailerError::CgroupControllerUnavailable(_))
+        ));
+        builder
+            .add_cgroup_property(
+                "cpuset.mems".to_string(),
+                "1".to_string(),
+                "101",
+                Path::new("fc_test_delegated"),
+            )
+            .unwrap();
+        builder.build().setup().unwrap();
+
+        assert_eq!(
+            read_first_line(cg_root.join("fc_test_delegated/101/cpuset.mems")).unwrap(),
+            "1\n"
+        );
+
+        // check that the controller was only enabled in the delegated subtree
+        assert!(
+            read_first_line(cg_root.join("fc_test_delegated/cgroup.subtree_control"))
+                .unwrap()
+                .contains("cpuset")
+        );
+        assert!(
+            !read_first_line(cg_root.join("cgroup.subtree_control"))
+                .unwrap()
+                .contains("cpuset")
+        );
+    }
+
+    #[test]
+    fn test_cgroup_conf_v2_vcpu_cgroup() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v2_mounts().unwrap();
+        let cg_root = mock_cgroups.sys_cgroups_path.join("unified");
+
+        let mut builder =
+            CgroupConfigurationBuilder::new(2, mock_cgroups.proc_mounts_path.to_str().unwrap())
+                .unwrap();
+        assert!(matches!(
+            builder.add_vcpu_cgroup_property(
+                "memory.max".to_string(),
+                "1".to_string(),
+                "101",
+                Path::new("fc_test_vcpus"),
+            ),
+            Err(JailerError::VcpuCgroupController(_))
+        ));
+        builder
+            .add_vcpu_cgroup_property(
+                "cpu.max".to_string(),
+                "50000 100000".to_string(),
+                "101",
+                Path::new("fc_test_vcpus"),
+            )
+            .unwrap();
+        let cg_conf = builder.build();
+        assert_eq!(
+            cg_conf.vcpu_cgroup_threads_path().unwrap(),
+            cg_root.join("fc_test_vcpus/101/vcpus/cgroup.threads")
+        );
+
+        // with real cgroups these files are created automatically
+        fs::create_dir_all(cg_root.join("fc_test_vcpus/101")).unwrap();
+        MockCgroupFs::create_file_with_contents(
+            cg_root.join("fc_test_vcpus/cgroup.subtree_control"),
+            "",
+        )
+        .unwrap();
+        MockCgroupFs::create_file_with_contents(
+            cg_root.join("fc_test_vcpus/101/cgroup.subtree_control"),
+            "",
+        )
+        .unwrap();
+
+        cg_conf.setup().unwrap();
+
+        assert_eq!(
+            read_first_line(cg_root.join("fc_test_vcpus/101/vcpus/cgroup.type")).unwrap(),
+            "threaded\n"
+        );
+        assert_eq!(
+            read_first_line(cg_root.join("fc_test_vcpus/101/vcpus/cpu.max")).unwrap(),
+            "50000 100000\n"
+        );
+        // the controller is enabled down to the microVM cgroup
+        assert!(
+            read_first_line(cg_root.join("fc_test_vcpus/101/cgroup.subtree_control"))
+                .unwrap()
+                .contains("cpu")
+        );
+        // the jailer is only attached to the microVM cgroup
+        assert!(cg_root.join("fc_test_vcpus/101/cgroup.procs").exists());
+        assert!(
+            !cg_root
+                .join("fc_test_vcpus/101/vcpus/cgroup.procs")
+                .exists()
+        );
+
+        // the vcpu cgroup requires cgroupsv2
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        let mut builder =
+            CgroupConfigurationBuilder::new(1, mock_cgroups.proc_mounts_path.to_str().unwrap())
+                .unwrap();
+        assert!(matches!(
+            builder.add_vcpu_cgroup_property(
+                "cpu.max".to_string(),
+                "max".to_string(),
+                "101",
+                Path::new("fc_test_vcpus"),
+            ),
+            Err(JailerError::CgroupV2Required(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_cgroup_preset() {
+        let valid = [
+            ("cpu-max", "50000", "cpu.max", "50000 100000"),
+            ("cpu-max", "max,20000", "cpu.max", "max 20000"),
+            ("cpu-weight", "100", "cpu.weight", "100"),
+            ("memory-max", "512M", "memory.max", "536870912"),
+            ("memory-high", "2g", "memory.high", "2147483648"),
+            ("memory-max", "max", "memory.max", "max"),
+            ("pids-max", "64", "pids.max", "64"),
+            (
+                "io-max",
+                "8:0,rbps=1024,wiops=max",
+                "io.max",
+                "8:0 rbps=1024 wiops=max",
+            ),
+        ];
+        for (arg, value, file, parsed) in valid {
+            assert_eq!(
+                parse_cgroup_preset(arg, value).unwrap(),
+                (file.to_string(), parsed.to_string())
+            );
+        }
+
+        let invalid = [
+            ("cpu-max", "500"),
+            ("cpu-max", "50000,100"),
+            ("cpu-max", "50000,"),
+            ("cpu-weight", "0"),
+            ("cpu-weight", "10001"),
+            ("memory-max", ""),
+            ("memory-max", "1X"),
+            ("memory-high", "18446744073709551615K"),
+            ("pids-max", "-1"),
+            ("io-max", "8:0"),
+            ("io-max", "8:0,rbps"),
+            ("io-max", "8:0,rbytes=1"),
+            ("io-max", "8,rbps=1"),
+            ("io-max", "/dev/null,rbps=1"),
+            ("io-max", "/nonexistent,rbps=1"),
+            ("cpu-shares", "1"),
+        ];
+        for (arg, value) in invalid {
+            let err = parse_cgroup_preset(arg, value).unwrap_err();
+            assert!(
+                matches!(err, JailerError::CgroupPreset(ref a, ref v) if a == arg && v == value),
+                "{:?}",
+                err
+            );
+        }
+    }
+
     #[test]
     fn test_inherit_from_parent() {
         // 1. If parent file does not exist, return an error.
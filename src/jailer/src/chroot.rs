@@ -14,9 +14,9 @@ const OLD_ROOT_DIR: &CStr = c"old_root";
 const ROOT_DIR: &CStr = c"/";
 const CURRENT_DIR: &CStr = c".";
 
-// This uses switching to a new mount namespace + pivot_root(), together with the regular chroot,
-// to provide a hardened jail (at least compared to only relying on chroot).
-pub fn chroot(path: &Path) -> Result<(), JailerError> {
+// Switches to a new mount namespace, in which the jail can be set up without affecting the mounts
+// of the host.
+pub fn unshare_mount_ns() -> Result<(), JailerError> {
     // We unshare into a new mount namespace.
     // SAFETY: The call is safe because we're invoking a C library
     // function with valid parameters.
@@ -37,8 +37,13 @@ pub fn chroot(path: &Path) -> Result<(), JailerError> {
         )
     })
     .into_empty_result()
-    .map_err(JailerError::MountPropagationSlave)?;
+    .map_err(JailerError::MountPropagationSlave)
+}
 
+// This uses pivot_root() inside the new mount namespace, together with the regular chroot, to
+// provide a hardened jail (at least compared to only relying on chroot). Mounts made inside the
+// jail root directory since switching to the new mount namespace are kept in the jail.
+pub fn chroot(path: &Path) -> Result<(), JailerError> {
     // We need a CString for the following mount call.
     let chroot_dir = to_cstring(path)?;
 
@@ -16,13 +16,22 @@ use utils::time::{ClockType, get_time_us};
 use utils::{arg_parser, validators};
 use vmm_sys_util::syscall::SyscallReturnCode;
 
-use crate::JailerError;
-use crate::cgroup::{CgroupConfiguration, CgroupConfigurationBuilder};
-use crate::chroot::chroot;
+use crate::cgroup::{
+    CGROUP_IO_MAX_ARG, CGROUP_PRESET_ARGS, CgroupConfiguration, CgroupConfigurationBuilder,
+    parse_cgroup_preset,
+};
+use crate::chroot::{chroot, unshare_mount_ns};
+use crate::landlock::{LandlockRuleset, PathAccess};
 use crate::resource_limits::{FSIZE_ARG, NO_FILE_ARG, ResourceLimits};
+use crate::{JailerError, to_cstring, writeln_special};
 
 pub const PROC_MOUNTS: &str = "/proc/mounts";
 
+// Files used to set up the id mappings of a new user namespace, in rootless mode.
+const PROC_SELF_SETGROUPS: &str = "/proc/self/setgroups";
+const PROC_SELF_UID_MAP: &str = "/proc/self/uid_map";
+const PROC_SELF_GID_MAP: &str = "/proc/self/gid_map";
+
 const STDIN_FILENO: libc::c_int = 0;
 const STDOUT_FILENO: libc::c_int = 1;
 const STDERR_FILENO: libc::c_int = 2;
@@ -124,13 +133,16 @@ pub struct Env {
     netns: Option<String>,
     daemonize: bool,
     new_pid_ns: bool,
+    rootless: bool,
     start_time_us: u64,
     start_time_cpu_us: u64,
     jailer_cpu_time_us: u64,
     extra_args: Vec<String>,
     cgroup_conf: Option<CgroupConfiguration>,
+    vcpu_cgroup_threads: Option<File>,
     resource_limits: ResourceLimits,
     uffd_dev_minor: Option<u32>,
+    landlock: Option<LandlockRuleset>,
 }
 
 impl Env {
@@ -187,6 +199,13 @@ impl Env {
 
         let new_pid_ns = arguments.flag_present("new-pid-ns");
 
+        let rootless = arguments.flag_present("rootless");
+        // Without privileges in the initial user namespace, `setns()` into a network namespace
+        // fails, which would leave the jailed process in the host network namespace.
+        if rootless && netns.is_some() {
+            return Err(JailerError::RootlessNetNs);
+        }
+
         // Optional arguments.
         let mut cgroup_conf = None;
         let parent_cgroup = match arguments.single_value("parent-cgroup") {
@@ -207,12 +226,50 @@ impl Env {
             .parse::<u8>()
             .map_err(|_| JailerError::CgroupInvalidVersion(cgroup_ver.to_string()))?;
 
-        let cgroups_args: &[String] = arguments.multiple_values("cgroup").unwrap_or_default();
+        // cgroup format: <cgroup_controller>.<cgroup_property>=<value>,...
+        let mut cgroup_props = arguments
+            .multiple_values("cgroup")
+            .unwrap_or_default()
+            .iter()
+            .map(|cg| Env::parse_cgroup_arg(cg))
+            .collect::<Result<Vec<_>, _>>()?;
+        let vcpu_cgroup_props = arguments
+            .multiple_values("vcpu-cgroup")
+            .unwrap_or_default()
+            .iter()
+            .map(|cg| Env::parse_cgroup_arg(cg))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The cgroup presets are validated, and only available with cgroupsv2.
+        let io_max_args = arguments
+            .multiple_values(CGROUP_IO_MAX_ARG)
+            .unwrap_or_default();
+        let presets = CGROUP_PRESET_ARGS
+            .into_iter()
+            .filter_map(|arg| arguments.single_value(arg).map(|value| (arg, value)))
+            .chain(io_max_args.iter().map(|value| (CGROUP_IO_MAX_ARG, value)));
+        for (arg, value) in presets {
+            if cgroup_ver != 2 {
+                return Err(JailerError::CgroupV2Required(format!("--{}", arg)));
+            }
+            cgroup_props.push(parse_cgroup_preset(arg, value)?);
+        }
+        if !vcpu_cgroup_props.is_empty() && cgroup_ver != 2 {
+            return Err(JailerError::CgroupV2Required("--vcpu-cgroup".to_string()));
+        }
+
+        let has_cgroups = !cgroup_props.is_empty() || !vcpu_cgroup_props.is_empty();
+
+        // An unprivileged user can only manage the cgroups delegated to it, which is only safe
+        // with cgroupsv2.
+        if rootless && has_cgroups && cgroup_ver != 2 {
+            return Err(JailerError::RootlessCgroupVersion(cgroup_ver.to_string()));
+        }
 
         // If the --parent-cgroup exists, and we have no other cgroups,
         // then the intent is to move the process to that cgroup.
         // Only applies to cgroupsv2 since it's a unified hierarchy
-        if cgroups_args.is_empty() && cgroup_ver == 2 {
+        if !has_cgroups && cgroup_ver == 2 {
             let builder = CgroupConfigurationBuilder::new(cgroup_ver, proc_mounts)?;
             let cg_parent = builder.get_v2_hierarchy_path()?.join(parent_cgroup);
             let cg_parent_procs = cg_parent.join("cgroup.procs");
@@ -222,27 +279,14 @@ impl Env {
             }
         }
 
-        // cgroup format: <cgroup_controller>.<cgroup_property>=<value>,...
-        if let Some(cgroups_args) = arguments.multiple_values("cgroup") {
+        if has_cgroups {
             let mut builder = CgroupConfigurationBuilder::new(cgroup_ver, proc_mounts)?;
-            for cg in cgroups_args {
-                let aux: Vec<&str> = cg.split('=').collect();
-                if aux.len() != 2 || aux[1].is_empty() {
-                    return Err(JailerError::CgroupFormat(cg.to_string()));
-                }
-                let file = Path::new(aux[0]);
-                if file.components().any(|c| {
-                    c == Component::CurDir || c == Component::ParentDir || c == Component::RootDir
-                }) {
-                    return Err(JailerError::CgroupInvalidFile(cg.to_string()));
-                }
-
-                builder.add_cgroup_property(
-                    aux[0].to_string(), // cgroup file
-                    aux[1].to_string(), // cgroup value
-                    id,
-                    parent_cgroup,
-                )?;
+            builder.set_delegated(rootless);
+            for (file, value) in cgroup_props {
+                builder.add_cgroup_property(file, value, id, parent_cgroup)?;
+            }
+            for (file, value) in vcpu_cgroup_props {
+                builder.add_vcpu_cgroup_property(file, value, id, parent_cgroup)?;
             }
             cgroup_conf = Some(builder.build());
         }
@@ -254,6 +298,22 @@ impl Env {
 
         let uffd_dev_minor = Self::get_userfaultfd_minor_dev_number().ok();
 
+        // The rules for the files named in the Firecracker arguments are added when they can be
+        // found inside the jail.
+        let landlock = match arguments.flag_present("landlock") {
+            true => {
+                let mut ruleset = LandlockRuleset::default();
+                for path in arguments.multiple_values("landlock-ro").unwrap_or_default() {
+                    ruleset.add_rule(path, PathAccess::ReadOnly);
+                }
+                for path in arguments.multiple_values("landlock-rw").unwrap_or_default() {
+                    ruleset.add_rule(path, PathAccess::ReadWrite);
+                }
+                Some(ruleset)
+            }
+            false => None,
+        };
+
         Ok(Env {
             id: id.to_owned(),
             chroot_dir,
@@ -263,13 +323,16 @@ impl Env {
             netns,
             daemonize,
             new_pid_ns,
+            rootless,
             start_time_us,
             start_time_cpu_us,
             jailer_cpu_time_us: 0,
             extra_args: arguments.extra_args(),
             cgroup_conf,
+            vcpu_cgroup_threads: None,
             resource_limits,
             uffd_dev_minor,
+            landlock,
         })
     }
 
@@ -304,6 +367,23 @@ impl Env {
         Ok((exec_file_path, exec_file_name))
     }
 
+    // Splits a cgroup argument into the cgroup file and its value.
+    fn parse_cgroup_arg(cg: &str) -> Result<(String, String), JailerError> {
+        let aux: Vec<&str> = cg.split('=').collect();
+        if aux.len() != 2 || aux[1].is_empty() {
+            return Err(JailerError::CgroupFormat(cg.to_string()));
+        }
+        let file = Path::new(aux[0]);
+        if file
+            .components()
+            .any(|c| c == Component::CurDir || c == Component::ParentDir || c == Component::RootDir)
+        {
+            return Err(JailerError::CgroupInvalidFile(cg.to_string()));
+        }
+
+        Ok((aux[0].to_string(), aux[1].to_string()))
+    }
+
     fn parse_resource_limits(
         resource_limits: &mut ResourceLimits,
         args: &[String],
@@ -370,6 +450,7 @@ impl Env {
                         .into_empty_result()
                         .map_err(JailerError::SetSid)?;
                 }
+                self.restrict_fs_access(&chroot_exec_file)?;
                 Err(JailerError::Exec(self.exec_command(chroot_exec_file)))
             }
             child_pid => {
@@ -451,6 +532,89 @@ impl Env {
             })
     }
 
+    fn bind_mount_dev(&self, dev_path: &CStr) -> Result<(), JailerError> {
+        // Safe to unwrap as we provided valid file names.
+        let dev_path_str = dev_path.to_str().unwrap();
+        let jailed_dev_path = self.chroot_dir.join(dev_path_str.trim_start_matches('/'));
+
+        // The mount point needs to exist, with the same type as the mounted device node.
+        // Ok to unwrap since the path is inside the chroot directory.
+        let dev_folder = jailed_dev_path.parent().unwrap();
+        fs::create_dir_all(dev_folder)
+            .map_err(|err| JailerError::CreateDir(dev_folder.to_owned(), err))?;
+        File::create(&jailed_dev_path)
+            .map_err(|err| JailerError::FileOpen(jailed_dev_path.clone(), err))?;
+
+        let jailed_dev_path = to_cstring(&jailed_dev_path)?;
+        // SAFETY: This is safe because both paths are null-terminated, and the return value is
+        // checked.
+        SyscallReturnCode(unsafe {
+            libc::mount(
+                dev_path.as_ptr(),
+                jailed_dev_path.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND,
+                std::ptr::null(),
+            )
+        })
+        .into_empty_result()
+        .map_err(|err| JailerError::MountBindDev(err, dev_path_str.to_owned()))
+    }
+
+    // Here we are creating the /dev/kvm and /dev/net/tun devices inside the jailer, using
+    // `create_dev`, which is given the path and the (major, minor) numbers of each device.
+    fn create_devs<F>(&self, create_dev: F) -> Result<(), JailerError>
+    where
+        F: Fn(&CStr, u32, u32) -> Result<(), JailerError>,
+    {
+        // Following commands can be translated into bash like this:
+        // $: mkdir -p $chroot_dir/dev/net
+        // $: dev_net_tun_path={$chroot_dir}/"tun"
+        // $: mknod $dev_net_tun_path c 10 200
+        // www.kernel.org/doc/Documentation/networking/tuntap.txt specifies 10 and 200 as the major
+        // and minor for the /dev/net/tun device.
+        create_dev(DEV_NET_TUN, DEV_NET_TUN_MAJOR, DEV_NET_TUN_MINOR)?;
+        // Do the same for /dev/kvm with (major, minor) = (10, 232).
+        create_dev(DEV_KVM, DEV_KVM_MAJOR, DEV_KVM_MINOR)?;
+        // And for /dev/urandom with (major, minor) = (1, 9).
+        // If the device is not accessible on the host, output a warning to inform user that MMDS
+        // version 2 will not be available to use.
+        let _ = create_dev(DEV_URANDOM, DEV_URANDOM_MAJOR, DEV_URANDOM_MINOR).map_err(|err| {
+            println!(
+                "Warning! Could not create /dev/urandom device inside jailer: {}.",
+                err
+            );
+            println!("MMDS version 2 will not be available to use.");
+        });
+
+        // If we have a minor version for /dev/userfaultfd the device is present on the host.
+        // Expose the device in the jailed environment.
+        if let Some(minor) = self.uffd_dev_minor {
+            create_dev(DEV_UFFD_PATH, DEV_UFFD_MAJOR, minor)?;
+        }
+        Ok(())
+    }
+
+    // Moves the jailer into a new user namespace, in which the unprivileged user running the
+    // jailer is mapped to the uid and gid of the jailed process. The jailer has all capabilities
+    // inside this namespace, which are dropped when exec-ing into the jailed binary.
+    fn unshare_user_ns(&self) -> Result<(), JailerError> {
+        // SAFETY: Safe because these functions are always successful.
+        let (host_uid, host_gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+
+        // SAFETY: The call is safe because we're invoking a C library
+        // function with valid parameters.
+        SyscallReturnCode(unsafe { libc::unshare(libc::CLONE_NEWUSER) })
+            .into_empty_result()
+            .map_err(JailerError::UnshareNewUserNs)?;
+
+        // An unprivileged process can only map its own ids, and only after denying setgroups(2)
+        // for the gid mapping.
+        writeln_special(&PROC_SELF_SETGROUPS, "deny")?;
+        writeln_special(&PROC_SELF_UID_MAP, format!("{} {} 1", self.uid(), host_uid))?;
+        writeln_special(&PROC_SELF_GID_MAP, format!("{} {} 1", self.gid(), host_gid))
+    }
+
     fn setup_jailed_folder(&self, folder: impl AsRef<Path>) -> Result<(), JailerError> {
         let folder_path = folder.as_ref();
         fs::create_dir_all(folder_path)
@@ -500,8 +664,65 @@ impl Env {
             .map_err(JailerError::SetNetNs)
     }
 
+    // Restricts the filesystem access of the jailed process with Landlock, if requested, right
+    // before exec-ing into it. This has to be done from inside the jail, where the paths passed
+    // to Firecracker are valid.
+    fn restrict_fs_access(&mut self, chroot_exec_file: &Path) -> Result<(), JailerError> {
+        let Some(mut ruleset) = self.landlock.take() else {
+            return Ok(());
+        };
+
+        ruleset.add_rule(chroot_exec_file, PathAccess::Execute);
+        for dev_path in [DEV_NET_TUN, DEV_KVM, DEV_URANDOM, DEV_UFFD_PATH] {
+            // Safe to unwrap as we provided valid file names.
+            let dev_path = Path::new(dev_path.to_str().unwrap());
+            // /dev/urandom and /dev/userfaultfd might not be available.
+            if dev_path.exists() {
+                ruleset.add_rule(dev_path, PathAccess::ReadWrite);
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        ruleset.add_rule("/sys", PathAccess::ReadOnly);
+        ruleset.add_firecracker_args(&self.extra_args)?;
+
+        ruleset.restrict_self()
+    }
+
+    // Opens the cgroup.threads file of the vcpu cgroup, which Firecracker inherits to move its
+    // vcpu threads there. The file is opened before dropping privileges and leaving the host
+    // root, since the permission to migrate threads is checked against the credentials of the
+    // opener (on kernels >= 5.16).
+    fn open_vcpu_cgroup_threads(path: &Path) -> Result<File, JailerError> {
+        let threads_file = OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|err| JailerError::FileOpen(path.to_path_buf(), err))?;
+
+        // SAFETY: Safe because the fd is valid, and the return value is checked.
+        let flags =
+            SyscallReturnCode(unsafe { libc::fcntl(threads_file.as_raw_fd(), libc::F_GETFD) })
+                .into_result()
+                .map_err(JailerError::GetOldFdFlags)?;
+        // SAFETY: Safe because the fd is valid, and the return value is checked.
+        SyscallReturnCode(unsafe {
+            libc::fcntl(
+                threads_file.as_raw_fd(),
+                libc::F_SETFD,
+                flags & !libc::FD_CLOEXEC,
+            )
+        })
+        .into_empty_result()
+        .map_err(JailerError::UnsetCloexec)?;
+
+        Ok(threads_file)
+    }
+
     fn exec_command(&self, chroot_exec_file: PathBuf) -> io::Error {
-        Command::new(chroot_exec_file)
+        let mut command = Command::new(chroot_exec_file);
+        if let Some(ref threads_file) = self.vcpu_cgroup_threads {
+            command.args(["--vcpu-cgroup-fd", &threads_file.as_raw_fd().to_string()]);
+        }
+        command
             .args(["--id", &self.id])
             .args(["--start-time-us", &self.start_time_us.to_string()])
             .args([
@@ -520,7 +741,7 @@ impl Env {
 
     #[cfg(target_arch = "aarch64")]
     fn copy_cache_info(&self) -> Result<(), JailerError> {
-        use crate::{readln_special, to_cstring, writeln_special};
+        use crate::readln_special;
 
         const HOST_CACHE_INFO: &str = "/sys/devices/system/cpu/cpu0/cache";
         // Based on https://elixir.free-electrons.com/linux/v4.9.62/source/arch/arm64/kernel/cacheinfo.c#L29.
@@ -584,7 +805,7 @@ impl Env {
 
     #[cfg(target_arch = "aarch64")]
     fn copy_midr_el1_info(&self) -> Result<(), JailerError> {
-        use crate::{readln_special, to_cstring, writeln_special};
+        use crate::readln_special;
 
         const HOST_MIDR_EL1_INFO: &str = "/sys/devices/system/cpu/cpu0/regs/identification";
 
@@ -625,6 +846,9 @@ impl Env {
         // We have to setup cgroups at this point, because we can't do it anymore after chrooting.
         if let Some(ref conf) = self.cgroup_conf {
             conf.setup()?;
+            if let Some(path) = conf.vcpu_cgroup_threads_path() {
+                self.vcpu_cgroup_threads = Some(Env::open_vcpu_cgroup_threads(&path)?);
+            }
         }
 
         // If daemonization was requested, open /dev/null before chrooting.
@@ -633,11 +857,27 @@ impl Env {
         } else {
             None
         };
+
+        // In rootless mode, switch to the new user namespace before creating the files owned by
+        // the jailed process, since their owner is only mapped in that namespace.
+        if self.rootless {
+            self.unshare_user_ns()?;
+        }
+
         #[cfg(target_arch = "aarch64")]
         self.copy_cache_info()?;
         #[cfg(target_arch = "aarch64")]
         self.copy_midr_el1_info()?;
 
+        unshare_mount_ns()?;
+
+        // Device nodes can't be created by an unprivileged user, even inside its user namespace,
+        // so in rootless mode the ones of the host are bind mounted inside the jail instead. This
+        // has to be done before leaving the host root.
+        if self.rootless {
+            self.create_devs(|dev_path, _, _| self.bind_mount_dev(dev_path))?;
+        }
+
         // Jail self.
         chroot(self.chroot_dir())?;
 
@@ -647,33 +887,10 @@ impl Env {
             .iter()
             .try_for_each(|f| self.setup_jailed_folder(f))?;
 
-        // Here we are creating the /dev/kvm and /dev/net/tun devices inside the jailer.
-        // Following commands can be translated into bash like this:
-        // $: mkdir -p $chroot_dir/dev/net
-        // $: dev_net_tun_path={$chroot_dir}/"tun"
-        // $: mknod $dev_net_tun_path c 10 200
-        // www.kernel.org/doc/Documentation/networking/tuntap.txt specifies 10 and 200 as the major
-        // and minor for the /dev/net/tun device.
-        self.mknod_and_own_dev(DEV_NET_TUN, DEV_NET_TUN_MAJOR, DEV_NET_TUN_MINOR)?;
-        // Do the same for /dev/kvm with (major, minor) = (10, 232).
-        self.mknod_and_own_dev(DEV_KVM, DEV_KVM_MAJOR, DEV_KVM_MINOR)?;
-        // And for /dev/urandom with (major, minor) = (1, 9).
-        // If the device is not accessible on the host, output a warning to inform user that MMDS
-        // version 2 will not be available to use.
-        let _ = self
-            .mknod_and_own_dev(DEV_URANDOM, DEV_URANDOM_MAJOR, DEV_URANDOM_MINOR)
-            .map_err(|err| {
-                println!(
-                    "Warning! Could not create /dev/urandom device inside jailer: {}.",
-                    err
-                );
-                println!("MMDS version 2 will not be available to use.");
-            });
-
-        // If we have a minor version for /dev/userfaultfd the device is present on the host.
-        // Expose the device in the jailed environment.
-        if let Some(minor) = self.uffd_dev_minor {
-            self.mknod_and_own_dev(DEV_UFFD_PATH, DEV_UFFD_MAJOR, minor)?;
+        if !self.rootless {
+            self.create_devs(|dev_path, major, minor| {
+                self.mknod_and_own_dev(dev_path, major, minor)
+            })?;
         }
 
         self.jailer_cpu_time_us = get_time_us(ClockType::ProcessCpu) - self.start_time_cpu_us;
@@ -736,6 +953,7 @@ impl Env {
             self.exec_into_new_pid_ns(chroot_exec_file)
         } else {
             self.save_exec_file_pid(id().try_into().unwrap(), chroot_exec_file.clone())?;
+            self.restrict_fs_access(&chroot_exec_file)?;
             Err(JailerError::Exec(self.exec_command(chroot_exec_file)))
         }
     }
@@ -776,6 +994,8 @@ mod tests {
         pub cgroups: Vec<&'a str>,
         pub resource_limits: Vec<&'a str>,
         pub parent_cgroup: Option<&'a str>,
+        pub cgroup_version: Option<&'a str>,
+        pub rootless: bool,
     }
 
     impl<'a> ArgVals<'a> {
@@ -795,6 +1015,8 @@ mod tests {
                 cgroups: vec!["cpu.shares=2", "cpuset.mems=0"],
                 resource_limits: vec!["no-file=1024", "fsize=1048575"],
                 parent_cgroup: None,
+                cgroup_version: None,
+                rootless: false,
             }
         }
     }
@@ -847,6 +1069,15 @@ mod tests {
             arg_vec.push(parent_cg.to_string());
         }
 
+        if let Some(cgroup_ver) = arg_vals.cgroup_version {
+            arg_vec.push("--cgroup-version".to_string());
+            arg_vec.push(cgroup_ver.to_string());
+        }
+
+        if arg_vals.rootless {
+            arg_vec.push("--rootless".to_string());
+        }
+
         arg_vec
     }
 
@@ -1325,6 +1556,208 @@ mod tests {
         Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
     }
 
+    #[test]
+    fn test_rootless_cgroups_parsing() {
+        let arg_parser = build_arg_parser();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let good_arg_vals = ArgVals {
+            cgroups: vec!["cpuset.cpus=2"],
+            netns: None,
+            rootless: true,
+            ..ArgVals::new(pseudo_exec_file_path.as_str())
+        };
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        mock_cgroups.add_v2_mounts().unwrap();
+
+        // Only the cpuset controller is delegated to the jailer user.
+        let delegated_cg = mock_cgroups.sys_cgroups_path.join("unified/fc_delegated");
+        fs::create_dir_all(&delegated_cg).unwrap();
+        MockCgroupFs::create_file_with_contents(delegated_cg.join("cgroup.controllers"), "cpuset")
+            .unwrap();
+
+        // Cgroups can't be used with cgroupsv1.
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&make_args(&good_arg_vals)).unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err, JailerError::RootlessCgroupVersion(ref ver) if ver == "1"),
+            "{:?}",
+            err
+        );
+
+        // Unless there are no cgroups to set up.
+        let mut args = arg_parser.arguments().clone();
+        let no_cgroup_arg_vals = ArgVals {
+            cgroups: vec![],
+            ..good_arg_vals.clone()
+        };
+        args.parse(&make_args(&no_cgroup_arg_vals)).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+        assert!(env.rootless);
+
+        // A network namespace can't be joined in rootless mode.
+        let mut args = arg_parser.arguments().clone();
+        let netns_arg_vals = ArgVals {
+            netns: Some("zzzns"),
+            ..no_cgroup_arg_vals.clone()
+        };
+        args.parse(&make_args(&netns_arg_vals)).unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(err, JailerError::RootlessNetNs), "{:?}", err);
+
+        // Only the controllers of the delegated parent cgroup can be used.
+        let mut args = arg_parser.arguments().clone();
+        let delegated_arg_vals = ArgVals {
+            cgroup_version: Some("2"),
+            parent_cgroup: Some("fc_delegated"),
+            ..good_arg_vals.clone()
+        };
+        args.parse(&make_args(&delegated_arg_vals)).unwrap();
+        Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+
+        let mut args = arg_parser.arguments().clone();
+        let undelegated_arg_vals = ArgVals {
+            cgroups: vec!["memory.max=1"],
+            ..delegated_arg_vals.clone()
+        };
+        args.parse(&make_args(&undelegated_arg_vals)).unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err, JailerError::CgroupControllerUnavailable(ref ctrl) if ctrl == "memory"),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_landlock_parsing() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let arg_vals = ArgVals::new(pseudo_exec_file_path.as_str());
+        let arg_parser = build_arg_parser();
+
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&make_args(&arg_vals)).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+        assert!(env.landlock.is_none());
+
+        // Additional paths can only be passed along with --landlock.
+        let mut landlock_args = make_args(&arg_vals);
+        landlock_args
+            .extend(["--landlock-ro", "/snapshots", "--landlock-rw", "/data"].map(String::from));
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&landlock_args).unwrap_err();
+
+        landlock_args.push("--landlock".to_string());
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&landlock_args).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+        assert!(env.landlock.is_some());
+    }
+
+    #[test]
+    fn test_cgroup_presets_parsing() {
+        let mut mock_cgroups = MockCgroupFs::new().unwrap();
+        mock_cgroups.add_v1_mounts().unwrap();
+        mock_cgroups.add_v2_mounts().unwrap();
+        let pseudo_exec_file_path = get_pseudo_exec_file_path();
+        let arg_vals = ArgVals {
+            cgroups: vec![],
+            ..ArgVals::new(pseudo_exec_file_path.as_str())
+        };
+        let v2_arg_vals = ArgVals {
+            cgroup_version: Some("2"),
+            ..arg_vals.clone()
+        };
+        let arg_parser = build_arg_parser();
+        let preset_args = [
+            "--cpu-max",
+            "50000",
+            "--memory-max",
+            "512M",
+            "--io-max",
+            "8:0,rbps=1048576,wiops=max",
+            "--io-max",
+            "8:16,riops=100",
+        ]
+        .map(String::from);
+
+        // The presets are only available with cgroupsv2.
+        let mut v1_args = make_args(&arg_vals);
+        v1_args.extend(preset_args.clone());
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&v1_args).unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err, JailerError::CgroupV2Required(ref arg) if arg == "--cpu-max"),
+            "{:?}",
+            err
+        );
+
+        let mut v2_args = make_args(&v2_arg_vals);
+        v2_args.extend(preset_args);
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&v2_args).unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+        assert!(env.cgroup_conf.is_some());
+
+        let mut invalid_args = make_args(&v2_arg_vals);
+        invalid_args.extend(["--cpu-weight", "0"].map(String::from));
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&invalid_args).unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid value for --cpu-weight: 0");
+
+        // The vcpu cgroup is also only available with cgroupsv2, and for threaded controllers.
+        let vcpu_args = ["--vcpu-cgroup", "cpu.max=25000 100000"].map(String::from);
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&[make_args(&arg_vals), vcpu_args.to_vec()].concat())
+            .unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err, JailerError::CgroupV2Required(ref arg) if arg == "--vcpu-cgroup"),
+            "{:?}",
+            err
+        );
+
+        let mut args = arg_parser.arguments().clone();
+        args.parse(&[make_args(&v2_arg_vals), vcpu_args.to_vec()].concat())
+            .unwrap();
+        let env = Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap();
+        assert!(
+            env.cgroup_conf
+                .unwrap()
+                .vcpu_cgroup_threads_path()
+                .unwrap()
+                .ends_with("vcpus/cgroup.threads")
+        );
+
+        let mut args = arg_parser.arguments().clone();
+        args.parse(
+            &[
+                make_args(&v2_arg_vals),
+                ["--vcpu-cgroup", "memory.max=1"].map(String::from).to_vec(),
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let err =
+            Env::new(&args, 0, 0, mock_cgroups.proc_mounts_path.to_str().unwrap()).unwrap_err();
+        assert!(
+            matches!(err, JailerError::VcpuCgroupController(ref ctrl) if ctrl == "memory"),
+            "{:?}",
+            err
+        );
+    }
+
     #[test]
     fn test_parse_resource_limits() {
         let mut resource_limits = ResourceLimits::default();
@@ -0,0 +1,394 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use vmm_sys_util::syscall::SyscallReturnCode;
+
+use crate::JailerError;
+
+// Landlock uapi definitions, taken from include/uapi/linux/landlock.h.
+const LANDLOCK_CREATE_RULESET_VERSION: libc::c_uint = 1 << 0;
+const LANDLOCK_RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+// Added in Landlock ABI version 2.
+const ACCESS_FS_REFER: u64 = 1 << 13;
+// Added in Landlock ABI version 3.
+const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+// All the access rights of Landlock ABI version 1, from ACCESS_FS_EXECUTE to ACCESS_FS_MAKE_SYM.
+const ACCESS_FS_V1: u64 = (1 << 13) - 1;
+// The access rights which can be granted on files, as opposed to directories.
+const ACCESS_FILE: u64 =
+    ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE | ACCESS_FS_TRUNCATE;
+
+#[repr(C)]
+struct LandlockRulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct LandlockPathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: libc::c_int,
+}
+
+// Firecracker arguments naming files which are only read by Firecracker.
+const FC_READ_ONLY_ARGS: [&str; 3] = ["--config-file", "--metadata", "--seccomp-filter"];
+// Firecracker arguments naming files which are written by Firecracker.
+const FC_READ_WRITE_ARGS: [&str; 2] = ["--log-path", "--metrics-path"];
+const FC_DEFAULT_API_SOCK: &str = "/run/firecracker.socket";
+
+// Access granted by the ruleset to a path, and everything beneath it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathAccess {
+    // Read files and list directories.
+    ReadOnly,
+    // Also write and truncate files, and create or remove them in directories.
+    ReadWrite,
+    // Read and execute files.
+    Execute,
+    // Create or remove Unix sockets in directories.
+    MakeSocket,
+}
+
+impl PathAccess {
+    fn access_fs(self) -> u64 {
+        match self {
+            Self::ReadOnly => ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR,
+            Self::ReadWrite => {
+                ACCESS_FS_READ_FILE
+                    | ACCESS_FS_READ_DIR
+                    | ACCESS_FS_WRITE_FILE
+                    | ACCESS_FS_TRUNCATE
+                    | ACCESS_FS_MAKE_REG
+                    | ACCESS_FS_REMOVE_FILE
+            }
+            Self::Execute => ACCESS_FS_READ_FILE | ACCESS_FS_EXECUTE,
+            Self::MakeSocket => ACCESS_FS_MAKE_SOCK | ACCESS_FS_REMOVE_FILE,
+        }
+    }
+}
+
+// Landlock ruleset restricting the filesystem access of the jailed process. Once enforced, only
+// the paths added to the ruleset can be accessed, and only with the access granted to them.
+#[derive(Debug, Default)]
+pub struct LandlockRuleset {
+    rules: Vec<(PathBuf, PathAccess)>,
+}
+
+impl LandlockRuleset {
+    pub fn add_rule<P: Into<PathBuf>>(&mut self, path: P, access: PathAccess) {
+        self.rules.push((path.into(), access));
+    }
+
+    // Unix sockets are created in (and stale ones removed from) the directory of their path.
+    fn add_socket_rule(&mut self, sock_path: &str) {
+        let sock_dir = match Path::new(sock_path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        self.add_rule(sock_dir, PathAccess::MakeSocket);
+    }
+
+    // Adds the rules for the files named in the Firecracker arguments, including the ones listed
+    // in the configuration file passed through `--config-file`.
+    pub fn add_firecracker_args(&mut self, args: &[String]) -> Result<(), JailerError> {
+        let arg_value = |name: &str| {
+            args.iter()
+                .position(|arg| arg == name)
+                .and_then(|idx| args.get(idx + 1))
+        };
+
+        for arg in FC_READ_ONLY_ARGS {
+            if let Some(path) = arg_value(arg) {
+                self.add_rule(path, PathAccess::ReadOnly);
+            }
+        }
+        for arg in FC_READ_WRITE_ARGS {
+            if let Some(path) = arg_value(arg) {
+                self.add_rule(path, PathAccess::ReadWrite);
+            }
+        }
+        if !args.iter().any(|arg| arg == "--no-api") {
+            let api_sock = arg_value("--api-sock").map_or(FC_DEFAULT_API_SOCK, String::as_str);
+            self.add_socket_rule(api_sock);
+        }
+
+        match arg_value("--config-file") {
+            Some(config_file) => self.add_config_file(Path::new(config_file)),
+            None => Ok(()),
+        }
+    }
+
+    fn add_config_file(&mut self, config_file: &Path) -> Result<(), JailerError> {
+        let config = fs::read_to_string(config_file)
+            .map_err(|err| JailerError::ReadToString(config_file.to_path_buf(), err))?;
+        let config: Value = serde_json::from_str(&config)
+            .map_err(|err| JailerError::LandlockConfigFile(config_file.to_path_buf(), err))?;
+        let str_value = |pointer: &str| config.pointer(pointer).and_then(Value::as_str);
+
+        // A custom CPU template can also be given inline, instead of by path.
+        for pointer in [
+            "/boot-source/kernel_image_path",
+            "/boot-source/initrd_path",
+            "/cpu-config",
+        ] {
+            if let Some(path) = str_value(pointer) {
+                self.add_rule(path, PathAccess::ReadOnly);
+            }
+        }
+        for pointer in ["/logger/log_path", "/metrics/metrics_path"] {
+            if let Some(path) = str_value(pointer) {
+                self.add_rule(path, PathAccess::ReadWrite);
+            }
+        }
+        if let Some(uds_path) = str_value("/vsock/uds_path") {
+            self.add_socket_rule(uds_path);
+        }
+
+        let drives = config.get("drives").and_then(Value::as_array);
+        for drive in drives.into_iter().flatten() {
+            if let Some(path) = drive.get("path_on_host").and_then(Value::as_str) {
+                let access = match drive.get("is_read_only").and_then(Value::as_bool) {
+                    Some(true) => PathAccess::ReadOnly,
+                    _ => PathAccess::ReadWrite,
+                };
+                self.add_rule(path, access);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the Landlock ABI version supported by the kernel.
+    fn abi_version() -> Result<libc::c_long, io::Error> {
+        SyscallReturnCode(
+            // SAFETY: Safe because the ruleset attributes are not read when querying the version.
+            unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    std::ptr::null::<LandlockRulesetAttr>(),
+                    0usize,
+                    LANDLOCK_CREATE_RULESET_VERSION,
+                )
+            },
+        )
+        .into_result()
+    }
+
+    // Enforces the ruleset on the calling process, and on the processes it execs. On kernels
+    // without Landlock support, a warning is printed and the access is left unrestricted.
+    pub fn restrict_self(&self) -> Result<(), JailerError> {
+        let abi_version = match Self::abi_version() {
+            Ok(abi_version) => abi_version,
+            Err(err) => {
+                println!("Warning! Landlock is not supported by the kernel: {}.", err);
+                println!("The filesystem access of the jailed process will not be restricted.");
+                return Ok(());
+            }
+        };
+
+        // Handling more access rights than the kernel supports is an error, while the ones which
+        // are handled but not granted by any rule are denied.
+        let mut handled_access_fs = ACCESS_FS_V1;
+        if abi_version >= 2 {
+            handled_access_fs |= ACCESS_FS_REFER;
+        }
+        if abi_version >= 3 {
+            handled_access_fs |= ACCESS_FS_TRUNCATE;
+        }
+
+        let ruleset_attr = LandlockRulesetAttr { handled_access_fs };
+        let ruleset_fd = SyscallReturnCode(
+            // SAFETY: Safe because we pass a valid ruleset attributes struct, along with its
+            // size, and the return value is checked.
+            unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_create_ruleset,
+                    std::ptr::from_ref(&ruleset_attr),
+                    std::mem::size_of::<LandlockRulesetAttr>(),
+                    0,
+                )
+            },
+        )
+        .into_result()
+        .map_err(JailerError::LandlockCreateRuleset)?;
+        // SAFETY: The ruleset fd was just created, and is owned by nobody else.
+        // Unwrap is needed because file descriptors are 32-bit.
+        let ruleset = unsafe { OwnedFd::from_raw_fd(ruleset_fd.try_into().unwrap()) };
+
+        for (path, access) in self.rules.iter() {
+            let mut allowed_access = access.access_fs() & handled_access_fs;
+            if !path.is_dir() {
+                allowed_access &= ACCESS_FILE;
+            }
+
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+                .open(path)
+                .map_err(|err| JailerError::FileOpen(path.clone(), err))?;
+            let rule_attr = LandlockPathBeneathAttr {
+                allowed_access,
+                parent_fd: file.as_raw_fd(),
+            };
+            // SAFETY: Safe because we pass a valid ruleset fd and rule attributes struct, and
+            // the return value is checked.
+            SyscallReturnCode(unsafe {
+                libc::syscall(
+                    libc::SYS_landlock_add_rule,
+                    ruleset.as_raw_fd(),
+                    LANDLOCK_RULE_PATH_BENEATH,
+                    std::ptr::from_ref(&rule_attr),
+                    0,
+                )
+            })
+            .into_empty_result()
+            .map_err(|err| JailerError::LandlockAddRule(path.clone(), err))?;
+        }
+
+        // Unprivileged processes can only restrict themselves with no_new_privs set.
+        // SAFETY: Safe because we provide valid parameters, and the return value is checked.
+        SyscallReturnCode(unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) })
+            .into_empty_result()
+            .map_err(JailerError::SetNoNewPrivs)?;
+
+        // SAFETY: Safe because we pass a valid ruleset fd, and the return value is checked.
+        SyscallReturnCode(unsafe {
+            libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0)
+        })
+        .into_empty_result()
+        .map_err(JailerError::LandlockRestrictSelf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_firecracker_args() {
+        let tmp_dir = TempDir::new().unwrap();
+        let config_file = tmp_dir.as_path().join("config.json");
+        fs::write(
+            &config_file,
+            r#"{
+                "boot-source": {"kernel_image_path": "vmlinux"},
+                "drives": [
+                    {"drive_id": "rootfs", "path_on_host": "rootfs.ext4", "is_root_device": true,
+                     "is_read_only": true},
+                    {"drive_id": "scratch", "path_on_host": "/data/scratch.ext4",
+                     "is_root_device": false}
+                ],
+                "logger": {"log_path": "logs.fifo"},
+                "vsock": {"guest_cid": 3, "uds_path": "/vsock/v.sock"}
+            }"#,
+        )
+        .unwrap();
+        let config_file = config_file.to_str().unwrap();
+
+        let mut ruleset = LandlockRuleset::default();
+        ruleset
+            .add_firecracker_args(&args(&[
+                "--config-file",
+                config_file,
+                "--metrics-path",
+                "metrics.fifo",
+                "--api-sock",
+                "api.sock",
+            ]))
+            .unwrap();
+        assert_eq!(
+            ruleset.rules,
+            vec![
+                (PathBuf::from(config_file), PathAccess::ReadOnly),
+                (PathBuf::from("metrics.fifo"), PathAccess::ReadWrite),
+                (PathBuf::from("."), PathAccess::MakeSocket),
+                (PathBuf::from("vmlinux"), PathAccess::ReadOnly),
+                (PathBuf::from("logs.fifo"), PathAccess::ReadWrite),
+                (PathBuf::from("/vsock"), PathAccess::MakeSocket),
+                (PathBuf::from("rootfs.ext4"), PathAccess::ReadOnly),
+                (PathBuf::from("/data/scratch.ext4"), PathAccess::ReadWrite),
+            ]
+        );
+
+        // Without a configuration file, only the default API socket is created.
+        let mut ruleset = LandlockRuleset::default();
+        ruleset.add_firecracker_args(&[]).unwrap();
+        assert_eq!(
+            ruleset.rules,
+            vec![(PathBuf::from("/run"), PathAccess::MakeSocket)]
+        );
+        let mut ruleset = LandlockRuleset::default();
+        ruleset
+            .add_firecracker_args(&args(&["--no-api", "--config-file", "/invalid"]))
+            .unwrap_err();
+
+        fs::write(tmp_dir.as_path().join("invalid.json"), "{").unwrap();
+        let invalid_config_file = tmp_dir.as_path().join("invalid.json");
+        let mut ruleset = LandlockRuleset::default();
+        let err = ruleset
+            .add_firecracker_args(&args(&[
+                "--config-file",
+                invalid_config_file.to_str().unwrap(),
+            ]))
+            .unwrap_err();
+        assert!(
+            matches!(err, JailerError::LandlockConfigFile(..)),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_restrict_self() {
+        if LandlockRuleset::abi_version().is_err() {
+            return;
+        }
+
+        let tmp_dir = TempDir::new().unwrap();
+        for dir in ["ro", "rw", "none"] {
+            fs::create_dir(tmp_dir.as_path().join(dir)).unwrap();
+            fs::write(tmp_dir.as_path().join(dir).join("file"), "data").unwrap();
+        }
+
+        // Landlock only restricts the calling thread, so the temporary directory can still be
+        // removed by the test thread.
+        let tmp_path = tmp_dir.as_path().to_path_buf();
+        std::thread::spawn(move || {
+            let mut ruleset = LandlockRuleset::default();
+            ruleset.add_rule(tmp_path.join("ro"), PathAccess::ReadOnly);
+            ruleset.add_rule(tmp_path.join("rw"), PathAccess::ReadWrite);
+            ruleset.restrict_self().unwrap();
+
+            fs::read(tmp_path.join("ro/file")).unwrap();
+            fs::write(tmp_path.join("ro/file"), "data").unwrap_err();
+            File::create(tmp_path.join("ro/new")).unwrap_err();
+            fs::write(tmp_path.join("rw/file"), "data").unwrap();
+            File::create(tmp_path.join("rw/new")).unwrap();
+            fs::read(tmp_path.join("none/file")).unwrap_err();
+        })
+        .join()
+        .unwrap();
+    }
+}
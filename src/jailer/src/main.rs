@@ -17,6 +17,7 @@ use crate::env::Env;
 mod cgroup;
 mod chroot;
 mod env;
+mod landlock;
 mod resource_limits;
 
 const JAILER_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -43,6 +44,10 @@ pub enum JailerError {
     CgroupInvalidVersion(String),
     #[error("Parent cgroup path is invalid. Path should not be absolute or contain '..' or '.'")]
     CgroupInvalidParentPath(),
+    #[error("Invalid value for --{0}: {1}")]
+    CgroupPreset(String, String),
+    #[error("{0} is only supported with cgroup version 2")]
+    CgroupV2Required(String),
     #[error("Failed to write to cgroups file: {0}")]
     CgroupWrite(io::Error),
     #[error("Failed to change owner for {0}: {1}")]
@@ -89,6 +94,14 @@ pub enum JailerError {
     Gid(String),
     #[error("Invalid instance ID: {0}")]
     InvalidInstanceId(validators::ValidatorError),
+    #[error("{}", format!("Failed to add a Landlock rule for {:?}: {}", .0, .1).replace('\"', ""))]
+    LandlockAddRule(PathBuf, io::Error),
+    #[error("{}", format!("Failed to parse the Firecracker configuration file {:?}: {}", .0, .1).replace('\"', ""))]
+    LandlockConfigFile(PathBuf, serde_json::Error),
+    #[error("Failed to create the Landlock ruleset: {0}")]
+    LandlockCreateRuleset(io::Error),
+    #[error("Failed to enforce the Landlock ruleset: {0}")]
+    LandlockRestrictSelf(io::Error),
     #[error("{}", format!("File {:?} doesn't have a parent", .0).replace('\"', ""))]
     MissingParent(PathBuf),
     #[error("Failed to create the jail root directory before pivoting root: {0}")]
@@ -99,6 +112,8 @@ pub enum JailerError {
     MountBind(io::Error),
     #[error("Failed to change the propagation type to slave: {0}")]
     MountPropagationSlave(io::Error),
+    #[error("Failed to bind mount {1} inside the jail: {0}")]
+    MountBindDev(io::Error, String),
     #[error("{}", format!("{:?} is not a file", .0).replace('\"', ""))]
     NotAFile(PathBuf),
     #[error("{}", format!("{:?} is not a directory", .0).replace('\"', ""))]
@@ -121,12 +136,18 @@ pub enum JailerError {
     ResLimitFormat(String),
     #[error("Invalid limit value for resource: {0}: {1}")]
     ResLimitValue(String, String),
+    #[error("Rootless mode only supports cgroup version 2, got: {0}")]
+    RootlessCgroupVersion(String),
+    #[error("Rootless mode can't join a network namespace, --netns must not be used with it")]
+    RootlessNetNs,
     #[error("Failed to remove old jail root directory: {0}")]
     RmOldRootDir(io::Error),
     #[error("Failed to change current directory: {0}")]
     SetCurrentDir(io::Error),
     #[error("Failed to join network namespace: netns: {0}")]
     SetNetNs(io::Error),
+    #[error("Failed to set the no_new_privs bit: {0}")]
+    SetNoNewPrivs(io::Error),
     #[error("Failed to set limit for resource: {0}")]
     Setrlimit(String),
     #[error("Failed to daemonize: setsid: {0}")]
@@ -139,10 +160,16 @@ pub enum JailerError {
     UnexpectedListenerFd(i32),
     #[error("Failed to unshare into new mount namespace: {0}")]
     UnshareNewNs(io::Error),
-    #[error("Failed to unset the O_CLOEXEC flag on the socket fd: {0}")]
+    #[error("Failed to unshare into new user namespace: {0}")]
+    UnshareNewUserNs(io::Error),
+    #[error("Failed to unset the O_CLOEXEC flag on the fd: {0}")]
     UnsetCloexec(io::Error),
     #[error("Slice contains invalid UTF-8 data : {0}")]
     UTF8Parsing(std::str::Utf8Error),
+    #[error(
+        "Controller {0} can't be used in the vcpu cgroup, which only supports threaded controllers"
+    )]
+    VcpuCgroupController(String),
     #[error("{}", format!("Failed to write to {:?}: {}", .0, .1).replace('\"', ""))]
     Write(PathBuf, io::Error),
 }
@@ -200,6 +227,40 @@ pub fn build_arg_parser() -> ArgParser<'static> {
              <cgroup_file>=<value> (e.g cpu.shares=10). This argument can be used multiple times \
              to add multiple cgroups.",
         ))
+        .arg(Argument::new("cpu-max").takes_value(true).help(
+            "Set the cpu.max cgroup v2 limit of the microVM. It must follow this format: \
+             <quota>[,<period>], where the quota is either max or a number of microseconds, and \
+             the period defaults to 100000 microseconds.",
+        ))
+        .arg(
+            Argument::new("cpu-weight")
+                .takes_value(true)
+                .help("Set the cpu.weight cgroup v2 value of the microVM, between 1 and 10000."),
+        )
+        .arg(Argument::new("memory-max").takes_value(true).help(
+            "Set the memory.max cgroup v2 limit of the microVM, either max or a number of bytes \
+             with an optional K, M, G or T suffix.",
+        ))
+        .arg(Argument::new("memory-high").takes_value(true).help(
+            "Set the memory.high cgroup v2 limit of the microVM, either max or a number of bytes \
+             with an optional K, M, G or T suffix.",
+        ))
+        .arg(Argument::new("pids-max").takes_value(true).help(
+            "Set the pids.max cgroup v2 limit of the microVM, either max or a number of tasks.",
+        ))
+        .arg(Argument::new("io-max").allow_multiple(true).help(
+            "Set the io.max cgroup v2 limits of the microVM for a block device. It must follow \
+             this format: <device>,<key>=<limit>[,<key>=<limit>...], where the device is either a \
+             block device path or <major>:<minor>, the keys are rbps, wbps, riops and wiops, and \
+             the limits are either max or a number. This argument can be used multiple times to \
+             limit multiple devices.",
+        ))
+        .arg(Argument::new("vcpu-cgroup").allow_multiple(true).help(
+            "Cgroup and value to be set by the jailer for the vCPU threads, which are placed in a \
+             threaded cgroup v2 child of the microVM cgroup. It must follow this format: \
+             <cgroup_file>=<value> (e.g cpu.max=50000), and only the cpu, cpuset, perf_event and \
+             pids controllers can be used. This argument can be used multiple times.",
+        ))
         .arg(Argument::new("resource-limit").allow_multiple(true).help(
             "Resource limit values to be set by the jailer. It must follow this format: \
              <resource>=<value> (e.g no-file=1024). This argument can be used multiple times to \
@@ -219,6 +280,34 @@ pub fn build_arg_parser() -> ArgParser<'static> {
                 .takes_value(true)
                 .help("Parent cgroup in which the cgroup of this microvm will be placed."),
         )
+        .arg(Argument::new("rootless").takes_value(false).help(
+            "Run the jailer as an unprivileged user, in a new user namespace where this user is \
+             mapped to the provided uid and gid. Device nodes are bind mounted inside the jail, \
+             and cgroups are created in the delegated cgroup v2 subtree of the parent cgroup. \
+             Can't be used with --netns.",
+        ))
+        .arg(Argument::new("landlock").takes_value(false).help(
+            "Restrict the filesystem access of the jailed process with Landlock, to the files \
+             named in its arguments and configuration file.",
+        ))
+        .arg(
+            Argument::new("landlock-ro")
+                .allow_multiple(true)
+                .requires("landlock")
+                .help(
+                    "Path inside the jail that the jailed process can read, in addition to the \
+                     ones found by --landlock. This argument can be used multiple times.",
+                ),
+        )
+        .arg(
+            Argument::new("landlock-rw")
+                .allow_multiple(true)
+                .requires("landlock")
+                .help(
+                    "Path inside the jail that the jailed process can read and write, in addition \
+                     to the ones found by --landlock. This argument can be used multiple times.",
+                ),
+        )
         .arg(
             Argument::new("version")
                 .takes_value(false)
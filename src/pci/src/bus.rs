@@ -111,11 +111,37 @@ impl PciBus {
         }
     }
 
+    /// Adds a device in the slot `device_id` of the bus, marking the slot as used.
     pub fn add_device(&mut self, device_id: u32, device: Arc<Mutex<dyn PciDevice>>) -> Result<()> {
+        let slot = device_id as usize;
+        if slot >= NUM_DEVICE_IDS {
+            return Err(PciRootError::InvalidPciDeviceSlot(slot));
+        }
+        if self.devices.contains_key(&device_id) {
+            return Err(PciRootError::AlreadyInUsePciDeviceSlot(slot));
+        }
+
+        self.device_ids[slot] = true;
         self.devices.insert(device_id, device);
         Ok(())
     }
 
+    /// Removes the device in the slot `device_id` of the bus, freeing the slot.
+    pub fn remove_device(&mut self, device_id: u32) -> Result<Arc<Mutex<dyn PciDevice>>> {
+        let slot = device_id as usize;
+        // The host bridge cannot be removed.
+        if device_id == 0 {
+            return Err(PciRootError::InvalidPciDeviceSlot(slot));
+        }
+
+        let device = self
+            .devices
+            .remove(&device_id)
+            .ok_or(PciRootError::InvalidPciDeviceSlot(slot))?;
+        self.device_ids[slot] = false;
+        Ok(device)
+    }
+
     pub fn next_device_id(&mut self) -> Result<u32> {
         for (idx, device_id) in self.device_ids.iter_mut().enumerate() {
             if !(*device_id) {
@@ -436,7 +462,7 @@ mod tests {
 
     use vm_device::BusDevice;
 
-    use super::{PciBus, PciConfigIo, PciConfigMmio, PciRoot};
+    use super::{PciBus, PciConfigIo, PciConfigMmio, PciRoot, PciRootError};
     use crate::bus::{DEVICE_ID_INTEL_VIRT_PCIE_HOST, VENDOR_ID_INTEL};
     use crate::{
         DeviceRelocation, PciBarConfiguration, PciBarPrefetchable, PciBarRegionType, PciClassCode,
@@ -626,6 +652,45 @@ mod tests {
         (PciConfigMmio::new(bus.clone()), PciConfigIo::new(bus), mock)
     }
 
+    #[test]
+    fn test_add_remove_device() {
+        let mut bus = PciBus::new(PciRoot::new(None), Arc::new(RelocationMock::default()));
+
+        // Adding a device in a slot reserves it.
+        bus.add_device(3, Arc::new(Mutex::new(PciDevMock::new())))
+            .unwrap();
+        assert!(matches!(
+            bus.add_device(3, Arc::new(Mutex::new(PciDevMock::new()))),
+            Err(PciRootError::AlreadyInUsePciDeviceSlot(3))
+        ));
+        assert!(matches!(
+            bus.add_device(0, Arc::new(Mutex::new(PciDevMock::new()))),
+            Err(PciRootError::AlreadyInUsePciDeviceSlot(0))
+        ));
+        assert!(matches!(
+            bus.add_device(32, Arc::new(Mutex::new(PciDevMock::new()))),
+            Err(PciRootError::InvalidPciDeviceSlot(32))
+        ));
+        for id in [1, 2, 4] {
+            assert_eq!(bus.next_device_id().unwrap(), id);
+        }
+
+        // Removing a device frees its slot.
+        bus.remove_device(3).unwrap();
+        assert!(!bus.devices.contains_key(&3));
+        assert_eq!(bus.next_device_id().unwrap(), 3);
+
+        // Empty slots and the host bridge cannot be removed.
+        assert!(matches!(
+            bus.remove_device(5),
+            Err(PciRootError::InvalidPciDeviceSlot(5))
+        ));
+        assert!(matches!(
+            bus.remove_device(0),
+            Err(PciRootError::InvalidPciDeviceSlot(0))
+        ));
+    }
+
     #[test]
     fn test_invalid_register_boundary_reads() {
         let (mut mmio_config, mut io_config, _) = initialize_bus();
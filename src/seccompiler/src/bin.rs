@@ -1,23 +1,32 @@
 // Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use clap::Parser;
+use std::fs::File;
+use std::io::Write;
+
+use clap::{Args, Parser, Subcommand};
+use seccompiler::learning::{LearningError, generate_filter_diff};
 use seccompiler::{CompilationError, compile_bpf};
 
 const DEFAULT_OUTPUT_FILENAME: &str = "seccomp_binary_filter.out";
 
 #[derive(Debug, Parser)]
-#[command(version = format!("v{}", env!("CARGO_PKG_VERSION")))]
+#[command(
+    version = format!("v{}", env!("CARGO_PKG_VERSION")),
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Cli {
     #[arg(
         short,
         long,
+        required = true,
         help = "The computer architecture where the BPF program runs. Supported architectures: \
                 x86_64, aarch64."
     )]
-    target_arch: String,
-    #[arg(short, long, help = "File path of the JSON input.")]
-    input_file: String,
+    target_arch: Option<String>,
+    #[arg(short, long, required = true, help = "File path of the JSON input.")]
+    input_file: Option<String>,
     #[arg(short, long, help = "Optional path of the output file.", default_value = DEFAULT_OUTPUT_FILENAME)]
     output_file: String,
     #[arg(
@@ -27,14 +36,76 @@ struct Cli {
                 and rule-level actions. Not recommended."
     )]
     basic: bool,
+    #[arg(
+        short,
+        long,
+        help = "Compiles the filters for the Firecracker seccomp learning mode: the syscalls \
+                which are not allowed are recorded instead of applying the default action."
+    )]
+    learning: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generates the rules missing from a JSON file, from a Firecracker seccomp learning log.
+    Learn(LearnArgs),
+}
+
+#[derive(Debug, Args)]
+struct LearnArgs {
+    #[arg(
+        short,
+        long,
+        help = "File path of the JSON filters the log was recorded with."
+    )]
+    input_file: String,
+    #[arg(short, long, help = "File path of the seccomp learning log.")]
+    log_file: String,
+    #[arg(
+        short,
+        long,
+        help = "Optional path of the output file. Defaults to the standard output."
+    )]
+    output_file: Option<String>,
+}
+
+/// Seccompiler-bin errors.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+enum SeccompilerError {
+    /// {0}
+    Compilation(#[from] CompilationError),
+    /// {0}
+    Learning(#[from] LearningError),
+    /// Cannot serialize json: {0}
+    JsonSerialize(serde_json::Error),
+    /// Cannot write output: {0}
+    OutputWrite(std::io::Error),
+}
+
+fn learn(args: &LearnArgs) -> Result<(), SeccompilerError> {
+    let diff = generate_filter_diff(&args.input_file, &args.log_file)?;
+    let json = serde_json::to_string_pretty(&diff).map_err(SeccompilerError::JsonSerialize)?;
+    match &args.output_file {
+        Some(path) => File::create(path).and_then(|mut file| writeln!(file, "{json}")),
+        None => writeln!(std::io::stdout(), "{json}"),
+    }
+    .map_err(SeccompilerError::OutputWrite)
 }
 
-fn main() -> Result<(), CompilationError> {
+fn main() -> Result<(), SeccompilerError> {
     let cli = Cli::parse();
+    if let Some(Command::Learn(args)) = &cli.command {
+        return learn(args);
+    }
+    // Clap requires these arguments when no subcommand is passed.
     compile_bpf(
-        &cli.input_file,
-        &cli.target_arch,
+        &cli.input_file.unwrap(),
+        &cli.target_arch.unwrap(),
         &cli.output_file,
         cli.basic,
-    )
+        cli.learning,
+    )?;
+    Ok(())
 }
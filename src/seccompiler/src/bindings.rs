@@ -75,6 +75,8 @@ pub const SCMP_ACT_TRACE_MASK: u32 = 0x7ff00000;
 pub const fn SCMP_ACT_TRACE(x: u16) -> u32 {
     SCMP_ACT_TRACE_MASK | x as u32
 }
+/// Notify the userspace listener of the filter
+pub const SCMP_ACT_NOTIFY: u32 = 0x7fc00000;
 /// Allow the syscall to be executed after the action has been logged
 pub const SCMP_ACT_LOG: u32 = 0x7ffc0000;
 /// Allow the syscall to be executed
@@ -113,6 +115,16 @@ unsafe extern "C" {
     /// returns [`__NR_SCMP_ERROR`] on failure.
     pub fn seccomp_syscall_resolve_name(name: *const c_char) -> c_int;
 
+    /// Resolve a syscall number to a name
+    ///
+    /// - `arch_token`: the architecture token, e.g. `SCMP_ARCH_*`
+    /// - `num`: the syscall number
+    ///
+    /// Resolve the given syscall number to the syscall name for the given
+    /// architecture.  Returns a pointer to a string allocated with `malloc`, which
+    /// the caller must free, on success; returns `ptr::null()` on failure.
+    pub fn seccomp_syscall_resolve_num_arch(arch_token: u32, num: c_int) -> *mut c_char;
+
     /// Add a new rule to the filter
     ///
     /// - `ctx`: the filter context
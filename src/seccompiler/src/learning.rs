@@ -0,0 +1,356 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Generation of filter diffs from the syscalls recorded by Firecracker in seccomp learning mode.
+
+use std::collections::BTreeMap;
+use std::ffi::{CStr, CString};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::*;
+use crate::types::*;
+
+const LEARNED_RULE_COMMENT: &str = "Recorded in seccomp learning mode";
+
+/// Syscall not allowed by a filter, as recorded by Firecracker in seccomp learning mode.
+#[derive(Debug, Deserialize)]
+pub struct SyscallRecord {
+    /// Thread category of the caller.
+    pub thread: String,
+    /// Audit architecture of the syscall, which is also its libseccomp architecture token.
+    pub arch: u32,
+    /// Syscall number.
+    pub nr: i32,
+    /// Syscall arguments.
+    pub args: [u64; 6],
+}
+
+/// Rules missing from the filter of a thread category.
+#[derive(Debug, Default, Serialize)]
+pub struct FilterDiff {
+    pub filter: Vec<SyscallRule>,
+}
+
+/// Filter diff generation errors.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LearningError {
+    /// Cannot open input file: {0}
+    InputOpen(std::io::Error),
+    /// Cannot read input file: {0}
+    InputRead(std::io::Error),
+    /// Cannot deserialize json: {0}
+    JsonDeserialize(serde_json::Error),
+    /// Cannot open log file: {0}
+    LogOpen(std::io::Error),
+    /// Cannot read log file: {0}
+    LogRead(std::io::Error),
+    /// Cannot parse line {0} of the log file: {1}
+    LogParse(usize, serde_json::Error),
+    /// The input file has no filter for thread category: {0}
+    UnknownThread(String),
+    /// Cannot resolve syscall number {1} for arch {0:#x}
+    LibSeccompSyscall(u32, i32),
+}
+
+/// Generates, for each thread category, the rules to add to the filters of the JSON file at
+/// `input_path` in order to allow the syscalls recorded in the learning log at `log_path`.
+pub fn generate_filter_diff(
+    input_path: &str,
+    log_path: &str,
+) -> Result<BTreeMap<String, FilterDiff>, LearningError> {
+    let mut file_content = String::new();
+    File::open(input_path)
+        .map_err(LearningError::InputOpen)?
+        .read_to_string(&mut file_content)
+        .map_err(LearningError::InputRead)?;
+    let bpf_map_json: BpfJson =
+        serde_json::from_str(&file_content).map_err(LearningError::JsonDeserialize)?;
+
+    let log = BufReader::new(File::open(log_path).map_err(LearningError::LogOpen)?);
+
+    filter_diff(&bpf_map_json, log)
+}
+
+fn filter_diff(
+    bpf_map_json: &BpfJson,
+    log: impl BufRead,
+) -> Result<BTreeMap<String, FilterDiff>, LearningError> {
+    let mut diff: BTreeMap<String, FilterDiff> = BTreeMap::new();
+    for (line_index, line) in log.lines().enumerate() {
+        let line = line.map_err(LearningError::LogRead)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: SyscallRecord = serde_json::from_str(&line)
+            .map_err(|err| LearningError::LogParse(line_index + 1, err))?;
+
+        let filter = bpf_map_json
+            .0
+            .get(&record.thread)
+            .ok_or_else(|| LearningError::UnknownThread(record.thread.clone()))?;
+        let syscall = resolve_syscall(record.arch, record.nr)?;
+
+        if let Some(rule) = missing_rule(filter, syscall, &record.args) {
+            let thread_diff = diff.entry(record.thread).or_default();
+            if !thread_diff.filter.contains(&rule) {
+                thread_diff.filter.push(rule);
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+fn resolve_syscall(arch: u32, nr: i32) -> Result<CString, LearningError> {
+    // SAFETY: Safe as all args are correct.
+    let name = unsafe { seccomp_syscall_resolve_num_arch(arch, nr) };
+    if name.is_null() {
+        return Err(LearningError::LibSeccompSyscall(arch, nr));
+    }
+    // SAFETY: Safe because libseccomp returned a valid nul-terminated string.
+    let syscall = unsafe { CStr::from_ptr(name) }.to_owned();
+    // SAFETY: Safe because the string was allocated by libseccomp with `malloc`
+    // and is not used anymore.
+    unsafe { libc::free(name.cast()) };
+    Ok(syscall)
+}
+
+/// Returns the rule allowing the syscall with the given arguments, or `None` if the filter
+/// already allows it.
+///
+/// When the filter checks the arguments of the syscall, the new rule checks the same
+/// arguments against the recorded values, so that it doesn't allow more than what was
+/// recorded.
+fn missing_rule(filter: &Filter, syscall: CString, args: &[u64; 6]) -> Option<SyscallRule> {
+    let existing_rules: Vec<&SyscallRule> = filter
+        .filter
+        .iter()
+        .filter(|rule| rule.syscall == syscall)
+        .collect();
+    if existing_rules.iter().any(|rule| rule.matches(args)) {
+        return None;
+    }
+
+    // Only the arguments checked by all the existing rules of the syscall are checked.
+    let mut conditions: Vec<SeccompCondition> = Vec::new();
+    for condition in existing_rules
+        .iter()
+        .filter_map(|rule| rule.args.as_ref())
+        .flatten()
+    {
+        if conditions.iter().any(|c| c.index == condition.index)
+            || !existing_rules.iter().all(|rule| {
+                rule.args
+                    .iter()
+                    .flatten()
+                    .any(|c| c.index == condition.index)
+            })
+        {
+            continue;
+        }
+        let Some(&arg) = args.get(usize::from(condition.index)) else {
+            continue;
+        };
+        let (op, val) = match condition.op {
+            SeccompCmpOp::MaskedEq(m) => (SeccompCmpOp::MaskedEq(m), arg & m),
+            _ => match condition.val_len {
+                SeccompCmpArgLen::Dword => (SeccompCmpOp::Eq, arg & 0x00000000FFFFFFFF),
+                SeccompCmpArgLen::Qword => (SeccompCmpOp::Eq, arg),
+            },
+        };
+        conditions.push(SeccompCondition {
+            index: condition.index,
+            op,
+            val,
+            val_len: condition.val_len.clone(),
+        });
+    }
+    conditions.sort_by_key(|condition| condition.index);
+
+    Some(SyscallRule {
+        syscall,
+        args: (!conditions.is_empty()).then_some(conditions),
+        comment: Some(LEARNED_RULE_COMMENT.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    const FILTER_JSON: &str = r#"{
+        "vmm": {
+            "default_action": "trap",
+            "filter_action": "allow",
+            "filter": [
+                {"syscall": "read"},
+                {
+                    "syscall": "ioctl",
+                    "args": [{"index": 1, "type": "dword", "op": "eq", "val": 44672}]
+                },
+                {
+                    "syscall": "futex",
+                    "args": [{"index": 1, "type": "dword", "op": {"masked_eq": 127}, "val": 0}]
+                },
+                {
+                    "syscall": "mmap",
+                    "args": [
+                        {"index": 2, "type": "qword", "op": "eq", "val": 3},
+                        {"index": 3, "type": "dword", "op": "eq", "val": 34}
+                    ]
+                },
+                {
+                    "syscall": "mmap",
+                    "args": [{"index": 3, "type": "dword", "op": "eq", "val": 50}]
+                }
+            ]
+        }
+    }"#;
+
+    fn bpf_map_json() -> BpfJson {
+        serde_json::from_str(FILTER_JSON).unwrap()
+    }
+
+    fn learned_rule(syscall: &str, args: Option<Vec<SeccompCondition>>) -> SyscallRule {
+        SyscallRule {
+            syscall: CString::new(syscall).unwrap(),
+            args,
+            comment: Some(LEARNED_RULE_COMMENT.to_string()),
+        }
+    }
+
+    fn missing(syscall: &str, args: [u64; 6]) -> Option<SyscallRule> {
+        let bpf_map_json = bpf_map_json();
+        missing_rule(
+            &bpf_map_json.0["vmm"],
+            CString::new(syscall).unwrap(),
+            &args,
+        )
+    }
+
+    #[test]
+    fn test_missing_rule() {
+        // Syscalls already allowed need no rule.
+        assert_eq!(missing("read", [0, 1, 2, 3, 4, 5]), None);
+        assert_eq!(
+            missing("ioctl", [12, 0xFFFF_FFFF_0000_AE80, 0, 0, 0, 0]),
+            None
+        );
+        assert_eq!(missing("futex", [0, 0x80, 0, 0, 0, 0]), None);
+        assert_eq!(missing("mmap", [0, 4096, 3, 34, 0, 0]), None);
+        assert_eq!(missing("mmap", [0, 4096, 1, 50, 0, 0]), None);
+
+        // Syscalls without rules are allowed whatever their arguments.
+        assert_eq!(
+            missing("write", [1, 2, 3, 0, 0, 0]),
+            Some(learned_rule("write", None))
+        );
+
+        // Otherwise, the arguments checked by the existing rules are checked against the
+        // recorded values, only keeping the low 32 bits of Dword arguments.
+        assert_eq!(
+            missing("ioctl", [12, 0xFFFF_FFFF_0000_AE81, 0, 0, 0, 0]),
+            Some(learned_rule(
+                "ioctl",
+                Some(vec![SeccompCondition {
+                    index: 1,
+                    op: SeccompCmpOp::Eq,
+                    val: 0xAE81,
+                    val_len: SeccompCmpArgLen::Dword,
+                }])
+            ))
+        );
+
+        // The mask of masked comparisons is kept.
+        assert_eq!(
+            missing("futex", [0, 0x81, 0, 0, 0, 0]),
+            Some(learned_rule(
+                "futex",
+                Some(vec![SeccompCondition {
+                    index: 1,
+                    op: SeccompCmpOp::MaskedEq(127),
+                    val: 1,
+                    val_len: SeccompCmpArgLen::Dword,
+                }])
+            ))
+        );
+
+        // Only the arguments checked by all the existing rules of the syscall are checked.
+        assert_eq!(
+            missing("mmap", [0, 4096, 3, 0x1_0000_0002, 0, 0]),
+            Some(learned_rule(
+                "mmap",
+                Some(vec![SeccompCondition {
+                    index: 3,
+                    op: SeccompCmpOp::Eq,
+                    val: 2,
+                    val_len: SeccompCmpArgLen::Dword,
+                }])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_filter_diff() {
+        let record = |thread: &str, nr: i32, args: &str| {
+            format!(
+                "{{\"thread\":\"{thread}\",\"arch\":{SCMP_ARCH_X86_64},\"nr\":{nr},\"args\":\
+                 [{args}]}}\n"
+            )
+        };
+        // On x86_64, syscalls 0, 1 and 16 are read, write and ioctl.
+        let log = [
+            record("vmm", 1, "1,0,0,0,0,0"),
+            "\n".to_string(),
+            record("vmm", 0, "3,0,0,0,0,0"),
+            record("vmm", 16, "12,44673,0,0,0,0"),
+            record("vmm", 1, "2,0,0,0,0,0"),
+            record("vmm", 16, "13,44673,0,0,0,0"),
+        ]
+        .concat();
+
+        // Each missing rule is only generated once.
+        let diff = filter_diff(&bpf_map_json(), Cursor::new(&log)).unwrap();
+        assert_eq!(diff.keys().collect::<Vec<_>>(), ["vmm"]);
+        assert_eq!(diff["vmm"].filter.len(), 2);
+        assert_eq!(diff["vmm"].filter[0], learned_rule("write", None));
+        assert_eq!(diff["vmm"].filter[1].syscall.to_str().unwrap(), "ioctl");
+
+        // Syscalls already allowed lead to an empty diff.
+        let log = record("vmm", 0, "3,0,0,0,0,0");
+        let diff = filter_diff(&bpf_map_json(), Cursor::new(&log)).unwrap();
+        assert!(diff.is_empty());
+
+        // The input file must have a filter for the thread category of every record.
+        let log = record("api", 1, "1,0,0,0,0,0");
+        let err = filter_diff(&bpf_map_json(), Cursor::new(&log)).unwrap_err();
+        assert!(
+            matches!(err, LearningError::UnknownThread(ref thread) if thread == "api"),
+            "{:?}",
+            err
+        );
+
+        // The syscall numbers must be known.
+        let log = record("vmm", -1, "0,0,0,0,0,0");
+        let err = filter_diff(&bpf_map_json(), Cursor::new(&log)).unwrap_err();
+        assert!(
+            matches!(err, LearningError::LibSeccompSyscall(SCMP_ARCH_X86_64, -1)),
+            "{:?}",
+            err
+        );
+
+        // Invalid lines are reported with their line number.
+        let log = [
+            record("vmm", 1, "1,0,0,0,0,0"),
+            "{\"thread\":\"vmm\"}\n".to_string(),
+        ]
+        .concat();
+        let err = filter_diff(&bpf_map_json(), Cursor::new(&log)).unwrap_err();
+        assert!(matches!(err, LearningError::LogParse(2, _)), "{:?}", err);
+    }
+}
@@ -15,6 +15,7 @@ use bincode::error::EncodeError as BincodeError;
 mod bindings;
 use bindings::*;
 
+pub mod learning;
 pub mod types;
 pub use types::*;
 use zerocopy::IntoBytes;
@@ -69,6 +70,7 @@ pub fn compile_bpf(
     arch: &str,
     out_path: &str,
     basic: bool,
+    learning: bool,
 ) -> Result<(), CompilationError> {
     let mut file_content = String::new();
     File::open(input_path)
@@ -93,7 +95,14 @@ pub fn compile_bpf(
 
     let mut bpf_map: BTreeMap<String, Vec<u64>> = BTreeMap::new();
     for (name, filter) in bpf_map_json.0.iter() {
-        let default_action = filter.default_action.to_scmp_type();
+        // In learning mode, the syscalls which are not allowed by the filter are
+        // handed to the seccomp listener of Firecracker, instead of applying the
+        // default action.
+        let default_action = if learning {
+            SCMP_ACT_NOTIFY
+        } else {
+            filter.default_action.to_scmp_type()
+        };
         let filter_action = filter.filter_action.to_scmp_type();
 
         // SAFETY: Safe as all args are correct.
@@ -11,7 +11,7 @@ use serde::*;
 use crate::bindings::*;
 
 /// Comparison to perform when matching a condition.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum SeccompCmpOp {
     Eq,
@@ -24,7 +24,7 @@ pub enum SeccompCmpOp {
 }
 
 /// Seccomp argument value length.
-#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SeccompCmpArgLen {
     /// Argument value length is 4 bytes.
@@ -34,7 +34,7 @@ pub enum SeccompCmpArgLen {
 }
 
 /// Condition that syscall must match in order to satisfy a rule.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct SeccompCondition {
     pub index: u8,
     pub op: SeccompCmpOp,
@@ -44,6 +44,27 @@ pub struct SeccompCondition {
 }
 
 impl SeccompCondition {
+    /// Whether the syscall arguments satisfy the condition, as checked by the compiled filter.
+    pub fn matches(&self, args: &[u64; 6]) -> bool {
+        let Some(&arg) = args.get(usize::from(self.index)) else {
+            return false;
+        };
+        // Only the low 32 bits of Dword arguments are compared, whatever the operator.
+        let arg = match self.val_len {
+            SeccompCmpArgLen::Dword => arg & 0x00000000FFFFFFFF,
+            SeccompCmpArgLen::Qword => arg,
+        };
+        match self.op {
+            SeccompCmpOp::Eq => arg == self.val,
+            SeccompCmpOp::Ge => arg >= self.val,
+            SeccompCmpOp::Gt => arg > self.val,
+            SeccompCmpOp::Le => arg <= self.val,
+            SeccompCmpOp::Lt => arg < self.val,
+            SeccompCmpOp::Ne => arg != self.val,
+            SeccompCmpOp::MaskedEq(m) => arg & m == self.val,
+        }
+    }
+
     pub fn to_scmp_type(&self) -> scmp_arg_cmp {
         match self.op {
             SeccompCmpOp::Eq => {
@@ -146,10 +167,28 @@ impl SeccompAction {
 /// If all conditions match then rule gets matched.
 /// The action of the first rule that matches will be applied to the calling process.
 /// If no rule matches the default action is applied.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub struct SyscallRule {
+    #[serde(serialize_with = "serialize_syscall")]
     pub syscall: CString,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub args: Option<Vec<SeccompCondition>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+impl SyscallRule {
+    /// Whether the syscall arguments satisfy all the conditions of the rule.
+    pub fn matches(&self, args: &[u64; 6]) -> bool {
+        self.args
+            .iter()
+            .flatten()
+            .all(|condition| condition.matches(args))
+    }
+}
+
+fn serialize_syscall<S: Serializer>(syscall: &CString, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&syscall.to_string_lossy())
 }
 
 /// Filter containing rules assigned to syscall numbers.
@@ -190,3 +229,76 @@ impl FromStr for TargetArch {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn condition(op: SeccompCmpOp, val: u64, val_len: SeccompCmpArgLen) -> SeccompCondition {
+        SeccompCondition {
+            index: 1,
+            op,
+            val,
+            val_len,
+        }
+    }
+
+    #[test]
+    fn test_condition_matches() {
+        let args = [0, 0xFFFF_FFFF_0000_0010, 0, 0, 0, 0];
+
+        // Only the low 32 bits of Dword arguments are compared.
+        let dword = |op, val| condition(op, val, SeccompCmpArgLen::Dword).matches(&args);
+        assert!(dword(SeccompCmpOp::Eq, 0x10));
+        assert!(dword(SeccompCmpOp::Ge, 0x10));
+        assert!(!dword(SeccompCmpOp::Gt, 0x10));
+        assert!(dword(SeccompCmpOp::Le, 0x10));
+        assert!(!dword(SeccompCmpOp::Lt, 0x10));
+        assert!(!dword(SeccompCmpOp::Ne, 0x10));
+        assert!(dword(SeccompCmpOp::Lt, 0x11));
+        assert!(dword(SeccompCmpOp::MaskedEq(0xF0), 0x10));
+        assert!(!dword(
+            SeccompCmpOp::MaskedEq(0xFFFF_FFFF_0000_00F0),
+            0xFFFF_FFFF_0000_0010
+        ));
+
+        // While Qword arguments are compared as a whole.
+        let qword = |op, val| condition(op, val, SeccompCmpArgLen::Qword).matches(&args);
+        assert!(!qword(SeccompCmpOp::Eq, 0x10));
+        assert!(qword(SeccompCmpOp::Eq, 0xFFFF_FFFF_0000_0010));
+        assert!(qword(SeccompCmpOp::Gt, 0x10));
+        assert!(!qword(SeccompCmpOp::Le, 0x10));
+        assert!(qword(SeccompCmpOp::Ne, 0x10));
+        assert!(qword(
+            SeccompCmpOp::MaskedEq(0xFFFF_FFFF_0000_00F0),
+            0xFFFF_FFFF_0000_0010
+        ));
+
+        // An argument index out of range never matches.
+        let out_of_range = SeccompCondition {
+            index: 6,
+            ..condition(SeccompCmpOp::Ge, 0, SeccompCmpArgLen::Qword)
+        };
+        assert!(!out_of_range.matches(&args));
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let mut rule = SyscallRule {
+            syscall: CString::new("ioctl").unwrap(),
+            args: None,
+            comment: None,
+        };
+        // A rule without conditions matches any arguments.
+        assert!(rule.matches(&[1, 2, 3, 4, 5, 6]));
+
+        // Otherwise, all of its conditions must match.
+        rule.args = Some(vec![
+            condition(SeccompCmpOp::Eq, 2, SeccompCmpArgLen::Dword),
+            condition(SeccompCmpOp::Le, 3, SeccompCmpArgLen::Qword),
+        ]);
+        assert!(rule.matches(&[1, 2, 3, 4, 5, 6]));
+        rule.args.as_mut().unwrap()[1].index = 3;
+        assert!(!rule.matches(&[1, 2, 3, 4, 5, 6]));
+    }
+}
@@ -26,6 +26,10 @@ pub enum EditMemoryError {
     SeekMemory(std::io::Error),
     /// Failed to send the file: {0}
     SendFile(std::io::Error),
+    /// The output file cannot be one of the memory files of the chain.
+    OutputInChain,
+    /// Failed to copy the base memory file: {0}
+    CopyBase(std::io::Error),
 }
 
 #[derive(Debug, Subcommand)]
@@ -39,6 +43,16 @@ pub enum EditMemorySubCommand {
         #[arg(short, long)]
         diff_path: PathBuf,
     },
+    /// Squash a chain of snapshots into a full memory file
+    Squash {
+        /// Paths to the memory files of the chain, from the full snapshot at its base to the
+        /// last diff snapshot.
+        #[arg(short, long, num_args = 1.., required = true)]
+        memory_paths: Vec<PathBuf>,
+        /// Path to the output memory file.
+        #[arg(short, long)]
+        output_path: PathBuf,
+    },
 }
 
 pub fn edit_memory_command(command: EditMemorySubCommand) -> Result<(), EditMemoryError> {
@@ -47,6 +61,24 @@ pub fn edit_memory_command(command: EditMemorySubCommand) -> Result<(), EditMemo
             memory_path,
             diff_path,
         } => rebase(memory_path, diff_path)?,
+        EditMemorySubCommand::Squash {
+            memory_paths,
+            output_path,
+        } => squash(&memory_paths, output_path)?,
+    }
+    Ok(())
+}
+
+fn squash(memory_paths: &[PathBuf], output_path: PathBuf) -> Result<(), EditMemoryError> {
+    if memory_paths.contains(&output_path) {
+        return Err(EditMemoryError::OutputInChain);
+    }
+    // Clap guarantees there is at least one memory file.
+    let (base_path, diff_paths) = memory_paths.split_first().unwrap();
+
+    std::fs::copy(base_path, &output_path).map_err(EditMemoryError::CopyBase)?;
+    for diff_path in diff_paths {
+        rebase(output_path.clone(), diff_path.clone())?;
     }
     Ok(())
 }
@@ -122,6 +154,48 @@ mod tests {
         assert_eq!(&buf, expected_content);
     }
 
+    #[test]
+    fn test_squash() {
+        let block_size = 4096;
+        let base = tempfile::TempFile::new().unwrap();
+        let first_diff = tempfile::TempFile::new().unwrap();
+        let second_diff = tempfile::TempFile::new().unwrap();
+        let output = tempfile::TempFile::new().unwrap();
+
+        // base:        [b] [b] [b]
+        // first diff:  [1] ___ [1]
+        // second diff: ___ ___ [2]
+        // expected:    [1] [b] [2]
+        let base_block = rand::rand_bytes(block_size);
+        let first_block = rand::rand_bytes(block_size);
+        let second_block = rand::rand_bytes(block_size);
+        base.as_file().write_all(&base_block.repeat(3)).unwrap();
+        first_diff.as_file().write_all(&first_block).unwrap();
+        first_diff
+            .as_file()
+            .write_all_at(&first_block, 2 * block_size as u64)
+            .unwrap();
+        second_diff
+            .as_file()
+            .write_all_at(&second_block, 2 * block_size as u64)
+            .unwrap();
+
+        let memory_paths = [&base, &first_diff, &second_diff]
+            .map(|file| file.as_path().to_path_buf())
+            .to_vec();
+        squash(&memory_paths, output.as_path().to_path_buf()).unwrap();
+
+        let expected_result = [first_block, base_block.clone(), second_block].concat();
+        check_file_content(output.as_file(), &expected_result);
+        // The chain is left untouched.
+        check_file_content(base.as_file(), &base_block.repeat(3));
+
+        assert!(matches!(
+            squash(&memory_paths, memory_paths[0].clone()),
+            Err(EditMemoryError::OutputInChain)
+        ));
+    }
+
     #[test]
     fn test_rebase_empty_files() {
         let base = tempfile::TempFile::new().unwrap();
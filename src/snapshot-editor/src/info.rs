@@ -35,6 +35,12 @@ pub enum InfoVmStateSubCommand {
         #[arg(short, long)]
         vmstate_path: PathBuf,
     },
+    /// Print the parent of a diff snapshot.
+    Parent {
+        /// Path to the vmstate file.
+        #[arg(short, long)]
+        vmstate_path: PathBuf,
+    },
 }
 
 pub fn info_vmstate_command(command: InfoVmStateSubCommand) -> Result<(), InfoVmStateError> {
@@ -44,6 +50,7 @@ pub fn info_vmstate_command(command: InfoVmStateSubCommand) -> Result<(), InfoVm
             info(&vmstate_path, info_vcpu_states)?
         }
         InfoVmStateSubCommand::VmState { vmstate_path } => info(&vmstate_path, info_vmstate)?,
+        InfoVmStateSubCommand::Parent { vmstate_path } => info(&vmstate_path, info_parent)?,
     }
     Ok(())
 }
@@ -74,3 +81,15 @@ fn info_vmstate(snapshot: &Snapshot<MicrovmState>) -> Result<(), InfoVmStateErro
     println!("{:#?}", snapshot.data);
     Ok(())
 }
+
+fn info_parent(snapshot: &Snapshot<MicrovmState>) -> Result<(), InfoVmStateError> {
+    match &snapshot.data.parent {
+        Some(parent) => println!(
+            "{} (checksum: {:#018x})",
+            parent.snapshot_path.display(),
+            parent.checksum
+        ),
+        None => println!("none"),
+    }
+    Ok(())
+}
@@ -0,0 +1,329 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contents of the guest memory, read from the memory files of a snapshot.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use vmm::snapshot::mem_file::{MEM_FILE_MAGIC, MemFileDecoder, MemFileError};
+use vmm::utils::{u64_to_usize, usize_to_u64};
+use vmm::vmm_config::snapshot::MemFileKey;
+use vmm_sys_util::seek_hole::SeekHole;
+
+/// Errors related to loading the memory files of a snapshot.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum BackendError {
+    /// No memory file given.
+    NoMemFile,
+    /// Failed to open memory file {0}: {1}
+    Open(PathBuf, std::io::Error),
+    /// Failed to find the data of memory file {0}: {1}
+    Extents(PathBuf, std::io::Error),
+    /// Failed to map memory file {0}: {1}
+    Mmap(PathBuf, std::io::Error),
+    /// Failed to decode memory file {0}: {1}
+    Decode(PathBuf, MemFileError),
+    /// Only the base memory file can be encoded, {0} is an encoded diff memory file.
+    EncodedDiff(PathBuf),
+    /// Diff memory file {0} is larger than the {1} bytes of the base memory file.
+    Size(PathBuf, u64),
+}
+
+/// Memory file mapped in the address space of the page server.
+#[derive(Debug)]
+struct Layer {
+    addr: *mut u8,
+    len: usize,
+    /// Sorted ranges of the file holding data. Holes are left to the layers below, and read as
+    /// zeroes in the base layer.
+    extents: Vec<Range<u64>>,
+}
+
+// SAFETY: The mapping of a layer is only read once the layer is built, and is unmapped when the
+// layer is dropped.
+unsafe impl Send for Layer {}
+// SAFETY: See above.
+unsafe impl Sync for Layer {}
+
+impl Layer {
+    fn mmap(path: &Path, len: usize, prot: i32, flags: i32, fd: i32) -> Result<Self, BackendError> {
+        if len == 0 {
+            return Ok(Self {
+                addr: ptr::null_mut(),
+                len,
+                extents: Vec::new(),
+            });
+        }
+        // SAFETY: The mapping is private to the layer, and `fd` is either -1 for an anonymous
+        // mapping or the descriptor of a file of at least `len` bytes.
+        let addr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, fd, 0) };
+        if addr == libc::MAP_FAILED {
+            return Err(BackendError::Mmap(
+                path.to_path_buf(),
+                std::io::Error::last_os_error(),
+            ));
+        }
+        Ok(Self {
+            addr: addr.cast(),
+            len,
+            extents: Vec::new(),
+        })
+    }
+
+    /// Maps a raw memory file, whose holes are not backed by any data.
+    fn map(path: &Path, mut file: File) -> Result<Self, BackendError> {
+        let extents_err = |err| BackendError::Extents(path.to_path_buf(), err);
+        let file_len = file.metadata().map_err(extents_err)?.len();
+        let mut layer = Self::mmap(
+            path,
+            u64_to_usize(file_len),
+            libc::PROT_READ,
+            libc::MAP_PRIVATE | libc::MAP_NORESERVE,
+            file.as_raw_fd(),
+        )?;
+
+        let mut cursor = 0;
+        while let Some(data_start) = file.seek_data(cursor).map_err(extents_err)? {
+            let data_end = file
+                .seek_hole(data_start)
+                .map_err(extents_err)?
+                .unwrap_or(file_len)
+                .min(file_len);
+            layer.extents.push(data_start..data_end);
+            cursor = data_end;
+        }
+        Ok(layer)
+    }
+
+    /// Decodes an encoded memory file into anonymous memory.
+    fn decode(path: &Path, file: File, key: Option<&MemFileKey>) -> Result<Self, BackendError> {
+        let decode_err = |err| BackendError::Decode(path.to_path_buf(), err);
+        let mut decoder = MemFileDecoder::new(BufReader::new(file), key).map_err(decode_err)?;
+        let mem_size = decoder.header().mem_size;
+        let mut layer = Self::mmap(
+            path,
+            u64_to_usize(mem_size),
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+            -1,
+        )?;
+        if layer.len > 0 {
+            // SAFETY: `len` bytes are mapped at `addr`, and are not referenced anywhere else.
+            let contents = unsafe { std::slice::from_raw_parts_mut(layer.addr, layer.len) };
+            decoder.read_exact(contents).map_err(decode_err)?;
+            layer.extents.push(0..mem_size);
+        }
+        Ok(layer)
+    }
+
+    /// Returns the first range of data of the layer ending after `offset`.
+    fn next_extent(&self, offset: u64) -> Option<&Range<u64>> {
+        let index = self.extents.partition_point(|extent| extent.end <= offset);
+        self.extents.get(index)
+    }
+}
+
+impl Drop for Layer {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: `len` bytes were mapped at `addr` when building the layer.
+            unsafe { libc::munmap(self.addr.cast(), self.len) };
+        }
+    }
+}
+
+/// Where a range of guest memory is read from.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Source {
+    /// The range is a hole in all the memory files, and reads as zeroes.
+    Zero,
+    /// The range is mapped at this address in the page server.
+    Data(*const u8),
+}
+
+/// Guest memory of a snapshot, from its base memory file overlaid with its diff memory files.
+#[derive(Debug)]
+pub struct MemoryBackend {
+    /// Layers from the base memory file to the most recent diff memory file.
+    layers: Vec<Layer>,
+    size: u64,
+}
+
+impl MemoryBackend {
+    /// Loads the memory files at `paths`, ordered from the base memory file to the memory file
+    /// of the snapshot being loaded. Only the base memory file can be encoded, in which case it
+    /// is decoded in memory, using `key` if it is encrypted.
+    pub fn new(paths: &[PathBuf], key: Option<&MemFileKey>) -> Result<Self, BackendError> {
+        let (base_path, diff_paths) = paths.split_first().ok_or(BackendError::NoMemFile)?;
+
+        let (base, encoded) = open(base_path)?;
+        let base = match (encoded, key) {
+            (true, _) => Layer::decode(base_path, base, key)?,
+            (false, Some(_)) => {
+                return Err(BackendError::Decode(
+                    base_path.clone(),
+                    MemFileError::NotEncrypted,
+                ));
+            }
+            (false, None) => Layer::map(base_path, base)?,
+        };
+        let size = usize_to_u64(base.len);
+
+        let mut layers = vec![base];
+        for path in diff_paths {
+            let (file, encoded) = open(path)?;
+            if encoded {
+                return Err(BackendError::EncodedDiff(path.clone()));
+            }
+            let layer = Layer::map(path, file)?;
+            if usize_to_u64(layer.len) > size {
+                return Err(BackendError::Size(path.clone(), size));
+            }
+            layers.push(layer);
+        }
+
+        Ok(Self { layers, size })
+    }
+
+    /// Size of the guest memory.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns where the guest memory at `offset` is read from, and the length of the range
+    /// starting at `offset`, up to `max_len`, read from the same place.
+    pub fn source(&self, offset: u64, max_len: u64) -> (Source, u64) {
+        let mut end = offset + max_len;
+        for layer in self.layers.iter().rev() {
+            match layer.next_extent(offset) {
+                Some(extent) if extent.start <= offset => {
+                    end = end.min(extent.end);
+                    // SAFETY: `offset` is within an extent of the file, so within its mapping.
+                    let addr = unsafe { layer.addr.add(u64_to_usize(offset)) };
+                    return (Source::Data(addr), end - offset);
+                }
+                // The range stops where a more recent layer has data.
+                Some(extent) => end = end.min(extent.start),
+                None => {}
+            }
+        }
+        (Source::Zero, end - offset)
+    }
+
+    /// Copies the guest memory at `offset` into `buf`.
+    pub fn read(&self, mut offset: u64, mut buf: &mut [u8]) {
+        debug_assert!(offset + usize_to_u64(buf.len()) <= self.size);
+        while !buf.is_empty() {
+            let (source, len) = self.source(offset, usize_to_u64(buf.len()));
+            let (head, tail) = buf.split_at_mut(u64_to_usize(len));
+            match source {
+                Source::Zero => head.fill(0),
+                Source::Data(addr) => {
+                    // SAFETY: `len` bytes of the same extent are mapped at `addr`.
+                    head.copy_from_slice(unsafe { std::slice::from_raw_parts(addr, head.len()) })
+                }
+            }
+            offset += len;
+            buf = tail;
+        }
+    }
+}
+
+/// Opens a memory file, and tells whether it is encoded.
+fn open(path: &Path) -> Result<(File, bool), BackendError> {
+    let file = File::open(path).map_err(|err| BackendError::Open(path.to_path_buf(), err))?;
+    let mut magic = [0u8; MEM_FILE_MAGIC.len()];
+    let encoded = file.read_exact_at(&mut magic, 0).is_ok() && magic == MEM_FILE_MAGIC;
+    Ok((file, encoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const PAGE: u64 = 4096;
+
+    fn sparse_file(len: u64, pages: &[(u64, u8)]) -> TempFile {
+        let file = TempFile::new().unwrap();
+        file.as_file().set_len(len).unwrap();
+        for (page, byte) in pages {
+            file.as_file()
+                .write_all_at(&[*byte; 4096], page * PAGE)
+                .unwrap();
+        }
+        file
+    }
+
+    #[test]
+    fn test_layers() {
+        let base = sparse_file(4 * PAGE, &[(0, 0xaa), (1, 0xaa), (3, 0xbb)]);
+        let diff = sparse_file(4 * PAGE, &[(1, 0xcc), (2, 0xdd)]);
+        let paths = [base.as_path().to_path_buf(), diff.as_path().to_path_buf()];
+        let backend = MemoryBackend::new(&paths, None).unwrap();
+        assert_eq!(backend.size(), 4 * PAGE);
+
+        // The first page is only in the base layer, up to the data of the diff layer.
+        let (source, len) = backend.source(0, 4 * PAGE);
+        assert!(matches!(source, Source::Data(_)));
+        assert_eq!(len, PAGE);
+        // The range is capped by the requested length.
+        let (source, len) = backend.source(0, PAGE / 2);
+        assert!(matches!(source, Source::Data(_)));
+        assert_eq!(len, PAGE / 2);
+
+        let mut contents = vec![0u8; u64_to_usize(4 * PAGE)];
+        backend.read(0, &mut contents);
+        let pages: Vec<u8> = contents.chunks(4096).map(|page| page[0]).collect();
+        assert_eq!(pages, [0xaa, 0xcc, 0xdd, 0xbb]);
+        assert!(
+            contents
+                .chunks(4096)
+                .all(|page| page.iter().all(|b| *b == page[0]))
+        );
+
+        let base = sparse_file(4 * PAGE, &[(0, 0xaa)]);
+        let backend = MemoryBackend::new(&[base.as_path().to_path_buf()], None).unwrap();
+        assert_eq!(backend.source(PAGE, 3 * PAGE), (Source::Zero, 3 * PAGE));
+    }
+
+    #[test]
+    fn test_invalid_layers() {
+        assert!(matches!(
+            MemoryBackend::new(&[], None),
+            Err(BackendError::NoMemFile)
+        ));
+
+        let base = sparse_file(PAGE, &[]);
+        let larger = sparse_file(2 * PAGE, &[]);
+        let paths = [base.as_path().to_path_buf(), larger.as_path().to_path_buf()];
+        assert!(matches!(
+            MemoryBackend::new(&paths, None),
+            Err(BackendError::Size(_, size)) if size == PAGE
+        ));
+
+        let encoded = TempFile::new().unwrap();
+        encoded.as_file().write_all_at(&MEM_FILE_MAGIC, 0).unwrap();
+        let paths = [
+            base.as_path().to_path_buf(),
+            encoded.as_path().to_path_buf(),
+        ];
+        assert!(matches!(
+            MemoryBackend::new(&paths, None),
+            Err(BackendError::EncodedDiff(_))
+        ));
+
+        let key = MemFileKey("key".to_string());
+        assert!(matches!(
+            MemoryBackend::new(&paths[..1], Some(&key)),
+            Err(BackendError::Decode(_, MemFileError::NotEncrypted))
+        ));
+    }
+}
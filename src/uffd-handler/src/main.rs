@@ -0,0 +1,224 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use userfaultfd::Uffd;
+use vmm::persist::GuestRegionUffdMapping;
+use vmm::vmm_config::snapshot::MemFileKey;
+use vmm_sys_util::sock_ctrl_msg::ScmSocket;
+
+mod backend;
+mod server;
+
+use backend::{BackendError, MemoryBackend};
+use server::{PageServer, ServerError};
+
+/// Maximum size of the guest memory mappings sent by Firecracker.
+const HANDSHAKE_MAX_LEN: usize = 64 << 10;
+/// Number of attempts at receiving the userfaultfd from Firecracker.
+const HANDSHAKE_ATTEMPTS: u32 = 5;
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+enum UffdHandlerError {
+    /// Failed to read the memory file key: {0}
+    Key(std::io::Error),
+    /// Failed to load the memory files: {0}
+    Backend(#[from] BackendError),
+    /// Failed to listen on the socket: {0}
+    Listen(std::io::Error),
+    /// Failed to get the process ID of Firecracker: {0}
+    PeerCredentials(std::io::Error),
+    /// Failed to receive the userfaultfd from Firecracker: {0}
+    Handshake(std::io::Error),
+    /// Firecracker did not send a userfaultfd.
+    NoUffd,
+    /// Invalid guest memory mappings: {0}
+    Mappings(serde_json::Error),
+    /// Failed to start the prefetch thread: {0}
+    Prefetch(std::io::Error),
+    /// Failed to poll the socket and the userfaultfd: {0}
+    Poll(std::io::Error),
+    /// Failed to serve guest memory: {0}
+    Server(#[from] ServerError),
+}
+
+/// Serves the guest memory of a snapshot to a Firecracker process loading it with the `Uffd`
+/// memory backend.
+#[derive(Debug, Parser)]
+#[command(version = format!("v{}", env!("CARGO_PKG_VERSION")))]
+struct Cli {
+    /// Path of the Unix domain socket to create, given to Firecracker as `backend_path`.
+    #[arg(long)]
+    socket: PathBuf,
+    /// Memory file of the snapshot. For diff snapshots, repeat it for each layer, from the
+    /// memory file of the full snapshot to the memory file of the snapshot being loaded.
+    #[arg(long, required = true)]
+    mem_file: Vec<PathBuf>,
+    /// File holding the base64 encoded key of an encrypted memory file.
+    #[arg(long)]
+    mem_file_key: Option<PathBuf>,
+    /// Populate the whole guest memory in the background, instead of only on page faults.
+    #[arg(long)]
+    prefetch: bool,
+}
+
+fn main_exec() -> Result<(), UffdHandlerError> {
+    let cli = Cli::parse();
+
+    let key = cli
+        .mem_file_key
+        .map(|path| std::fs::read_to_string(path).map(|key| MemFileKey(key.trim().to_string())))
+        .transpose()
+        .map_err(UffdHandlerError::Key)?;
+    let backend = MemoryBackend::new(&cli.mem_file, key.as_ref())?;
+
+    let listener = UnixListener::bind(&cli.socket).map_err(UffdHandlerError::Listen)?;
+    let (stream, _) = listener.accept().map_err(UffdHandlerError::Listen)?;
+
+    // Firecracker waits for its guest memory forever if the page server stops serving it, so
+    // it is killed instead.
+    let firecracker_pid = peer_pid(&stream)?;
+    let default_panic_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic_info| {
+        kill(firecracker_pid);
+        default_panic_hook(panic_info);
+    }));
+
+    let result = serve(&stream, backend, cli.prefetch);
+    if result.is_err() {
+        kill(firecracker_pid);
+    }
+    result
+}
+
+/// Serves the guest memory of the Firecracker process connected with `stream`, until it exits.
+fn serve(
+    stream: &UnixStream,
+    backend: MemoryBackend,
+    prefetch: bool,
+) -> Result<(), UffdHandlerError> {
+    let (mappings, uffd) = receive_handshake(stream)?;
+    let server = Arc::new(PageServer::new(uffd, mappings, backend)?);
+
+    if prefetch {
+        let server = server.clone();
+        std::thread::Builder::new()
+            .name("prefetch".to_string())
+            .spawn(move || {
+                // Page faults are still served without prefetching.
+                if let Err(err) = server.prefetch() {
+                    eprintln!("Stopped prefetching guest memory: {err}");
+                }
+            })
+            .map_err(UffdHandlerError::Prefetch)?;
+    }
+
+    let mut pollfds = [
+        // Firecracker closes the socket when it exits.
+        libc::pollfd {
+            fd: stream.as_raw_fd(),
+            events: 0,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: server.uffd().as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let mut faults = Vec::new();
+    loop {
+        // SAFETY: `pollfds` is a valid array of `pollfd` of the given length.
+        let ret = unsafe { libc::poll(pollfds.as_mut_ptr(), 2, -1) };
+        if ret == -1 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == std::io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(UffdHandlerError::Poll(err));
+        }
+        if pollfds
+            .iter()
+            .any(|pollfd| pollfd.revents & (libc::POLLHUP | libc::POLLERR) != 0)
+        {
+            return Ok(());
+        }
+        if pollfds[1].revents & libc::POLLIN != 0 {
+            server.handle_events(&mut faults)?;
+        }
+    }
+}
+
+/// Receives the guest memory mappings, and the userfaultfd they are registered with.
+fn receive_handshake(
+    stream: &UnixStream,
+) -> Result<(Vec<GuestRegionUffdMapping>, Uffd), UffdHandlerError> {
+    let mut message = vec![0u8; HANDSHAKE_MAX_LEN];
+    // The userfaultfd is sometimes missing from the first message read, so it is read again a
+    // few times.
+    for _ in 0..HANDSHAKE_ATTEMPTS {
+        let (len, file) = stream
+            .recv_with_fd(&mut message)
+            .map_err(|err| UffdHandlerError::Handshake(err.into()))?;
+        if let Some(file) = file {
+            let mappings =
+                serde_json::from_slice(&message[..len]).map_err(UffdHandlerError::Mappings)?;
+            // SAFETY: The descriptor is a userfaultfd owned by the file, which is consumed.
+            let uffd = unsafe { Uffd::from_raw_fd(file.into_raw_fd()) };
+            return Ok((mappings, uffd));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Err(UffdHandlerError::NoUffd)
+}
+
+fn peer_pid(stream: &UnixStream) -> Result<libc::pid_t, UffdHandlerError> {
+    let mut creds = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut creds_len = u32::try_from(std::mem::size_of::<libc::ucred>()).unwrap();
+    // SAFETY: `creds` and `creds_len` are valid for the size of `ucred`.
+    let ret = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut creds).cast(),
+            &raw mut creds_len,
+        )
+    };
+    if ret != 0 {
+        return Err(UffdHandlerError::PeerCredentials(
+            std::io::Error::last_os_error(),
+        ));
+    }
+    Ok(creds.pid)
+}
+
+fn kill(pid: libc::pid_t) {
+    // SAFETY: Sending a signal has no memory safety implications.
+    if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+        eprintln!(
+            "Failed to kill Firecracker: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+fn main() -> Result<(), UffdHandlerError> {
+    let result = main_exec();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        Err(e)
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Serving the guest memory of a Firecracker process through its userfaultfd.
+
+use std::collections::HashSet;
+use std::ffi::c_void;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use userfaultfd::{Error as UffdError, Event, Uffd};
+use vmm::persist::GuestRegionUffdMapping;
+use vmm::utils::{get_page_size, u64_to_usize, usize_to_u64};
+
+use crate::backend::{MemoryBackend, Source};
+
+/// Length of guest memory populated at once when prefetching.
+const PREFETCH_CHUNK_SIZE: usize = 2 << 20;
+/// Delay before prefetching again while remove events are pending.
+const PREFETCH_BACKOFF: Duration = Duration::from_millis(1);
+
+/// Errors related to serving guest memory.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ServerError {
+    /// No guest memory region to serve.
+    NoRegion,
+    /// Invalid page size {0} of the guest memory region at {1:#x}.
+    PageSize(usize, u64),
+    /// Guest memory region at {0:#x} is outside the {1} bytes of the memory files.
+    Region(u64, u64),
+    /// Failed to get the host page size: {0}
+    HostPageSize(vmm_sys_util::errno::Error),
+    /// Failed to read a userfaultfd event: {0}
+    ReadEvent(UffdError),
+    /// Page fault at {0:#x} outside the guest memory regions.
+    FaultAddress(u64),
+    /// Failed to populate guest memory at {0:#x}: {1}
+    Populate(u64, UffdError),
+}
+
+/// Page fault handler of the guest memory of a Firecracker process.
+#[derive(Debug)]
+pub struct PageServer {
+    uffd: Uffd,
+    regions: Vec<GuestRegionUffdMapping>,
+    page_size: usize,
+    backend: MemoryBackend,
+    /// Page frame numbers of the pages removed by the guest, which read as zeroes from then on.
+    /// The lock is held while reading events, so that pages are never populated between the
+    /// time their removal is read and the time it is recorded.
+    removed_pages: Mutex<HashSet<u64>>,
+    /// Page of zeroes to populate pages of hugetlbfs, which does not support `UFFDIO_ZEROPAGE`.
+    zero_page: Option<Vec<u8>>,
+}
+
+impl PageServer {
+    /// Creates a page server populating the guest memory `regions` registered with `uffd` from
+    /// `backend`.
+    pub fn new(
+        uffd: Uffd,
+        regions: Vec<GuestRegionUffdMapping>,
+        backend: MemoryBackend,
+    ) -> Result<Self, ServerError> {
+        let page_size = regions.first().ok_or(ServerError::NoRegion)?.page_size;
+        for region in &regions {
+            if !region.page_size.is_power_of_two() || region.page_size != page_size {
+                return Err(ServerError::PageSize(
+                    region.page_size,
+                    region.base_host_virt_addr,
+                ));
+            }
+            if region.offset + usize_to_u64(region.size) > backend.size() {
+                return Err(ServerError::Region(
+                    region.base_host_virt_addr,
+                    backend.size(),
+                ));
+            }
+        }
+        let host_page_size = get_page_size().map_err(ServerError::HostPageSize)?;
+        let zero_page = (page_size != host_page_size).then(|| vec![0u8; page_size]);
+
+        Ok(Self {
+            uffd,
+            regions,
+            page_size,
+            backend,
+            removed_pages: Mutex::new(HashSet::new()),
+            zero_page,
+        })
+    }
+
+    /// The userfaultfd of the guest memory.
+    pub fn uffd(&self) -> &Uffd {
+        &self.uffd
+    }
+
+    /// Reads the pending userfaultfd events and serves the page faults, including the page
+    /// faults in `faults` which could not be served yet. Page faults which cannot be served
+    /// because of pending remove events are left in `faults`, to be served on the next events.
+    ///
+    /// Remove events are recorded before serving any page fault, since the kernel may queue the
+    /// page fault on a page after the removal of the page was requested.
+    pub fn handle_events(&self, faults: &mut Vec<u64>) -> Result<(), ServerError> {
+        {
+            let mut removed_pages = self.removed_pages.lock().expect("Poisoned lock");
+            while let Some(event) = self.uffd.read_event().map_err(ServerError::ReadEvent)? {
+                match event {
+                    Event::Pagefault { addr, .. } => faults.push(addr as u64),
+                    Event::Remove { start, end } => {
+                        let page_size = usize_to_u64(self.page_size);
+                        removed_pages.extend(start as u64 / page_size..end as u64 / page_size);
+                    }
+                    // Firecracker does not fork, remap or unmap the guest memory while running.
+                    _ => {}
+                }
+            }
+        }
+
+        let mut pending = Vec::new();
+        for addr in faults.drain(..) {
+            if !self.serve_fault(addr)? {
+                pending.push(addr);
+            }
+        }
+        *faults = pending;
+        Ok(())
+    }
+
+    /// Serves a page fault at `addr`. Returns false if the page could not be populated because
+    /// of pending remove events.
+    fn serve_fault(&self, addr: u64) -> Result<bool, ServerError> {
+        let page_size = usize_to_u64(self.page_size);
+        let page = addr - addr % page_size;
+        if self.is_removed(page / page_size) {
+            return Ok(self.zero(page, self.page_size)?.is_some());
+        }
+        let offset = self
+            .file_offset(page)
+            .ok_or(ServerError::FaultAddress(addr))?;
+        Ok(self.populate(page, offset, self.page_size)?.is_some())
+    }
+
+    /// Populates the whole guest memory in chunks, except the removed pages, so that the guest
+    /// no longer faults on it.
+    pub fn prefetch(&self) -> Result<(), ServerError> {
+        let page_size = usize_to_u64(self.page_size);
+        let chunk_size = PREFETCH_CHUNK_SIZE.max(self.page_size);
+
+        for region in &self.regions {
+            let mut done = 0;
+            while done < region.size {
+                let dst = region.base_host_virt_addr + usize_to_u64(done);
+                let len = (region.size - done).min(chunk_size);
+                let populated = {
+                    let removed_pages = self.removed_pages.lock().expect("Poisoned lock");
+                    let first_pfn = dst / page_size;
+                    // Stop the chunk at the first removed page.
+                    match (0..usize_to_u64(len / self.page_size))
+                        .find(|i| removed_pages.contains(&(first_pfn + i)))
+                    {
+                        Some(0) => Some(self.page_size),
+                        Some(pages) => self.populate(
+                            dst,
+                            region.offset + usize_to_u64(done),
+                            u64_to_usize(pages * page_size),
+                        )?,
+                        None => self.populate(dst, region.offset + usize_to_u64(done), len)?,
+                    }
+                };
+                match populated {
+                    Some(len) => done += len,
+                    // Let the event loop read the pending remove events first.
+                    None => std::thread::sleep(PREFETCH_BACKOFF),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_removed(&self, pfn: u64) -> bool {
+        self.removed_pages
+            .lock()
+            .expect("Poisoned lock")
+            .contains(&pfn)
+    }
+
+    /// Returns the offset in the memory files of the guest memory at host address `addr`.
+    fn file_offset(&self, addr: u64) -> Option<u64> {
+        self.regions.iter().find_map(|region| {
+            let region_offset = addr.checked_sub(region.base_host_virt_addr)?;
+            (region_offset < usize_to_u64(region.size)).then_some(region.offset + region_offset)
+        })
+    }
+
+    /// Populates up to `len` bytes of guest memory at host address `dst` from `offset` in the
+    /// memory files. Returns the number of bytes populated, including pages which were already
+    /// populated, or `None` if nothing was populated because of pending remove events.
+    fn populate(&self, dst: u64, offset: u64, len: usize) -> Result<Option<usize>, ServerError> {
+        let (source, source_len) = self.backend.source(offset, usize_to_u64(len));
+        let source_len = u64_to_usize(source_len);
+        let pages_len = source_len - source_len % self.page_size;
+        match source {
+            // The first page is read from several memory files, so it is assembled first.
+            _ if pages_len == 0 => {
+                let mut page = vec![0u8; self.page_size];
+                self.backend.read(offset, &mut page);
+                self.copy(page.as_ptr(), dst, self.page_size)
+            }
+            Source::Data(src) => self.copy(src, dst, pages_len),
+            Source::Zero => self.zero(dst, pages_len),
+        }
+    }
+
+    fn copy(&self, src: *const u8, dst: u64, len: usize) -> Result<Option<usize>, ServerError> {
+        // SAFETY: `len` bytes are readable at `src`, and `dst` is within a guest memory region
+        // registered with the userfaultfd.
+        match unsafe { self.uffd.copy(src.cast(), dst as *mut c_void, len, true) } {
+            Ok(copied) => Ok(Some(copied)),
+            // On EAGAIN, the copied length is the number of bytes copied before remove events
+            // were queued, or the negative error code if nothing was copied.
+            Err(UffdError::PartiallyCopied(copied)) if copied > 0 && copied < len => {
+                Ok(Some(copied))
+            }
+            Err(UffdError::PartiallyCopied(_)) => Ok(None),
+            Err(UffdError::CopyFailed(errno)) if errno as i32 == libc::EEXIST => {
+                self.skip_populated(dst)
+            }
+            Err(err) => Err(ServerError::Populate(dst, err)),
+        }
+    }
+
+    fn zero(&self, dst: u64, len: usize) -> Result<Option<usize>, ServerError> {
+        if let Some(zero_page) = &self.zero_page {
+            return self.copy(zero_page.as_ptr(), dst, zero_page.len());
+        }
+        // SAFETY: `dst` is within a guest memory region registered with the userfaultfd.
+        match unsafe { self.uffd.zeropage(dst as *mut c_void, len, true) } {
+            Ok(zeroed) => Ok(Some(zeroed)),
+            Err(UffdError::ZeropageFailed(errno)) if errno as i32 == libc::EAGAIN => Ok(None),
+            Err(UffdError::ZeropageFailed(errno)) if errno as i32 == libc::EEXIST => {
+                self.skip_populated(dst)
+            }
+            Err(err) => Err(ServerError::Populate(dst, err)),
+        }
+    }
+
+    /// Skips the page at `dst`, populated by the other thread, and wakes up the threads which
+    /// faulted on it in the meantime.
+    fn skip_populated(&self, dst: u64) -> Result<Option<usize>, ServerError> {
+        self.uffd
+            .wake(dst as *mut c_void, self.page_size)
+            .map_err(|err| ServerError::Populate(dst, err))?;
+        Ok(Some(self.page_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    #[allow(deprecated)]
+    fn region(base_host_virt_addr: u64, size: usize, offset: u64) -> GuestRegionUffdMapping {
+        GuestRegionUffdMapping {
+            base_host_virt_addr,
+            size,
+            offset,
+            page_size: 4096,
+            page_size_kib: 4096,
+        }
+    }
+
+    fn server(regions: Vec<GuestRegionUffdMapping>) -> Result<PageServer, ServerError> {
+        let mem_file = TempFile::new().unwrap();
+        mem_file.as_file().set_len(0x4000).unwrap();
+        let backend = MemoryBackend::new(&[mem_file.as_path().to_path_buf()], None).unwrap();
+        // The userfaultfd is not used when validating the regions.
+        let dummy_file = TempFile::new().unwrap().into_file();
+        // SAFETY: The descriptor is owned by the file, which is consumed.
+        let uffd = unsafe { Uffd::from_raw_fd(dummy_file.into_raw_fd()) };
+        PageServer::new(uffd, regions, backend)
+    }
+
+    #[test]
+    fn test_regions() {
+        assert!(matches!(server(vec![]), Err(ServerError::NoRegion)));
+
+        let mut huge_region = region(0x10_0000, 0x2000, 0x2000);
+        huge_region.page_size = 2 << 20;
+        assert!(matches!(
+            server(vec![region(0x1000, 0x2000, 0), huge_region]),
+            Err(ServerError::PageSize(size, 0x10_0000)) if size == 2 << 20
+        ));
+
+        assert!(matches!(
+            server(vec![
+                region(0x1000, 0x2000, 0),
+                region(0x10_0000, 0x4000, 0x2000)
+            ]),
+            Err(ServerError::Region(0x10_0000, 0x4000))
+        ));
+
+        let server = server(vec![
+            region(0x1000, 0x2000, 0),
+            region(0x10_0000, 0x2000, 0x2000),
+        ])
+        .unwrap();
+        assert_eq!(server.file_offset(0x1000), Some(0));
+        assert_eq!(server.file_offset(0x2fff), Some(0x1fff));
+        assert_eq!(server.file_offset(0x3000), None);
+        assert_eq!(server.file_offset(0x10_1000), Some(0x3000));
+        assert_eq!(server.file_offset(0x10_2000), None);
+    }
+}
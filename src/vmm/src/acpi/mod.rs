@@ -131,13 +131,14 @@ impl AcpiTableWriter<'_> {
         &mut self,
         resource_allocator: &mut ResourceAllocator,
         nr_vcpus: u8,
+        max_vcpus: u8,
     ) -> Result<u64, AcpiError> {
         let mut madt = Madt::new(
             OEM_ID,
             *b"FCVMMADT",
             OEM_REVISION,
             apic_addr(),
-            setup_interrupt_controllers(nr_vcpus),
+            setup_interrupt_controllers(nr_vcpus, max_vcpus),
         );
         self.write_acpi_table(resource_allocator, &mut madt)
     }
@@ -204,7 +205,9 @@ pub(crate) fn create_acpi_tables(
     let dsdt_addr = writer.build_dsdt(device_manager, resource_allocator)?;
 
     let fadt_addr = writer.build_fadt(resource_allocator, dsdt_addr)?;
-    let madt_addr = writer.build_madt(resource_allocator, vcpus.len().try_into().unwrap())?;
+    let nr_vcpus = vcpus.len().try_into().unwrap();
+    let max_vcpus = device_manager.acpi_devices.max_vcpus().unwrap_or(nr_vcpus);
+    let madt_addr = writer.build_madt(resource_allocator, nr_vcpus, max_vcpus)?;
     let mcfg_addr = writer.build_mcfg(resource_allocator, layout::PCI_MMCONFIG_START)?;
     let xsdt_addr = writer.build_xsdt(resource_allocator, fadt_addr, madt_addr, mcfg_addr)?;
     writer.build_rsdp(xsdt_addr)
@@ -537,6 +537,7 @@ mod tests {
     use crate::device_manager::mmio::tests::DummyDevice;
     use crate::device_manager::tests::default_device_manager;
     use crate::test_utils::arch_mem;
+    use crate::vmm_config::serial::SerialConfig;
     use crate::vstate::memory::GuestAddress;
     use crate::{EventManager, Kvm, Vm};
 
@@ -561,7 +562,12 @@ mod tests {
         cmdline.insert("console", "/dev/tty0").unwrap();
 
         device_manager
-            .attach_legacy_devices_aarch64(&vm, &mut event_manager, &mut cmdline, None)
+            .attach_legacy_devices_aarch64(
+                &vm,
+                &mut event_manager,
+                &mut cmdline,
+                &SerialConfig::default(),
+            )
             .unwrap();
         let dummy = Arc::new(Mutex::new(DummyDevice::new()));
         device_manager
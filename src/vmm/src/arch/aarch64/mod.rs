@@ -125,8 +125,11 @@ pub fn configure_system_for_boot(
         .as_cstring()
         .expect("Cannot create cstring from cmdline string");
 
+    // Hotpluggable memory is not described in the FDT, it is discovered through the virtio-mem
+    // device.
+    let boot_memory = vm.boot_memory();
     let fdt = fdt::create_fdt(
-        vm.guest_memory(),
+        &boot_memory,
         vcpu_mpidr,
         cmdline,
         device_manager,
@@ -134,7 +137,7 @@ pub fn configure_system_for_boot(
         initrd,
     )?;
 
-    let fdt_address = GuestAddress(get_fdt_addr(vm.guest_memory()));
+    let fdt_address = GuestAddress(get_fdt_addr(&boot_memory));
     vm.guest_memory().write_slice(fdt.as_slice(), fdt_address)?;
 
     Ok(())
@@ -44,11 +44,11 @@ pub use x86_64::vm::{ArchVm, ArchVmError, VmState};
 
 #[cfg(target_arch = "x86_64")]
 pub use crate::arch::x86_64::{
-    ConfigurationError, arch_memory_regions, configure_system_for_boot, get_kernel_start,
-    initrd_load_addr, layout::APIC_ADDR, layout::BOOT_DEVICE_MEM_START, layout::CMDLINE_MAX_SIZE,
-    layout::GSI_LEGACY_END, layout::GSI_LEGACY_NUM, layout::GSI_LEGACY_START, layout::GSI_MSI_END,
-    layout::GSI_MSI_NUM, layout::GSI_MSI_START, layout::IOAPIC_ADDR,
-    layout::MEM_32BIT_DEVICES_SIZE, layout::MEM_32BIT_DEVICES_START,
+    ConfigurationError, arch_memory_regions, configure_hotplugged_vcpus, configure_system_for_boot,
+    get_kernel_start, initrd_load_addr, layout::APIC_ADDR, layout::BOOT_DEVICE_MEM_START,
+    layout::CMDLINE_MAX_SIZE, layout::GSI_LEGACY_END, layout::GSI_LEGACY_NUM,
+    layout::GSI_LEGACY_START, layout::GSI_MSI_END, layout::GSI_MSI_NUM, layout::GSI_MSI_START,
+    layout::IOAPIC_ADDR, layout::MEM_32BIT_DEVICES_SIZE, layout::MEM_32BIT_DEVICES_START,
     layout::MEM_64BIT_DEVICES_SIZE, layout::MEM_64BIT_DEVICES_START, layout::MMIO32_MEM_SIZE,
     layout::MMIO32_MEM_START, layout::PCI_MMCONFIG_SIZE, layout::PCI_MMCONFIG_START,
     layout::PCI_MMIO_CONFIG_SIZE_PER_SEGMENT, layout::SYSTEM_MEM_SIZE, layout::SYSTEM_MEM_START,
@@ -153,3 +153,47 @@ fn arch_memory_regions_with_gap(
         Some(_) => Some((first_addr_past_gap.max(region_start), region_size)),
     }
 }
+
+/// Alignment of the start of the memory hotplug region.
+pub const MEMORY_HOTPLUG_ALIGNMENT: u64 = 1 << 30;
+
+/// Returns the address where the memory hotplug region starts, given the boot memory `regions`
+/// returned by [`arch_memory_regions`].
+///
+/// The hotplug region is placed after both the boot memory and the 64-bit MMIO gap, so that it
+/// never needs to be split around a hole in the address space.
+pub fn memory_hotplug_start(regions: &[(GuestAddress, usize)]) -> GuestAddress {
+    let boot_mem_end = regions
+        .iter()
+        .map(|&(start, len)| start.0 + len as u64)
+        .max()
+        .unwrap_or(0);
+    let start = boot_mem_end.max(MEM_64BIT_DEVICES_START + MEM_64BIT_DEVICES_SIZE);
+    GuestAddress(crate::utils::align_up(start, MEMORY_HOTPLUG_ALIGNMENT))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mib_to_bytes;
+
+    #[test]
+    fn test_memory_hotplug_start() {
+        let first_addr_past_64bit_mmio = MEM_64BIT_DEVICES_START + MEM_64BIT_DEVICES_SIZE;
+
+        // Boot memory ends before the 64-bit MMIO gap.
+        let regions = arch_memory_regions(mib_to_bytes(128));
+        assert_eq!(
+            memory_hotplug_start(&regions),
+            GuestAddress(first_addr_past_64bit_mmio)
+        );
+
+        // Boot memory continues past the 64-bit MMIO gap.
+        let regions = arch_memory_regions(mib_to_bytes(300 << 10) + mib_to_bytes(2));
+        let start = memory_hotplug_start(&regions);
+        let (last_start, last_len) = *regions.last().unwrap();
+        assert!(start.0 >= last_start.0 + last_len as u64);
+        assert!(start.0 > first_addr_past_64bit_mmio);
+        assert_eq!(start.0 % MEMORY_HOTPLUG_ALIGNMENT, 0);
+    }
+}
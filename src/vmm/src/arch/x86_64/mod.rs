@@ -188,8 +188,10 @@ pub fn configure_system_for_boot(
     // Apply CPU template to the base CpuConfiguration.
     let cpu_config = CpuConfiguration::apply_template(cpu_config, cpu_template)?;
 
+    // The CPU topology covers the vCPUs that can be hotplugged later on, so that it stays the
+    // same for the lifetime of the microVM.
     let vcpu_config = VcpuConfig {
-        vcpu_count: machine_config.vcpu_count,
+        vcpu_count: machine_config.max_vcpus(),
         smt: machine_config.smt,
         cpu_config,
     };
@@ -218,17 +220,20 @@ pub fn configure_system_for_boot(
     mptable::setup_mptable(
         vm.guest_memory(),
         &mut vm.resource_allocator(),
-        vcpu_config.vcpu_count,
+        machine_config.vcpu_count,
     )
     .map_err(ConfigurationError::MpTableSetup)?;
 
+    // Hotpluggable memory is not described in the memory map, it is discovered through the
+    // virtio-mem device.
+    let boot_memory = vm.boot_memory();
     match entry_point.protocol {
         BootProtocol::PvhBoot => {
-            configure_pvh(vm.guest_memory(), GuestAddress(CMDLINE_START), initrd)?;
+            configure_pvh(&boot_memory, GuestAddress(CMDLINE_START), initrd)?;
         }
         BootProtocol::LinuxBoot => {
             configure_64bit_boot(
-                vm.guest_memory(),
+                &boot_memory,
                 GuestAddress(CMDLINE_START),
                 cmdline_size,
                 initrd,
@@ -247,6 +252,29 @@ pub fn configure_system_for_boot(
     Ok(())
 }
 
+/// Configures vCPUs hotplugged into a running microVM, using the same CPU configuration as the
+/// vCPUs the microVM booted with.
+pub fn configure_hotplugged_vcpus(
+    kvm: &Kvm,
+    vcpus: &mut [Vcpu],
+    machine_config: &MachineConfig,
+    cpu_template: &CustomCpuTemplate,
+) -> Result<(), ConfigurationError> {
+    let cpu_config = CpuConfiguration::new(kvm.supported_cpuid.clone(), cpu_template, &vcpus[0])?;
+    let cpu_config = CpuConfiguration::apply_template(cpu_config, cpu_template)?;
+
+    let vcpu_config = VcpuConfig {
+        vcpu_count: machine_config.max_vcpus(),
+        smt: machine_config.smt,
+        cpu_config,
+    };
+
+    for vcpu in vcpus.iter_mut() {
+        vcpu.kvm_vcpu.configure_hotplugged(&vcpu_config)?;
+    }
+    Ok(())
+}
+
 fn configure_pvh(
     guest_mem: &GuestMemoryMmap,
     cmdline_addr: GuestAddress,
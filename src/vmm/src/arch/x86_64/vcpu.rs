@@ -201,6 +201,38 @@ impl KvmVcpu {
         guest_mem: &GuestMemoryMmap,
         kernel_entry_point: EntryPoint,
         vcpu_config: &VcpuConfig,
+    ) -> Result<(), KvmVcpuConfigureError> {
+        self.configure_cpu_features(vcpu_config)?;
+        crate::arch::x86_64::regs::setup_regs(&self.fd, kernel_entry_point)?;
+        crate::arch::x86_64::regs::setup_fpu(&self.fd)?;
+        crate::arch::x86_64::regs::setup_sregs(guest_mem, &self.fd, kernel_entry_point.protocol)?;
+        crate::arch::x86_64::interrupts::set_lint(&self.fd)?;
+        Ok(())
+    }
+
+    /// Configures a x86_64 specific vcpu hotplugged into a running microVM.
+    ///
+    /// Unlike [`KvmVcpu::configure`], this leaves the registers in their reset state: the vcpu
+    /// waits for the INIT-SIPI sequence of the guest kernel, which sets them up when it brings the
+    /// vcpu online.
+    ///
+    /// # Arguments
+    ///
+    /// * `vcpu_config` - The vCPU configuration.
+    pub fn configure_hotplugged(
+        &mut self,
+        vcpu_config: &VcpuConfig,
+    ) -> Result<(), KvmVcpuConfigureError> {
+        self.configure_cpu_features(vcpu_config)?;
+        crate::arch::x86_64::regs::setup_fpu(&self.fd)?;
+        crate::arch::x86_64::interrupts::set_lint(&self.fd)?;
+        Ok(())
+    }
+
+    // Sets the CPUID and MSRs of the vcpu.
+    fn configure_cpu_features(
+        &mut self,
+        vcpu_config: &VcpuConfig,
     ) -> Result<(), KvmVcpuConfigureError> {
         let mut cpuid = vcpu_config.cpu_config.cpuid.clone();
 
@@ -259,10 +291,6 @@ impl KvmVcpu {
             .collect::<Vec<_>>();
 
         crate::arch::x86_64::msr::set_msrs(&self.fd, &kvm_msrs)?;
-        crate::arch::x86_64::regs::setup_regs(&self.fd, kernel_entry_point)?;
-        crate::arch::x86_64::regs::setup_fpu(&self.fd)?;
-        crate::arch::x86_64::regs::setup_sregs(guest_mem, &self.fd, kernel_entry_point.protocol)?;
-        crate::arch::x86_64::interrupts::set_lint(&self.fd)?;
         Ok(())
     }
 
@@ -960,6 +988,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_configure_hotplugged_vcpu() {
+        let (kvm, vm, _) = setup_vcpu(0x10000);
+        let mut vcpu = KvmVcpu::new(1, &vm).unwrap();
+        let regs = vcpu.fd.get_regs().unwrap();
+
+        let mut vcpu_config =
+            create_vcpu_config(&kvm, &vcpu, &CustomCpuTemplate::default()).unwrap();
+        vcpu_config.vcpu_count = 2;
+        vcpu.configure_hotplugged(&vcpu_config).unwrap();
+
+        // The registers are left for the guest to set up.
+        let configured_regs = vcpu.fd.get_regs().unwrap();
+        assert_eq!(configured_regs.rip, regs.rip);
+        assert_eq!(configured_regs.rsp, regs.rsp);
+    }
+
     #[test]
     fn test_vcpu_cpuid_restore() {
         let (kvm, _, vcpu) = setup_vcpu(0x10000);
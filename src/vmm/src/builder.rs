@@ -32,6 +32,8 @@ use crate::device_manager::{
 use crate::devices::acpi::vmgenid::VmGenIdError;
 use crate::devices::virtio::balloon::Balloon;
 use crate::devices::virtio::block::device::Block;
+use crate::devices::virtio::generated::virtio_ids;
+use crate::devices::virtio::mem::{VirtioMem, VirtioMemError};
 use crate::devices::virtio::net::Net;
 use crate::devices::virtio::rng::Entropy;
 use crate::devices::virtio::vsock::{Vsock, VsockUnixBackend};
@@ -43,6 +45,7 @@ use crate::persist::{MicrovmState, MicrovmStateError};
 use crate::resources::VmResources;
 use crate::seccomp::BpfThreadMap;
 use crate::snapshot::Persist;
+use crate::utils::{mib_to_bytes, usize_to_u64};
 use crate::vmm_config::instance_info::InstanceInfo;
 use crate::vmm_config::machine_config::MachineConfigError;
 use crate::vstate::kvm::{Kvm, KvmError};
@@ -110,6 +113,8 @@ pub enum StartMicrovmError {
     SetVmResources(MachineConfigError),
     /// Cannot create the entropy device: {0}
     CreateEntropyDevice(crate::devices::virtio::rng::EntropyError),
+    /// Cannot create the virtio-mem device: {0}
+    CreateVirtioMemDevice(VirtioMemError),
     /// Failed to allocate guest resource: {0}
     AllocateResources(#[from] vm_allocator::Error),
     /// Error starting GDB debug session
@@ -169,18 +174,25 @@ pub fn build_microvm_for_boot(
     let mut vm = Vm::new(&kvm)?;
     let (mut vcpus, vcpus_exit_evt) = vm.create_vcpus(vm_resources.machine_config.vcpu_count)?;
     vm.register_memory_regions(guest_memory)?;
+    if let Some(region) = vm_resources
+        .allocate_memory_hotplug_region()
+        .map_err(StartMicrovmError::GuestMemory)?
+    {
+        vm.register_hotplug_memory_region(region)?;
+    }
 
     let mut device_manager = DeviceManager::new(
         event_manager,
         &vcpus_exit_evt,
         &vm,
-        vm_resources.serial_out_path.as_ref(),
+        &vm_resources.serial_config,
     )?;
 
     let vm = Arc::new(vm);
 
-    let entry_point = load_kernel(&boot_config.kernel_file, vm.guest_memory())?;
-    let initrd = InitrdConfig::from_config(boot_config, vm.guest_memory())?;
+    let boot_memory = vm.boot_memory();
+    let entry_point = load_kernel(&boot_config.kernel_file, &boot_memory)?;
+    let initrd = InitrdConfig::from_config(boot_config, &boot_memory)?;
 
     #[cfg(feature = "gdb")]
     let (gdb_tx, gdb_rx) = mpsc::channel();
@@ -252,16 +264,42 @@ pub fn build_microvm_for_boot(
         )?;
     }
 
+    if let (Some(config), Some((addr, size))) =
+        (&vm_resources.memory_hotplug, vm.memory_hotplug_region())
+    {
+        let block_size = usize_to_u64(mib_to_bytes(config.block_size_mib));
+        let virtio_mem = VirtioMem::new(addr, size, block_size)
+            .map_err(StartMicrovmError::CreateVirtioMemDevice)?;
+        attach_virtio_mem_device(
+            &mut device_manager,
+            &vm,
+            &mut boot_cmdline,
+            &Arc::new(Mutex::new(virtio_mem)),
+            event_manager,
+        )?;
+    }
+
     #[cfg(target_arch = "aarch64")]
     device_manager.attach_legacy_devices_aarch64(
         &vm,
         event_manager,
         &mut boot_cmdline,
-        vm_resources.serial_out_path.as_ref(),
+        &vm_resources.serial_config,
     )?;
 
     device_manager.attach_vmgenid_device(vm.guest_memory(), &vm)?;
 
+    let vcpu_count = vm_resources.machine_config.vcpu_count;
+    let max_vcpus = vm_resources.machine_config.max_vcpus();
+    if max_vcpus > vcpu_count {
+        device_manager.attach_cpu_hotplug_device(&vm, vcpu_count, max_vcpus)?;
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    if vm_resources.pci_enabled {
+        device_manager.attach_pci_hotplug_device(&vm)?;
+    }
+
     #[cfg(target_arch = "aarch64")]
     if vcpus[0].kvm_vcpu.supports_pvtime() {
         setup_pvtime(&mut vm.resource_allocator(), &mut vcpus)?;
@@ -288,8 +326,11 @@ pub fn build_microvm_for_boot(
         vm,
         uffd: None,
         vcpus_handles: Vec::new(),
+        vcpus_slots: Vec::new(),
         vcpus_exit_evt,
         device_manager,
+        last_snapshot: None,
+        migration: None,
     };
 
     let vmm = Arc::new(Mutex::new(vmm));
@@ -320,16 +361,19 @@ pub fn build_microvm_for_boot(
         )
         .map_err(VmmError::VcpuStart)?;
 
+    let vmm_seccomp_filter = seccomp_filters
+        .get("vmm")
+        .ok_or_else(|| StartMicrovmError::MissingSeccompFilters("vmm".to_string()))?;
+    vmm.lock()
+        .unwrap()
+        .start_migration_worker(vmm_seccomp_filter.clone())
+        .map_err(VmmError::MigrationWorker)?;
+
     // Load seccomp filters for the VMM thread.
     // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
     // altogether is the desired behaviour.
     // Keep this as the last step before resuming vcpus.
-    crate::seccomp::apply_filter(
-        seccomp_filters
-            .get("vmm")
-            .ok_or_else(|| StartMicrovmError::MissingSeccompFilters("vmm".to_string()))?,
-    )
-    .map_err(VmmError::SeccompFilters)?;
+    crate::seccomp::apply_filter(vmm_seccomp_filter).map_err(VmmError::SeccompFilters)?;
 
     event_manager.add_subscriber(vmm.clone());
 
@@ -390,10 +434,14 @@ pub enum BuildMicrovmFromSnapshotError {
     RestoreVcpus(#[from] VcpuError),
     /// Failed to apply VMM secccomp filter as none found.
     MissingVmmSeccompFilters,
+    /// Failed to start the live migration thread: {0}
+    MigrationWorker(std::io::Error),
     /// Failed to apply VMM secccomp filter: {0}
     SeccompFiltersInternal(#[from] crate::seccomp::InstallationError),
     /// Failed to restore devices: {0}
     RestoreDevices(#[from] DevicePersistError),
+    /// Failed to restore rate limiter groups: {0}
+    RestoreRateLimiterGroups(std::io::Error),
 }
 
 /// Builds and starts a microVM based on the provided MicrovmState.
@@ -462,6 +510,12 @@ pub fn build_microvm_from_snapshot(
     // Restore the boot source config paths.
     vm_resources.boot_source.config = microvm_state.vm_info.boot_source;
 
+    // Restore the rate limiter groups before the devices that share them.
+    vm_resources
+        .rate_limiter_groups
+        .restore(&microvm_state.vm_info.rate_limiter_groups)
+        .map_err(BuildMicrovmFromSnapshotError::RestoreRateLimiterGroups)?;
+
     let vm = Arc::new(vm);
 
     // Restore devices states.
@@ -488,8 +542,11 @@ pub fn build_microvm_from_snapshot(
         vm,
         uffd,
         vcpus_handles: Vec::new(),
+        vcpus_slots: Vec::new(),
         vcpus_exit_evt,
         device_manager,
+        last_snapshot: None,
+        migration: None,
     };
 
     // Move vcpus to their own threads and start their state machine in the 'Paused' state.
@@ -500,17 +557,18 @@ pub fn build_microvm_from_snapshot(
             .ok_or(BuildMicrovmFromSnapshotError::MissingVcpuSeccompFilters)?
             .clone(),
     )?;
+    let vmm_seccomp_filter = seccomp_filters
+        .get("vmm")
+        .ok_or(BuildMicrovmFromSnapshotError::MissingVmmSeccompFilters)?;
+    vmm.start_migration_worker(vmm_seccomp_filter.clone())
+        .map_err(BuildMicrovmFromSnapshotError::MigrationWorker)?;
 
     let vmm = Arc::new(Mutex::new(vmm));
     event_manager.add_subscriber(vmm.clone());
 
     // Load seccomp filters for the VMM thread.
     // Keep this as the last step of the building process.
-    crate::seccomp::apply_filter(
-        seccomp_filters
-            .get("vmm")
-            .ok_or(BuildMicrovmFromSnapshotError::MissingVmmSeccompFilters)?,
-    )?;
+    crate::seccomp::apply_filter(vmm_seccomp_filter)?;
     debug!("event_end: build microvm from snapshot");
 
     Ok(vmm)
@@ -576,6 +634,19 @@ fn attach_entropy_device(
     device_manager.attach_virtio_device(vm, id, entropy_device.clone(), cmdline, false)
 }
 
+fn attach_virtio_mem_device(
+    device_manager: &mut DeviceManager,
+    vm: &Arc<Vm>,
+    cmdline: &mut LoaderKernelCmdline,
+    virtio_mem: &Arc<Mutex<VirtioMem>>,
+    event_manager: &mut EventManager,
+) -> Result<(), AttachDeviceError> {
+    let id = virtio_mem.lock().expect("Poisoned lock").id().to_string();
+
+    event_manager.add_subscriber(virtio_mem.clone());
+    device_manager.attach_virtio_device(vm, id, virtio_mem.clone(), cmdline, false)
+}
+
 fn attach_block_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Block>>> + Debug>(
     device_manager: &mut DeviceManager,
     vm: &Arc<Vm>,
@@ -599,8 +670,19 @@ fn attach_block_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Block>>> + Debug>(
             (locked.id().to_string(), locked.is_vhost_user())
         };
         // The device mutex mustn't be locked here otherwise it will deadlock.
-        event_manager.add_subscriber(block.clone());
-        device_manager.attach_virtio_device(vm, id, block.clone(), cmdline, is_vhost_user)?;
+        let subscriber_id = event_manager.add_subscriber(block.clone());
+        device_manager.attach_virtio_device(
+            vm,
+            id.clone(),
+            block.clone(),
+            cmdline,
+            is_vhost_user,
+        )?;
+        device_manager.pci_devices.set_subscriber_id(
+            virtio_ids::VIRTIO_ID_BLOCK,
+            &id,
+            subscriber_id,
+        );
     }
     Ok(())
 }
@@ -614,9 +696,12 @@ fn attach_net_devices<'a, I: Iterator<Item = &'a Arc<Mutex<Net>>> + Debug>(
 ) -> Result<(), StartMicrovmError> {
     for net_device in net_devices {
         let id = net_device.lock().expect("Poisoned lock").id().clone();
-        event_manager.add_subscriber(net_device.clone());
+        let subscriber_id = event_manager.add_subscriber(net_device.clone());
         // The device mutex mustn't be locked here otherwise it will deadlock.
-        device_manager.attach_virtio_device(vm, id, net_device.clone(), cmdline, false)?;
+        device_manager.attach_virtio_device(vm, id.clone(), net_device.clone(), cmdline, false)?;
+        device_manager
+            .pci_devices
+            .set_subscriber_id(virtio_ids::VIRTIO_ID_NET, &id, subscriber_id);
     }
     Ok(())
 }
@@ -654,21 +739,24 @@ pub(crate) mod tests {
     use vmm_sys_util::tempfile::TempFile;
 
     use super::*;
+    use crate::arch::{MEM_64BIT_DEVICES_SIZE, MEM_64BIT_DEVICES_START};
     use crate::device_manager::tests::default_device_manager;
     use crate::devices::virtio::block::CacheType;
     use crate::devices::virtio::generated::virtio_ids;
+    use crate::devices::virtio::mem::MEM_DEV_ID;
     use crate::devices::virtio::rng::device::ENTROPY_DEV_ID;
     use crate::devices::virtio::vsock::VSOCK_DEV_ID;
     use crate::mmds::data_store::{Mmds, MmdsVersion};
     use crate::mmds::ns::MmdsNetworkStack;
-    use crate::utils::mib_to_bytes;
     use crate::vmm_config::balloon::{BALLOON_DEV_ID, BalloonBuilder, BalloonDeviceConfig};
     use crate::vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
     use crate::vmm_config::drive::{BlockBuilder, BlockDeviceConfig};
     use crate::vmm_config::entropy::{EntropyDeviceBuilder, EntropyDeviceConfig};
+    use crate::vmm_config::memory_hotplug::{MemoryHotplugConfig, MemoryHotplugStatus};
     use crate::vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
     use crate::vmm_config::vsock::tests::default_config;
     use crate::vmm_config::vsock::{VsockBuilder, VsockDeviceConfig};
+    use crate::vstate::memory::GuestAddress;
     use crate::vstate::vm::tests::setup_vm_with_memory;
 
     #[derive(Debug)]
@@ -736,8 +824,11 @@ pub(crate) mod tests {
             vm: Arc::new(vm),
             uffd: None,
             vcpus_handles: Vec::new(),
+            vcpus_slots: Vec::new(),
             vcpus_exit_evt,
             device_manager: default_device_manager(),
+            last_snapshot: None,
+            migration: None,
         }
     }
 
@@ -769,7 +860,10 @@ pub(crate) mod tests {
                         .to_string(),
                 ),
                 rate_limiter: None,
+                rate_limiter_group: None,
                 file_engine_type: None,
+                discard: None,
+                format: None,
 
                 socket: None,
             };
@@ -821,6 +915,7 @@ pub(crate) mod tests {
         mmds.set_version(mmds_version);
         net.lock().unwrap().configure_mmds_network_stack(
             MmdsNetworkStack::default_ipv4_addr(),
+            None,
             Arc::new(Mutex::new(mmds)),
         );
 
@@ -885,6 +980,35 @@ pub(crate) mod tests {
         );
     }
 
+    pub(crate) fn insert_virtio_mem_device(
+        vmm: &mut Vmm,
+        cmdline: &mut Cmdline,
+        event_manager: &mut EventManager,
+        config: MemoryHotplugConfig,
+    ) {
+        let virtio_mem = VirtioMem::new(
+            GuestAddress(MEM_64BIT_DEVICES_START + MEM_64BIT_DEVICES_SIZE),
+            usize_to_u64(mib_to_bytes(config.total_size_mib)),
+            usize_to_u64(mib_to_bytes(config.block_size_mib)),
+        )
+        .unwrap();
+
+        attach_virtio_mem_device(
+            &mut vmm.device_manager,
+            &vmm.vm,
+            cmdline,
+            &Arc::new(Mutex::new(virtio_mem)),
+            event_manager,
+        )
+        .unwrap();
+
+        assert!(
+            vmm.device_manager
+                .get_virtio_device(virtio_ids::VIRTIO_ID_MEM, MEM_DEV_ID)
+                .is_some()
+        );
+    }
+
     #[cfg(target_arch = "x86_64")]
     pub(crate) fn insert_vmgenid_device(vmm: &mut Vmm) {
         vmm.device_manager
@@ -930,6 +1054,9 @@ pub(crate) mod tests {
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            rx_rate_limiter_group: None,
+            tx_rate_limiter_group: None,
+            num_queues: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1153,6 +1280,9 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -1182,6 +1312,37 @@ pub(crate) mod tests {
         ));
     }
 
+    #[test]
+    fn test_attach_virtio_mem_device() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        let config = MemoryHotplugConfig {
+            total_size_mib: 1024,
+            block_size_mib: 2,
+        };
+
+        let mut cmdline = default_kernel_cmdline();
+        insert_virtio_mem_device(&mut vmm, &mut cmdline, &mut event_manager, config);
+        // Check if the virtio-mem device is described in kernel_cmdline.
+        #[cfg(target_arch = "x86_64")]
+        assert!(cmdline_contains(
+            &cmdline,
+            "virtio_mmio.device=4K@0xc0001000:5"
+        ));
+
+        vmm.update_memory_hotplug_size(512).unwrap();
+        vmm.update_memory_hotplug_size(2048).unwrap_err();
+        assert_eq!(
+            vmm.memory_hotplug_status().unwrap(),
+            MemoryHotplugStatus {
+                total_size_mib: 1024,
+                block_size_mib: 2,
+                plugged_size_mib: 0,
+                requested_size_mib: 512,
+            }
+        );
+    }
+
     #[test]
     fn test_attach_vsock_device() {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
@@ -1,15 +1,27 @@
 // Copyright 2024 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::sync::{Arc, Mutex};
+
 use acpi_tables::{Aml, aml};
 
 use crate::Vm;
+use crate::devices::acpi::cpu_hotplug::{
+    CPU_HOTPLUG_MMIO_SIZE, CpuHotplugController, CpuHotplugError,
+};
+use crate::devices::acpi::pci_hotplug::{
+    PCI_HOTPLUG_MMIO_SIZE, PciHotplugController, PciHotplugError,
+};
 use crate::devices::acpi::vmgenid::VmGenId;
 
 #[derive(Debug, Default)]
 pub struct ACPIDeviceManager {
     /// VMGenID device
     pub vmgenid: Option<VmGenId>,
+    /// CPU hotplug controller
+    pub cpu_hotplug: Option<Arc<Mutex<CpuHotplugController>>>,
+    /// PCI hotplug controller
+    pub pci_hotplug: Option<Arc<Mutex<PciHotplugController>>>,
 }
 
 impl ACPIDeviceManager {
@@ -27,6 +39,44 @@ impl ACPIDeviceManager {
         Ok(())
     }
 
+    /// Attach a new CPU hotplug controller to the microVM
+    ///
+    /// This will register the controller's interrupt with KVM and insert its registers in the
+    /// MMIO bus
+    pub fn attach_cpu_hotplug(
+        &mut self,
+        cpu_hotplug: CpuHotplugController,
+        vm: &Vm,
+    ) -> Result<(), CpuHotplugError> {
+        vm.register_irq(&cpu_hotplug.interrupt_evt, cpu_hotplug.gsi)?;
+        let mmio_addr = cpu_hotplug.mmio_addr;
+        let cpu_hotplug = Arc::new(Mutex::new(cpu_hotplug));
+        vm.common
+            .mmio_bus
+            .insert(cpu_hotplug.clone(), mmio_addr, CPU_HOTPLUG_MMIO_SIZE)?;
+        self.cpu_hotplug = Some(cpu_hotplug);
+        Ok(())
+    }
+
+    /// Attach a new PCI hotplug controller to the microVM
+    ///
+    /// This will register the controller's interrupt with KVM and insert its registers in the
+    /// MMIO bus
+    pub fn attach_pci_hotplug(
+        &mut self,
+        pci_hotplug: PciHotplugController,
+        vm: &Vm,
+    ) -> Result<(), PciHotplugError> {
+        vm.register_irq(&pci_hotplug.interrupt_evt, pci_hotplug.gsi)?;
+        let mmio_addr = pci_hotplug.mmio_addr;
+        let pci_hotplug = Arc::new(Mutex::new(pci_hotplug));
+        vm.common
+            .mmio_bus
+            .insert(pci_hotplug.clone(), mmio_addr, PCI_HOTPLUG_MMIO_SIZE)?;
+        self.pci_hotplug = Some(pci_hotplug);
+        Ok(())
+    }
+
     /// If it exists, notify guest VMGenID device that we have resumed from a snapshot.
     pub fn notify_vmgenid(&mut self) -> Result<(), std::io::Error> {
         if let Some(vmgenid) = &mut self.vmgenid {
@@ -34,52 +84,114 @@ impl ACPIDeviceManager {
         }
         Ok(())
     }
+
+    /// Maximum number of vCPUs of the microVM, if vCPUs can be hotplugged.
+    pub fn max_vcpus(&self) -> Option<u8> {
+        self.cpu_hotplug
+            .as_ref()
+            .map(|cpu_hotplug| cpu_hotplug.lock().expect("Poisoned lock").max_vcpus)
+    }
+}
+
+// AML calling `handler` when the GED event of `gsi` fires
+struct GedEvent<'a> {
+    gsi: u32,
+    handler: &'a dyn Aml,
+}
+
+impl Aml for GedEvent<'_> {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        aml::If::new(
+            // We know that the maximum IRQ number fits in a u8. We have up to 32 IRQs in x86 and
+            // up to 128 in ARM (look into `vmm::crate::arch::layout::GSI_LEGACY_END`)
+            #[allow(clippy::cast_possible_truncation)]
+            &aml::Equal::new(&aml::Arg(0), &(self.gsi as u8)),
+            vec![self.handler],
+        )
+        .append_aml_bytes(v)
+    }
 }
 
 impl Aml for ACPIDeviceManager {
     fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
-        // If we have a VMGenID device, create the AML for the device and GED interrupt handler
-        match self.vmgenid.as_ref() {
-            Some(vmgenid) => {
-                // AML for GED
-                aml::Device::new(
-                    "_SB_.GED_".try_into()?,
-                    vec![
-                        &aml::Name::new("_HID".try_into()?, &"ACPI0013")?,
-                        &aml::Name::new(
-                            "_CRS".try_into()?,
-                            &aml::ResourceTemplate::new(vec![&aml::Interrupt::new(
-                                true,
-                                true,
-                                false,
-                                false,
-                                vmgenid.gsi,
-                            )]),
-                        )?,
-                        &aml::Method::new(
-                            "_EVT".try_into()?,
-                            1,
-                            true,
-                            vec![&aml::If::new(
-                                // We know that the maximum IRQ number fits in a u8. We have up to
-                                // 32 IRQs in x86 and up to 128 in
-                                // ARM (look into
-                                // `vmm::crate::arch::layout::GSI_LEGACY_END`)
-                                #[allow(clippy::cast_possible_truncation)]
-                                &aml::Equal::new(&aml::Arg(0), &(vmgenid.gsi as u8)),
-                                vec![&aml::Notify::new(
-                                    &aml::Path::new("\\_SB_.VGEN")?,
-                                    &0x80usize,
-                                )],
-                            )],
-                        ),
-                    ],
-                )
-                .append_aml_bytes(v)?;
-                // AML for VMGenID itself.
-                vmgenid.append_aml_bytes(v)
-            }
-            None => Ok(()),
+        let cpu_hotplug = self
+            .cpu_hotplug
+            .as_ref()
+            .map(|cpu_hotplug| cpu_hotplug.lock().expect("Poisoned lock"));
+        let pci_hotplug = self
+            .pci_hotplug
+            .as_ref()
+            .map(|pci_hotplug| pci_hotplug.lock().expect("Poisoned lock"));
+
+        let notify_vmgenid = aml::Notify::new(&aml::Path::new("\\_SB_.VGEN")?, &0x80usize);
+        let scan_cpus = aml::MethodCall::new("\\_SB_.CPUS.CSCN".try_into()?, vec![]);
+        let scan_pci_devices = aml::MethodCall::new("\\_SB_.PC00.PCNT".try_into()?, vec![]);
+        let mut events = Vec::new();
+        if let Some(vmgenid) = self.vmgenid.as_ref() {
+            events.push(GedEvent {
+                gsi: vmgenid.gsi,
+                handler: &notify_vmgenid,
+            });
+        }
+        if let Some(cpu_hotplug) = cpu_hotplug.as_ref() {
+            events.push(GedEvent {
+                gsi: cpu_hotplug.gsi,
+                handler: &scan_cpus,
+            });
+        }
+        if let Some(pci_hotplug) = pci_hotplug.as_ref() {
+            events.push(GedEvent {
+                gsi: pci_hotplug.gsi,
+                handler: &scan_pci_devices,
+            });
+        }
+
+        // The GED is only needed if we have devices that notify the guest
+        if events.is_empty() {
+            return Ok(());
         }
+
+        let interrupts = events
+            .iter()
+            .map(|event| aml::Interrupt::new(true, true, false, false, event.gsi))
+            .collect::<Vec<_>>();
+
+        // AML for GED
+        aml::Device::new(
+            "_SB_.GED_".try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &"ACPI0013")?,
+                &aml::Name::new(
+                    "_CRS".try_into()?,
+                    &aml::ResourceTemplate::new(
+                        interrupts
+                            .iter()
+                            .map(|interrupt| interrupt as &dyn Aml)
+                            .collect(),
+                    ),
+                )?,
+                &aml::Method::new(
+                    "_EVT".try_into()?,
+                    1,
+                    true,
+                    events.iter().map(|event| event as &dyn Aml).collect(),
+                ),
+            ],
+        )
+        .append_aml_bytes(v)?;
+
+        // AML for VMGenID itself.
+        if let Some(vmgenid) = self.vmgenid.as_ref() {
+            vmgenid.append_aml_bytes(v)?;
+        }
+        // AML for the CPU hotplug controller and the processors it manages.
+        if let Some(cpu_hotplug) = cpu_hotplug.as_ref() {
+            cpu_hotplug.append_aml_bytes(v)?;
+        }
+        // AML for the PCI hotplug controller. The slots calling it are part of the PCI segment.
+        if let Some(pci_hotplug) = pci_hotplug.as_ref() {
+            pci_hotplug.append_aml_bytes(v)?;
+        }
+        Ok(())
     }
 }
@@ -8,7 +8,6 @@
 use std::convert::Infallible;
 use std::fmt::Debug;
 use std::os::unix::prelude::OpenOptionsExt;
-use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use acpi::ACPIDeviceManager;
@@ -24,18 +23,21 @@ use serde::{Deserialize, Serialize};
 use utils::time::TimestampUs;
 use vmm_sys_util::eventfd::EventFd;
 
+use crate::devices::acpi::cpu_hotplug::{CpuHotplugController, CpuHotplugError};
+use crate::devices::acpi::pci_hotplug::{PciHotplugController, PciHotplugError};
 use crate::devices::acpi::vmgenid::{VmGenId, VmGenIdError};
 #[cfg(target_arch = "x86_64")]
 use crate::devices::legacy::I8042Device;
 #[cfg(target_arch = "aarch64")]
 use crate::devices::legacy::RTCDevice;
-use crate::devices::legacy::serial::SerialOut;
+use crate::devices::legacy::serial::{SerialIn, SerialOut, SerialSocket};
 use crate::devices::legacy::{IER_RDA_BIT, IER_RDA_OFFSET, SerialDevice};
 use crate::devices::pseudo::BootTimer;
 use crate::devices::virtio::device::VirtioDevice;
 use crate::devices::virtio::transport::mmio::{IrqTrigger, MmioTransport};
 use crate::resources::VmResources;
 use crate::snapshot::Persist;
+use crate::vmm_config::serial::SerialConfig;
 use crate::vstate::memory::GuestMemoryMmap;
 use crate::{EmulateSerialInitError, EventManager, Vm};
 
@@ -73,6 +75,10 @@ pub enum AttachDeviceError {
     CreateVmGenID(#[from] VmGenIdError),
     /// Error while registering VMGenID with KVM: {0}
     AttachVmGenID(#[from] kvm_ioctls::Error),
+    /// Error attaching the CPU hotplug controller: {0}
+    CpuHotplug(#[from] CpuHotplugError),
+    /// Error attaching the PCI hotplug controller: {0}
+    PciHotplug(#[from] PciHotplugError),
     #[cfg(target_arch = "aarch64")]
     /// Cmdline error
     Cmdline,
@@ -127,10 +133,10 @@ impl DeviceManager {
     /// Sets up the serial device.
     fn setup_serial_device(
         event_manager: &mut EventManager,
-        output: Option<&PathBuf>,
+        config: &SerialConfig,
     ) -> Result<Arc<Mutex<SerialDevice>>, std::io::Error> {
-        let (serial_in, serial_out) = match output {
-            Some(ref path) => (
+        let (serial_in, serial_out) = match (&config.serial_out_path, &config.serial_socket_path) {
+            (Some(path), _) => (
                 None,
                 std::fs::OpenOptions::new()
                     .custom_flags(libc::O_NONBLOCK)
@@ -138,10 +144,20 @@ impl DeviceManager {
                     .open(path)
                     .map(SerialOut::File)?,
             ),
-            None => {
+            (None, Some(path)) => {
+                let socket = Arc::new(Mutex::new(SerialSocket::new(path)?));
+                (
+                    Some(SerialIn::Socket(socket.clone())),
+                    SerialOut::Socket(socket),
+                )
+            }
+            (None, None) => {
                 Self::set_stdout_nonblocking();
 
-                (Some(std::io::stdin()), SerialOut::Stdout(std::io::stdout()))
+                (
+                    Some(SerialIn::Stdin(std::io::stdin())),
+                    SerialOut::Stdout(std::io::stdout()),
+                )
             }
         };
 
@@ -155,10 +171,10 @@ impl DeviceManager {
         event_manager: &mut EventManager,
         vcpus_exit_evt: &EventFd,
         vm: &Vm,
-        serial_output: Option<&PathBuf>,
+        serial_config: &SerialConfig,
     ) -> Result<PortIODeviceManager, DeviceManagerCreateError> {
         // Create serial device
-        let serial = Self::setup_serial_device(event_manager, serial_output)?;
+        let serial = Self::setup_serial_device(event_manager, serial_config)?;
         let reset_evt = vcpus_exit_evt
             .try_clone()
             .map_err(DeviceManagerCreateError::EventFd)?;
@@ -176,11 +192,11 @@ impl DeviceManager {
         event_manager: &mut EventManager,
         vcpus_exit_evt: &EventFd,
         vm: &Vm,
-        serial_output: Option<&PathBuf>,
+        serial_config: &SerialConfig,
     ) -> Result<Self, DeviceManagerCreateError> {
         #[cfg(target_arch = "x86_64")]
         let legacy_devices =
-            Self::create_legacy_devices(event_manager, vcpus_exit_evt, vm, serial_output)?;
+            Self::create_legacy_devices(event_manager, vcpus_exit_evt, vm, serial_config)?;
 
         Ok(DeviceManager {
             mmio_devices: MMIODeviceManager::new(),
@@ -254,13 +270,35 @@ impl DeviceManager {
         Ok(())
     }
 
+    /// Attaches a CPU hotplug controller managing `max_vcpus` vCPUs, `boot_vcpus` of which are
+    /// present at boot time.
+    pub(crate) fn attach_cpu_hotplug_device(
+        &mut self,
+        vm: &Vm,
+        boot_vcpus: u8,
+        max_vcpus: u8,
+    ) -> Result<(), AttachDeviceError> {
+        let cpu_hotplug =
+            CpuHotplugController::new(boot_vcpus, max_vcpus, &mut vm.resource_allocator())?;
+        self.acpi_devices.attach_cpu_hotplug(cpu_hotplug, vm)?;
+        Ok(())
+    }
+
+    /// Attaches a PCI hotplug controller, through which devices are hotplugged in the PCI segment.
+    #[cfg(target_arch = "x86_64")]
+    pub(crate) fn attach_pci_hotplug_device(&mut self, vm: &Vm) -> Result<(), AttachDeviceError> {
+        let pci_hotplug = PciHotplugController::new(&mut vm.resource_allocator())?;
+        self.acpi_devices.attach_pci_hotplug(pci_hotplug, vm)?;
+        Ok(())
+    }
+
     #[cfg(target_arch = "aarch64")]
     pub(crate) fn attach_legacy_devices_aarch64(
         &mut self,
         vm: &Vm,
         event_manager: &mut EventManager,
         cmdline: &mut Cmdline,
-        serial_out_path: Option<&PathBuf>,
+        serial_config: &SerialConfig,
     ) -> Result<(), AttachDeviceError> {
         // Serial device setup.
         let cmdline_contains_console = cmdline
@@ -271,7 +309,7 @@ impl DeviceManager {
             .contains("console=");
 
         if cmdline_contains_console {
-            let serial = Self::setup_serial_device(event_manager, serial_out_path)?;
+            let serial = Self::setup_serial_device(event_manager, serial_config)?;
             self.mmio_devices.register_mmio_serial(vm, serial, None)?;
             self.mmio_devices.add_mmio_serial_to_cmdline(cmdline)?;
         }
@@ -477,7 +515,7 @@ impl<'a> Persist<'a> for DeviceManager {
             constructor_args.event_manager,
             constructor_args.vcpus_exit_evt,
             constructor_args.vm,
-            constructor_args.vm_resources.serial_out_path.as_ref(),
+            &constructor_args.vm_resources.serial_config,
         )?;
 
         // Restore MMIO devices
@@ -607,7 +645,12 @@ pub(crate) mod tests {
         let mut cmdline = Cmdline::new(4096).unwrap();
         let mut event_manager = EventManager::new().unwrap();
         vmm.device_manager
-            .attach_legacy_devices_aarch64(&vmm.vm, &mut event_manager, &mut cmdline, None)
+            .attach_legacy_devices_aarch64(
+                &vmm.vm,
+                &mut event_manager,
+                &mut cmdline,
+                &SerialConfig::default(),
+            )
             .unwrap();
         assert!(vmm.device_manager.mmio_devices.rtc.is_some());
         assert!(vmm.device_manager.mmio_devices.serial.is_none());
@@ -615,7 +658,12 @@ pub(crate) mod tests {
         let mut vmm = default_vmm();
         cmdline.insert("console", "/dev/blah").unwrap();
         vmm.device_manager
-            .attach_legacy_devices_aarch64(&vmm.vm, &mut event_manager, &mut cmdline, None)
+            .attach_legacy_devices_aarch64(
+                &vmm.vm,
+                &mut event_manager,
+                &mut cmdline,
+                &SerialConfig::default(),
+            )
             .unwrap();
         assert!(vmm.device_manager.mmio_devices.rtc.is_some());
         assert!(vmm.device_manager.mmio_devices.serial.is_some());
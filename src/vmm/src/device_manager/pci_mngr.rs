@@ -6,9 +6,9 @@ use std::fmt::Debug;
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex};
 
-use event_manager::{MutEventSubscriber, SubscriberOps};
+use event_manager::{MutEventSubscriber, SubscriberId, SubscriberOps};
 use log::{debug, error, warn};
-use pci::{PciBarRegionType, PciDevice, PciDeviceError, PciRootError};
+use pci::{PciBarRegionType, PciBdf, PciDevice, PciDeviceError, PciRootError};
 use serde::{Deserialize, Serialize};
 use vm_device::BusError;
 
@@ -20,6 +20,8 @@ use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::block::persist::{BlockConstructorArgs, BlockState};
 use crate::devices::virtio::device::VirtioDevice;
 use crate::devices::virtio::generated::virtio_ids;
+use crate::devices::virtio::mem::VirtioMem;
+use crate::devices::virtio::mem::persist::{VirtioMemConstructorArgs, VirtioMemState};
 use crate::devices::virtio::net::Net;
 use crate::devices::virtio::net::persist::{NetConstructorArgs, NetState};
 use crate::devices::virtio::rng::Entropy;
@@ -44,6 +46,23 @@ pub struct PciDevices {
     pub pci_segment: Option<PciSegment>,
     /// All VirtIO PCI devices of the system
     pub virtio_devices: HashMap<(u32, String), Arc<Mutex<VirtioPciDevice>>>,
+    /// Event manager subscribers of the VirtIO PCI devices, removed when they are unplugged
+    subscriber_ids: HashMap<(u32, String), SubscriberId>,
+    /// Subscribers of the hotplugged devices, not yet added to the event manager
+    pending_subscribers: Vec<((u32, String), DeviceSubscriber)>,
+    /// Subscribers of the unplugged devices, not yet removed from the event manager
+    stale_subscribers: Vec<SubscriberId>,
+    /// Unplugged devices, not yet removed from the microVM configuration
+    pub(crate) unplugged_devices: Vec<(u32, String)>,
+}
+
+// Event manager subscriber of a hotplugged device.
+struct DeviceSubscriber(Arc<Mutex<dyn MutEventSubscriber>>);
+
+impl Debug for DeviceSubscriber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceSubscriber").finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -64,6 +83,8 @@ pub enum PciManagerError {
     Kvm(#[from] vmm_sys_util::errno::Error),
     /// MMDS error: {0}
     Mmds(#[from] MmdsConfigError),
+    /// No device in PCI slot {0}
+    SlotNotFound(u8),
 }
 
 impl PciDevices {
@@ -107,7 +128,7 @@ impl PciDevices {
         vm: &Arc<Vm>,
         id: String,
         device: Arc<Mutex<T>>,
-    ) -> Result<(), PciManagerError> {
+    ) -> Result<PciBdf, PciManagerError> {
         // We should only be reaching this point if PCI is enabled
         let pci_segment = self.pci_segment.as_ref().unwrap();
         let pci_device_bdf = pci_segment.next_device_bdf()?;
@@ -151,7 +172,119 @@ impl PciDevices {
             .expect("Poisoned lock")
             .register_notification_ioevent(vm)?;
 
-        Ok(())
+        Ok(pci_device_bdf)
+    }
+
+    /// Hotplugs a VirtIO device in the PCI segment and returns its slot.
+    ///
+    /// The event manager is busy while we handle the hotplug request, so the device is only
+    /// subscribed to it by the next call to [`PciDevices::update_event_subscribers`].
+    pub(crate) fn hotplug_pci_virtio_device<
+        T: 'static + VirtioDevice + MutEventSubscriber + Debug,
+    >(
+        &mut self,
+        vm: &Arc<Vm>,
+        id: String,
+        device: Arc<Mutex<T>>,
+    ) -> Result<u8, PciManagerError> {
+        let device_type: u32 = device.lock().expect("Poisoned lock").device_type();
+        let pci_device_bdf = self.attach_pci_virtio_device(vm, id.clone(), device.clone())?;
+        self.pending_subscribers
+            .push(((device_type, id), DeviceSubscriber(device)));
+        Ok(pci_device_bdf.device())
+    }
+
+    /// Removes the VirtIO device of `slot` from the PCI segment, releases its resources and
+    /// returns its id.
+    ///
+    /// Its event manager subscriber is removed by the next call to
+    /// [`PciDevices::update_event_subscribers`].
+    pub(crate) fn detach_pci_virtio_device(
+        &mut self,
+        vm: &Arc<Vm>,
+        slot: u8,
+    ) -> Result<String, PciManagerError> {
+        let key = self
+            .virtio_devices
+            .iter()
+            .find(|(_, device)| {
+                device
+                    .lock()
+                    .expect("Poisoned lock")
+                    .pci_device_bdf()
+                    .device()
+                    == slot
+            })
+            .map(|(key, _)| key.clone())
+            .ok_or(PciManagerError::SlotNotFound(slot))?;
+
+        // We should only be reaching this point if PCI is enabled
+        let pci_segment = self.pci_segment.as_ref().unwrap();
+        pci_segment
+            .pci_bus
+            .lock()
+            .expect("Poisoned lock")
+            .remove_device(u32::from(slot))?;
+        // Safe to unwrap because we just found the key.
+        let virtio_device = self.virtio_devices.remove(&key).unwrap();
+        let mut locked_device = virtio_device.lock().expect("Poisoned lock");
+
+        let bar = &locked_device.bar_region;
+        debug!("Removing MMIO BAR region: {:#x}:{:#x}", bar.addr, bar.size);
+        vm.common.mmio_bus.remove(bar.addr, bar.size)?;
+        locked_device.unregister_notification_ioevent(vm)?;
+        locked_device.msi_vector_group().release()?;
+
+        let mut resource_allocator_lock = vm.resource_allocator();
+        let resource_allocator = resource_allocator_lock.deref_mut();
+        locked_device.free_bars(
+            &mut resource_allocator.mmio32_memory,
+            &mut resource_allocator.mmio64_memory,
+        )?;
+
+        match self.subscriber_ids.remove(&key) {
+            Some(subscriber_id) => self.stale_subscribers.push(subscriber_id),
+            // The device was unplugged before being subscribed to the event manager.
+            None => self
+                .pending_subscribers
+                .retain(|(pending_key, _)| pending_key != &key),
+        }
+        self.unplugged_devices.push(key.clone());
+
+        Ok(key.1)
+    }
+
+    /// Returns the type and id of the devices removed from the PCI segment since the last call.
+    pub(crate) fn take_unplugged_devices(&mut self) -> Vec<(u32, String)> {
+        std::mem::take(&mut self.unplugged_devices)
+    }
+
+    /// Records the event manager subscriber of a VirtIO PCI device, so that it can be removed if
+    /// the device is unplugged.
+    pub(crate) fn set_subscriber_id(
+        &mut self,
+        device_type: u32,
+        id: &str,
+        subscriber_id: SubscriberId,
+    ) {
+        let key = (device_type, id.to_string());
+        if self.virtio_devices.contains_key(&key) {
+            self.subscriber_ids.insert(key, subscriber_id);
+        }
+    }
+
+    /// Adds the subscribers of the hotplugged devices to the event manager, and removes the ones
+    /// of the unplugged devices.
+    pub fn update_event_subscribers(&mut self, event_manager: &mut EventManager) {
+        for subscriber_id in self.stale_subscribers.drain(..) {
+            if let Err(err) = event_manager.remove_subscriber(subscriber_id) {
+                error!("Could not remove the subscriber of an unplugged device: {err:?}");
+            }
+        }
+        for (key, subscriber) in self.pending_subscribers.drain(..) {
+            let subscriber_id = event_manager.add_subscriber(subscriber.0);
+            self.subscriber_ids.insert(key, subscriber_id);
+        }
     }
 
     fn restore_pci_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
@@ -196,7 +329,9 @@ impl PciDevices {
             .expect("Poisoned lock")
             .register_notification_ioevent(vm)?;
 
-        event_manager.add_subscriber(device);
+        let subscriber_id = event_manager.add_subscriber(device);
+        self.subscriber_ids
+            .insert((device_type, device_id.to_string()), subscriber_id);
 
         Ok(())
     }
@@ -240,6 +375,8 @@ pub struct PciDevicesState {
     pub mmds: Option<MmdsState>,
     /// Entropy device state.
     pub entropy_device: Option<VirtioDeviceState<EntropyState>>,
+    /// Virtio-mem device state.
+    pub virtio_mem_device: Option<VirtioDeviceState<VirtioMemState>>,
 }
 
 pub struct PciDevicesConstructorArgs<'a> {
@@ -331,6 +468,7 @@ impl<'a> Persist<'a> for PciDevices {
                         state.mmds = Some(MmdsState {
                             version: mmds_guard.version(),
                             imds_compat: mmds_guard.imds_compat(),
+                            policy: mmds_guard.policy().clone(),
                         });
                     }
                     net_dev.prepare_save();
@@ -388,6 +526,20 @@ impl<'a> Persist<'a> for PciDevices {
                         transport_state,
                     })
                 }
+                virtio_ids::VIRTIO_ID_MEM => {
+                    let mem_dev = locked_virtio_dev
+                        .as_mut_any()
+                        .downcast_mut::<VirtioMem>()
+                        .unwrap();
+                    let device_state = mem_dev.save();
+
+                    state.virtio_mem_device = Some(VirtioDeviceState {
+                        device_id: mem_dev.id().to_string(),
+                        pci_device_bdf,
+                        device_state,
+                        transport_state,
+                    })
+                }
                 _ => unreachable!(),
             }
         }
@@ -438,7 +590,14 @@ impl<'a> Persist<'a> for PciDevices {
         for block_state in &state.block_devices {
             let device = Arc::new(Mutex::new(
                 Block::restore(
-                    BlockConstructorArgs { mem: mem.clone() },
+                    BlockConstructorArgs {
+                        mem: mem.clone(),
+                        rate_limiter_groups: constructor_args
+                            .vm_resources
+                            .rate_limiter_groups
+                            .groups()
+                            .to_vec(),
+                    },
                     &block_state.device_state,
                 )
                 .unwrap(),
@@ -464,7 +623,12 @@ impl<'a> Persist<'a> for PciDevices {
         if let Some(mmds) = &state.mmds {
             constructor_args
                 .vm_resources
-                .set_mmds_basic_config(mmds.version, mmds.imds_compat, constructor_args.instance_id)
+                .set_mmds_basic_config(
+                    mmds.version,
+                    mmds.imds_compat,
+                    mmds.policy.clone(),
+                    constructor_args.instance_id,
+                )
                 .unwrap();
         } else if state
             .net_devices
@@ -488,6 +652,11 @@ impl<'a> Persist<'a> for PciDevices {
                             .as_ref()
                             // Clone the Arc reference.
                             .cloned(),
+                        rate_limiter_groups: constructor_args
+                            .vm_resources
+                            .rate_limiter_groups
+                            .groups()
+                            .to_vec(),
                     },
                     &net_state.device_state,
                 )
@@ -566,6 +735,32 @@ impl<'a> Persist<'a> for PciDevices {
                 .unwrap()
         }
 
+        if let Some(virtio_mem_state) = &state.virtio_mem_device {
+            let ctor_args = VirtioMemConstructorArgs {
+                mem: mem.clone(),
+                restored_from_file: constructor_args.restored_from_file,
+            };
+
+            let device = Arc::new(Mutex::new(
+                VirtioMem::restore(ctor_args, &virtio_mem_state.device_state).unwrap(),
+            ));
+
+            constructor_args
+                .vm_resources
+                .update_from_restored_device(SharedDeviceType::VirtioMem(device.clone()))
+                .unwrap();
+
+            pci_devices
+                .restore_pci_device(
+                    constructor_args.vm,
+                    device,
+                    &virtio_mem_state.device_id,
+                    &virtio_mem_state.transport_state,
+                    constructor_args.event_manager,
+                )
+                .unwrap()
+        }
+
         Ok(pci_devices)
     }
 }
@@ -582,10 +777,79 @@ mod tests {
     use crate::resources::VmmConfig;
     use crate::snapshot::Snapshot;
     use crate::vmm_config::balloon::BalloonDeviceConfig;
+    use crate::vmm_config::drive::BlockDeviceConfig;
     use crate::vmm_config::entropy::EntropyDeviceConfig;
     use crate::vmm_config::net::NetworkInterfaceConfig;
     use crate::vmm_config::vsock::VsockDeviceConfig;
 
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_hotplug_detach_device() {
+        let mut event_manager = EventManager::new().expect("Unable to create EventManager");
+        let mut vmm = default_vmm();
+        Arc::get_mut(&mut vmm.vm).unwrap().setup_irqchip().unwrap();
+        vmm.device_manager.enable_pci(&vmm.vm).unwrap();
+        let backing_file = TempFile::new().unwrap();
+        let block = || {
+            let config = BlockDeviceConfig {
+                drive_id: String::from("scratch"),
+                partuuid: None,
+                is_root_device: false,
+                cache_type: CacheType::Unsafe,
+
+                is_read_only: Some(true),
+                path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+                rate_limiter: None,
+                rate_limiter_group: None,
+                file_engine_type: None,
+                discard: None,
+                format: None,
+
+                socket: None,
+            };
+            Arc::new(Mutex::new(Block::new(config).unwrap()))
+        };
+        let key = (virtio_ids::VIRTIO_ID_BLOCK, String::from("scratch"));
+
+        let pci_devices = &mut vmm.device_manager.pci_devices;
+        let slot = pci_devices
+            .hotplug_pci_virtio_device(&vmm.vm, key.1.clone(), block())
+            .unwrap();
+        assert!(pci_devices.get_virtio_device(key.0, &key.1).is_some());
+        assert_eq!(pci_devices.pending_subscribers.len(), 1);
+        pci_devices.update_event_subscribers(&mut event_manager);
+        assert!(pci_devices.pending_subscribers.is_empty());
+        assert!(pci_devices.subscriber_ids.contains_key(&key));
+
+        assert_eq!(
+            pci_devices.detach_pci_virtio_device(&vmm.vm, slot).unwrap(),
+            key.1
+        );
+        assert!(pci_devices.get_virtio_device(key.0, &key.1).is_none());
+        assert!(matches!(
+            pci_devices.detach_pci_virtio_device(&vmm.vm, slot),
+            Err(PciManagerError::SlotNotFound(s)) if s == slot
+        ));
+        assert_eq!(pci_devices.stale_subscribers.len(), 1);
+        assert_eq!(pci_devices.take_unplugged_devices(), vec![key.clone()]);
+        assert!(pci_devices.take_unplugged_devices().is_empty());
+        pci_devices.update_event_subscribers(&mut event_manager);
+        assert!(pci_devices.stale_subscribers.is_empty());
+        assert!(pci_devices.subscriber_ids.is_empty());
+
+        // The slot and the resources of the unplugged device can be reused, and a device
+        // unplugged before being subscribed is never added to the event manager.
+        let new_slot = pci_devices
+            .hotplug_pci_virtio_device(&vmm.vm, key.1.clone(), block())
+            .unwrap();
+        assert_eq!(new_slot, slot);
+        pci_devices
+            .detach_pci_virtio_device(&vmm.vm, new_slot)
+            .unwrap();
+        assert!(pci_devices.pending_subscribers.is_empty());
+        assert!(pci_devices.stale_subscribers.is_empty());
+    }
+
     #[test]
     fn test_device_manager_persistence() {
         let mut buf = vec![0; 65536];
@@ -605,6 +869,9 @@ mod tests {
                 amount_mib: 123,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 1,
+                free_page_hinting: false,
+                free_page_reporting: false,
+                policy: None,
             };
             insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_cfg);
             // Add a block device.
@@ -625,6 +892,9 @@ mod tests {
                 guest_mac: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                rx_rate_limiter_group: None,
+                tx_rate_limiter_group: None,
+                num_queues: None,
             };
             insert_net_device_with_mmds(
                 &mut vmm,
@@ -639,6 +909,7 @@ mod tests {
                 vsock_id: Some(vsock_dev_id.to_string()),
                 guest_cid: 3,
                 uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                port_forwards: Default::default(),
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
             // Add an entropy device.
@@ -679,7 +950,9 @@ mod tests {
   "balloon": {{
     "amount_mib": 123,
     "deflate_on_oom": false,
-    "stats_polling_interval_s": 1
+    "stats_polling_interval_s": 1,
+    "free_page_hinting": false,
+    "free_page_reporting": false
   }},
   "drives": [
     {{
@@ -691,6 +964,8 @@ mod tests {
       "path_on_host": "{}",
       "rate_limiter": null,
       "io_engine": "Sync",
+      "discard": null,
+      "format": "Raw",
       "socket": null
     }}
   ],
@@ -723,7 +998,8 @@ mod tests {
       "host_dev_name": "hostname",
       "guest_mac": null,
       "rx_rate_limiter": null,
-      "tx_rate_limiter": null
+      "tx_rate_limiter": null,
+      "num_queues": 1
     }}
   ],
   "vsock": {{
@@ -732,7 +1008,8 @@ mod tests {
   }},
   "entropy": {{
     "rate_limiter": null
-  }}
+  }},
+  "memory-hotplug": null
 }}"#,
             _block_files.last().unwrap().as_path().to_str().unwrap(),
             tmp_sock_file.as_path().to_str().unwrap()
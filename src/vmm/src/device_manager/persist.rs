@@ -14,6 +14,12 @@ use super::acpi::ACPIDeviceManager;
 use super::mmio::*;
 #[cfg(target_arch = "aarch64")]
 use crate::arch::DeviceType;
+use crate::devices::acpi::cpu_hotplug::{
+    CpuHotplugController, CpuHotplugControllerState, CpuHotplugError,
+};
+use crate::devices::acpi::pci_hotplug::{
+    PciHotplugController, PciHotplugControllerState, PciHotplugError,
+};
 use crate::devices::acpi::vmgenid::{VMGenIDState, VMGenIdConstructorArgs, VmGenId, VmGenIdError};
 #[cfg(target_arch = "aarch64")]
 use crate::devices::legacy::RTCDevice;
@@ -25,6 +31,10 @@ use crate::devices::virtio::block::device::Block;
 use crate::devices::virtio::block::persist::{BlockConstructorArgs, BlockState};
 use crate::devices::virtio::device::VirtioDevice;
 use crate::devices::virtio::generated::virtio_ids;
+use crate::devices::virtio::mem::VirtioMem;
+use crate::devices::virtio::mem::persist::{
+    VirtioMemConstructorArgs, VirtioMemPersistError, VirtioMemState,
+};
 use crate::devices::virtio::net::Net;
 use crate::devices::virtio::net::persist::{
     NetConstructorArgs, NetPersistError as NetError, NetState,
@@ -40,6 +50,7 @@ use crate::devices::virtio::vsock::persist::{
 };
 use crate::devices::virtio::vsock::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
 use crate::mmds::data_store::MmdsVersion;
+use crate::mmds::policy::MmdsPolicy;
 use crate::resources::{ResourcesError, VmResources};
 use crate::snapshot::Persist;
 use crate::vmm_config::mmds::MmdsConfigError;
@@ -72,6 +83,8 @@ pub enum DevicePersistError {
     MmdsConfig(#[from] MmdsConfigError),
     /// Entropy: {0}
     Entropy(#[from] EntropyError),
+    /// Virtio-mem: {0}
+    VirtioMem(#[from] VirtioMemPersistError),
     /// Resource misconfiguration: {0}. Is the snapshot file corrupted?
     ResourcesError(#[from] ResourcesError),
     /// Could not activate device: {0}
@@ -105,6 +118,7 @@ pub struct ConnectedLegacyState {
 pub struct MmdsState {
     pub version: MmdsVersion,
     pub imds_compat: bool,
+    pub policy: MmdsPolicy,
 }
 
 /// Holds the device states.
@@ -125,6 +139,8 @@ pub struct DeviceStates {
     pub mmds: Option<MmdsState>,
     /// Entropy device state.
     pub entropy_device: Option<VirtioDeviceState<EntropyState>>,
+    /// Virtio-mem device state.
+    pub virtio_mem_device: Option<VirtioDeviceState<VirtioMemState>>,
 }
 
 /// A type used to extract the concrete `Arc<Mutex<T>>` for each of the device
@@ -136,6 +152,7 @@ pub enum SharedDeviceType {
     Balloon(Arc<Mutex<Balloon>>),
     Vsock(Arc<Mutex<Vsock<VsockUnixBackend>>>),
     Entropy(Arc<Mutex<Entropy>>),
+    VirtioMem(Arc<Mutex<VirtioMem>>),
 }
 
 pub struct MMIODevManagerConstructorArgs<'a> {
@@ -162,6 +179,8 @@ impl fmt::Debug for MMIODevManagerConstructorArgs<'_> {
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct ACPIDeviceManagerState {
     vmgenid: Option<VMGenIDState>,
+    cpu_hotplug: Option<CpuHotplugControllerState>,
+    pci_hotplug: Option<PciHotplugControllerState>,
 }
 
 #[derive(Debug)]
@@ -176,6 +195,10 @@ pub enum ACPIDeviceManagerRestoreError {
     Interrupt(#[from] kvm_ioctls::Error),
     /// Could not create VMGenID device: {0}
     VMGenID(#[from] VmGenIdError),
+    /// Could not restore CPU hotplug controller: {0}
+    CpuHotplug(#[from] CpuHotplugError),
+    /// Could not restore PCI hotplug controller: {0}
+    PciHotplug(#[from] PciHotplugError),
 }
 
 impl<'a> Persist<'a> for ACPIDeviceManager {
@@ -186,6 +209,14 @@ impl<'a> Persist<'a> for ACPIDeviceManager {
     fn save(&self) -> Self::State {
         ACPIDeviceManagerState {
             vmgenid: self.vmgenid.as_ref().map(|dev| dev.save()),
+            cpu_hotplug: self
+                .cpu_hotplug
+                .as_ref()
+                .map(|dev| dev.lock().expect("Poisoned lock").save()),
+            pci_hotplug: self
+                .pci_hotplug
+                .as_ref()
+                .map(|dev| dev.lock().expect("Poisoned lock").save()),
         }
     }
 
@@ -204,6 +235,14 @@ impl<'a> Persist<'a> for ACPIDeviceManager {
             )?;
             dev_manager.attach_vmgenid(vmgenid, constructor_args.vm)?;
         }
+        if let Some(cpu_hotplug_state) = &state.cpu_hotplug {
+            let cpu_hotplug = CpuHotplugController::restore((), cpu_hotplug_state)?;
+            dev_manager.attach_cpu_hotplug(cpu_hotplug, constructor_args.vm)?;
+        }
+        if let Some(pci_hotplug_state) = &state.pci_hotplug {
+            let pci_hotplug = PciHotplugController::restore((), pci_hotplug_state)?;
+            dev_manager.attach_pci_hotplug(pci_hotplug, constructor_args.vm)?;
+        }
         Ok(dev_manager)
     }
 }
@@ -280,6 +319,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         states.mmds = Some(MmdsState {
                             version: mmds_guard.version(),
                             imds_compat: mmds_guard.imds_compat(),
+                            policy: mmds_guard.policy().clone(),
                         });
                     }
 
@@ -335,6 +375,20 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         device_info,
                     });
                 }
+                virtio_ids::VIRTIO_ID_MEM => {
+                    let virtio_mem = locked_device
+                        .as_mut_any()
+                        .downcast_mut::<VirtioMem>()
+                        .unwrap();
+                    let device_state = virtio_mem.save();
+
+                    states.virtio_mem_device = Some(VirtioDeviceState {
+                        device_id,
+                        device_state,
+                        transport_state,
+                        device_info,
+                    });
+                }
                 _ => unreachable!(),
             };
 
@@ -357,7 +411,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                 if state.type_ == DeviceType::Serial {
                     let serial = crate::DeviceManager::setup_serial_device(
                         constructor_args.event_manager,
-                        constructor_args.vm_resources.serial_out_path.as_ref(),
+                        &constructor_args.vm_resources.serial_config,
                     )?;
 
                     dev_manager.register_mmio_serial(vm, serial, Some(state.device_info))?;
@@ -437,7 +491,14 @@ impl<'a> Persist<'a> for MMIODeviceManager {
 
         for block_state in &state.block_devices {
             let device = Arc::new(Mutex::new(Block::restore(
-                BlockConstructorArgs { mem: mem.clone() },
+                BlockConstructorArgs {
+                    mem: mem.clone(),
+                    rate_limiter_groups: constructor_args
+                        .vm_resources
+                        .rate_limiter_groups
+                        .groups()
+                        .to_vec(),
+                },
                 &block_state.device_state,
             )?));
 
@@ -462,6 +523,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             constructor_args.vm_resources.set_mmds_basic_config(
                 mmds.version,
                 mmds.imds_compat,
+                mmds.policy.clone(),
                 constructor_args.instance_id,
             )?;
         }
@@ -476,6 +538,11 @@ impl<'a> Persist<'a> for MMIODeviceManager {
                         .as_ref()
                         // Clone the Arc reference.
                         .cloned(),
+                    rate_limiter_groups: constructor_args
+                        .vm_resources
+                        .rate_limiter_groups
+                        .groups()
+                        .to_vec(),
                 },
                 &net_state.device_state,
             )?));
@@ -549,6 +616,33 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             )?;
         }
 
+        if let Some(virtio_mem_state) = &state.virtio_mem_device {
+            let ctor_args = VirtioMemConstructorArgs {
+                mem: mem.clone(),
+                restored_from_file: constructor_args.restored_from_file,
+            };
+
+            let device = Arc::new(Mutex::new(VirtioMem::restore(
+                ctor_args,
+                &virtio_mem_state.device_state,
+            )?));
+
+            constructor_args
+                .vm_resources
+                .update_from_restored_device(SharedDeviceType::VirtioMem(device.clone()))?;
+
+            restore_helper(
+                device.clone(),
+                virtio_mem_state.device_state.virtio_state.activated,
+                false,
+                device,
+                &virtio_mem_state.device_id,
+                &virtio_mem_state.transport_state,
+                &virtio_mem_state.device_info,
+                constructor_args.event_manager,
+            )?;
+        }
+
         Ok(dev_manager)
     }
 }
@@ -626,6 +720,9 @@ mod tests {
                 amount_mib: 123,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 1,
+                free_page_hinting: false,
+                free_page_reporting: false,
+                policy: None,
             };
             insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_cfg);
             // Add a block device.
@@ -646,6 +743,9 @@ mod tests {
                 guest_mac: None,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                rx_rate_limiter_group: None,
+                tx_rate_limiter_group: None,
+                num_queues: None,
             };
             insert_net_device_with_mmds(
                 &mut vmm,
@@ -660,6 +760,7 @@ mod tests {
                 vsock_id: Some(vsock_dev_id.to_string()),
                 guest_cid: 3,
                 uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                port_forwards: Default::default(),
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
             // Add an entropy device.
@@ -696,7 +797,9 @@ mod tests {
   "balloon": {{
     "amount_mib": 123,
     "deflate_on_oom": false,
-    "stats_polling_interval_s": 1
+    "stats_polling_interval_s": 1,
+    "free_page_hinting": false,
+    "free_page_reporting": false
   }},
   "drives": [
     {{
@@ -708,6 +811,8 @@ mod tests {
       "path_on_host": "{}",
       "rate_limiter": null,
       "io_engine": "Sync",
+      "discard": null,
+      "format": "Raw",
       "socket": null
     }}
   ],
@@ -740,7 +845,8 @@ mod tests {
       "host_dev_name": "hostname",
       "guest_mac": null,
       "rx_rate_limiter": null,
-      "tx_rate_limiter": null
+      "tx_rate_limiter": null,
+      "num_queues": 1
     }}
   ],
   "vsock": {{
@@ -749,7 +855,8 @@ mod tests {
   }},
   "entropy": {{
     "rate_limiter": null
-  }}
+  }},
+  "memory-hotplug": null
 }}"#,
             _block_files.last().unwrap().as_path().to_str().unwrap(),
             tmp_sock_file.as_path().to_str().unwrap()
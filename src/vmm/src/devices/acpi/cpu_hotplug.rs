@@ -0,0 +1,500 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::ops::Range;
+use std::sync::{Arc, Barrier};
+
+use acpi_tables::madt::LocalAPIC;
+use acpi_tables::{Aml, aml};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use vm_superio::Trigger;
+use vmm_sys_util::eventfd::EventFd;
+use zerocopy::IntoBytes;
+
+use super::super::legacy::EventFdTrigger;
+use crate::snapshot::Persist;
+use crate::vstate::resources::ResourceAllocator;
+
+/// Bytes of MMIO space we allocate for the CPU hotplug controller
+pub const CPU_HOTPLUG_MMIO_SIZE: u64 = 0x10;
+
+// Offset of the register used by the guest to select the vCPU the status register refers to.
+const CPU_SELECTOR_OFFSET: u64 = 0;
+// Offset of the status register of the selected vCPU.
+const CPU_STATUS_OFFSET: u64 = 4;
+
+// The selected vCPU is present. Read-only.
+const CPU_PRESENT_FLAG: u8 = 1 << 0;
+// The selected vCPU has been inserted and the guest has not acknowledged it yet. The guest
+// acknowledges the insertion by writing this bit back.
+const CPU_INSERTING_FLAG: u8 = 1 << 1;
+
+/// Hotplug status of a vCPU slot
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CpuSlot {
+    /// The vCPU exists and can be brought online by the guest
+    pub present: bool,
+    /// The vCPU has been hotplugged, but the guest has not scanned it yet
+    pub inserting: bool,
+}
+
+/// ACPI CPU hotplug controller
+///
+/// The controller tells the guest which of the `max_vcpus` possible vCPUs are present. When vCPUs
+/// get hotplugged, it raises an interrupt handled by the Generic Event Device (GED), whose AML
+/// handler scans the controller registers and notifies the guest about the new processors.
+///
+/// The register layout of the controller is the following:
+/// * offset 0, 32 bits: index of the selected vCPU (read/write)
+/// * offset 4, 8 bits: status of the selected vCPU. Bit 0 is set if the vCPU is present, bit 1 is
+///   set if the vCPU has been inserted. Writing 1 to bit 1 acknowledges the insertion.
+#[derive(Debug)]
+pub struct CpuHotplugController {
+    /// Maximum number of vCPUs of the microVM
+    pub max_vcpus: u8,
+    /// Status of every possible vCPU
+    slots: Vec<CpuSlot>,
+    /// vCPU currently selected by the guest
+    selected: u32,
+    /// Interrupt line for notifying the guest about hotplugged vCPUs
+    pub interrupt_evt: EventFdTrigger,
+    /// Guest physical address of the controller registers
+    pub mmio_addr: u64,
+    /// GSI number for the device
+    pub gsi: u32,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum CpuHotplugError {
+    /// Error with CPU hotplug interrupt: {0}
+    Interrupt(#[from] std::io::Error),
+    /// Failed to allocate requested resource: {0}
+    Allocator(#[from] vm_allocator::Error),
+    /// Could not register the CPU hotplug interrupt: {0}
+    RegisterInterrupt(#[from] kvm_ioctls::Error),
+    /// Could not insert the CPU hotplug controller in the MMIO bus: {0}
+    Bus(#[from] vm_device::BusError),
+    /// Invalid number of vCPUs in CPU hotplug controller state: {0}
+    InvalidState(usize),
+}
+
+impl CpuHotplugController {
+    /// Create a CPU hotplug controller from its registers address, its GSI and the status of its
+    /// vCPUs.
+    pub fn from_parts(
+        mmio_addr: u64,
+        gsi: u32,
+        slots: Vec<CpuSlot>,
+    ) -> Result<Self, CpuHotplugError> {
+        debug!(
+            "cpu_hotplug: building CPU hotplug controller. Address: {:#010x}. IRQ: {}",
+            mmio_addr, gsi
+        );
+        let max_vcpus =
+            u8::try_from(slots.len()).map_err(|_| CpuHotplugError::InvalidState(slots.len()))?;
+        let interrupt_evt = EventFdTrigger::new(EventFd::new(libc::EFD_NONBLOCK)?);
+
+        Ok(Self {
+            max_vcpus,
+            slots,
+            selected: 0,
+            interrupt_evt,
+            mmio_addr,
+            gsi,
+        })
+    }
+
+    /// Create a new CPU hotplug controller with `boot_vcpus` present vCPUs, out of `max_vcpus`.
+    ///
+    /// Allocate MMIO space for the controller registers and a GSI for sending notifications.
+    pub fn new(
+        boot_vcpus: u8,
+        max_vcpus: u8,
+        resource_allocator: &mut ResourceAllocator,
+    ) -> Result<Self, CpuHotplugError> {
+        let gsi = resource_allocator.allocate_gsi_legacy(1)?;
+        let mmio_addr = resource_allocator.allocate_32bit_mmio_memory(
+            CPU_HOTPLUG_MMIO_SIZE,
+            CPU_HOTPLUG_MMIO_SIZE,
+            vm_allocator::AllocPolicy::LastMatch,
+        )?;
+        let slots = (0..max_vcpus)
+            .map(|index| CpuSlot {
+                present: index < boot_vcpus,
+                inserting: false,
+            })
+            .collect();
+
+        Self::from_parts(mmio_addr, gsi[0], slots)
+    }
+
+    /// Number of vCPUs currently present in the microVM.
+    pub fn present_vcpus(&self) -> u8 {
+        // There are at most `max_vcpus` slots, so the count fits in a u8.
+        u8::try_from(self.slots.iter().filter(|slot| slot.present).count()).unwrap()
+    }
+
+    /// Mark the vCPUs in `indexes` as present and notify the guest about them.
+    pub fn insert_vcpus(&mut self, indexes: Range<u8>) -> Result<(), std::io::Error> {
+        for index in indexes {
+            let slot = &mut self.slots[usize::from(index)];
+            slot.present = true;
+            slot.inserting = true;
+        }
+        self.notify_guest()
+    }
+
+    /// Send an ACPI notification to the guest, which will scan the controller for inserted vCPUs.
+    pub fn notify_guest(&self) -> Result<(), std::io::Error> {
+        self.interrupt_evt
+            .trigger()
+            .inspect_err(|err| error!("cpu_hotplug: could not send guest notification: {err}"))?;
+        debug!("cpu_hotplug: notifying guest about inserted vCPUs");
+        Ok(())
+    }
+
+    fn selected_slot(&mut self) -> Option<&mut CpuSlot> {
+        self.slots.get_mut(usize::try_from(self.selected).ok()?)
+    }
+}
+
+impl vm_device::BusDevice for CpuHotplugController {
+    fn read(&mut self, _base: u64, offset: u64, data: &mut [u8]) {
+        match (offset, data.len()) {
+            (CPU_SELECTOR_OFFSET, 4) => data.copy_from_slice(&self.selected.to_le_bytes()),
+            (CPU_STATUS_OFFSET, 1) => {
+                data[0] = self.selected_slot().map_or(0, |slot| {
+                    (u8::from(slot.present) * CPU_PRESENT_FLAG)
+                        | (u8::from(slot.inserting) * CPU_INSERTING_FLAG)
+                });
+            }
+            _ => {
+                warn!(
+                    "cpu_hotplug: invalid read at offset {offset:#x} of length {}",
+                    data.len()
+                );
+                data.fill(0);
+            }
+        }
+    }
+
+    fn write(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        match (offset, data.len()) {
+            (CPU_SELECTOR_OFFSET, 4) => {
+                self.selected = u32::from_le_bytes(data.try_into().unwrap());
+            }
+            (CPU_STATUS_OFFSET, 1) => {
+                let acknowledged = data[0] & CPU_INSERTING_FLAG != 0;
+                if let Some(slot) = self.selected_slot().filter(|_| acknowledged) {
+                    slot.inserting = false;
+                }
+            }
+            _ => warn!(
+                "cpu_hotplug: invalid write at offset {offset:#x} of length {}",
+                data.len()
+            ),
+        }
+        None
+    }
+}
+
+/// Logic to save/restore the state of a CPU hotplug controller
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct CpuHotplugControllerState {
+    /// GSI used for the CPU hotplug controller
+    pub gsi: u32,
+    /// Guest physical address of the controller registers
+    pub mmio_addr: u64,
+    /// Status of every possible vCPU
+    pub slots: Vec<CpuSlot>,
+}
+
+impl Persist<'_> for CpuHotplugController {
+    type State = CpuHotplugControllerState;
+    type ConstructorArgs = ();
+    type Error = CpuHotplugError;
+
+    fn save(&self) -> Self::State {
+        CpuHotplugControllerState {
+            gsi: self.gsi,
+            mmio_addr: self.mmio_addr,
+            slots: self.slots.clone(),
+        }
+    }
+
+    fn restore(
+        _constructor_args: Self::ConstructorArgs,
+        state: &Self::State,
+    ) -> std::result::Result<Self, Self::Error> {
+        Self::from_parts(state.mmio_addr, state.gsi, state.slots.clone())
+    }
+}
+
+// AML of a processor device
+struct Processor {
+    index: u8,
+}
+
+impl Aml for Processor {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        // The processor UID matches the one of the Local APIC entry in the MADT.
+        let mat = aml::Buffer::new(LocalAPIC::new(self.index).as_bytes().to_vec());
+        aml::Device::new(
+            format!("C{:03X}", self.index).as_str().try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &"ACPI0007")?,
+                &aml::Name::new("_UID".try_into()?, &self.index)?,
+                &aml::Method::new(
+                    "_STA".try_into()?,
+                    0,
+                    false,
+                    vec![&aml::Return::new(&aml::MethodCall::new(
+                        "CSTA".try_into()?,
+                        vec![&self.index],
+                    ))],
+                ),
+                &aml::Method::new("_MAT".try_into()?, 0, false, vec![&aml::Return::new(&mat)]),
+            ],
+        )
+        .append_aml_bytes(v)
+    }
+}
+
+// AML notifying a processor device when it is the one selected by the first method argument
+struct ProcessorNotify {
+    index: u8,
+}
+
+impl Aml for ProcessorNotify {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        aml::If::new(
+            &aml::Equal::new(&aml::Arg(0), &self.index),
+            vec![&aml::Notify::new(
+                &aml::Path::new(&format!("C{:03X}", self.index))?,
+                &aml::Arg(1),
+            )],
+        )
+        .append_aml_bytes(v)
+    }
+}
+
+impl Aml for CpuHotplugController {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        // MMIO32 addresses always fit in a u32.
+        #[allow(clippy::cast_possible_truncation)]
+        let mmio_addr = self.mmio_addr as u32;
+        #[allow(clippy::cast_possible_truncation)]
+        let mmio_size = CPU_HOTPLUG_MMIO_SIZE as u32;
+
+        // Controller registers
+        aml::Device::new(
+            "_SB_.PRES".try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &aml::EisaName::new("PNP0A06")?)?,
+                &aml::Name::new("_UID".try_into()?, &"CPU Hotplug Controller")?,
+                // Protects the selector register, which is shared by all processors.
+                &aml::Mutex::new("CPLK".try_into()?, 0),
+                &aml::Name::new(
+                    "_CRS".try_into()?,
+                    &aml::ResourceTemplate::new(vec![&aml::Memory32Fixed::new(
+                        true, mmio_addr, mmio_size,
+                    )]),
+                )?,
+                &aml::OpRegion::new(
+                    "PRST".try_into()?,
+                    aml::OpRegionSpace::SystemMemory,
+                    mmio_addr as usize,
+                    mmio_size as usize,
+                ),
+                &aml::Field::new(
+                    "PRST".try_into()?,
+                    aml::FieldAccessType::DWord,
+                    aml::FieldUpdateRule::Preserve,
+                    vec![aml::FieldEntry::Named(*b"CSEL", 32)],
+                ),
+                &aml::Field::new(
+                    "PRST".try_into()?,
+                    aml::FieldAccessType::Byte,
+                    aml::FieldUpdateRule::WriteAsZeroes,
+                    vec![
+                        aml::FieldEntry::Reserved(32),
+                        aml::FieldEntry::Named(*b"CPEN", 1),
+                        aml::FieldEntry::Named(*b"CINS", 1),
+                    ],
+                ),
+            ],
+        )
+        .append_aml_bytes(v)?;
+
+        let lock = "\\_SB_.PRES.CPLK";
+        let selector = aml::Path::new("\\_SB_.PRES.CSEL")?;
+        let present = aml::Path::new("\\_SB_.PRES.CPEN")?;
+        let inserting = aml::Path::new("\\_SB_.PRES.CINS")?;
+
+        // Returns the _STA value of the processor selected by the first argument.
+        let status_method = aml::Method::new(
+            "CSTA".try_into()?,
+            1,
+            true,
+            vec![
+                &aml::Acquire::new(lock.try_into()?, 0xffff),
+                &aml::Store::new(&aml::Local(0), &0usize),
+                &aml::Store::new(&selector, &aml::Arg(0)),
+                &aml::If::new(
+                    &aml::Equal::new(&present, &1usize),
+                    vec![&aml::Store::new(&aml::Local(0), &0xfusize)],
+                ),
+                &aml::Release::new(lock.try_into()?),
+                &aml::Return::new(&aml::Local(0)),
+            ],
+        );
+
+        // Sends the notification of the second argument to the processor selected by the first
+        // argument.
+        let notifies = (0..self.max_vcpus)
+            .map(|index| ProcessorNotify { index })
+            .collect::<Vec<_>>();
+        let notify_method = aml::Method::new(
+            "CTFY".try_into()?,
+            2,
+            false,
+            notifies.iter().map(|notify| notify as &dyn Aml).collect(),
+        );
+
+        // Notifies the guest about every inserted processor and acknowledges the insertions.
+        let max_vcpus = usize::from(self.max_vcpus);
+        let scan_method = aml::Method::new(
+            "CSCN".try_into()?,
+            0,
+            true,
+            vec![
+                &aml::Acquire::new(lock.try_into()?, 0xffff),
+                &aml::Store::new(&aml::Local(0), &0usize),
+                &aml::While::new(
+                    &aml::LessThan::new(&aml::Local(0), &max_vcpus),
+                    vec![
+                        &aml::Store::new(&selector, &aml::Local(0)),
+                        &aml::If::new(
+                            &aml::Equal::new(&inserting, &1usize),
+                            vec![
+                                &aml::MethodCall::new(
+                                    "CTFY".try_into()?,
+                                    vec![&aml::Local(0), &1usize],
+                                ),
+                                &aml::Store::new(&inserting, &1usize),
+                            ],
+                        ),
+                        &aml::Add::new(&aml::Local(0), &aml::Local(0), &1usize),
+                    ],
+                ),
+                &aml::Release::new(lock.try_into()?),
+            ],
+        );
+
+        let processors = (0..self.max_vcpus)
+            .map(|index| Processor { index })
+            .collect::<Vec<_>>();
+        let hid = aml::Name::new("_HID".try_into()?, &"ACPI0010")?;
+        let cid = aml::Name::new("_CID".try_into()?, &aml::EisaName::new("PNP0A05")?)?;
+        let mut children: Vec<&dyn Aml> =
+            vec![&hid, &cid, &status_method, &notify_method, &scan_method];
+        children.extend(processors.iter().map(|processor| processor as &dyn Aml));
+
+        aml::Device::new("_SB_.CPUS".try_into()?, children).append_aml_bytes(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vm_device::BusDevice;
+
+    use super::*;
+
+    fn controller(boot_vcpus: u8, max_vcpus: u8) -> CpuHotplugController {
+        let slots = (0..max_vcpus)
+            .map(|index| CpuSlot {
+                present: index < boot_vcpus,
+                inserting: false,
+            })
+            .collect();
+        CpuHotplugController::from_parts(0xd000_0000, 5, slots).unwrap()
+    }
+
+    fn select(controller: &mut CpuHotplugController, index: u32) -> u8 {
+        controller.write(0, CPU_SELECTOR_OFFSET, &index.to_le_bytes());
+        let mut status = [0u8];
+        controller.read(0, CPU_STATUS_OFFSET, &mut status);
+        status[0]
+    }
+
+    #[test]
+    fn test_cpu_hotplug_registers() {
+        let mut controller = controller(2, 4);
+        assert_eq!(controller.present_vcpus(), 2);
+
+        assert_eq!(select(&mut controller, 1), CPU_PRESENT_FLAG);
+        assert_eq!(select(&mut controller, 2), 0);
+        // Out of range vCPUs are reported as not present.
+        assert_eq!(select(&mut controller, 42), 0);
+
+        let mut selector = [0u8; 4];
+        controller.read(0, CPU_SELECTOR_OFFSET, &mut selector);
+        assert_eq!(u32::from_le_bytes(selector), 42);
+
+        controller.insert_vcpus(2..4).unwrap();
+        assert_eq!(controller.present_vcpus(), 4);
+        assert_eq!(controller.interrupt_evt.read().unwrap(), 1);
+        assert_eq!(
+            select(&mut controller, 3),
+            CPU_PRESENT_FLAG | CPU_INSERTING_FLAG
+        );
+
+        // Acknowledge the insertion of the selected vCPU. Writing the present flag is ignored.
+        controller.write(0, CPU_STATUS_OFFSET, &[CPU_INSERTING_FLAG]);
+        assert_eq!(select(&mut controller, 3), CPU_PRESENT_FLAG);
+        assert_eq!(
+            select(&mut controller, 2),
+            CPU_PRESENT_FLAG | CPU_INSERTING_FLAG
+        );
+        controller.write(0, CPU_STATUS_OFFSET, &[0]);
+        assert_eq!(
+            select(&mut controller, 2),
+            CPU_PRESENT_FLAG | CPU_INSERTING_FLAG
+        );
+
+        // Accesses with an unexpected size are ignored.
+        let mut data = [0xffu8; 2];
+        controller.read(0, CPU_SELECTOR_OFFSET, &mut data);
+        assert_eq!(data, [0, 0]);
+    }
+
+    #[test]
+    fn test_cpu_hotplug_persistence() {
+        let mut controller = controller(1, 3);
+        controller.insert_vcpus(1..2).unwrap();
+
+        let state = controller.save();
+        let mut restored = CpuHotplugController::restore((), &state).unwrap();
+        assert_eq!(restored.max_vcpus, 3);
+        assert_eq!(restored.gsi, controller.gsi);
+        assert_eq!(restored.mmio_addr, controller.mmio_addr);
+        assert_eq!(restored.present_vcpus(), 2);
+        assert_eq!(
+            select(&mut restored, 1),
+            CPU_PRESENT_FLAG | CPU_INSERTING_FLAG
+        );
+        assert_eq!(select(&mut restored, 2), 0);
+    }
+
+    #[test]
+    fn test_cpu_hotplug_aml() {
+        let controller = controller(1, 2);
+        let mut aml = Vec::new();
+        controller.append_aml_bytes(&mut aml).unwrap();
+        assert!(aml.windows(4).any(|name| name == b"PRES"));
+        assert!(aml.windows(4).any(|name| name == b"C001"));
+        assert!(!aml.windows(4).any(|name| name == b"C002"));
+    }
+}
@@ -0,0 +1,415 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::sync::{Arc, Barrier};
+
+use acpi_tables::{Aml, aml};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use vm_superio::Trigger;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::super::legacy::EventFdTrigger;
+use crate::snapshot::Persist;
+use crate::vstate::resources::ResourceAllocator;
+
+/// Bytes of MMIO space we allocate for the PCI hotplug controller
+pub const PCI_HOTPLUG_MMIO_SIZE: u64 = 0x10;
+
+// Offset of the bitmap of the slots with an inserted device. Cleared when read.
+const PCI_DEVICES_UP_OFFSET: u64 = 0;
+// Offset of the bitmap of the slots whose device must be removed. Cleared when read.
+const PCI_DEVICES_DOWN_OFFSET: u64 = 4;
+// Offset of the register used by the guest to eject the devices of a bitmap of slots.
+const PCI_EJECT_OFFSET: u64 = 8;
+// Offset of the register used by the guest to select the PCI segment.
+const PCI_SEGMENT_OFFSET: u64 = 12;
+
+/// ACPI PCI hotplug controller
+///
+/// The controller tells the guest which slots of the PCI segment had a device inserted, or have a
+/// device that must be removed. When devices get hotplugged or unplugged, it raises an interrupt
+/// handled by the Generic Event Device (GED), whose AML handler reads the controller registers and
+/// notifies the guest about the slots. The guest ejects a device by calling the `_EJ0` method of
+/// its slot, after which the VMM can tear the device down.
+///
+/// The register layout of the controller is the following, all registers being 32 bits wide:
+/// * offset 0: bitmap of the slots with an inserted device. Reading it clears it.
+/// * offset 4: bitmap of the slots whose device must be removed. Reading it clears it.
+/// * offset 8: writing a bitmap of slots ejects their devices.
+/// * offset 12: selected PCI segment (read/write). We only support segment 0.
+#[derive(Debug)]
+pub struct PciHotplugController {
+    /// Slots with an inserted device not yet reported to the guest
+    devices_up: u32,
+    /// Slots whose device removal has not been reported to the guest yet
+    devices_down: u32,
+    /// Slots whose device removal has been requested, but not ejected by the guest yet
+    unplugging: u32,
+    /// Slots ejected by the guest, whose device has not been torn down yet
+    ejected: u32,
+    /// PCI segment selected by the guest
+    segment: u32,
+    /// Interrupt line for notifying the guest about hotplugged or unplugged devices
+    pub interrupt_evt: EventFdTrigger,
+    /// Signaled when the guest ejects devices, so that the VMM tears them down
+    pub eject_evt: EventFd,
+    /// Guest physical address of the controller registers
+    pub mmio_addr: u64,
+    /// GSI number for the device
+    pub gsi: u32,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum PciHotplugError {
+    /// Error with PCI hotplug eventfds: {0}
+    EventFd(#[from] std::io::Error),
+    /// Failed to allocate requested resource: {0}
+    Allocator(#[from] vm_allocator::Error),
+    /// Could not register the PCI hotplug interrupt: {0}
+    RegisterInterrupt(#[from] kvm_ioctls::Error),
+    /// Could not insert the PCI hotplug controller in the MMIO bus: {0}
+    Bus(#[from] vm_device::BusError),
+}
+
+impl PciHotplugController {
+    /// Create a PCI hotplug controller from its registers address, its GSI and the state of its
+    /// slots.
+    pub fn from_parts(
+        mmio_addr: u64,
+        gsi: u32,
+        state: &PciHotplugControllerState,
+    ) -> Result<Self, PciHotplugError> {
+        debug!(
+            "pci_hotplug: building PCI hotplug controller. Address: {:#010x}. IRQ: {}",
+            mmio_addr, gsi
+        );
+        let controller = Self {
+            devices_up: state.devices_up,
+            devices_down: state.devices_down,
+            unplugging: state.unplugging,
+            ejected: state.ejected,
+            segment: 0,
+            interrupt_evt: EventFdTrigger::new(EventFd::new(libc::EFD_NONBLOCK)?),
+            eject_evt: EventFd::new(libc::EFD_NONBLOCK)?,
+            mmio_addr,
+            gsi,
+        };
+        // Devices ejected before a snapshot still need to be torn down.
+        if controller.ejected != 0 {
+            controller.eject_evt.write(1)?;
+        }
+        Ok(controller)
+    }
+
+    /// Create a new PCI hotplug controller.
+    ///
+    /// Allocate MMIO space for the controller registers and a GSI for sending notifications.
+    pub fn new(resource_allocator: &mut ResourceAllocator) -> Result<Self, PciHotplugError> {
+        let gsi = resource_allocator.allocate_gsi_legacy(1)?;
+        let mmio_addr = resource_allocator.allocate_32bit_mmio_memory(
+            PCI_HOTPLUG_MMIO_SIZE,
+            PCI_HOTPLUG_MMIO_SIZE,
+            vm_allocator::AllocPolicy::LastMatch,
+        )?;
+
+        Self::from_parts(mmio_addr, gsi[0], &PciHotplugControllerState::default())
+    }
+
+    /// Report the device inserted in `slot` to the guest.
+    pub fn insert_device(&mut self, slot: u8) -> Result<(), std::io::Error> {
+        self.devices_up |= 1 << slot;
+        self.notify_guest()
+    }
+
+    /// Ask the guest to eject the device of `slot`.
+    pub fn remove_device(&mut self, slot: u8) -> Result<(), std::io::Error> {
+        self.devices_down |= 1 << slot;
+        self.unplugging |= 1 << slot;
+        self.notify_guest()
+    }
+
+    /// Whether the removal of the device of `slot` has been requested and is not complete yet.
+    pub fn is_unplugging(&self, slot: u8) -> bool {
+        (self.unplugging | self.ejected) & (1 << slot) != 0
+    }
+
+    /// Returns the bitmap of the slots ejected by the guest since the last call, whose devices
+    /// must be torn down.
+    pub fn take_ejected(&mut self) -> u32 {
+        std::mem::take(&mut self.ejected)
+    }
+
+    /// Send an ACPI notification to the guest, which will scan the controller for inserted and
+    /// removed devices.
+    fn notify_guest(&self) -> Result<(), std::io::Error> {
+        self.interrupt_evt
+            .trigger()
+            .inspect_err(|err| error!("pci_hotplug: could not send guest notification: {err}"))?;
+        debug!("pci_hotplug: notifying guest about PCI devices changes");
+        Ok(())
+    }
+
+    fn eject(&mut self, slots: u32) {
+        let unexpected = slots & !self.unplugging;
+        if unexpected != 0 {
+            warn!("pci_hotplug: ignoring ejection of slots {unexpected:#x} not being unplugged");
+        }
+        let slots = slots & self.unplugging;
+        if slots == 0 {
+            return;
+        }
+
+        debug!("pci_hotplug: guest ejected slots {slots:#x}");
+        self.unplugging &= !slots;
+        self.ejected |= slots;
+        if let Err(err) = self.eject_evt.write(1) {
+            error!("pci_hotplug: could not signal ejected devices: {err}");
+        }
+    }
+}
+
+impl vm_device::BusDevice for PciHotplugController {
+    fn read(&mut self, _base: u64, offset: u64, data: &mut [u8]) {
+        let value = match (offset, data.len()) {
+            (PCI_DEVICES_UP_OFFSET, 4) => std::mem::take(&mut self.devices_up),
+            (PCI_DEVICES_DOWN_OFFSET, 4) => std::mem::take(&mut self.devices_down),
+            (PCI_EJECT_OFFSET, 4) => 0,
+            (PCI_SEGMENT_OFFSET, 4) => self.segment,
+            _ => {
+                warn!(
+                    "pci_hotplug: invalid read at offset {offset:#x} of length {}",
+                    data.len()
+                );
+                data.fill(0);
+                return;
+            }
+        };
+        data.copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn write(&mut self, _base: u64, offset: u64, data: &[u8]) -> Option<Arc<Barrier>> {
+        match (offset, data.len()) {
+            (PCI_EJECT_OFFSET, 4) => {
+                let slots = u32::from_le_bytes(data.try_into().unwrap());
+                if self.segment == 0 {
+                    self.eject(slots);
+                } else {
+                    warn!(
+                        "pci_hotplug: ignoring ejection on PCI segment {}",
+                        self.segment
+                    );
+                }
+            }
+            (PCI_SEGMENT_OFFSET, 4) => {
+                self.segment = u32::from_le_bytes(data.try_into().unwrap());
+            }
+            _ => warn!(
+                "pci_hotplug: invalid write at offset {offset:#x} of length {}",
+                data.len()
+            ),
+        }
+        None
+    }
+}
+
+/// Logic to save/restore the state of a PCI hotplug controller
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct PciHotplugControllerState {
+    /// GSI used for the PCI hotplug controller
+    pub gsi: u32,
+    /// Guest physical address of the controller registers
+    pub mmio_addr: u64,
+    /// Slots with an inserted device not yet reported to the guest
+    pub devices_up: u32,
+    /// Slots whose device removal has not been reported to the guest yet
+    pub devices_down: u32,
+    /// Slots whose device removal has been requested, but not ejected by the guest yet
+    pub unplugging: u32,
+    /// Slots ejected by the guest, whose device has not been torn down yet
+    pub ejected: u32,
+}
+
+impl Persist<'_> for PciHotplugController {
+    type State = PciHotplugControllerState;
+    type ConstructorArgs = ();
+    type Error = PciHotplugError;
+
+    fn save(&self) -> Self::State {
+        PciHotplugControllerState {
+            gsi: self.gsi,
+            mmio_addr: self.mmio_addr,
+            devices_up: self.devices_up,
+            devices_down: self.devices_down,
+            unplugging: self.unplugging,
+            ejected: self.ejected,
+        }
+    }
+
+    fn restore(
+        _constructor_args: Self::ConstructorArgs,
+        state: &Self::State,
+    ) -> std::result::Result<Self, Self::Error> {
+        Self::from_parts(state.mmio_addr, state.gsi, state)
+    }
+}
+
+impl Aml for PciHotplugController {
+    fn append_aml_bytes(&self, v: &mut Vec<u8>) -> Result<(), aml::AmlError> {
+        // MMIO32 addresses always fit in a u32.
+        #[allow(clippy::cast_possible_truncation)]
+        let mmio_addr = self.mmio_addr as u32;
+        #[allow(clippy::cast_possible_truncation)]
+        let mmio_size = PCI_HOTPLUG_MMIO_SIZE as u32;
+
+        // The slots of the PCI segment call `PCEJ` to eject their device, and the segment `PCNT`
+        // method reads the `PCIU` and `PCID` registers to notify the slots.
+        aml::Device::new(
+            "_SB_.PHPR".try_into()?,
+            vec![
+                &aml::Name::new("_HID".try_into()?, &aml::EisaName::new("PNP0A06")?)?,
+                &aml::Name::new("_UID".try_into()?, &"PCI Hotplug Controller")?,
+                // Protects the segment selector register.
+                &aml::Mutex::new("BLCK".try_into()?, 0),
+                &aml::Name::new(
+                    "_CRS".try_into()?,
+                    &aml::ResourceTemplate::new(vec![&aml::Memory32Fixed::new(
+                        true, mmio_addr, mmio_size,
+                    )]),
+                )?,
+                &aml::OpRegion::new(
+                    "PCST".try_into()?,
+                    aml::OpRegionSpace::SystemMemory,
+                    mmio_addr as usize,
+                    mmio_size as usize,
+                ),
+                &aml::Field::new(
+                    "PCST".try_into()?,
+                    aml::FieldAccessType::DWord,
+                    aml::FieldUpdateRule::WriteAsZeroes,
+                    vec![
+                        aml::FieldEntry::Named(*b"PCIU", 32),
+                        aml::FieldEntry::Named(*b"PCID", 32),
+                        aml::FieldEntry::Named(*b"B0EJ", 32),
+                        aml::FieldEntry::Named(*b"PSEG", 32),
+                    ],
+                ),
+                // Ejects the device in the slot of the first argument, on the PCI segment of the
+                // second argument.
+                &aml::Method::new(
+                    "PCEJ".try_into()?,
+                    2,
+                    true,
+                    vec![
+                        &aml::Acquire::new("BLCK".try_into()?, 0xffff),
+                        &aml::Store::new(&aml::Path::new("PSEG")?, &aml::Arg(1)),
+                        &aml::ShiftLeft::new(&aml::Path::new("B0EJ")?, &aml::ONE, &aml::Arg(0)),
+                        &aml::Release::new("BLCK".try_into()?),
+                        &aml::Return::new(&aml::ZERO),
+                    ],
+                ),
+            ],
+        )
+        .append_aml_bytes(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vm_device::BusDevice;
+
+    use super::*;
+
+    fn controller() -> PciHotplugController {
+        PciHotplugController::from_parts(0xd000_0000, 6, &PciHotplugControllerState::default())
+            .unwrap()
+    }
+
+    fn read(controller: &mut PciHotplugController, offset: u64) -> u32 {
+        let mut data = [0u8; 4];
+        controller.read(0, offset, &mut data);
+        u32::from_le_bytes(data)
+    }
+
+    fn eject(controller: &mut PciHotplugController, slots: u32) {
+        controller.write(0, PCI_EJECT_OFFSET, &slots.to_le_bytes());
+    }
+
+    #[test]
+    fn test_pci_hotplug_registers() {
+        let mut controller = controller();
+        assert_eq!(read(&mut controller, PCI_DEVICES_UP_OFFSET), 0);
+        assert_eq!(read(&mut controller, PCI_DEVICES_DOWN_OFFSET), 0);
+
+        // Inserted devices are reported once.
+        controller.insert_device(3).unwrap();
+        controller.insert_device(5).unwrap();
+        assert_eq!(controller.interrupt_evt.read().unwrap(), 2);
+        assert_eq!(read(&mut controller, PCI_DEVICES_UP_OFFSET), 0b10_1000);
+        assert_eq!(read(&mut controller, PCI_DEVICES_UP_OFFSET), 0);
+
+        // Removed devices are reported once, and stay unplugging until ejected.
+        controller.remove_device(3).unwrap();
+        assert_eq!(controller.interrupt_evt.read().unwrap(), 1);
+        assert!(controller.is_unplugging(3));
+        assert!(!controller.is_unplugging(5));
+        assert_eq!(read(&mut controller, PCI_DEVICES_DOWN_OFFSET), 0b1000);
+        assert_eq!(read(&mut controller, PCI_DEVICES_DOWN_OFFSET), 0);
+        assert_eq!(controller.take_ejected(), 0);
+
+        // Only the devices being unplugged can be ejected.
+        eject(&mut controller, 1 << 5);
+        controller.eject_evt.read().unwrap_err();
+        eject(&mut controller, (1 << 3) | (1 << 5));
+        assert_eq!(controller.eject_evt.read().unwrap(), 1);
+        assert!(controller.is_unplugging(3));
+        assert_eq!(controller.take_ejected(), 1 << 3);
+        assert_eq!(controller.take_ejected(), 0);
+        assert!(!controller.is_unplugging(3));
+
+        // Ejections on other segments are ignored.
+        controller.remove_device(4).unwrap();
+        controller.write(0, PCI_SEGMENT_OFFSET, &1u32.to_le_bytes());
+        assert_eq!(read(&mut controller, PCI_SEGMENT_OFFSET), 1);
+        eject(&mut controller, 1 << 4);
+        assert_eq!(controller.take_ejected(), 0);
+
+        // Accesses with an unexpected size are ignored.
+        let mut data = [0xffu8; 2];
+        controller.read(0, PCI_DEVICES_UP_OFFSET, &mut data);
+        assert_eq!(data, [0, 0]);
+    }
+
+    #[test]
+    fn test_pci_hotplug_persistence() {
+        let mut controller = controller();
+        controller.insert_device(1).unwrap();
+        controller.remove_device(2).unwrap();
+        controller.remove_device(4).unwrap();
+        eject(&mut controller, 1 << 4);
+
+        let state = controller.save();
+        let mut restored = PciHotplugController::restore((), &state).unwrap();
+        assert_eq!(restored.gsi, controller.gsi);
+        assert_eq!(restored.mmio_addr, controller.mmio_addr);
+        assert_eq!(read(&mut restored, PCI_DEVICES_UP_OFFSET), 1 << 1);
+        assert_eq!(
+            read(&mut restored, PCI_DEVICES_DOWN_OFFSET),
+            (1 << 2) | (1 << 4)
+        );
+        assert!(restored.is_unplugging(2));
+        // Ejected devices are signaled again after a restore.
+        assert_eq!(restored.eject_evt.read().unwrap(), 1);
+        assert_eq!(restored.take_ejected(), 1 << 4);
+    }
+
+    #[test]
+    fn test_pci_hotplug_aml() {
+        let controller = controller();
+        let mut aml = Vec::new();
+        controller.append_aml_bytes(&mut aml).unwrap();
+        assert!(aml.windows(4).any(|name| name == b"PHPR"));
+        assert!(aml.windows(4).any(|name| name == b"PCEJ"));
+    }
+}
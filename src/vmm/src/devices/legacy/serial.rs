@@ -6,19 +6,23 @@
 // found in the THIRD-PARTY file.
 
 //! Implements a wrapper over an UART serial device.
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{self, Read, Stdin, Write};
+use std::os::unix::fs::FileTypeExt;
 use std::os::unix::io::{AsRawFd, RawFd};
-use std::sync::{Arc, Barrier};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::{Arc, Barrier, Mutex};
 
 use event_manager::{EventOps, Events, MutEventSubscriber};
 use libc::EFD_NONBLOCK;
-use log::{error, warn};
+use log::{error, info, warn};
 use serde::Serialize;
 use vm_superio::serial::{Error as SerialError, SerialEvents};
 use vm_superio::{Serial, Trigger};
-use vmm_sys_util::epoll::EventSet;
+use vmm_sys_util::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
 use vmm_sys_util::eventfd::EventFd;
 
 use crate::devices::legacy::EventFdTrigger;
@@ -29,6 +33,8 @@ use crate::logger::{IncMetric, SharedIncMetric};
 pub const IER_RDA_BIT: u8 = 0b0000_0001;
 /// Received Data Available interrupt offset
 pub const IER_RDA_OFFSET: u8 = 1;
+/// Number of bytes of recent guest output replayed to a client attaching to the serial socket.
+pub const SERIAL_SOCKET_HISTORY_SIZE: usize = 64 * 1024;
 
 /// Metrics specific to the UART device.
 #[derive(Debug, Serialize, Default)]
@@ -126,11 +132,191 @@ impl SerialEvents for SerialEventsWrapper {
     }
 }
 
+/// Unix socket through which a client can interact with the guest serial console.
+///
+/// A single client is attached at a time and a newly accepted connection replaces the current
+/// one. The most recent guest output is kept in a ring buffer and replayed to every client as it
+/// attaches, so that reconnecting does not lose the console context.
+#[derive(Debug)]
+pub struct SerialSocket {
+    /// Socket on which clients connect.
+    listener: UnixListener,
+    /// The currently attached client, if any.
+    client: Option<UnixStream>,
+    /// The last `SERIAL_SOCKET_HISTORY_SIZE` bytes written by the guest.
+    history: VecDeque<u8>,
+    /// Nested epoll set watching the listener and the client, polled upstream as a single fd.
+    epoll: Epoll,
+}
+
+impl SerialSocket {
+    /// Binds the serial console socket at `path`.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        // A socket left behind by a previous Firecracker process would make binding fail.
+        if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        let epoll = Epoll::new()?;
+        epoll.ctl(
+            ControlOperation::Add,
+            listener.as_raw_fd(),
+            EpollEvent::new(EventSet::IN, 0),
+        )?;
+
+        Ok(SerialSocket {
+            listener,
+            client: None,
+            history: VecDeque::with_capacity(SERIAL_SOCKET_HISTORY_SIZE),
+            epoll,
+        })
+    }
+
+    /// Accepts all pending connections, keeping the most recent one as the client.
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.attach(stream),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    warn!("Failed to accept serial socket connection: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn attach(&mut self, stream: UnixStream) {
+        let res = stream.set_nonblocking(true).and_then(|()| {
+            let (front, back) = self.history.as_slices();
+            write_nonblocking(&stream, front)?;
+            write_nonblocking(&stream, back)?;
+            self.epoll.ctl(
+                ControlOperation::Add,
+                stream.as_raw_fd(),
+                EpollEvent::new(EventSet::IN, 1),
+            )
+        });
+        if let Err(err) = res {
+            warn!("Failed to attach serial socket client: {}", err);
+            return;
+        }
+
+        // Dropping the previous client closes it, which also removes it from the epoll set.
+        if self.client.replace(stream).is_some() {
+            info!("Serial socket client replaced by a new connection.");
+        } else {
+            info!("Serial socket client attached.");
+        }
+    }
+
+    fn detach(&mut self) {
+        self.client = None;
+        info!("Serial socket client detached.");
+    }
+}
+
+/// Writes as much of `buf` as the stream accepts without blocking, discarding the rest.
+fn write_nonblocking(mut stream: &UnixStream, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match stream.write(buf) {
+            Ok(0) => break,
+            Ok(count) => buf = &buf[count..],
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+impl Read for SerialSocket {
+    // Never reports EOF: a client going away only detaches it and the socket keeps accepting new
+    // connections, so the serial input must stay registered with the event manager.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.accept();
+
+        let res = match self.client.as_mut() {
+            Some(client) => client.read(buf),
+            None => return Err(io::Error::from_raw_os_error(libc::EWOULDBLOCK)),
+        };
+        match res {
+            Ok(0) => self.detach(),
+            Ok(count) => return Ok(count),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Err(err),
+            Err(err) => {
+                warn!("Failed to read from serial socket client: {}", err);
+                self.detach();
+            }
+        }
+        Err(io::Error::from_raw_os_error(libc::EWOULDBLOCK))
+    }
+}
+
+impl Write for SerialSocket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.history.extend(buf);
+        let excess = self
+            .history
+            .len()
+            .saturating_sub(SERIAL_SOCKET_HISTORY_SIZE);
+        self.history.drain(..excess);
+
+        if let Some(client) = self.client.as_ref() {
+            if let Err(err) = write_nonblocking(client, buf) {
+                warn!("Failed to write to serial socket client: {}", err);
+                self.detach();
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for SerialSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll.as_raw_fd()
+    }
+}
+
+/// Input source of the serial device.
+#[derive(Debug)]
+pub enum SerialIn {
+    /// Firecracker's standard input.
+    Stdin(Stdin),
+    /// The client attached to the serial socket.
+    Socket(Arc<Mutex<SerialSocket>>),
+}
+
+impl Read for SerialIn {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Stdin(stdin) => stdin.read(buf),
+            Self::Socket(socket) => socket.lock().expect("Poisoned lock").read(buf),
+        }
+    }
+}
+
+impl AsRawFd for SerialIn {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Stdin(stdin) => stdin.as_raw_fd(),
+            Self::Socket(socket) => socket.lock().expect("Poisoned lock").as_raw_fd(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SerialOut {
     Sink,
     Stdout(std::io::Stdout),
     File(File),
+    Socket(Arc<Mutex<SerialSocket>>),
 }
 impl std::io::Write for SerialOut {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
@@ -138,6 +324,7 @@ impl std::io::Write for SerialOut {
             Self::Sink => Ok(buf.len()),
             Self::Stdout(stdout) => stdout.write(buf),
             Self::File(file) => file.write(buf),
+            Self::Socket(socket) => socket.lock().expect("Poisoned lock").write(buf),
         }
     }
     fn flush(&mut self) -> std::io::Result<()> {
@@ -145,6 +332,7 @@ impl std::io::Write for SerialOut {
             Self::Sink => Ok(()),
             Self::Stdout(stdout) => stdout.flush(),
             Self::File(file) => file.flush(),
+            Self::Socket(socket) => socket.lock().expect("Poisoned lock").flush(),
         }
     }
 }
@@ -226,10 +414,10 @@ impl<I: Read + AsRawFd + Send + Debug> SerialWrapper<EventFdTrigger, SerialEvent
 }
 
 /// Type for representing a serial device.
-pub type SerialDevice = SerialWrapper<EventFdTrigger, SerialEventsWrapper, Stdin>;
+pub type SerialDevice = SerialWrapper<EventFdTrigger, SerialEventsWrapper, SerialIn>;
 
 impl SerialDevice {
-    pub fn new(serial_in: Option<Stdin>, serial_out: SerialOut) -> Result<Self, std::io::Error> {
+    pub fn new(serial_in: Option<SerialIn>, serial_out: SerialOut) -> Result<Self, std::io::Error> {
         let interrupt_evt = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?);
         let buffer_read_event_fd = EventFdTrigger::new(EventFd::new(EFD_NONBLOCK)?);
 
@@ -248,9 +436,7 @@ impl SerialDevice {
     }
 }
 
-impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
-    for SerialWrapper<EventFdTrigger, SerialEventsWrapper, I>
-{
+impl MutEventSubscriber for SerialDevice {
     /// Handle events on the serial input fd.
     fn process(&mut self, event: Events, ops: &mut EventOps) {
         #[inline]
@@ -331,9 +517,14 @@ impl<I: Read + AsRawFd + Send + Debug> MutEventSubscriber
             // stdin, stdout and stderr to be open('/dev/null'). However, if stdin is redirected
             // from /dev/null then trying to register FILENO_STDIN to epoll will fail with EPERM.
             // Therefore, only try to register stdin to epoll if it is a terminal or a FIFO pipe.
-            // SAFETY: isatty has no invariants that need to be upheld. If serial_fd is an invalid
-            // argument, it will return 0 and set errno to EBADF.
-            if unsafe { libc::isatty(serial_fd) } == 1 || is_fifo(serial_fd) {
+            // The serial socket is always pollable, as it is backed by its own epoll fd.
+            let pollable = match self.input {
+                Some(SerialIn::Socket(_)) => true,
+                // SAFETY: isatty has no invariants that need to be upheld. If serial_fd is an
+                // invalid argument, it will return 0 and set errno to EBADF.
+                _ => (unsafe { libc::isatty(serial_fd) } == 1) || is_fifo(serial_fd),
+            };
+            if pollable {
                 if let Err(err) = ops.add(Events::new(&serial_fd, EventSet::IN)) {
                     warn!("Failed to register serial input fd: {}", err);
                 }
@@ -413,7 +604,7 @@ mod tests {
                 },
                 SerialOut::Sink,
             ),
-            input: None::<std::io::Stdin>,
+            input: None::<SerialIn>,
         };
         serial.serial.raw_input(b"abc").unwrap();
 
@@ -452,6 +643,73 @@ mod tests {
         assert!(!is_fifo(tmp_file.as_file().as_raw_fd()));
     }
 
+    #[test]
+    fn test_serial_socket() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let path = tmp_dir.as_path().join("serial.sock");
+        let mut socket = SerialSocket::new(&path).unwrap();
+        let mut buf = [0u8; 16];
+
+        // Without a client, input would block and output is only recorded.
+        let err = socket.read(&mut buf).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EWOULDBLOCK));
+        assert_eq!(socket.write(b"boot").unwrap(), 4);
+
+        // A client attaching gets the recorded output replayed.
+        let mut client = UnixStream::connect(&path).unwrap();
+        let err = socket.read(&mut buf).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EWOULDBLOCK));
+        assert!(socket.client.is_some());
+        client.read_exact(&mut buf[..4]).unwrap();
+        assert_eq!(&buf[..4], b"boot");
+
+        // Output is forwarded and input is received.
+        socket.write_all(b"$ ").unwrap();
+        client.read_exact(&mut buf[..2]).unwrap();
+        assert_eq!(&buf[..2], b"$ ");
+        client.write_all(b"ls\n").unwrap();
+        assert_eq!(socket.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"ls\n");
+
+        // A new client replaces the previous one and gets the full history.
+        let mut new_client = UnixStream::connect(&path).unwrap();
+        socket.read(&mut buf).unwrap_err();
+        assert_eq!(client.read(&mut buf).unwrap(), 0);
+        new_client.read_exact(&mut buf[..6]).unwrap();
+        assert_eq!(&buf[..6], b"boot$ ");
+
+        // The client going away detaches it without reporting EOF.
+        drop(new_client);
+        let err = socket.read(&mut buf).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EWOULDBLOCK));
+        assert!(socket.client.is_none());
+
+        // Only the most recent output is kept for replay.
+        socket
+            .write_all(&vec![b'x'; SERIAL_SOCKET_HISTORY_SIZE])
+            .unwrap();
+        assert_eq!(socket.history.len(), SERIAL_SOCKET_HISTORY_SIZE);
+        assert!(socket.history.iter().all(|b| *b == b'x'));
+    }
+
+    #[test]
+    fn test_serial_socket_stale_path() {
+        let tmp_dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let path = tmp_dir.as_path().join("serial.sock");
+
+        // The socket file of a listener that went away is replaced.
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+        let _socket = SerialSocket::new(&path).unwrap();
+        UnixStream::connect(&path).unwrap();
+
+        // Other files are left alone.
+        let file_path = tmp_dir.as_path().join("serial.log");
+        File::create(&file_path).unwrap();
+        SerialSocket::new(&file_path).unwrap_err();
+        assert!(file_path.exists());
+    }
+
     #[test]
     fn test_serial_dev_metrics() {
         let serial_metrics: SerialDeviceMetrics = SerialDeviceMetrics::new();
@@ -6,7 +6,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use log::{error, info};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 use vmm_sys_util::eventfd::EventFd;
 
@@ -18,11 +18,12 @@ use super::util::{compact_page_frame_numbers, remove_range};
 use super::{
     BALLOON_DEV_ID, BALLOON_NUM_QUEUES, BALLOON_QUEUE_SIZES, DEFLATE_INDEX, INFLATE_INDEX,
     MAX_PAGE_COMPACT_BUFFER, MAX_PAGES_IN_DESC, MIB_TO_4K_PAGES, STATS_INDEX,
-    VIRTIO_BALLOON_F_DEFLATE_ON_OOM, VIRTIO_BALLOON_F_STATS_VQ, VIRTIO_BALLOON_PFN_SHIFT,
-    VIRTIO_BALLOON_S_AVAIL, VIRTIO_BALLOON_S_CACHES, VIRTIO_BALLOON_S_HTLB_PGALLOC,
-    VIRTIO_BALLOON_S_HTLB_PGFAIL, VIRTIO_BALLOON_S_MAJFLT, VIRTIO_BALLOON_S_MEMFREE,
-    VIRTIO_BALLOON_S_MEMTOT, VIRTIO_BALLOON_S_MINFLT, VIRTIO_BALLOON_S_SWAP_IN,
-    VIRTIO_BALLOON_S_SWAP_OUT,
+    VIRTIO_BALLOON_CMD_ID_DONE, VIRTIO_BALLOON_CMD_ID_STOP, VIRTIO_BALLOON_F_DEFLATE_ON_OOM,
+    VIRTIO_BALLOON_F_FREE_PAGE_HINT, VIRTIO_BALLOON_F_FREE_PAGE_REPORTING,
+    VIRTIO_BALLOON_F_STATS_VQ, VIRTIO_BALLOON_PFN_SHIFT, VIRTIO_BALLOON_S_AVAIL,
+    VIRTIO_BALLOON_S_CACHES, VIRTIO_BALLOON_S_HTLB_PGALLOC, VIRTIO_BALLOON_S_HTLB_PGFAIL,
+    VIRTIO_BALLOON_S_MAJFLT, VIRTIO_BALLOON_S_MEMFREE, VIRTIO_BALLOON_S_MEMTOT,
+    VIRTIO_BALLOON_S_MINFLT, VIRTIO_BALLOON_S_SWAP_IN, VIRTIO_BALLOON_S_SWAP_OUT,
 };
 use crate::devices::virtio::balloon::BalloonError;
 use crate::devices::virtio::device::ActiveState;
@@ -31,7 +32,7 @@ use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_BALLOON;
 use crate::devices::virtio::queue::InvalidAvailIdx;
 use crate::devices::virtio::transport::{VirtioInterrupt, VirtioInterruptType};
 use crate::logger::IncMetric;
-use crate::utils::u64_to_usize;
+use crate::utils::{mib_to_bytes, u64_to_usize, usize_to_u64};
 use crate::vstate::memory::{Address, ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 use crate::{impl_device_type, mem_size_mib};
 
@@ -55,6 +56,7 @@ fn pages_to_mib(amount_pages: u32) -> u32 {
 pub(crate) struct ConfigSpace {
     pub num_pages: u32,
     pub actual_pages: u32,
+    pub free_page_hint_cmd_id: u32,
 }
 
 // SAFETY: Safe because ConfigSpace only contains plain data.
@@ -81,6 +83,54 @@ pub struct BalloonConfig {
     pub deflate_on_oom: bool,
     /// Interval of time in seconds at which the balloon statistics are updated.
     pub stats_polling_interval_s: u16,
+    /// Whether the guest hints its free pages when asked to.
+    pub free_page_hinting: bool,
+    /// Whether the guest reports its free pages.
+    pub free_page_reporting: bool,
+    /// Policy adjusting the target size of the balloon.
+    pub policy: Option<BalloonPolicy>,
+}
+
+/// Policy adjusting the target size of the balloon on each statistics update, to keep an amount
+/// of guest memory free.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalloonPolicy {
+    /// Amount of guest memory to keep free, in MiB.
+    pub target_free_mib: u32,
+    /// Minimum target size of the balloon, in MiB.
+    #[serde(default)]
+    pub min_mib: u32,
+    /// Maximum target size of the balloon, in MiB.
+    pub max_mib: u32,
+    /// Difference between the free guest memory and `target_free_mib` tolerated before
+    /// adjusting the target size of the balloon, in MiB.
+    #[serde(default)]
+    pub hysteresis_mib: u32,
+}
+
+/// Progress of a free page hinting run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HintingState {
+    /// No run is in progress, the guest allocator owns all the free pages.
+    #[default]
+    Idle,
+    /// A run was requested, but the guest did not start hinting free pages yet.
+    Requested,
+    /// The guest is hinting free pages.
+    Hinting,
+    /// The guest hinted all its free pages, and holds them until the run is stopped.
+    Complete,
+}
+
+/// Status of free page hinting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HintingStatus {
+    /// Progress of the current run.
+    pub state: HintingState,
+    /// Amount of guest memory hinted as free during the current run, in MiB.
+    pub hinted_mib: u64,
 }
 
 /// BalloonStats holds statistics returned from the stats_queue.
@@ -180,6 +230,13 @@ pub struct Balloon {
     pub(crate) latest_stats: BalloonStats,
     // A buffer used as pfn accumulator during descriptor processing.
     pub(crate) pfn_buffer: [u32; MAX_PAGE_COMPACT_BUFFER],
+    pub(crate) hinting_state: HintingState,
+    // The command ID of the last free page hinting run, the current one unless it was stopped.
+    pub(crate) last_hint_cmd_id: u32,
+    // The guest memory ranges hinted as free during the current run. The guest holds the pages
+    // until the run is stopped, and they were discarded when hinted.
+    pub(crate) hinted_ranges: Vec<(GuestAddress, u64)>,
+    pub(crate) policy: Option<BalloonPolicy>,
 }
 
 impl Balloon {
@@ -188,6 +245,8 @@ impl Balloon {
         amount_mib: u32,
         deflate_on_oom: bool,
         stats_polling_interval_s: u16,
+        free_page_hinting: bool,
+        free_page_reporting: bool,
         restored_from_file: bool,
     ) -> Result<Balloon, BalloonError> {
         let mut avail_features = 1u64 << VIRTIO_F_VERSION_1;
@@ -200,19 +259,29 @@ impl Balloon {
             avail_features |= 1u64 << VIRTIO_BALLOON_F_STATS_VQ;
         }
 
+        if free_page_hinting {
+            avail_features |= 1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT;
+        }
+
+        if free_page_reporting {
+            avail_features |= 1u64 << VIRTIO_BALLOON_F_FREE_PAGE_REPORTING;
+        }
+
         let queue_evts = [
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
             EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(BalloonError::EventFd)?,
         ];
 
-        let mut queues: Vec<Queue> = BALLOON_QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
-
-        // The VirtIO specification states that the statistics queue should
-        // not be present at all if the statistics are not enabled.
-        if stats_polling_interval_s == 0 {
-            let _ = queues.remove(STATS_INDEX);
-        }
+        // The VirtIO specification states that the statistics, free page hinting and free page
+        // reporting queues should not be present at all if their feature is not enabled.
+        let num_queues = num_queues(avail_features);
+        let queues: Vec<Queue> = BALLOON_QUEUE_SIZES[..num_queues]
+            .iter()
+            .map(|&s| Queue::new(s))
+            .collect();
 
         let stats_timer =
             TimerFd::new_custom(ClockId::Monotonic, true, true).map_err(BalloonError::Timer)?;
@@ -223,6 +292,7 @@ impl Balloon {
             config_space: ConfigSpace {
                 num_pages: mib_to_pages(amount_mib)?,
                 actual_pages: 0,
+                free_page_hint_cmd_id: VIRTIO_BALLOON_CMD_ID_DONE,
             },
             queue_evts,
             queues,
@@ -234,6 +304,10 @@ impl Balloon {
             stats_desc_index: None,
             latest_stats: BalloonStats::default(),
             pfn_buffer: [0u32; MAX_PAGE_COMPACT_BUFFER],
+            hinting_state: HintingState::Idle,
+            last_hint_cmd_id: VIRTIO_BALLOON_CMD_ID_DONE,
+            hinted_ranges: Vec::new(),
+            policy: None,
         })
     }
 
@@ -258,6 +332,20 @@ impl Balloon {
         self.process_stats_queue()
     }
 
+    pub(crate) fn process_free_page_hint_queue_event(&mut self) -> Result<(), BalloonError> {
+        self.queue_evts[self.free_page_hint_index()]
+            .read()
+            .map_err(BalloonError::EventFd)?;
+        self.process_free_page_hint_queue()
+    }
+
+    pub(crate) fn process_free_page_reporting_queue_event(&mut self) -> Result<(), BalloonError> {
+        self.queue_evts[self.free_page_reporting_index()]
+            .read()
+            .map_err(BalloonError::EventFd)?;
+        self.process_free_page_reporting_queue()
+    }
+
     pub(crate) fn process_stats_timer_event(&mut self) -> Result<(), BalloonError> {
         self.stats_timer.read();
         self.trigger_stats_update()
@@ -376,6 +464,7 @@ impl Balloon {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = &self.device_state.active_state().unwrap().mem;
         METRICS.stats_updates_count.inc();
+        let mut updated = false;
 
         while let Some(head) = self.queues[STATS_INDEX].pop()? {
             if let Some(prev_stats_desc) = self.stats_desc_index {
@@ -403,11 +492,160 @@ impl Balloon {
             }
 
             self.stats_desc_index = Some(head.index);
+            updated = true;
         }
 
+        if updated {
+            self.apply_policy()?;
+        }
         Ok(())
     }
 
+    /// Adjusts the target size of the balloon for the guest to have the free memory requested by
+    /// the policy, according to the latest statistics.
+    fn apply_policy(&mut self) -> Result<(), BalloonError> {
+        let Some(policy) = self.policy else {
+            return Ok(());
+        };
+        // The available memory accounts for the caches the guest can reclaim, but older drivers
+        // only report the free memory.
+        let Some(free_bytes) = self
+            .latest_stats
+            .available_memory
+            .or(self.latest_stats.free_memory)
+        else {
+            return Ok(());
+        };
+        let free_mib = free_bytes >> 20;
+        let target_free_mib = u64::from(policy.target_free_mib);
+        let hysteresis_mib = u64::from(policy.hysteresis_mib);
+        // The free memory was measured with the pages the balloon actually holds.
+        let actual_mib = u64::from(pages_to_mib(self.config_space.actual_pages));
+        let target_mib = if free_mib > target_free_mib + hysteresis_mib {
+            actual_mib + (free_mib - target_free_mib)
+        } else if free_mib + hysteresis_mib < target_free_mib {
+            actual_mib.saturating_sub(target_free_mib - free_mib)
+        } else {
+            return Ok(());
+        };
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem_mib = mem_size_mib(&self.device_state.active_state().unwrap().mem);
+        let target_mib = target_mib
+            .clamp(u64::from(policy.min_mib), u64::from(policy.max_mib))
+            .min(mem_mib);
+        // The target size is at most `max_mib`.
+        let target_mib = u32::try_from(target_mib).unwrap_or(policy.max_mib);
+
+        let current_mib = self.size_mb();
+        if target_mib == current_mib {
+            return Ok(());
+        }
+        if target_mib > current_mib {
+            METRICS.policy_inflate_count.inc();
+        } else {
+            METRICS.policy_deflate_count.inc();
+        }
+        info!(
+            "balloon: {} MiB of guest memory free, policy changes the target size from {} MiB to \
+             {} MiB.",
+            free_mib, current_mib, target_mib
+        );
+        self.update_size(target_mib)
+    }
+
+    pub(crate) fn process_free_page_hint_queue(&mut self) -> Result<(), BalloonError> {
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = &self.device_state.active_state().unwrap().mem;
+        let queue_idx = self.free_page_hint_index();
+        let queue = &mut self.queues[queue_idx];
+        let mut needs_interrupt = false;
+
+        while let Some(head) = queue.pop()? {
+            let head_index = head.index;
+            if head.is_write_only() {
+                // The guest hints its free pages as device writable buffers. The guest holds the
+                // pages until the run is stopped, so they are discarded right away.
+                for desc in head {
+                    if self.hinting_state != HintingState::Hinting {
+                        break;
+                    }
+                    METRICS.free_page_hint_count.inc();
+                    let range = (desc.addr, u64::from(desc.len));
+                    if let Err(err) = remove_range(mem, range, self.restored_from_file) {
+                        error!("Error removing hinted memory range: {:?}", err);
+                        METRICS.free_page_hint_fails.inc();
+                        continue;
+                    }
+                    METRICS.free_page_hint_bytes_count.add(range.1);
+                    match self.hinted_ranges.last_mut() {
+                        Some((addr, len)) if addr.unchecked_add(*len) == range.0 => *len += range.1,
+                        _ => self.hinted_ranges.push(range),
+                    }
+                }
+            } else {
+                // The guest sends the command ID of the run when it starts hinting, and the stop
+                // command ID when it has no more free pages to hint.
+                let cmd_id = mem
+                    .read_obj::<u32>(head.addr)
+                    .map_err(|_| BalloonError::MalformedDescriptor)?;
+                match (self.hinting_state, cmd_id) {
+                    (HintingState::Requested, cmd_id)
+                        if cmd_id == self.config_space.free_page_hint_cmd_id =>
+                    {
+                        self.hinting_state = HintingState::Hinting;
+                    }
+                    (HintingState::Hinting, VIRTIO_BALLOON_CMD_ID_STOP) => {
+                        self.hinting_state = HintingState::Complete;
+                    }
+                    // Command IDs of previous runs.
+                    _ => {}
+                }
+            }
+            queue.add_used(head_index, 0)?;
+            needs_interrupt = true;
+        }
+        queue.advance_used_ring_idx();
+
+        if needs_interrupt {
+            self.signal_used_queue(queue_idx)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn process_free_page_reporting_queue(&mut self) -> Result<(), BalloonError> {
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = &self.device_state.active_state().unwrap().mem;
+        let queue_idx = self.free_page_reporting_index();
+        let queue = &mut self.queues[queue_idx];
+        let mut needs_interrupt = false;
+
+        while let Some(head) = queue.pop()? {
+            let head_index = head.index;
+            // Each descriptor of the chain is a range of free pages, which the guest allocator
+            // only hands out again after the report is acknowledged.
+            for desc in head {
+                METRICS.free_page_report_count.inc();
+                let range = (desc.addr, u64::from(desc.len));
+                if let Err(err) = remove_range(mem, range, self.restored_from_file) {
+                    error!("Error removing reported memory range: {:?}", err);
+                    METRICS.free_page_report_fails.inc();
+                    continue;
+                }
+                METRICS.free_page_report_bytes_count.add(range.1);
+            }
+            queue.add_used(head_index, 0)?;
+            needs_interrupt = true;
+        }
+        queue.advance_used_ring_idx();
+
+        if needs_interrupt {
+            self.signal_used_queue(queue_idx)
+        } else {
+            Ok(())
+        }
+    }
+
     pub(crate) fn signal_used_queue(&self, qidx: usize) -> Result<(), BalloonError> {
         self.interrupt_trigger()
             .trigger(VirtioInterruptType::Queue(
@@ -428,6 +666,18 @@ impl Balloon {
         if let Err(BalloonError::InvalidAvailIdx(err)) = self.process_deflate_queue() {
             return Err(err);
         }
+        if self.free_page_hinting() {
+            if let Err(BalloonError::InvalidAvailIdx(err)) = self.process_free_page_hint_queue() {
+                return Err(err);
+            }
+        }
+        if self.free_page_reporting() {
+            if let Err(BalloonError::InvalidAvailIdx(err)) =
+                self.process_free_page_reporting_queue()
+            {
+                return Err(err);
+            }
+        }
 
         Ok(())
     }
@@ -512,6 +762,89 @@ impl Balloon {
         self.stats_polling_interval_s
     }
 
+    /// Sets the policy adjusting the target size of the balloon, or removes it.
+    pub fn set_policy(&mut self, policy: Option<BalloonPolicy>) -> Result<(), BalloonError> {
+        if let Some(policy) = policy {
+            if !self.stats_enabled() {
+                return Err(BalloonError::PolicyWithoutStatistics);
+            }
+            if policy.min_mib > policy.max_mib {
+                return Err(BalloonError::InvalidPolicy(policy.min_mib, policy.max_mib));
+            }
+        }
+        self.policy = policy;
+        Ok(())
+    }
+
+    pub fn free_page_hinting(&self) -> bool {
+        self.avail_features & (1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT) != 0
+    }
+
+    pub fn free_page_reporting(&self) -> bool {
+        self.avail_features & (1u64 << VIRTIO_BALLOON_F_FREE_PAGE_REPORTING) != 0
+    }
+
+    /// Asks the guest to hint its free pages, in a new run replacing the current one.
+    pub fn start_hinting(&mut self) -> Result<(), BalloonError> {
+        self.check_hinting_enabled()?;
+        // Command IDs are arbitrary, except for the stop and done command IDs.
+        self.last_hint_cmd_id = self
+            .last_hint_cmd_id
+            .wrapping_add(1)
+            .max(VIRTIO_BALLOON_CMD_ID_DONE + 1);
+        self.config_space.free_page_hint_cmd_id = self.last_hint_cmd_id;
+        self.hinting_state = HintingState::Requested;
+        self.hinted_ranges.clear();
+        self.interrupt_trigger()
+            .trigger(VirtioInterruptType::Config)
+            .map_err(BalloonError::InterruptError)
+    }
+
+    /// Stops the current free page hinting run, letting the guest reuse the hinted pages.
+    pub fn stop_hinting(&mut self) -> Result<(), BalloonError> {
+        self.check_hinting_enabled()?;
+        self.config_space.free_page_hint_cmd_id = VIRTIO_BALLOON_CMD_ID_DONE;
+        self.hinting_state = HintingState::Idle;
+        self.hinted_ranges.clear();
+        self.interrupt_trigger()
+            .trigger(VirtioInterruptType::Config)
+            .map_err(BalloonError::InterruptError)
+    }
+
+    /// Returns the progress of the current free page hinting run.
+    pub fn hinting_status(&self) -> Result<HintingStatus, BalloonError> {
+        self.check_hinting_enabled()?;
+        let hinted_bytes: u64 = self.hinted_ranges.iter().map(|(_, len)| len).sum();
+        Ok(HintingStatus {
+            state: self.hinting_state,
+            hinted_mib: hinted_bytes / usize_to_u64(mib_to_bytes(1)),
+        })
+    }
+
+    /// Returns the guest memory ranges hinted as free during the current run. Their pages were
+    /// discarded, and the guest does not care about their contents unless it accessed them since.
+    pub fn free_page_hints(&self) -> &[(GuestAddress, u64)] {
+        &self.hinted_ranges
+    }
+
+    fn check_hinting_enabled(&self) -> Result<(), BalloonError> {
+        if !self.is_activated() {
+            return Err(BalloonError::DeviceNotActive);
+        }
+        if self.acked_features & (1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT) == 0 {
+            return Err(BalloonError::FreePageHintingDisabled);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn free_page_hint_index(&self) -> usize {
+        STATS_INDEX + usize::from(self.stats_enabled())
+    }
+
+    pub(crate) fn free_page_reporting_index(&self) -> usize {
+        self.free_page_hint_index() + usize::from(self.free_page_hinting())
+    }
+
     /// Retrieve latest stats for the balloon device.
     pub fn latest_stats(&mut self) -> Result<BalloonStats, BalloonError> {
         if self.stats_enabled() {
@@ -531,6 +864,9 @@ impl Balloon {
             amount_mib: self.size_mb(),
             deflate_on_oom: self.deflate_on_oom(),
             stats_polling_interval_s: self.stats_polling_interval_s(),
+            free_page_hinting: self.free_page_hinting(),
+            free_page_reporting: self.free_page_reporting(),
+            policy: self.policy,
         }
     }
 
@@ -543,6 +879,15 @@ impl Balloon {
     }
 }
 
+/// Returns the number of queues present with `avail_features`.
+pub(crate) fn num_queues(avail_features: u64) -> usize {
+    let has_queue = |feature: u32| usize::from(avail_features & (1u64 << feature) != 0);
+    BALLOON_NUM_QUEUES
+        - (1 - has_queue(VIRTIO_BALLOON_F_STATS_VQ))
+        - (1 - has_queue(VIRTIO_BALLOON_F_FREE_PAGE_HINT))
+        - (1 - has_queue(VIRTIO_BALLOON_F_FREE_PAGE_REPORTING))
+}
+
 impl VirtioDevice for Balloon {
     impl_device_type!(VIRTIO_ID_BALLOON);
 
@@ -739,55 +1084,79 @@ pub(crate) mod tests {
         // Test all feature combinations.
         for deflate_on_oom in [true, false].iter() {
             for stats_interval in [0, 1].iter() {
-                let mut balloon = Balloon::new(0, *deflate_on_oom, *stats_interval, false).unwrap();
-                assert_eq!(balloon.device_type(), VIRTIO_ID_BALLOON);
-
-                let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
-                    | (u64::from(*deflate_on_oom) << VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
-                    | ((u64::from(*stats_interval)) << VIRTIO_BALLOON_F_STATS_VQ);
-
-                assert_eq!(
-                    balloon.avail_features_by_page(0),
-                    (features & 0xFFFFFFFF) as u32
-                );
-                assert_eq!(balloon.avail_features_by_page(1), (features >> 32) as u32);
-                for i in 2..10 {
-                    assert_eq!(balloon.avail_features_by_page(i), 0u32);
-                }
+                for (hinting, reporting) in
+                    [(false, false), (true, false), (false, true), (true, true)]
+                {
+                    let mut balloon = Balloon::new(
+                        0,
+                        *deflate_on_oom,
+                        *stats_interval,
+                        hinting,
+                        reporting,
+                        false,
+                    )
+                    .unwrap();
+                    assert_eq!(balloon.device_type(), VIRTIO_ID_BALLOON);
+                    assert_eq!(
+                        balloon.queues().len(),
+                        2 + usize::from(*stats_interval)
+                            + usize::from(hinting)
+                            + usize::from(reporting)
+                    );
+
+                    let features: u64 = (1u64 << VIRTIO_F_VERSION_1)
+                        | (u64::from(*deflate_on_oom) << VIRTIO_BALLOON_F_DEFLATE_ON_OOM)
+                        | ((u64::from(*stats_interval)) << VIRTIO_BALLOON_F_STATS_VQ)
+                        | (u64::from(hinting) << VIRTIO_BALLOON_F_FREE_PAGE_HINT)
+                        | (u64::from(reporting) << VIRTIO_BALLOON_F_FREE_PAGE_REPORTING);
+
+                    assert_eq!(
+                        balloon.avail_features_by_page(0),
+                        (features & 0xFFFFFFFF) as u32
+                    );
+                    assert_eq!(balloon.avail_features_by_page(1), (features >> 32) as u32);
+                    for i in 2..10 {
+                        assert_eq!(balloon.avail_features_by_page(i), 0u32);
+                    }
 
-                for i in 0..10 {
-                    balloon.ack_features_by_page(i, u32::MAX);
+                    for i in 0..10 {
+                        balloon.ack_features_by_page(i, u32::MAX);
+                    }
+                    // Only present features should be acknowledged.
+                    assert_eq!(balloon.acked_features, features);
                 }
-                // Only present features should be acknowledged.
-                assert_eq!(balloon.acked_features, features);
             }
         }
     }
 
     #[test]
     fn test_virtio_read_config() {
-        let balloon = Balloon::new(0x10, true, 0, false).unwrap();
+        let balloon = Balloon::new(0x10, true, 0, false, false, false).unwrap();
 
         let cfg = BalloonConfig {
             amount_mib: 16,
             deflate_on_oom: true,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         };
         assert_eq!(balloon.config(), cfg);
 
         let mut actual_config_space = [0u8; BALLOON_CONFIG_SPACE_SIZE];
         balloon.read_config(0, &mut actual_config_space);
-        // The first 4 bytes are num_pages, the last 4 bytes are actual_pages.
-        // The config space is little endian.
+        // The first 4 bytes are num_pages, the next 4 bytes are actual_pages, and the last 4
+        // bytes are the free page hinting command ID. The config space is little endian.
         // 0x10 MB in the constructor corresponds to 0x1000 pages in the
         // config space.
-        let expected_config_space: [u8; BALLOON_CONFIG_SPACE_SIZE] =
-            [0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let expected_config_space: [u8; BALLOON_CONFIG_SPACE_SIZE] = [
+            0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ];
         assert_eq!(actual_config_space, expected_config_space);
 
         // Invalid read.
         let expected_config_space: [u8; BALLOON_CONFIG_SPACE_SIZE] =
-            [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf];
+            [0xd, 0xe, 0xa, 0xd, 0xb, 0xe, 0xe, 0xf, 0xd, 0xe, 0xa, 0xd];
         actual_config_space = expected_config_space;
         balloon.read_config(
             BALLOON_CONFIG_SPACE_SIZE as u64 + 1,
@@ -800,10 +1169,11 @@ pub(crate) mod tests {
 
     #[test]
     fn test_virtio_write_config() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
 
-        let expected_config_space: [u8; BALLOON_CONFIG_SPACE_SIZE] =
-            [0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let expected_config_space: [u8; BALLOON_CONFIG_SPACE_SIZE] = [
+            0x00, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ];
         balloon.write_config(0, &expected_config_space);
 
         let mut actual_config_space = [0u8; BALLOON_CONFIG_SPACE_SIZE];
@@ -826,7 +1196,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_invalid_request() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         // Only initialize the inflate queue to demonstrate invalid request handling.
@@ -887,7 +1257,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_inflate() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -959,7 +1329,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_deflate() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         let defq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -1009,7 +1379,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_stats() {
-        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 1, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         let statsq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -1099,9 +1469,197 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_free_page_reporting() {
+        let mut balloon = Balloon::new(0, true, 0, false, true, false).unwrap();
+        let mem = default_mem();
+        let interrupt = default_interrupt();
+        let repq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        balloon.set_queue(INFLATE_INDEX, repq.create_queue());
+        balloon.set_queue(DEFLATE_INDEX, repq.create_queue());
+        // The free page reporting queue follows the deflate queue when the statistics and free
+        // page hinting are disabled.
+        balloon.set_queue(STATS_INDEX, repq.create_queue());
+        balloon.activate(mem.clone(), interrupt).unwrap();
+
+        // Fill the second page with non-zero bytes.
+        for i in 0..0x1000 {
+            mem.write_obj::<u8>(1, GuestAddress((1 << 12) + i)).unwrap();
+        }
+
+        set_request(&repq, 0, 0x1000, 0x1000, VIRTQ_DESC_F_WRITE);
+        check_metric_after_block!(
+            METRICS.free_page_report_count,
+            1,
+            balloon.process_free_page_reporting_queue_event().unwrap()
+        );
+        check_request_completion(&repq, 0);
+        assert!(
+            balloon
+                .interrupt_trigger()
+                .has_pending_interrupt(VirtioInterruptType::Queue(STATS_INDEX.try_into().unwrap()))
+        );
+
+        // Check that the reported page was discarded.
+        for i in 0..0x1000 {
+            assert_eq!(mem.read_obj::<u8>(GuestAddress((1 << 12) + i)).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_free_page_hinting() {
+        let mut balloon = Balloon::new(0, true, 0, true, false, false).unwrap();
+        assert!(matches!(
+            balloon.start_hinting(),
+            Err(BalloonError::DeviceNotActive)
+        ));
+
+        let mem = default_mem();
+        let interrupt = default_interrupt();
+        let hintq = VirtQueue::new(GuestAddress(0), &mem, 16);
+        balloon.set_queue(INFLATE_INDEX, hintq.create_queue());
+        balloon.set_queue(DEFLATE_INDEX, hintq.create_queue());
+        balloon.set_queue(STATS_INDEX, hintq.create_queue());
+        balloon.activate(mem.clone(), interrupt).unwrap();
+        // The guest driver did not acknowledge free page hinting.
+        assert!(matches!(
+            balloon.start_hinting(),
+            Err(BalloonError::FreePageHintingDisabled)
+        ));
+        balloon.ack_features_by_page(0, u32::MAX);
+
+        balloon.start_hinting().unwrap();
+        assert_eq!(
+            balloon.hinting_status().unwrap().state,
+            HintingState::Requested
+        );
+        assert!(
+            balloon
+                .interrupt_trigger()
+                .has_pending_interrupt(VirtioInterruptType::Config)
+        );
+        let mut cmd_id = [0u8; 4];
+        balloon.read_config(8, &mut cmd_id);
+        assert_eq!(u32::from_le_bytes(cmd_id), 2);
+
+        // Fill the second page with non-zero bytes.
+        for i in 0..0x1000 {
+            mem.write_obj::<u8>(1, GuestAddress((1 << 12) + i)).unwrap();
+        }
+
+        // Pages hinted before the guest starts the run are ignored.
+        set_request(&hintq, 0, 0x1000, 0x1000, VIRTQ_DESC_F_WRITE);
+        balloon.process_free_page_hint_queue_event().unwrap();
+        check_request_completion(&hintq, 0);
+        assert!(balloon.free_page_hints().is_empty());
+
+        // The guest starts the run with its command ID.
+        let cmd_id_addr = 0x2000;
+        mem.write_obj::<u32>(2, GuestAddress(cmd_id_addr)).unwrap();
+        set_request(&hintq, 1, cmd_id_addr, 4, 0);
+        balloon.process_free_page_hint_queue_event().unwrap();
+        check_request_completion(&hintq, 1);
+        assert_eq!(
+            balloon.hinting_status().unwrap().state,
+            HintingState::Hinting
+        );
+
+        set_request(&hintq, 2, 0x1000, 0x1000, VIRTQ_DESC_F_WRITE);
+        check_metric_after_block!(
+            METRICS.free_page_hint_count,
+            1,
+            balloon.process_free_page_hint_queue_event().unwrap()
+        );
+        check_request_completion(&hintq, 2);
+        assert_eq!(balloon.free_page_hints(), [(GuestAddress(0x1000), 0x1000)]);
+        // Check that the hinted page was discarded.
+        for i in 0..0x1000 {
+            assert_eq!(mem.read_obj::<u8>(GuestAddress((1 << 12) + i)).unwrap(), 0);
+        }
+
+        // The guest stops the run once all its free pages are hinted.
+        mem.write_obj::<u32>(VIRTIO_BALLOON_CMD_ID_STOP, GuestAddress(cmd_id_addr))
+            .unwrap();
+        set_request(&hintq, 3, cmd_id_addr, 4, 0);
+        balloon.process_free_page_hint_queue_event().unwrap();
+        check_request_completion(&hintq, 3);
+        assert_eq!(
+            balloon.hinting_status().unwrap(),
+            HintingStatus {
+                state: HintingState::Complete,
+                hinted_mib: 0,
+            }
+        );
+
+        balloon.stop_hinting().unwrap();
+        assert_eq!(balloon.hinting_status().unwrap().state, HintingState::Idle);
+        assert!(balloon.free_page_hints().is_empty());
+        balloon.read_config(8, &mut cmd_id);
+        assert_eq!(u32::from_le_bytes(cmd_id), VIRTIO_BALLOON_CMD_ID_DONE);
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = BalloonPolicy {
+            target_free_mib: 8,
+            min_mib: 0,
+            max_mib: 16,
+            hysteresis_mib: 2,
+        };
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
+        assert!(matches!(
+            balloon.set_policy(Some(policy)),
+            Err(BalloonError::PolicyWithoutStatistics)
+        ));
+        let mut balloon = Balloon::new(0, true, 1, false, false, false).unwrap();
+        assert!(matches!(
+            balloon.set_policy(Some(BalloonPolicy {
+                min_mib: 17,
+                ..policy
+            })),
+            Err(BalloonError::InvalidPolicy(17, 16))
+        ));
+        balloon.set_policy(Some(policy)).unwrap();
+        assert_eq!(balloon.config().policy, Some(policy));
+        // Switch the state to active.
+        balloon.device_state = DeviceState::Activated(ActiveState {
+            mem: single_region_mem(32 << 20),
+            interrupt: default_interrupt(),
+        });
+
+        // Too much free memory, the balloon inflates.
+        balloon.latest_stats.available_memory = Some(20 << 20);
+        check_metric_after_block!(
+            METRICS.policy_inflate_count,
+            1,
+            balloon.apply_policy().unwrap()
+        );
+        assert_eq!(balloon.size_mb(), 12);
+
+        // The free memory is within the hysteresis.
+        balloon.update_actual_pages(mib_to_pages(12).unwrap());
+        balloon.latest_stats.available_memory = Some(9 << 20);
+        balloon.apply_policy().unwrap();
+        assert_eq!(balloon.size_mb(), 12);
+
+        // Too little free memory, the balloon deflates.
+        balloon.latest_stats.available_memory = Some(2 << 20);
+        check_metric_after_block!(
+            METRICS.policy_deflate_count,
+            1,
+            balloon.apply_policy().unwrap()
+        );
+        assert_eq!(balloon.size_mb(), 6);
+
+        // The target size is bounded by the policy.
+        balloon.latest_stats.available_memory = Some(30 << 20);
+        balloon.apply_policy().unwrap();
+        assert_eq!(balloon.size_mb(), 16);
+    }
+
     #[test]
     fn test_process_balloon_queues() {
-        let mut balloon = Balloon::new(0x10, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0x10, true, 0, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -1116,7 +1674,7 @@ pub(crate) mod tests {
 
     #[test]
     fn test_update_stats_interval() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         let mem = default_mem();
         let q = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(INFLATE_INDEX, q.create_queue());
@@ -1129,7 +1687,7 @@ pub(crate) mod tests {
         );
         balloon.update_stats_polling_interval(0).unwrap();
 
-        let mut balloon = Balloon::new(0, true, 1, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 1, false, false, false).unwrap();
         let mem = default_mem();
         let q = VirtQueue::new(GuestAddress(0), &mem, 16);
         balloon.set_queue(INFLATE_INDEX, q.create_queue());
@@ -1147,14 +1705,14 @@ pub(crate) mod tests {
 
     #[test]
     fn test_cannot_update_inactive_device() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         // Assert that we can't update an inactive device.
         balloon.update_size(1).unwrap_err();
     }
 
     #[test]
     fn test_num_pages() {
-        let mut balloon = Balloon::new(0, true, 0, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 0, false, false, false).unwrap();
         // Switch the state to active.
         balloon.device_state = DeviceState::Activated(ActiveState {
             mem: single_region_mem(32 << 20),
@@ -1172,7 +1730,10 @@ pub(crate) mod tests {
 
         let mut actual_config = vec![0; BALLOON_CONFIG_SPACE_SIZE];
         balloon.read_config(0, &mut actual_config);
-        assert_eq!(actual_config, vec![0x0, 0x10, 0x0, 0x0, 0x34, 0x12, 0, 0]);
+        assert_eq!(
+            actual_config,
+            vec![0x0, 0x10, 0x0, 0x0, 0x34, 0x12, 0, 0, 0x1, 0, 0, 0]
+        );
         assert_eq!(balloon.num_pages(), 0x1000);
         assert_eq!(balloon.actual_pages(), 0x1234);
         assert_eq!(balloon.size_mb(), 16);
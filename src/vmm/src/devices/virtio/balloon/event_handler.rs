@@ -15,6 +15,8 @@ impl Balloon {
     const PROCESS_VIRTQ_DEFLATE: u32 = 2;
     const PROCESS_VIRTQ_STATS: u32 = 3;
     const PROCESS_STATS_TIMER: u32 = 4;
+    const PROCESS_VIRTQ_FREE_PAGE_HINT: u32 = 5;
+    const PROCESS_VIRTQ_FREE_PAGE_REPORTING: u32 = 6;
 
     fn register_runtime_events(&self, ops: &mut EventOps) {
         if let Err(err) = ops.add(Events::with_data(
@@ -47,6 +49,27 @@ impl Balloon {
                 error!("Failed to register stats timerfd event: {}", err);
             }
         }
+        if self.free_page_hinting() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[self.free_page_hint_index()],
+                Self::PROCESS_VIRTQ_FREE_PAGE_HINT,
+                EventSet::IN,
+            )) {
+                error!("Failed to register free page hinting queue event: {}", err);
+            }
+        }
+        if self.free_page_reporting() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[self.free_page_reporting_index()],
+                Self::PROCESS_VIRTQ_FREE_PAGE_REPORTING,
+                EventSet::IN,
+            )) {
+                error!(
+                    "Failed to register free page reporting queue event: {}",
+                    err
+                );
+            }
+        }
     }
 
     fn register_activate_event(&self, ops: &mut EventOps) {
@@ -103,6 +126,12 @@ impl MutEventSubscriber for Balloon {
                 Self::PROCESS_STATS_TIMER => self
                     .process_stats_timer_event()
                     .unwrap_or_else(report_balloon_event_fail),
+                Self::PROCESS_VIRTQ_FREE_PAGE_HINT => self
+                    .process_free_page_hint_queue_event()
+                    .unwrap_or_else(report_balloon_event_fail),
+                Self::PROCESS_VIRTQ_FREE_PAGE_REPORTING => self
+                    .process_free_page_reporting_queue_event()
+                    .unwrap_or_else(report_balloon_event_fail),
                 _ => {
                     warn!("Balloon: Spurious event received: {:?}", source);
                 }
@@ -142,7 +171,7 @@ pub mod tests {
     #[test]
     fn test_event_handler() {
         let mut event_manager = EventManager::new().unwrap();
-        let mut balloon = Balloon::new(0, true, 10, false).unwrap();
+        let mut balloon = Balloon::new(0, true, 10, false, false, false).unwrap();
         let mem = default_mem();
         let interrupt = default_interrupt();
         let infq = VirtQueue::new(GuestAddress(0), &mem, 16);
@@ -63,6 +63,22 @@ pub(super) struct BalloonDeviceMetrics {
     pub deflate_count: SharedIncMetric,
     /// Number of times when handling events on a balloon device failed.
     pub event_fails: SharedIncMetric,
+    /// Number of free memory ranges hinted by the driver.
+    pub free_page_hint_count: SharedIncMetric,
+    /// Number of bytes of hinted free memory discarded.
+    pub free_page_hint_bytes_count: SharedIncMetric,
+    /// Number of hinted free memory ranges which could not be discarded.
+    pub free_page_hint_fails: SharedIncMetric,
+    /// Number of free memory ranges reported by the driver.
+    pub free_page_report_count: SharedIncMetric,
+    /// Number of bytes of reported free memory discarded.
+    pub free_page_report_bytes_count: SharedIncMetric,
+    /// Number of reported free memory ranges which could not be discarded.
+    pub free_page_report_fails: SharedIncMetric,
+    /// Number of times the balloon policy increased the target size of the balloon.
+    pub policy_inflate_count: SharedIncMetric,
+    /// Number of times the balloon policy decreased the target size of the balloon.
+    pub policy_deflate_count: SharedIncMetric,
 }
 impl BalloonDeviceMetrics {
     /// Const default construction.
@@ -74,6 +90,14 @@ impl BalloonDeviceMetrics {
             stats_update_fails: SharedIncMetric::new(),
             deflate_count: SharedIncMetric::new(),
             event_fails: SharedIncMetric::new(),
+            free_page_hint_count: SharedIncMetric::new(),
+            free_page_hint_bytes_count: SharedIncMetric::new(),
+            free_page_hint_fails: SharedIncMetric::new(),
+            free_page_report_count: SharedIncMetric::new(),
+            free_page_report_bytes_count: SharedIncMetric::new(),
+            free_page_report_fails: SharedIncMetric::new(),
+            policy_inflate_count: SharedIncMetric::new(),
+            policy_deflate_count: SharedIncMetric::new(),
         }
     }
 }
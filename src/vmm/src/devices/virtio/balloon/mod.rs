@@ -8,11 +8,13 @@ mod event_handler;
 pub mod metrics;
 pub mod persist;
 pub mod test_utils;
-mod util;
+pub(crate) mod util;
 
 use log::error;
 
-pub use self::device::{Balloon, BalloonConfig, BalloonStats};
+pub use self::device::{
+    Balloon, BalloonConfig, BalloonPolicy, BalloonStats, HintingState, HintingStatus,
+};
 use super::queue::{InvalidAvailIdx, QueueError};
 use crate::devices::virtio::balloon::metrics::METRICS;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
@@ -22,15 +24,19 @@ use crate::logger::IncMetric;
 /// Because Balloon is unique per-vm, this ID can be hardcoded.
 pub const BALLOON_DEV_ID: &str = "balloon";
 /// The size of the config space.
-pub const BALLOON_CONFIG_SPACE_SIZE: usize = 8;
-/// Number of virtio queues.
-pub const BALLOON_NUM_QUEUES: usize = 3;
+pub const BALLOON_CONFIG_SPACE_SIZE: usize = 12;
+/// Maximum number of virtio queues.
+pub const BALLOON_NUM_QUEUES: usize = 5;
 /// Virtio queue sizes, in number of descriptor chain heads.
-//  There are 3 queues for a virtio device (in this order): RX, TX, Event
+//  There are up to 5 queues for a virtio balloon device (in this order): inflate, deflate,
+//  statistics, free page hinting and free page reporting. Queues of disabled features are not
+//  present, and the following queues take their index.
 pub const BALLOON_QUEUE_SIZES: [u16; BALLOON_NUM_QUEUES] = [
     FIRECRACKER_MAX_QUEUE_SIZE,
     FIRECRACKER_MAX_QUEUE_SIZE,
     FIRECRACKER_MAX_QUEUE_SIZE,
+    FIRECRACKER_MAX_QUEUE_SIZE,
+    FIRECRACKER_MAX_QUEUE_SIZE,
 ];
 // Number of 4K pages in a MiB.
 pub const MIB_TO_4K_PAGES: u32 = 256;
@@ -51,6 +57,14 @@ pub const STATS_INDEX: usize = 2;
 // The feature bitmap for virtio balloon.
 const VIRTIO_BALLOON_F_STATS_VQ: u32 = 1; // Enable statistics.
 const VIRTIO_BALLOON_F_DEFLATE_ON_OOM: u32 = 2; // Deflate balloon on OOM.
+const VIRTIO_BALLOON_F_FREE_PAGE_HINT: u32 = 3; // Hint free pages on request.
+const VIRTIO_BALLOON_F_FREE_PAGE_REPORTING: u32 = 5; // Report free pages.
+
+// The free page hinting command IDs with a special meaning.
+/// Command ID telling the host that the guest stopped hinting free pages.
+pub const VIRTIO_BALLOON_CMD_ID_STOP: u32 = 0;
+/// Command ID telling the guest to give the hinted free pages back to its allocator.
+pub const VIRTIO_BALLOON_CMD_ID_DONE: u32 = 1;
 
 // The statistics tags.
 const VIRTIO_BALLOON_S_SWAP_IN: u16 = 0;
@@ -81,8 +95,14 @@ pub enum BalloonError {
     QueueRestoreError,
     /// Received stats query when stats are disabled.
     StatisticsDisabled,
+    /// Free page hinting is not enabled, or was not acknowledged by the guest driver.
+    FreePageHintingDisabled,
     /// Statistics cannot be enabled/disabled after activation.
     StatisticsStateChange,
+    /// The balloon policy needs the statistics to be enabled.
+    PolicyWithoutStatistics,
+    /// The minimum size of the balloon policy, {0}MiB, is larger than its maximum size, {1}MiB.
+    InvalidPolicy(u32, u32),
     /// Requested memory should be less than {0}MiB
     TooMuchMemoryRequested(u32),
     /// Error while processing the virt queues: {0}
@@ -10,14 +10,16 @@ use serde::{Deserialize, Serialize};
 use timerfd::{SetTimeFlags, TimerState};
 
 use super::*;
-use crate::devices::virtio::balloon::device::{BalloonStats, ConfigSpace};
+use crate::devices::virtio::balloon::device::{
+    BalloonPolicy, BalloonStats, ConfigSpace, HintingState, num_queues,
+};
 use crate::devices::virtio::device::{ActiveState, DeviceState};
 use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_BALLOON;
 use crate::devices::virtio::persist::VirtioDeviceState;
 use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
 use crate::devices::virtio::transport::VirtioInterrupt;
 use crate::snapshot::Persist;
-use crate::vstate::memory::GuestMemoryMmap;
+use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
 
 /// Information about the balloon config's that are saved
 /// at snapshot.
@@ -25,6 +27,7 @@ use crate::vstate::memory::GuestMemoryMmap;
 pub struct BalloonConfigSpaceState {
     num_pages: u32,
     actual_pages: u32,
+    free_page_hint_cmd_id: u32,
 }
 
 /// Information about the balloon stats that are saved
@@ -87,6 +90,10 @@ pub struct BalloonState {
     stats_desc_index: Option<u16>,
     latest_stats: BalloonStatsState,
     config_space: BalloonConfigSpaceState,
+    hinting_state: HintingState,
+    last_hint_cmd_id: u32,
+    hinted_ranges: Vec<(u64, u64)>,
+    policy: Option<BalloonPolicy>,
     pub virtio_state: VirtioDeviceState,
 }
 
@@ -111,7 +118,16 @@ impl Persist<'_> for Balloon {
             config_space: BalloonConfigSpaceState {
                 num_pages: self.config_space.num_pages,
                 actual_pages: self.config_space.actual_pages,
+                free_page_hint_cmd_id: self.config_space.free_page_hint_cmd_id,
             },
+            hinting_state: self.hinting_state,
+            last_hint_cmd_id: self.last_hint_cmd_id,
+            hinted_ranges: self
+                .hinted_ranges
+                .iter()
+                .map(|(addr, len)| (addr.0, *len))
+                .collect(),
+            policy: self.policy,
             virtio_state: VirtioDeviceState::from_device(self),
         }
     }
@@ -120,21 +136,21 @@ impl Persist<'_> for Balloon {
         constructor_args: Self::ConstructorArgs,
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
+        let avail_features = state.virtio_state.avail_features;
         // We can safely create the balloon with arbitrary flags and
         // num_pages because we will overwrite them after.
         let mut balloon = Balloon::new(
             0,
             false,
             state.stats_polling_interval_s,
+            avail_features & (1u64 << VIRTIO_BALLOON_F_FREE_PAGE_HINT) != 0,
+            avail_features & (1u64 << VIRTIO_BALLOON_F_FREE_PAGE_REPORTING) != 0,
             constructor_args.restored_from_file,
         )?;
 
-        let mut num_queues = BALLOON_NUM_QUEUES;
-        // As per the virtio 1.1 specification, the statistics queue
-        // should not exist if the statistics are not enabled.
-        if state.stats_polling_interval_s == 0 {
-            num_queues -= 1;
-        }
+        // As per the virtio 1.1 specification, the statistics, free page hinting and free page
+        // reporting queues should not exist if their feature is not enabled.
+        let num_queues = num_queues(avail_features);
         balloon.queues = state
             .virtio_state
             .build_queues_checked(
@@ -150,7 +166,16 @@ impl Persist<'_> for Balloon {
         balloon.config_space = ConfigSpace {
             num_pages: state.config_space.num_pages,
             actual_pages: state.config_space.actual_pages,
+            free_page_hint_cmd_id: state.config_space.free_page_hint_cmd_id,
         };
+        balloon.hinting_state = state.hinting_state;
+        balloon.last_hint_cmd_id = state.last_hint_cmd_id;
+        balloon.hinted_ranges = state
+            .hinted_ranges
+            .iter()
+            .map(|(addr, len)| (GuestAddress(*addr), *len))
+            .collect();
+        balloon.policy = state.policy;
 
         if state.virtio_state.activated && balloon.stats_enabled() {
             // Restore the stats descriptor.
@@ -184,7 +209,15 @@ mod tests {
         let mut mem = vec![0; 4096];
 
         // Create and save the balloon device.
-        let balloon = Balloon::new(0x42, false, 2, false).unwrap();
+        let mut balloon = Balloon::new(0x42, false, 2, true, true, false).unwrap();
+        balloon
+            .set_policy(Some(BalloonPolicy {
+                target_free_mib: 64,
+                min_mib: 0,
+                max_mib: 128,
+                hysteresis_mib: 8,
+            }))
+            .unwrap();
 
         Snapshot::new(balloon.save())
             .save(&mut mem.as_mut_slice())
@@ -218,5 +251,9 @@ mod tests {
         );
         assert_eq!(restored_balloon.stats_desc_index, balloon.stats_desc_index);
         assert_eq!(restored_balloon.latest_stats, balloon.latest_stats);
+        assert_eq!(restored_balloon.hinting_state, balloon.hinting_state);
+        assert_eq!(restored_balloon.last_hint_cmd_id, balloon.last_hint_cmd_id);
+        assert_eq!(restored_balloon.hinted_ranges, balloon.hinted_ranges);
+        assert_eq!(restored_balloon.policy, balloon.policy);
     }
 }
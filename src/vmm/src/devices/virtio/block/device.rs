@@ -17,7 +17,7 @@ use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_BLOCK;
 use crate::devices::virtio::queue::{InvalidAvailIdx, Queue};
 use crate::devices::virtio::transport::VirtioInterrupt;
 use crate::impl_device_type;
-use crate::rate_limiter::BucketUpdate;
+use crate::rate_limiter::{BucketUpdate, RateLimiter};
 use crate::snapshot::Persist;
 use crate::vmm_config::drive::BlockDeviceConfig;
 use crate::vstate::memory::GuestMemoryMmap;
@@ -75,6 +75,16 @@ impl Block {
         }
     }
 
+    pub fn set_rate_limiter(&mut self, rate_limiter: RateLimiter) -> Result<(), BlockError> {
+        match self {
+            Self::Virtio(b) => {
+                b.rate_limiter = rate_limiter;
+                Ok(())
+            }
+            Self::VhostUser(_) => Err(BlockError::InvalidBlockBackend),
+        }
+    }
+
     pub fn update_config(&mut self) -> Result<(), BlockError> {
         match self {
             Self::Virtio(_) => Err(BlockError::InvalidBlockBackend),
@@ -1,13 +1,14 @@
 // Copyright 2023 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
 use super::vhost_user::persist::VhostUserBlockState;
 use super::virtio::persist::VirtioBlockState;
 use crate::devices::virtio::transport::VirtioInterrupt;
+use crate::rate_limiter::RateLimiterGroup;
 use crate::vstate::memory::GuestMemoryMmap;
 
 /// Block device state.
@@ -30,4 +31,6 @@ impl BlockState {
 #[derive(Debug)]
 pub struct BlockConstructorArgs {
     pub mem: GuestMemoryMmap,
+    /// The rate limiter groups the device may belong to.
+    pub rate_limiter_groups: Vec<Arc<Mutex<RateLimiterGroup>>>,
 }
@@ -73,7 +73,10 @@ impl TryFrom<&BlockDeviceConfig> for VhostUserBlockConfig {
             && value.is_read_only.is_none()
             && value.path_on_host.is_none()
             && value.rate_limiter.is_none()
+            && value.rate_limiter_group.is_none()
             && value.file_engine_type.is_none()
+            && value.discard.is_none()
+            && value.format.is_none()
         {
             Ok(Self {
                 drive_id: value.drive_id.clone(),
@@ -100,7 +103,10 @@ impl From<VhostUserBlockConfig> for BlockDeviceConfig {
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: Some(value.socket),
         }
@@ -404,7 +410,10 @@ mod tests {
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: Some("sock".to_string()),
         };
@@ -419,7 +428,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Some(FileEngineType::Sync),
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -434,7 +446,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Some(FileEngineType::Sync),
+            discard: None,
+            format: None,
 
             socket: Some("sock".to_string()),
         };
@@ -11,7 +11,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{Seek, SeekFrom};
 use std::ops::Deref;
 use std::os::linux::fs::MetadataExt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use block_io::FileEngine;
@@ -27,7 +27,8 @@ use crate::devices::virtio::block::CacheType;
 use crate::devices::virtio::block::virtio::metrics::{BlockDeviceMetrics, BlockMetricsPerDevice};
 use crate::devices::virtio::device::{ActiveState, DeviceState, VirtioDevice};
 use crate::devices::virtio::generated::virtio_blk::{
-    VIRTIO_BLK_F_FLUSH, VIRTIO_BLK_F_RO, VIRTIO_BLK_ID_BYTES,
+    VIRTIO_BLK_F_DISCARD, VIRTIO_BLK_F_FLUSH, VIRTIO_BLK_F_RO, VIRTIO_BLK_F_WRITE_ZEROES,
+    VIRTIO_BLK_ID_BYTES,
 };
 use crate::devices::virtio::generated::virtio_config::VIRTIO_F_VERSION_1;
 use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_BLOCK;
@@ -52,6 +53,51 @@ pub enum FileEngineType {
     Sync,
 }
 
+/// Format of the disk image backing a block device.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ImageFormat {
+    /// The guest sees the contents of the file as is.
+    #[default]
+    Raw,
+    /// A qcow2 image, optionally on top of a chain of read-only backing files.
+    Qcow2,
+}
+
+/// Limits advertised to the guest when discard and write zeroes requests are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DiscardConfig {
+    /// Maximum number of sectors in a single discard request.
+    #[serde(default = "DiscardConfig::default_max_sectors")]
+    pub max_discard_sectors: u32,
+    /// Maximum number of sectors in a single write zeroes request.
+    #[serde(default = "DiscardConfig::default_max_sectors")]
+    pub max_write_zeroes_sectors: u32,
+    /// Preferred alignment, in sectors, of the ranges passed to discard requests.
+    #[serde(default = "DiscardConfig::default_sector_alignment")]
+    pub discard_sector_alignment: u32,
+}
+
+impl DiscardConfig {
+    fn default_max_sectors() -> u32 {
+        u32::MAX
+    }
+
+    fn default_sector_alignment() -> u32 {
+        1
+    }
+}
+
+impl Default for DiscardConfig {
+    fn default() -> Self {
+        Self {
+            max_discard_sectors: Self::default_max_sectors(),
+            max_write_zeroes_sectors: Self::default_max_sectors(),
+            discard_sector_alignment: Self::default_sector_alignment(),
+        }
+    }
+}
+
 /// Helper object for setting up all `Block` fields derived from its backing file.
 #[derive(Debug)]
 pub struct DiskProperties {
@@ -95,15 +141,35 @@ impl DiskProperties {
         disk_image_path: String,
         is_disk_read_only: bool,
         file_engine_type: FileEngineType,
+        image_format: ImageFormat,
     ) -> Result<Self, VirtioBlockError> {
         let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
-        let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
         let image_id = Self::build_disk_image_id(&disk_image);
 
+        let (file_engine, disk_size) = match image_format {
+            ImageFormat::Raw => {
+                let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
+                let file_engine = FileEngine::from_file(disk_image, file_engine_type)
+                    .map_err(VirtioBlockError::FileEngine)?;
+                (file_engine, disk_size)
+            }
+            ImageFormat::Qcow2 => {
+                if file_engine_type != FileEngineType::Sync {
+                    return Err(VirtioBlockError::Qcow2AsyncEngine);
+                }
+                let engine =
+                    block_io::Qcow2FileEngine::from_file(disk_image, Path::new(&disk_image_path))
+                        .map_err(|err| {
+                        VirtioBlockError::FileEngine(block_io::BlockIoError::Qcow2(err))
+                    })?;
+                let disk_size = engine.virtual_size();
+                (FileEngine::Qcow2(engine), disk_size)
+            }
+        };
+
         Ok(Self {
             file_path: disk_image_path,
-            file_engine: FileEngine::from_file(disk_image, file_engine_type)
-                .map_err(VirtioBlockError::FileEngine)?,
+            file_engine,
             nsectors: disk_size >> SECTOR_SHIFT,
             image_id,
         })
@@ -116,12 +182,19 @@ impl DiskProperties {
         is_disk_read_only: bool,
     ) -> Result<(), VirtioBlockError> {
         let mut disk_image = Self::open_file(&disk_image_path, is_disk_read_only)?;
-        let disk_size = Self::file_size(&disk_image_path, &mut disk_image)?;
 
         self.image_id = Self::build_disk_image_id(&disk_image);
+        let raw_disk_size = match self.file_engine {
+            FileEngine::Qcow2(_) => None,
+            _ => Some(Self::file_size(&disk_image_path, &mut disk_image)?),
+        };
         self.file_engine
-            .update_file_path(disk_image)
+            .update_file_path(disk_image, Path::new(&disk_image_path))
             .map_err(VirtioBlockError::FileEngine)?;
+        let disk_size = match &self.file_engine {
+            FileEngine::Qcow2(engine) => engine.virtual_size(),
+            _ => raw_disk_size.unwrap_or_default(),
+        };
         self.nsectors = disk_size >> SECTOR_SHIFT;
         self.file_path = disk_image_path;
 
@@ -160,10 +233,40 @@ impl DiskProperties {
     }
 }
 
+/// Layout of the block device configuration space, up to the write zeroes fields.
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 #[repr(C)]
 pub struct ConfigSpace {
     pub capacity: u64,
+    // Fields for features we don't offer (size_max, seg_max, geometry, blk_size, topology,
+    // writeback, num_queues).
+    _unused0: [u8; 28],
+    pub max_discard_sectors: u32,
+    pub max_discard_seg: u32,
+    pub discard_sector_alignment: u32,
+    pub max_write_zeroes_sectors: u32,
+    pub max_write_zeroes_seg: u32,
+    pub write_zeroes_may_unmap: u8,
+    _unused1: [u8; 7],
+}
+
+impl ConfigSpace {
+    /// Build the configuration space for a disk of `nsectors` sectors.
+    pub fn new(nsectors: u64, discard: Option<&DiscardConfig>) -> Self {
+        let mut config_space = ConfigSpace {
+            capacity: nsectors.to_le(),
+            ..Default::default()
+        };
+        if let Some(discard) = discard {
+            config_space.max_discard_sectors = discard.max_discard_sectors.to_le();
+            config_space.max_discard_seg = 1u32.to_le();
+            config_space.discard_sector_alignment = discard.discard_sector_alignment.to_le();
+            config_space.max_write_zeroes_sectors = discard.max_write_zeroes_sectors.to_le();
+            config_space.max_write_zeroes_seg = 1u32.to_le();
+            config_space.write_zeroes_may_unmap = 1;
+        }
+        config_space
+    }
 }
 
 // SAFETY: `ConfigSpace` contains only PODs in `repr(C)` or `repr(transparent)`, without padding.
@@ -194,10 +297,18 @@ pub struct VirtioBlockConfig {
     pub path_on_host: String,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Id of the rate limiter group the device shares its I/O budget with.
+    pub rate_limiter_group: Option<String>,
     /// The type of IO engine used by the device.
     #[serde(default)]
     #[serde(rename = "io_engine")]
     pub file_engine_type: FileEngineType,
+    /// Enables discard and write zeroes requests with the given limits.
+    #[serde(default)]
+    pub discard: Option<DiscardConfig>,
+    /// The format of the disk image.
+    #[serde(default)]
+    pub format: ImageFormat,
 }
 
 impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
@@ -214,7 +325,10 @@ impl TryFrom<&BlockDeviceConfig> for VirtioBlockConfig {
                 is_read_only: value.is_read_only.unwrap_or(false),
                 path_on_host: value.path_on_host.as_ref().unwrap().clone(),
                 rate_limiter: value.rate_limiter,
+                rate_limiter_group: value.rate_limiter_group.clone(),
                 file_engine_type: value.file_engine_type.unwrap_or_default(),
+                discard: value.discard,
+                format: value.format.unwrap_or_default(),
             })
         } else {
             Err(VirtioBlockError::Config)
@@ -233,7 +347,10 @@ impl From<VirtioBlockConfig> for BlockDeviceConfig {
             is_read_only: Some(value.is_read_only),
             path_on_host: Some(value.path_on_host),
             rate_limiter: value.rate_limiter,
+            rate_limiter_group: value.rate_limiter_group,
             file_engine_type: Some(value.file_engine_type),
+            discard: value.discard,
+            format: Some(value.format),
 
             socket: None,
         }
@@ -260,6 +377,7 @@ pub struct VirtioBlock {
     pub cache_type: CacheType,
     pub root_device: bool,
     pub read_only: bool,
+    pub discard: Option<DiscardConfig>,
 
     // Host file and properties.
     pub disk: DiskProperties,
@@ -272,7 +390,7 @@ macro_rules! unwrap_async_file_engine_or_return {
     ($file_engine: expr) => {
         match $file_engine {
             FileEngine::Async(engine) => engine,
-            FileEngine::Sync(_) => {
+            _ => {
                 error!("The block device doesn't use an async IO engine");
                 return;
             }
@@ -285,10 +403,26 @@ impl VirtioBlock {
     ///
     /// The given file must be seekable and sizable.
     pub fn new(config: VirtioBlockConfig) -> Result<VirtioBlock, VirtioBlockError> {
+        if let Some(discard) = &config.discard {
+            if config.is_read_only {
+                return Err(VirtioBlockError::DiscardReadOnly);
+            }
+            if config.format == ImageFormat::Qcow2 {
+                return Err(VirtioBlockError::DiscardQcow2);
+            }
+            if discard.max_discard_sectors == 0
+                || discard.max_write_zeroes_sectors == 0
+                || discard.discard_sector_alignment == 0
+            {
+                return Err(VirtioBlockError::InvalidDiscardConfig);
+            }
+        }
+
         let disk_properties = DiskProperties::new(
             config.path_on_host,
             config.is_read_only,
             config.file_engine_type,
+            config.format,
         )?;
 
         let rate_limiter = config
@@ -308,13 +442,15 @@ impl VirtioBlock {
             avail_features |= 1u64 << VIRTIO_BLK_F_RO;
         };
 
+        if config.discard.is_some() {
+            avail_features |= (1u64 << VIRTIO_BLK_F_DISCARD) | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
+        }
+
         let queue_evts = [EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?];
 
         let queues = BLOCK_QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
 
-        let config_space = ConfigSpace {
-            capacity: disk_properties.nsectors.to_le(),
-        };
+        let config_space = ConfigSpace::new(disk_properties.nsectors, config.discard.as_ref());
 
         Ok(VirtioBlock {
             avail_features,
@@ -331,6 +467,7 @@ impl VirtioBlock {
             cache_type: config.cache_type,
             root_device: config.is_root_device,
             read_only: config.is_read_only,
+            discard: config.discard,
 
             disk: disk_properties,
             rate_limiter,
@@ -350,7 +487,10 @@ impl VirtioBlock {
             is_read_only: self.read_only,
             cache_type: self.cache_type,
             rate_limiter: rl.into_option(),
+            rate_limiter_group: self.rate_limiter.group_id(),
             file_engine_type: self.file_engine_type(),
+            discard: self.discard,
+            format: self.image_format(),
         }
     }
 
@@ -409,6 +549,7 @@ impl VirtioBlock {
 
                         request.process(
                             &mut self.disk,
+                            self.discard.as_ref(),
                             head.index,
                             &active_state.mem,
                             &self.metrics,
@@ -558,11 +699,19 @@ impl VirtioBlock {
     /// Retrieve the file engine type.
     pub fn file_engine_type(&self) -> FileEngineType {
         match self.disk.file_engine {
-            FileEngine::Sync(_) => FileEngineType::Sync,
+            FileEngine::Sync(_) | FileEngine::Qcow2(_) => FileEngineType::Sync,
             FileEngine::Async(_) => FileEngineType::Async,
         }
     }
 
+    /// Retrieve the format of the disk image.
+    pub fn image_format(&self) -> ImageFormat {
+        match self.disk.file_engine {
+            FileEngine::Qcow2(_) => ImageFormat::Qcow2,
+            FileEngine::Sync(_) | FileEngine::Async(_) => ImageFormat::Raw,
+        }
+    }
+
     fn drain_and_flush(&mut self, discard: bool) {
         if let Err(err) = self.disk.file_engine.drain_and_flush(discard) {
             error!("Failed to drain ops and flush block data: {:?}", err);
@@ -693,6 +842,7 @@ mod tests {
     use std::fs::metadata;
     use std::io::{Read, Write};
     use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::FileExt;
     use std::thread;
     use std::time::Duration;
 
@@ -722,7 +872,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Default::default(),
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -737,7 +890,10 @@ mod tests {
             is_read_only: None,
             path_on_host: None,
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Default::default(),
+            discard: None,
+            format: None,
 
             socket: Some("sock".to_string()),
         };
@@ -752,7 +908,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some("path".to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Default::default(),
+            discard: None,
+            format: None,
 
             socket: Some("sock".to_string()),
         };
@@ -767,16 +926,25 @@ mod tests {
         f.as_file().set_len(size).unwrap();
 
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
-            let disk_properties =
-                DiskProperties::new(String::from(f.as_path().to_str().unwrap()), true, engine)
-                    .unwrap();
+            let disk_properties = DiskProperties::new(
+                String::from(f.as_path().to_str().unwrap()),
+                true,
+                engine,
+                ImageFormat::Raw,
+            )
+            .unwrap();
 
             assert_eq!(size, u64::from(SECTOR_SIZE) * num_sectors);
             assert_eq!(disk_properties.nsectors, num_sectors);
             // Testing `backing_file.virtio_block_disk_image_id()` implies
             // duplicating that logic in tests, so skipping it.
 
-            let res = DiskProperties::new("invalid-disk-path".to_string(), true, engine);
+            let res = DiskProperties::new(
+                "invalid-disk-path".to_string(),
+                true,
+                engine,
+                ImageFormat::Raw,
+            );
             assert!(
                 matches!(res, Err(VirtioBlockError::BackingFile(_, _))),
                 "{:?}",
@@ -821,11 +989,17 @@ mod tests {
             // This will read the number of sectors.
             // The block's backing file size is 0x1000, so there are 8 (4096/512) sectors.
             // The config space is little endian.
-            let expected_config_space = ConfigSpace { capacity: 8 };
+            let expected_config_space = ConfigSpace {
+                capacity: 8,
+                ..Default::default()
+            };
             assert_eq!(actual_config_space, expected_config_space);
 
             // Invalid read.
-            let expected_config_space = ConfigSpace { capacity: 696969 };
+            let expected_config_space = ConfigSpace {
+                capacity: 696969,
+                ..Default::default()
+            };
             actual_config_space = expected_config_space;
             block.read_config(
                 std::mem::size_of::<ConfigSpace>() as u64 + 1,
@@ -842,7 +1016,10 @@ mod tests {
         for engine in [FileEngineType::Sync, FileEngineType::Async] {
             let mut block = default_block(engine);
 
-            let expected_config_space = ConfigSpace { capacity: 696969 };
+            let expected_config_space = ConfigSpace {
+                capacity: 696969,
+                ..Default::default()
+            };
             block.write_config(0, expected_config_space.as_slice());
 
             let mut actual_config_space = ConfigSpace::default();
@@ -852,6 +1029,7 @@ mod tests {
             // If privileged user writes to `/dev/mem`, in block config space - byte by byte.
             let expected_config_space = ConfigSpace {
                 capacity: 0x1122334455667788,
+                ..Default::default()
             };
             let expected_config_space_slice = expected_config_space.as_slice();
             for (i, b) in expected_config_space_slice.iter().enumerate() {
@@ -863,6 +1041,7 @@ mod tests {
             // Invalid write.
             let new_config_space = ConfigSpace {
                 capacity: 0xDEADBEEF,
+                ..Default::default()
             };
             block.write_config(5, new_config_space.as_slice());
             // Make sure nothing got written.
@@ -1534,6 +1713,234 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_discard_write_zeroes() {
+        for engine in [FileEngineType::Sync, FileEngineType::Async] {
+            let mut block = default_block(engine);
+            let mem = default_mem();
+            let interrupt = default_interrupt();
+            let vq = VirtQueue::new(GuestAddress(0), &mem, 16);
+            set_queue(&mut block, 0, vq.create_queue());
+            block.activate(mem.clone(), interrupt).unwrap();
+            read_blk_req_descriptors(&vq);
+
+            let request_type_addr = GuestAddress(vq.dtable[0].addr.get());
+            let data_addr = GuestAddress(vq.dtable[1].addr.get());
+            let status_addr = GuestAddress(vq.dtable[2].addr.get());
+
+            // The segment is read by the device.
+            vq.dtable[1].flags.set(VIRTQ_DESC_F_NEXT);
+            vq.dtable[1].len.set(16);
+
+            let backing_file = block.disk.file_engine.file().try_clone().unwrap();
+            backing_file.write_all_at(&[0xAA; 0x1000], 0).unwrap();
+            let read_backing_file = |offset, len| {
+                let mut buf = vec![0u8; len];
+                backing_file.read_exact_at(&mut buf, offset).unwrap();
+                buf
+            };
+
+            let mut send_request = |block: &mut VirtioBlock, request_type, segment, completes| {
+                vq.used.idx.set(0);
+                set_queue(block, 0, vq.create_queue());
+                mem.write_obj(RequestHeader::new(request_type, 0), request_type_addr)
+                    .unwrap();
+                mem.write_obj::<DiscardWriteZeroesSegment>(segment, data_addr)
+                    .unwrap();
+                if completes {
+                    simulate_queue_and_async_completion_events(block, true);
+                } else {
+                    simulate_queue_event(block, Some(true));
+                }
+                assert_eq!(vq.used.idx.get(), 1);
+                assert_eq!(vq.used.ring[0].get().len, 1);
+                mem.read_obj::<u8>(status_addr).unwrap()
+            };
+
+            // Requests are rejected if the feature is not enabled.
+            let status = send_request(
+                &mut block,
+                VIRTIO_BLK_T_DISCARD,
+                DiscardWriteZeroesSegment::new(0, 2, 0),
+                false,
+            );
+            assert_eq!(u32::from(status), VIRTIO_BLK_S_UNSUPP);
+            assert_eq!(read_backing_file(0, 1024), vec![0xAA; 1024]);
+
+            block.discard = Some(DiscardConfig {
+                max_write_zeroes_sectors: 2,
+                ..Default::default()
+            });
+
+            // Discard deallocates the range.
+            let discard_count = block.metrics.discard_count.count();
+            let status = send_request(
+                &mut block,
+                VIRTIO_BLK_T_DISCARD,
+                DiscardWriteZeroesSegment::new(0, 2, 0),
+                true,
+            );
+            assert_eq!(u32::from(status), VIRTIO_BLK_S_OK);
+            assert_eq!(read_backing_file(0, 1024), vec![0; 1024]);
+            assert_eq!(read_backing_file(1024, 512), vec![0xAA; 512]);
+            assert_eq!(block.metrics.discard_count.count(), discard_count + 1);
+
+            // Discard doesn't accept the unmap flag.
+            let status = send_request(
+                &mut block,
+                VIRTIO_BLK_T_DISCARD,
+                DiscardWriteZeroesSegment::new(2, 2, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP),
+                false,
+            );
+            assert_eq!(u32::from(status), VIRTIO_BLK_S_UNSUPP);
+
+            // Write zeroes, with and without the unmap flag.
+            let write_zeroes_count = block.metrics.write_zeroes_count.count();
+            for (sector, flags) in [(2, 0), (4, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP)] {
+                let status = send_request(
+                    &mut block,
+                    VIRTIO_BLK_T_WRITE_ZEROES,
+                    DiscardWriteZeroesSegment::new(sector, 2, flags),
+                    true,
+                );
+                assert_eq!(u32::from(status), VIRTIO_BLK_S_OK);
+            }
+            assert_eq!(read_backing_file(0, 3072), vec![0; 3072]);
+            assert_eq!(read_backing_file(3072, 1024), vec![0xAA; 1024]);
+            assert_eq!(
+                block.metrics.write_zeroes_count.count(),
+                write_zeroes_count + 2
+            );
+
+            // The range is larger than the advertised limit.
+            let status = send_request(
+                &mut block,
+                VIRTIO_BLK_T_WRITE_ZEROES,
+                DiscardWriteZeroesSegment::new(5, 3, 0),
+                false,
+            );
+            assert_eq!(u32::from(status), VIRTIO_BLK_S_IOERR);
+            assert_eq!(read_backing_file(3072, 1024), vec![0xAA; 1024]);
+        }
+    }
+
+    #[test]
+    fn test_discard_config() {
+        let f = TempFile::new().unwrap();
+        f.as_file().set_len(0x1000).unwrap();
+        let config = |is_read_only, discard| VirtioBlockConfig {
+            drive_id: "test".to_string(),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: CacheType::Unsafe,
+            is_read_only,
+            path_on_host: f.as_path().to_str().unwrap().to_string(),
+            rate_limiter: None,
+            rate_limiter_group: None,
+            file_engine_type: FileEngineType::Sync,
+            discard,
+            format: ImageFormat::Raw,
+        };
+
+        let res = VirtioBlock::new(config(true, Some(DiscardConfig::default())));
+        assert!(
+            matches!(res, Err(VirtioBlockError::DiscardReadOnly)),
+            "{res:?}"
+        );
+
+        let res = VirtioBlock::new(config(
+            false,
+            Some(DiscardConfig {
+                max_discard_sectors: 0,
+                ..Default::default()
+            }),
+        ));
+        assert!(
+            matches!(res, Err(VirtioBlockError::InvalidDiscardConfig)),
+            "{res:?}"
+        );
+
+        let block = VirtioBlock::new(config(
+            false,
+            Some(DiscardConfig {
+                max_discard_sectors: 8,
+                max_write_zeroes_sectors: 4,
+                discard_sector_alignment: 2,
+            }),
+        ))
+        .unwrap();
+        let features = (1u64 << VIRTIO_BLK_F_DISCARD) | (1u64 << VIRTIO_BLK_F_WRITE_ZEROES);
+        assert_eq!(block.avail_features() & features, features);
+
+        let mut config_space = ConfigSpace::default();
+        block.read_config(0, config_space.as_mut_slice());
+        assert_eq!(config_space.capacity, 8);
+        assert_eq!(config_space.max_discard_sectors, 8);
+        assert_eq!(config_space.max_discard_seg, 1);
+        assert_eq!(config_space.discard_sector_alignment, 2);
+        assert_eq!(config_space.max_write_zeroes_sectors, 4);
+        assert_eq!(config_space.max_write_zeroes_seg, 1);
+        assert_eq!(config_space.write_zeroes_may_unmap, 1);
+        // The offsets of the discard fields are fixed by the specification.
+        assert_eq!(std::mem::size_of::<ConfigSpace>(), 64);
+        assert_eq!(config_space.as_slice()[36..40], 8u32.to_le_bytes());
+        assert_eq!(config_space.as_slice()[56], 1);
+    }
+
+    #[test]
+    fn test_qcow2_config() {
+        let f = TempFile::new().unwrap();
+        block_io::qcow2::tests::create_qcow2_image(f.as_path(), 0x10_0000, None);
+        let config = |file_engine_type, discard| VirtioBlockConfig {
+            drive_id: "test".to_string(),
+            partuuid: None,
+            is_root_device: false,
+            cache_type: CacheType::Unsafe,
+            is_read_only: false,
+            path_on_host: f.as_path().to_str().unwrap().to_string(),
+            rate_limiter: None,
+            rate_limiter_group: None,
+            file_engine_type,
+            discard,
+            format: ImageFormat::Qcow2,
+        };
+
+        let res = VirtioBlock::new(config(FileEngineType::Async, None));
+        assert!(
+            matches!(res, Err(VirtioBlockError::Qcow2AsyncEngine)),
+            "{res:?}"
+        );
+        let res = VirtioBlock::new(config(FileEngineType::Sync, Some(DiscardConfig::default())));
+        assert!(
+            matches!(res, Err(VirtioBlockError::DiscardQcow2)),
+            "{res:?}"
+        );
+
+        // The guest sees the virtual size of the image, not the size of the file.
+        let mut block = VirtioBlock::new(config(FileEngineType::Sync, None)).unwrap();
+        assert_eq!(block.config_space.capacity, 0x10_0000 >> SECTOR_SHIFT);
+        assert_eq!(block.file_engine_type(), FileEngineType::Sync);
+        assert_eq!(block.config(), config(FileEngineType::Sync, None));
+
+        // Updating the image keeps the format.
+        let f2 = TempFile::new().unwrap();
+        block_io::qcow2::tests::create_qcow2_image(f2.as_path(), 0x20_0000, None);
+        block
+            .update_disk_image(f2.as_path().to_str().unwrap().to_string())
+            .unwrap();
+        assert_eq!(block.image_format(), ImageFormat::Qcow2);
+        assert_eq!(block.config_space.capacity, 0x20_0000 >> SECTOR_SHIFT);
+
+        // Raw files are not valid qcow2 images.
+        let raw = TempFile::new().unwrap();
+        raw.as_file().set_len(0x1000).unwrap();
+        let res = block.update_disk_image(raw.as_path().to_str().unwrap().to_string());
+        assert!(
+            matches!(res, Err(VirtioBlockError::FileEngine(_))),
+            "{res:?}"
+        );
+    }
+
     fn add_flush_requests_batch(block: &mut VirtioBlock, vq: &VirtQueue, count: u16) {
         let mem = vq.memory();
         vq.avail.idx.set(0);
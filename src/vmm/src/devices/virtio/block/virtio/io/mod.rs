@@ -2,12 +2,15 @@
 // SPDX-License-Identifier: Apache-2.0
 
 pub mod async_io;
+pub mod qcow2;
 pub mod sync_io;
 
 use std::fmt::Debug;
 use std::fs::File;
+use std::path::Path;
 
 pub use self::async_io::{AsyncFileEngine, AsyncIoError};
+pub use self::qcow2::{Qcow2FileEngine, Qcow2IoError};
 pub use self::sync_io::{SyncFileEngine, SyncIoError};
 use crate::devices::virtio::block::virtio::PendingRequest;
 use crate::devices::virtio::block::virtio::device::FileEngineType;
@@ -25,12 +28,33 @@ pub enum FileEngineOk {
     Executed(RequestOk),
 }
 
+/// Space management operation performed on a range of the backing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallocateMode {
+    /// Deallocate the range. Subsequent reads of the range return zeroes.
+    PunchHole,
+    /// Zero the range while keeping it allocated.
+    ZeroRange,
+}
+
+impl FallocateMode {
+    /// Flags passed to `fallocate` for this operation. The file size never changes.
+    pub fn flags(self) -> libc::c_int {
+        match self {
+            FallocateMode::PunchHole => libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            FallocateMode::ZeroRange => libc::FALLOC_FL_ZERO_RANGE | libc::FALLOC_FL_KEEP_SIZE,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum BlockIoError {
     /// Sync error: {0}
     Sync(SyncIoError),
     /// Async error: {0}
     Async(AsyncIoError),
+    /// Qcow2 error: {0}
+    Qcow2(Qcow2IoError),
 }
 
 impl BlockIoError {
@@ -54,6 +78,7 @@ pub enum FileEngine {
     #[allow(unused)]
     Async(AsyncFileEngine),
     Sync(SyncFileEngine),
+    Qcow2(Qcow2FileEngine),
 }
 
 impl FileEngine {
@@ -66,10 +91,15 @@ impl FileEngine {
         }
     }
 
-    pub fn update_file_path(&mut self, file: File) -> Result<(), BlockIoError> {
+    /// Update the file of the engine. `path` is used to resolve relative backing file paths of
+    /// qcow2 images.
+    pub fn update_file_path(&mut self, file: File, path: &Path) -> Result<(), BlockIoError> {
         match self {
             FileEngine::Async(engine) => engine.update_file(file).map_err(BlockIoError::Async)?,
             FileEngine::Sync(engine) => engine.update_file(file),
+            FileEngine::Qcow2(engine) => engine
+                .update_file(file, path)
+                .map_err(BlockIoError::Qcow2)?,
         };
 
         Ok(())
@@ -80,6 +110,7 @@ impl FileEngine {
         match self {
             FileEngine::Async(engine) => engine.file(),
             FileEngine::Sync(engine) => engine.file(),
+            FileEngine::Qcow2(engine) => engine.file(),
         }
     }
 
@@ -106,6 +137,13 @@ impl FileEngine {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Qcow2(engine) => match engine.read(offset, mem, addr, count) {
+                Ok(count) => Ok(FileEngineOk::Executed(RequestOk { req, count })),
+                Err(err) => Err(RequestError {
+                    req,
+                    error: BlockIoError::Qcow2(err),
+                }),
+            },
         }
     }
 
@@ -132,6 +170,13 @@ impl FileEngine {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Qcow2(engine) => match engine.write(offset, mem, addr, count) {
+                Ok(count) => Ok(FileEngineOk::Executed(RequestOk { req, count })),
+                Err(err) => Err(RequestError {
+                    req,
+                    error: BlockIoError::Qcow2(err),
+                }),
+            },
         }
     }
 
@@ -154,13 +199,49 @@ impl FileEngine {
                     error: BlockIoError::Sync(err),
                 }),
             },
+            FileEngine::Qcow2(engine) => match engine.flush() {
+                Ok(_) => Ok(FileEngineOk::Executed(RequestOk { req, count: 0 })),
+                Err(err) => Err(RequestError {
+                    req,
+                    error: BlockIoError::Qcow2(err),
+                }),
+            },
+        }
+    }
+
+    pub fn fallocate(
+        &mut self,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+        req: PendingRequest,
+    ) -> Result<FileEngineOk, RequestError<BlockIoError>> {
+        match self {
+            FileEngine::Async(engine) => match engine.push_fallocate(offset, len, mode, req) {
+                Ok(_) => Ok(FileEngineOk::Submitted),
+                Err(err) => Err(RequestError {
+                    req: err.req,
+                    error: BlockIoError::Async(err.error),
+                }),
+            },
+            FileEngine::Sync(engine) => match engine.fallocate(offset, len, mode) {
+                Ok(_) => Ok(FileEngineOk::Executed(RequestOk { req, count: 0 })),
+                Err(err) => Err(RequestError {
+                    req,
+                    error: BlockIoError::Sync(err),
+                }),
+            },
+            FileEngine::Qcow2(_) => Err(RequestError {
+                req,
+                error: BlockIoError::Qcow2(Qcow2IoError::Unsupported("discard")),
+            }),
         }
     }
 
     pub fn drain(&mut self, discard: bool) -> Result<(), BlockIoError> {
         match self {
             FileEngine::Async(engine) => engine.drain(discard).map_err(BlockIoError::Async),
-            FileEngine::Sync(_engine) => Ok(()),
+            FileEngine::Sync(_) | FileEngine::Qcow2(_) => Ok(()),
         }
     }
 
@@ -170,6 +251,7 @@ impl FileEngine {
                 engine.drain_and_flush(discard).map_err(BlockIoError::Async)
             }
             FileEngine::Sync(engine) => engine.flush().map_err(BlockIoError::Sync),
+            FileEngine::Qcow2(engine) => engine.flush().map_err(BlockIoError::Qcow2),
         }
     }
 }
@@ -325,6 +407,41 @@ pub mod tests {
         mem.read_slice(&mut buf, GuestAddress(0)).unwrap();
         assert_eq!(buf, data.as_slice());
 
+        // Zero out the first half of the file, then deallocate the second half
+        let half = FILE_LEN / 2;
+        assert_sync_execution!(
+            engine.fallocate(
+                0,
+                u64::from(half),
+                FallocateMode::ZeroRange,
+                PendingRequest::default()
+            ),
+            0
+        );
+        assert_sync_execution!(
+            engine.fallocate(
+                u64::from(half),
+                u64::from(half),
+                FallocateMode::PunchHole,
+                PendingRequest::default()
+            ),
+            0
+        );
+        let mem = create_mem();
+        assert_sync_execution!(
+            engine.read(
+                0,
+                &mem,
+                GuestAddress(0),
+                FILE_LEN,
+                PendingRequest::default()
+            ),
+            FILE_LEN
+        );
+        let mut buf = vec![0xffu8; FILE_LEN as usize];
+        mem.read_slice(&mut buf, GuestAddress(0)).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+
         // Check other ops
         engine.flush(PendingRequest::default()).unwrap();
         engine.drain(true).unwrap();
@@ -381,6 +498,29 @@ pub mod tests {
         check_dirty_mem(&mem, addr, FILE_LEN);
         check_clean_mem(&mem, GuestAddress(4096), 4096);
 
+        // Zero out the first half of the file, then deallocate the second half
+        let half = FILE_LEN / 2;
+        assert_queued!(engine.fallocate(
+            0,
+            u64::from(half),
+            FallocateMode::ZeroRange,
+            PendingRequest::default()
+        ));
+        assert_async_execution(&mem, &mut engine, 0);
+        assert_queued!(engine.fallocate(
+            u64::from(half),
+            u64::from(half),
+            FallocateMode::PunchHole,
+            PendingRequest::default()
+        ));
+        assert_async_execution(&mem, &mut engine, 0);
+        let mem = create_mem();
+        assert_queued!(engine.read(0, &mem, addr, FILE_LEN, PendingRequest::default()));
+        assert_async_execution(&mem, &mut engine, FILE_LEN);
+        let mut buf = vec![0xffu8; FILE_LEN as usize];
+        mem.read_slice(&mut buf, GuestAddress(0)).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+
         // Check other ops
         assert_queued!(engine.flush(PendingRequest::default()));
         assert_async_execution(&mem, &mut engine, 0);
@@ -0,0 +1,1026 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Synchronous file engine for qcow2 images.
+//!
+//! Supports qcow2 version 2 and 3 images, with backing file chains made of qcow2 or raw images.
+//! Backing files are always opened read-only. Guest writes go to the top image only: clusters
+//! that are not allocated in the top image are copied from the backing chain on first write.
+//!
+//! Compressed clusters, encryption, external data files and extended L2 entries are not
+//! supported.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+use vm_memory::GuestMemoryError;
+
+use crate::utils::u64_to_usize;
+use crate::vstate::memory::{Bytes, GuestAddress, GuestMemory, GuestMemoryMmap};
+
+const QCOW2_MAGIC: u32 = 0x5146_49fb;
+const V2_HEADER_LEN: u32 = 72;
+const V3_HEADER_LEN: u32 = 104;
+
+const MIN_CLUSTER_BITS: u32 = 9;
+const MAX_CLUSTER_BITS: u32 = 21;
+// Same limits as QEMU, to avoid allocating huge tables for a corrupted image.
+const MAX_L1_TABLE_SIZE: u64 = 32 << 20;
+const MAX_REFCOUNT_TABLE_SIZE: u64 = 8 << 20;
+/// Maximum number of images in a backing file chain, including the top image.
+pub const MAX_BACKING_CHAIN_LEN: usize = 16;
+// Number of L2 tables kept in memory per image.
+const L2_CACHE_SIZE: usize = 32;
+
+const HEADER_EXT_END: u32 = 0;
+const HEADER_EXT_BACKING_FORMAT: u32 = 0xe279_2aca;
+
+const TABLE_OFFSET_MASK: u64 = 0x00ff_ffff_ffff_fe00;
+const REFCOUNT_TABLE_OFFSET_MASK: u64 = 0xffff_ffff_ffff_fe00;
+const ENTRY_COPIED: u64 = 1 << 63;
+const L2_ENTRY_COMPRESSED: u64 = 1 << 62;
+const L2_ENTRY_ZERO: u64 = 1;
+// We only update refcounts of images using the default 16 bit width.
+const WRITABLE_REFCOUNT_ORDER: u32 = 4;
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum Qcow2IoError {
+    /// Invalid qcow2 image: {0}
+    InvalidImage(&'static str),
+    /// Unsupported qcow2 feature: {0}
+    Unsupported(&'static str),
+    /// Cannot open backing file {0}: {1}
+    BackingFile(String, std::io::Error),
+    /// Backing file chain has more than 16 images
+    BackingChainTooLong,
+    /// Read: {0}
+    Read(std::io::Error),
+    /// Write: {0}
+    Write(std::io::Error),
+    /// Flush: {0}
+    Flush(std::io::Error),
+    /// Access beyond the end of the disk at offset {0}
+    OutOfRange(u64),
+    /// The refcount table of the image is full
+    RefcountTableFull,
+    /// Transfer: {0}
+    Transfer(GuestMemoryError),
+}
+
+fn read_be_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_be_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_be_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_table(file: &File, offset: u64, entries: u64) -> Result<Vec<u64>, Qcow2IoError> {
+    let mut buf = vec![0u8; u64_to_usize(entries * 8)];
+    file.read_exact_at(&mut buf, offset)
+        .map_err(Qcow2IoError::Read)?;
+    Ok(buf
+        .chunks_exact(8)
+        .map(|entry| u64::from_be_bytes(entry.try_into().unwrap()))
+        .collect())
+}
+
+/// The fields of the qcow2 header we care about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Qcow2Header {
+    version: u32,
+    backing_file_offset: u64,
+    backing_file_size: u32,
+    cluster_bits: u32,
+    size: u64,
+    l1_size: u32,
+    l1_table_offset: u64,
+    refcount_table_offset: u64,
+    refcount_table_clusters: u32,
+    refcount_order: u32,
+    header_length: u32,
+}
+
+impl Qcow2Header {
+    fn read_from(file: &File) -> Result<Self, Qcow2IoError> {
+        let mut buf = [0u8; V3_HEADER_LEN as usize];
+        file.read_exact_at(&mut buf[..V2_HEADER_LEN as usize], 0)
+            .map_err(Qcow2IoError::Read)?;
+
+        if read_be_u32(&buf, 0) != QCOW2_MAGIC {
+            return Err(Qcow2IoError::InvalidImage("bad magic"));
+        }
+        let version = read_be_u32(&buf, 4);
+        if version != 2 && version != 3 {
+            return Err(Qcow2IoError::Unsupported("image version"));
+        }
+        if read_be_u32(&buf, 32) != 0 {
+            return Err(Qcow2IoError::Unsupported("encryption"));
+        }
+
+        let (refcount_order, header_length) = if version == 3 {
+            file.read_exact_at(&mut buf[V2_HEADER_LEN as usize..], u64::from(V2_HEADER_LEN))
+                .map_err(Qcow2IoError::Read)?;
+            if read_be_u64(&buf, 72) != 0 {
+                return Err(Qcow2IoError::Unsupported("incompatible features"));
+            }
+            (read_be_u32(&buf, 96), read_be_u32(&buf, 100))
+        } else {
+            (WRITABLE_REFCOUNT_ORDER, V2_HEADER_LEN)
+        };
+
+        let header = Qcow2Header {
+            version,
+            backing_file_offset: read_be_u64(&buf, 8),
+            backing_file_size: read_be_u32(&buf, 16),
+            cluster_bits: read_be_u32(&buf, 20),
+            size: read_be_u64(&buf, 24),
+            l1_size: read_be_u32(&buf, 36),
+            l1_table_offset: read_be_u64(&buf, 40),
+            refcount_table_offset: read_be_u64(&buf, 48),
+            refcount_table_clusters: read_be_u32(&buf, 56),
+            refcount_order,
+            header_length,
+        };
+        header.validate()?;
+        Ok(header)
+    }
+
+    fn validate(&self) -> Result<(), Qcow2IoError> {
+        if !(MIN_CLUSTER_BITS..=MAX_CLUSTER_BITS).contains(&self.cluster_bits) {
+            return Err(Qcow2IoError::InvalidImage("cluster size"));
+        }
+        if self.refcount_order > 6 {
+            return Err(Qcow2IoError::InvalidImage("refcount order"));
+        }
+        if self.version == 3 && self.header_length < V3_HEADER_LEN {
+            return Err(Qcow2IoError::InvalidImage("header length"));
+        }
+
+        let cluster_size = self.cluster_size();
+        if self.l1_table_offset % cluster_size != 0
+            || self.refcount_table_offset % cluster_size != 0
+        {
+            return Err(Qcow2IoError::InvalidImage("unaligned table offset"));
+        }
+        if u64::from(self.l1_size) * 8 > MAX_L1_TABLE_SIZE {
+            return Err(Qcow2IoError::InvalidImage("L1 table too large"));
+        }
+        if u64::from(self.refcount_table_clusters) * cluster_size > MAX_REFCOUNT_TABLE_SIZE {
+            return Err(Qcow2IoError::InvalidImage("refcount table too large"));
+        }
+
+        // The L1 table must be able to map the whole virtual disk.
+        let bytes_per_l1_entry = cluster_size * self.l2_entries();
+        if self.size.div_ceil(bytes_per_l1_entry) > u64::from(self.l1_size) {
+            return Err(Qcow2IoError::InvalidImage("L1 table too small"));
+        }
+
+        Ok(())
+    }
+
+    fn cluster_size(&self) -> u64 {
+        1 << self.cluster_bits
+    }
+
+    fn l2_entries(&self) -> u64 {
+        self.cluster_size() / 8
+    }
+
+    fn refcount_block_entries(&self) -> u64 {
+        (self.cluster_size() * 8) >> self.refcount_order
+    }
+
+    // Reads the backing file name and, if present, its format.
+    fn read_backing_file(
+        &self,
+        file: &File,
+    ) -> Result<Option<(String, Option<String>)>, Qcow2IoError> {
+        if self.backing_file_offset == 0 {
+            return Ok(None);
+        }
+        if self.backing_file_size == 0 || self.backing_file_size > 1023 {
+            return Err(Qcow2IoError::InvalidImage("backing file name length"));
+        }
+        let mut name = vec![0u8; self.backing_file_size as usize];
+        file.read_exact_at(&mut name, self.backing_file_offset)
+            .map_err(Qcow2IoError::Read)?;
+        let name =
+            String::from_utf8(name).map_err(|_| Qcow2IoError::InvalidImage("backing file name"))?;
+
+        // Header extensions follow the header, up to the end of the first cluster.
+        let mut format = None;
+        let mut offset = u64::from(self.header_length);
+        while offset + 8 <= self.cluster_size() {
+            let mut ext = [0u8; 8];
+            file.read_exact_at(&mut ext, offset)
+                .map_err(Qcow2IoError::Read)?;
+            let ext_type = read_be_u32(&ext, 0);
+            let ext_len = u64::from(read_be_u32(&ext, 4));
+            if ext_type == HEADER_EXT_END {
+                break;
+            }
+            if ext_type == HEADER_EXT_BACKING_FORMAT {
+                let mut value = vec![0u8; u64_to_usize(ext_len.min(32))];
+                file.read_exact_at(&mut value, offset + 8)
+                    .map_err(Qcow2IoError::Read)?;
+                format = Some(String::from_utf8_lossy(&value).into_owned());
+            }
+            offset += 8 + ext_len.next_multiple_of(8);
+        }
+
+        Ok(Some((name, format)))
+    }
+}
+
+/// Location of a guest cluster in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClusterMapping {
+    /// Not allocated in this image; data comes from the backing file, or is zero.
+    Unallocated,
+    /// Reads as zeroes. The cluster may be preallocated at the given host offset.
+    Zero(Option<u64>),
+    /// Stored at the given host offset. Writes are only allowed in place if `copied` is set,
+    /// which means the cluster is not shared with an internal snapshot.
+    Allocated { host_offset: u64, copied: bool },
+    /// Stored compressed.
+    Compressed,
+}
+
+/// A read-only image in a backing file chain.
+#[derive(Debug)]
+enum BackingImage {
+    Raw { file: File, size: u64 },
+    Qcow2(Box<Qcow2Image>),
+}
+
+impl BackingImage {
+    fn open(path: &Path, format: Option<&str>, chain_len: usize) -> Result<Self, Qcow2IoError> {
+        if chain_len >= MAX_BACKING_CHAIN_LEN {
+            return Err(Qcow2IoError::BackingChainTooLong);
+        }
+        let backing_file_err = |err| Qcow2IoError::BackingFile(path.display().to_string(), err);
+        let file = OpenOptions::new()
+            .read(true)
+            .open(path)
+            .map_err(backing_file_err)?;
+
+        let is_qcow2 = match format {
+            Some("qcow2") => true,
+            Some("raw") => false,
+            Some(_) => return Err(Qcow2IoError::Unsupported("backing file format")),
+            None => {
+                let mut magic = [0u8; 4];
+                file.read_exact_at(&mut magic, 0).is_ok()
+                    && u32::from_be_bytes(magic) == QCOW2_MAGIC
+            }
+        };
+
+        if is_qcow2 {
+            Ok(BackingImage::Qcow2(Box::new(Qcow2Image::open(
+                file, path, chain_len,
+            )?)))
+        } else {
+            let size = file.metadata().map_err(backing_file_err)?.len();
+            Ok(BackingImage::Raw { file, size })
+        }
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<(), Qcow2IoError> {
+        let size = match self {
+            BackingImage::Raw { size, .. } => *size,
+            BackingImage::Qcow2(image) => image.header.size,
+        };
+        // Backing files may be smaller than the images on top of them.
+        let len = u64_to_usize(size.saturating_sub(offset)).min(buf.len());
+        let (data, zeroes) = buf.split_at_mut(len);
+        zeroes.fill(0);
+        if data.is_empty() {
+            return Ok(());
+        }
+        match self {
+            BackingImage::Raw { file, .. } => {
+                file.read_exact_at(data, offset).map_err(Qcow2IoError::Read)
+            }
+            BackingImage::Qcow2(image) => image.read_at(data, offset),
+        }
+    }
+}
+
+/// A qcow2 image, along with its backing file chain.
+#[derive(Debug)]
+struct Qcow2Image {
+    file: File,
+    header: Qcow2Header,
+    l1_table: Vec<u64>,
+    l2_cache: HashMap<u64, Vec<u64>>,
+    refcount_table: Vec<u64>,
+    backing: Option<BackingImage>,
+    // Host offset of the next cluster we allocate. New clusters are always appended.
+    next_free_cluster: u64,
+}
+
+impl Qcow2Image {
+    fn open(file: File, path: &Path, chain_len: usize) -> Result<Self, Qcow2IoError> {
+        let header = Qcow2Header::read_from(&file)?;
+
+        let l1_table = read_table(&file, header.l1_table_offset, u64::from(header.l1_size))?;
+        let refcount_table = read_table(
+            &file,
+            header.refcount_table_offset,
+            u64::from(header.refcount_table_clusters) * header.l2_entries(),
+        )?;
+
+        let backing = match header.read_backing_file(&file)? {
+            Some((name, format)) => {
+                let mut backing_path = PathBuf::from(&name);
+                if backing_path.is_relative() {
+                    if let Some(dir) = path.parent() {
+                        backing_path = dir.join(backing_path);
+                    }
+                }
+                Some(BackingImage::open(
+                    &backing_path,
+                    format.as_deref(),
+                    chain_len + 1,
+                )?)
+            }
+            None => None,
+        };
+
+        let file_len = file.metadata().map_err(Qcow2IoError::Read)?.len();
+        let next_free_cluster = file_len.next_multiple_of(header.cluster_size());
+
+        Ok(Qcow2Image {
+            file,
+            header,
+            l1_table,
+            l2_cache: HashMap::new(),
+            refcount_table,
+            backing,
+            next_free_cluster,
+        })
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.header.cluster_size()
+    }
+
+    // Returns the L1 index and L2 index of a guest offset.
+    fn table_indexes(&self, guest_offset: u64) -> (usize, usize) {
+        let cluster = guest_offset >> self.header.cluster_bits;
+        let l2_entries = self.header.l2_entries();
+        (
+            u64_to_usize(cluster / l2_entries),
+            u64_to_usize(cluster % l2_entries),
+        )
+    }
+
+    fn l2_table(&mut self, l2_offset: u64) -> Result<&mut Vec<u64>, Qcow2IoError> {
+        if !self.l2_cache.contains_key(&l2_offset) {
+            let table = read_table(&self.file, l2_offset, self.header.l2_entries())?;
+            if self.l2_cache.len() >= L2_CACHE_SIZE {
+                self.l2_cache.clear();
+            }
+            self.l2_cache.insert(l2_offset, table);
+        }
+        Ok(self.l2_cache.get_mut(&l2_offset).unwrap())
+    }
+
+    fn cluster_mapping(&mut self, guest_offset: u64) -> Result<ClusterMapping, Qcow2IoError> {
+        let (l1_index, l2_index) = self.table_indexes(guest_offset);
+        let l2_offset = self.l1_table[l1_index] & TABLE_OFFSET_MASK;
+        if l2_offset == 0 {
+            return Ok(ClusterMapping::Unallocated);
+        }
+
+        let entry = self.l2_table(l2_offset)?[l2_index];
+        if entry & L2_ENTRY_COMPRESSED != 0 {
+            return Ok(ClusterMapping::Compressed);
+        }
+        let host_offset = entry & TABLE_OFFSET_MASK;
+        // The zero flag only exists since version 3.
+        if self.header.version >= 3 && entry & L2_ENTRY_ZERO != 0 {
+            return Ok(ClusterMapping::Zero(
+                (host_offset != 0).then_some(host_offset),
+            ));
+        }
+        if host_offset == 0 {
+            return Ok(ClusterMapping::Unallocated);
+        }
+        Ok(ClusterMapping::Allocated {
+            host_offset,
+            copied: entry & ENTRY_COPIED != 0,
+        })
+    }
+
+    fn check_range(&self, offset: u64, len: usize) -> Result<(), Qcow2IoError> {
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= self.header.size => Ok(()),
+            _ => Err(Qcow2IoError::OutOfRange(offset)),
+        }
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], offset: u64) -> Result<(), Qcow2IoError> {
+        self.check_range(offset, buf.len())?;
+
+        let cluster_size = self.cluster_size();
+        let mut done = 0;
+        while done < buf.len() {
+            let guest_offset = offset + done as u64;
+            let in_cluster = guest_offset % cluster_size;
+            let len = u64_to_usize(cluster_size - in_cluster).min(buf.len() - done);
+            let chunk = &mut buf[done..done + len];
+
+            match self.cluster_mapping(guest_offset)? {
+                ClusterMapping::Unallocated => match self.backing.as_mut() {
+                    Some(backing) => backing.read_at(chunk, guest_offset)?,
+                    None => chunk.fill(0),
+                },
+                ClusterMapping::Zero(_) => chunk.fill(0),
+                ClusterMapping::Allocated { host_offset, .. } => self
+                    .file
+                    .read_exact_at(chunk, host_offset + in_cluster)
+                    .map_err(Qcow2IoError::Read)?,
+                ClusterMapping::Compressed => {
+                    return Err(Qcow2IoError::Unsupported("compressed clusters"));
+                }
+            }
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    fn write_at(&mut self, buf: &[u8], offset: u64) -> Result<(), Qcow2IoError> {
+        self.check_range(offset, buf.len())?;
+
+        let cluster_size = self.cluster_size();
+        let mut done = 0;
+        while done < buf.len() {
+            let guest_offset = offset + done as u64;
+            let in_cluster = guest_offset % cluster_size;
+            let len = u64_to_usize(cluster_size - in_cluster).min(buf.len() - done);
+            let chunk = &buf[done..done + len];
+
+            match self.cluster_mapping(guest_offset)? {
+                ClusterMapping::Allocated {
+                    host_offset,
+                    copied: true,
+                } => self
+                    .file
+                    .write_all_at(chunk, host_offset + in_cluster)
+                    .map_err(Qcow2IoError::Write)?,
+                ClusterMapping::Allocated { copied: false, .. } => {
+                    return Err(Qcow2IoError::Unsupported("writes to shared clusters"));
+                }
+                ClusterMapping::Compressed => {
+                    return Err(Qcow2IoError::Unsupported("compressed clusters"));
+                }
+                mapping @ (ClusterMapping::Unallocated | ClusterMapping::Zero(_)) => {
+                    self.write_new_cluster(mapping, guest_offset, chunk)?
+                }
+            }
+            done += len;
+        }
+
+        Ok(())
+    }
+
+    // Writes `chunk` to a cluster that has no data of its own in this image yet. The rest of
+    // the cluster is filled from the backing file, or with zeroes.
+    fn write_new_cluster(
+        &mut self,
+        mapping: ClusterMapping,
+        guest_offset: u64,
+        chunk: &[u8],
+    ) -> Result<(), Qcow2IoError> {
+        let cluster_size = self.cluster_size();
+        let cluster_start = guest_offset - guest_offset % cluster_size;
+
+        let host_offset = match mapping {
+            ClusterMapping::Zero(Some(host_offset)) => host_offset,
+            _ => self.allocate_cluster()?,
+        };
+
+        if chunk.len() as u64 != cluster_size {
+            let mut base = vec![0u8; u64_to_usize(cluster_size)];
+            if let (ClusterMapping::Unallocated, Some(backing)) = (mapping, self.backing.as_mut()) {
+                backing.read_at(&mut base, cluster_start)?;
+            }
+            self.file
+                .write_all_at(&base, host_offset)
+                .map_err(Qcow2IoError::Write)?;
+        }
+        self.file
+            .write_all_at(chunk, host_offset + guest_offset - cluster_start)
+            .map_err(Qcow2IoError::Write)?;
+
+        // Only point the metadata to the cluster once its data is in place.
+        self.set_l2_entry(guest_offset, host_offset | ENTRY_COPIED)
+    }
+
+    fn set_l2_entry(&mut self, guest_offset: u64, entry: u64) -> Result<(), Qcow2IoError> {
+        let (l1_index, l2_index) = self.table_indexes(guest_offset);
+
+        let mut l2_offset = self.l1_table[l1_index] & TABLE_OFFSET_MASK;
+        if l2_offset == 0 {
+            l2_offset = self.allocate_cluster()?;
+            let table = vec![0u64; u64_to_usize(self.header.l2_entries())];
+            self.file
+                .write_all_at(&vec![0u8; u64_to_usize(self.cluster_size())], l2_offset)
+                .map_err(Qcow2IoError::Write)?;
+            self.l2_cache.insert(l2_offset, table);
+
+            let l1_entry = l2_offset | ENTRY_COPIED;
+            self.file
+                .write_all_at(
+                    &l1_entry.to_be_bytes(),
+                    self.header.l1_table_offset + l1_index as u64 * 8,
+                )
+                .map_err(Qcow2IoError::Write)?;
+            self.l1_table[l1_index] = l1_entry;
+        }
+
+        self.file
+            .write_all_at(&entry.to_be_bytes(), l2_offset + l2_index as u64 * 8)
+            .map_err(Qcow2IoError::Write)?;
+        self.l2_table(l2_offset)?[l2_index] = entry;
+        Ok(())
+    }
+
+    fn allocate_cluster(&mut self) -> Result<u64, Qcow2IoError> {
+        let host_offset = self.next_free_cluster;
+        self.next_free_cluster += self.cluster_size();
+        self.set_refcount(host_offset, 1)?;
+        Ok(host_offset)
+    }
+
+    fn set_refcount(&mut self, host_offset: u64, refcount: u16) -> Result<(), Qcow2IoError> {
+        if self.header.refcount_order != WRITABLE_REFCOUNT_ORDER {
+            return Err(Qcow2IoError::Unsupported("refcount width"));
+        }
+
+        let cluster_index = host_offset >> self.header.cluster_bits;
+        let block_entries = self.header.refcount_block_entries();
+        let table_index = u64_to_usize(cluster_index / block_entries);
+        let block_index = cluster_index % block_entries;
+
+        let mut block_offset = *self
+            .refcount_table
+            .get(table_index)
+            .ok_or(Qcow2IoError::RefcountTableFull)?
+            & REFCOUNT_TABLE_OFFSET_MASK;
+        if block_offset == 0 {
+            // Allocate a new refcount block. Its own refcount may end up in the new block or in
+            // yet another one.
+            block_offset = self.next_free_cluster;
+            self.next_free_cluster += self.cluster_size();
+            self.file
+                .write_all_at(&vec![0u8; u64_to_usize(self.cluster_size())], block_offset)
+                .map_err(Qcow2IoError::Write)?;
+            self.file
+                .write_all_at(
+                    &block_offset.to_be_bytes(),
+                    self.header.refcount_table_offset + table_index as u64 * 8,
+                )
+                .map_err(Qcow2IoError::Write)?;
+            self.refcount_table[table_index] = block_offset;
+            self.set_refcount(block_offset, 1)?;
+        }
+
+        self.file
+            .write_all_at(&refcount.to_be_bytes(), block_offset + block_index * 2)
+            .map_err(Qcow2IoError::Write)
+    }
+}
+
+#[derive(Debug)]
+pub struct Qcow2FileEngine {
+    image: Qcow2Image,
+}
+
+impl Qcow2FileEngine {
+    /// Opens the qcow2 image in `file`. Relative backing file paths are resolved against the
+    /// directory of `path`.
+    pub fn from_file(file: File, path: &Path) -> Result<Qcow2FileEngine, Qcow2IoError> {
+        Ok(Qcow2FileEngine {
+            image: Qcow2Image::open(file, path, 0)?,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn file(&self) -> &File {
+        &self.image.file
+    }
+
+    /// Size of the disk exposed to the guest.
+    pub fn virtual_size(&self) -> u64 {
+        self.image.header.size
+    }
+
+    /// Update the image of the engine.
+    pub fn update_file(&mut self, file: File, path: &Path) -> Result<(), Qcow2IoError> {
+        self.image = Qcow2Image::open(file, path, 0)?;
+        Ok(())
+    }
+
+    pub fn read(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, Qcow2IoError> {
+        if !mem.check_range(addr, count as usize) {
+            return Err(Qcow2IoError::Transfer(
+                GuestMemoryError::InvalidGuestAddress(addr),
+            ));
+        }
+        let mut buf = vec![0u8; count as usize];
+        self.image.read_at(&mut buf, offset)?;
+        mem.write_slice(&buf, addr)
+            .map_err(Qcow2IoError::Transfer)?;
+        Ok(count)
+    }
+
+    pub fn write(
+        &mut self,
+        offset: u64,
+        mem: &GuestMemoryMmap,
+        addr: GuestAddress,
+        count: u32,
+    ) -> Result<u32, Qcow2IoError> {
+        let mut buf = vec![0u8; count as usize];
+        mem.read_slice(&mut buf, addr)
+            .map_err(Qcow2IoError::Transfer)?;
+        self.image.write_at(&buf, offset)?;
+        Ok(count)
+    }
+
+    pub fn flush(&mut self) -> Result<(), Qcow2IoError> {
+        self.image.file.sync_all().map_err(Qcow2IoError::Flush)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::os::unix::ffi::OsStrExt;
+
+    use vmm_sys_util::tempfile::TempFile;
+
+    use super::*;
+
+    const CLUSTER_BITS: u32 = 9;
+    const CLUSTER_SIZE: u64 = 1 << CLUSTER_BITS;
+
+    /// Creates an empty qcow2 version 3 image, with 512 byte clusters.
+    ///
+    /// The layout is: header, L1 table, refcount table, refcount block.
+    pub fn create_qcow2_image(path: &Path, size: u64, backing: Option<(&str, &str)>) {
+        let l1_size = size.div_ceil(CLUSTER_SIZE * CLUSTER_SIZE / 8);
+        assert!(l1_size * 8 <= CLUSTER_SIZE);
+
+        let mut header = vec![0u8; u64_to_usize(CLUSTER_SIZE)];
+        header[0..4].copy_from_slice(&QCOW2_MAGIC.to_be_bytes());
+        header[4..8].copy_from_slice(&3u32.to_be_bytes());
+        header[20..24].copy_from_slice(&CLUSTER_BITS.to_be_bytes());
+        header[24..32].copy_from_slice(&size.to_be_bytes());
+        header[36..40].copy_from_slice(&u32::try_from(l1_size).unwrap().to_be_bytes());
+        header[40..48].copy_from_slice(&CLUSTER_SIZE.to_be_bytes());
+        header[48..56].copy_from_slice(&(2 * CLUSTER_SIZE).to_be_bytes());
+        header[56..60].copy_from_slice(&1u32.to_be_bytes());
+        header[96..100].copy_from_slice(&WRITABLE_REFCOUNT_ORDER.to_be_bytes());
+        header[100..104].copy_from_slice(&V3_HEADER_LEN.to_be_bytes());
+        if let Some((name, format)) = backing {
+            // Optional backing format extension, end of extensions, then the backing file name.
+            let mut name_offset = 112;
+            if !format.is_empty() {
+                let format = format.as_bytes();
+                header[104..108].copy_from_slice(&HEADER_EXT_BACKING_FORMAT.to_be_bytes());
+                header[108..112]
+                    .copy_from_slice(&u32::try_from(format.len()).unwrap().to_be_bytes());
+                header[112..112 + format.len()].copy_from_slice(format);
+                name_offset += format.len().next_multiple_of(8) + 8;
+            }
+            header[name_offset..name_offset + name.len()].copy_from_slice(name.as_bytes());
+            header[8..16].copy_from_slice(&(name_offset as u64).to_be_bytes());
+            header[16..20].copy_from_slice(&u32::try_from(name.len()).unwrap().to_be_bytes());
+        }
+
+        let mut refcount_table = vec![0u8; u64_to_usize(CLUSTER_SIZE)];
+        refcount_table[0..8].copy_from_slice(&(3 * CLUSTER_SIZE).to_be_bytes());
+        let mut refcount_block = vec![0u8; u64_to_usize(CLUSTER_SIZE)];
+        for cluster in 0..4 {
+            refcount_block[cluster * 2..cluster * 2 + 2].copy_from_slice(&1u16.to_be_bytes());
+        }
+
+        let file = File::create(path).unwrap();
+        file.write_all_at(&header, 0).unwrap();
+        file.write_all_at(&vec![0u8; u64_to_usize(CLUSTER_SIZE)], CLUSTER_SIZE)
+            .unwrap();
+        file.write_all_at(&refcount_table, 2 * CLUSTER_SIZE)
+            .unwrap();
+        file.write_all_at(&refcount_block, 3 * CLUSTER_SIZE)
+            .unwrap();
+    }
+
+    fn open_image(path: &Path) -> Qcow2Image {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        Qcow2Image::open(file, path, 0).unwrap()
+    }
+
+    fn read_refcount(file: &File, host_offset: u64) -> u16 {
+        // Each refcount block holds the 16 bit refcounts of 256 clusters.
+        let cluster = host_offset / CLUSTER_SIZE;
+        let mut table_entry = [0u8; 8];
+        file.read_exact_at(&mut table_entry, 2 * CLUSTER_SIZE + (cluster / 256) * 8)
+            .unwrap();
+        let block_offset = u64::from_be_bytes(table_entry);
+        let mut refcount = [0u8; 2];
+        file.read_exact_at(&mut refcount, block_offset + (cluster % 256) * 2)
+            .unwrap();
+        u16::from_be_bytes(refcount)
+    }
+
+    #[test]
+    fn test_header_validation() {
+        let tmp = TempFile::new().unwrap();
+        let path = tmp.as_path();
+        create_qcow2_image(path, 1 << 20, None);
+        let file = File::open(path).unwrap();
+        let header = Qcow2Header::read_from(&file).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.size, 1 << 20);
+        assert_eq!(header.cluster_size(), CLUSTER_SIZE);
+        assert_eq!(header.refcount_block_entries(), 256);
+
+        // Bad magic.
+        let rw = OpenOptions::new().write(true).open(path).unwrap();
+        rw.write_all_at(&[0u8; 4], 0).unwrap();
+        assert!(matches!(
+            Qcow2Header::read_from(&file),
+            Err(Qcow2IoError::InvalidImage("bad magic"))
+        ));
+        rw.write_all_at(&QCOW2_MAGIC.to_be_bytes(), 0).unwrap();
+
+        // Incompatible features.
+        rw.write_all_at(&1u64.to_be_bytes(), 72).unwrap();
+        assert!(matches!(
+            Qcow2Header::read_from(&file),
+            Err(Qcow2IoError::Unsupported("incompatible features"))
+        ));
+        rw.write_all_at(&0u64.to_be_bytes(), 72).unwrap();
+
+        // Cluster size out of range.
+        rw.write_all_at(&22u32.to_be_bytes(), 20).unwrap();
+        assert!(matches!(
+            Qcow2Header::read_from(&file),
+            Err(Qcow2IoError::InvalidImage("cluster size"))
+        ));
+        rw.write_all_at(&CLUSTER_BITS.to_be_bytes(), 20).unwrap();
+
+        // L1 table cannot map the whole disk.
+        rw.write_all_at(&(1u64 << 30).to_be_bytes(), 24).unwrap();
+        assert!(matches!(
+            Qcow2Header::read_from(&file),
+            Err(Qcow2IoError::InvalidImage("L1 table too small"))
+        ));
+    }
+
+    #[test]
+    fn test_read_write() {
+        let tmp = TempFile::new().unwrap();
+        let path = tmp.as_path();
+        create_qcow2_image(path, 1 << 20, None);
+        let mut image = open_image(path);
+
+        // Unallocated clusters read as zeroes.
+        let mut buf = vec![0xffu8; 2048];
+        image.read_at(&mut buf, 1000).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+
+        // Write across cluster boundaries, then read it back.
+        let data: Vec<u8> = (0..1500u32).map(|i| (i % 251) as u8).collect();
+        image.write_at(&data, 300).unwrap();
+        let mut buf = vec![0u8; 1500];
+        image.read_at(&mut buf, 300).unwrap();
+        assert_eq!(buf, data);
+        // The rest of the touched clusters is zeroed.
+        let mut buf = vec![0xffu8; 300];
+        image.read_at(&mut buf, 0).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+
+        // Overwrite in place.
+        image.write_at(&[7u8; 10], 310).unwrap();
+        let mut buf = vec![0u8; 12];
+        image.read_at(&mut buf, 309).unwrap();
+        assert_eq!(buf[0], data[9]);
+        assert_eq!(&buf[1..11], &[7u8; 10]);
+        assert_eq!(buf[11], data[20]);
+
+        // Out of range accesses.
+        assert!(matches!(
+            image.read_at(&mut [0u8; 2], (1 << 20) - 1),
+            Err(Qcow2IoError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            image.write_at(&[0u8; 1], 1 << 20),
+            Err(Qcow2IoError::OutOfRange(_))
+        ));
+
+        // The metadata is persisted: reopen the image and read the data again.
+        drop(image);
+        let mut image = open_image(path);
+        let mut buf = vec![0u8; 1500];
+        image.read_at(&mut buf, 300).unwrap();
+        assert_eq!(&buf[..10], &data[..10]);
+        assert_eq!(&buf[10..20], &[7u8; 10]);
+        assert_eq!(&buf[20..], &data[20..]);
+
+        // One L2 table and 4 data clusters were allocated after the 4 metadata clusters, and
+        // all of them are accounted for in the refcount block.
+        let file_len = image.file.metadata().unwrap().len();
+        assert_eq!(file_len, 9 * CLUSTER_SIZE);
+        for cluster in 0..9 {
+            assert_eq!(read_refcount(&image.file, cluster * CLUSTER_SIZE), 1);
+        }
+        assert_eq!(read_refcount(&image.file, 9 * CLUSTER_SIZE), 0);
+    }
+
+    #[test]
+    fn test_refcount_block_allocation() {
+        let tmp = TempFile::new().unwrap();
+        let path = tmp.as_path();
+        create_qcow2_image(path, 1 << 20, None);
+        let mut image = open_image(path);
+
+        // The first refcount block covers 256 clusters. Writing 300 clusters forces the
+        // allocation of a second one.
+        let data = vec![0x5au8; u64_to_usize(300 * CLUSTER_SIZE)];
+        image.write_at(&data, 0).unwrap();
+        assert_ne!(image.refcount_table[1], 0);
+        let second_block = image.refcount_table[1];
+        assert_eq!(read_refcount(&image.file, second_block), 1);
+
+        drop(image);
+        let mut image = open_image(path);
+        let mut buf = vec![0u8; data.len()];
+        image.read_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, data);
+    }
+
+    #[test]
+    fn test_zero_and_compressed_clusters() {
+        let tmp = TempFile::new().unwrap();
+        let path = tmp.as_path();
+        create_qcow2_image(path, 1 << 20, None);
+        let mut image = open_image(path);
+        image.write_at(&[1u8; 1024], 0).unwrap();
+
+        // Mark the first cluster as zero, keeping its preallocated host cluster.
+        let host_offset = match image.cluster_mapping(0).unwrap() {
+            ClusterMapping::Allocated { host_offset, .. } => host_offset,
+            other => panic!("unexpected mapping {other:?}"),
+        };
+        image
+            .set_l2_entry(0, host_offset | ENTRY_COPIED | L2_ENTRY_ZERO)
+            .unwrap();
+        let mut buf = [0xffu8; 512];
+        image.read_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, [0u8; 512]);
+
+        // Writing to it reuses the preallocated cluster.
+        image.write_at(&[2u8; 16], 16).unwrap();
+        assert_eq!(
+            image.cluster_mapping(0).unwrap(),
+            ClusterMapping::Allocated {
+                host_offset,
+                copied: true
+            }
+        );
+        image.read_at(&mut buf, 0).unwrap();
+        assert_eq!(buf[..16], [0u8; 16]);
+        assert_eq!(buf[16..32], [2u8; 16]);
+        assert_eq!(buf[32..], [0u8; 480]);
+
+        // Compressed clusters are not supported.
+        image
+            .set_l2_entry(512, L2_ENTRY_COMPRESSED | 0x1000)
+            .unwrap();
+        assert!(matches!(
+            image.read_at(&mut buf, 512),
+            Err(Qcow2IoError::Unsupported("compressed clusters"))
+        ));
+        assert!(matches!(
+            image.write_at(&buf, 512),
+            Err(Qcow2IoError::Unsupported("compressed clusters"))
+        ));
+    }
+
+    #[test]
+    fn test_backing_chain() {
+        let dir = vmm_sys_util::tempdir::TempDir::new().unwrap();
+        let dir_path = dir.as_path();
+
+        // A raw base image, smaller than the disk.
+        let base: Vec<u8> = (0..4096u32).map(|i| (i % 253) as u8).collect();
+        std::fs::write(dir_path.join("base.raw"), &base).unwrap();
+
+        // A qcow2 image on top of it, with some data of its own.
+        let mid_path = dir_path.join("mid.qcow2");
+        create_qcow2_image(&mid_path, 8192, Some(("base.raw", "raw")));
+        let mut mid = open_image(&mid_path);
+        mid.write_at(&[0xaa; 100], 1000).unwrap();
+        drop(mid);
+
+        // The writable overlay, referring to its backing file with an absolute path and no
+        // format, so that the format is probed.
+        let top_path = dir_path.join("top.qcow2");
+        let mid_name = mid_path.to_str().unwrap().to_string();
+        create_qcow2_image(&top_path, 8192, Some((&mid_name, "")));
+        let mut top = open_image(&top_path);
+
+        let mut expected = vec![0u8; 8192];
+        expected[..4096].copy_from_slice(&base);
+        expected[1000..1100].fill(0xaa);
+        let mut buf = vec![0u8; 8192];
+        top.read_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, expected);
+
+        // Writes to the overlay copy the rest of the cluster from the backing chain.
+        top.write_at(&[0xbb; 10], 1050).unwrap();
+        expected[1050..1060].fill(0xbb);
+        top.read_at(&mut buf, 0).unwrap();
+        assert_eq!(buf, expected);
+
+        // The backing files are left untouched.
+        assert_eq!(std::fs::read(dir_path.join("base.raw")).unwrap(), base);
+        let mut mid = open_image(&mid_path);
+        let mut mid_buf = vec![0u8; 10];
+        mid.read_at(&mut mid_buf, 1050).unwrap();
+        assert_eq!(mid_buf, [0xaa; 10]);
+
+        // Missing backing file.
+        let broken_path = dir_path.join("broken.qcow2");
+        create_qcow2_image(&broken_path, 8192, Some(("missing.raw", "raw")));
+        let file = File::open(&broken_path).unwrap();
+        assert!(matches!(
+            Qcow2Image::open(file, &broken_path, 0),
+            Err(Qcow2IoError::BackingFile(_, _))
+        ));
+
+        // An image backed by itself.
+        let looped_path = dir_path.join("loop.qcow2");
+        create_qcow2_image(&looped_path, 8192, Some(("loop.qcow2", "qcow2")));
+        let file = File::open(&looped_path).unwrap();
+        assert!(matches!(
+            Qcow2Image::open(file, &looped_path, 0),
+            Err(Qcow2IoError::BackingChainTooLong)
+        ));
+    }
+
+    #[test]
+    fn test_engine() {
+        let tmp = TempFile::new().unwrap();
+        let path = tmp.as_path();
+        create_qcow2_image(path, 1 << 20, None);
+        let mut engine = Qcow2FileEngine::from_file(File::open(path).unwrap(), path).unwrap();
+        assert_eq!(engine.virtual_size(), 1 << 20);
+
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .unwrap();
+        engine.update_file(file, path).unwrap();
+
+        let mem = crate::test_utils::single_region_mem(0x2000);
+        let data = vmm_sys_util::rand::rand_alphanumerics(1024);
+        mem.write_slice(data.as_bytes(), GuestAddress(0)).unwrap();
+        assert_eq!(
+            engine.write(512, &mem, GuestAddress(0), 1024).unwrap(),
+            1024
+        );
+        engine.flush().unwrap();
+        assert_eq!(
+            engine.read(512, &mem, GuestAddress(0x1000), 1024).unwrap(),
+            1024
+        );
+        let mut buf = vec![0u8; 1024];
+        mem.read_slice(&mut buf, GuestAddress(0x1000)).unwrap();
+        assert_eq!(buf, data.as_bytes());
+
+        // Guest memory out of range.
+        assert!(matches!(
+            engine.read(0, &mem, GuestAddress(0x1f00), 512),
+            Err(Qcow2IoError::Transfer(_))
+        ));
+    }
+}
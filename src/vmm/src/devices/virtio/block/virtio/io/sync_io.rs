@@ -3,13 +3,18 @@
 
 use std::fs::File;
 use std::io::{Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
 
 use vm_memory::{GuestMemoryError, ReadVolatile, WriteVolatile};
+use vmm_sys_util::syscall::SyscallReturnCode;
 
+use super::FallocateMode;
 use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap};
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum SyncIoError {
+    /// Fallocate: {0}
+    Fallocate(std::io::Error),
     /// Flush: {0}
     Flush(std::io::Error),
     /// Seek: {0}
@@ -81,4 +86,26 @@ impl SyncFileEngine {
         // Sync data out to physical media on host.
         self.file.sync_all().map_err(SyncIoError::SyncAll)
     }
+
+    pub fn fallocate(
+        &mut self,
+        offset: u64,
+        len: u64,
+        mode: FallocateMode,
+    ) -> Result<(), SyncIoError> {
+        let to_off = |value: u64| {
+            libc::off64_t::try_from(value).map_err(|err| {
+                SyncIoError::Fallocate(std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+            })
+        };
+        let (offset, len) = (to_off(offset)?, to_off(len)?);
+
+        // SAFETY: Safe because the file descriptor is valid for the lifetime of `self.file` and
+        // fallocate does not access any memory owned by us.
+        SyscallReturnCode(unsafe {
+            libc::fallocate64(self.file.as_raw_fd(), mode.flags(), offset, len)
+        })
+        .into_empty_result()
+        .map_err(SyncIoError::Fallocate)
+    }
 }
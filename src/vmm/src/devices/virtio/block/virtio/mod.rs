@@ -61,6 +61,14 @@ pub enum VirtioBlockError {
     Interrupt(std::io::Error),
     /// Error coming from the rate limiter: {0}
     RateLimiter(std::io::Error),
+    /// Discard and write zeroes cannot be enabled on a read-only drive.
+    DiscardReadOnly,
+    /// Discard limits must be greater than zero.
+    InvalidDiscardConfig,
+    /// Discard and write zeroes are not supported on qcow2 images.
+    DiscardQcow2,
+    /// Qcow2 images are only supported by the Sync IO engine.
+    Qcow2AsyncEngine,
     /// Persistence error: {0}
     Persist(crate::devices::virtio::persist::PersistError),
 }
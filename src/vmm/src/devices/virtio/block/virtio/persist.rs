@@ -10,7 +10,7 @@ use vmm_sys_util::eventfd::EventFd;
 use super::device::DiskProperties;
 use super::*;
 use crate::devices::virtio::block::persist::BlockConstructorArgs;
-use crate::devices::virtio::block::virtio::device::FileEngineType;
+use crate::devices::virtio::block::virtio::device::{DiscardConfig, FileEngineType, ImageFormat};
 use crate::devices::virtio::block::virtio::metrics::BlockMetricsPerDevice;
 use crate::devices::virtio::device::{ActiveState, DeviceState};
 use crate::devices::virtio::generated::virtio_blk::VIRTIO_BLK_F_RO;
@@ -61,6 +61,8 @@ pub struct VirtioBlockState {
     pub virtio_state: VirtioDeviceState,
     rate_limiter_state: RateLimiterState,
     file_engine_type: FileEngineTypeState,
+    discard: Option<DiscardConfig>,
+    format: ImageFormat,
 }
 
 impl Persist<'_> for VirtioBlock {
@@ -79,6 +81,8 @@ impl Persist<'_> for VirtioBlock {
             virtio_state: VirtioDeviceState::from_device(self),
             rate_limiter_state: self.rate_limiter.save(),
             file_engine_type: FileEngineTypeState::from(self.file_engine_type()),
+            discard: self.discard,
+            format: self.image_format(),
         }
     }
 
@@ -87,13 +91,17 @@ impl Persist<'_> for VirtioBlock {
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
         let is_read_only = state.virtio_state.avail_features & (1u64 << VIRTIO_BLK_F_RO) != 0;
-        let rate_limiter = RateLimiter::restore((), &state.rate_limiter_state)
-            .map_err(VirtioBlockError::RateLimiter)?;
+        let rate_limiter = RateLimiter::restore(
+            &constructor_args.rate_limiter_groups,
+            &state.rate_limiter_state,
+        )
+        .map_err(VirtioBlockError::RateLimiter)?;
 
         let disk_properties = DiskProperties::new(
             state.disk_path.clone(),
             is_read_only,
             state.file_engine_type.into(),
+            state.format,
         )?;
 
         let queue_evts = [EventFd::new(libc::EFD_NONBLOCK).map_err(VirtioBlockError::EventFd)?];
@@ -111,9 +119,7 @@ impl Persist<'_> for VirtioBlock {
         let avail_features = state.virtio_state.avail_features;
         let acked_features = state.virtio_state.acked_features;
 
-        let config_space = ConfigSpace {
-            capacity: disk_properties.nsectors.to_le(),
-        };
+        let config_space = ConfigSpace::new(disk_properties.nsectors, state.discard.as_ref());
 
         Ok(VirtioBlock {
             avail_features,
@@ -130,6 +136,7 @@ impl Persist<'_> for VirtioBlock {
             cache_type: state.cache_type,
             root_device: state.root_device,
             read_only: is_read_only,
+            discard: state.discard,
 
             disk: disk_properties,
             rate_limiter,
@@ -163,7 +170,10 @@ mod tests {
             is_read_only: false,
             cache_type: CacheType::Writeback,
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: FileEngineType::default(),
+            discard: None,
+            format: ImageFormat::Raw,
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -207,7 +217,10 @@ mod tests {
             is_read_only: false,
             cache_type: CacheType::Unsafe,
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: FileEngineType::default(),
+            discard: Some(DiscardConfig::default()),
+            format: ImageFormat::Raw,
         };
 
         let block = VirtioBlock::new(config).unwrap();
@@ -222,7 +235,10 @@ mod tests {
 
         // Restore the block device.
         let restored_block = VirtioBlock::restore(
-            BlockConstructorArgs { mem: guest_mem },
+            BlockConstructorArgs {
+                mem: guest_mem,
+                rate_limiter_groups: Vec::new(),
+            },
             &Snapshot::load_without_crc_check(mem.as_slice())
                 .unwrap()
                 .data,
@@ -239,5 +255,7 @@ mod tests {
 
         // Test that block specific fields are the same.
         assert_eq!(restored_block.disk.file_path, block.disk.file_path);
+        assert_eq!(restored_block.discard, block.discard);
+        assert_eq!(restored_block.config_space, block.config_space);
     }
 }
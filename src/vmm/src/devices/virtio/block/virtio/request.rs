@@ -10,11 +10,12 @@ use std::convert::From;
 use vm_memory::GuestMemoryError;
 
 use super::{SECTOR_SHIFT, SECTOR_SIZE, VirtioBlockError, io as block_io};
-use crate::devices::virtio::block::virtio::device::DiskProperties;
+use crate::devices::virtio::block::virtio::device::{DiscardConfig, DiskProperties};
 use crate::devices::virtio::block::virtio::metrics::BlockDeviceMetrics;
 pub use crate::devices::virtio::generated::virtio_blk::{
     VIRTIO_BLK_ID_BYTES, VIRTIO_BLK_S_IOERR, VIRTIO_BLK_S_OK, VIRTIO_BLK_S_UNSUPP,
-    VIRTIO_BLK_T_FLUSH, VIRTIO_BLK_T_GET_ID, VIRTIO_BLK_T_IN, VIRTIO_BLK_T_OUT,
+    VIRTIO_BLK_T_DISCARD, VIRTIO_BLK_T_FLUSH, VIRTIO_BLK_T_GET_ID, VIRTIO_BLK_T_IN,
+    VIRTIO_BLK_T_OUT, VIRTIO_BLK_T_WRITE_ZEROES, VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP,
 };
 use crate::devices::virtio::queue::DescriptorChain;
 use crate::logger::{IncMetric, error};
@@ -24,8 +25,16 @@ use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
 #[derive(Debug, derive_more::From)]
 pub enum IoErr {
     GetId(GuestMemoryError),
-    PartialTransfer { completed: u32, expected: u32 },
+    PartialTransfer {
+        completed: u32,
+        expected: u32,
+    },
     FileEngine(block_io::BlockIoError),
+    #[from(skip)]
+    SegmentTooLarge {
+        num_sectors: u32,
+        max: u32,
+    },
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -34,6 +43,8 @@ pub enum RequestType {
     Out,
     Flush,
     GetDeviceID,
+    Discard,
+    WriteZeroes,
     Unsupported(u32),
 }
 
@@ -44,6 +55,8 @@ impl From<u32> for RequestType {
             VIRTIO_BLK_T_OUT => RequestType::Out,
             VIRTIO_BLK_T_FLUSH => RequestType::Flush,
             VIRTIO_BLK_T_GET_ID => RequestType::GetDeviceID,
+            VIRTIO_BLK_T_DISCARD => RequestType::Discard,
+            VIRTIO_BLK_T_WRITE_ZEROES => RequestType::WriteZeroes,
             t => RequestType::Unsupported(t),
         }
     }
@@ -176,6 +189,18 @@ impl PendingRequest {
             (Ok(transferred_data_len), RequestType::GetDeviceID) => {
                 Status::from_data(self.data_len, transferred_data_len, true)
             }
+            (Ok(_), RequestType::Discard) => {
+                block_metrics.discard_count.inc();
+                Status::Ok {
+                    num_bytes_to_mem: 0,
+                }
+            }
+            (Ok(_), RequestType::WriteZeroes) => {
+                block_metrics.write_zeroes_count.inc();
+                Status::Ok {
+                    num_bytes_to_mem: 0,
+                }
+            }
             (_, RequestType::Unsupported(op)) => Status::Unsupported { op },
             (Err(err), _) => Status::IoErr {
                 num_bytes_to_mem: 0,
@@ -231,6 +256,36 @@ impl RequestHeader {
     }
 }
 
+/// A single range of a discard or write zeroes request.
+///
+/// The driver places the segments in the data descriptor, with the following layout:
+///   * sector: an u64 value representing the first sector of the range.
+///   * num_sectors: an u32 value representing the number of sectors in the range.
+///   * flags: an u32 value; only the `unmap` bit is defined, and only for write zeroes.
+#[derive(Debug, Copy, Clone, Default)]
+#[repr(C)]
+pub struct DiscardWriteZeroesSegment {
+    sector: u64,
+    num_sectors: u32,
+    flags: u32,
+}
+
+// SAFETY: Safe because DiscardWriteZeroesSegment only contains plain data.
+unsafe impl ByteValued for DiscardWriteZeroesSegment {}
+
+impl DiscardWriteZeroesSegment {
+    pub fn new(sector: u64, num_sectors: u32, flags: u32) -> Self {
+        DiscardWriteZeroesSegment {
+            sector,
+            num_sectors,
+            flags,
+        }
+    }
+}
+
+/// Size of a discard or write zeroes segment in guest memory.
+pub const DISCARD_SEGMENT_SIZE: u32 = 16;
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct Request {
     pub r#type: RequestType,
@@ -238,6 +293,9 @@ pub struct Request {
     pub status_addr: GuestAddress,
     sector: u64,
     data_addr: GuestAddress,
+    // Only used by discard and write zeroes requests.
+    num_sectors: u32,
+    flags: u32,
 }
 
 impl Request {
@@ -258,6 +316,8 @@ impl Request {
             data_addr: GuestAddress(0),
             data_len: 0,
             status_addr: GuestAddress(0),
+            num_sectors: 0,
+            flags: 0,
         };
 
         let data_desc;
@@ -278,7 +338,12 @@ impl Request {
                 .next_descriptor()
                 .ok_or(VirtioBlockError::DescriptorChainTooShort)?;
 
-            if data_desc.is_write_only() && req.r#type == RequestType::Out {
+            if data_desc.is_write_only()
+                && matches!(
+                    req.r#type,
+                    RequestType::Out | RequestType::Discard | RequestType::WriteZeroes
+                )
+            {
                 return Err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
             }
             if !data_desc.is_write_only() && req.r#type == RequestType::In {
@@ -313,6 +378,25 @@ impl Request {
                     return Err(VirtioBlockError::InvalidDataLength);
                 }
             }
+            RequestType::Discard | RequestType::WriteZeroes => {
+                // We advertise a maximum of one segment per request.
+                if req.data_len != DISCARD_SEGMENT_SIZE {
+                    return Err(VirtioBlockError::InvalidDataLength);
+                }
+                let segment: DiscardWriteZeroesSegment = mem
+                    .read_obj(req.data_addr)
+                    .map_err(VirtioBlockError::GuestMemory)?;
+                let top_sector = segment
+                    .sector
+                    .checked_add(u64::from(segment.num_sectors))
+                    .ok_or(VirtioBlockError::InvalidOffset)?;
+                if top_sector > num_disk_sectors {
+                    return Err(VirtioBlockError::InvalidOffset);
+                }
+                req.sector = segment.sector;
+                req.num_sectors = segment.num_sectors;
+                req.flags = segment.flags;
+            }
             _ => {}
         }
 
@@ -363,13 +447,55 @@ impl Request {
         }
     }
 
+    // Checks a discard or write zeroes request against the device configuration and returns
+    // the fallocate operation to perform on the backing file.
+    fn discard_mode(&self, discard: &DiscardConfig) -> Result<block_io::FallocateMode, IoErr> {
+        let max = match self.r#type {
+            RequestType::Discard => discard.max_discard_sectors,
+            _ => discard.max_write_zeroes_sectors,
+        };
+        if self.num_sectors > max {
+            return Err(IoErr::SegmentTooLarge {
+                num_sectors: self.num_sectors,
+                max,
+            });
+        }
+
+        // Write zeroes may deallocate the range only if the driver allows it.
+        if self.r#type == RequestType::WriteZeroes
+            && self.flags & VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP == 0
+        {
+            Ok(block_io::FallocateMode::ZeroRange)
+        } else {
+            Ok(block_io::FallocateMode::PunchHole)
+        }
+    }
+
     pub(crate) fn process(
-        self,
+        mut self,
         disk: &mut DiskProperties,
+        discard: Option<&DiscardConfig>,
         desc_idx: u16,
         mem: &GuestMemoryMmap,
         block_metrics: &BlockDeviceMetrics,
     ) -> ProcessingResult {
+        // The driver must not send discard or write zeroes requests unless we offered the
+        // features. It also must not set the unmap flag on discard, nor any unknown flag.
+        let op = match self.r#type {
+            RequestType::Discard => Some(VIRTIO_BLK_T_DISCARD),
+            RequestType::WriteZeroes => Some(VIRTIO_BLK_T_WRITE_ZEROES),
+            _ => None,
+        };
+        if let Some(op) = op {
+            let allowed_flags = match self.r#type {
+                RequestType::WriteZeroes => VIRTIO_BLK_WRITE_ZEROES_FLAG_UNMAP,
+                _ => 0,
+            };
+            if discard.is_none() || self.flags & !allowed_flags != 0 {
+                self.r#type = RequestType::Unsupported(op);
+            }
+        }
+
         let pending = self.to_pending_request(desc_idx);
         let res = match self.r#type {
             RequestType::In => {
@@ -390,6 +516,30 @@ impl Request {
                     .map_err(IoErr::GetId);
                 return ProcessingResult::Executed(pending.finish(mem, res, block_metrics));
             }
+            RequestType::Discard | RequestType::WriteZeroes => {
+                // Checked above.
+                let discard = discard.unwrap();
+                let mode = match self.discard_mode(discard) {
+                    Ok(mode) => mode,
+                    Err(err) => {
+                        return ProcessingResult::Executed(pending.finish(
+                            mem,
+                            Err(err),
+                            block_metrics,
+                        ));
+                    }
+                };
+                // Nothing to do for empty ranges, and fallocate rejects them.
+                if self.num_sectors == 0 {
+                    return ProcessingResult::Executed(pending.finish(mem, Ok(0), block_metrics));
+                }
+                disk.file_engine.fallocate(
+                    self.offset(),
+                    u64::from(self.num_sectors) << SECTOR_SHIFT,
+                    mode,
+                    pending,
+                )
+            }
             RequestType::Unsupported(_) => {
                 return ProcessingResult::Executed(pending.finish(mem, Ok(0), block_metrics));
             }
@@ -449,6 +599,8 @@ mod tests {
             VIRTIO_BLK_T_OUT,
             VIRTIO_BLK_T_FLUSH,
             VIRTIO_BLK_T_GET_ID,
+            VIRTIO_BLK_T_DISCARD,
+            VIRTIO_BLK_T_WRITE_ZEROES,
         ];
 
         for request_type in supported_request_types {
@@ -475,6 +627,14 @@ mod tests {
             RequestType::from(VIRTIO_BLK_T_GET_ID),
             RequestType::GetDeviceID
         );
+        assert_eq!(
+            RequestType::from(VIRTIO_BLK_T_DISCARD),
+            RequestType::Discard
+        );
+        assert_eq!(
+            RequestType::from(VIRTIO_BLK_T_WRITE_ZEROES),
+            RequestType::WriteZeroes
+        );
         assert_eq!(RequestType::from(42), RequestType::Unsupported(42));
     }
 
@@ -670,6 +830,51 @@ mod tests {
         chain.check_parse(true);
     }
 
+    #[test]
+    fn test_parse_discard_write_zeroes() {
+        let mem = &default_mem();
+        let queue = VirtQueue::new(GuestAddress(0), mem, 16);
+        let chain = RequestDescriptorChain::new(&queue);
+
+        for request_type in [VIRTIO_BLK_T_DISCARD, VIRTIO_BLK_T_WRITE_ZEROES] {
+            let request_header = RequestHeader::new(request_type, 0);
+            chain.set_header(request_header);
+            let mut segment = DiscardWriteZeroesSegment::new(8, 16, 0);
+            let data_addr = GuestAddress(chain.data_desc.addr.get());
+            mem.write_obj(segment, data_addr).unwrap();
+
+            // Write only data descriptor.
+            chain
+                .data_desc
+                .flags
+                .set(VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE);
+            chain.data_desc.len.set(DISCARD_SEGMENT_SIZE);
+            chain.check_parse_err(VirtioBlockError::UnexpectedWriteOnlyDescriptor);
+
+            // More than one segment.
+            chain.data_desc.flags.set(VIRTQ_DESC_F_NEXT);
+            chain.data_desc.len.set(2 * DISCARD_SEGMENT_SIZE);
+            chain.check_parse_err(VirtioBlockError::InvalidDataLength);
+
+            // Range goes beyond the end of the disk.
+            chain.data_desc.len.set(DISCARD_SEGMENT_SIZE);
+            segment.sector = NUM_DISK_SECTORS - 8;
+            mem.write_obj(segment, data_addr).unwrap();
+            chain.check_parse_err(VirtioBlockError::InvalidOffset);
+
+            // Valid segment.
+            segment.sector = NUM_DISK_SECTORS - 16;
+            mem.write_obj(segment, data_addr).unwrap();
+            let mut q = chain.driver_queue.create_queue();
+            let request =
+                Request::parse(&q.pop().unwrap().unwrap(), mem, NUM_DISK_SECTORS).unwrap();
+            assert_eq!(request.r#type, RequestType::from(request_type));
+            assert_eq!(request.sector, NUM_DISK_SECTORS - 16);
+            assert_eq!(request.num_sectors, 16);
+            assert_eq!(request.flags, 0);
+        }
+    }
+
     use std::convert::TryInto;
 
     /// -------------------------------------
@@ -715,9 +920,9 @@ mod tests {
                     1u32,
                     std::sync::Arc::new(Strategy::prop_map(any::<u32>(), |id| {
                         // Random unsupported requests for our implementation start at
-                        // VIRTIO_BLK_T_GET_ID + 1 = 9.
-                        // This can be further refined to include unsupported requests ids < 9.
-                        RequestType::Unsupported(id.checked_add(9).unwrap_or(9))
+                        // VIRTIO_BLK_T_WRITE_ZEROES + 1 = 14.
+                        // This can be further refined to include unsupported requests ids < 14.
+                        RequestType::Unsupported(id.checked_add(14).unwrap_or(14))
                     })),
                 ),
             ))
@@ -731,6 +936,8 @@ mod tests {
                 RequestType::Out => VIRTIO_BLK_T_OUT,
                 RequestType::Flush => VIRTIO_BLK_T_FLUSH,
                 RequestType::GetDeviceID => VIRTIO_BLK_T_GET_ID,
+                RequestType::Discard => VIRTIO_BLK_T_DISCARD,
+                RequestType::WriteZeroes => VIRTIO_BLK_T_WRITE_ZEROES,
                 RequestType::Unsupported(id) => id,
             }
         }
@@ -743,6 +950,7 @@ mod tests {
             RequestType::Out => VIRTQ_DESC_F_NEXT,
             RequestType::Flush => VIRTQ_DESC_F_NEXT,
             RequestType::GetDeviceID => VIRTQ_DESC_F_NEXT | VIRTQ_DESC_F_WRITE,
+            RequestType::Discard | RequestType::WriteZeroes => VIRTQ_DESC_F_NEXT,
             RequestType::Unsupported(_) => VIRTQ_DESC_F_NEXT,
         }
     }
@@ -834,6 +1042,8 @@ mod tests {
             status_addr,
             sector: sector & (NUM_DISK_SECTORS - sectors_len),
             data_addr,
+            num_sectors: 0,
+            flags: 0,
         };
         let mut request_header = RequestHeader::new(virtio_request_id, request.sector);
 
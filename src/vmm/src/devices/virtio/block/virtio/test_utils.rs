@@ -12,7 +12,7 @@ use vmm_sys_util::tempfile::TempFile;
 
 use super::RequestHeader;
 use super::device::VirtioBlockConfig;
-use crate::devices::virtio::block::virtio::device::FileEngineType;
+use crate::devices::virtio::block::virtio::device::{FileEngineType, ImageFormat};
 #[cfg(test)]
 use crate::devices::virtio::block::virtio::io::FileEngine;
 use crate::devices::virtio::block::virtio::{CacheType, VirtioBlock};
@@ -57,7 +57,10 @@ pub fn default_block_with_path(path: String, file_engine_type: FileEngineType) -
                 refill_time: 10,
             }),
         }),
+        rate_limiter_group: None,
         file_engine_type,
+        discard: None,
+        format: ImageFormat::Raw,
     };
 
     // The default block device is read-write and non-root.
@@ -119,7 +122,7 @@ pub fn simulate_queue_and_async_completion_events(b: &mut VirtioBlock, expected_
             simulate_queue_event(b, None);
             simulate_async_completion_event(b, expected_irq);
         }
-        FileEngine::Sync(_) => {
+        FileEngine::Sync(_) | FileEngine::Qcow2(_) => {
             simulate_queue_event(b, Some(expected_irq));
         }
     }
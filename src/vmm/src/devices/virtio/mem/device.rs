@@ -0,0 +1,817 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::ops::{Deref, Range};
+use std::sync::Arc;
+
+use log::info;
+use vm_memory::GuestMemoryError;
+use vmm_sys_util::eventfd::EventFd;
+
+use super::metrics::METRICS;
+use super::{
+    MEM_DEV_ID, MEM_NUM_QUEUES, MEM_QUEUE, VIRTIO_MEM_REQ_PLUG, VIRTIO_MEM_REQ_STATE,
+    VIRTIO_MEM_REQ_UNPLUG, VIRTIO_MEM_REQ_UNPLUG_ALL, VIRTIO_MEM_RESP_ACK, VIRTIO_MEM_RESP_ERROR,
+    VIRTIO_MEM_RESP_NACK, VIRTIO_MEM_STATE_MIXED, VIRTIO_MEM_STATE_PLUGGED,
+    VIRTIO_MEM_STATE_UNPLUGGED,
+};
+use crate::devices::virtio::ActivateError;
+use crate::devices::virtio::balloon::RemoveRegionError;
+use crate::devices::virtio::balloon::util::remove_range;
+use crate::devices::virtio::device::{ActiveState, DeviceState, VirtioDevice};
+use crate::devices::virtio::generated::virtio_config::VIRTIO_F_VERSION_1;
+use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_MEM;
+use crate::devices::virtio::queue::{
+    DescriptorChain, FIRECRACKER_MAX_QUEUE_SIZE, InvalidAvailIdx, Queue, QueueError,
+};
+use crate::devices::virtio::transport::{VirtioInterrupt, VirtioInterruptType};
+use crate::impl_device_type;
+use crate::logger::{IncMetric, debug, error};
+use crate::utils::u64_to_usize;
+use crate::vstate::memory::{ByteValued, Bytes, GuestAddress, GuestMemoryMmap};
+
+/// Errors triggered by the virtio-mem device.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum VirtioMemError {
+    /// Error while handling an Event file descriptor: {0}
+    EventFd(#[from] io::Error),
+    /// Block size must be a power of two: {0}
+    InvalidBlockSize(u64),
+    /// Region size {0} must be a non-zero multiple of the block size
+    InvalidRegionSize(u64),
+    /// Requested size {0} must be a multiple of the block size and not exceed the region size
+    InvalidRequestedSize(u64),
+    /// Guest gave us a malformed descriptor.
+    MalformedDescriptor,
+    /// Bad guest memory buffer: {0}
+    GuestMemory(#[from] GuestMemoryError),
+    /// Could not discard unplugged memory: {0}
+    DiscardMemory(#[from] RemoveRegionError),
+    /// Error while processing the virt queues: {0}
+    Queue(#[from] QueueError),
+    /// {0}
+    InvalidAvailIdx(#[from] InvalidAvailIdx),
+    /// Received error while sending an interrupt: {0}
+    InterruptError(io::Error),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct ConfigSpace {
+    pub block_size: u64,
+    pub node_id: u16,
+    pub padding: [u8; 6],
+    pub addr: u64,
+    pub region_size: u64,
+    pub usable_region_size: u64,
+    pub plugged_size: u64,
+    pub requested_size: u64,
+}
+
+// SAFETY: Safe because ConfigSpace only contains plain data.
+unsafe impl ByteValued for ConfigSpace {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct Request {
+    pub req_type: u16,
+    pub padding: [u16; 3],
+    pub addr: u64,
+    pub nb_blocks: u16,
+    pub padding_1: [u16; 3],
+}
+
+// SAFETY: Safe because Request only contains plain data.
+unsafe impl ByteValued for Request {}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Response {
+    pub resp_type: u16,
+    pub padding: [u16; 3],
+    pub state: u16,
+}
+
+// SAFETY: Safe because Response only contains plain data.
+unsafe impl ByteValued for Response {}
+
+impl Response {
+    fn new(resp_type: u16) -> Self {
+        Response {
+            resp_type,
+            ..Default::default()
+        }
+    }
+}
+
+const SIZE_OF_REQUEST: usize = std::mem::size_of::<Request>();
+const SIZE_OF_RESPONSE: usize = std::mem::size_of::<Response>();
+
+/// Virtio device exposing a hotpluggable memory region to the guest.
+///
+/// The whole region is mapped into the guest physical address space, but the guest only uses the
+/// memory blocks it has asked the device to plug. The device asks the guest to (un)plug memory
+/// by updating the requested size in its config space.
+#[derive(Debug)]
+pub struct VirtioMem {
+    // VirtIO fields
+    avail_features: u64,
+    acked_features: u64,
+    activate_event: EventFd,
+
+    // Transport fields
+    device_state: DeviceState,
+    pub(crate) queues: Vec<Queue>,
+    queue_events: Vec<EventFd>,
+
+    // Device specific fields
+    pub(crate) config_space: ConfigSpace,
+    // One bit per memory block, set if the block is plugged.
+    pub(crate) plugged_blocks: Vec<u64>,
+    pub(crate) restored_from_file: bool,
+}
+
+impl VirtioMem {
+    /// Creates a virtio-mem device managing the `region_size` bytes of guest memory starting at
+    /// `addr`, with memory being plugged in units of `block_size` bytes.
+    pub fn new(
+        addr: GuestAddress,
+        region_size: u64,
+        block_size: u64,
+    ) -> Result<Self, VirtioMemError> {
+        let queues = vec![Queue::new(FIRECRACKER_MAX_QUEUE_SIZE); MEM_NUM_QUEUES];
+        Self::new_with_queues(queues, addr, region_size, block_size, false)
+    }
+
+    pub fn new_with_queues(
+        queues: Vec<Queue>,
+        addr: GuestAddress,
+        region_size: u64,
+        block_size: u64,
+        restored_from_file: bool,
+    ) -> Result<Self, VirtioMemError> {
+        if !block_size.is_power_of_two() {
+            return Err(VirtioMemError::InvalidBlockSize(block_size));
+        }
+        if region_size == 0 || region_size % block_size != 0 {
+            return Err(VirtioMemError::InvalidRegionSize(region_size));
+        }
+
+        let activate_event = EventFd::new(libc::EFD_NONBLOCK)?;
+        let queue_events = (0..MEM_NUM_QUEUES)
+            .map(|_| EventFd::new(libc::EFD_NONBLOCK))
+            .collect::<Result<Vec<EventFd>, io::Error>>()?;
+        let nb_blocks = region_size / block_size;
+
+        Ok(Self {
+            avail_features: 1 << VIRTIO_F_VERSION_1,
+            acked_features: 0u64,
+            activate_event,
+            device_state: DeviceState::Inactive,
+            queues,
+            queue_events,
+            config_space: ConfigSpace {
+                block_size,
+                addr: addr.0,
+                region_size,
+                usable_region_size: region_size,
+                ..Default::default()
+            },
+            plugged_blocks: vec![0; u64_to_usize(nb_blocks.div_ceil(64))],
+            restored_from_file,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        MEM_DEV_ID
+    }
+
+    /// Start address of the hotpluggable memory region.
+    pub fn addr(&self) -> GuestAddress {
+        GuestAddress(self.config_space.addr)
+    }
+
+    /// Size of the hotpluggable memory region, in bytes.
+    pub fn region_size(&self) -> u64 {
+        self.config_space.region_size
+    }
+
+    /// Granularity at which memory is plugged and unplugged, in bytes.
+    pub fn block_size(&self) -> u64 {
+        self.config_space.block_size
+    }
+
+    /// Amount of memory currently plugged by the guest, in bytes.
+    pub fn plugged_size(&self) -> u64 {
+        self.config_space.plugged_size
+    }
+
+    /// Amount of memory the guest was asked to plug, in bytes.
+    pub fn requested_size(&self) -> u64 {
+        self.config_space.requested_size
+    }
+
+    /// Asks the guest to grow or shrink the plugged memory to `requested_size` bytes.
+    pub fn update_requested_size(&mut self, requested_size: u64) -> Result<(), VirtioMemError> {
+        if requested_size > self.region_size() || requested_size % self.block_size() != 0 {
+            return Err(VirtioMemError::InvalidRequestedSize(requested_size));
+        }
+
+        self.config_space.requested_size = requested_size;
+        // If the driver is not up yet, it will pick up the new size when reading the config space.
+        if self.is_activated() {
+            self.interrupt_trigger()
+                .trigger(VirtioInterruptType::Config)
+                .map_err(VirtioMemError::InterruptError)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_plugged_blocks(&mut self, plugged_blocks: &[u64]) {
+        let nb_blocks = self.plugged_blocks.len();
+        self.plugged_blocks
+            .copy_from_slice(&plugged_blocks[..nb_blocks.min(plugged_blocks.len())]);
+        self.config_space.plugged_size = u64::from(
+            self.plugged_blocks
+                .iter()
+                .map(|word| word.count_ones())
+                .sum::<u32>(),
+        ) * self.block_size();
+    }
+
+    fn is_block_plugged(&self, block: usize) -> bool {
+        self.plugged_blocks[block / 64] & (1 << (block % 64)) != 0
+    }
+
+    fn set_blocks_plugged(&mut self, blocks: Range<usize>, plugged: bool) {
+        for block in blocks {
+            if plugged {
+                self.plugged_blocks[block / 64] |= 1 << (block % 64);
+            } else {
+                self.plugged_blocks[block / 64] &= !(1 << (block % 64));
+            }
+        }
+    }
+
+    // Translates a guest request into the range of blocks it covers, if the range is valid.
+    fn block_range(&self, addr: u64, nb_blocks: u16) -> Option<Range<usize>> {
+        let offset = addr.checked_sub(self.config_space.addr)?;
+        let size = u64::from(nb_blocks).checked_mul(self.block_size())?;
+        if nb_blocks == 0
+            || offset % self.block_size() != 0
+            || offset.checked_add(size)? > self.config_space.usable_region_size
+        {
+            return None;
+        }
+
+        let first = u64_to_usize(offset / self.block_size());
+        Some(first..first + usize::from(nb_blocks))
+    }
+
+    fn discard(&self, mem: &GuestMemoryMmap, blocks: Range<usize>) -> Result<(), VirtioMemError> {
+        let block_size = self.block_size();
+        let addr = GuestAddress(self.config_space.addr + blocks.start as u64 * block_size);
+        let len = blocks.len() as u64 * block_size;
+        remove_range(mem, (addr, len), self.restored_from_file)?;
+        Ok(())
+    }
+
+    fn plug(&mut self, addr: u64, nb_blocks: u16) -> Response {
+        METRICS.plug_count.inc();
+        let Some(blocks) = self.block_range(addr, nb_blocks) else {
+            METRICS.plug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        };
+        let size = blocks.len() as u64 * self.block_size();
+
+        if self.plugged_size() + size > self.requested_size() {
+            return Response::new(VIRTIO_MEM_RESP_NACK);
+        }
+        if blocks.clone().any(|block| self.is_block_plugged(block)) {
+            METRICS.plug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        }
+
+        self.set_blocks_plugged(blocks, true);
+        self.config_space.plugged_size += size;
+        Response::new(VIRTIO_MEM_RESP_ACK)
+    }
+
+    fn unplug(&mut self, mem: &GuestMemoryMmap, addr: u64, nb_blocks: u16) -> Response {
+        METRICS.unplug_count.inc();
+        let Some(blocks) = self.block_range(addr, nb_blocks) else {
+            METRICS.unplug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        };
+        if !blocks.clone().all(|block| self.is_block_plugged(block)) {
+            METRICS.unplug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        }
+
+        if let Err(err) = self.discard(mem, blocks.clone()) {
+            error!("virtio-mem: {err}");
+            METRICS.unplug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        }
+        self.set_blocks_plugged(blocks.clone(), false);
+        self.config_space.plugged_size -= blocks.len() as u64 * self.block_size();
+        Response::new(VIRTIO_MEM_RESP_ACK)
+    }
+
+    fn unplug_all(&mut self, mem: &GuestMemoryMmap) -> Response {
+        METRICS.unplug_all_count.inc();
+        let nb_blocks = u64_to_usize(self.region_size() / self.block_size());
+        if let Err(err) = self.discard(mem, 0..nb_blocks) {
+            error!("virtio-mem: {err}");
+            METRICS.unplug_fails.inc();
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        }
+
+        self.plugged_blocks.fill(0);
+        self.config_space.plugged_size = 0;
+        Response::new(VIRTIO_MEM_RESP_ACK)
+    }
+
+    fn state(&self, addr: u64, nb_blocks: u16) -> Response {
+        METRICS.state_count.inc();
+        let Some(blocks) = self.block_range(addr, nb_blocks) else {
+            return Response::new(VIRTIO_MEM_RESP_ERROR);
+        };
+        let plugged = blocks
+            .clone()
+            .filter(|&block| self.is_block_plugged(block))
+            .count();
+
+        let state = match plugged {
+            0 => VIRTIO_MEM_STATE_UNPLUGGED,
+            n if n == blocks.len() => VIRTIO_MEM_STATE_PLUGGED,
+            _ => VIRTIO_MEM_STATE_MIXED,
+        };
+        Response {
+            state,
+            ..Response::new(VIRTIO_MEM_RESP_ACK)
+        }
+    }
+
+    fn handle_request(
+        &mut self,
+        mem: &GuestMemoryMmap,
+        head: &DescriptorChain,
+    ) -> Result<u32, VirtioMemError> {
+        if head.is_write_only() || (head.len as usize) < SIZE_OF_REQUEST {
+            return Err(VirtioMemError::MalformedDescriptor);
+        }
+        let resp_desc = head
+            .next_descriptor()
+            .filter(|desc| desc.is_write_only() && desc.len as usize >= SIZE_OF_RESPONSE)
+            .ok_or(VirtioMemError::MalformedDescriptor)?;
+
+        let request: Request = mem.read_obj(head.addr)?;
+        debug!("virtio-mem: guest request {request:?}");
+        let response = match request.req_type {
+            VIRTIO_MEM_REQ_PLUG => self.plug(request.addr, request.nb_blocks),
+            VIRTIO_MEM_REQ_UNPLUG => self.unplug(mem, request.addr, request.nb_blocks),
+            VIRTIO_MEM_REQ_UNPLUG_ALL => self.unplug_all(mem),
+            VIRTIO_MEM_REQ_STATE => self.state(request.addr, request.nb_blocks),
+            _ => Response::new(VIRTIO_MEM_RESP_ERROR),
+        };
+
+        mem.write_obj(response, resp_desc.addr)?;
+        // SIZE_OF_RESPONSE is a small compile-time constant.
+        Ok(u32::try_from(SIZE_OF_RESPONSE).unwrap())
+    }
+
+    pub(crate) fn process_mem_queue(&mut self) -> Result<(), VirtioMemError> {
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = self.device_state.active_state().unwrap().mem.clone();
+        let mut used_any = false;
+
+        while let Some(head) = self.queues[MEM_QUEUE].pop()? {
+            let len = self.handle_request(&mem, &head).unwrap_or_else(|err| {
+                error!("virtio-mem: Could not handle request: {err}");
+                METRICS.event_fails.inc();
+                0
+            });
+            self.queues[MEM_QUEUE].add_used(head.index, len)?;
+            used_any = true;
+        }
+        self.queues[MEM_QUEUE].advance_used_ring_idx();
+
+        if used_any {
+            self.signal_used_queue()?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn process_mem_queue_event(&mut self) {
+        METRICS.queue_event_count.inc();
+        if let Err(err) = self.queue_events[MEM_QUEUE].read() {
+            error!("virtio-mem: Failed to read queue event: {err}");
+            METRICS.event_fails.inc();
+        } else if let Err(err) = self.process_mem_queue() {
+            report_mem_event_fail(err);
+        }
+    }
+
+    fn signal_used_queue(&self) -> Result<(), VirtioMemError> {
+        self.interrupt_trigger()
+            .trigger(VirtioInterruptType::Queue(MEM_QUEUE.try_into().unwrap()))
+            .map_err(VirtioMemError::InterruptError)
+    }
+
+    /// Process device virtio queue(s).
+    pub fn process_virtio_queues(&mut self) -> Result<(), InvalidAvailIdx> {
+        if let Err(VirtioMemError::InvalidAvailIdx(err)) = self.process_mem_queue() {
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_avail_features(&mut self, features: u64) {
+        self.avail_features = features;
+    }
+
+    pub(crate) fn set_acked_features(&mut self, features: u64) {
+        self.acked_features = features;
+    }
+
+    pub(crate) fn activate_event(&self) -> &EventFd {
+        &self.activate_event
+    }
+}
+
+fn report_mem_event_fail(err: VirtioMemError) {
+    if let VirtioMemError::InvalidAvailIdx(err) = err {
+        panic!("{}", err);
+    }
+    error!("virtio-mem: {err:?}");
+    METRICS.event_fails.inc();
+}
+
+impl VirtioDevice for VirtioMem {
+    impl_device_type!(VIRTIO_ID_MEM);
+
+    fn queues(&self) -> &[Queue] {
+        &self.queues
+    }
+
+    fn queues_mut(&mut self) -> &mut [Queue] {
+        &mut self.queues
+    }
+
+    fn queue_events(&self) -> &[EventFd] {
+        &self.queue_events
+    }
+
+    fn interrupt_trigger(&self) -> &dyn VirtioInterrupt {
+        self.device_state
+            .active_state()
+            .expect("Device is not initialized")
+            .interrupt
+            .deref()
+    }
+
+    fn avail_features(&self) -> u64 {
+        self.avail_features
+    }
+
+    fn acked_features(&self) -> u64 {
+        self.acked_features
+    }
+
+    fn set_acked_features(&mut self, acked_features: u64) {
+        self.acked_features = acked_features;
+    }
+
+    fn read_config(&self, offset: u64, data: &mut [u8]) {
+        if let Some(config_space_bytes) = self.config_space.as_slice().get(u64_to_usize(offset)..) {
+            let len = config_space_bytes.len().min(data.len());
+            data[..len].copy_from_slice(&config_space_bytes[..len]);
+        } else {
+            error!("virtio-mem: Failed to read config space");
+        }
+    }
+
+    // The virtio-mem config space is read-only for the driver.
+    fn write_config(&mut self, _offset: u64, _data: &[u8]) {}
+
+    fn is_activated(&self) -> bool {
+        self.device_state.is_activated()
+    }
+
+    fn activate(
+        &mut self,
+        mem: GuestMemoryMmap,
+        interrupt: Arc<dyn VirtioInterrupt>,
+    ) -> Result<(), ActivateError> {
+        for q in self.queues.iter_mut() {
+            q.initialize(&mem)
+                .map_err(ActivateError::QueueMemoryError)?;
+        }
+
+        self.activate_event.write(1).map_err(|_| {
+            METRICS.activate_fails.inc();
+            ActivateError::EventFd
+        })?;
+        self.device_state = DeviceState::Activated(ActiveState { mem, interrupt });
+        Ok(())
+    }
+
+    fn kick(&mut self) {
+        if self.is_activated() {
+            info!("kick virtio-mem {}.", self.id());
+            self.process_virtio_queues();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::test_utils::{VirtQueue, default_interrupt};
+    use crate::test_utils::multi_region_mem;
+    use crate::utils::mib_to_bytes;
+
+    const REGION_START: u64 = 0x1000_0000;
+    const BLOCK_SIZE: u64 = 0x20_0000;
+    const NB_BLOCKS: u64 = 8;
+    // Guest addresses of the request and response buffers.
+    const REQ_ADDR: u64 = 0x10_0000;
+    const RESP_ADDR: u64 = 0x10_1000;
+
+    impl VirtioMem {
+        pub(crate) fn set_queue(&mut self, idx: usize, q: Queue) {
+            self.queues[idx] = q;
+        }
+    }
+
+    pub(crate) fn default_virtio_mem() -> VirtioMem {
+        VirtioMem::new(
+            GuestAddress(REGION_START),
+            NB_BLOCKS * BLOCK_SIZE,
+            BLOCK_SIZE,
+        )
+        .unwrap()
+    }
+
+    fn test_mem() -> GuestMemoryMmap {
+        multi_region_mem(&[
+            (GuestAddress(0), mib_to_bytes(2)),
+            (
+                GuestAddress(REGION_START),
+                u64_to_usize(NB_BLOCKS * BLOCK_SIZE),
+            ),
+        ])
+    }
+
+    fn send_request(
+        mem_dev: &mut VirtioMem,
+        queue: &VirtQueue,
+        req_type: u16,
+        addr: u64,
+        nb_blocks: u16,
+    ) -> Response {
+        let mem = queue.memory();
+        let request = Request {
+            req_type,
+            addr,
+            nb_blocks,
+            ..Default::default()
+        };
+        mem.write_obj(request, GuestAddress(REQ_ADDR)).unwrap();
+
+        let idx = queue.avail.idx.get();
+        queue.dtable[0].set(
+            REQ_ADDR,
+            u32::try_from(SIZE_OF_REQUEST).unwrap(),
+            VIRTQ_DESC_F_NEXT,
+            1,
+        );
+        queue.dtable[1].set(
+            RESP_ADDR,
+            u32::try_from(SIZE_OF_RESPONSE).unwrap(),
+            VIRTQ_DESC_F_WRITE,
+            0,
+        );
+        queue.avail.ring[usize::from(idx) % usize::from(queue.size())].set(0);
+        queue.avail.idx.set(idx + 1);
+
+        mem_dev.queue_events[MEM_QUEUE].write(1).unwrap();
+        mem_dev.process_mem_queue_event();
+        assert_eq!(queue.used.idx.get(), idx + 1);
+
+        mem.read_obj(GuestAddress(RESP_ADDR)).unwrap()
+    }
+
+    fn activated_device(mem: &GuestMemoryMmap, queue: &VirtQueue) -> VirtioMem {
+        let mut mem_dev = default_virtio_mem();
+        mem_dev.set_queue(MEM_QUEUE, queue.create_queue());
+        mem_dev.activate(mem.clone(), default_interrupt()).unwrap();
+        mem_dev
+    }
+
+    #[test]
+    fn test_struct_sizes() {
+        assert_eq!(
+            std::mem::size_of::<ConfigSpace>(),
+            super::super::MEM_CONFIG_SPACE_SIZE
+        );
+        assert_eq!(SIZE_OF_REQUEST, 24);
+        assert_eq!(SIZE_OF_RESPONSE, 10);
+    }
+
+    #[test]
+    fn test_new() {
+        let mem_dev = default_virtio_mem();
+        assert_eq!(mem_dev.id(), MEM_DEV_ID);
+        assert_eq!(mem_dev.device_type(), VIRTIO_ID_MEM);
+        assert_eq!(mem_dev.avail_features(), 1 << VIRTIO_F_VERSION_1);
+        assert_eq!(mem_dev.addr(), GuestAddress(REGION_START));
+        assert_eq!(mem_dev.region_size(), NB_BLOCKS * BLOCK_SIZE);
+        assert_eq!(mem_dev.block_size(), BLOCK_SIZE);
+        assert_eq!(mem_dev.plugged_size(), 0);
+        assert_eq!(mem_dev.requested_size(), 0);
+        assert!(!mem_dev.is_activated());
+
+        assert!(matches!(
+            VirtioMem::new(GuestAddress(REGION_START), BLOCK_SIZE, 3 << 20),
+            Err(VirtioMemError::InvalidBlockSize(_))
+        ));
+        assert!(matches!(
+            VirtioMem::new(GuestAddress(REGION_START), 0, BLOCK_SIZE),
+            Err(VirtioMemError::InvalidRegionSize(0))
+        ));
+        assert!(matches!(
+            VirtioMem::new(GuestAddress(REGION_START), BLOCK_SIZE + 1, BLOCK_SIZE),
+            Err(VirtioMemError::InvalidRegionSize(_))
+        ));
+    }
+
+    #[test]
+    fn test_read_write_config() {
+        let mut mem_dev = default_virtio_mem();
+        mem_dev.update_requested_size(2 * BLOCK_SIZE).unwrap();
+
+        let mut config = [0u8; 8];
+        // addr
+        mem_dev.read_config(16, &mut config);
+        assert_eq!(u64::from_le_bytes(config), REGION_START);
+        // requested_size
+        mem_dev.read_config(48, &mut config);
+        assert_eq!(u64::from_le_bytes(config), 2 * BLOCK_SIZE);
+
+        // The driver cannot change the config space.
+        mem_dev.write_config(48, &[0u8; 8]);
+        mem_dev.read_config(48, &mut config);
+        assert_eq!(u64::from_le_bytes(config), 2 * BLOCK_SIZE);
+
+        // Reads past the end of the config space do nothing.
+        let mut config = [0xffu8; 8];
+        mem_dev.read_config(1024, &mut config);
+        assert_eq!(config, [0xffu8; 8]);
+    }
+
+    #[test]
+    fn test_update_requested_size() {
+        let mem = test_mem();
+        let queue = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut mem_dev = activated_device(&mem, &queue);
+
+        mem_dev.update_requested_size(4 * BLOCK_SIZE).unwrap();
+        assert_eq!(mem_dev.requested_size(), 4 * BLOCK_SIZE);
+        assert!(
+            mem_dev
+                .interrupt_trigger()
+                .has_pending_interrupt(VirtioInterruptType::Config)
+        );
+
+        assert!(matches!(
+            mem_dev.update_requested_size(BLOCK_SIZE / 2),
+            Err(VirtioMemError::InvalidRequestedSize(_))
+        ));
+        assert!(matches!(
+            mem_dev.update_requested_size((NB_BLOCKS + 1) * BLOCK_SIZE),
+            Err(VirtioMemError::InvalidRequestedSize(_))
+        ));
+        assert_eq!(mem_dev.requested_size(), 4 * BLOCK_SIZE);
+    }
+
+    #[test]
+    fn test_plug_unplug() {
+        let mem = test_mem();
+        let queue = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut mem_dev = activated_device(&mem, &queue);
+
+        // Nothing can be plugged before the host asks for it.
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, REGION_START, 1);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_NACK);
+
+        mem_dev.update_requested_size(4 * BLOCK_SIZE).unwrap();
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, REGION_START, 2);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+        assert_eq!(mem_dev.plugged_size(), 2 * BLOCK_SIZE);
+
+        // Plugging already plugged blocks is an error.
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, REGION_START, 1);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ERROR);
+
+        // Plugging past the requested size is refused.
+        let addr = REGION_START + 2 * BLOCK_SIZE;
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, addr, 3);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_NACK);
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, addr, 2);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+        assert_eq!(mem_dev.plugged_size(), 4 * BLOCK_SIZE);
+
+        // Write to a plugged block, then unplug it.
+        mem.write_obj(0xdead_beef_u64, GuestAddress(REGION_START))
+            .unwrap();
+        mem_dev.update_requested_size(BLOCK_SIZE).unwrap();
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_UNPLUG, REGION_START, 1);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+        assert_eq!(mem_dev.plugged_size(), 3 * BLOCK_SIZE);
+        // Unplugged memory is given back to the host.
+        assert_eq!(mem.read_obj::<u64>(GuestAddress(REGION_START)).unwrap(), 0);
+
+        // Unplugging blocks that are not plugged is an error.
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_UNPLUG, REGION_START, 2);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ERROR);
+        assert_eq!(mem_dev.plugged_size(), 3 * BLOCK_SIZE);
+
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_UNPLUG_ALL, 0, 0);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+        assert_eq!(mem_dev.plugged_size(), 0);
+        assert!(mem_dev.plugged_blocks.iter().all(|&word| word == 0));
+    }
+
+    #[test]
+    fn test_state() {
+        let mem = test_mem();
+        let queue = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut mem_dev = activated_device(&mem, &queue);
+
+        mem_dev.update_requested_size(2 * BLOCK_SIZE).unwrap();
+        let addr = REGION_START + BLOCK_SIZE;
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_PLUG, addr, 2);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_STATE, addr, 2);
+        assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ACK);
+        assert_eq!(resp.state, VIRTIO_MEM_STATE_PLUGGED);
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_STATE, REGION_START, 2);
+        assert_eq!(resp.state, VIRTIO_MEM_STATE_MIXED);
+        let resp = send_request(&mut mem_dev, &queue, VIRTIO_MEM_REQ_STATE, REGION_START, 1);
+        assert_eq!(resp.state, VIRTIO_MEM_STATE_UNPLUGGED);
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        let mem = test_mem();
+        let queue = VirtQueue::new(GuestAddress(0), &mem, 16);
+        let mut mem_dev = activated_device(&mem, &queue);
+        mem_dev
+            .update_requested_size(NB_BLOCKS * BLOCK_SIZE)
+            .unwrap();
+
+        for (req_type, addr, nb_blocks) in [
+            // Unknown request type.
+            (42, REGION_START, 1),
+            // Address outside of the region.
+            (VIRTIO_MEM_REQ_PLUG, 0, 1),
+            // Address not aligned to the block size.
+            (VIRTIO_MEM_REQ_PLUG, REGION_START + 0x1000, 1),
+            // Empty range.
+            (VIRTIO_MEM_REQ_PLUG, REGION_START, 0),
+            // Range going past the end of the region.
+            (VIRTIO_MEM_REQ_PLUG, REGION_START + BLOCK_SIZE, 8),
+            (VIRTIO_MEM_REQ_UNPLUG, REGION_START, 9),
+            (VIRTIO_MEM_REQ_STATE, REGION_START, 9),
+        ] {
+            let resp = send_request(&mut mem_dev, &queue, req_type, addr, nb_blocks);
+            assert_eq!(resp.resp_type, VIRTIO_MEM_RESP_ERROR);
+        }
+        assert_eq!(mem_dev.plugged_size(), 0);
+
+        // A request without a response descriptor is dropped.
+        queue.dtable[0].set(REQ_ADDR, u32::try_from(SIZE_OF_REQUEST).unwrap(), 0, 0);
+        let idx = queue.avail.idx.get();
+        queue.avail.ring[usize::from(idx) % usize::from(queue.size())].set(0);
+        queue.avail.idx.set(idx + 1);
+        mem_dev.process_mem_queue().unwrap();
+        assert_eq!(queue.used.idx.get(), idx + 1);
+        queue.check_used_elem(idx, 0, 0);
+    }
+
+    #[test]
+    fn test_set_plugged_blocks() {
+        let mut mem_dev = default_virtio_mem();
+        mem_dev.set_plugged_blocks(&[0b1011]);
+        assert_eq!(mem_dev.plugged_size(), 3 * BLOCK_SIZE);
+        assert!(mem_dev.is_block_plugged(0));
+        assert!(!mem_dev.is_block_plugged(2));
+        assert!(mem_dev.is_block_plugged(3));
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the metrics system for the virtio-mem device.
+//!
+//! # Metrics format
+//! The metrics are flushed in JSON when requested by vmm::logger::metrics::METRICS.write().
+//!
+//! ## JSON example with metrics:
+//! ```json
+//!  "memory_hotplug": {
+//!     "activate_fails": "SharedIncMetric",
+//!     "event_fails": "SharedIncMetric",
+//!     "plug_count": "SharedIncMetric",
+//!     ...
+//!  }
+//! }
+//! ```
+//! Since there can only be one virtio-mem device per microVM, there are no per device metrics
+//! and `memory_hotplug` represents the aggregate metrics of the device.
+//!
+//! The system implements 1 type of metrics:
+//! * Shared Incremental Metrics (SharedIncMetrics) - dedicated for the metrics which need a counter
+//!   (i.e the number of times an API request failed). These metrics are reset upon flush.
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::logger::SharedIncMetric;
+
+/// Stores aggregated virtio-mem metrics
+pub(super) static METRICS: VirtioMemDeviceMetrics = VirtioMemDeviceMetrics::new();
+
+/// Called by METRICS.flush(), this function facilitates serialization of virtio-mem metrics.
+pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    let mut seq = serializer.serialize_map(Some(1))?;
+    seq.serialize_entry("memory_hotplug", &METRICS)?;
+    seq.end()
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct VirtioMemDeviceMetrics {
+    /// Number of device activation failures
+    pub activate_fails: SharedIncMetric,
+    /// Number of queue event handling failures
+    pub event_fails: SharedIncMetric,
+    /// Number of queue events handled
+    pub queue_event_count: SharedIncMetric,
+    /// Number of plug requests
+    pub plug_count: SharedIncMetric,
+    /// Number of failed plug requests
+    pub plug_fails: SharedIncMetric,
+    /// Number of unplug requests
+    pub unplug_count: SharedIncMetric,
+    /// Number of failed unplug and unplug all requests
+    pub unplug_fails: SharedIncMetric,
+    /// Number of unplug all requests
+    pub unplug_all_count: SharedIncMetric,
+    /// Number of state requests
+    pub state_count: SharedIncMetric,
+}
+impl VirtioMemDeviceMetrics {
+    /// Const default construction.
+    const fn new() -> Self {
+        Self {
+            activate_fails: SharedIncMetric::new(),
+            event_fails: SharedIncMetric::new(),
+            queue_event_count: SharedIncMetric::new(),
+            plug_count: SharedIncMetric::new(),
+            plug_fails: SharedIncMetric::new(),
+            unplug_count: SharedIncMetric::new(),
+            unplug_fails: SharedIncMetric::new(),
+            unplug_all_count: SharedIncMetric::new(),
+            state_count: SharedIncMetric::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::logger::IncMetric;
+
+    #[test]
+    fn test_virtio_mem_dev_metrics() {
+        let mem_metrics: VirtioMemDeviceMetrics = VirtioMemDeviceMetrics::new();
+        let mem_metrics_local: String = serde_json::to_string(&mem_metrics).unwrap();
+        // the 1st serialize flushes the metrics and resets values to 0 so that
+        // we can compare the values with local metrics.
+        serde_json::to_string(&METRICS).unwrap();
+        let mem_metrics_global: String = serde_json::to_string(&METRICS).unwrap();
+        assert_eq!(mem_metrics_local, mem_metrics_global);
+        mem_metrics.plug_count.inc();
+        assert_eq!(mem_metrics.plug_count.count(), 1);
+    }
+}
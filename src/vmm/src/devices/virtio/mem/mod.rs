@@ -0,0 +1,37 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Implements a virtio-mem device, used for hotplugging guest memory at runtime.
+
+pub mod device;
+mod event_handler;
+pub mod metrics;
+pub mod persist;
+
+pub use self::device::{VirtioMem, VirtioMemError};
+
+/// Device ID used in MMIO device identification.
+/// Because the virtio-mem device is unique per-vm, this ID can be hardcoded.
+pub const MEM_DEV_ID: &str = "mem";
+/// The size of the config space.
+pub const MEM_CONFIG_SPACE_SIZE: usize = 56;
+
+pub(crate) const MEM_NUM_QUEUES: usize = 1;
+
+pub(crate) const MEM_QUEUE: usize = 0;
+
+// Request types, as defined in the virtio specification v1.2, section 5.15.6.
+const VIRTIO_MEM_REQ_PLUG: u16 = 0;
+const VIRTIO_MEM_REQ_UNPLUG: u16 = 1;
+const VIRTIO_MEM_REQ_UNPLUG_ALL: u16 = 2;
+const VIRTIO_MEM_REQ_STATE: u16 = 3;
+
+// Response types.
+const VIRTIO_MEM_RESP_ACK: u16 = 0;
+const VIRTIO_MEM_RESP_NACK: u16 = 1;
+const VIRTIO_MEM_RESP_ERROR: u16 = 3;
+
+// Memory block states reported in response to a state request.
+const VIRTIO_MEM_STATE_PLUGGED: u16 = 0;
+const VIRTIO_MEM_STATE_UNPLUGGED: u16 = 1;
+const VIRTIO_MEM_STATE_MIXED: u16 = 2;
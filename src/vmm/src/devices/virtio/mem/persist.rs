@@ -0,0 +1,125 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the structures needed for saving/restoring virtio-mem devices.
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_MEM;
+use crate::devices::virtio::mem::{MEM_NUM_QUEUES, VirtioMem, VirtioMemError};
+use crate::devices::virtio::persist::{PersistError as VirtioStateError, VirtioDeviceState};
+use crate::devices::virtio::queue::FIRECRACKER_MAX_QUEUE_SIZE;
+use crate::snapshot::Persist;
+use crate::vstate::memory::{GuestAddress, GuestMemoryMmap};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VirtioMemState {
+    pub virtio_state: VirtioDeviceState,
+    addr: u64,
+    region_size: u64,
+    block_size: u64,
+    requested_size: u64,
+    plugged_blocks: Vec<u64>,
+}
+
+#[derive(Debug)]
+pub struct VirtioMemConstructorArgs {
+    pub mem: GuestMemoryMmap,
+    pub restored_from_file: bool,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum VirtioMemPersistError {
+    /// Create virtio-mem: {0}
+    CreateVirtioMem(#[from] VirtioMemError),
+    /// Virtio state: {0}
+    VirtioState(#[from] VirtioStateError),
+}
+
+impl Persist<'_> for VirtioMem {
+    type State = VirtioMemState;
+    type ConstructorArgs = VirtioMemConstructorArgs;
+    type Error = VirtioMemPersistError;
+
+    fn save(&self) -> Self::State {
+        VirtioMemState {
+            virtio_state: VirtioDeviceState::from_device(self),
+            addr: self.addr().0,
+            region_size: self.region_size(),
+            block_size: self.block_size(),
+            requested_size: self.requested_size(),
+            plugged_blocks: self.plugged_blocks.clone(),
+        }
+    }
+
+    fn restore(
+        constructor_args: Self::ConstructorArgs,
+        state: &Self::State,
+    ) -> Result<Self, Self::Error> {
+        let queues = state.virtio_state.build_queues_checked(
+            &constructor_args.mem,
+            VIRTIO_ID_MEM,
+            MEM_NUM_QUEUES,
+            FIRECRACKER_MAX_QUEUE_SIZE,
+        )?;
+
+        let mut virtio_mem = VirtioMem::new_with_queues(
+            queues,
+            GuestAddress(state.addr),
+            state.region_size,
+            state.block_size,
+            constructor_args.restored_from_file,
+        )?;
+        virtio_mem.set_avail_features(state.virtio_state.avail_features);
+        virtio_mem.set_acked_features(state.virtio_state.acked_features);
+        // The config interrupt is only sent on activation, which is handled by the caller.
+        virtio_mem.config_space.requested_size = state.requested_size;
+        virtio_mem.set_plugged_blocks(&state.plugged_blocks);
+
+        Ok(virtio_mem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::virtio::device::VirtioDevice;
+    use crate::devices::virtio::mem::MEM_DEV_ID;
+    use crate::devices::virtio::mem::device::tests::default_virtio_mem;
+    use crate::devices::virtio::test_utils::test::create_virtio_mem;
+    use crate::snapshot::Snapshot;
+
+    #[test]
+    fn test_persistence() {
+        let mut mem = vec![0u8; 4096];
+        let mut virtio_mem = default_virtio_mem();
+        virtio_mem
+            .update_requested_size(3 * virtio_mem.block_size())
+            .unwrap();
+        virtio_mem.set_plugged_blocks(&[0b101]);
+
+        Snapshot::new(virtio_mem.save())
+            .save(&mut mem.as_mut_slice())
+            .unwrap();
+
+        let restored = VirtioMem::restore(
+            VirtioMemConstructorArgs {
+                mem: create_virtio_mem(),
+                restored_from_file: false,
+            },
+            &Snapshot::load_without_crc_check(mem.as_slice())
+                .unwrap()
+                .data,
+        )
+        .unwrap();
+
+        assert_eq!(restored.device_type(), VIRTIO_ID_MEM);
+        assert_eq!(restored.id(), MEM_DEV_ID);
+        assert!(!restored.is_activated());
+        assert_eq!(restored.avail_features(), virtio_mem.avail_features());
+        assert_eq!(restored.acked_features(), virtio_mem.acked_features());
+        assert_eq!(restored.config_space, virtio_mem.config_space);
+        assert_eq!(restored.plugged_blocks, virtio_mem.plugged_blocks);
+        assert_eq!(restored.plugged_size(), 2 * virtio_mem.block_size());
+    }
+}
@@ -18,6 +18,7 @@ pub mod device;
 pub mod generated;
 mod iov_deque;
 pub mod iovec;
+pub mod mem;
 pub mod net;
 pub mod persist;
 pub mod queue;
@@ -61,6 +62,8 @@ pub enum ActivateError {
     VhostUser(vhost_user::VhostUserError),
     /// Setting tap interface offload flags failed: {0}
     TapSetOffload(TapError),
+    /// Attaching or detaching tap interface queues failed: {0}
+    TapSetQueue(TapError),
     /// Error setting pointers in the queue: (0)
     QueueMemoryError(QueueError),
 }
@@ -7,49 +7,79 @@
 
 use std::collections::VecDeque;
 use std::mem::{self};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::Wrapping;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use libc::{EAGAIN, iovec};
 use log::{error, info};
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 use vmm_sys_util::eventfd::EventFd;
 
-use super::NET_QUEUE_MAX_SIZE;
+use super::{NET_MAX_QUEUE_PAIRS, NET_QUEUE_MAX_SIZE, net_num_queues};
 use crate::devices::virtio::ActivateError;
 use crate::devices::virtio::device::{ActiveState, DeviceState, VirtioDevice};
 use crate::devices::virtio::generated::virtio_config::VIRTIO_F_VERSION_1;
 use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_NET;
 use crate::devices::virtio::generated::virtio_net::{
-    VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_TSO6,
-    VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO,
-    VIRTIO_NET_F_MAC, VIRTIO_NET_F_MRG_RXBUF, virtio_net_hdr_v1,
+    VIRTIO_NET_F_CSUM, VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4,
+    VIRTIO_NET_F_GUEST_TSO6, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4,
+    VIRTIO_NET_F_HOST_TSO6, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC, VIRTIO_NET_F_MQ,
+    VIRTIO_NET_F_MRG_RXBUF, virtio_net_hdr_v1,
 };
 use crate::devices::virtio::generated::virtio_ring::VIRTIO_RING_F_EVENT_IDX;
 use crate::devices::virtio::iovec::{
     IoVecBuffer, IoVecBufferMut, IoVecError, ParsedDescriptorChain,
 };
-use crate::devices::virtio::net::metrics::{NetDeviceMetrics, NetMetricsPerDevice};
+use crate::devices::virtio::net::metrics::{
+    NetDeviceMetrics, NetMetricsPerDevice, NetQueueMetrics,
+};
 use crate::devices::virtio::net::tap::Tap;
 use crate::devices::virtio::net::{
-    MAX_BUFFER_SIZE, NET_QUEUE_SIZES, NetError, NetQueue, RX_INDEX, TX_INDEX, generated,
+    MAX_BUFFER_SIZE, NetError, RX_INDEX, TX_INDEX, TapError, generated,
 };
 use crate::devices::virtio::queue::{DescriptorChain, InvalidAvailIdx, Queue};
 use crate::devices::virtio::transport::{VirtioInterrupt, VirtioInterruptType};
 use crate::devices::{DeviceError, report_net_event_fail};
-use crate::dumbo::pdu::arp::ETH_IPV4_FRAME_LEN;
 use crate::dumbo::pdu::ethernet::{EthernetFrame, PAYLOAD_OFFSET};
+use crate::dumbo::pdu::icmpv6::NDP_MESSAGE_HEADER_LEN;
+use crate::dumbo::pdu::ipv6;
 use crate::impl_device_type;
 use crate::logger::{IncMetric, METRICS};
 use crate::mmds::data_store::Mmds;
 use crate::mmds::ns::MmdsNetworkStack;
-use crate::rate_limiter::{BucketUpdate, RateLimiter, TokenType};
+use crate::rate_limiter::{BucketUpdate, RateLimiter, RateLimiterGroup, TokenType};
 use crate::utils::net::mac::MacAddr;
 use crate::utils::u64_to_usize;
-use crate::vstate::memory::{ByteValued, GuestMemoryMmap};
+use crate::vmm_config::RateLimiterConfig;
+use crate::vstate::memory::{ByteValued, Bytes, GuestMemoryMmap};
+
+const FRAME_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + ipv6::HEADER_LEN + NDP_MESSAGE_HEADER_LEN;
+
+// How often the MMDS requests waiting for the data store contents to change are handled again.
+const MMDS_TIMER_PERIOD: Duration = Duration::from_millis(100);
+
+// Control queue definitions, as per the virtio specification:
+// https://docs.oasis-open.org/virtio/virtio/v1.2/csd01/virtio-v1.2-csd01.html#x1-2250008
+const VIRTIO_NET_OK: u8 = 0;
+const VIRTIO_NET_ERR: u8 = 1;
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+// The largest control request we handle is a `VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET` one, so there is
+// no point in reading more than a few bytes of a request.
+const CTRL_REQUEST_MAX_LEN: usize = 64;
+
+// Index of the RX queue of the queue pair `pair` in the device queues/queue_evts vectors.
+const fn rx_queue_index(pair: usize) -> usize {
+    2 * pair + RX_INDEX
+}
 
-const FRAME_HEADER_MAX_LEN: usize = PAYLOAD_OFFSET + ETH_IPV4_FRAME_LEN;
+// Index of the TX queue of the queue pair `pair` in the device queues/queue_evts vectors.
+const fn tx_queue_index(pair: usize) -> usize {
+    2 * pair + TX_INDEX
+}
 
 pub(crate) const fn vnet_hdr_len() -> usize {
     mem::size_of::<virtio_net_hdr_v1>()
@@ -57,7 +87,8 @@ pub(crate) const fn vnet_hdr_len() -> usize {
 
 // This returns the maximum frame header length. This includes the VNET header plus
 // the maximum L2 frame header bytes which includes the ethernet frame header plus
-// the header IPv4 ARP header which is 28 bytes long.
+// the IPv6 header and the NDP message header (up to the target address), which are
+// 64 bytes long. This covers the 28 bytes long IPv4 ARP header as well.
 const fn frame_hdr_len() -> usize {
     vnet_hdr_len() + FRAME_HEADER_MAX_LEN
 }
@@ -90,6 +121,8 @@ fn init_vnet_hdr(buf: &mut [u8]) {
 #[repr(C)]
 pub struct ConfigSpace {
     pub guest_mac: MacAddr,
+    pub status: u16,
+    pub max_virtqueue_pairs: u16,
 }
 
 // SAFETY: `ConfigSpace` contains only PODs in `repr(C)` or `repr(transparent)`, without padding.
@@ -230,25 +263,58 @@ impl RxBuffers {
     }
 }
 
+/// A pair of RX and TX queues, along with the tap queue and rate limiters backing them.
+#[derive(Debug)]
+pub struct NetQueuePair {
+    /// The backend for this queue pair: a tap queue.
+    pub tap: Tap,
+    /// Whether the tap queue is attached to the tap interface.
+    pub(crate) tap_enabled: bool,
+
+    pub(crate) rx_rate_limiter: RateLimiter,
+    pub(crate) tx_rate_limiter: RateLimiter,
+
+    pub(crate) rx_buffer: RxBuffers,
+    pub(crate) metrics: Option<Arc<NetQueueMetrics>>,
+}
+
+impl NetQueuePair {
+    fn new(
+        tap: Tap,
+        rx_rate_limiter: RateLimiter,
+        tx_rate_limiter: RateLimiter,
+        metrics: Option<Arc<NetQueueMetrics>>,
+    ) -> Result<Self, NetError> {
+        Ok(NetQueuePair {
+            tap,
+            tap_enabled: true,
+            rx_rate_limiter,
+            tx_rate_limiter,
+            rx_buffer: RxBuffers::new()?,
+            metrics,
+        })
+    }
+}
+
 /// VirtIO network device.
 ///
 /// It emulates a network device able to exchange L2 frames between the guest
-/// and a host-side tap device.
+/// and a host-side tap device. Each RX/TX queue pair of the device is served
+/// by its own queue of the tap device.
 #[derive(Debug)]
 pub struct Net {
     pub(crate) id: String,
 
-    /// The backend for this device: a tap.
-    pub tap: Tap,
-
     pub(crate) avail_features: u64,
     pub(crate) acked_features: u64,
 
     pub(crate) queues: Vec<Queue>,
     pub(crate) queue_evts: Vec<EventFd>,
 
-    pub(crate) rx_rate_limiter: RateLimiter,
-    pub(crate) tx_rate_limiter: RateLimiter,
+    /// The RX/TX queue pairs of this device.
+    pub(crate) queue_pairs: Vec<NetQueuePair>,
+    /// The number of queue pairs the driver has enabled.
+    pub(crate) active_queue_pairs: u16,
 
     rx_frame_buf: [u8; MAX_BUFFER_SIZE],
 
@@ -263,21 +329,31 @@ pub struct Net {
     /// The MMDS stack corresponding to this interface.
     /// Only if MMDS transport has been associated with it.
     pub mmds_ns: Option<MmdsNetworkStack>,
+    /// Periodic timer which is armed while some MMDS requests are waiting for the data store
+    /// contents to change.
+    pub(crate) mmds_timer: TimerFd,
     pub(crate) metrics: Arc<NetDeviceMetrics>,
 
     tx_buffer: IoVecBuffer,
-    pub(crate) rx_buffer: RxBuffers,
 }
 
 impl Net {
-    /// Create a new virtio network device with the given TAP interface.
-    pub fn new_with_tap(
+    /// Create a new virtio network device with the given TAP queues, one per RX/TX queue pair.
+    ///
+    /// `rate_limiters` holds the RX and TX rate limiters of every queue pair.
+    pub fn new_with_taps(
         id: String,
-        tap: Tap,
+        taps: Vec<Tap>,
         guest_mac: Option<MacAddr>,
-        rx_rate_limiter: RateLimiter,
-        tx_rate_limiter: RateLimiter,
+        rate_limiters: Vec<(RateLimiter, RateLimiter)>,
     ) -> Result<Self, NetError> {
+        let num_queue_pairs = u16::try_from(taps.len()).unwrap_or(u16::MAX);
+        if !(1..=NET_MAX_QUEUE_PAIRS).contains(&num_queue_pairs)
+            || rate_limiters.len() != taps.len()
+        {
+            return Err(NetError::InvalidQueuePairs(num_queue_pairs));
+        }
+
         let mut avail_features = (1 << VIRTIO_NET_F_GUEST_CSUM)
             | (1 << VIRTIO_NET_F_CSUM)
             | (1 << VIRTIO_NET_F_GUEST_TSO4)
@@ -298,22 +374,42 @@ impl Net {
             avail_features |= 1 << VIRTIO_NET_F_MAC;
         }
 
+        if num_queue_pairs > 1 {
+            // The driver selects how many queue pairs it uses through the control queue.
+            avail_features |= (1 << VIRTIO_NET_F_MQ) | (1 << VIRTIO_NET_F_CTRL_VQ);
+            config_space.max_virtqueue_pairs = num_queue_pairs;
+        }
+
         let mut queue_evts = Vec::new();
         let mut queues = Vec::new();
-        for size in NET_QUEUE_SIZES {
+        for _ in 0..net_num_queues(num_queue_pairs) {
             queue_evts.push(EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?);
-            queues.push(Queue::new(size));
+            queues.push(Queue::new(NET_QUEUE_MAX_SIZE));
+        }
+
+        let mut queue_pairs = Vec::with_capacity(taps.len());
+        for (index, (tap, (rx_rate_limiter, tx_rate_limiter))) in
+            taps.into_iter().zip(rate_limiters).enumerate()
+        {
+            // Devices with a single queue pair only report the per device metrics.
+            let metrics =
+                (num_queue_pairs > 1).then(|| NetMetricsPerDevice::alloc_queue(&id, index));
+            queue_pairs.push(NetQueuePair::new(
+                tap,
+                rx_rate_limiter,
+                tx_rate_limiter,
+                metrics,
+            )?);
         }
 
         Ok(Net {
             id: id.clone(),
-            tap,
             avail_features,
             acked_features: 0u64,
             queues,
             queue_evts,
-            rx_rate_limiter,
-            tx_rate_limiter,
+            queue_pairs,
+            active_queue_pairs: 1,
             rx_frame_buf: [0u8; MAX_BUFFER_SIZE],
             tx_frame_headers: [0u8; frame_hdr_len()],
             config_space,
@@ -321,27 +417,52 @@ impl Net {
             device_state: DeviceState::Inactive,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(NetError::EventFd)?,
             mmds_ns: None,
+            // The timer is created even if the device never serves MMDS requests, because the
+            // MMDS can be configured later, when the timer could be seccomp-blocked from being
+            // created.
+            mmds_timer: TimerFd::new_custom(ClockId::Monotonic, true, true)
+                .map_err(NetError::MmdsTimer)?,
             metrics: NetMetricsPerDevice::alloc(id),
             tx_buffer: Default::default(),
-            rx_buffer: RxBuffers::new()?,
         })
     }
 
     /// Create a new virtio network device given the interface name.
+    ///
+    /// Every one of the `num_queue_pairs` RX/TX queue pairs is rate limited independently,
+    /// using the configuration of `rx_rate_limiter` and `tx_rate_limiter`.
     pub fn new(
         id: String,
         tap_if_name: &str,
         guest_mac: Option<MacAddr>,
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
+        num_queue_pairs: u16,
     ) -> Result<Self, NetError> {
-        let tap = Tap::open_named(tap_if_name).map_err(NetError::TapOpen)?;
+        if !(1..=NET_MAX_QUEUE_PAIRS).contains(&num_queue_pairs) {
+            return Err(NetError::InvalidQueuePairs(num_queue_pairs));
+        }
+
+        let taps =
+            Tap::open_multi_queue(tap_if_name, num_queue_pairs).map_err(NetError::TapOpen)?;
 
         let vnet_hdr_size = i32::try_from(vnet_hdr_len()).unwrap();
-        tap.set_vnet_hdr_size(vnet_hdr_size)
-            .map_err(NetError::TapSetVnetHdrSize)?;
+        for tap in &taps {
+            tap.set_vnet_hdr_size(vnet_hdr_size)
+                .map_err(NetError::TapSetVnetHdrSize)?;
+        }
+
+        let rx_config = RateLimiterConfig::from(&rx_rate_limiter);
+        let tx_config = RateLimiterConfig::from(&tx_rate_limiter);
+        let mut rate_limiters = vec![(rx_rate_limiter, tx_rate_limiter)];
+        for _ in 1..num_queue_pairs {
+            rate_limiters.push((
+                rx_config.try_into().map_err(NetError::RateLimiter)?,
+                tx_config.try_into().map_err(NetError::RateLimiter)?,
+            ));
+        }
 
-        Self::new_with_tap(id, tap, guest_mac, rx_rate_limiter, tx_rate_limiter)
+        Self::new_with_taps(id, taps, guest_mac, rate_limiters)
     }
 
     /// Provides the ID of this net device.
@@ -356,7 +477,13 @@ impl Net {
 
     /// Provides the host IFACE name of this net device.
     pub fn iface_name(&self) -> String {
-        self.tap.if_name_as_str().to_string()
+        self.queue_pairs[0].tap.if_name_as_str().to_string()
+    }
+
+    /// Provides the number of RX/TX queue pairs of this net device.
+    pub fn num_queue_pairs(&self) -> u16 {
+        // The number of queue pairs is validated at construction time.
+        u16::try_from(self.queue_pairs.len()).unwrap()
     }
 
     /// Provides the MmdsNetworkStack of this net device.
@@ -365,13 +492,18 @@ impl Net {
     }
 
     /// Configures the `MmdsNetworkStack` to allow device to forward MMDS requests.
-    /// If the device already supports MMDS, updates the IPv4 address.
-    pub fn configure_mmds_network_stack(&mut self, ipv4_addr: Ipv4Addr, mmds: Arc<Mutex<Mmds>>) {
-        if let Some(mmds_ns) = self.mmds_ns.as_mut() {
-            mmds_ns.set_ipv4_addr(ipv4_addr);
-        } else {
-            self.mmds_ns = Some(MmdsNetworkStack::new_with_defaults(Some(ipv4_addr), mmds))
-        }
+    /// If the device already supports MMDS, updates the IP addresses.
+    pub fn configure_mmds_network_stack(
+        &mut self,
+        ipv4_addr: Ipv4Addr,
+        ipv6_addr: Option<Ipv6Addr>,
+        mmds: Arc<Mutex<Mmds>>,
+    ) {
+        let mmds_ns = self
+            .mmds_ns
+            .get_or_insert_with(|| MmdsNetworkStack::new_with_defaults(Some(ipv4_addr), mmds));
+        mmds_ns.set_ipv4_addr(ipv4_addr);
+        mmds_ns.set_ipv6_addr(ipv6_addr);
     }
 
     /// Disables the `MmdsNetworkStack` to prevent device to forward MMDS requests.
@@ -380,24 +512,49 @@ impl Net {
     }
 
     /// Provides a reference to the configured RX rate limiter.
+    ///
+    /// All queue pairs share the same rate limiter configuration, so this is the RX rate
+    /// limiter of the first queue pair.
     pub fn rx_rate_limiter(&self) -> &RateLimiter {
-        &self.rx_rate_limiter
+        &self.queue_pairs[0].rx_rate_limiter
     }
 
     /// Provides a reference to the configured TX rate limiter.
+    ///
+    /// All queue pairs share the same rate limiter configuration, so this is the TX rate
+    /// limiter of the first queue pair.
     pub fn tx_rate_limiter(&self) -> &RateLimiter {
-        &self.tx_rate_limiter
+        &self.queue_pairs[0].tx_rate_limiter
+    }
+
+    /// Makes the RX and TX rate limiters of every queue pair draw from the provided rate limiter
+    /// groups. The rate limiters are left unchanged for the directions without a group.
+    pub fn set_rate_limiter_groups(
+        &mut self,
+        rx_group: Option<Arc<Mutex<RateLimiterGroup>>>,
+        tx_group: Option<Arc<Mutex<RateLimiterGroup>>>,
+    ) -> std::io::Result<()> {
+        for queue_pair in &mut self.queue_pairs {
+            if let Some(group) = &rx_group {
+                queue_pair.rx_rate_limiter = RateLimiter::with_group(group.clone())?;
+            }
+            if let Some(group) = &tx_group {
+                queue_pair.tx_rate_limiter = RateLimiter::with_group(group.clone())?;
+            }
+        }
+        Ok(())
+    }
+
+    // Index of the control queue, present only on devices with more than one queue pair.
+    fn ctrl_queue_index(&self) -> usize {
+        2 * self.queue_pairs.len()
     }
 
     /// Trigger queue notification for the guest if we used enough descriptors
     /// for the notification to be enabled.
     /// https://docs.oasis-open.org/virtio/virtio/v1.1/csprd01/virtio-v1.1-csprd01.html#x1-320005
     /// 2.6.7.1 Driver Requirements: Used Buffer Notification Suppression
-    fn try_signal_queue(&mut self, queue_type: NetQueue) -> Result<(), DeviceError> {
-        let qidx = match queue_type {
-            NetQueue::Rx => RX_INDEX,
-            NetQueue::Tx => TX_INDEX,
-        };
+    fn try_signal_queue(&mut self, qidx: usize) -> Result<(), DeviceError> {
         self.queues[qidx].advance_used_ring_idx();
 
         if self.queues[qidx].prepare_kick() {
@@ -432,17 +589,21 @@ impl Net {
         rate_limiter.manual_replenish(size, TokenType::Bytes);
     }
 
-    // Attempts to copy a single frame into the guest if there is enough
-    // rate limiting budget.
+    // Attempts to copy a single frame into the guest, on the RX queue of the queue pair `pair`,
+    // if there is enough rate limiting budget.
     // Returns true on successful frame delivery.
-    pub fn rate_limited_rx_single_frame(&mut self, frame_size: u32) -> bool {
-        let rx_queue = &mut self.queues[RX_INDEX];
-        if !Self::rate_limiter_consume_op(&mut self.rx_rate_limiter, frame_size as u64) {
+    pub fn rate_limited_rx_single_frame(&mut self, pair: usize, frame_size: u32) -> bool {
+        let rx_queue = &mut self.queues[rx_queue_index(pair)];
+        let queue_pair = &mut self.queue_pairs[pair];
+        if !Self::rate_limiter_consume_op(&mut queue_pair.rx_rate_limiter, frame_size as u64) {
             self.metrics.rx_rate_limiter_throttled.inc();
+            if let Some(metrics) = &queue_pair.metrics {
+                metrics.rx_rate_limiter_throttled.inc();
+            }
             return false;
         }
 
-        self.rx_buffer.finish_frame(rx_queue);
+        queue_pair.rx_buffer.finish_frame(rx_queue);
         true
     }
 
@@ -463,15 +624,16 @@ impl Net {
         }
     }
 
-    /// Parse available RX `DescriptorChains` from the queue
-    pub fn parse_rx_descriptors(&mut self) -> Result<(), InvalidAvailIdx> {
+    /// Parse available RX `DescriptorChains` from the RX queue of the queue pair `pair`
+    pub fn parse_rx_descriptors(&mut self, pair: usize) -> Result<(), InvalidAvailIdx> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = &self.device_state.active_state().unwrap().mem;
-        let queue = &mut self.queues[RX_INDEX];
+        let queue = &mut self.queues[rx_queue_index(pair)];
+        let rx_buffer = &mut self.queue_pairs[pair].rx_buffer;
         while let Some(head) = queue.pop_or_enable_notification()? {
             let index = head.index;
             // SAFETY: we are only using this `DescriptorChain` here.
-            if let Err(err) = unsafe { self.rx_buffer.add_buffer(mem, head) } {
+            if let Err(err) = unsafe { rx_buffer.add_buffer(mem, head) } {
                 self.metrics.rx_fails.inc();
 
                 // If guest uses dirty tricks to make us add more descriptors than
@@ -489,24 +651,24 @@ impl Net {
                 // SAFETY:
                 // index is verified on `DescriptorChain` creation.
                 queue
-                    .write_used_element(self.rx_buffer.used_descriptors, index, 0)
+                    .write_used_element(rx_buffer.used_descriptors, index, 0)
                     .unwrap();
-                self.rx_buffer.used_descriptors += 1;
+                rx_buffer.used_descriptors += 1;
             }
         }
 
         Ok(())
     }
 
-    // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP.
+    // Tries to detour the frame to MMDS and if MMDS doesn't accept it, sends it on the host TAP
+    // of the queue pair.
     //
     // Returns whether MMDS consumed the frame.
     fn write_to_mmds_or_tap(
         mmds_ns: Option<&mut MmdsNetworkStack>,
-        rate_limiter: &mut RateLimiter,
         headers: &mut [u8],
         frame_iovec: &IoVecBuffer,
-        tap: &mut Tap,
+        queue_pair: &mut NetQueuePair,
         guest_mac: Option<MacAddr>,
         net_metrics: &NetDeviceMetrics,
     ) -> Result<bool, NetError> {
@@ -537,7 +699,10 @@ impl Net {
                 METRICS.mmds.rx_accepted.inc();
 
                 // MMDS frames are not accounted by the rate limiter.
-                Self::rate_limiter_replenish_op(rate_limiter, u64::from(frame_iovec.len()));
+                Self::rate_limiter_replenish_op(
+                    &mut queue_pair.tx_rate_limiter,
+                    u64::from(frame_iovec.len()),
+                );
 
                 // MMDS consumed the frame.
                 return Ok(true);
@@ -556,12 +721,16 @@ impl Net {
         }
 
         let _metric = net_metrics.tap_write_agg.record_latency_metrics();
-        match Self::write_tap(tap, frame_iovec) {
+        match Self::write_tap(&mut queue_pair.tap, frame_iovec) {
             Ok(_) => {
                 let len = u64::from(frame_iovec.len());
                 net_metrics.tx_bytes_count.add(len);
                 net_metrics.tx_packets_count.inc();
                 net_metrics.tx_count.inc();
+                if let Some(queue_metrics) = &queue_pair.metrics {
+                    queue_metrics.tx_bytes_count.add(len);
+                    queue_metrics.tx_packets_count.inc();
+                }
             }
             Err(err) => {
                 error!("Failed to write to tap: {:?}", err);
@@ -572,22 +741,24 @@ impl Net {
     }
 
     // We currently prioritize packets from the MMDS over regular network packets.
-    fn read_from_mmds_or_tap(&mut self) -> Result<Option<u32>, NetError> {
+    fn read_from_mmds_or_tap(&mut self, pair: usize) -> Result<Option<u32>, NetError> {
         // We only want to read from TAP (or mmds) if we have at least 64K of available capacity as
         // this is the max size of 1 packet.
         // SAFETY:
         // * MAX_BUFFER_SIZE is constant and fits into u32
         #[allow(clippy::cast_possible_truncation)]
-        if self.rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
-            self.parse_rx_descriptors()?;
+        if self.queue_pairs[pair].rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
+            self.parse_rx_descriptors(pair)?;
 
             // If after parsing the RX queue we still don't have enough capacity, stop processing RX
             // frames.
-            if self.rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
+            if self.queue_pairs[pair].rx_buffer.capacity() < MAX_BUFFER_SIZE as u32 {
                 return Ok(None);
             }
         }
 
+        let rx_queue = &mut self.queues[rx_queue_index(pair)];
+        let rx_buffer = &mut self.queue_pairs[pair].rx_buffer;
         if let Some(ns) = self.mmds_ns.as_mut() {
             if let Some(len) =
                 ns.write_next_frame(frame_bytes_from_buf_mut(&mut self.rx_frame_buf)?)
@@ -596,7 +767,7 @@ impl Net {
                 METRICS.mmds.tx_frames.inc();
                 METRICS.mmds.tx_bytes.add(len as u64);
                 init_vnet_hdr(&mut self.rx_frame_buf);
-                self.rx_buffer
+                rx_buffer
                     .iovec
                     .write_all_volatile_at(&self.rx_frame_buf[..vnet_hdr_len() + len], 0)?;
                 // SAFETY:
@@ -609,15 +780,16 @@ impl Net {
                 // * `rx_frame_buf` has size of `MAX_BUFFER_SIZE` and all `DescriptorChain` objects
                 //   are at least that big.
                 unsafe {
-                    self.rx_buffer.mark_used(len, &mut self.queues[RX_INDEX]);
+                    rx_buffer.mark_used(len, rx_queue);
                 }
                 return Ok(Some(len));
             }
         }
 
         // SAFETY:
-        // * We ensured that `self.rx_buffer` has at least one DescriptorChain parsed in it.
-        let len = unsafe { self.read_tap().map_err(NetError::IO) }?;
+        // * We ensured that the `rx_buffer` of the queue pair has at least one DescriptorChain
+        //   parsed in it.
+        let len = unsafe { self.read_tap(pair).map_err(NetError::IO) }?;
         // SAFETY:
         // * len will never be bigger that u32::MAX
         let len: u32 = len.try_into().unwrap();
@@ -627,15 +799,17 @@ impl Net {
         // * `read_tap` passes the first `DescriptorChain` to `readv` so we can't have read more
         //   bytes than its capacity.
         unsafe {
-            self.rx_buffer.mark_used(len, &mut self.queues[RX_INDEX]);
+            self.queue_pairs[pair]
+                .rx_buffer
+                .mark_used(len, &mut self.queues[rx_queue_index(pair)]);
         }
         Ok(Some(len))
     }
 
-    /// Read as many frames as possible.
-    fn process_rx(&mut self) -> Result<(), DeviceError> {
+    /// Read as many frames as possible on the queue pair `pair`.
+    fn process_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
         loop {
-            match self.read_from_mmds_or_tap() {
+            match self.read_from_mmds_or_tap(pair) {
                 Ok(None) => {
                     self.metrics.no_rx_avail_buffer.inc();
                     break;
@@ -644,7 +818,11 @@ impl Net {
                     self.metrics.rx_count.inc();
                     self.metrics.rx_bytes_count.add(bytes as u64);
                     self.metrics.rx_packets_count.inc();
-                    if !self.rate_limited_rx_single_frame(bytes) {
+                    if let Some(metrics) = &self.queue_pairs[pair].metrics {
+                        metrics.rx_bytes_count.add(bytes as u64);
+                        metrics.rx_packets_count.inc();
+                    }
+                    if !self.rate_limited_rx_single_frame(pair, bytes) {
                         break;
                     }
                 }
@@ -670,23 +848,29 @@ impl Net {
             }
         }
 
-        self.try_signal_queue(NetQueue::Rx)
+        self.try_signal_queue(rx_queue_index(pair))
     }
 
-    fn resume_rx(&mut self) -> Result<(), DeviceError> {
+    fn resume_rx(&mut self, pair: usize) -> Result<(), DeviceError> {
+        // A detached tap queue doesn't receive any frames.
+        if !self.queue_pairs[pair].tap_enabled {
+            return Ok(());
+        }
+
         // First try to handle any deferred frame
-        if self.rx_buffer.used_bytes != 0 {
+        let used_bytes = self.queue_pairs[pair].rx_buffer.used_bytes;
+        if used_bytes != 0 {
             // If can't finish sending this frame, re-set it as deferred and return; we can't
             // process any more frames from the TAP.
-            if !self.rate_limited_rx_single_frame(self.rx_buffer.used_bytes) {
+            if !self.rate_limited_rx_single_frame(pair, used_bytes) {
                 return Ok(());
             }
         }
 
-        self.process_rx()
+        self.process_rx(pair)
     }
 
-    fn process_tx(&mut self) -> Result<(), DeviceError> {
+    fn process_tx(&mut self, pair: usize) -> Result<(), DeviceError> {
         // This is safe since we checked in the event handler that the device is activated.
         let mem = &self.device_state.active_state().unwrap().mem;
 
@@ -695,8 +879,13 @@ impl Net {
         // trigger a process_rx() which checks if there are any new frames to be sent, starting
         // with the MMDS network stack.
         let mut process_rx_for_mmds = false;
+        let mmds_was_waiting = self
+            .mmds_ns
+            .as_ref()
+            .is_some_and(MmdsNetworkStack::has_waiting_requests);
         let mut used_any = false;
-        let tx_queue = &mut self.queues[TX_INDEX];
+        let tx_queue = &mut self.queues[tx_queue_index(pair)];
+        let queue_pair = &mut self.queue_pairs[pair];
 
         while let Some(head) = tx_queue.pop_or_enable_notification()? {
             self.metrics
@@ -722,25 +911,27 @@ impl Net {
             }
 
             if !Self::rate_limiter_consume_op(
-                &mut self.tx_rate_limiter,
+                &mut queue_pair.tx_rate_limiter,
                 u64::from(self.tx_buffer.len()),
             ) {
                 tx_queue.undo_pop();
                 self.metrics.tx_rate_limiter_throttled.inc();
+                if let Some(metrics) = &queue_pair.metrics {
+                    metrics.tx_rate_limiter_throttled.inc();
+                }
                 break;
             }
 
             let frame_consumed_by_mmds = Self::write_to_mmds_or_tap(
                 self.mmds_ns.as_mut(),
-                &mut self.tx_rate_limiter,
                 &mut self.tx_frame_headers,
                 &self.tx_buffer,
-                &mut self.tap,
+                queue_pair,
                 self.guest_mac,
                 &self.metrics,
             )
             .unwrap_or(false);
-            if frame_consumed_by_mmds && self.rx_buffer.used_bytes == 0 {
+            if frame_consumed_by_mmds && queue_pair.rx_buffer.used_bytes == 0 {
                 // MMDS consumed this frame/request, let's also try to process the response.
                 process_rx_for_mmds = true;
             }
@@ -755,16 +946,140 @@ impl Net {
 
         // Cleanup tx_buffer to ensure no two buffers point at the same memory
         self.tx_buffer.clear();
-        self.try_signal_queue(NetQueue::Tx)?;
+        self.try_signal_queue(tx_queue_index(pair))?;
+
+        // Requests waiting for the MMDS data store contents to change are handled again
+        // periodically, until they stop waiting.
+        if !mmds_was_waiting
+            && self
+                .mmds_ns
+                .as_ref()
+                .is_some_and(MmdsNetworkStack::has_waiting_requests)
+        {
+            self.mmds_timer.set_state(
+                TimerState::Periodic {
+                    current: MMDS_TIMER_PERIOD,
+                    interval: MMDS_TIMER_PERIOD,
+                },
+                SetTimeFlags::Default,
+            );
+        }
 
         // An incoming frame for the MMDS may trigger the transmission of a new message.
         if process_rx_for_mmds {
-            self.process_rx()
+            self.process_rx(pair)
         } else {
             Ok(())
         }
     }
 
+    /// Attaches the tap queues of the first `active_queue_pairs` queue pairs and detaches the
+    /// remaining ones, so that the host only steers traffic to the queues the driver uses.
+    pub(crate) fn set_active_queue_pairs(
+        &mut self,
+        active_queue_pairs: u16,
+    ) -> Result<(), TapError> {
+        for (index, queue_pair) in self.queue_pairs.iter_mut().enumerate() {
+            let enabled = index < usize::from(active_queue_pairs);
+            if queue_pair.tap_enabled != enabled {
+                queue_pair.tap.set_queue_enabled(enabled)?;
+                queue_pair.tap_enabled = enabled;
+            }
+        }
+        self.active_queue_pairs = active_queue_pairs;
+
+        Ok(())
+    }
+
+    /// Handles a single control queue request, returning the ack to report to the driver.
+    fn handle_ctrl_request(&mut self, request: &[u8]) -> u8 {
+        match request {
+            [
+                VIRTIO_NET_CTRL_MQ,
+                VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET,
+                pairs @ ..,
+            ] => {
+                let Ok(pairs) = <[u8; 2]>::try_from(pairs).map(u16::from_le_bytes) else {
+                    error!("net: Malformed VQ_PAIRS_SET control request");
+                    return VIRTIO_NET_ERR;
+                };
+                if pairs == 0 || usize::from(pairs) > self.queue_pairs.len() {
+                    error!("net: Invalid number of queue pairs requested: {pairs}");
+                    return VIRTIO_NET_ERR;
+                }
+                match self.set_active_queue_pairs(pairs) {
+                    Ok(()) => VIRTIO_NET_OK,
+                    Err(err) => {
+                        error!("net: Failed to enable {pairs} queue pairs: {err}");
+                        VIRTIO_NET_ERR
+                    }
+                }
+            }
+            [class, command, ..] => {
+                info!("net: Unsupported control request: class {class}, command {command}");
+                VIRTIO_NET_ERR
+            }
+            _ => {
+                error!("net: Malformed control request");
+                VIRTIO_NET_ERR
+            }
+        }
+    }
+
+    fn process_ctrl_queue(&mut self) -> Result<(), DeviceError> {
+        // This is safe since we checked in the event handler that the device is activated.
+        let mem = self.device_state.active_state().unwrap().mem.clone();
+        let ctrl_queue_index = self.ctrl_queue_index();
+
+        while let Some(head) = self.queues[ctrl_queue_index].pop_or_enable_notification()? {
+            let head_index = head.index;
+
+            // The request is made of the driver readable descriptors, followed by a device
+            // writable descriptor for the ack.
+            let mut request = Vec::new();
+            let mut ack_addr = None;
+            let mut malformed = false;
+            let mut next_desc = Some(head);
+            while let Some(desc) = next_desc {
+                if desc.is_write_only() {
+                    ack_addr = Some(desc.addr);
+                    break;
+                }
+                let start = request.len();
+                let len = desc.len as usize;
+                if start + len > CTRL_REQUEST_MAX_LEN {
+                    malformed = true;
+                    break;
+                }
+                request.resize(start + len, 0);
+                if mem.read_slice(&mut request[start..], desc.addr).is_err() {
+                    malformed = true;
+                    break;
+                }
+                next_desc = desc.next_descriptor();
+            }
+
+            let ack = if malformed {
+                error!("net: Malformed control request");
+                VIRTIO_NET_ERR
+            } else {
+                self.handle_ctrl_request(&request)
+            };
+
+            let used_len = match ack_addr {
+                Some(addr) if mem.write_obj(ack, addr).is_ok() => 1,
+                _ => {
+                    error!("net: Failed to write the control request ack");
+                    self.metrics.event_fails.inc();
+                    0
+                }
+            };
+            self.queues[ctrl_queue_index].add_used(head_index, used_len)?;
+        }
+
+        self.try_signal_queue(ctrl_queue_index)
+    }
+
     /// Builds the offload features we will setup on the TAP device based on the features that the
     /// guest supports.
     pub fn build_tap_offload_features(guest_supported_features: u64) -> u32 {
@@ -805,7 +1120,7 @@ impl Net {
         tap_features
     }
 
-    /// Updates the parameters for the rate limiters
+    /// Updates the parameters for the rate limiters of all queue pairs
     pub fn patch_rate_limiters(
         &mut self,
         rx_bytes: BucketUpdate,
@@ -813,22 +1128,31 @@ impl Net {
         tx_bytes: BucketUpdate,
         tx_ops: BucketUpdate,
     ) {
-        self.rx_rate_limiter.update_buckets(rx_bytes, rx_ops);
-        self.tx_rate_limiter.update_buckets(tx_bytes, tx_ops);
+        for queue_pair in self.queue_pairs.iter_mut() {
+            queue_pair
+                .rx_rate_limiter
+                .update_buckets(rx_bytes.clone(), rx_ops.clone());
+            queue_pair
+                .tx_rate_limiter
+                .update_buckets(tx_bytes.clone(), tx_ops.clone());
+        }
     }
 
-    /// Reads a frame from the TAP device inside the first descriptor held by `self.rx_buffer`.
+    /// Reads a frame from the TAP device of the queue pair `pair` inside the first descriptor
+    /// held by its `rx_buffer`.
     ///
     /// # Safety
     ///
-    /// `self.rx_buffer` needs to have at least one descriptor chain parsed
-    pub unsafe fn read_tap(&mut self) -> std::io::Result<usize> {
-        let slice = if self.has_feature(VIRTIO_NET_F_MRG_RXBUF as u64) {
-            self.rx_buffer.all_chains_slice_mut()
+    /// The `rx_buffer` of the queue pair needs to have at least one descriptor chain parsed
+    pub unsafe fn read_tap(&mut self, pair: usize) -> std::io::Result<usize> {
+        let mrg_rxbuf = self.has_feature(VIRTIO_NET_F_MRG_RXBUF as u64);
+        let queue_pair = &mut self.queue_pairs[pair];
+        let slice = if mrg_rxbuf {
+            queue_pair.rx_buffer.all_chains_slice_mut()
         } else {
-            self.rx_buffer.single_chain_slice_mut()
+            queue_pair.rx_buffer.single_chain_slice_mut()
         };
-        self.tap.read_iovec(slice)
+        queue_pair.tap.read_iovec(slice)
     }
 
     fn write_tap(tap: &mut Tap, buf: &IoVecBuffer) -> std::io::Result<usize> {
@@ -838,70 +1162,70 @@ impl Net {
     /// Process a single RX queue event.
     ///
     /// This is called by the event manager responding to the guest adding a new
-    /// buffer in the RX queue.
-    pub fn process_rx_queue_event(&mut self) {
+    /// buffer in the RX queue of the queue pair `pair`.
+    pub fn process_rx_queue_event(&mut self, pair: usize) {
         self.metrics.rx_queue_event_count.inc();
 
-        if let Err(err) = self.queue_evts[RX_INDEX].read() {
+        if let Err(err) = self.queue_evts[rx_queue_index(pair)].read() {
             // rate limiters present but with _very high_ allowed rate
             error!("Failed to get rx queue event: {:?}", err);
             self.metrics.event_fails.inc();
             return;
         } else {
-            self.parse_rx_descriptors().unwrap();
+            self.parse_rx_descriptors(pair).unwrap();
         }
 
-        if self.rx_rate_limiter.is_blocked() {
+        if self.queue_pairs[pair].rx_rate_limiter.is_blocked() {
             self.metrics.rx_rate_limiter_throttled.inc();
         } else {
             // If the limiter is not blocked, resume the receiving of bytes.
-            self.resume_rx()
+            self.resume_rx(pair)
                 .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
         }
     }
 
-    pub fn process_tap_rx_event(&mut self) {
+    pub fn process_tap_rx_event(&mut self, pair: usize) {
         // This is safe since we checked in the event handler that the device is activated.
         self.metrics.rx_tap_event_count.inc();
 
         // While limiter is blocked, don't process any more incoming.
-        if self.rx_rate_limiter.is_blocked() {
+        if self.queue_pairs[pair].rx_rate_limiter.is_blocked() {
             self.metrics.rx_rate_limiter_throttled.inc();
             return;
         }
 
-        self.resume_rx()
+        self.resume_rx(pair)
             .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
     }
 
     /// Process a single TX queue event.
     ///
     /// This is called by the event manager responding to the guest adding a new
-    /// buffer in the TX queue.
-    pub fn process_tx_queue_event(&mut self) {
+    /// buffer in the TX queue of the queue pair `pair`.
+    pub fn process_tx_queue_event(&mut self, pair: usize) {
         self.metrics.tx_queue_event_count.inc();
-        if let Err(err) = self.queue_evts[TX_INDEX].read() {
+        if let Err(err) = self.queue_evts[tx_queue_index(pair)].read() {
             error!("Failed to get tx queue event: {:?}", err);
             self.metrics.event_fails.inc();
-        } else if !self.tx_rate_limiter.is_blocked()
+        } else if !self.queue_pairs[pair].tx_rate_limiter.is_blocked()
         // If the limiter is not blocked, continue transmitting bytes.
         {
-            self.process_tx()
+            self.process_tx(pair)
                 .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
         } else {
             self.metrics.tx_rate_limiter_throttled.inc();
         }
     }
 
-    pub fn process_rx_rate_limiter_event(&mut self) {
+    pub fn process_rx_rate_limiter_event(&mut self, pair: usize) {
         self.metrics.rx_event_rate_limiter_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
 
-        match self.rx_rate_limiter.event_handler() {
+        match self.queue_pairs[pair].rx_rate_limiter.event_handler() {
             Ok(_) => {
                 // There might be enough budget now to receive the frame.
-                self.resume_rx()
+                self.resume_rx(pair)
                     .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
             }
             Err(err) => {
@@ -911,14 +1235,14 @@ impl Net {
         }
     }
 
-    pub fn process_tx_rate_limiter_event(&mut self) {
+    pub fn process_tx_rate_limiter_event(&mut self, pair: usize) {
         self.metrics.tx_rate_limiter_event_count.inc();
         // Upon rate limiter event, call the rate limiter handler
         // and restart processing the queue.
-        match self.tx_rate_limiter.event_handler() {
+        match self.queue_pairs[pair].tx_rate_limiter.event_handler() {
             Ok(_) => {
                 // There might be enough budget now to send the frame.
-                self.process_tx()
+                self.process_tx(pair)
                     .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
             }
             Err(err) => {
@@ -928,13 +1252,60 @@ impl Net {
         }
     }
 
+    /// Process a single MMDS timer event.
+    ///
+    /// This is called by the event manager while some MMDS requests are waiting for the data store
+    /// contents to change.
+    pub fn process_mmds_timer_event(&mut self) {
+        self.mmds_timer.read();
+
+        let Some(ns) = self.mmds_ns.as_mut() else {
+            return;
+        };
+        ns.poll_waiting_requests();
+        if !ns.has_waiting_requests() {
+            self.mmds_timer
+                .set_state(TimerState::Disarmed, SetTimeFlags::Default);
+        }
+
+        // The responses of the requests which stopped waiting are sent through the first queue
+        // pair, which is always in use.
+        if self.queue_pairs[0].rx_rate_limiter.is_blocked() {
+            self.metrics.rx_rate_limiter_throttled.inc();
+        } else {
+            self.resume_rx(0)
+                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+        }
+    }
+
+    /// Process a single control queue event.
+    ///
+    /// This is called by the event manager responding to the guest adding a new
+    /// request in the control queue.
+    pub fn process_ctrl_queue_event(&mut self) {
+        if let Err(err) = self.queue_evts[self.ctrl_queue_index()].read() {
+            error!("Failed to get ctrl queue event: {:?}", err);
+            self.metrics.event_fails.inc();
+        } else {
+            self.process_ctrl_queue()
+                .unwrap_or_else(|err| report_net_event_fail(&self.metrics, err));
+        }
+    }
+
     /// Process device virtio queue(s).
     pub fn process_virtio_queues(&mut self) -> Result<(), InvalidAvailIdx> {
-        if let Err(DeviceError::InvalidAvailIdx(err)) = self.resume_rx() {
-            return Err(err);
+        for pair in 0..self.queue_pairs.len() {
+            if let Err(DeviceError::InvalidAvailIdx(err)) = self.resume_rx(pair) {
+                return Err(err);
+            }
+            if let Err(DeviceError::InvalidAvailIdx(err)) = self.process_tx(pair) {
+                return Err(err);
+            }
         }
-        if let Err(DeviceError::InvalidAvailIdx(err)) = self.process_tx() {
-            return Err(err);
+        if self.queue_pairs.len() > 1 {
+            if let Err(DeviceError::InvalidAvailIdx(err)) = self.process_ctrl_queue() {
+                return Err(err);
+            }
         }
 
         Ok(())
@@ -949,15 +1320,29 @@ impl Net {
             return;
         }
 
-        // Give potential deferred RX frame to guest
-        self.rx_buffer.finish_frame(&mut self.queues[RX_INDEX]);
-        // Reset the parsed available descriptors, so we will re-parse them
-        self.queues[RX_INDEX].next_avail -=
-            Wrapping(u16::try_from(self.rx_buffer.parsed_descriptors.len()).unwrap());
-        self.rx_buffer.parsed_descriptors.clear();
-        self.rx_buffer.iovec.clear();
-        self.rx_buffer.used_bytes = 0;
-        self.rx_buffer.used_descriptors = 0;
+        for (pair, queue_pair) in self.queue_pairs.iter_mut().enumerate() {
+            let rx_queue = &mut self.queues[rx_queue_index(pair)];
+            let rx_buffer = &mut queue_pair.rx_buffer;
+            // Give potential deferred RX frame to guest
+            rx_buffer.finish_frame(rx_queue);
+            // Reset the parsed available descriptors, so we will re-parse them
+            rx_queue.next_avail -=
+                Wrapping(u16::try_from(rx_buffer.parsed_descriptors.len()).unwrap());
+            rx_buffer.parsed_descriptors.clear();
+            rx_buffer.iovec.clear();
+            rx_buffer.used_bytes = 0;
+            rx_buffer.used_descriptors = 0;
+        }
+    }
+
+    // Devices with a single queue pair don't offer `VIRTIO_NET_F_MQ`, so their config space only
+    // holds the MAC address.
+    fn config_space_len(&self) -> usize {
+        if self.queue_pairs.len() > 1 {
+            mem::size_of::<ConfigSpace>()
+        } else {
+            mem::offset_of!(ConfigSpace, status)
+        }
     }
 }
 
@@ -997,7 +1382,8 @@ impl VirtioDevice for Net {
     }
 
     fn read_config(&self, offset: u64, data: &mut [u8]) {
-        if let Some(config_space_bytes) = self.config_space.as_slice().get(u64_to_usize(offset)..) {
+        let config_space = &self.config_space.as_slice()[..self.config_space_len()];
+        if let Some(config_space_bytes) = config_space.get(u64_to_usize(offset)..) {
             let len = config_space_bytes.len().min(data.len());
             data[..len].copy_from_slice(&config_space_bytes[..len]);
         } else {
@@ -1007,7 +1393,9 @@ impl VirtioDevice for Net {
     }
 
     fn write_config(&mut self, offset: u64, data: &[u8]) {
-        let config_space_bytes = self.config_space.as_mut_slice();
+        // Only the MAC address is writable by the driver.
+        let config_space_bytes =
+            &mut self.config_space.as_mut_slice()[..mem::offset_of!(ConfigSpace, status)];
         let start = usize::try_from(offset).ok();
         let end = start.and_then(|s| s.checked_add(data.len()));
         let Some(dst) = start
@@ -1042,11 +1430,20 @@ impl VirtioDevice for Net {
         }
 
         let supported_flags: u32 = Net::build_tap_offload_features(self.acked_features);
-        self.tap
-            .set_offload(supported_flags)
-            .map_err(super::super::ActivateError::TapSetOffload)?;
+        for queue_pair in &self.queue_pairs {
+            queue_pair
+                .tap
+                .set_offload(supported_flags)
+                .map_err(super::super::ActivateError::TapSetOffload)?;
+        }
+        // Only steer traffic to the queue pairs the driver has enabled.
+        self.set_active_queue_pairs(self.active_queue_pairs)
+            .map_err(ActivateError::TapSetQueue)?;
 
-        self.rx_buffer.min_buffer_size = self.minimum_rx_buffer_size();
+        let min_buffer_size = self.minimum_rx_buffer_size();
+        for queue_pair in self.queue_pairs.iter_mut() {
+            queue_pair.rx_buffer.min_buffer_size = min_buffer_size;
+        }
 
         if self.activate_evt.write(1).is_err() {
             self.metrics.activate_fails.inc();
@@ -1094,11 +1491,11 @@ pub mod tests {
     };
     use crate::devices::virtio::net::test_utils::test::TestHelper;
     use crate::devices::virtio::net::test_utils::{
-        NetEvent, NetQueue, TapTrafficSimulator, default_net, if_index, inject_tap_tx_frame,
-        set_mac,
+        NetEvent, NetQueue, TapTrafficSimulator, default_net, default_net_multi_queue, if_index,
+        inject_tap_tx_frame, set_mac,
     };
-    use crate::devices::virtio::queue::VIRTQ_DESC_F_WRITE;
-    use crate::devices::virtio::test_utils::VirtQueue;
+    use crate::devices::virtio::queue::{VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE};
+    use crate::devices::virtio::test_utils::{VirtQueue, VirtqDesc, default_interrupt};
     use crate::dumbo::EthernetFrame;
     use crate::dumbo::pdu::arp::{ETH_IPV4_FRAME_LEN, EthIPv4ArpFrame};
     use crate::dumbo::pdu::ethernet::ETHERTYPE_ARP;
@@ -1110,7 +1507,9 @@ pub mod tests {
 
     impl Net {
         pub fn finish_frame(&mut self) {
-            self.rx_buffer.finish_frame(&mut self.queues[RX_INDEX]);
+            self.queue_pairs[0]
+                .rx_buffer
+                .finish_frame(&mut self.queues[RX_INDEX]);
             self.queues[RX_INDEX].advance_used_ring_idx();
         }
     }
@@ -1279,6 +1678,205 @@ pub mod tests {
         assert_eq!(new_config, new_config_read);
     }
 
+    // Sets up the queues of a multi-queue net device and activates it.
+    fn activate_multi_queue<'a>(net: &mut Net, mem: &'a GuestMemoryMmap) -> Vec<VirtQueue<'a>> {
+        let mut next_addr = GuestAddress(0);
+        let vqs: Vec<_> = (0..net.queues.len())
+            .map(|_| {
+                let vq = VirtQueue::new(next_addr, mem, 16);
+                next_addr = vq.end().unchecked_align_up(VirtqDesc::ALIGNMENT);
+                vq
+            })
+            .collect();
+        net.queues = vqs.iter().map(VirtQueue::create_queue).collect();
+        net.activate(mem.clone(), default_interrupt()).unwrap();
+
+        vqs
+    }
+
+    // Sends a control request made of `header` and `data` and returns the ack of the device.
+    fn send_ctrl_request(
+        net: &mut Net,
+        ctrlq: &VirtQueue,
+        data_addr: u64,
+        header: &[u8],
+        data: &[u8],
+    ) -> u8 {
+        let mem = ctrlq.memory();
+        let ack_addr = GuestAddress(data_addr + 0x100);
+        mem.write_slice(header, GuestAddress(data_addr)).unwrap();
+        mem.write_slice(data, GuestAddress(data_addr + 0x10))
+            .unwrap();
+        mem.write_obj(0xffu8, ack_addr).unwrap();
+
+        ctrlq.dtable[0].set(data_addr, header.len() as u32, VIRTQ_DESC_F_NEXT, 1);
+        ctrlq.dtable[1].set(data_addr + 0x10, data.len() as u32, VIRTQ_DESC_F_NEXT, 2);
+        ctrlq.dtable[2].set(ack_addr.raw_value(), 1, VIRTQ_DESC_F_WRITE, 0);
+        let avail_idx = ctrlq.avail.idx.get();
+        ctrlq.avail.ring[usize::from(avail_idx % ctrlq.size())].set(0);
+        ctrlq.avail.idx.set(avail_idx + 1);
+
+        let ctrl_queue_index = net.ctrl_queue_index();
+        net.queue_evts[ctrl_queue_index].write(1).unwrap();
+        net.process_ctrl_queue_event();
+
+        assert_eq!(ctrlq.used.idx.get(), avail_idx + 1);
+        ctrlq.check_used_elem(avail_idx, 0, 1);
+        mem.read_obj(ack_addr).unwrap()
+    }
+
+    #[test]
+    fn test_multi_queue_config() {
+        let mut net = default_net_multi_queue(4);
+        assert_ne!(net.avail_features() & (1 << VIRTIO_NET_F_MQ), 0);
+        assert_ne!(net.avail_features() & (1 << VIRTIO_NET_F_CTRL_VQ), 0);
+        assert_eq!(net.num_queue_pairs(), 4);
+        // 4 RX/TX queue pairs and a control queue.
+        assert_eq!(net.queues().len(), 9);
+        assert_eq!(net.queue_events().len(), 9);
+
+        let max_pairs_offset = mem::offset_of!(ConfigSpace, max_virtqueue_pairs) as u64;
+        let mut max_pairs = [0u8; 2];
+        net.read_config(max_pairs_offset, &mut max_pairs);
+        assert_eq!(u16::from_le_bytes(max_pairs), 4);
+
+        // Only the MAC address is writable.
+        let cfg_fails = net.metrics.cfg_fails.count();
+        net.write_config(max_pairs_offset, &[1, 0]);
+        assert_eq!(net.metrics.cfg_fails.count(), cfg_fails + 1);
+        net.read_config(max_pairs_offset, &mut max_pairs);
+        assert_eq!(u16::from_le_bytes(max_pairs), 4);
+
+        // A device with a single queue pair doesn't offer multi-queue.
+        let net = default_net();
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_MQ), 0);
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_CTRL_VQ), 0);
+        assert_eq!(net.queues().len(), NET_QUEUE_SIZES.len());
+
+        for num_queue_pairs in [0, NET_MAX_QUEUE_PAIRS + 1] {
+            assert!(matches!(
+                Net::new(
+                    "mq".to_string(),
+                    "net-device%d",
+                    None,
+                    RateLimiter::default(),
+                    RateLimiter::default(),
+                    num_queue_pairs,
+                ),
+                Err(NetError::InvalidQueuePairs(n)) if n == num_queue_pairs
+            ));
+        }
+    }
+
+    #[test]
+    fn test_ctrl_queue() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut net = default_net_multi_queue(2);
+        let vqs = activate_multi_queue(&mut net, &mem);
+        let ctrlq = &vqs[net.ctrl_queue_index()];
+        let data_addr = vqs.last().unwrap().end().raw_value();
+
+        // Only the first queue pair is in use after activation.
+        assert_eq!(net.active_queue_pairs, 1);
+        assert!(net.queue_pairs[0].tap_enabled);
+        assert!(!net.queue_pairs[1].tap_enabled);
+
+        let vq_pairs_set = [VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET];
+        assert_eq!(
+            send_ctrl_request(
+                &mut net,
+                ctrlq,
+                data_addr,
+                &vq_pairs_set,
+                &2u16.to_le_bytes()
+            ),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.active_queue_pairs, 2);
+        assert!(net.queue_pairs[1].tap_enabled);
+        assert!(
+            net.interrupt_trigger()
+                .has_pending_interrupt(VirtioInterruptType::Queue(net.ctrl_queue_index() as u16))
+        );
+
+        // Invalid number of queue pairs.
+        for pairs in [0u16, 3] {
+            assert_eq!(
+                send_ctrl_request(
+                    &mut net,
+                    ctrlq,
+                    data_addr,
+                    &vq_pairs_set,
+                    &pairs.to_le_bytes()
+                ),
+                VIRTIO_NET_ERR
+            );
+            assert_eq!(net.active_queue_pairs, 2);
+        }
+        // Malformed request.
+        assert_eq!(
+            send_ctrl_request(&mut net, ctrlq, data_addr, &vq_pairs_set, &[1]),
+            VIRTIO_NET_ERR
+        );
+        // Unsupported request (VIRTIO_NET_CTRL_RX, VIRTIO_NET_CTRL_RX_PROMISC).
+        assert_eq!(
+            send_ctrl_request(&mut net, ctrlq, data_addr, &[0, 0], &[1]),
+            VIRTIO_NET_ERR
+        );
+
+        assert_eq!(
+            send_ctrl_request(
+                &mut net,
+                ctrlq,
+                data_addr,
+                &vq_pairs_set,
+                &1u16.to_le_bytes()
+            ),
+            VIRTIO_NET_OK
+        );
+        assert_eq!(net.active_queue_pairs, 1);
+        assert!(!net.queue_pairs[1].tap_enabled);
+    }
+
+    #[test]
+    fn test_multi_queue_tx() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut net = default_net_multi_queue(2);
+        let vqs = activate_multi_queue(&mut net, &mem);
+        net.set_active_queue_pairs(2).unwrap();
+        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.queue_pairs[0].tap));
+
+        // Send a frame on the TX queue of the second queue pair.
+        let txq = &vqs[tx_queue_index(1)];
+        let data_addr = vqs.last().unwrap().end().raw_value();
+        let mut frame = vmm_sys_util::rand::rand_bytes(1000);
+        init_vnet_hdr(&mut frame);
+        mem.write_slice(&frame, GuestAddress(data_addr)).unwrap();
+        txq.dtable[0].set(data_addr, 1000, 0, 0);
+        txq.avail.ring[0].set(0);
+        txq.avail.idx.set(1);
+
+        let queue_metrics = net.queue_pairs[1].metrics.clone().unwrap();
+        let tx_packets_count = queue_metrics.tx_packets_count.count();
+        net.queue_evts[tx_queue_index(1)].write(1).unwrap();
+        check_metric_after_block!(
+            net.metrics.tx_packets_count,
+            1,
+            net.process_tx_queue_event(1)
+        );
+        assert_eq!(queue_metrics.tx_packets_count.count(), tx_packets_count + 1);
+        assert_eq!(txq.used.idx.get(), 1);
+        assert!(
+            net.interrupt_trigger()
+                .has_pending_interrupt(VirtioInterruptType::Queue(tx_queue_index(1) as u16))
+        );
+
+        // Check that the frame was sent to the tap.
+        let mut buf = vec![0; 1000];
+        assert!(tap_traffic_simulator.pop_rx_packet(&mut buf[vnet_hdr_len()..]));
+        assert_eq!(&buf[vnet_hdr_len()..], &frame[vnet_hdr_len()..]);
+    }
+
     #[test]
     fn test_rx_missing_queue_signal() {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
@@ -1452,7 +2050,7 @@ pub mod tests {
         th.rxq.check_used_elem(1, 3, 0);
         th.rxq.check_used_elem(2, 4, 0);
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_descriptors == 0);
+        assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors == 0);
         // Check that the frame has been written successfully to the valid Rx descriptor chain.
         th.rxq
             .check_used_elem(3, 5, frame.len().try_into().unwrap());
@@ -1500,7 +2098,7 @@ pub mod tests {
         );
 
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_descriptors == 0);
+        assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 1);
         assert!(
@@ -1567,7 +2165,7 @@ pub mod tests {
         );
 
         // Check that the frames weren't deferred.
-        assert!(th.net().rx_buffer.used_bytes == 0);
+        assert!(th.net().queue_pairs[0].rx_buffer.used_bytes == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 2);
         assert!(
@@ -1629,7 +2227,7 @@ pub mod tests {
         );
 
         // Check that the frame wasn't deferred.
-        assert!(th.net().rx_buffer.used_bytes == 0);
+        assert!(th.net().queue_pairs[0].rx_buffer.used_bytes == 0);
         // Check that the used queue has advanced.
         assert_eq!(th.rxq.used.idx.get(), 2);
         assert!(
@@ -1671,7 +2269,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 4096, 0)]);
         th.net().queue_evts[TX_INDEX].read().unwrap();
@@ -1692,7 +2291,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         let desc_list = [(0, 100, 0), (1, 100, VIRTQ_DESC_F_WRITE), (2, 500, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
@@ -1716,7 +2316,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 1, 0)]);
@@ -1743,7 +2344,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Send an invalid frame (too big, maximum buffer is MAX_BUFFER_SIZE).
         th.add_desc_chain(
@@ -1774,7 +2376,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Send an invalid frame (too small, VNET header missing).
         th.add_desc_chain(NetQueue::Tx, 0, &[(0, 0, 0)]);
@@ -1801,7 +2404,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Add invalid descriptor chain - writeable descriptor.
         th.add_desc_chain(
@@ -1848,7 +2452,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Add gaps between the descriptor ids in order to ensure that we follow
         // the `next` field.
@@ -1883,7 +2488,7 @@ pub mod tests {
         th.activate_net();
         // force the next write to the tap to return an error by simply closing the fd
         // SAFETY: its a valid fd
-        unsafe { libc::close(th.net.lock().unwrap().tap.as_raw_fd()) };
+        unsafe { libc::close(th.net.lock().unwrap().queue_pairs[0].tap.as_raw_fd()) };
 
         let desc_list = [(0, 1000, 0)];
         th.add_desc_chain(NetQueue::Tx, 0, &desc_list);
@@ -1913,7 +2518,8 @@ pub mod tests {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
-        let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&th.net().tap));
+        let tap_traffic_simulator =
+            TapTrafficSimulator::new(if_index(&th.net().queue_pairs[0].tap));
 
         // Write the first frame to the Tx queue
         let desc_list = [(0, 50, 0), (1, 100, 0), (2, 150, 0)];
@@ -1993,8 +2599,9 @@ pub mod tests {
         // MMDS frame. One iovec will be just fine.
         let mut fake_buffer = vec![0u8; MAX_BUFFER_SIZE];
         let iov_buffer = IoVecBufferMut::from(fake_buffer.as_mut_slice());
-        net.rx_buffer.iovec = iov_buffer;
-        net.rx_buffer
+        net.queue_pairs[0].rx_buffer.iovec = iov_buffer;
+        net.queue_pairs[0]
+            .rx_buffer
             .parsed_descriptors
             .push_back(ParsedDescriptorChain {
                 head_index: 1,
@@ -2021,10 +2628,9 @@ pub mod tests {
             assert!(
                 Net::write_to_mmds_or_tap(
                     net.mmds_ns.as_mut(),
-                    &mut net.tx_rate_limiter,
                     &mut headers,
                     &buffer,
-                    &mut net.tap,
+                    &mut net.queue_pairs[0],
                     Some(src_mac),
                     &net.metrics,
                 )
@@ -2036,7 +2642,7 @@ pub mod tests {
         check_metric_after_block!(
             &METRICS.mmds.tx_frames,
             1,
-            net.read_from_mmds_or_tap().unwrap()
+            net.read_from_mmds_or_tap(0).unwrap()
         );
     }
 
@@ -2060,10 +2666,9 @@ pub mod tests {
             0,
             Net::write_to_mmds_or_tap(
                 net.mmds_ns.as_mut(),
-                &mut net.tx_rate_limiter,
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                &mut net.queue_pairs[0],
                 Some(guest_mac),
                 &net.metrics,
             )
@@ -2075,10 +2680,9 @@ pub mod tests {
             1,
             Net::write_to_mmds_or_tap(
                 net.mmds_ns.as_mut(),
-                &mut net.tx_rate_limiter,
                 &mut headers,
                 &buffer,
-                &mut net.tap,
+                &mut net.queue_pairs[0],
                 Some(not_guest_mac),
                 &net.metrics,
             )
@@ -2118,11 +2722,11 @@ pub mod tests {
         th.activate_net();
         // force the next write to the tap to return an error by simply closing the fd
         // SAFETY: its a valid fd
-        unsafe { libc::close(th.net.lock().unwrap().tap.as_raw_fd()) };
+        unsafe { libc::close(th.net.lock().unwrap().queue_pairs[0].tap.as_raw_fd()) };
 
         // The RX queue is empty and there is a deferred frame.
-        th.net().rx_buffer.used_descriptors = 1;
-        th.net().rx_buffer.used_bytes = 100;
+        th.net().queue_pairs[0].rx_buffer.used_descriptors = 1;
+        th.net().queue_pairs[0].rx_buffer.used_bytes = 100;
         check_metric_after_block!(
             th.net().metrics.no_rx_avail_buffer,
             1,
@@ -2132,8 +2736,8 @@ pub mod tests {
         // We need to set this here to false, otherwise the device will try to
         // handle a deferred frame, it will fail and will never try to read from
         // the tap.
-        th.net().rx_buffer.used_descriptors = 0;
-        th.net().rx_buffer.used_bytes = 0;
+        th.net().queue_pairs[0].rx_buffer.used_descriptors = 0;
+        th.net().queue_pairs[0].rx_buffer.used_bytes = 0;
 
         th.add_desc_chain(
             NetQueue::Rx,
@@ -2156,7 +2760,7 @@ pub mod tests {
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
 
-        th.net().rx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
+        th.net().queue_pairs[0].rx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
         // There is no actual event on the rate limiter's timerfd.
         check_metric_after_block!(
             th.net().metrics.event_fails,
@@ -2171,7 +2775,7 @@ pub mod tests {
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
 
-        th.net().tx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
+        th.net().queue_pairs[0].tx_rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0).unwrap();
         th.simulate_event(NetEvent::TxRateLimiter);
         // There is no actual event on the rate limiter's timerfd.
         check_metric_after_block!(
@@ -2181,6 +2785,27 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn test_mmds_timer_handling() {
+        let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
+        let mut th = TestHelper::get_default(&mem);
+        th.activate_net();
+
+        // The timer is disarmed once there are no MMDS requests waiting.
+        th.net().mmds_timer.set_state(
+            TimerState::Periodic {
+                current: MMDS_TIMER_PERIOD,
+                interval: MMDS_TIMER_PERIOD,
+            },
+            SetTimeFlags::Default,
+        );
+        th.simulate_event(NetEvent::MmdsTimer);
+        assert!(matches!(
+            th.net().mmds_timer.get_state(),
+            TimerState::Disarmed
+        ));
+    }
+
     #[test]
     fn test_bandwidth_rate_limiter() {
         let mem = single_region_mem(2 * MAX_BUFFER_SIZE);
@@ -2195,7 +2820,7 @@ pub mod tests {
             assert!(rl.consume(0x1000, TokenType::Bytes));
 
             // set this tx rate limiter to be used
-            th.net().tx_rate_limiter = rl;
+            th.net().queue_pairs[0].tx_rate_limiter = rl;
 
             // try doing TX
             // following TX procedure should fail because of bandwidth rate limiting
@@ -2205,7 +2830,7 @@ pub mod tests {
                 th.simulate_event(NetEvent::TxQueue);
 
                 // assert that limiter is blocked
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().queue_pairs[0].tx_rate_limiter.is_blocked());
                 assert_eq!(th.net().metrics.tx_rate_limiter_throttled.count(), 1);
                 // make sure the data is still queued for processing
                 assert_eq!(th.txq.used.idx.get(), 0);
@@ -2234,7 +2859,7 @@ pub mod tests {
                 );
                 // This should be still blocked. We managed to send the first frame, but
                 // not enough budget for the second
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().queue_pairs[0].tx_rate_limiter.is_blocked());
                 // make sure the data queue advanced
                 assert_eq!(th.txq.used.idx.get(), 1);
             }
@@ -2250,7 +2875,7 @@ pub mod tests {
                     th.simulate_event(NetEvent::TxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().tx_rate_limiter.is_blocked());
+                assert!(!th.net().queue_pairs[0].tx_rate_limiter.is_blocked());
                 // make sure the data queue advance one more place
                 assert_eq!(th.txq.used.idx.get(), 2);
             }
@@ -2262,7 +2887,7 @@ pub mod tests {
             let mut rl = RateLimiter::new(1000, 0, 1000, 0, 0, 0).unwrap();
 
             // set up RX
-            assert!(th.net().rx_buffer.used_descriptors == 0);
+            assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors == 0);
             th.add_desc_chain(
                 NetQueue::Rx,
                 0,
@@ -2276,7 +2901,7 @@ pub mod tests {
             assert!(rl.consume(1000, TokenType::Bytes));
 
             // set this rx rate limiter to be used
-            th.net().rx_rate_limiter = rl;
+            th.net().queue_pairs[0].rx_rate_limiter = rl;
 
             // following RX procedure should fail because of bandwidth rate limiting
             {
@@ -2284,9 +2909,9 @@ pub mod tests {
                 th.simulate_event(NetEvent::Tap);
 
                 // assert that limiter is blocked
-                assert!(th.net().rx_rate_limiter.is_blocked());
+                assert!(th.net().queue_pairs[0].rx_rate_limiter.is_blocked());
                 assert_eq!(th.net().metrics.rx_rate_limiter_throttled.count(), 1);
-                assert!(th.net().rx_buffer.used_descriptors != 0);
+                assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors != 0);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(
                     th.net()
@@ -2318,7 +2943,7 @@ pub mod tests {
                     th.simulate_event(NetEvent::RxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().rx_rate_limiter.is_blocked());
+                assert!(!th.net().queue_pairs[0].rx_rate_limiter.is_blocked());
                 // make sure the virtio queue operation completed this time
                 assert!(
                     th.net()
@@ -2349,7 +2974,7 @@ pub mod tests {
             assert!(rl.consume(1, TokenType::Ops));
 
             // set this tx rate limiter to be used
-            th.net().tx_rate_limiter = rl;
+            th.net().queue_pairs[0].tx_rate_limiter = rl;
 
             // try doing TX
             // following TX procedure should fail because of ops rate limiting
@@ -2363,7 +2988,7 @@ pub mod tests {
                 );
 
                 // assert that limiter is blocked
-                assert!(th.net().tx_rate_limiter.is_blocked());
+                assert!(th.net().queue_pairs[0].tx_rate_limiter.is_blocked());
                 // make sure the data is still queued for processing
                 assert_eq!(th.txq.used.idx.get(), 0);
             }
@@ -2381,7 +3006,7 @@ pub mod tests {
                     th.simulate_event(NetEvent::TxRateLimiter)
                 );
                 // validate the rate_limiter is no longer blocked
-                assert!(!th.net().tx_rate_limiter.is_blocked());
+                assert!(!th.net().queue_pairs[0].tx_rate_limiter.is_blocked());
                 // make sure the data queue advanced
                 assert_eq!(th.txq.used.idx.get(), 1);
             }
@@ -2393,7 +3018,7 @@ pub mod tests {
             let mut rl = RateLimiter::new(0, 0, 0, 1, 0, 1000).unwrap();
 
             // set up RX
-            assert!(th.net().rx_buffer.used_descriptors == 0);
+            assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors == 0);
             th.add_desc_chain(
                 NetQueue::Rx,
                 0,
@@ -2405,7 +3030,7 @@ pub mod tests {
             assert!(rl.consume(1, TokenType::Ops));
 
             // set this rx rate limiter to be used
-            th.net().rx_rate_limiter = rl;
+            th.net().queue_pairs[0].rx_rate_limiter = rl;
 
             // following RX procedure should fail because of ops rate limiting
             {
@@ -2417,9 +3042,9 @@ pub mod tests {
                 );
 
                 // assert that limiter is blocked
-                assert!(th.net().rx_rate_limiter.is_blocked());
+                assert!(th.net().queue_pairs[0].rx_rate_limiter.is_blocked());
                 assert!(th.net().metrics.rx_rate_limiter_throttled.count() >= 1);
-                assert!(th.net().rx_buffer.used_descriptors != 0);
+                assert!(th.net().queue_pairs[0].rx_buffer.used_descriptors != 0);
                 // assert that no operation actually completed (limiter blocked it)
                 assert!(
                     th.net()
@@ -2470,8 +3095,8 @@ pub mod tests {
         let mut th = TestHelper::get_default(&mem);
         th.activate_net();
 
-        th.net().rx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
-        th.net().tx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
+        th.net().queue_pairs[0].rx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
+        th.net().queue_pairs[0].tx_rate_limiter = RateLimiter::new(10, 0, 10, 2, 0, 2).unwrap();
 
         let rx_bytes = TokenBucket::new(1000, 1001, 1002).unwrap();
         let rx_ops = TokenBucket::new(1003, 1004, 1005).unwrap();
@@ -2489,10 +3114,22 @@ pub mod tests {
             assert_eq!(a.one_time_burst(), b.one_time_burst());
             assert_eq!(a.refill_time_ms(), b.refill_time_ms());
         };
-        compare_buckets(th.net().rx_rate_limiter.bandwidth().unwrap(), &rx_bytes);
-        compare_buckets(th.net().rx_rate_limiter.ops().unwrap(), &rx_ops);
-        compare_buckets(th.net().tx_rate_limiter.bandwidth().unwrap(), &tx_bytes);
-        compare_buckets(th.net().tx_rate_limiter.ops().unwrap(), &tx_ops);
+        compare_buckets(
+            th.net().queue_pairs[0].rx_rate_limiter.bandwidth().unwrap(),
+            &rx_bytes,
+        );
+        compare_buckets(
+            th.net().queue_pairs[0].rx_rate_limiter.ops().unwrap(),
+            &rx_ops,
+        );
+        compare_buckets(
+            th.net().queue_pairs[0].tx_rate_limiter.bandwidth().unwrap(),
+            &tx_bytes,
+        );
+        compare_buckets(
+            th.net().queue_pairs[0].tx_rate_limiter.ops().unwrap(),
+            &tx_ops,
+        );
 
         th.net().patch_rate_limiters(
             BucketUpdate::Disabled,
@@ -2500,10 +3137,20 @@ pub mod tests {
             BucketUpdate::Disabled,
             BucketUpdate::Disabled,
         );
-        assert!(th.net().rx_rate_limiter.bandwidth().is_none());
-        assert!(th.net().rx_rate_limiter.ops().is_none());
-        assert!(th.net().tx_rate_limiter.bandwidth().is_none());
-        assert!(th.net().tx_rate_limiter.ops().is_none());
+        assert!(
+            th.net().queue_pairs[0]
+                .rx_rate_limiter
+                .bandwidth()
+                .is_none()
+        );
+        assert!(th.net().queue_pairs[0].rx_rate_limiter.ops().is_none());
+        assert!(
+            th.net().queue_pairs[0]
+                .tx_rate_limiter
+                .bandwidth()
+                .is_none()
+        );
+        assert!(th.net().queue_pairs[0].tx_rate_limiter.ops().is_none());
     }
 
     #[test]
@@ -16,42 +16,73 @@ impl Net {
     const PROCESS_TAP_RX: u32 = 3;
     const PROCESS_RX_RATE_LIMITER: u32 = 4;
     const PROCESS_TX_RATE_LIMITER: u32 = 5;
+    const PROCESS_VIRTQ_CTRL: u32 = 6;
+    const PROCESS_MMDS_TIMER: u32 = 7;
+
+    // The low byte of the event data holds the event source, while the remaining bits hold the
+    // index of the queue pair the event belongs to.
+    const QUEUE_PAIR_SHIFT: u32 = 8;
+    const EVENT_SOURCE_MASK: u32 = (1 << Self::QUEUE_PAIR_SHIFT) - 1;
+
+    fn queue_pair_event(source: u32, pair: usize) -> u32 {
+        source | (u32::try_from(pair).unwrap() << Self::QUEUE_PAIR_SHIFT)
+    }
 
     fn register_runtime_events(&self, ops: &mut EventOps) {
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[RX_INDEX],
-            Self::PROCESS_VIRTQ_RX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register rx queue event: {}", err);
-        }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.queue_evts[TX_INDEX],
-            Self::PROCESS_VIRTQ_TX,
-            EventSet::IN,
-        )) {
-            error!("Failed to register tx queue event: {}", err);
-        }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.rx_rate_limiter,
-            Self::PROCESS_RX_RATE_LIMITER,
-            EventSet::IN,
-        )) {
-            error!("Failed to register rx queue event: {}", err);
+        for (pair, queue_pair) in self.queue_pairs.iter().enumerate() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[2 * pair + RX_INDEX],
+                Self::queue_pair_event(Self::PROCESS_VIRTQ_RX, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register rx queue event: {}", err);
+            }
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[2 * pair + TX_INDEX],
+                Self::queue_pair_event(Self::PROCESS_VIRTQ_TX, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register tx queue event: {}", err);
+            }
+            if let Err(err) = ops.add(Events::with_data(
+                &queue_pair.rx_rate_limiter,
+                Self::queue_pair_event(Self::PROCESS_RX_RATE_LIMITER, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register rx queue event: {}", err);
+            }
+            if let Err(err) = ops.add(Events::with_data(
+                &queue_pair.tx_rate_limiter,
+                Self::queue_pair_event(Self::PROCESS_TX_RATE_LIMITER, pair),
+                EventSet::IN,
+            )) {
+                error!("Failed to register tx queue event: {}", err);
+            }
+            if let Err(err) = ops.add(Events::with_data(
+                &queue_pair.tap,
+                Self::queue_pair_event(Self::PROCESS_TAP_RX, pair),
+                EventSet::IN | EventSet::EDGE_TRIGGERED,
+            )) {
+                error!("Failed to register tap event: {}", err);
+            }
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.tx_rate_limiter,
-            Self::PROCESS_TX_RATE_LIMITER,
-            EventSet::IN,
-        )) {
-            error!("Failed to register tx queue event: {}", err);
+        if self.queue_pairs.len() > 1 {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.queue_evts[2 * self.queue_pairs.len()],
+                Self::PROCESS_VIRTQ_CTRL,
+                EventSet::IN,
+            )) {
+                error!("Failed to register ctrl queue event: {}", err);
+            }
         }
-        if let Err(err) = ops.add(Events::with_data(
-            &self.tap,
-            Self::PROCESS_TAP_RX,
-            EventSet::IN | EventSet::EDGE_TRIGGERED,
-        )) {
-            error!("Failed to register tap event: {}", err);
+        if self.mmds_ns.is_some() {
+            if let Err(err) = ops.add(Events::with_data(
+                &self.mmds_timer,
+                Self::PROCESS_MMDS_TIMER,
+                EventSet::IN,
+            )) {
+                error!("Failed to register mmds timer event: {}", err);
+            }
         }
     }
 
@@ -97,13 +128,26 @@ impl MutEventSubscriber for Net {
         }
 
         if self.is_activated() {
-            match source {
+            let pair = (source >> Self::QUEUE_PAIR_SHIFT) as usize;
+            if pair >= self.queue_pairs.len() {
+                warn!("Net: Spurious event received: {:?}", source);
+                self.metrics.event_fails.inc();
+                return;
+            }
+
+            match source & Self::EVENT_SOURCE_MASK {
                 Self::PROCESS_ACTIVATE => self.process_activate_event(ops),
-                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(),
-                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(),
-                Self::PROCESS_TAP_RX => self.process_tap_rx_event(),
-                Self::PROCESS_RX_RATE_LIMITER => self.process_rx_rate_limiter_event(),
-                Self::PROCESS_TX_RATE_LIMITER => self.process_tx_rate_limiter_event(),
+                Self::PROCESS_VIRTQ_RX => self.process_rx_queue_event(pair),
+                Self::PROCESS_VIRTQ_TX => self.process_tx_queue_event(pair),
+                Self::PROCESS_TAP_RX => self.process_tap_rx_event(pair),
+                Self::PROCESS_RX_RATE_LIMITER => self.process_rx_rate_limiter_event(pair),
+                Self::PROCESS_TX_RATE_LIMITER => self.process_tx_rate_limiter_event(pair),
+                Self::PROCESS_VIRTQ_CTRL if self.queue_pairs.len() > 1 => {
+                    self.process_ctrl_queue_event()
+                }
+                Self::PROCESS_MMDS_TIMER if self.mmds_ns.is_some() => {
+                    self.process_mmds_timer_event()
+                }
                 _ => {
                     warn!("Net: Spurious event received: {:?}", source);
                     self.metrics.event_fails.inc();
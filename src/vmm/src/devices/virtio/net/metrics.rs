@@ -51,6 +51,10 @@
 //! `net_iface_id` represent metrics for the endpoint "/network-interfaces/{iface_id}"
 //! network device respectively and `net` is the aggregate of all the per device metrics.
 //!
+//! Network devices with more than one RX/TX queue pair additionally report a
+//! `NetQueueMetrics` structure per queue pair, named "net_$iface_id_queue$index". These are
+//! not part of the `net` aggregate, since the per device metrics already account for them.
+//!
 //! # Limitations
 //! Network device currently do not have `vmm::logger::metrics::StoreMetrics` so aggregate
 //! doesn't consider them.
@@ -94,6 +98,8 @@ use crate::logger::{IncMetric, LatencyAggregateMetrics, SharedIncMetric};
 pub struct NetMetricsPerDevice {
     /// used to access per net device metrics
     pub metrics: BTreeMap<String, Arc<NetDeviceMetrics>>,
+    /// used to access per queue pair metrics of multi-queue net devices
+    pub queue_metrics: BTreeMap<String, Arc<NetQueueMetrics>>,
 }
 
 impl NetMetricsPerDevice {
@@ -112,6 +118,20 @@ impl NetMetricsPerDevice {
                 .or_insert_with(|| Arc::new(NetDeviceMetrics::default())),
         )
     }
+
+    /// Allocate `NetQueueMetrics` for the queue pair `queue_pair` of the
+    /// net device having id `iface_id`. Same as for `alloc`, the metrics
+    /// are only allocated if they don't exist already.
+    pub fn alloc_queue(iface_id: &str, queue_pair: usize) -> Arc<NetQueueMetrics> {
+        Arc::clone(
+            METRICS
+                .write()
+                .unwrap()
+                .queue_metrics
+                .entry(format!("{iface_id}_queue{queue_pair}"))
+                .or_insert_with(|| Arc::new(NetQueueMetrics::default())),
+        )
+    }
 }
 
 /// Pool of Network-related metrics per device behind a lock to
@@ -119,13 +139,14 @@ impl NetMetricsPerDevice {
 /// it is safe to unwrap it without any check.
 static METRICS: RwLock<NetMetricsPerDevice> = RwLock::new(NetMetricsPerDevice {
     metrics: BTreeMap::new(),
+    queue_metrics: BTreeMap::new(),
 });
 
 /// This function facilitates aggregation and serialization of
 /// per net device metrics.
 pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
     let net_metrics = METRICS.read().unwrap();
-    let metrics_len = net_metrics.metrics.len();
+    let metrics_len = net_metrics.metrics.len() + net_metrics.queue_metrics.len();
     // +1 to accomodate aggregate net metrics
     let mut seq = serializer.serialize_map(Some(1 + metrics_len))?;
 
@@ -138,6 +159,11 @@ pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
         net_aggregated.aggregate(m);
         seq.serialize_entry(&devn, m)?;
     }
+
+    for (name, metrics) in net_metrics.queue_metrics.iter() {
+        let queuen = format!("net_{}", name);
+        seq.serialize_entry(&queuen, metrics.as_ref())?;
+    }
     seq.serialize_entry("net", &net_aggregated)?;
     seq.end()
 }
@@ -271,6 +297,23 @@ impl NetDeviceMetrics {
     }
 }
 
+/// Metrics of a single RX/TX queue pair of a network device.
+#[derive(Default, Debug, Serialize)]
+pub struct NetQueueMetrics {
+    /// Number of bytes received on the queue pair.
+    pub rx_bytes_count: SharedIncMetric,
+    /// Number of packets received on the queue pair.
+    pub rx_packets_count: SharedIncMetric,
+    /// Number of RX rate limiter throttling events of the queue pair.
+    pub rx_rate_limiter_throttled: SharedIncMetric,
+    /// Number of bytes transmitted on the queue pair.
+    pub tx_bytes_count: SharedIncMetric,
+    /// Number of packets transmitted on the queue pair.
+    pub tx_packets_count: SharedIncMetric,
+    /// Number of TX rate limiter throttling events of the queue pair.
+    pub tx_rate_limiter_throttled: SharedIncMetric,
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::*;
@@ -443,4 +486,23 @@ pub mod tests {
                 >= 5
         );
     }
+
+    #[test]
+    fn test_net_queue_metrics() {
+        let queue_metrics = NetMetricsPerDevice::alloc_queue("mq_eth0", 1);
+        queue_metrics.rx_bytes_count.add(10);
+        // Allocating again returns the same metrics.
+        assert!(Arc::ptr_eq(
+            &queue_metrics,
+            &NetMetricsPerDevice::alloc_queue("mq_eth0", 1)
+        ));
+
+        let mut buf = Vec::new();
+        flush_metrics(&mut serde_json::Serializer::new(&mut buf)).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["net_mq_eth0_queue1"]["rx_bytes_count"], 10);
+        assert_eq!(json["net_mq_eth0_queue1"]["tx_bytes_count"], 0);
+        // Queue pair metrics are not devices on their own.
+        assert!(json.get("net_mq_eth0").is_none());
+    }
 }
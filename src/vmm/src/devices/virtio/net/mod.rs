@@ -16,6 +16,20 @@ pub const NET_QUEUE_SIZES: [u16; NET_NUM_QUEUES] = [NET_QUEUE_MAX_SIZE; NET_NUM_
 pub const RX_INDEX: usize = 0;
 /// The index of the tx queue from Net device queues/queues_evts vector.
 pub const TX_INDEX: usize = 1;
+/// Maximum number of RX/TX queue pairs of a network device.
+pub const NET_MAX_QUEUE_PAIRS: u16 = 16;
+
+/// Returns the total number of queues of a network device with `queue_pairs` RX/TX queue pairs.
+///
+/// Devices with more than one queue pair also expose a control queue, placed after all the
+/// RX/TX queues.
+pub const fn net_num_queues(queue_pairs: u16) -> usize {
+    if queue_pairs > 1 {
+        2 * queue_pairs as usize + 1
+    } else {
+        NET_NUM_QUEUES
+    }
+}
 
 pub mod device;
 mod event_handler;
@@ -49,8 +63,14 @@ pub enum NetError {
     TapOpen(TapError),
     /// Setting vnet header size failed: {0}
     TapSetVnetHdrSize(TapError),
+    /// Invalid number of queue pairs: {0}
+    InvalidQueuePairs(u16),
+    /// Error creating rate limiter: {0}
+    RateLimiter(io::Error),
     /// EventFd error: {0}
     EventFd(io::Error),
+    /// Error creating the MMDS timer: {0}
+    MmdsTimer(io::Error),
     /// IO error: {0}
     IO(io::Error),
     /// Error writing in guest memory: {0}
@@ -9,7 +9,7 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 use super::device::{Net, RxBuffers};
-use super::{NET_NUM_QUEUES, NET_QUEUE_MAX_SIZE, RX_INDEX, TapError};
+use super::{NET_QUEUE_MAX_SIZE, RX_INDEX, TapError, net_num_queues};
 use crate::devices::virtio::device::{ActiveState, DeviceState};
 use crate::devices::virtio::generated::virtio_ids::VIRTIO_ID_NET;
 use crate::devices::virtio::persist::{PersistError as VirtioStateError, VirtioDeviceState};
@@ -17,8 +17,8 @@ use crate::devices::virtio::transport::VirtioInterrupt;
 use crate::mmds::data_store::Mmds;
 use crate::mmds::ns::MmdsNetworkStack;
 use crate::mmds::persist::MmdsNetworkStackState;
-use crate::rate_limiter::RateLimiter;
 use crate::rate_limiter::persist::RateLimiterState;
+use crate::rate_limiter::{RateLimiter, RateLimiterGroup};
 use crate::snapshot::Persist;
 use crate::utils::net::mac::MacAddr;
 use crate::vstate::memory::GuestMemoryMmap;
@@ -30,14 +30,22 @@ pub struct NetConfigSpaceState {
     guest_mac: Option<MacAddr>,
 }
 
+/// Information about a RX/TX queue pair of the network device that
+/// is saved at snapshot.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetQueuePairState {
+    rx_rate_limiter_state: RateLimiterState,
+    tx_rate_limiter_state: RateLimiterState,
+}
+
 /// Information about the network device that are saved
 /// at snapshot.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetState {
     pub id: String,
     pub tap_if_name: String,
-    rx_rate_limiter_state: RateLimiterState,
-    tx_rate_limiter_state: RateLimiterState,
+    queue_pairs: Vec<NetQueuePairState>,
+    active_queue_pairs: u16,
     /// The associated MMDS network stack.
     pub mmds_ns: Option<MmdsNetworkStackState>,
     config_space: NetConfigSpaceState,
@@ -51,6 +59,8 @@ pub struct NetConstructorArgs {
     pub mem: GuestMemoryMmap,
     /// Pointer to the MMDS data store.
     pub mmds: Option<Arc<Mutex<Mmds>>>,
+    /// The rate limiter groups the device may belong to.
+    pub rate_limiter_groups: Vec<Arc<Mutex<RateLimiterGroup>>>,
 }
 
 /// Errors triggered when trying to construct a network device at resume time.
@@ -58,6 +68,8 @@ pub struct NetConstructorArgs {
 pub enum NetPersistError {
     /// Failed to create a network device: {0}
     CreateNet(#[from] super::NetError),
+    /// Invalid number of active queue pairs: {0}
+    InvalidActiveQueuePairs(u16),
     /// Failed to create a rate limiter: {0}
     CreateRateLimiter(#[from] io::Error),
     /// Failed to re-create the virtio state (i.e queues etc): {0}
@@ -77,8 +89,15 @@ impl Persist<'_> for Net {
         NetState {
             id: self.id().clone(),
             tap_if_name: self.iface_name(),
-            rx_rate_limiter_state: self.rx_rate_limiter.save(),
-            tx_rate_limiter_state: self.tx_rate_limiter.save(),
+            queue_pairs: self
+                .queue_pairs
+                .iter()
+                .map(|queue_pair| NetQueuePairState {
+                    rx_rate_limiter_state: queue_pair.rx_rate_limiter.save(),
+                    tx_rate_limiter_state: queue_pair.tx_rate_limiter.save(),
+                })
+                .collect(),
+            active_queue_pairs: self.active_queue_pairs,
             mmds_ns: self.mmds_ns.as_ref().map(|mmds| mmds.save()),
             config_space: NetConfigSpaceState {
                 guest_mac: self.guest_mac,
@@ -91,16 +110,33 @@ impl Persist<'_> for Net {
         constructor_args: Self::ConstructorArgs,
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
-        // RateLimiter::restore() can fail at creating a timerfd.
-        let rx_rate_limiter = RateLimiter::restore((), &state.rx_rate_limiter_state)?;
-        let tx_rate_limiter = RateLimiter::restore((), &state.tx_rate_limiter_state)?;
+        let num_queue_pairs = u16::try_from(state.queue_pairs.len())
+            .map_err(|_| super::NetError::InvalidQueuePairs(u16::MAX))?;
+        if !(1..=num_queue_pairs).contains(&state.active_queue_pairs) {
+            return Err(NetPersistError::InvalidActiveQueuePairs(
+                state.active_queue_pairs,
+            ));
+        }
         let mut net = Net::new(
             state.id.clone(),
             &state.tap_if_name,
             state.config_space.guest_mac,
-            rx_rate_limiter,
-            tx_rate_limiter,
+            RateLimiter::default(),
+            RateLimiter::default(),
+            num_queue_pairs,
         )?;
+        for (queue_pair, queue_pair_state) in net.queue_pairs.iter_mut().zip(&state.queue_pairs) {
+            // RateLimiter::restore() can fail at creating a timerfd.
+            queue_pair.rx_rate_limiter = RateLimiter::restore(
+                &constructor_args.rate_limiter_groups,
+                &queue_pair_state.rx_rate_limiter_state,
+            )?;
+            queue_pair.tx_rate_limiter = RateLimiter::restore(
+                &constructor_args.rate_limiter_groups,
+                &queue_pair_state.tx_rate_limiter_state,
+            )?;
+        }
+        net.active_queue_pairs = state.active_queue_pairs;
 
         // We trust the MMIODeviceManager::restore to pass us an MMDS data store reference if
         // there is at least one net device having the MMDS NS present and/or the mmds version was
@@ -122,7 +158,7 @@ impl Persist<'_> for Net {
         net.queues = state.virtio_state.build_queues_checked(
             &constructor_args.mem,
             VIRTIO_ID_NET,
-            NET_NUM_QUEUES,
+            net_num_queues(num_queue_pairs),
             NET_QUEUE_MAX_SIZE,
         )?;
         net.avail_features = state.virtio_state.avail_features;
@@ -137,7 +173,9 @@ mod tests {
 
     use super::*;
     use crate::devices::virtio::device::VirtioDevice;
-    use crate::devices::virtio::net::test_utils::{default_net, default_net_no_mmds};
+    use crate::devices::virtio::net::test_utils::{
+        default_net, default_net_multi_queue, default_net_no_mmds,
+    };
     use crate::devices::virtio::test_utils::{default_interrupt, default_mem};
     use crate::snapshot::Snapshot;
 
@@ -150,6 +188,8 @@ mod tests {
         let has_mmds_ns;
         let allow_mmds_requests;
         let virtio_state;
+        let num_queue_pairs;
+        let active_queue_pairs;
 
         // Create and save the net device.
         {
@@ -163,6 +203,8 @@ mod tests {
             has_mmds_ns = net.mmds_ns.is_some();
             allow_mmds_requests = has_mmds_ns && mmds_ds.is_some();
             virtio_state = VirtioDeviceState::from_device(&net);
+            num_queue_pairs = net.num_queue_pairs();
+            active_queue_pairs = net.active_queue_pairs;
         }
 
         // Drop the initial net device so that we don't get an error when trying to recreate the
@@ -174,6 +216,7 @@ mod tests {
                 NetConstructorArgs {
                     mem: guest_mem,
                     mmds: mmds_ds,
+                    rate_limiter_groups: Vec::new(),
                 },
                 &Snapshot::load_without_crc_check(mem.as_slice())
                     .unwrap()
@@ -190,8 +233,12 @@ mod tests {
                     assert_eq!(&restored_net.id, &id);
                     assert_eq!(&restored_net.iface_name(), &tap_if_name);
                     assert_eq!(restored_net.mmds_ns.is_some(), allow_mmds_requests);
-                    assert_eq!(restored_net.rx_rate_limiter, RateLimiter::default());
-                    assert_eq!(restored_net.tx_rate_limiter, RateLimiter::default());
+                    assert_eq!(restored_net.queue_pairs.len(), usize::from(num_queue_pairs));
+                    assert_eq!(restored_net.active_queue_pairs, active_queue_pairs);
+                    for queue_pair in &restored_net.queue_pairs {
+                        assert_eq!(queue_pair.rx_rate_limiter, RateLimiter::default());
+                        assert_eq!(queue_pair.tx_rate_limiter, RateLimiter::default());
+                    }
                 }
                 Err(NetPersistError::NoMmdsDataStore) => {
                     assert!(has_mmds_ns && !allow_mmds_requests)
@@ -215,5 +262,36 @@ mod tests {
         // Check what happens if the MMIODeviceManager does not give us the reference to the MMDS
         // data store. This will return an error.
         validate_save_and_restore(default_net(), None);
+
+        // Check that every queue pair of a multi-queue device is restored.
+        let mut net = default_net_multi_queue(3);
+        net.set_active_queue_pairs(2).unwrap();
+        validate_save_and_restore(net, None);
+    }
+
+    #[test]
+    fn test_restore_invalid_active_queue_pairs() {
+        let net = default_net_multi_queue(2);
+        let state = net.save();
+        drop(net);
+
+        for active_queue_pairs in [0, 3] {
+            let state = NetState {
+                active_queue_pairs,
+                ..state.clone()
+            };
+            let result = Net::restore(
+                NetConstructorArgs {
+                    mem: default_mem(),
+                    mmds: None,
+                    rate_limiter_groups: Vec::new(),
+                },
+                &state,
+            );
+            assert!(matches!(
+                result,
+                Err(NetPersistError::InvalidActiveQueuePairs(n)) if n == active_queue_pairs
+            ));
+        }
     }
 }
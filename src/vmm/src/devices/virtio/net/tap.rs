@@ -35,12 +35,20 @@ pub enum TapError {
     SetOffloadFlags(IoError),
     /// Error while setting size of the vnet header: {0}
     SetSizeOfVnetHdr(IoError),
+    /// Error while attaching or detaching a queue of a multi-queue tap: {0}
+    SetQueue(IoError),
 }
 
 const TUNTAP: ::std::os::raw::c_uint = 84;
 ioctl_iow_nr!(TUNSETIFF, TUNTAP, 202, ::std::os::raw::c_int);
 ioctl_iow_nr!(TUNSETOFFLOAD, TUNTAP, 208, ::std::os::raw::c_uint);
 ioctl_iow_nr!(TUNSETVNETHDRSZ, TUNTAP, 216, ::std::os::raw::c_int);
+ioctl_iow_nr!(TUNSETQUEUE, TUNTAP, 217, ::std::os::raw::c_int);
+
+// As defined in the Linux UAPI:
+// https://elixir.bootlin.com/linux/v5.10/source/include/uapi/linux/if_tun.h#L70
+const IFF_ATTACH_QUEUE: u32 = 0x0200;
+const IFF_DETACH_QUEUE: u32 = 0x0400;
 
 /// Handle for a network tap interface.
 ///
@@ -118,6 +126,38 @@ impl Tap {
     ///
     /// * `if_name` - the name of the interface.
     pub fn open_named(if_name: &str) -> Result<Tap, TapError> {
+        Self::open_with_flags(if_name, 0)
+    }
+
+    /// Create a multi-queue TUN/TAP device given the interface name, returning one `Tap` per
+    /// queue.
+    ///
+    /// A single queue is opened exactly as [`Tap::open_named`] does, so that devices with one
+    /// queue keep working with taps that were not created with `IFF_MULTI_QUEUE`.
+    /// # Arguments
+    ///
+    /// * `if_name` - the name of the interface.
+    /// * `num_queues` - the number of queues to open.
+    pub fn open_multi_queue(if_name: &str, num_queues: u16) -> Result<Vec<Tap>, TapError> {
+        if num_queues <= 1 {
+            return Ok(vec![Self::open_named(if_name)?]);
+        }
+
+        let first = Self::open_with_flags(if_name, generated::IFF_MULTI_QUEUE)?;
+        // Use the name resolved by the kernel, in case `if_name` was a template such as "tap%d".
+        let resolved_name = first.if_name_as_str().to_owned();
+        let mut taps = vec![first];
+        for _ in 1..num_queues {
+            taps.push(Self::open_with_flags(
+                &resolved_name,
+                generated::IFF_MULTI_QUEUE,
+            )?);
+        }
+
+        Ok(taps)
+    }
+
+    fn open_with_flags(if_name: &str, extra_flags: u32) -> Result<Tap, TapError> {
         // SAFETY: Open calls are safe because we give a constant null-terminated
         // string and verify the result.
         let fd = unsafe {
@@ -137,8 +177,13 @@ impl Tap {
         let ifreq = IfReqBuilder::new()
             .if_name(&terminated_if_name)
             .flags(
-                i16::try_from(generated::IFF_TAP | generated::IFF_NO_PI | generated::IFF_VNET_HDR)
-                    .unwrap(),
+                i16::try_from(
+                    generated::IFF_TAP
+                        | generated::IFF_NO_PI
+                        | generated::IFF_VNET_HDR
+                        | extra_flags,
+                )
+                .unwrap(),
             )
             .execute(&tuntap, TUNSETIFF())
             .map_err(|io_error| TapError::IfreqExecuteError(io_error, if_name.to_owned()))?;
@@ -180,6 +225,23 @@ impl Tap {
         Ok(())
     }
 
+    /// Attach this queue to, or detach it from, its multi-queue tap interface.
+    ///
+    /// A detached queue neither receives nor transmits any frames.
+    pub fn set_queue_enabled(&self, enabled: bool) -> Result<(), TapError> {
+        let flags = if enabled {
+            IFF_ATTACH_QUEUE
+        } else {
+            IFF_DETACH_QUEUE
+        };
+        IfReqBuilder::new()
+            .flags(i16::try_from(flags).unwrap())
+            .execute(&self.tap_file, TUNSETQUEUE())
+            .map_err(TapError::SetQueue)?;
+
+        Ok(())
+    }
+
     /// Write an `IoVecBuffer` to tap
     pub(crate) fn write_iovec(&mut self, buffer: &IoVecBuffer) -> Result<usize, IoError> {
         let iovcnt = i32::try_from(buffer.iovec_count()).unwrap();
@@ -282,6 +344,30 @@ pub mod tests {
         tap.set_offload(0).unwrap();
     }
 
+    #[test]
+    fn test_tap_multi_queue() {
+        // A single queue doesn't need a multi-queue tap.
+        let taps = Tap::open_multi_queue("mqtap0", 1).unwrap();
+        assert_eq!(taps.len(), 1);
+        taps[0].set_queue_enabled(false).unwrap_err();
+        drop(taps);
+
+        let taps = Tap::open_multi_queue("mqtap%d", 4).unwrap();
+        assert_eq!(taps.len(), 4);
+        assert_ne!(taps[0].if_name_as_str(), "mqtap%d");
+        for tap in &taps[1..] {
+            assert_eq!(tap.if_name_as_str(), taps[0].if_name_as_str());
+        }
+
+        taps[3].set_queue_enabled(false).unwrap();
+        // Detaching an already detached queue is not permitted.
+        taps[3].set_queue_enabled(false).unwrap_err();
+        taps[3].set_queue_enabled(true).unwrap();
+
+        // A multi-queue tap cannot be opened as a single queue one.
+        Tap::open_named(taps[0].if_name_as_str()).unwrap_err();
+    }
+
     #[test]
     fn test_raw_fd() {
         let tap = Tap::open_named("").unwrap();
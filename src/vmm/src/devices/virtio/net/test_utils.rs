@@ -45,13 +45,15 @@ pub fn default_net() -> Net {
         Some(guest_mac),
         RateLimiter::default(),
         RateLimiter::default(),
+        1,
     )
     .unwrap();
     net.configure_mmds_network_stack(
         MmdsNetworkStack::default_ipv4_addr(),
+        None,
         Arc::new(Mutex::new(Mmds::default())),
     );
-    enable(&net.tap);
+    enable(&net.queue_pairs[0].tap);
 
     net
 }
@@ -68,9 +70,30 @@ pub fn default_net_no_mmds() -> Net {
         Some(guest_mac),
         RateLimiter::default(),
         RateLimiter::default(),
+        1,
     )
     .unwrap();
-    enable(&net.tap);
+    enable(&net.queue_pairs[0].tap);
+
+    net
+}
+
+pub fn default_net_multi_queue(num_queue_pairs: u16) -> Net {
+    let next_tap = NEXT_INDEX.fetch_add(1, Ordering::SeqCst);
+    let tap_device_id = format!("net-device{}", next_tap);
+
+    let guest_mac = default_guest_mac();
+
+    let net = Net::new(
+        tap_device_id,
+        "net-device%d",
+        Some(guest_mac),
+        RateLimiter::default(),
+        RateLimiter::default(),
+        num_queue_pairs,
+    )
+    .unwrap();
+    enable(&net.queue_pairs[0].tap);
 
     net
 }
@@ -83,6 +106,7 @@ pub enum NetQueue {
 
 #[derive(Debug)]
 pub enum NetEvent {
+    MmdsTimer,
     RxQueue,
     RxRateLimiter,
     Tap,
@@ -255,7 +279,7 @@ pub(crate) fn inject_tap_tx_frame(net: &Net, len: usize) -> Vec<u8> {
     use std::os::unix::ffi::OsStrExt;
 
     assert!(len >= vnet_hdr_len());
-    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.tap));
+    let tap_traffic_simulator = TapTrafficSimulator::new(if_index(&net.queue_pairs[0].tap));
     let mut frame = vmm_sys_util::rand::rand_alphanumerics(len - vnet_hdr_len())
         .as_bytes()
         .to_vec();
@@ -373,11 +397,12 @@ pub mod test {
 
         pub fn simulate_event(&mut self, event: NetEvent) {
             match event {
-                NetEvent::RxQueue => self.net().process_rx_queue_event(),
-                NetEvent::RxRateLimiter => self.net().process_rx_rate_limiter_event(),
-                NetEvent::Tap => self.net().process_tap_rx_event(),
-                NetEvent::TxQueue => self.net().process_tx_queue_event(),
-                NetEvent::TxRateLimiter => self.net().process_tx_rate_limiter_event(),
+                NetEvent::MmdsTimer => self.net().process_mmds_timer_event(),
+                NetEvent::RxQueue => self.net().process_rx_queue_event(0),
+                NetEvent::RxRateLimiter => self.net().process_rx_rate_limiter_event(0),
+                NetEvent::Tap => self.net().process_tap_rx_event(0),
+                NetEvent::TxQueue => self.net().process_tx_queue_event(0),
+                NetEvent::TxRateLimiter => self.net().process_tx_rate_limiter_event(0),
             };
         }
 
@@ -427,7 +452,7 @@ pub mod test {
         /// Generate a tap frame of `frame_len` and check that it is not read and
         /// the descriptor chain has been discarded
         pub fn check_rx_discarded_buffer(&mut self, frame_len: usize) -> Vec<u8> {
-            let old_used_descriptors = self.net().rx_buffer.used_descriptors;
+            let old_used_descriptors = self.net().queue_pairs[0].rx_buffer.used_descriptors;
 
             // Inject frame to tap and run epoll.
             let frame = inject_tap_tx_frame(&self.net(), frame_len);
@@ -438,7 +463,7 @@ pub mod test {
             );
             // Check that the descriptor chain has been discarded.
             assert_eq!(
-                self.net().rx_buffer.used_descriptors,
+                self.net().queue_pairs[0].rx_buffer.used_descriptors,
                 old_used_descriptors + 1
             );
 
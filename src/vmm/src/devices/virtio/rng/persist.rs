@@ -61,7 +61,7 @@ impl Persist<'_> for Entropy {
             FIRECRACKER_MAX_QUEUE_SIZE,
         )?;
 
-        let rate_limiter = RateLimiter::restore((), &state.rate_limiter_state)?;
+        let rate_limiter = RateLimiter::restore(&[], &state.rate_limiter_state)?;
         let mut entropy = Entropy::new_with_queues(queues, rate_limiter)?;
         entropy.set_avail_features(state.virtio_state.avail_features);
         entropy.set_acked_features(state.virtio_state.acked_features);
@@ -633,6 +633,39 @@ impl VirtioPciDevice {
         Ok(())
     }
 
+    /// Unregister the IoEvent notification for a VirtIO device
+    pub fn unregister_notification_ioevent(
+        &self,
+        vm: &Vm,
+    ) -> std::result::Result<(), errno::Error> {
+        let bar_addr = self.config_bar_addr();
+        for (i, queue_evt) in self
+            .device
+            .lock()
+            .expect("Poisoned lock")
+            .queue_events()
+            .iter()
+            .enumerate()
+        {
+            let notify_base = bar_addr + NOTIFICATION_BAR_OFFSET;
+            let io_addr =
+                IoEventAddress::Mmio(notify_base + i as u64 * NOTIFY_OFF_MULTIPLIER as u64);
+            vm.fd()
+                .unregister_ioevent(queue_evt, &io_addr, NoDatamatch)?;
+        }
+        Ok(())
+    }
+
+    /// BDF assigned to the device
+    pub fn pci_device_bdf(&self) -> PciBdf {
+        self.pci_device_bdf
+    }
+
+    /// MSI-X interrupts of the device
+    pub fn msi_vector_group(&self) -> &Arc<MsiVectorGroup> {
+        &self.interrupt_source_group
+    }
+
     pub fn state(&self) -> VirtioPciDeviceState {
         VirtioPciDeviceState {
             pci_device_bdf: self.pci_device_bdf,
@@ -839,6 +872,17 @@ impl PciDevice for VirtioPciDevice {
         Ok(())
     }
 
+    fn free_bars(
+        &mut self,
+        _mmio32_allocator: &mut AddressAllocator,
+        mmio64_allocator: &mut AddressAllocator,
+    ) -> std::result::Result<(), PciDeviceError> {
+        let bar = &self.bar_region;
+        RangeInclusive::new(bar.addr, bar.addr + bar.size - 1)
+            .and_then(|range| mmio64_allocator.free(&range))
+            .map_err(|_| PciDeviceError::MissingResource)
+    }
+
     fn move_bar(
         &mut self,
         old_base: u64,
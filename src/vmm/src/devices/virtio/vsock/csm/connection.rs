@@ -14,6 +14,11 @@ use std::fmt::Debug;
 /// - `VsockEpollListener` for getting notified about the availability of data or free buffer
 ///   space at the host stream.
 ///
+/// For SOCK_SEQPACKET connections, the host stream is expected to preserve message boundaries:
+/// each read yields, and each write sends, a single message. Host messages are split into as
+/// many data packets as needed, the last one being flagged with EOM and EOR, while guest
+/// messages are gathered from their data packets until the one flagged with EOM arrives.
+///
 /// Note: there is a certain asymmetry to the RX and TX data flows:
 ///       - RX transfers do not need any data buffering, since data is read straight from the
 ///         host stream and into the guest-provided RX buffer;
@@ -83,12 +88,13 @@ use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::{Duration, Instant};
 
 use log::{debug, error, info, warn};
-use vm_memory::GuestMemoryError;
 use vm_memory::io::{ReadVolatile, WriteVolatile};
+use vm_memory::{GuestMemoryError, VolatileMemoryError, VolatileSlice};
 use vmm_sys_util::epoll::EventSet;
 
 use super::super::defs::uapi;
 use super::super::{VsockChannel, VsockEpollListener, VsockError};
+use super::msgbuf::MsgBuf;
 use super::txbuf::TxBuf;
 use super::{ConnState, PendingRx, PendingRxSet, VsockCsmError, defs};
 use crate::devices::virtio::vsock::metrics::METRICS;
@@ -117,10 +123,21 @@ pub struct VsockConnection<S: VsockConnectionBackend> {
     local_port: u32,
     /// The peer (guest) port.
     peer_port: u32,
+    /// The vsock socket type: `VSOCK_TYPE_STREAM` or `VSOCK_TYPE_SEQPACKET`.
+    type_: u16,
     /// The (connected) host-side stream.
     stream: S,
     /// The TX buffer for this connection.
     tx_buf: TxBuf,
+    /// The TX message buffer, used instead of `tx_buf` for SOCK_SEQPACKET connections.
+    tx_msgs: MsgBuf,
+    /// The buffer holding the last message read from the host stream, for SOCK_SEQPACKET
+    /// connections. It is allocated on the first read, and reused for all the messages.
+    rx_msg: Vec<u8>,
+    /// The length of the last message read into `rx_msg`.
+    rx_msg_len: usize,
+    /// The offset of the `rx_msg` data that is yet to be sent to the peer.
+    rx_msg_off: usize,
     /// Total number of bytes that have been successfully written to `self.stream`, either
     /// directly, or flushed from `self.tx_buf`.
     fwd_cnt: Wrapping<u32>,
@@ -214,6 +231,12 @@ where
                 return Ok(());
             }
 
+            // Messages can't be read straight to the RX buffer, since they may need more than
+            // one packet.
+            if self.type_ == uapi::VSOCK_TYPE_SEQPACKET {
+                return self.recv_msg_pkt(pkt);
+            }
+
             // The maximum amount of data we can read in is limited by both the RX buffer size and
             // the peer available buffer space.
             let max_len = std::cmp::min(pkt.buf_size(), self.peer_avail_credit());
@@ -298,6 +321,18 @@ where
         self.peer_fwd_cnt = Wrapping(pkt.hdr.fwd_cnt());
         METRICS.tx_packets_count.inc();
 
+        // If we were waiting for credit to send the rest of a host message, we may now be able
+        // to resume.
+        if self.rx_msg_off < self.rx_msg_len
+            && matches!(
+                self.state,
+                ConnState::Established | ConnState::PeerClosed(false, _)
+            )
+            && !self.need_credit_update_from_peer()
+        {
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+
         match self.state {
             // Most frequent case: this is an established connection that needs to forward some
             // data to the host stream. Also works for a connection that has begun shutting
@@ -305,7 +340,8 @@ where
             ConnState::Established | ConnState::PeerClosed(_, false)
                 if pkt.hdr.op() == uapi::VSOCK_OP_RW =>
             {
-                if pkt.buf_size() == 0 {
+                // An empty packet can still end a message.
+                if pkt.buf_size() == 0 && self.type_ != uapi::VSOCK_TYPE_SEQPACKET {
                     info!(
                         "vsock: dropping empty data packet from guest (lp={}, pp={}",
                         self.local_port, self.peer_port
@@ -313,8 +349,11 @@ where
                     return Ok(());
                 }
 
-                // Unwrapping here is safe, since we just checked `pkt.buf()` above.
-                if let Err(err) = self.send_bytes(pkt) {
+                let res = match self.type_ {
+                    uapi::VSOCK_TYPE_SEQPACKET => self.send_msg_bytes(pkt),
+                    _ => self.send_bytes(pkt),
+                };
+                if let Err(err) = res {
                     // If we can't write to the host stream, that's an unrecoverable error, so
                     // we'll terminate this connection.
                     warn!(
@@ -350,7 +389,7 @@ where
                 let send_off = pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0;
                 self.state = ConnState::PeerClosed(recv_off, send_off);
                 if recv_off && send_off {
-                    if self.tx_buf.is_empty() {
+                    if !self.has_pending_tx() {
                         self.pending_rx.insert(PendingRx::Rst);
                     } else {
                         self.expiry = Some(
@@ -367,7 +406,7 @@ where
             {
                 *recv_off = *recv_off || (pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_RCV != 0);
                 *send_off = *send_off || (pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0);
-                if *recv_off && *send_off && self.tx_buf.is_empty() {
+                if *recv_off && *send_off && !self.has_pending_tx() {
                     self.pending_rx.insert(PendingRx::Rst);
                 }
             }
@@ -430,7 +469,7 @@ where
     /// - data can be written to the host stream, and the TX buffer needs to be flushed.
     fn get_polled_evset(&self) -> EventSet {
         let mut evset = EventSet::empty();
-        if !self.tx_buf.is_empty() {
+        if self.has_pending_tx() {
             // There's data waiting in the TX buffer, so we are interested in being notified
             // when writing to the host stream wouldn't block.
             evset.insert(EventSet::OUT);
@@ -440,6 +479,8 @@ where
         match self.state {
             ConnState::Killed | ConnState::LocalClosed | ConnState::PeerClosed(true, _) => (),
             _ if self.need_credit_update_from_peer() => (),
+            // The last host message must be sent to the peer before reading the next one.
+            _ if self.rx_msg_off < self.rx_msg_len => (),
             _ => evset.insert(EventSet::IN),
         }
         evset
@@ -456,37 +497,16 @@ where
         if evset.contains(EventSet::OUT) {
             // Data can be written to the host stream. Time to flush out the TX buffer.
             //
-            if self.tx_buf.is_empty() {
+            if !self.has_pending_tx() {
                 METRICS.conn_event_fails.inc();
                 info!("vsock: connection received unexpected EPOLLOUT event");
                 return;
             }
-            let flushed = self
-                .tx_buf
-                .flush_to(&mut self.stream)
-                .unwrap_or_else(|err| {
-                    METRICS.tx_flush_fails.inc();
-                    warn!(
-                        "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
-                        self.local_port, self.peer_port, err
-                    );
-                    match err {
-                        VsockCsmError::TxBufFlush(inner)
-                            if inner.kind() == ErrorKind::WouldBlock =>
-                        {
-                            // This should never happen (EWOULDBLOCK after EPOLLOUT), but
-                            // it does, so let's absorb it.
-                        }
-                        _ => self.kill(),
-                    };
-                    0
-                });
-            self.fwd_cnt += wrap_usize_to_u32(flushed);
-            METRICS.tx_bytes_count.add(flushed as u64);
+            self.flush_tx();
 
             // If this connection was shutting down, but is waiting to drain the TX buffer
             // before forceful termination, the wait might be over.
-            if self.state == ConnState::PeerClosed(true, true) && self.tx_buf.is_empty() {
+            if self.state == ConnState::PeerClosed(true, true) && !self.has_pending_tx() {
                 self.pending_rx.insert(PendingRx::Rst);
             } else if self.peer_needs_credit_update() {
                 // If we've freed up some more buffer space, we may need to let the peer know it
@@ -509,15 +529,21 @@ where
         local_port: u32,
         peer_port: u32,
         peer_buf_alloc: u32,
+        type_: u16,
     ) -> Self {
         Self {
             local_cid,
             peer_cid,
             local_port,
             peer_port,
+            type_,
             stream,
             state: ConnState::PeerInit,
             tx_buf: TxBuf::new(),
+            tx_msgs: MsgBuf::new(),
+            rx_msg: Vec::new(),
+            rx_msg_len: 0,
+            rx_msg_off: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc,
             peer_fwd_cnt: Wrapping(0),
@@ -535,15 +561,21 @@ where
         peer_cid: u64,
         local_port: u32,
         peer_port: u32,
+        type_: u16,
     ) -> Self {
         Self {
             local_cid,
             peer_cid,
             local_port,
             peer_port,
+            type_,
             stream,
             state: ConnState::LocalInit,
             tx_buf: TxBuf::new(),
+            tx_msgs: MsgBuf::new(),
+            rx_msg: Vec::new(),
+            rx_msg_len: 0,
+            rx_msg_off: 0,
             fwd_cnt: Wrapping(0),
             peer_buf_alloc: 0,
             peer_fwd_cnt: Wrapping(0),
@@ -589,6 +621,16 @@ where
         self.state
     }
 
+    /// Return the vsock socket type of the connection.
+    pub fn type_(&self) -> u16 {
+        self.type_
+    }
+
+    /// Return the host-side stream of the connection.
+    pub fn stream(&self) -> &S {
+        &self.stream
+    }
+
     /// Send some raw, untracked, data straight to the underlying connected stream.
     /// Returns: number of bytes written, or the error describing the write failure.
     ///
@@ -646,6 +688,191 @@ where
         Ok(())
     }
 
+    /// Send the data of a packet to the host stream, for SOCK_SEQPACKET connections.
+    ///
+    /// The data is added to the message being gathered in the TX message buffer, and the message
+    /// is sent once its last packet (flagged with EOM) arrives.
+    fn send_msg_bytes(&mut self, pkt: &VsockPacketTx) -> Result<(), VsockError> {
+        pkt.write_from_offset_to(&mut self.tx_msgs, 0, pkt.hdr.len())?;
+
+        if pkt.hdr.flags() & uapi::VSOCK_FLAGS_SEQ_EOM == 0 {
+            // The peer can't send the rest of a message which fills up the whole buffer, since
+            // it would run out of credit first.
+            if self.tx_msgs.partial_len() == MsgBuf::SIZE {
+                warn!(
+                    "vsock: message too large for connection (lp={}, pp={})",
+                    self.local_port, self.peer_port
+                );
+                self.kill();
+            }
+            return Ok(());
+        }
+
+        // If there were messages in the buffer already, we're registered for EPOLLOUT events, and
+        // `self.notify()` will flush the new message along with them.
+        let was_empty = self.tx_msgs.is_empty();
+        self.tx_msgs.end_msg();
+        if !was_empty {
+            return Ok(());
+        }
+
+        match self.tx_msgs.flush_to(&mut self.stream) {
+            Ok(written) => {
+                self.fwd_cnt += wrap_usize_to_u32(written);
+                METRICS.tx_bytes_count.add(written as u64);
+            }
+            Err(VsockCsmError::TxBufFlush(err)) if err.kind() == ErrorKind::WouldBlock => {
+                // Absorb any would-block errors, since we can always try again later.
+            }
+            Err(err) => {
+                METRICS.tx_write_fails.inc();
+                warn!(
+                    "vsock: error writing message to local stream (lp={}, pp={}): {:?}",
+                    self.local_port, self.peer_port, err
+                );
+                self.kill();
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fill in a data packet with the next part of the last message read from the host stream,
+    /// reading a new message first if needed, for SOCK_SEQPACKET connections.
+    fn recv_msg_pkt(&mut self, pkt: &mut VsockPacketRx) -> Result<(), VsockError> {
+        if self.rx_msg_off == self.rx_msg_len {
+            // Read into a buffer one byte larger than the maximum message size, so that larger
+            // (and thus truncated) messages can be told apart.
+            if self.rx_msg.is_empty() {
+                self.rx_msg = vec![0; defs::CONN_MAX_MSG_SIZE as usize + 1];
+            }
+            self.rx_msg_off = 0;
+            let res = self
+                .stream
+                .read_volatile(&mut VolatileSlice::from(self.rx_msg.as_mut_slice()));
+            self.rx_msg_len = match res {
+                Ok(read_cnt) if read_cnt <= defs::CONN_MAX_MSG_SIZE as usize => read_cnt,
+                _ => 0,
+            };
+
+            match res {
+                Ok(0) if self.stream_hung_up() => {
+                    // Empty messages are also read as 0 bytes, so a 0-length read only means
+                    // that the host stream was closed down once the host peer has hung up.
+                    self.state = ConnState::LocalClosed;
+                    self.expiry = Some(
+                        Instant::now() + Duration::from_millis(defs::CONN_SHUTDOWN_TIMEOUT_MS),
+                    );
+                    pkt.hdr
+                        .set_op(uapi::VSOCK_OP_SHUTDOWN)
+                        .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_RCV)
+                        .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_SEND);
+                    self.last_fwd_cnt_to_peer = self.fwd_cnt;
+                    return Ok(());
+                }
+                Ok(read_cnt) if read_cnt > defs::CONN_MAX_MSG_SIZE as usize => {
+                    METRICS.rx_read_fails.inc();
+                    error!(
+                        "vsock: message too large in backing stream: lp={}, pp={}",
+                        self.local_port, self.peer_port
+                    );
+                    pkt.hdr.set_op(uapi::VSOCK_OP_RST);
+                    self.last_fwd_cnt_to_peer = self.fwd_cnt;
+                    return Ok(());
+                }
+                Ok(_) => (),
+                Err(VolatileMemoryError::IOError(err)) if err.kind() == ErrorKind::WouldBlock => {
+                    warn!(
+                        "vsock: unexpected EWOULDBLOCK while reading from backing stream: lp={}, \
+                         pp={}, err={:?}",
+                        self.local_port, self.peer_port, err
+                    );
+                    return Err(VsockError::NoData);
+                }
+                Err(err) => {
+                    METRICS.rx_read_fails.inc();
+                    error!(
+                        "vsock: error reading from backing stream: lp={}, pp={}, err={:?}",
+                        self.local_port, self.peer_port, err
+                    );
+                    pkt.hdr.set_op(uapi::VSOCK_OP_RST);
+                    self.last_fwd_cnt_to_peer = self.fwd_cnt;
+                    return Ok(());
+                }
+            }
+        }
+
+        // The amount of data we can send in this packet is limited by both the RX buffer size and
+        // the peer available buffer space.
+        let mut data = &self.rx_msg[self.rx_msg_off..self.rx_msg_len];
+        let len = std::cmp::min(
+            std::cmp::min(pkt.buf_size(), self.peer_avail_credit()),
+            wrap_usize_to_u32(data.len()),
+        );
+        pkt.read_at_offset_from(&mut data, 0, len)?;
+        self.rx_msg_off += len as usize;
+
+        pkt.hdr.set_op(uapi::VSOCK_OP_RW).set_len(len);
+        if self.rx_msg_off == self.rx_msg_len {
+            // Every message read from the host stream is a record of its own.
+            pkt.hdr
+                .set_flag(uapi::VSOCK_FLAGS_SEQ_EOM)
+                .set_flag(uapi::VSOCK_FLAGS_SEQ_EOR);
+        } else {
+            // We'll need more packets to send the rest of the message.
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+        METRICS.rx_bytes_count.add(u64::from(len));
+        self.rx_cnt += Wrapping(len);
+        self.last_fwd_cnt_to_peer = self.fwd_cnt;
+        Ok(())
+    }
+
+    /// Check whether the host peer has hung up, or shut down its sending side of the host stream.
+    fn stream_hung_up(&self) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd: self.stream.as_raw_fd(),
+            events: libc::POLLRDHUP,
+            revents: 0,
+        };
+        // SAFETY: Safe because `pollfd` is a single valid entry, the call doesn't block, and the
+        // return value is checked.
+        let ret = unsafe { libc::poll(&mut pollfd, 1, 0) };
+        // If the stream can't be polled, it is considered closed, like for stream connections.
+        ret < 0 || pollfd.revents & (libc::POLLRDHUP | libc::POLLHUP | libc::POLLERR) != 0
+    }
+
+    /// Flush the TX buffer (or the TX message buffer, for SOCK_SEQPACKET connections) to the host
+    /// stream.
+    fn flush_tx(&mut self) {
+        let res = match self.type_ {
+            uapi::VSOCK_TYPE_SEQPACKET => self.tx_msgs.flush_to(&mut self.stream),
+            _ => self.tx_buf.flush_to(&mut self.stream),
+        };
+        let flushed = res.unwrap_or_else(|err| {
+            METRICS.tx_flush_fails.inc();
+            warn!(
+                "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
+                self.local_port, self.peer_port, err
+            );
+            match err {
+                VsockCsmError::TxBufFlush(inner) if inner.kind() == ErrorKind::WouldBlock => {
+                    // This should never happen (EWOULDBLOCK after EPOLLOUT), but
+                    // it does, so let's absorb it.
+                }
+                _ => self.kill(),
+            };
+            0
+        });
+        self.fwd_cnt += wrap_usize_to_u32(flushed);
+        METRICS.tx_bytes_count.add(flushed as u64);
+    }
+
+    /// Check if there is any TX data waiting to be flushed to the host stream.
+    fn has_pending_tx(&self) -> bool {
+        !self.tx_buf.is_empty() || !self.tx_msgs.is_empty()
+    }
+
     /// Check if the credit information the peer has last received from us is outdated.
     fn peer_needs_credit_update(&self) -> bool {
         let peer_seen_free_buf =
@@ -671,7 +898,7 @@ where
             .set_dst_cid(self.peer_cid)
             .set_src_port(self.local_port)
             .set_dst_port(self.peer_port)
-            .set_type(uapi::VSOCK_TYPE_STREAM)
+            .set_type(self.type_)
             .set_buf_alloc(defs::CONN_TX_BUF_SIZE)
             .set_fwd_cnt(self.fwd_cnt.0);
     }
@@ -681,10 +908,10 @@ where
 mod tests {
     use std::io::{Error as IoError, ErrorKind, Write};
     use std::os::unix::io::RawFd;
+    use std::os::unix::net::UnixStream;
     use std::time::{Duration, Instant};
 
     use vm_memory::{VolatileMemoryError, VolatileSlice};
-    use vmm_sys_util::eventfd::EventFd;
 
     use super::super::super::defs::uapi;
     use super::super::defs as csm_defs;
@@ -710,7 +937,9 @@ mod tests {
 
     #[derive(Debug)]
     struct TestStream {
-        fd: EventFd,
+        fd: UnixStream,
+        // The other end of `fd`, which hangs up once dropped.
+        peer: Option<UnixStream>,
         read_buf: Vec<u8>,
         read_state: StreamState,
         write_buf: Vec<u8>,
@@ -718,8 +947,10 @@ mod tests {
     }
     impl TestStream {
         fn new() -> Self {
+            let (fd, peer) = UnixStream::pair().unwrap();
             Self {
-                fd: EventFd::new(libc::EFD_NONBLOCK).unwrap(),
+                fd,
+                peer: Some(peer),
                 read_state: StreamState::Ready,
                 write_state: StreamState::Ready,
                 read_buf: Vec::new(),
@@ -857,6 +1088,14 @@ mod tests {
         }
 
         fn new(conn_state: ConnState) -> Self {
+            Self::new_with_type(conn_state, uapi::VSOCK_TYPE_STREAM)
+        }
+
+        fn new_seqpacket_established() -> Self {
+            Self::new_with_type(ConnState::Established, uapi::VSOCK_TYPE_SEQPACKET)
+        }
+
+        fn new_with_type(conn_state: ConnState, type_: u16) -> Self {
             let vsock_test_ctx = TestContext::new();
             let mut handler_ctx = vsock_test_ctx.create_event_handler_context();
             let stream = TestStream::new();
@@ -882,9 +1121,10 @@ mod tests {
                     LOCAL_PORT,
                     PEER_PORT,
                     PEER_BUF_ALLOC,
+                    type_,
                 ),
                 ConnState::LocalInit => VsockConnection::<TestStream>::new_local_init(
-                    stream, LOCAL_CID, PEER_CID, LOCAL_PORT, PEER_PORT,
+                    stream, LOCAL_CID, PEER_CID, LOCAL_PORT, PEER_PORT, type_,
                 ),
                 ConnState::Established => {
                     let mut conn = VsockConnection::<TestStream>::new_peer_init(
@@ -894,6 +1134,7 @@ mod tests {
                         LOCAL_PORT,
                         PEER_PORT,
                         PEER_BUF_ALLOC,
+                        type_,
                     );
                     assert!(conn.has_pending_rx());
                     conn.recv_pkt(&mut rx_pkt).unwrap();
@@ -941,6 +1182,7 @@ mod tests {
 
         fn init_tx_pkt(&mut self, op: u16, len: u32) -> &mut VsockPacketTx {
             init_pkt_hdr(&mut self.tx_pkt.hdr, op, len);
+            self.tx_pkt.hdr.set_type(self.conn.type_);
             &mut self.tx_pkt
         }
 
@@ -1306,4 +1548,131 @@ mod tests {
         ctx.recv();
         assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
     }
+
+    #[test]
+    fn test_seqpacket_rx_msg() {
+        let mut ctx = CsmTestContext::new_seqpacket_established();
+        assert_eq!(ctx.conn.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        let data = &[1, 2, 3, 4, 5, 6];
+        ctx.set_stream(TestStream::new_with_read_buf(data));
+        // The peer only has room for a part of the message.
+        ctx.set_peer_credit(4);
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 4);
+        assert_eq!(ctx.rx_pkt.hdr.flags(), 0);
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 4), [1, 2, 3, 4]);
+
+        // The next message can't be read before the rest of this one is sent.
+        assert!(!ctx.conn.get_polled_evset().contains(EventSet::IN));
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_CREDIT_REQUEST);
+        assert!(!ctx.conn.has_pending_rx());
+
+        // Once the peer has room again, the rest of the message follows, ending the record.
+        ctx.init_tx_pkt(uapi::VSOCK_OP_CREDIT_UPDATE, 0)
+            .hdr
+            .set_fwd_cnt(PEER_BUF_ALLOC);
+        ctx.send();
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 2);
+        assert_eq!(
+            ctx.rx_pkt.hdr.flags(),
+            uapi::VSOCK_FLAGS_SEQ_EOM | uapi::VSOCK_FLAGS_SEQ_EOR
+        );
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 2), [5, 6]);
+        assert!(!ctx.conn.has_pending_rx());
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::IN));
+
+        // A message larger than the maximum message size resets the connection.
+        let data = vec![0u8; csm_defs::CONN_MAX_MSG_SIZE as usize + 1];
+        ctx.set_stream(TestStream::new_with_read_buf(&data));
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+    }
+
+    #[test]
+    fn test_seqpacket_rx_empty_msg() {
+        let mut ctx = CsmTestContext::new_seqpacket_established();
+
+        // An empty message is read as 0 bytes, but the host peer is still connected.
+        let mut stream = TestStream::new();
+        stream.read_state = StreamState::Closed;
+        ctx.set_stream(stream);
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.len(), 0);
+        assert_eq!(
+            ctx.rx_pkt.hdr.flags(),
+            uapi::VSOCK_FLAGS_SEQ_EOM | uapi::VSOCK_FLAGS_SEQ_EOR
+        );
+        assert_eq!(ctx.conn.state, ConnState::Established);
+        assert!(!ctx.conn.has_pending_rx());
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::IN));
+
+        // Once the host peer has hung up, a 0-length read means the host stream was closed.
+        drop(ctx.conn.stream.peer.take());
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_SHUTDOWN);
+        assert_ne!(ctx.rx_pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND, 0);
+        assert_ne!(ctx.rx_pkt.hdr.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_RCV, 0);
+        assert_eq!(ctx.conn.state, ConnState::LocalClosed);
+        assert!(ctx.conn.will_expire());
+    }
+
+    #[test]
+    fn test_seqpacket_tx_msg() {
+        let mut ctx = CsmTestContext::new_seqpacket_established();
+
+        // The message is only written to the host stream once its last packet arrives.
+        ctx.init_data_tx_pkt(&[1, 2]);
+        ctx.send();
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        ctx.init_data_tx_pkt(&[3, 4]);
+        ctx.tx_pkt.hdr.set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert_eq!(ctx.conn.stream.write_buf, [1, 2, 3, 4]);
+        assert_eq!(ctx.conn.fwd_cnt().0, 4);
+
+        // Messages are buffered while the host stream would block.
+        let mut stream = TestStream::new();
+        stream.write_state = StreamState::WouldBlock;
+        ctx.set_stream(stream);
+        ctx.init_data_tx_pkt(&[5, 6]);
+        ctx.tx_pkt.hdr.set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        assert_eq!(ctx.conn.tx_msgs.len(), 2);
+
+        ctx.set_stream(TestStream::new());
+        ctx.notify_epollout();
+        assert!(!ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        assert_eq!(ctx.conn.stream.write_buf, [5, 6]);
+        assert_eq!(ctx.conn.fwd_cnt().0, 6);
+    }
+
+    #[test]
+    fn test_seqpacket_tx_msg_too_large() {
+        let mut ctx = CsmTestContext::new_seqpacket_established();
+
+        // A message filling up the whole TX buffer without ending can never be completed.
+        let data = vec![0u8; ctx.tx_pkt.buf_size() as usize];
+        ctx.init_data_tx_pkt(data.as_slice());
+        for _i in 0..(csm_defs::CONN_TX_BUF_SIZE as usize / data.len()) {
+            assert_eq!(ctx.conn.state, ConnState::Established);
+            ctx.send();
+        }
+
+        assert_eq!(ctx.conn.state, ConnState::Killed);
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+    }
 }
@@ -4,6 +4,7 @@
 /// This module implements our vsock connection state machine. The heavy lifting is done by
 /// `connection::VsockConnection`, while this file only defines some constants and helper structs.
 mod connection;
+mod msgbuf;
 mod txbuf;
 
 pub use connection::{VsockConnection, VsockConnectionBackend};
@@ -21,6 +22,9 @@ pub mod defs {
 
     /// Connection graceful shutdown timeout, in millis.
     pub const CONN_SHUTDOWN_TIMEOUT_MS: u64 = 2000;
+
+    /// Maximum size of a message sent over a SOCK_SEQPACKET connection, in either direction.
+    pub const CONN_MAX_MSG_SIZE: u32 = CONN_TX_BUF_SIZE;
 }
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
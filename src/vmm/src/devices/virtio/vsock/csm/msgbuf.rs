@@ -0,0 +1,231 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io::{ErrorKind, Write};
+
+use vm_memory::{VolatileMemoryError, VolatileSlice, WriteVolatile};
+
+use super::{VsockCsmError, defs};
+use crate::vstate::memory::{BitmapSlice, Bytes};
+
+/// A message buffer, used by SOCK_SEQPACKET vsock connections to buffer TX (guest -> host)
+/// messages. Each write to the host socket sends a whole message, so the data of a guest message
+/// is gathered from its packets until the last one (flagged with EOM) arrives, and complete
+/// messages are kept until the host socket can take them.
+#[derive(Debug)]
+pub struct MsgBuf {
+    /// The data of the message that is being gathered.
+    partial: Vec<u8>,
+    /// The complete messages, in the order they were received.
+    complete: VecDeque<Vec<u8>>,
+    /// The total number of bytes held by this buffer.
+    len: usize,
+}
+
+impl MsgBuf {
+    /// Total buffer size, in bytes.
+    pub const SIZE: usize = defs::CONN_TX_BUF_SIZE as usize;
+
+    /// Message buffer constructor.
+    pub fn new() -> Self {
+        Self {
+            partial: Vec::new(),
+            complete: VecDeque::new(),
+            len: 0,
+        }
+    }
+
+    /// Get the used length of this buffer - number of bytes that have been pushed in, but not
+    /// yet flushed out, including the message being gathered.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Get the length of the message being gathered.
+    pub fn partial_len(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Push a byte slice onto the message being gathered.
+    ///
+    /// Either the entire source slice will be pushed to the buffer, or none of it, if there isn't
+    /// enough room, in which case `Err(Error::TxBufFull)` is returned.
+    pub fn push(&mut self, src: &VolatileSlice<impl BitmapSlice>) -> Result<(), VsockCsmError> {
+        if self.len + src.len() > Self::SIZE {
+            return Err(VsockCsmError::TxBufFull);
+        }
+
+        let start = self.partial.len();
+        self.partial.resize(start + src.len(), 0);
+        let _ = src.read(&mut self.partial[start..], 0);
+        self.len += src.len();
+
+        Ok(())
+    }
+
+    /// Mark the message being gathered as complete, so that it can be flushed.
+    pub fn end_msg(&mut self) {
+        self.complete.push_back(std::mem::take(&mut self.partial));
+    }
+
+    /// Flush the complete messages to a writable stream, with one write per message.
+    ///
+    /// Return the number of bytes that have been transferred out of the buffer and into the
+    /// writable stream.
+    pub fn flush_to<W: Write + Debug>(&mut self, sink: &mut W) -> Result<usize, VsockCsmError> {
+        let mut flushed = 0;
+
+        while let Some(msg) = self.complete.front() {
+            match sink.write(msg) {
+                Ok(written) if written == msg.len() => (),
+                // Writes to SOCK_SEQPACKET sockets are atomic, so a partial write means that
+                // the message boundaries can no longer be preserved.
+                Ok(_) => {
+                    return Err(VsockCsmError::TxBufFlush(std::io::Error::from(
+                        ErrorKind::WriteZero,
+                    )));
+                }
+                // If some messages were already flushed, we consider the flush action a success,
+                // and leave the error to the next attempt.
+                Err(_) if flushed > 0 => break,
+                Err(err) => return Err(VsockCsmError::TxBufFlush(err)),
+            }
+
+            flushed += msg.len();
+            self.len -= msg.len();
+            self.complete.pop_front();
+        }
+
+        Ok(flushed)
+    }
+
+    /// Check if the buffer holds no complete message waiting to be flushed.
+    pub fn is_empty(&self) -> bool {
+        self.complete.is_empty()
+    }
+}
+
+impl WriteVolatile for MsgBuf {
+    fn write_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        self.push(buf)
+            .map(|()| buf.len())
+            .map_err(|err| VolatileMemoryError::IOError(std::io::Error::other(err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error as IoError;
+
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct TestSink {
+        msgs: Vec<Vec<u8>>,
+        err: Option<IoError>,
+    }
+
+    impl Write for TestSink {
+        fn write(&mut self, src: &[u8]) -> Result<usize, IoError> {
+            if let Some(err) = self.err.take() {
+                return Err(err);
+            }
+            self.msgs.push(src.to_vec());
+            Ok(src.len())
+        }
+        fn flush(&mut self) -> Result<(), IoError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_msg_boundaries() {
+        let mut msgbuf = MsgBuf::new();
+        let mut sink = TestSink::default();
+
+        msgbuf
+            .push(&VolatileSlice::from([1, 2].as_mut_slice()))
+            .unwrap();
+        msgbuf
+            .write_all_volatile(&VolatileSlice::from([3, 4].as_mut_slice()))
+            .unwrap();
+        assert_eq!(msgbuf.len(), 4);
+        assert_eq!(msgbuf.partial_len(), 4);
+        // The message being gathered can't be flushed yet.
+        assert!(msgbuf.is_empty());
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 0);
+        assert!(sink.msgs.is_empty());
+
+        msgbuf.end_msg();
+        msgbuf
+            .push(&VolatileSlice::from([5].as_mut_slice()))
+            .unwrap();
+        msgbuf.end_msg();
+        msgbuf
+            .push(&VolatileSlice::from([6].as_mut_slice()))
+            .unwrap();
+        assert!(!msgbuf.is_empty());
+        assert_eq!(msgbuf.partial_len(), 1);
+
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 5);
+        assert_eq!(sink.msgs, vec![vec![1, 2, 3, 4], vec![5]]);
+        assert!(msgbuf.is_empty());
+        assert_eq!(msgbuf.len(), 1);
+    }
+
+    #[test]
+    fn test_push_error() {
+        let mut msgbuf = MsgBuf::new();
+        let mut tmp = vec![0u8; MsgBuf::SIZE - 1];
+
+        msgbuf
+            .push(&VolatileSlice::from(tmp.as_mut_slice()))
+            .unwrap();
+        msgbuf.end_msg();
+        match msgbuf.push(&VolatileSlice::from([1, 2].as_mut_slice())) {
+            Err(VsockCsmError::TxBufFull) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        match msgbuf.write_volatile(&VolatileSlice::from([1, 2].as_mut_slice())) {
+            Err(err) => {
+                assert_eq!(
+                    format!("{}", err),
+                    "Attempted to push data to a full TX buffer"
+                );
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_flush_error() {
+        let mut msgbuf = MsgBuf::new();
+        let mut sink = TestSink::default();
+
+        msgbuf
+            .push(&VolatileSlice::from([1, 2].as_mut_slice()))
+            .unwrap();
+        msgbuf.end_msg();
+        msgbuf
+            .push(&VolatileSlice::from([3].as_mut_slice()))
+            .unwrap();
+        msgbuf.end_msg();
+
+        sink.err = Some(IoError::from(ErrorKind::WouldBlock));
+        match msgbuf.flush_to(&mut sink) {
+            Err(VsockCsmError::TxBufFlush(ref err)) if err.kind() == ErrorKind::WouldBlock => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        assert_eq!(msgbuf.len(), 3);
+
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 3);
+        assert_eq!(sink.msgs, vec![vec![1, 2], vec![3]]);
+        assert_eq!(msgbuf.len(), 0);
+    }
+}
@@ -28,7 +28,10 @@ use vmm_sys_util::epoll::EventSet;
 pub use self::defs::VSOCK_DEV_ID;
 pub use self::device::Vsock;
 use self::packet::{VsockPacketRx, VsockPacketTx};
-pub use self::unix::{VsockUnixBackend, VsockUnixBackendError};
+pub use self::unix::{
+    VsockGuestPortForward, VsockHostPortForward, VsockPortForwards, VsockUnixBackend,
+    VsockUnixBackendError, seqpacket_sock_path,
+};
 use super::iov_deque::IovDequeError;
 use crate::devices::virtio::iovec::IoVecError;
 use crate::devices::virtio::persist::PersistError as VirtioStateError;
@@ -80,12 +83,19 @@ mod defs {
         pub const VSOCK_FLAGS_SHUTDOWN_RCV: u32 = 1;
         /// Valid with a VSOCK_OP_SHUTDOWN packet: the packet sender will send no more data.
         pub const VSOCK_FLAGS_SHUTDOWN_SEND: u32 = 2;
+        /// Valid with a VSOCK_OP_RW packet of a seqpacket connection: the packet carries the last
+        /// part of a message.
+        pub const VSOCK_FLAGS_SEQ_EOM: u32 = 1;
+        /// Valid with a VSOCK_OP_RW packet of a seqpacket connection: the message ends a record.
+        pub const VSOCK_FLAGS_SEQ_EOR: u32 = 2;
 
         /// Vsock packet type.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
-        /// Stream / connection-oriented packet (the only currently valid type).
+        /// Stream / connection-oriented packet.
         pub const VSOCK_TYPE_STREAM: u16 = 1;
+        /// Sequenced packet / connection-oriented packet, preserving message boundaries.
+        pub const VSOCK_TYPE_SEQPACKET: u16 = 2;
 
         pub const VSOCK_HOST_CID: u64 = 2;
     }
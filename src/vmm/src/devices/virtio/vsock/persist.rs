@@ -46,6 +46,8 @@ pub enum VsockBackendState {
 pub struct VsockUdsState {
     /// The path for the UDS socket.
     pub(crate) path: String,
+    /// The port forwarding table.
+    pub(crate) port_forwards: VsockPortForwards,
 }
 
 /// A helper structure that holds the constructor arguments for VsockUnixBackend
@@ -72,6 +74,7 @@ impl Persist<'_> for VsockUnixBackend {
     fn save(&self) -> Self::State {
         VsockBackendState::Uds(VsockUdsState {
             path: self.host_sock_path.clone(),
+            port_forwards: self.port_forwards().clone(),
         })
     }
 
@@ -83,6 +86,7 @@ impl Persist<'_> for VsockUnixBackend {
             VsockBackendState::Uds(uds_state) => Ok(VsockUnixBackend::new(
                 constructor_args.cid,
                 uds_state.path.clone(),
+                uds_state.port_forwards.clone(),
             )?),
         }
     }
@@ -145,6 +149,7 @@ pub(crate) mod tests {
         fn save(&self) -> Self::State {
             VsockBackendState::Uds(VsockUdsState {
                 path: "test".to_owned(),
+                port_forwards: test_port_forwards(),
             })
         }
 
@@ -155,6 +160,19 @@ pub(crate) mod tests {
         }
     }
 
+    fn test_port_forwards() -> VsockPortForwards {
+        VsockPortForwards {
+            guest_to_host: vec![VsockGuestPortForward {
+                vsock_port: 52,
+                host_addr: "127.0.0.1:8080".parse().unwrap(),
+            }],
+            host_to_guest: vec![VsockHostPortForward {
+                host_addr: "[::1]:2222".parse().unwrap(),
+                vsock_port: 22,
+            }],
+        }
+    }
+
     #[test]
     fn test_persist_uds_backend() {
         let ctx = TestContext::new();
@@ -189,6 +207,7 @@ pub(crate) mod tests {
                 backend: match restored_state.backend {
                     VsockBackendState::Uds(uds_state) => {
                         assert_eq!(uds_state.path, "test".to_owned());
+                        assert_eq!(uds_state.port_forwards, test_port_forwards());
                         TestBackend::new()
                     }
                 },
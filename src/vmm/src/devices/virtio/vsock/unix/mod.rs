@@ -10,10 +10,20 @@
 mod muxer;
 mod muxer_killq;
 mod muxer_rxq;
+mod port_forward;
+mod seqpacket;
 
-pub use muxer::VsockMuxer as VsockUnixBackend;
+use std::io::Write;
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+pub use muxer::{VsockMuxer as VsockUnixBackend, seqpacket_sock_path};
+pub use port_forward::{VsockGuestPortForward, VsockHostPortForward, VsockPortForwards};
+use vm_memory::{ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile};
 
 use crate::devices::virtio::vsock::csm::VsockConnectionBackend;
+use crate::vstate::memory::BitmapSlice;
 
 mod defs {
     /// Maximum number of established connections that we can handle.
@@ -45,8 +55,74 @@ pub enum VsockUnixBackendError {
     UnixRead(std::io::Error),
     /// Muxer connection limit reached.
     TooManyConnections,
+    /// Vsock port {0} is forwarded more than once.
+    DuplicatePortForward(u32),
+    /// Error accepting a new connection from a host-side TCP socket: {0}
+    TcpAccept(std::io::Error),
+    /// Error binding to a host-side TCP address: {0}
+    TcpBind(std::io::Error),
+    /// Error connecting to a host-side TCP address: {0}
+    TcpConnect(std::io::Error),
+}
+
+/// The host-side stream of a muxer connection.
+#[derive(Debug)]
+pub enum HostStream {
+    /// A Unix socket stream, connected through `uds_path` or `<uds_path>_<port>`.
+    Unix(UnixStream),
+    /// A TCP stream, connected through a port forwarding rule.
+    Tcp(TcpStream),
+}
+
+impl ReadVolatile for HostStream {
+    fn read_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &mut VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        match self {
+            HostStream::Unix(stream) => stream.read_volatile(buf),
+            HostStream::Tcp(stream) => stream.read_volatile(buf),
+        }
+    }
+}
+
+impl WriteVolatile for HostStream {
+    fn write_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        match self {
+            HostStream::Unix(stream) => stream.write_volatile(buf),
+            HostStream::Tcp(stream) => stream.write_volatile(buf),
+        }
+    }
+}
+
+impl Write for HostStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            HostStream::Unix(stream) => stream.write(buf),
+            HostStream::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            HostStream::Unix(stream) => stream.flush(),
+            HostStream::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+impl AsRawFd for HostStream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            HostStream::Unix(stream) => stream.as_raw_fd(),
+            HostStream::Tcp(stream) => stream.as_raw_fd(),
+        }
+    }
 }
 
-type MuxerConnection = super::csm::VsockConnection<std::os::unix::net::UnixStream>;
+type MuxerConnection = super::csm::VsockConnection<HostStream>;
 
-impl VsockConnectionBackend for std::os::unix::net::UnixStream {}
+impl VsockConnectionBackend for HostStream {}
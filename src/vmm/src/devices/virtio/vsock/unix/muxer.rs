@@ -17,12 +17,14 @@
 ///    belong to an existing connection and, as such, the muxer simply forwards them.
 /// 2. Event dispatcher There are three event categories that the vsock backend is interested
 ///    it:
-///    1. A new host-initiated connection is ready to be accepted from the listening host Unix
-///       socket;
+///    1. A new host-initiated connection is ready to be accepted from one of the listening
+///       host Unix sockets (a SOCK_STREAM one, and a SOCK_SEQPACKET one for connections which
+///       need to preserve message boundaries), or from one of the host TCP sockets listening
+///       for connections forwarded to guest ports;
 ///    2. Data is available for reading from a newly-accepted host-initiated connection (i.e.
 ///       the host is ready to issue a vsock connection request, informing us of the
 ///       destination port to which it wants to connect);
-///    3. Some event was triggered for a connected Unix socket, that belongs to a
+///    3. Some event was triggered for a connected Unix or TCP socket, that belongs to a
 ///       `VsockConnection`.
 ///
 ///  The muxer gets notified about all of these events, because, as a `VsockEpollListener`
@@ -33,6 +35,7 @@
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::io::Read;
+use std::net::TcpListener;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
 
@@ -44,7 +47,9 @@ use super::super::defs::uapi;
 use super::super::{VsockBackend, VsockChannel, VsockEpollListener, VsockError};
 use super::muxer_killq::MuxerKillQ;
 use super::muxer_rxq::MuxerRxQ;
-use super::{MuxerConnection, VsockUnixBackendError, defs};
+use super::port_forward::{self, VsockPortForwards};
+use super::seqpacket::{self, SeqpacketListener};
+use super::{HostStream, MuxerConnection, VsockUnixBackendError, defs};
 use crate::devices::virtio::vsock::metrics::METRICS;
 use crate::devices::virtio::vsock::packet::{VsockPacketRx, VsockPacketTx};
 use crate::logger::IncMetric;
@@ -63,7 +68,11 @@ pub enum MuxerRx {
     /// The packet must be fetched from the connection identified by `ConnMapKey`.
     ConnRx(ConnMapKey),
     /// The muxer must produce an RST packet.
-    RstPkt { local_port: u32, peer_port: u32 },
+    RstPkt {
+        local_port: u32,
+        peer_port: u32,
+        type_: u16,
+    },
 }
 
 /// An epoll listener, registered under the muxer's nested epoll FD.
@@ -75,9 +84,20 @@ enum EpollListener {
     Connection { key: ConnMapKey, evset: EventSet },
     /// A listener interested in new host-initiated connections.
     HostSock,
+    /// A listener interested in new host-initiated SOCK_SEQPACKET connections.
+    HostSeqpacketSock,
+    /// A listener interested in new host TCP connections, forwarded as per the
+    /// `host_to_guest` port forwarding rule at the given index.
+    HostTcpSock(usize),
     /// A listener interested in reading host `connect <port>` commands from a freshly
-    /// connected host socket.
-    LocalStream(UnixStream),
+    /// connected host socket, of the given vsock socket type.
+    LocalStream(UnixStream, u16),
+}
+
+/// Get the file system path of the host-side SOCK_SEQPACKET Unix socket, through which
+/// host-initiated SOCK_SEQPACKET connections are accepted.
+pub fn seqpacket_sock_path(host_sock_path: &str) -> String {
+    format!("{}_seqpacket", host_sock_path)
 }
 
 /// The vsock connection multiplexer.
@@ -98,9 +118,17 @@ pub struct VsockMuxer {
     killq: MuxerKillQ,
     /// The Unix socket, through which host-initiated connections are accepted.
     host_sock: UnixListener,
+    /// The SOCK_SEQPACKET Unix socket, through which host-initiated SOCK_SEQPACKET connections
+    /// are accepted.
+    host_seqpacket_sock: SeqpacketListener,
     /// The file system path of the host-side Unix socket. This is used to figure out the path
     /// to Unix sockets listening on specific ports. I.e. `"<this path>_<port number>"`.
     pub(crate) host_sock_path: String,
+    /// The port forwarding table.
+    port_forwards: VsockPortForwards,
+    /// The TCP sockets, through which host connections forwarded to guest ports are accepted,
+    /// in the order of the `host_to_guest` port forwarding rules.
+    host_tcp_socks: Vec<TcpListener>,
     /// The nested epoll event set, used to register epoll listeners.
     epoll: Epoll,
     /// A hash set used to keep track of used host-side (local) ports, in order to assign local
@@ -131,6 +159,7 @@ impl VsockChannel for VsockMuxer {
                 MuxerRx::RstPkt {
                     local_port,
                     peer_port,
+                    type_,
                 } => {
                     pkt.hdr
                         .set_op(uapi::VSOCK_OP_RST)
@@ -139,7 +168,7 @@ impl VsockChannel for VsockMuxer {
                         .set_src_port(local_port)
                         .set_dst_port(peer_port)
                         .set_len(0)
-                        .set_type(uapi::VSOCK_TYPE_STREAM)
+                        .set_type(type_)
                         .set_flags(0)
                         .set_buf_alloc(0)
                         .set_fwd_cnt(0);
@@ -202,10 +231,13 @@ impl VsockChannel for VsockMuxer {
             pkt.hdr
         );
 
-        // If this packet has an unsupported type (!=stream), we must send back an RST.
+        // If this packet has an unsupported type (!=stream and !=seqpacket), we must send back
+        // an RST.
         //
-        if pkt.hdr.type_() != uapi::VSOCK_TYPE_STREAM {
-            self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port());
+        if pkt.hdr.type_() != uapi::VSOCK_TYPE_STREAM
+            && pkt.hdr.type_() != uapi::VSOCK_TYPE_SEQPACKET
+        {
+            self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_());
             return Ok(());
         }
 
@@ -228,7 +260,7 @@ impl VsockChannel for VsockMuxer {
                 self.handle_peer_request_pkt(pkt);
             } else {
                 // Send back an RST, to let the drive know we weren't expecting this packet.
-                self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port());
+                self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_());
             }
             return Ok(());
         }
@@ -303,21 +335,46 @@ impl VsockBackend for VsockMuxer {}
 
 impl VsockMuxer {
     /// Muxer constructor.
-    pub fn new(cid: u64, host_sock_path: String) -> Result<Self, VsockUnixBackendError> {
+    pub fn new(
+        cid: u64,
+        host_sock_path: String,
+        port_forwards: VsockPortForwards,
+    ) -> Result<Self, VsockUnixBackendError> {
+        // Bind on the host TCP addresses forwarded to guest ports first, so that no socket
+        // file is left behind if one of them is not available.
+        port_forwards.validate()?;
+        let host_tcp_socks = port_forwards
+            .host_to_guest
+            .iter()
+            .map(|fwd| {
+                TcpListener::bind(fwd.host_addr)
+                    .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+                    .map_err(VsockUnixBackendError::TcpBind)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
         // Open/bind on the host Unix socket, so we can accept host-initiated
         // connections.
         let host_sock = UnixListener::bind(&host_sock_path)
             .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
             .map_err(VsockUnixBackendError::UnixBind)?;
+        let host_seqpacket_sock = SeqpacketListener::bind(&seqpacket_sock_path(&host_sock_path))
+            .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+            .map_err(VsockUnixBackendError::UnixBind)?;
 
+        // Room for the connections, and the listening Unix and TCP sockets.
+        let listener_map = HashMap::with_capacity(defs::MAX_CONNECTIONS + 2 + host_tcp_socks.len());
         let mut muxer = Self {
             cid,
             host_sock,
+            host_seqpacket_sock,
             host_sock_path,
+            port_forwards,
+            host_tcp_socks,
             epoll: Epoll::new().map_err(VsockUnixBackendError::EpollFdCreate)?,
             rxq: MuxerRxQ::new(),
             conn_map: HashMap::with_capacity(defs::MAX_CONNECTIONS),
-            listener_map: HashMap::with_capacity(defs::MAX_CONNECTIONS + 1),
+            listener_map,
             killq: MuxerKillQ::new(),
             local_port_last: (1u32 << 30) - 1,
             local_port_set: HashSet::with_capacity(defs::MAX_CONNECTIONS),
@@ -325,6 +382,17 @@ impl VsockMuxer {
 
         // Listen on the host initiated socket, for incoming connections.
         muxer.add_listener(muxer.host_sock.as_raw_fd(), EpollListener::HostSock)?;
+        muxer.add_listener(
+            muxer.host_seqpacket_sock.as_raw_fd(),
+            EpollListener::HostSeqpacketSock,
+        )?;
+        // Listen on the host TCP sockets, for incoming forwarded connections.
+        for index in 0..muxer.host_tcp_socks.len() {
+            muxer.add_listener(
+                muxer.host_tcp_socks[index].as_raw_fd(),
+                EpollListener::HostTcpSock(index),
+            )?;
+        }
         Ok(muxer)
     }
 
@@ -333,6 +401,11 @@ impl VsockMuxer {
         &self.host_sock_path
     }
 
+    /// Return the port forwarding table.
+    pub fn port_forwards(&self) -> &VsockPortForwards {
+        &self.port_forwards
+    }
+
     /// Handle/dispatch an epoll event to its listener.
     fn handle_event(&mut self, fd: RawFd, event_set: EventSet) {
         debug!(
@@ -356,39 +429,28 @@ impl VsockMuxer {
 
             // A new host-initiated connection is ready to be accepted.
             Some(EpollListener::HostSock) => {
-                if self.conn_map.len() == defs::MAX_CONNECTIONS {
-                    // If we're already maxed-out on connections, we'll just accept and
-                    // immediately discard this potentially new one.
-                    warn!("vsock: connection limit reached; refusing new host connection");
-                    self.host_sock.accept().map(|_| 0).unwrap_or(0);
-                    return;
-                }
-                self.host_sock
-                    .accept()
-                    .map_err(VsockUnixBackendError::UnixAccept)
-                    .and_then(|(stream, _)| {
-                        stream
-                            .set_nonblocking(true)
-                            .map(|_| stream)
-                            .map_err(VsockUnixBackendError::UnixAccept)
-                    })
-                    .and_then(|stream| {
-                        // Before forwarding this connection to a listening AF_VSOCK socket on
-                        // the guest side, we need to know the destination port. We'll read
-                        // that port from a "connect" command received on this socket, so the
-                        // next step is to ask to be notified the moment we can read from it.
-                        self.add_listener(stream.as_raw_fd(), EpollListener::LocalStream(stream))
-                    })
-                    .unwrap_or_else(|err| {
-                        warn!("vsock: unable to accept local connection: {:?}", err);
-                    });
+                self.accept_local_stream(uapi::VSOCK_TYPE_STREAM);
+            }
+
+            // A new host-initiated SOCK_SEQPACKET connection is ready to be accepted.
+            Some(EpollListener::HostSeqpacketSock) => {
+                self.accept_local_stream(uapi::VSOCK_TYPE_SEQPACKET);
+            }
+
+            // A new host TCP connection, to be forwarded to a guest port, is ready to be
+            // accepted.
+            Some(EpollListener::HostTcpSock(index)) => {
+                let index = *index;
+                self.accept_tcp_stream(index);
             }
 
             // Data is ready to be read from a host-initiated connection. That would be the
             // "connect" command that we're expecting.
-            Some(EpollListener::LocalStream(_)) => {
-                if let Some(EpollListener::LocalStream(mut stream)) = self.remove_listener(fd) {
-                    Self::read_local_stream_port(&mut stream)
+            Some(EpollListener::LocalStream(..)) => {
+                if let Some(EpollListener::LocalStream(mut stream, type_)) =
+                    self.remove_listener(fd)
+                {
+                    Self::read_local_stream_port(&mut stream, type_)
                         .map(|peer_port| (self.allocate_local_port(), peer_port))
                         .and_then(|(local_port, peer_port)| {
                             self.add_connection(
@@ -397,11 +459,12 @@ impl VsockMuxer {
                                     peer_port,
                                 },
                                 MuxerConnection::new_local_init(
-                                    stream,
+                                    HostStream::Unix(stream),
                                     uapi::VSOCK_HOST_CID,
                                     self.cid,
                                     local_port,
                                     peer_port,
+                                    type_,
                                 ),
                             )
                         })
@@ -421,29 +484,121 @@ impl VsockMuxer {
         }
     }
 
+    /// Accept a new host-initiated connection, of the given vsock socket type.
+    fn accept_local_stream(&mut self, type_: u16) {
+        let accept = |muxer: &Self| match type_ {
+            uapi::VSOCK_TYPE_SEQPACKET => muxer.host_seqpacket_sock.accept(),
+            _ => muxer.host_sock.accept().map(|(stream, _)| stream),
+        };
+
+        if self.conn_map.len() == defs::MAX_CONNECTIONS {
+            // If we're already maxed-out on connections, we'll just accept and
+            // immediately discard this potentially new one.
+            warn!("vsock: connection limit reached; refusing new host connection");
+            accept(self).map(|_| 0).unwrap_or(0);
+            return;
+        }
+        accept(self)
+            .map_err(VsockUnixBackendError::UnixAccept)
+            .and_then(|stream| {
+                stream
+                    .set_nonblocking(true)
+                    .map(|_| stream)
+                    .map_err(VsockUnixBackendError::UnixAccept)
+            })
+            .and_then(|stream| {
+                // Before forwarding this connection to a listening AF_VSOCK socket on
+                // the guest side, we need to know the destination port. We'll read
+                // that port from a "connect" command received on this socket, so the
+                // next step is to ask to be notified the moment we can read from it.
+                self.add_listener(
+                    stream.as_raw_fd(),
+                    EpollListener::LocalStream(stream, type_),
+                )
+            })
+            .unwrap_or_else(|err| {
+                warn!("vsock: unable to accept local connection: {:?}", err);
+            });
+    }
+
+    /// Accept a new host TCP connection, and forward it as per the `host_to_guest` port
+    /// forwarding rule at `index`.
+    fn accept_tcp_stream(&mut self, index: usize) {
+        if self.conn_map.len() == defs::MAX_CONNECTIONS {
+            // If we're already maxed-out on connections, we'll just accept and
+            // immediately discard this potentially new one.
+            warn!("vsock: connection limit reached; refusing new host TCP connection");
+            self.host_tcp_socks[index].accept().map(|_| 0).unwrap_or(0);
+            return;
+        }
+
+        // Unlike connections accepted on the Unix sockets, the destination port is known
+        // already, so the connection can be forwarded right away.
+        let peer_port = self.port_forwards.host_to_guest[index].vsock_port;
+        self.host_tcp_socks[index]
+            .accept()
+            .and_then(|(stream, _)| stream.set_nonblocking(true).map(|_| stream))
+            .map_err(VsockUnixBackendError::TcpAccept)
+            .and_then(|stream| {
+                let local_port = self.allocate_local_port();
+                self.add_connection(
+                    ConnMapKey {
+                        local_port,
+                        peer_port,
+                    },
+                    MuxerConnection::new_local_init(
+                        HostStream::Tcp(stream),
+                        uapi::VSOCK_HOST_CID,
+                        self.cid,
+                        local_port,
+                        peer_port,
+                        uapi::VSOCK_TYPE_STREAM,
+                    ),
+                )
+            })
+            .unwrap_or_else(|err| {
+                warn!("vsock: unable to accept host TCP connection: {:?}", err);
+            });
+    }
+
     /// Parse a host "connect" command, and extract the destination vsock port.
-    fn read_local_stream_port(stream: &mut UnixStream) -> Result<u32, VsockUnixBackendError> {
+    fn read_local_stream_port(
+        stream: &mut UnixStream,
+        type_: u16,
+    ) -> Result<u32, VsockUnixBackendError> {
         let mut buf = [0u8; 32];
 
         // This is the minimum number of bytes that we should be able to read, when parsing a
         // valid connection request. I.e. `b"connect 0\n".len()`.
         const MIN_READ_LEN: usize = 10;
 
-        // Bring in the minimum number of bytes that we should be able to read.
-        stream
-            .read_exact(&mut buf[..MIN_READ_LEN])
-            .map_err(VsockUnixBackendError::UnixRead)?;
-
-        // Now, finish reading the destination port number, by bringing in one byte at a time,
-        // until we reach an EOL terminator (or our buffer space runs out).  Yeah, not
-        // particularly proud of this approach, but it will have to do for now.
-        let mut blen = MIN_READ_LEN;
-        while buf[blen - 1] != b'\n' && blen < buf.len() {
+        let blen = if type_ == uapi::VSOCK_TYPE_SEQPACKET {
+            // The command is sent as a single message, which has to be read all at once.
+            let blen = stream
+                .read(&mut buf)
+                .map_err(VsockUnixBackendError::UnixRead)?;
+            if blen < MIN_READ_LEN {
+                return Err(VsockUnixBackendError::InvalidPortRequest);
+            }
+            blen
+        } else {
+            // Bring in the minimum number of bytes that we should be able to read.
             stream
-                .read_exact(&mut buf[blen..=blen])
+                .read_exact(&mut buf[..MIN_READ_LEN])
                 .map_err(VsockUnixBackendError::UnixRead)?;
-            blen += 1;
-        }
+
+            // Now, finish reading the destination port number, by bringing in one byte at a time,
+            // until we reach an EOL terminator (or our buffer space runs out).  Yeah, not
+            // particularly proud of this approach, but it will have to do for now.
+            let mut blen = MIN_READ_LEN;
+            while buf[blen - 1] != b'\n' && blen < buf.len() {
+                stream
+                    .read_exact(&mut buf[blen..=blen])
+                    .map_err(VsockUnixBackendError::UnixRead)?;
+                blen += 1;
+            }
+            blen
+        };
 
         let mut word_iter = std::str::from_utf8(&buf[..blen])
             .map_err(|_| VsockUnixBackendError::InvalidPortRequest)?
@@ -548,8 +703,10 @@ impl VsockMuxer {
     ) -> Result<(), VsockUnixBackendError> {
         let evset = match listener {
             EpollListener::Connection { evset, .. } => evset,
-            EpollListener::LocalStream(_) => EventSet::IN,
-            EpollListener::HostSock => EventSet::IN,
+            EpollListener::LocalStream(..) => EventSet::IN,
+            EpollListener::HostSock
+            | EpollListener::HostSeqpacketSock
+            | EpollListener::HostTcpSock(_) => EventSet::IN,
         };
 
         self.epoll
@@ -607,32 +764,47 @@ impl VsockMuxer {
     /// Handle a new connection request comming from our peer (the guest vsock driver).
     ///
     /// This will attempt to connect to a host-side Unix socket, expected to be listening at
-    /// the file system path corresponing to the destination port. If successful, a new
-    /// connection object will be created and added to the connection pool. On failure, a new
-    /// RST packet will be scheduled for delivery to the guest.
+    /// the file system path corresponing to the destination port, or to the host TCP address
+    /// the destination port is forwarded to. If successful, a new connection object will be
+    /// created and added to the connection pool. On failure, a new RST packet will be scheduled
+    /// for delivery to the guest.
     fn handle_peer_request_pkt(&mut self, pkt: &VsockPacketTx) {
         let port_path = format!("{}_{}", self.host_sock_path, pkt.hdr.dst_port());
-
-        UnixStream::connect(port_path)
-            .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
-            .map_err(VsockUnixBackendError::UnixConnect)
-            .and_then(|stream| {
-                self.add_connection(
-                    ConnMapKey {
-                        local_port: pkt.hdr.dst_port(),
-                        peer_port: pkt.hdr.src_port(),
-                    },
-                    MuxerConnection::new_peer_init(
-                        stream,
-                        uapi::VSOCK_HOST_CID,
-                        self.cid,
-                        pkt.hdr.dst_port(),
-                        pkt.hdr.src_port(),
-                        pkt.hdr.buf_alloc(),
-                    ),
-                )
-            })
-            .unwrap_or_else(|_| self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port()));
+        let tcp_addr = self.port_forwards.guest_forward_addr(pkt.hdr.dst_port());
+
+        match (pkt.hdr.type_(), tcp_addr) {
+            // The TCP connection is established in the background, and any error will reset the
+            // vsock connection.
+            (uapi::VSOCK_TYPE_STREAM, Some(addr)) => port_forward::tcp_connect(&addr)
+                .map(HostStream::Tcp)
+                .map_err(VsockUnixBackendError::TcpConnect),
+            (uapi::VSOCK_TYPE_SEQPACKET, _) => seqpacket::connect(&port_path)
+                .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
+                .map(HostStream::Unix)
+                .map_err(VsockUnixBackendError::UnixConnect),
+            _ => UnixStream::connect(port_path)
+                .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
+                .map(HostStream::Unix)
+                .map_err(VsockUnixBackendError::UnixConnect),
+        }
+        .and_then(|stream| {
+            self.add_connection(
+                ConnMapKey {
+                    local_port: pkt.hdr.dst_port(),
+                    peer_port: pkt.hdr.src_port(),
+                },
+                MuxerConnection::new_peer_init(
+                    stream,
+                    uapi::VSOCK_HOST_CID,
+                    self.cid,
+                    pkt.hdr.dst_port(),
+                    pkt.hdr.src_port(),
+                    pkt.hdr.buf_alloc(),
+                    pkt.hdr.type_(),
+                ),
+            )
+        })
+        .unwrap_or_else(|_| self.enq_rst(pkt.hdr.dst_port(), pkt.hdr.src_port(), pkt.hdr.type_()));
     }
 
     /// Perform an action that might mutate a connection's state.
@@ -654,8 +826,11 @@ impl VsockMuxer {
             mut_fn(conn);
 
             // If this is a host-initiated connection that has just become established, we'll have
-            // to send an ack message to the host end.
-            if prev_state == ConnState::LocalInit && conn.state() == ConnState::Established {
+            // to send an ack message to the host end. Forwarded TCP connections don't expect one.
+            if prev_state == ConnState::LocalInit
+                && conn.state() == ConnState::Established
+                && matches!(conn.stream(), HostStream::Unix(_))
+            {
                 let msg = format!("OK {}\n", key.local_port);
                 match conn.send_bytes_raw(msg.as_bytes()) {
                     Ok(written) if written == msg.len() => (),
@@ -772,10 +947,11 @@ impl VsockMuxer {
     /// Enqueue errors aren't propagated up the call chain, since there is nothing we can do to
     /// handle them. We do, however, log a warning, since not being able to enqueue an RST
     /// packet means we have to drop it, which is not normal operation.
-    fn enq_rst(&mut self, local_port: u32, peer_port: u32) {
+    fn enq_rst(&mut self, local_port: u32, peer_port: u32, type_: u16) {
         let pushed = self.rxq.push(MuxerRx::RstPkt {
             local_port,
             peer_port,
+            type_,
         });
         if !pushed {
             warn!(
@@ -789,6 +965,7 @@ impl VsockMuxer {
 #[cfg(test)]
 mod tests {
     use std::io::{Read, Write};
+    use std::net::TcpStream;
     use std::ops::Drop;
     use std::os::unix::net::{UnixListener, UnixStream};
     use std::path::{Path, PathBuf};
@@ -798,8 +975,8 @@ mod tests {
     use super::super::super::csm::defs as csm_defs;
     use super::*;
     use crate::devices::virtio::vsock::device::{RXQ_INDEX, TXQ_INDEX};
-    use crate::devices::virtio::vsock::test_utils;
     use crate::devices::virtio::vsock::test_utils::TestContext as VsockTestContext;
+    use crate::devices::virtio::vsock::{VsockGuestPortForward, VsockHostPortForward, test_utils};
 
     const PEER_CID: u64 = 3;
     const PEER_BUF_ALLOC: u32 = 64 * 1024;
@@ -816,6 +993,7 @@ mod tests {
     impl Drop for MuxerTestContext {
         fn drop(&mut self) {
             std::fs::remove_file(self.muxer.host_sock_path.as_str()).unwrap();
+            std::fs::remove_file(seqpacket_sock_path(&self.muxer.host_sock_path)).unwrap();
         }
     }
 
@@ -832,6 +1010,10 @@ mod tests {
 
     impl MuxerTestContext {
         fn new(name: &str) -> Self {
+            Self::new_with_forwards(name, VsockPortForwards::default())
+        }
+
+        fn new_with_forwards(name: &str, port_forwards: VsockPortForwards) -> Self {
             let vsock_test_ctx = VsockTestContext::new();
             let mut handler_ctx = vsock_test_ctx.create_event_handler_context();
             let mut rx_pkt = VsockPacketRx::new().unwrap();
@@ -849,7 +1031,7 @@ mod tests {
                 )
                 .unwrap();
 
-            let muxer = VsockMuxer::new(PEER_CID, get_file(name)).unwrap();
+            let muxer = VsockMuxer::new(PEER_CID, get_file(name), port_forwards).unwrap();
             Self {
                 _vsock_test_ctx: vsock_test_ctx,
                 rx_pkt,
@@ -900,12 +1082,19 @@ mod tests {
             self.muxer.notify(EventSet::IN);
         }
 
+        // Wait for an event to be available on the muxer's nested epoll FD. TCP loopback
+        // traffic may be processed asynchronously, unlike Unix socket traffic.
+        fn wait_muxer(&self) {
+            let mut events = [EpollEvent::new(EventSet::empty(), 0)];
+            assert_eq!(self.muxer.epoll.wait(1000, &mut events).unwrap(), 1);
+        }
+
         fn count_epoll_listeners(&self) -> (usize, usize) {
             let mut local_lsn_count = 0usize;
             let mut conn_lsn_count = 0usize;
             for key in self.muxer.listener_map.values() {
                 match key {
-                    EpollListener::LocalStream(_) => local_lsn_count += 1,
+                    EpollListener::LocalStream(..) => local_lsn_count += 1,
                     EpollListener::Connection { .. } => conn_lsn_count += 1,
                     _ => (),
                 };
@@ -1027,7 +1216,7 @@ mod tests {
     fn test_bad_peer_pkt() {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
-        const SOCK_DGRAM: u16 = 2;
+        const SOCK_DGRAM: u16 = 3;
 
         let mut ctx = MuxerTestContext::new("bad_peer_pkt");
         let tx_pkt = ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
@@ -1035,7 +1224,7 @@ mod tests {
         ctx.send();
 
         // The guest sent a SOCK_DGRAM packet. Per the vsock spec, we need to reply with an RST
-        // packet, since vsock only supports stream sockets.
+        // packet, since vsock only supports stream and seqpacket sockets.
         assert!(ctx.muxer.has_pending_rx());
         ctx.recv();
         assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
@@ -1534,4 +1723,296 @@ mod tests {
         // Check that the connection was removed.
         assert_eq!(METRICS.conns_removed.count(), conns_removed + 1);
     }
+
+    #[test]
+    fn test_seqpacket_peer_connection() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("seqpacket_peer_connection");
+
+        // Test peer connection refused. The RST must have the type of the request, to be
+        // routed to the guest socket.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test peer connection accepted.
+        let port_path = format!("{}_{}", ctx.muxer.host_sock_path, LOCAL_PORT);
+        let listener = SeqpacketListener::bind(&port_path).unwrap();
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        let mut stream = listener.accept().unwrap();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test guest -> host data flow. The message is written once its last part arrives.
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[1, 2])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET)
+            .set_flags(0);
+        ctx.send();
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &[3])
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET)
+            .set_flags(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+
+        // Test host -> guest data flow.
+        stream.write_all(&[4, 5, 6]).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(
+            ctx.rx_pkt.hdr.flags(),
+            uapi::VSOCK_FLAGS_SEQ_EOM | uapi::VSOCK_FLAGS_SEQ_EOR
+        );
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 3), [4, 5, 6]);
+        assert!(!ctx.muxer.has_pending_rx());
+
+        std::fs::remove_file(port_path).unwrap();
+    }
+
+    #[test]
+    fn test_seqpacket_local_connection() {
+        let peer_port = 1025;
+        let mut ctx = MuxerTestContext::new("seqpacket_local_connection");
+
+        let mut stream =
+            seqpacket::connect(&seqpacket_sock_path(&ctx.muxer.host_sock_path)).unwrap();
+        ctx.notify_muxer();
+        let (local_lsn_count, _) = ctx.count_epoll_listeners();
+        assert_eq!(local_lsn_count, 1);
+
+        // The connect command is sent as a single message.
+        stream
+            .write_all(format!("CONNECT {}\n", peer_port).as_bytes())
+            .unwrap();
+        ctx.notify_muxer();
+        let local_port = ctx.muxer.local_port_last;
+        let key = ConnMapKey {
+            local_port,
+            peer_port,
+        };
+        assert_eq!(ctx.muxer.conn_map[&key].type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_REQUEST);
+        assert_eq!(ctx.rx_pkt.hdr.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.init_tx_pkt(local_port, peer_port, uapi::VSOCK_OP_RESPONSE)
+            .hdr
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+
+        let mut buf = [0u8; 32];
+        let len = stream.read(&mut buf[..]).unwrap();
+        assert_eq!(&buf[..len], format!("OK {}\n", local_port).as_bytes());
+
+        // Each host message is delivered as a record of its own.
+        stream.write_all(&[1, 2]).unwrap();
+        stream.write_all(&[3]).unwrap();
+        for data in [&[1u8, 2][..], &[3][..]] {
+            ctx.notify_muxer();
+            ctx.recv();
+            assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+            assert_eq!(
+                ctx.rx_pkt.hdr.flags(),
+                uapi::VSOCK_FLAGS_SEQ_EOM | uapi::VSOCK_FLAGS_SEQ_EOR
+            );
+            assert_eq!(
+                test_utils::read_packet_data(&ctx.tx_pkt, ctx.rx_pkt.hdr.len()),
+                data
+            );
+        }
+    }
+
+    #[test]
+    fn test_port_forwards_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let host_addr = listener.local_addr().unwrap();
+
+        let port_forwards = VsockPortForwards {
+            guest_to_host: vec![
+                VsockGuestPortForward {
+                    vsock_port: 1026,
+                    host_addr,
+                };
+                2
+            ],
+            host_to_guest: vec![],
+        };
+        assert!(matches!(
+            VsockMuxer::new(PEER_CID, get_file("port_forwards_error"), port_forwards),
+            Err(VsockUnixBackendError::DuplicatePortForward(1026))
+        ));
+
+        // The host address is already in use.
+        let port_forwards = VsockPortForwards {
+            guest_to_host: vec![],
+            host_to_guest: vec![VsockHostPortForward {
+                host_addr,
+                vsock_port: 1025,
+            }],
+        };
+        let host_sock_path = get_file("port_forwards_error");
+        assert!(matches!(
+            VsockMuxer::new(PEER_CID, host_sock_path.clone(), port_forwards),
+            Err(VsockUnixBackendError::TcpBind(_))
+        ));
+        // No socket file is left behind.
+        assert!(!Path::new(&host_sock_path).exists());
+    }
+
+    #[test]
+    fn test_tcp_guest_forward() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port_forwards = VsockPortForwards {
+            guest_to_host: vec![VsockGuestPortForward {
+                vsock_port: LOCAL_PORT,
+                host_addr: listener.local_addr().unwrap(),
+            }],
+            host_to_guest: vec![],
+        };
+        let mut ctx = MuxerTestContext::new_with_forwards("tcp_guest_forward", port_forwards);
+
+        // The guest connection is forwarded to the TCP address, instead of a Unix socket.
+        ctx.init_tx_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RESPONSE);
+        let (mut stream, _) = listener.accept().unwrap();
+
+        // Test guest -> host data flow.
+        let data = [1, 2, 3, 4];
+        ctx.init_data_tx_pkt(LOCAL_PORT, PEER_PORT, &data);
+        ctx.send();
+        let mut buf = vec![0; data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+        assert_eq!(buf.as_slice(), data);
+
+        // Test host -> guest data flow.
+        let data = [5, 6, 7, 8];
+        stream.write_all(&data).unwrap();
+        ctx.wait_muxer();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.rx_pkt.hdr.src_port(), LOCAL_PORT);
+        assert_eq!(ctx.rx_pkt.hdr.dst_port(), PEER_PORT);
+        assert_eq!(test_utils::read_packet_data(&ctx.tx_pkt, 4), data);
+    }
+
+    #[test]
+    fn test_tcp_host_forward() {
+        const PEER_PORT: u32 = 1025;
+
+        let port_forwards = VsockPortForwards {
+            guest_to_host: vec![],
+            host_to_guest: vec![VsockHostPortForward {
+                host_addr: "127.0.0.1:0".parse().unwrap(),
+                vsock_port: PEER_PORT,
+            }],
+        };
+        let mut ctx = MuxerTestContext::new_with_forwards("tcp_host_forward", port_forwards);
+        assert_eq!(ctx.muxer.port_forwards().host_to_guest.len(), 1);
+
+        let mut stream =
+            TcpStream::connect(ctx.muxer.host_tcp_socks[0].local_addr().unwrap()).unwrap();
+        stream.set_nonblocking(true).unwrap();
+        ctx.wait_muxer();
+        ctx.notify_muxer();
+
+        // The connection is forwarded right away, without a connect command.
+        let (_, conn_lsn_count) = ctx.count_epoll_listeners();
+        assert_eq!(conn_lsn_count, 1);
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_REQUEST);
+        assert_eq!(ctx.rx_pkt.hdr.dst_port(), PEER_PORT);
+        let local_port = ctx.rx_pkt.hdr.src_port();
+
+        ctx.init_tx_pkt(local_port, PEER_PORT, uapi::VSOCK_OP_RESPONSE);
+        ctx.send();
+        let key = ConnMapKey {
+            local_port,
+            peer_port: PEER_PORT,
+        };
+        assert_eq!(ctx.muxer.conn_map[&key].state(), ConnState::Established);
+
+        // No ack message is sent to the TCP peer.
+        let mut buf = [0u8; 4];
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            std::io::ErrorKind::WouldBlock
+        );
+
+        // Test guest -> host data flow.
+        let data = [1, 2, 3, 4];
+        ctx.init_data_tx_pkt(local_port, PEER_PORT, &data);
+        ctx.send();
+        stream.set_nonblocking(false).unwrap();
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data);
+
+        // The guest refusing a connection closes the TCP stream.
+        let mut stream =
+            TcpStream::connect(ctx.muxer.host_tcp_socks[0].local_addr().unwrap()).unwrap();
+        ctx.wait_muxer();
+        ctx.notify_muxer();
+        ctx.recv();
+        assert_eq!(ctx.rx_pkt.hdr.op(), uapi::VSOCK_OP_REQUEST);
+        let local_port = ctx.rx_pkt.hdr.src_port();
+        ctx.init_tx_pkt(local_port, PEER_PORT, uapi::VSOCK_OP_RST);
+        ctx.send();
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_tcp_host_forward_connection_limit() {
+        const PEER_PORT: u32 = 1025;
+
+        let port_forwards = VsockPortForwards {
+            guest_to_host: vec![],
+            host_to_guest: vec![VsockHostPortForward {
+                host_addr: "127.0.0.1:0".parse().unwrap(),
+                vsock_port: PEER_PORT,
+            }],
+        };
+        let mut ctx =
+            MuxerTestContext::new_with_forwards("tcp_host_forward_connection_limit", port_forwards);
+        let host_addr = ctx.muxer.host_tcp_socks[0].local_addr().unwrap();
+
+        // Fill up the connection map.
+        let mut streams: Vec<TcpStream> = Vec::new();
+        for _ in 0..defs::MAX_CONNECTIONS {
+            streams.push(TcpStream::connect(host_addr).unwrap());
+            ctx.wait_muxer();
+            ctx.notify_muxer();
+        }
+        assert_eq!(ctx.muxer.conn_map.len(), defs::MAX_CONNECTIONS);
+
+        // Any new connection is accepted and immediately closed.
+        let mut stream = TcpStream::connect(host_addr).unwrap();
+        ctx.wait_muxer();
+        ctx.notify_muxer();
+        assert_eq!(ctx.muxer.conn_map.len(), defs::MAX_CONNECTIONS);
+        let mut buf = [0u8; 1];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+    }
 }
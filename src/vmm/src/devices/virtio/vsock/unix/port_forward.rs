@@ -0,0 +1,209 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Port forwarding between guest vsock ports and host TCP addresses.
+//!
+//! Guest connections to a forwarded vsock port (of the host CID) are bridged to a TCP
+//! connection to the configured host address, instead of to the `<uds_path>_<port>` Unix
+//! socket. TCP connections accepted on a forwarded host address are bridged to a vsock
+//! connection to the configured guest port, without the "CONNECT <port>" handshake required on
+//! `uds_path`.
+
+use std::collections::HashSet;
+use std::io::Error;
+use std::net::{SocketAddr, TcpStream};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use serde::{Deserialize, Serialize};
+
+use super::VsockUnixBackendError;
+
+/// Forwarding of guest connections to a host TCP address.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockGuestPortForward {
+    /// The vsock port, of the host CID, the guest connects to.
+    pub vsock_port: u32,
+    /// The host TCP address the guest connections are forwarded to.
+    pub host_addr: SocketAddr,
+}
+
+/// Forwarding of host TCP connections to a guest vsock port.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockHostPortForward {
+    /// The host TCP address to listen on for connections.
+    pub host_addr: SocketAddr,
+    /// The guest vsock port the host connections are forwarded to.
+    pub vsock_port: u32,
+}
+
+/// The port forwarding table of a vsock device.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockPortForwards {
+    /// Guest connections forwarded to host TCP addresses.
+    #[serde(default)]
+    pub guest_to_host: Vec<VsockGuestPortForward>,
+    /// Host TCP connections forwarded to guest vsock ports.
+    #[serde(default)]
+    pub host_to_guest: Vec<VsockHostPortForward>,
+}
+
+impl VsockPortForwards {
+    /// Check whether the table has no forwarding rules.
+    pub fn is_empty(&self) -> bool {
+        self.guest_to_host.is_empty() && self.host_to_guest.is_empty()
+    }
+
+    /// Check that each vsock port of the host CID is forwarded at most once.
+    pub fn validate(&self) -> Result<(), VsockUnixBackendError> {
+        let mut ports = HashSet::new();
+        match self
+            .guest_to_host
+            .iter()
+            .find(|fwd| !ports.insert(fwd.vsock_port))
+        {
+            Some(fwd) => Err(VsockUnixBackendError::DuplicatePortForward(fwd.vsock_port)),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the host TCP address the guest connections to `vsock_port` are forwarded to, if any.
+    pub fn guest_forward_addr(&self, vsock_port: u32) -> Option<SocketAddr> {
+        self.guest_to_host
+            .iter()
+            .find(|fwd| fwd.vsock_port == vsock_port)
+            .map(|fwd| fwd.host_addr)
+    }
+}
+
+/// Start connecting a nonblocking TCP stream to `addr`.
+///
+/// The connection is established in the background: until then, reads and writes fail with
+/// `EWOULDBLOCK`, and if it can't be established, they fail with the connection error.
+pub fn tcp_connect(addr: &SocketAddr) -> Result<TcpStream, Error> {
+    let domain = match addr {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    // SAFETY: Safe because the arguments are valid, and the return value is checked.
+    let fd = unsafe { libc::socket(domain, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    // SAFETY: `fd` is a newly created file descriptor, owned by nobody else.
+    let stream = TcpStream::from(unsafe { OwnedFd::from_raw_fd(fd) });
+    stream.set_nonblocking(true)?;
+
+    let ret = match addr {
+        SocketAddr::V4(addr) => {
+            // SAFETY: `sockaddr_in` is a plain C struct, for which all zeroes is a valid value.
+            let mut sin: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+            sin.sin_family = libc::sa_family_t::try_from(libc::AF_INET).unwrap();
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            // SAFETY: Safe because `sin` is a valid `sockaddr_in`, whose size is passed along,
+            // and the return value is checked.
+            unsafe {
+                libc::connect(
+                    stream.as_raw_fd(),
+                    std::ptr::from_ref(&sin).cast(),
+                    libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_in>()).unwrap(),
+                )
+            }
+        }
+        SocketAddr::V6(addr) => {
+            // SAFETY: `sockaddr_in6` is a plain C struct, for which all zeroes is a valid value.
+            let mut sin6: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+            sin6.sin6_family = libc::sa_family_t::try_from(libc::AF_INET6).unwrap();
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_scope_id = addr.scope_id();
+            // SAFETY: Safe because `sin6` is a valid `sockaddr_in6`, whose size is passed along,
+            // and the return value is checked.
+            unsafe {
+                libc::connect(
+                    stream.as_raw_fd(),
+                    std::ptr::from_ref(&sin6).cast(),
+                    libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_in6>()).unwrap(),
+                )
+            }
+        }
+    };
+    if ret < 0 {
+        let err = Error::last_os_error();
+        if err.raw_os_error() != Some(libc::EINPROGRESS) {
+            return Err(err);
+        }
+    }
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn test_port_forwards() {
+        let mut forwards: VsockPortForwards = serde_json::from_str(
+            r#"{
+                "guest_to_host": [{"vsock_port": 52, "host_addr": "127.0.0.1:8080"}],
+                "host_to_guest": [{"host_addr": "[::1]:2222", "vsock_port": 22}]
+            }"#,
+        )
+        .unwrap();
+        assert!(!forwards.is_empty());
+        forwards.validate().unwrap();
+        assert_eq!(
+            forwards.guest_forward_addr(52),
+            Some(SocketAddr::from(([127, 0, 0, 1], 8080)))
+        );
+        assert_eq!(forwards.guest_forward_addr(22), None);
+
+        forwards.guest_to_host.push(VsockGuestPortForward {
+            vsock_port: 52,
+            host_addr: SocketAddr::from(([127, 0, 0, 1], 8081)),
+        });
+        assert!(matches!(
+            forwards.validate(),
+            Err(VsockUnixBackendError::DuplicatePortForward(52))
+        ));
+
+        assert!(VsockPortForwards::default().is_empty());
+        serde_json::from_str::<VsockPortForwards>(
+            r#"{"guest_to_host": [{"vsock_port": 52, "host_addr": "127.0.0.1"}]}"#,
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_tcp_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut stream = tcp_connect(&addr).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        stream.set_nonblocking(false).unwrap();
+        stream.write_all(&[1, 2, 3]).unwrap();
+        let mut buf = [0u8; 3];
+        peer.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+
+        // Connection errors are reported either right away, or by the stream.
+        drop(listener);
+        let err = match tcp_connect(&addr) {
+            Ok(mut stream) => {
+                stream.set_nonblocking(false).unwrap();
+                stream.read(&mut buf).unwrap_err()
+            }
+            Err(err) => err,
+        };
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    }
+}
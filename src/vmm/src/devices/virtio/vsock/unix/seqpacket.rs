@@ -0,0 +1,172 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+/// Helpers for SOCK_SEQPACKET Unix sockets, which the standard library doesn't support.
+///
+/// Connected sockets are handed out as `UnixStream` objects: `read()` and `write()` use the
+/// same syscalls for both socket types, each call transferring a single message for
+/// SOCK_SEQPACKET sockets.
+use std::io::{Error, ErrorKind};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+/// Maximum number of pending connections on a listening socket.
+const LISTEN_BACKLOG: libc::c_int = 128;
+
+/// Build the address of the Unix socket at `path`.
+fn sockaddr_un(path: &str) -> Result<libc::sockaddr_un, Error> {
+    // SAFETY: `sockaddr_un` is a plain C struct, for which all zeroes is a valid value.
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::sa_family_t::try_from(libc::AF_UNIX).unwrap();
+
+    // The path needs to be NUL-terminated.
+    if path.len() >= addr.sun_path.len() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "path must be shorter than SUN_LEN",
+        ));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(path.as_bytes()) {
+        *dst = libc::c_char::from_ne_bytes([*src]);
+    }
+    Ok(addr)
+}
+
+/// Create a new SOCK_SEQPACKET Unix socket.
+fn socket() -> Result<OwnedFd, Error> {
+    // SAFETY: Safe because the arguments are valid, and the return value is checked.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(Error::last_os_error());
+    }
+    // SAFETY: `fd` is a newly created file descriptor, owned by nobody else.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Connect to the SOCK_SEQPACKET Unix socket listening at `path`.
+pub fn connect(path: &str) -> Result<UnixStream, Error> {
+    let fd = socket()?;
+    let addr = sockaddr_un(path)?;
+    // SAFETY: Safe because `addr` is a valid `sockaddr_un`, whose size is passed along, and the
+    // return value is checked.
+    let ret = unsafe {
+        libc::connect(
+            fd.as_raw_fd(),
+            std::ptr::from_ref(&addr).cast(),
+            libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_un>()).unwrap(),
+        )
+    };
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(UnixStream::from(fd))
+}
+
+/// A SOCK_SEQPACKET Unix socket, listening for connections.
+#[derive(Debug)]
+pub struct SeqpacketListener {
+    fd: OwnedFd,
+}
+
+impl SeqpacketListener {
+    /// Create a socket listening at `path`.
+    pub fn bind(path: &str) -> Result<Self, Error> {
+        let fd = socket()?;
+        let addr = sockaddr_un(path)?;
+        // SAFETY: Safe because `addr` is a valid `sockaddr_un`, whose size is passed along, and
+        // the return value is checked.
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                std::ptr::from_ref(&addr).cast(),
+                libc::socklen_t::try_from(std::mem::size_of::<libc::sockaddr_un>()).unwrap(),
+            )
+        };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        // SAFETY: Safe because `fd` is a valid socket, and the return value is checked.
+        if unsafe { libc::listen(fd.as_raw_fd(), LISTEN_BACKLOG) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
+    /// Accept a new connection.
+    pub fn accept(&self) -> Result<UnixStream, Error> {
+        // SAFETY: Safe because `self.fd` is a valid socket, the peer address isn't requested,
+        // and the return value is checked.
+        let fd = unsafe {
+            libc::accept4(
+                self.fd.as_raw_fd(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                libc::SOCK_CLOEXEC,
+            )
+        };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        // SAFETY: `fd` is a newly accepted file descriptor, owned by nobody else.
+        Ok(UnixStream::from(unsafe { OwnedFd::from_raw_fd(fd) }))
+    }
+
+    /// Move the socket into or out of nonblocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), Error> {
+        let mut nonblocking = libc::c_int::from(nonblocking);
+        // SAFETY: Safe because `self.fd` is a valid socket, `FIONBIO` takes a pointer to a valid
+        // `c_int`, and the return value is checked.
+        if unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::FIONBIO, &mut nonblocking) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl AsRawFd for SeqpacketListener {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_seqpacket() {
+        let tmp_dir = TempDir::new().unwrap();
+        let path = format!("{}/seqpacket.sock", tmp_dir.as_path().to_str().unwrap());
+
+        assert_eq!(
+            sockaddr_un(&"a".repeat(108)).unwrap_err().kind(),
+            ErrorKind::InvalidInput
+        );
+        connect(&path).unwrap_err();
+
+        let listener = SeqpacketListener::bind(&path).unwrap();
+        SeqpacketListener::bind(&path).unwrap_err();
+        listener.set_nonblocking(true).unwrap();
+        assert_eq!(listener.accept().unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        let mut client = connect(&path).unwrap();
+        let mut server = listener.accept().unwrap();
+
+        // Message boundaries are preserved.
+        client.write_all(&[1, 2, 3]).unwrap();
+        client.write_all(&[4, 5]).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(server.read(&mut buf).unwrap(), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+        assert_eq!(server.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf[..2], [4, 5]);
+
+        drop(client);
+        assert_eq!(server.read(&mut buf).unwrap(), 0);
+    }
+}
@@ -10,9 +10,11 @@ use std::ops::Index;
 
 pub use crate::dumbo::pdu::arp::{ETH_IPV4_FRAME_LEN, EthIPv4ArpFrame};
 pub use crate::dumbo::pdu::ethernet::{
-    ETHERTYPE_ARP, ETHERTYPE_IPV4, EthernetFrame, PAYLOAD_OFFSET as ETHERNET_PAYLOAD_OFFSET,
+    ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6, EthernetFrame,
+    PAYLOAD_OFFSET as ETHERNET_PAYLOAD_OFFSET,
 };
 pub use crate::dumbo::pdu::ipv4::{IPv4Packet, PROTOCOL_TCP, PROTOCOL_UDP};
+pub use crate::dumbo::pdu::ipv6::{IPv6Packet, PROTOCOL_ICMPV6};
 pub use crate::dumbo::pdu::udp::{UDP_HEADER_SIZE, UdpDatagram};
 use crate::utils::net::mac::MacAddr;
 
@@ -0,0 +1,487 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing the ICMPv6 messages used by the Neighbor Discovery
+//! Protocol (NDP) to resolve IPv6 addresses into link-layer addresses.
+//!
+//! Only Neighbor Solicitation and Neighbor Advertisement messages are supported, which are the
+//! IPv6 counterparts of ARP requests and replies. Their layout is described in [RFC 4861].
+//!
+//! [RFC 4861]: https://www.rfc-editor.org/rfc/rfc4861#section-4.3
+
+use std::fmt::Debug;
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use bitflags::bitflags;
+
+use super::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use super::ipv6::{self, IPv6Packet, PROTOCOL_ICMPV6};
+use super::{ChecksumProto, ethernet};
+use crate::dumbo::MacAddr;
+use crate::utils::net::mac::MAC_ADDR_LEN;
+
+const TYPE_OFFSET: usize = 0;
+const CODE_OFFSET: usize = 1;
+const CHECKSUM_OFFSET: usize = 2;
+const FLAGS_OFFSET: usize = 4;
+const TARGET_ADDRESS_OFFSET: usize = 8;
+const OPTIONS_OFFSET: usize = 24;
+
+const OPTION_TYPE_SOURCE_LINK_LAYER_ADDRESS: u8 = 1;
+const OPTION_TYPE_TARGET_LINK_LAYER_ADDRESS: u8 = 2;
+// Option lengths are expressed in units of 8 octets.
+const OPTION_LEN_UNIT: usize = 8;
+// The length of a link-layer address option for Ethernet, in units of 8 octets.
+const OPTION_LEN_ETHERNET_LINK_LAYER_ADDRESS: u8 = 1;
+
+/// ICMPv6 type of Neighbor Solicitation messages.
+pub const TYPE_NEIGHBOR_SOLICITATION: u8 = 135;
+/// ICMPv6 type of Neighbor Advertisement messages.
+pub const TYPE_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// Length of a Neighbor Solicitation or Advertisement message without any options.
+pub const NDP_MESSAGE_HEADER_LEN: usize = OPTIONS_OFFSET;
+/// Length of a Neighbor Solicitation or Advertisement message carrying a single Ethernet
+/// link-layer address option.
+pub const NDP_MESSAGE_LEN: usize = OPTIONS_OFFSET + OPTION_LEN_UNIT;
+
+bitflags! {
+    /// Represents the flags of a Neighbor Advertisement message.
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct AdvertisementFlags: u8 {
+        /// The sender is a router.
+        const ROUTER = 1 << 7;
+        /// The advertisement was sent in response to a Neighbor Solicitation.
+        const SOLICITED = 1 << 6;
+        /// The advertisement should override an existing cache entry.
+        const OVERRIDE = 1 << 5;
+    }
+}
+
+/// Describes the errors which may occur when handling NDP messages.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum NdpError {
+    /// Invalid checksum.
+    Checksum,
+    /// Invalid code.
+    Code,
+    /// Unexpected ICMPv6 message type.
+    MessageType,
+    /// The specified byte sequence is shorter than the NDP message length.
+    SliceTooShort,
+}
+
+/// Interprets the inner bytes as an NDP Neighbor Solicitation or Advertisement message.
+#[derive(Debug)]
+pub struct NdpMessage<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<T: NetworkBytes + Debug> NdpMessage<'_, T> {
+    /// Interprets the given bytes as an NDP message, without doing any validity checks
+    /// beforehand.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        NdpMessage {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Tries to interpret a byte slice as a valid Neighbor Solicitation message.
+    ///
+    /// The checksum is verified when `verify_checksum` holds the source and destination addresses
+    /// of the enclosing IPv6 packet.
+    pub fn solicitation_from_bytes(
+        bytes: T,
+        verify_checksum: Option<(Ipv6Addr, Ipv6Addr)>,
+    ) -> Result<Self, NdpError> {
+        if bytes.len() < NDP_MESSAGE_HEADER_LEN {
+            return Err(NdpError::SliceTooShort);
+        }
+
+        let message = NdpMessage::from_bytes_unchecked(bytes);
+
+        if message.msg_type() != TYPE_NEIGHBOR_SOLICITATION {
+            return Err(NdpError::MessageType);
+        }
+
+        if message.code() != 0 {
+            return Err(NdpError::Code);
+        }
+
+        if let Some((src_addr, dst_addr)) = verify_checksum {
+            if message.compute_checksum(src_addr, dst_addr) != 0 {
+                return Err(NdpError::Checksum);
+            }
+        }
+
+        Ok(message)
+    }
+
+    /// Returns the ICMPv6 type of the message.
+    #[inline]
+    pub fn msg_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the ICMPv6 code of the message.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.bytes[CODE_OFFSET]
+    }
+
+    /// Returns the value of the `checksum` field.
+    #[inline]
+    pub fn checksum(&self) -> u16 {
+        self.bytes.ntohs_unchecked(CHECKSUM_OFFSET)
+    }
+
+    /// Returns the flags of the message (only meaningful for Neighbor Advertisements).
+    #[inline]
+    pub fn flags(&self) -> AdvertisementFlags {
+        AdvertisementFlags::from_bits_truncate(self.bytes[FLAGS_OFFSET])
+    }
+
+    /// Returns the target address of the message.
+    #[inline]
+    pub fn target_address(&self) -> Ipv6Addr {
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&self.bytes[TARGET_ADDRESS_OFFSET..OPTIONS_OFFSET]);
+        Ipv6Addr::from(octets)
+    }
+
+    /// Returns the link-layer address carried by the source link-layer address option, if present.
+    #[inline]
+    pub fn source_link_layer_address(&self) -> Option<MacAddr> {
+        self.link_layer_address_option(OPTION_TYPE_SOURCE_LINK_LAYER_ADDRESS)
+    }
+
+    /// Returns the link-layer address carried by the target link-layer address option, if present.
+    #[inline]
+    pub fn target_link_layer_address(&self) -> Option<MacAddr> {
+        self.link_layer_address_option(OPTION_TYPE_TARGET_LINK_LAYER_ADDRESS)
+    }
+
+    // Looks for an Ethernet link-layer address option of the given type. Stops at the first
+    // malformed option.
+    fn link_layer_address_option(&self, option_type: u8) -> Option<MacAddr> {
+        let mut offset = OPTIONS_OFFSET;
+        while offset + 2 <= self.bytes.len() {
+            let option_len = usize::from(self.bytes[offset + 1]) * OPTION_LEN_UNIT;
+            if option_len == 0 || offset + option_len > self.bytes.len() {
+                return None;
+            }
+            if self.bytes[offset] == option_type
+                && option_len
+                    == usize::from(OPTION_LEN_ETHERNET_LINK_LAYER_ADDRESS) * OPTION_LEN_UNIT
+            {
+                return Some(MacAddr::from_bytes_unchecked(
+                    &self.bytes[offset + 2..offset + 2 + usize::from(MAC_ADDR_LEN)],
+                ));
+            }
+            offset += option_len;
+        }
+        None
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Computes the ICMPv6 checksum of the message, based on the addresses of the enclosing IPv6
+    /// packet.
+    pub fn compute_checksum(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> u16 {
+        crate::dumbo::pdu::compute_checksum_ipv6(
+            &self.bytes,
+            src_addr,
+            dst_addr,
+            ChecksumProto::Icmpv6,
+        )
+    }
+}
+
+impl<T: NetworkBytesMut + Debug> NdpMessage<'_, T> {
+    #[allow(clippy::too_many_arguments)]
+    fn write_raw(
+        buf: T,
+        msg_type: u8,
+        flags: AdvertisementFlags,
+        target: Ipv6Addr,
+        option_type: u8,
+        mac: MacAddr,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Self, NdpError> {
+        if buf.len() < NDP_MESSAGE_LEN {
+            return Err(NdpError::SliceTooShort);
+        }
+
+        // This is ok, because we've checked the length of the slice.
+        let mut message = NdpMessage::from_bytes_unchecked(buf);
+        message.bytes.shrink_unchecked(NDP_MESSAGE_LEN);
+
+        message
+            .set_msg_type(msg_type)
+            .set_code(0)
+            .set_checksum(0)
+            .set_flags(flags)
+            .set_target_address(target);
+        // The reserved bytes following the flags must be 0.
+        message.bytes[FLAGS_OFFSET + 1..TARGET_ADDRESS_OFFSET].fill(0);
+
+        message.bytes[OPTIONS_OFFSET] = option_type;
+        message.bytes[OPTIONS_OFFSET + 1] = OPTION_LEN_ETHERNET_LINK_LAYER_ADDRESS;
+        message.bytes[OPTIONS_OFFSET + 2..NDP_MESSAGE_LEN].copy_from_slice(mac.get_bytes());
+
+        let checksum = message.compute_checksum(src_addr, dst_addr);
+        message.set_checksum(checksum);
+
+        Ok(message)
+    }
+
+    /// Attempts to write a Neighbor Solicitation for `target` to `buf`, which carries the
+    /// source link-layer address option. The checksum is computed based on the addresses of the
+    /// enclosing IPv6 packet.
+    #[inline]
+    pub fn write_solicitation(
+        buf: T,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+        target: Ipv6Addr,
+        source_mac: MacAddr,
+    ) -> Result<Self, NdpError> {
+        Self::write_raw(
+            buf,
+            TYPE_NEIGHBOR_SOLICITATION,
+            AdvertisementFlags::empty(),
+            target,
+            OPTION_TYPE_SOURCE_LINK_LAYER_ADDRESS,
+            source_mac,
+            src_addr,
+            dst_addr,
+        )
+    }
+
+    /// Attempts to write a Neighbor Advertisement for `target` to `buf`, which carries the
+    /// target link-layer address option. The checksum is computed based on the addresses of the
+    /// enclosing IPv6 packet.
+    #[inline]
+    pub fn write_advertisement(
+        buf: T,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+        flags: AdvertisementFlags,
+        target: Ipv6Addr,
+        target_mac: MacAddr,
+    ) -> Result<Self, NdpError> {
+        Self::write_raw(
+            buf,
+            TYPE_NEIGHBOR_ADVERTISEMENT,
+            flags,
+            target,
+            OPTION_TYPE_TARGET_LINK_LAYER_ADDRESS,
+            target_mac,
+            src_addr,
+            dst_addr,
+        )
+    }
+
+    /// Sets the ICMPv6 type of the message.
+    #[inline]
+    pub fn set_msg_type(&mut self, value: u8) -> &mut Self {
+        self.bytes[TYPE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the ICMPv6 code of the message.
+    #[inline]
+    pub fn set_code(&mut self, value: u8) -> &mut Self {
+        self.bytes[CODE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `checksum` field.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(CHECKSUM_OFFSET, value);
+        self
+    }
+
+    /// Sets the flags of the message.
+    #[inline]
+    pub fn set_flags(&mut self, flags: AdvertisementFlags) -> &mut Self {
+        self.bytes[FLAGS_OFFSET] = flags.bits();
+        self
+    }
+
+    /// Sets the target address of the message.
+    #[inline]
+    pub fn set_target_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[TARGET_ADDRESS_OFFSET..OPTIONS_OFFSET].copy_from_slice(&addr.octets());
+        self
+    }
+}
+
+/// This function checks if `buf` may hold a Neighbor Solicitation for the given target address,
+/// carried by an IPv6 packet without extension headers. Cannot produce false negatives.
+#[inline]
+pub fn test_speculative_target_addr(buf: &[u8], addr: Ipv6Addr) -> bool {
+    // The unchecked methods are safe because we actually check the buffer length beforehand.
+    if buf.len() >= ethernet::PAYLOAD_OFFSET + ipv6::HEADER_LEN + NDP_MESSAGE_HEADER_LEN {
+        let bytes = &buf[ethernet::PAYLOAD_OFFSET..];
+        if IPv6Packet::from_bytes_unchecked(bytes).next_header() == PROTOCOL_ICMPV6 {
+            let message = NdpMessage::from_bytes_unchecked(&bytes[ipv6::HEADER_LEN..]);
+            if message.msg_type() == TYPE_NEIGHBOR_SOLICITATION && message.target_address() == addr
+            {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::dumbo::pdu::ethernet::{ETHERTYPE_IPV6, EthernetFrame};
+
+    const SRC_ADDR: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+    const DST_ADDR: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00, 0xfe);
+    const TARGET_ADDR: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xfe);
+
+    #[test]
+    fn test_ndp_message() {
+        let mut a = [0u8; 100];
+        let mut small = [0u8; NDP_MESSAGE_LEN - 1];
+        let mac = MacAddr::from_str("01:23:45:67:89:ab").unwrap();
+
+        assert_eq!(
+            NdpMessage::write_solicitation(small.as_mut(), SRC_ADDR, DST_ADDR, TARGET_ADDR, mac)
+                .unwrap_err(),
+            NdpError::SliceTooShort
+        );
+
+        let len = {
+            let ns =
+                NdpMessage::write_solicitation(a.as_mut(), SRC_ADDR, DST_ADDR, TARGET_ADDR, mac)
+                    .unwrap();
+            assert_eq!(ns.msg_type(), TYPE_NEIGHBOR_SOLICITATION);
+            assert_eq!(ns.code(), 0);
+            assert_eq!(ns.target_address(), TARGET_ADDR);
+            assert_eq!(ns.source_link_layer_address(), Some(mac));
+            assert_eq!(ns.target_link_layer_address(), None);
+            assert_eq!(ns.compute_checksum(SRC_ADDR, DST_ADDR), 0);
+            ns.len()
+        };
+        assert_eq!(len, NDP_MESSAGE_LEN);
+
+        let ns =
+            NdpMessage::solicitation_from_bytes(&a[..len], Some((SRC_ADDR, DST_ADDR))).unwrap();
+        assert_eq!(ns.target_address(), TARGET_ADDR);
+
+        // Messages without options are valid.
+        NdpMessage::solicitation_from_bytes(&a[..NDP_MESSAGE_HEADER_LEN], None).unwrap();
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(&a[..NDP_MESSAGE_HEADER_LEN - 1], None)
+                .unwrap_err(),
+            NdpError::SliceTooShort
+        );
+
+        // The checksum depends on the addresses of the enclosing packet.
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(&a[..len], Some((SRC_ADDR, TARGET_ADDR)))
+                .unwrap_err(),
+            NdpError::Checksum
+        );
+
+        NdpMessage::from_bytes_unchecked(a.as_mut()).set_code(1);
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(&a[..len], None).unwrap_err(),
+            NdpError::Code
+        );
+
+        // An option with a length of 0 is malformed.
+        a[OPTIONS_OFFSET + 1] = 0;
+        assert_eq!(
+            NdpMessage::from_bytes_unchecked(&a[..len]).source_link_layer_address(),
+            None
+        );
+
+        let len = NdpMessage::write_advertisement(
+            a.as_mut(),
+            TARGET_ADDR,
+            SRC_ADDR,
+            AdvertisementFlags::SOLICITED | AdvertisementFlags::OVERRIDE,
+            TARGET_ADDR,
+            mac,
+        )
+        .unwrap()
+        .len();
+
+        let na = NdpMessage::from_bytes_unchecked(&a[..len]);
+        assert_eq!(na.msg_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+        assert_eq!(
+            na.flags(),
+            AdvertisementFlags::SOLICITED | AdvertisementFlags::OVERRIDE
+        );
+        assert_eq!(na.target_address(), TARGET_ADDR);
+        assert_eq!(na.target_link_layer_address(), Some(mac));
+        assert_eq!(na.compute_checksum(TARGET_ADDR, SRC_ADDR), 0);
+
+        assert_eq!(
+            NdpMessage::solicitation_from_bytes(&a[..len], None).unwrap_err(),
+            NdpError::MessageType
+        );
+    }
+
+    #[test]
+    fn test_speculative() {
+        let mut a = [0u8; 1000];
+        let mac = MacAddr::from_bytes_unchecked(&[0; 6]);
+
+        assert!(!test_speculative_target_addr(a.as_ref(), TARGET_ADDR));
+
+        {
+            let mut eth =
+                EthernetFrame::write_incomplete(a.as_mut(), mac, mac, ETHERTYPE_IPV6).unwrap();
+            let mut ip = IPv6Packet::write_header(
+                eth.inner_mut().payload_mut(),
+                PROTOCOL_ICMPV6,
+                SRC_ADDR,
+                DST_ADDR,
+            )
+            .unwrap();
+            NdpMessage::write_solicitation(
+                ip.inner_mut().payload_mut(),
+                SRC_ADDR,
+                DST_ADDR,
+                TARGET_ADDR,
+                mac,
+            )
+            .unwrap();
+        }
+
+        assert!(test_speculative_target_addr(a.as_ref(), TARGET_ADDR));
+        assert!(!test_speculative_target_addr(a.as_ref(), SRC_ADDR));
+
+        // Only ICMPv6 packets may carry NDP messages.
+        IPv6Packet::from_bytes_unchecked(&mut a[ethernet::PAYLOAD_OFFSET..])
+            .set_next_header(crate::dumbo::pdu::ipv4::PROTOCOL_TCP);
+        assert!(!test_speculative_target_addr(a.as_ref(), TARGET_ADDR));
+
+        // Let's also test for a very small buffer.
+        let small = [0u8; 1];
+        assert!(!test_speculative_target_addr(small.as_ref(), TARGET_ADDR));
+    }
+}
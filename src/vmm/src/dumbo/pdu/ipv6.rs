@@ -0,0 +1,433 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing IPv6 packets.
+//!
+//! A picture of the IPv6 packet header can be found [here] (watch out for the MSB 0 bit numbering).
+//! Extension headers are not supported, so the payload always starts right after the fixed
+//! header.
+//!
+//! [here]: https://en.wikipedia.org/wiki/IPv6_packet#Fixed_header
+
+use std::fmt::Debug;
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use crate::dumbo::pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use crate::dumbo::pdu::{Incomplete, ethernet};
+
+const VERSION_TC_FLOW_OFFSET: usize = 0;
+const PAYLOAD_LEN_OFFSET: usize = 4;
+const NEXT_HEADER_OFFSET: usize = 6;
+const HOP_LIMIT_OFFSET: usize = 7;
+const SOURCE_ADDRESS_OFFSET: usize = 8;
+const DESTINATION_ADDRESS_OFFSET: usize = 24;
+
+const IPV6_ADDR_LEN: usize = 16;
+
+/// Length of the fixed IPv6 header, which is also the payload offset.
+pub const HEADER_LEN: usize = 40;
+
+/// Indicates version 6 of the IP protocol
+pub const IPV6_VERSION: u8 = 0x06;
+/// Default hop limit value. Neighbor Discovery messages are only accepted with a hop limit of 255,
+/// so we use that for every packet.
+pub const DEFAULT_HOP_LIMIT: u8 = 255;
+
+/// The next header value associated with ICMPv6.
+pub const PROTOCOL_ICMPV6: u8 = 0x3a;
+
+/// Describes the errors which may occur while handling IPv6 packets.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum Ipv6Error {
+    /// The payload length of the packet is invalid.
+    InvalidPayloadLen,
+    /// The length of the given slice does not match the length of the packet.
+    SliceExactLen,
+    /// The length of the given slice is less than the IPv6 header length.
+    SliceTooShort,
+    /// The version header field is invalid.
+    Version,
+}
+
+/// Interprets the inner bytes as an IPv6 packet.
+#[derive(Debug)]
+pub struct IPv6Packet<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<T: NetworkBytes + Debug> IPv6Packet<'_, T> {
+    /// Interpret `bytes` as an IPv6Packet without checking the validity of the header fields, and
+    /// the length of the inner byte sequence.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        IPv6Packet {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as an IPv6 packet, checking the validity of the header fields
+    /// and the length of the inner byte sequence.
+    pub fn from_bytes(bytes: T) -> Result<Self, Ipv6Error> {
+        let bytes_len = bytes.len();
+
+        if bytes_len < HEADER_LEN {
+            return Err(Ipv6Error::SliceTooShort);
+        }
+
+        let packet = IPv6Packet::from_bytes_unchecked(bytes);
+
+        if packet.version() != IPV6_VERSION {
+            return Err(Ipv6Error::Version);
+        }
+
+        // A payload length of 0 is used by jumbograms, which we don't support.
+        let payload_len = usize::from(packet.payload_len());
+        if payload_len == 0 {
+            return Err(Ipv6Error::InvalidPayloadLen);
+        }
+
+        if HEADER_LEN + payload_len != bytes_len {
+            return Err(Ipv6Error::SliceExactLen);
+        }
+
+        // Like for IPv4, we ignore the hop limit since only routers should care about it.
+
+        Ok(packet)
+    }
+
+    /// Returns the value of the `version` header field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.bytes[VERSION_TC_FLOW_OFFSET] >> 4
+    }
+
+    /// Returns the value of the `traffic class` header field.
+    #[inline]
+    pub fn traffic_class(&self) -> u8 {
+        (self.bytes[VERSION_TC_FLOW_OFFSET] << 4) | (self.bytes[VERSION_TC_FLOW_OFFSET + 1] >> 4)
+    }
+
+    /// Returns the value of the `flow label` header field.
+    #[inline]
+    pub fn flow_label(&self) -> u32 {
+        self.bytes.ntohl_unchecked(VERSION_TC_FLOW_OFFSET) & 0x000f_ffff
+    }
+
+    /// Returns the value of the `payload length` header field.
+    #[inline]
+    pub fn payload_len(&self) -> u16 {
+        self.bytes.ntohs_unchecked(PAYLOAD_LEN_OFFSET)
+    }
+
+    /// Returns the value of the `next header` header field.
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.bytes[NEXT_HEADER_OFFSET]
+    }
+
+    /// Returns the value of the `hop limit` header field.
+    #[inline]
+    pub fn hop_limit(&self) -> u8 {
+        self.bytes[HOP_LIMIT_OFFSET]
+    }
+
+    /// Returns the source IPv6 address of the packet.
+    #[inline]
+    pub fn source_address(&self) -> Ipv6Addr {
+        read_addr_unchecked(&self.bytes, SOURCE_ADDRESS_OFFSET)
+    }
+
+    /// Returns the destination IPv6 address of the packet.
+    #[inline]
+    pub fn destination_address(&self) -> Ipv6Addr {
+        read_addr_unchecked(&self.bytes, DESTINATION_ADDRESS_OFFSET)
+    }
+
+    /// Returns a byte slice that contains the payload of the packet.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        self.bytes.split_at(HEADER_LEN).1
+    }
+
+    /// Returns the length of the inner byte sequence.
+    ///
+    /// This is equal to the header length plus the output of the `payload_len()` method for
+    /// properly constructed instances of `IPv6Packet`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<T: NetworkBytesMut + Debug> IPv6Packet<'_, T> {
+    /// Attempts to write an IPv6 packet header to `buf`, making sure there is enough space.
+    ///
+    /// This method returns an incomplete packet, because the size of the payload might be unknown
+    /// at this point. The `traffic class` and `flow label` fields are set to 0, and the
+    /// `hop limit` is set to a default value. The `payload length` field will be set when the
+    /// length of the incomplete packet is determined.
+    pub fn write_header(
+        buf: T,
+        next_header: u8,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Incomplete<Self>, Ipv6Error> {
+        if buf.len() < HEADER_LEN {
+            return Err(Ipv6Error::SliceTooShort);
+        }
+        let mut packet = IPv6Packet::from_bytes_unchecked(buf);
+        packet
+            .set_version_tc_and_flow_label(IPV6_VERSION, 0, 0)
+            .set_next_header(next_header)
+            .set_hop_limit(DEFAULT_HOP_LIMIT)
+            .set_source_address(src_addr)
+            .set_destination_address(dst_addr);
+
+        Ok(Incomplete::new(packet))
+    }
+
+    /// Sets the values of the `version`, `traffic class` and `flow label` header fields.
+    #[inline]
+    pub fn set_version_tc_and_flow_label(
+        &mut self,
+        version: u8,
+        traffic_class: u8,
+        flow_label: u32,
+    ) -> &mut Self {
+        let value = (u32::from(version) << 28)
+            | (u32::from(traffic_class) << 20)
+            | (flow_label & 0x000f_ffff);
+        self.bytes.htonl_unchecked(VERSION_TC_FLOW_OFFSET, value);
+        self
+    }
+
+    /// Sets the value of the `payload length` header field.
+    #[inline]
+    pub fn set_payload_len(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(PAYLOAD_LEN_OFFSET, value);
+        self
+    }
+
+    /// Sets the value of the `next header` header field.
+    #[inline]
+    pub fn set_next_header(&mut self, value: u8) -> &mut Self {
+        self.bytes[NEXT_HEADER_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `hop limit` header field.
+    #[inline]
+    pub fn set_hop_limit(&mut self, value: u8) -> &mut Self {
+        self.bytes[HOP_LIMIT_OFFSET] = value;
+        self
+    }
+
+    /// Sets the source address of the packet.
+    #[inline]
+    pub fn set_source_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[SOURCE_ADDRESS_OFFSET..SOURCE_ADDRESS_OFFSET + IPV6_ADDR_LEN]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Sets the destination address of the packet.
+    #[inline]
+    pub fn set_destination_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[DESTINATION_ADDRESS_OFFSET..DESTINATION_ADDRESS_OFFSET + IPV6_ADDR_LEN]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Returns a mutable byte slice representing the payload of the packet.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.bytes.split_at_mut(HEADER_LEN).1
+    }
+}
+
+/// An incomplete packet is one where the payload length has not been determined yet.
+///
+/// It can be transformed into an `IPv6Packet` by specifying the size of the payload, and
+/// shrinking the inner byte sequence to be as large as the packet itself (this includes setting
+/// the `payload length` header field).
+impl<'a, T: NetworkBytesMut + Debug> Incomplete<IPv6Packet<'a, T>> {
+    /// Transforms `self` into an `IPv6Packet` based on the supplied payload length.
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `payload_len` is larger than the room left for the
+    /// payload in the inner byte sequence.
+    #[inline]
+    pub fn with_payload_len_unchecked(mut self, payload_len: u16) -> IPv6Packet<'a, T> {
+        let packet = &mut self.inner;
+        // This unchecked is fine as long as the total length is smaller than the length of the
+        // original slice, which should be the case if our code is not wrong.
+        packet
+            .bytes
+            .shrink_unchecked(HEADER_LEN + usize::from(payload_len));
+        packet.set_payload_len(payload_len);
+        self.inner
+    }
+}
+
+// Reads the IPv6 address found at `offset` in `bytes`.
+#[inline]
+fn read_addr_unchecked(bytes: &[u8], offset: usize) -> Ipv6Addr {
+    let mut octets = [0u8; IPV6_ADDR_LEN];
+    octets.copy_from_slice(&bytes[offset..offset + IPV6_ADDR_LEN]);
+    Ipv6Addr::from(octets)
+}
+
+/// This function checks if `buf` may hold an IPv6Packet heading towards the given address. Cannot
+/// produce false negatives.
+#[inline]
+pub fn test_speculative_dst_addr(buf: &[u8], addr: Ipv6Addr) -> bool {
+    // The unchecked methods are safe because we actually check the buffer length beforehand.
+    if buf.len() >= ethernet::PAYLOAD_OFFSET + HEADER_LEN {
+        let bytes = &buf[ethernet::PAYLOAD_OFFSET..];
+        if IPv6Packet::from_bytes_unchecked(bytes).destination_address() == addr {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dumbo::MacAddr;
+    use crate::dumbo::pdu::ipv4::PROTOCOL_TCP;
+
+    #[test]
+    fn test_set_get() {
+        let mut a = [0u8; 100];
+        let mut p = IPv6Packet::from_bytes_unchecked(a.as_mut());
+
+        assert_eq!(p.version(), 0);
+        assert_eq!(p.traffic_class(), 0);
+        assert_eq!(p.flow_label(), 0);
+        p.set_version_tc_and_flow_label(IPV6_VERSION, 0xab, 0x12345);
+        assert_eq!(p.version(), IPV6_VERSION);
+        assert_eq!(p.traffic_class(), 0xab);
+        assert_eq!(p.flow_label(), 0x12345);
+
+        assert_eq!(p.payload_len(), 0);
+        p.set_payload_len(123);
+        assert_eq!(p.payload_len(), 123);
+
+        assert_eq!(p.next_header(), 0);
+        p.set_next_header(PROTOCOL_ICMPV6);
+        assert_eq!(p.next_header(), PROTOCOL_ICMPV6);
+
+        assert_eq!(p.hop_limit(), 0);
+        p.set_hop_limit(64);
+        assert_eq!(p.hop_limit(), 64);
+
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0xfe);
+
+        assert_eq!(p.source_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_source_address(src);
+        assert_eq!(p.source_address(), src);
+
+        assert_eq!(p.destination_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_destination_address(dst);
+        assert_eq!(p.destination_address(), dst);
+
+        // The version, traffic class and flow label fields did not change.
+        assert_eq!(p.version(), IPV6_VERSION);
+        assert_eq!(p.traffic_class(), 0xab);
+        assert_eq!(p.flow_label(), 0x12345);
+
+        assert_eq!(p.payload_mut().len(), 100 - HEADER_LEN);
+    }
+
+    #[test]
+    fn test_constructors() {
+        let mut a = [0u8; 100];
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xfe);
+
+        // Not enough room for the header.
+        assert_eq!(
+            IPv6Packet::write_header(&mut a[..HEADER_LEN - 1], PROTOCOL_TCP, src, dst).unwrap_err(),
+            Ipv6Error::SliceTooShort
+        );
+
+        let payload_len = 30;
+        let len = {
+            let mut p = IPv6Packet::write_header(a.as_mut(), PROTOCOL_TCP, src, dst).unwrap();
+            p.inner_mut().payload_mut()[..payload_len].copy_from_slice(&[0xaa; 30]);
+            p.with_payload_len_unchecked(u16::try_from(payload_len).unwrap())
+                .len()
+        };
+        assert_eq!(len, HEADER_LEN + payload_len);
+
+        {
+            let p = IPv6Packet::from_bytes(&a[..len]).unwrap();
+            assert_eq!(p.version(), IPV6_VERSION);
+            assert_eq!(p.payload_len(), 30);
+            assert_eq!(p.next_header(), PROTOCOL_TCP);
+            assert_eq!(p.hop_limit(), DEFAULT_HOP_LIMIT);
+            assert_eq!(p.source_address(), src);
+            assert_eq!(p.destination_address(), dst);
+            assert_eq!(p.payload(), [0xaa; 30].as_ref());
+        }
+
+        // The slice is shorter than the header.
+        assert_eq!(
+            IPv6Packet::from_bytes(&a[..HEADER_LEN - 1]).unwrap_err(),
+            Ipv6Error::SliceTooShort
+        );
+        // The slice does not match the payload length.
+        assert_eq!(
+            IPv6Packet::from_bytes(&a[..len + 1]).unwrap_err(),
+            Ipv6Error::SliceExactLen
+        );
+
+        // Jumbograms are not supported.
+        IPv6Packet::from_bytes_unchecked(a.as_mut()).set_payload_len(0);
+        assert_eq!(
+            IPv6Packet::from_bytes(&a[..len]).unwrap_err(),
+            Ipv6Error::InvalidPayloadLen
+        );
+
+        // Only version 6 is accepted.
+        IPv6Packet::from_bytes_unchecked(a.as_mut())
+            .set_payload_len(30)
+            .set_version_tc_and_flow_label(4, 0, 0);
+        assert_eq!(
+            IPv6Packet::from_bytes(&a[..len]).unwrap_err(),
+            Ipv6Error::Version
+        );
+    }
+
+    #[test]
+    fn test_speculative() {
+        let mut a = [0u8; 1000];
+        let addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xfe);
+
+        assert!(!test_speculative_dst_addr(a.as_ref(), addr));
+
+        {
+            let mac = MacAddr::from_bytes_unchecked(&[0; 6]);
+            let mut eth =
+                ethernet::EthernetFrame::write_incomplete(a.as_mut(), mac, mac, 0).unwrap();
+            IPv6Packet::from_bytes_unchecked(eth.inner_mut().payload_mut())
+                .set_destination_address(addr);
+        }
+
+        assert!(test_speculative_dst_addr(a.as_ref(), addr));
+
+        // Let's also test for a very small buffer.
+        let small = [0u8; 1];
+        assert!(!test_speculative_dst_addr(small.as_ref(), addr));
+    }
+}
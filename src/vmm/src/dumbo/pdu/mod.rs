@@ -8,15 +8,18 @@
 //! units.
 
 use std::fmt::Debug;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use crate::dumbo::pdu::bytes::NetworkBytes;
 use crate::dumbo::pdu::ipv4::{PROTOCOL_TCP, PROTOCOL_UDP};
+use crate::dumbo::pdu::ipv6::PROTOCOL_ICMPV6;
 
 pub mod arp;
 pub mod bytes;
 pub mod ethernet;
+pub mod icmpv6;
 pub mod ipv4;
+pub mod ipv6;
 pub mod tcp;
 pub mod udp;
 
@@ -57,6 +60,7 @@ impl<T: Debug> Incomplete<T> {
 enum ChecksumProto {
     Tcp = PROTOCOL_TCP,
     Udp = PROTOCOL_UDP,
+    Icmpv6 = PROTOCOL_ICMPV6,
 }
 
 /// Computes the checksum of a TCP/UDP packet. Since both protocols use
@@ -88,6 +92,40 @@ fn compute_checksum<T: NetworkBytes + Debug>(
     sum += b & 0xffff;
     sum += b >> 16;
 
+    finalize_checksum(bytes, sum, protocol)
+}
+
+/// Computes the checksum of a TCP segment or an ICMPv6 message carried by an IPv6 packet.
+///
+/// The algorithm is the same as for IPv4, only the pseudo-header differs, as it contains the
+/// IPv6 source and destination addresses. More details can be found in [RFC 8200].
+///
+/// [RFC 8200]: https://www.rfc-editor.org/rfc/rfc8200#section-8.1
+#[inline]
+fn compute_checksum_ipv6<T: NetworkBytes + Debug>(
+    bytes: &T,
+    src_addr: Ipv6Addr,
+    dst_addr: Ipv6Addr,
+    protocol: ChecksumProto,
+) -> u16 {
+    let sum = src_addr
+        .segments()
+        .iter()
+        .chain(dst_addr.segments().iter())
+        .map(|segment| usize::from(*segment))
+        .sum();
+
+    finalize_checksum(bytes, sum, protocol)
+}
+
+// Adds the length and protocol fields of the pseudo-header, followed by the contents of `bytes`,
+// to the sum of the pseudo-header addresses, and returns the resulting checksum.
+#[inline]
+fn finalize_checksum<T: NetworkBytes + Debug>(
+    bytes: &T,
+    mut sum: usize,
+    protocol: ChecksumProto,
+) -> u16 {
     let len = bytes.len();
     sum += protocol as usize;
     sum += len;
@@ -9,7 +9,7 @@
 
 use std::cmp::min;
 use std::fmt::Debug;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroU16;
 use std::result::Result;
 
@@ -89,8 +89,8 @@ pub enum TcpError {
     SliceTooShort,
 }
 
-// TODO: The implementation of TcpSegment is IPv4 specific in regard to checksum computation. Maybe
-// make it more generic at some point.
+// The checksum related methods come in IPv4 and IPv6 flavours, because the checksum computation
+// depends on the addresses of the enclosing packet.
 
 /// Interprets the inner bytes as a TCP segment.
 #[derive(Debug)]
@@ -217,6 +217,16 @@ impl<T: NetworkBytes + Debug> TcpSegment<'_, T> {
         crate::dumbo::pdu::compute_checksum(&self.bytes, src_addr, dst_addr, ChecksumProto::Tcp)
     }
 
+    /// Computes the TCP checksum of a segment carried by an IPv6 packet.
+    pub fn compute_checksum_ipv6(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> u16 {
+        crate::dumbo::pdu::compute_checksum_ipv6(
+            &self.bytes,
+            src_addr,
+            dst_addr,
+            ChecksumProto::Tcp,
+        )
+    }
+
     /// Parses TCP header options (only `MSS` is supported for now).
     ///
     /// If no error is encountered, returns the `MSS` value, or `None` if the option is not
@@ -581,6 +591,28 @@ impl<'a, T: NetworkBytesMut + Debug> Incomplete<TcpSegment<'a, T>> {
         }
         self.inner
     }
+
+    /// Same as [`finalize`], but computes the TCP checksum (if requested) of a segment carried by
+    /// an IPv6 packet.
+    ///
+    /// [`finalize`]: #method.finalize
+    #[inline]
+    pub fn finalize_ipv6(
+        mut self,
+        src_port: u16,
+        dst_port: u16,
+        compute_checksum: Option<(Ipv6Addr, Ipv6Addr)>,
+    ) -> TcpSegment<'a, T> {
+        self.inner.set_source_port(src_port);
+        self.inner.set_destination_port(dst_port);
+        if let Some((src_addr, dst_addr)) = compute_checksum {
+            // Set this to 0 first.
+            self.inner.set_checksum(0);
+            let checksum = self.inner.compute_checksum_ipv6(src_addr, dst_addr);
+            self.inner.set_checksum(checksum);
+        }
+        self.inner
+    }
 }
 
 #[cfg(test)]
@@ -813,4 +845,43 @@ mod tests {
             TcpError::MssRemaining
         );
     }
+
+    #[test]
+    fn test_finalize_ipv6() {
+        let mut a = [0u8; 100];
+        let src_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst_addr = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 0xfe);
+        let payload = [0xabu8; 11];
+
+        let mut segment = TcpSegment::write_incomplete_segment(
+            a.as_mut(),
+            123,
+            456,
+            Flags::ACK | Flags::PSH,
+            10000,
+            None,
+            100,
+            Some((payload.as_ref(), payload.len())),
+        )
+        .unwrap()
+        .finalize_ipv6(1234, 80, Some((src_addr, dst_addr)));
+
+        assert_eq!(segment.source_port(), 1234);
+        assert_eq!(segment.destination_port(), 80);
+        assert_eq!(segment.payload(), payload.as_ref());
+
+        // A valid checksum makes the computation over the whole segment yield 0.
+        assert_eq!(segment.compute_checksum_ipv6(src_addr, dst_addr), 0);
+        assert_ne!(
+            segment.compute_checksum_ipv6(Ipv6Addr::LOCALHOST, dst_addr),
+            0
+        );
+
+        // The checksum is left untouched when not requested.
+        let checksum = segment.checksum();
+        segment.set_checksum(0);
+        let segment = Incomplete::new(segment).finalize_ipv6(1234, 80, None);
+        assert_eq!(segment.checksum(), 0);
+        assert_ne!(checksum, 0);
+    }
 }
@@ -1,25 +1,27 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-//! Exposes simple TCP over IPv4 listener functionality via the [`TcpIPv4Handler`] structure.
+//! Exposes simple TCP over IPv4 listener functionality via the [`TcpIPv4Handler`] structure,
+//! which can also accept connections over IPv6 when given a local IPv6 address.
 //!
 //! [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
+use std::time::Duration;
 
 use micro_http::{Request, Response};
 
+use crate::dumbo::pdu::Incomplete;
 use crate::dumbo::pdu::bytes::NetworkBytes;
 use crate::dumbo::pdu::ipv4::{IPv4Packet, Ipv4Error as IPv4PacketError, PROTOCOL_TCP};
+use crate::dumbo::pdu::ipv6::{IPv6Packet, Ipv6Error as IPv6PacketError};
 use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpError as TcpSegmentError, TcpSegment};
 use crate::dumbo::tcp::endpoint::Endpoint;
 use crate::dumbo::tcp::{NextSegmentStatus, RstConfig};
 
-// TODO: This is currently IPv4 specific. Maybe change it to a more generic implementation.
-
 /// Describes events which may occur when the handler receives packets.
 #[derive(Debug, PartialEq, Eq)]
 pub enum RecvEvent {
@@ -61,6 +63,8 @@ pub enum WriteEvent {
 pub enum RecvError {
     /// The inner segment has an invalid destination port.
     InvalidPort,
+    /// The handler received an IPv6 packet, but has no local IPv6 address.
+    Ipv6Disabled,
     /// The handler encountered an error while parsing the inner TCP segment: {0}
     TcpSegment(#[from] TcpSegmentError),
 }
@@ -74,21 +78,26 @@ pub enum RecvError {
 pub enum WriteNextError {
     /// There was an error while writing the contents of the IPv4 packet: {0}
     IPv4Packet(#[from] IPv4PacketError),
+    /// There was an error while writing the contents of the IPv6 packet: {0}
+    IPv6Packet(#[from] IPv6PacketError),
+    /// The handler has to write an IPv6 packet, but has no local IPv6 address.
+    Ipv6Disabled,
     /// There was an error while writing the contents of the inner TCP segment: {0}
     TcpSegment(#[from] TcpSegmentError),
 }
 
-// Generally speaking, a TCP/IPv4 connection is identified using the four-tuple (src_addr, src_port,
-// dst_addr, dst_port). However, the IPv4 address and TCP port of the MMDS endpoint are fixed, so
+// Generally speaking, a TCP/IP connection is identified using the four-tuple (src_addr, src_port,
+// dst_addr, dst_port). However, the IP addresses and TCP port of the MMDS endpoint are fixed, so
 // we can get away with uniquely identifying connections using just the remote address and port.
+// The version of the remote address also tells which local address is used by the connection.
 #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
 struct ConnectionTuple {
-    remote_addr: Ipv4Addr,
+    remote_addr: IpAddr,
     remote_port: u16,
 }
 
 impl ConnectionTuple {
-    fn new(remote_addr: Ipv4Addr, remote_port: u16) -> Self {
+    fn new(remote_addr: IpAddr, remote_port: u16) -> Self {
         ConnectionTuple {
             remote_addr,
             remote_port,
@@ -96,19 +105,86 @@ impl ConnectionTuple {
     }
 }
 
-/// Implements a minimalist TCP over IPv4 listener.
+// The local addresses and TCP port of the handler, used for every connection.
+#[derive(Debug)]
+struct LocalAddr {
+    ipv4_addr: Ipv4Addr,
+    ipv6_addr: Option<Ipv6Addr>,
+    port: u16,
+}
+
+impl LocalAddr {
+    // Writes to `buf` a packet heading towards the remote end of `tuple`, which carries the
+    // segment written by `write_segment` to the payload of the packet. The version of the packet
+    // matches the one of the remote address. Returns `None` when `write_segment` has nothing to
+    // write.
+    fn write_packet<F>(
+        &self,
+        buf: &mut [u8],
+        tuple: ConnectionTuple,
+        write_segment: F,
+    ) -> Result<Option<NonZeroUsize>, WriteNextError>
+    where
+        F: FnOnce(
+            &mut [u8],
+        )
+            -> Result<Option<Incomplete<TcpSegment<'_, &mut [u8]>>>, TcpSegmentError>,
+    {
+        match tuple.remote_addr {
+            IpAddr::V4(remote_addr) => {
+                let mut packet =
+                    IPv4Packet::write_header(buf, PROTOCOL_TCP, self.ipv4_addr, remote_addr)?;
+                let segment_len = match write_segment(packet.inner_mut().payload_mut())? {
+                    Some(segment) => segment
+                        .finalize(
+                            self.port,
+                            tuple.remote_port,
+                            Some((self.ipv4_addr, remote_addr)),
+                        )
+                        .len(),
+                    None => return Ok(None),
+                };
+                Ok(NonZeroUsize::new(
+                    packet.with_payload_len_unchecked(segment_len, true).len(),
+                ))
+            }
+            IpAddr::V6(remote_addr) => {
+                let local_addr = self.ipv6_addr.ok_or(WriteNextError::Ipv6Disabled)?;
+                let mut packet =
+                    IPv6Packet::write_header(buf, PROTOCOL_TCP, local_addr, remote_addr)?;
+                let segment_len = match write_segment(packet.inner_mut().payload_mut())? {
+                    Some(segment) => segment
+                        .finalize_ipv6(
+                            self.port,
+                            tuple.remote_port,
+                            Some((local_addr, remote_addr)),
+                        )
+                        .len(),
+                    None => return Ok(None),
+                };
+                Ok(NonZeroUsize::new(
+                    packet.with_payload_len_unchecked(segment_len).len(),
+                ))
+            }
+        }
+    }
+}
+
+/// Implements a minimalist TCP over IPv4 listener, which also listens over IPv6 when it has a
+/// local IPv6 address.
 ///
 /// Forwards incoming TCP segments to the appropriate connection object, based on the associated
 /// tuple, or attempts to establish new connections (when receiving `SYN` segments). Aside from
 /// constructors, the handler operation is based on three methods:
 ///
-/// * [`receive_packet`] examines an incoming IPv4 packet. It checks whether the destination address
-///   is correct, the attempts examine the inner TCP segment, making sure the destination port
-///   number is also correct. Then, it steers valid segments towards exiting connections, creates
-///   new connections for incoming `SYN` segments, and enqueues `RST` replies in response to any
-///   segments which cannot be associated with a connection (except other `RST` segments). On
-///   success, also describes any internal status changes triggered by the reception of the packet.
-/// * [`write_next_packet`] writes the next IPv4 packet (if available) that would be sent by the
+/// * [`receive_packet`] examines an incoming IPv4 packet (its [`receive_ipv6_packet`] counterpart
+///   does the same for IPv6 packets). It checks whether the destination address is correct, the
+///   attempts examine the inner TCP segment, making sure the destination port number is also
+///   correct. Then, it steers valid segments towards exiting connections, creates new connections
+///   for incoming `SYN` segments, and enqueues `RST` replies in response to any segments which
+///   cannot be associated with a connection (except other `RST` segments). On success, also
+///   describes any internal status changes triggered by the reception of the packet.
+/// * [`write_next_packet`] writes the next IP packet (if available) that would be sent by the
 ///   handler itself (right now it can only mean an enqueued `RST`), or one of the existing
 ///   connections. On success, also describes any internal status changes triggered as the packet
 ///   gets transmitted.
@@ -117,15 +193,19 @@ impl ConnectionTuple {
 ///   for the moment. This is used to determine whether it's appropriate to call
 ///   [`write_next_packet`].
 ///
+/// The callback handling requests may defer them by not returning a response. The connections
+/// with deferred requests keep counting towards the maximum number of connections, and their
+/// requests are handled again by [`poll_deferred_requests`].
+///
 /// [`receive_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_packet
+/// [`receive_ipv6_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_ipv6_packet
 /// [`write_next_packet`]: ../handler/struct.TcpIPv4Handler.html#method.write_next_packet
 /// [`next_segment_status`]: ../handler/struct.TcpIPv4Handler.html#method.next_segment_status
+/// [`poll_deferred_requests`]: ../handler/struct.TcpIPv4Handler.html#method.poll_deferred_requests
 #[derive(Debug)]
 pub struct TcpIPv4Handler {
-    // Handler IPv4 address used for every connection.
-    local_ipv4_addr: Ipv4Addr,
-    // Handler TCP port used for every connection.
-    local_port: u16,
+    // Handler IP addresses and TCP port used for every connection.
+    local_addr: LocalAddr,
     // This map holds the currently active endpoints, identified by their connection tuple.
     connections: HashMap<ConnectionTuple, Endpoint>,
     // Maximum number of concurrent connections we are willing to handle.
@@ -165,8 +245,11 @@ impl TcpIPv4Handler {
         max_pending_resets: NonZeroUsize,
     ) -> Self {
         TcpIPv4Handler {
-            local_ipv4_addr,
-            local_port,
+            local_addr: LocalAddr {
+                ipv4_addr: local_ipv4_addr,
+                ipv6_addr: None,
+                port: local_port,
+            },
             connections: HashMap::with_capacity(max_connections.get()),
             max_connections,
             active_connections: HashSet::with_capacity(max_connections.get()),
@@ -178,17 +261,28 @@ impl TcpIPv4Handler {
 
     /// Setter for the local IPv4 address of this TCP handler.
     pub fn set_local_ipv4_addr(&mut self, ipv4_addr: Ipv4Addr) {
-        self.local_ipv4_addr = ipv4_addr;
+        self.local_addr.ipv4_addr = ipv4_addr;
     }
 
     /// Returns the local IPv4 address of this TCP handler.
     pub fn local_ipv4_addr(&self) -> Ipv4Addr {
-        self.local_ipv4_addr
+        self.local_addr.ipv4_addr
+    }
+
+    /// Setter for the local IPv6 address of this TCP handler. Connections over IPv6 are only
+    /// accepted when the handler has a local IPv6 address.
+    pub fn set_local_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.local_addr.ipv6_addr = ipv6_addr;
+    }
+
+    /// Returns the local IPv6 address of this TCP handler, if any.
+    pub fn local_ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.local_addr.ipv6_addr
     }
 
     /// Returns the local port of this TCP handler.
     pub fn local_port(&self) -> u16 {
-        self.local_port
+        self.local_addr.port
     }
 
     /// Returns the max connections of this TCP handler.
@@ -201,24 +295,62 @@ impl TcpIPv4Handler {
         self.max_pending_resets
     }
 
-    /// Contains logic for handling incoming segments.
+    /// Contains logic for handling incoming segments carried by IPv4 packets.
     ///
     /// Any changes to the state of the handler are communicated through an `Ok(RecvEvent)`.
-    pub fn receive_packet<T: NetworkBytes + Debug, F: FnOnce(Request) -> Response>(
+    pub fn receive_packet<
+        T: NetworkBytes + Debug,
+        F: FnOnce(Request, Duration) -> Option<Response>,
+    >(
         &mut self,
         packet: &IPv4Packet<T>,
         callback: F,
+    ) -> Result<RecvEvent, RecvError> {
+        self.receive_segment(
+            IpAddr::V4(packet.source_address()),
+            packet.payload(),
+            callback,
+        )
+    }
+
+    /// Contains logic for handling incoming segments carried by IPv6 packets. Fails if the
+    /// handler has no local IPv6 address.
+    ///
+    /// Any changes to the state of the handler are communicated through an `Ok(RecvEvent)`.
+    pub fn receive_ipv6_packet<
+        T: NetworkBytes + Debug,
+        F: FnOnce(Request, Duration) -> Option<Response>,
+    >(
+        &mut self,
+        packet: &IPv6Packet<T>,
+        callback: F,
+    ) -> Result<RecvEvent, RecvError> {
+        if self.local_addr.ipv6_addr.is_none() {
+            return Err(RecvError::Ipv6Disabled);
+        }
+        self.receive_segment(
+            IpAddr::V6(packet.source_address()),
+            packet.payload(),
+            callback,
+        )
+    }
+
+    fn receive_segment<F: FnOnce(Request, Duration) -> Option<Response>>(
+        &mut self,
+        remote_addr: IpAddr,
+        payload: &[u8],
+        callback: F,
     ) -> Result<RecvEvent, RecvError> {
         // TODO: We skip verifying the checksum, just in case the device model relies on offloading
         // checksum computation from the guest to some other entity. Clear this up at some point!
         // (Issue #520)
-        let segment = TcpSegment::from_bytes(packet.payload(), None)?;
+        let segment = TcpSegment::from_bytes(payload, None)?;
 
-        if segment.destination_port() != self.local_port {
+        if segment.destination_port() != self.local_addr.port {
             return Err(RecvError::InvalidPort);
         }
 
-        let tuple = ConnectionTuple::new(packet.source_address(), segment.source_port());
+        let tuple = ConnectionTuple::new(remote_addr, segment.source_port());
 
         let outcome = if let Some(endpoint) = self.connections.get_mut(&tuple) {
             endpoint.receive_segment(&segment, callback);
@@ -385,10 +517,6 @@ impl TcpIPv4Handler {
         let mut writer_status = None;
         let mut event = WriteEvent::Nothing;
 
-        // Write an incomplete Ipv4 packet and complete it afterwards with missing information.
-        let mut packet =
-            IPv4Packet::write_header(buf, PROTOCOL_TCP, Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST)?;
-
         // We set mss_used to 0, because we don't add any IP options.
         // TODO: Maybe get this nicely from packet at some point.
         let mss_reserved = 0;
@@ -398,34 +526,20 @@ impl TcpIPv4Handler {
         // any TCP options, or a payload.
         if let Some((tuple, rst_cfg)) = self.rst_queue.pop() {
             let (seq, ack, flags_after_ns) = rst_cfg.seq_ack_tcp_flags();
-            let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
-                packet.inner_mut().payload_mut(),
-                seq,
-                ack,
-                flags_after_ns,
-                10000,
-                None,
-                0,
-                None,
-            )?
-            .finalize(
-                self.local_port,
-                tuple.remote_port,
-                Some((self.local_ipv4_addr, tuple.remote_addr)),
-            )
-            .len();
-
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let packet_len = packet.with_payload_len_unchecked(segment_len, true).len();
-            // The unwrap() is safe because packet_len > 0.
-            return Ok((
-                Some(NonZeroUsize::new(packet_len).unwrap()),
-                WriteEvent::Nothing,
-            ));
+            let packet_len = self.local_addr.write_packet(buf, tuple, |payload| {
+                TcpSegment::write_incomplete_segment::<[u8]>(
+                    payload,
+                    seq,
+                    ack,
+                    flags_after_ns,
+                    10000,
+                    None,
+                    0,
+                    None,
+                )
+                .map(Some)
+            })?;
+            return Ok((packet_len, WriteEvent::Nothing));
         }
 
         for tuple in self
@@ -436,33 +550,15 @@ impl TcpIPv4Handler {
             // Tuples in self.active_connection or self.next_timeout should also appear as keys
             // in self.connections.
             let endpoint = self.connections.get_mut(tuple).unwrap();
-            // We need this block to clearly delimit the lifetime of the mutable borrow started by
-            // the following packet.inner_mut().
-            let segment_len = {
-                let maybe_segment =
-                    endpoint.write_next_segment(packet.inner_mut().payload_mut(), mss_reserved);
+            let maybe_len = self.local_addr.write_packet(buf, *tuple, |payload| {
+                Ok(endpoint.write_next_segment(payload, mss_reserved))
+            })?;
 
-                match maybe_segment {
-                    Some(segment) => segment
-                        .finalize(
-                            self.local_port,
-                            tuple.remote_port,
-                            Some((self.local_ipv4_addr, tuple.remote_addr)),
-                        )
-                        .len(),
-                    None => continue,
-                }
-            };
-
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let ip_len = packet.with_payload_len_unchecked(segment_len, true).len();
+            if maybe_len.is_none() {
+                continue;
+            }
 
-            // The unwrap is safe because ip_len > 0.
-            len = Some(NonZeroUsize::new(ip_len).unwrap());
+            len = maybe_len;
             writer_status = Some((*tuple, endpoint.is_done()));
 
             break;
@@ -485,6 +581,37 @@ impl TcpIPv4Handler {
         Ok((len, event))
     }
 
+    /// Returns whether any of the existing endpoints has a deferred request.
+    pub fn has_deferred_requests(&self) -> bool {
+        self.connections
+            .values()
+            .any(Endpoint::has_deferred_request)
+    }
+
+    /// Handles the deferred requests of the existing endpoints again, using `callback`, which is
+    /// also given how long each request has been deferred.
+    pub fn poll_deferred_requests<F: FnMut(Request, Duration) -> Option<Response>>(
+        &mut self,
+        mut callback: F,
+    ) {
+        let tuples: Vec<ConnectionTuple> = self
+            .connections
+            .iter()
+            .filter(|(_, endpoint)| endpoint.has_deferred_request())
+            .map(|(tuple, _)| *tuple)
+            .collect();
+
+        for tuple in tuples {
+            // The unwrap is safe because the tuple was just found in self.connections.
+            let endpoint = self.connections.get_mut(&tuple).unwrap();
+            endpoint.poll_deferred_request(&mut callback);
+            let status = endpoint.next_segment_status();
+            if !self.check_next_segment_status(tuple, status) {
+                self.active_connections.remove(&tuple);
+            }
+        }
+    }
+
     /// Describes the status of the next segment to be sent by the handler.
     #[inline]
     pub fn next_segment_status(&self) -> NextSegmentStatus {
@@ -708,8 +835,8 @@ mod tests {
         assert_eq!(h.next_segment_status(), NextSegmentStatus::Available);
         assert_eq!(drain_packets(&mut h, local_addr, remote_addr), Ok(1));
 
-        let remote_tuple = ConnectionTuple::new(remote_addr, remote_port);
-        let remote_tuple2 = ConnectionTuple::new(remote_addr, remote_port + 1);
+        let remote_tuple = ConnectionTuple::new(IpAddr::V4(remote_addr), remote_port);
+        let remote_tuple2 = ConnectionTuple::new(IpAddr::V4(remote_addr), remote_port + 1);
 
         // Also, there should be a retransmission timer associated with the previous SYNACK now.
         assert_eq!(h.active_connections.len(), 0);
@@ -770,7 +897,10 @@ mod tests {
         // The timeout associated with the SYNACK of the second connection should be next.
         assert_eq!(h.active_connections.len(), 0);
         if let Some((_, tuple)) = h.next_timeout {
-            assert_ne!(tuple, ConnectionTuple::new(remote_addr, remote_port));
+            assert_ne!(
+                tuple,
+                ConnectionTuple::new(IpAddr::V4(remote_addr), remote_port)
+            );
         } else {
             panic!("missing third expected timeout");
         }
@@ -822,4 +952,85 @@ mod tests {
         assert_eq!(h.connections.len(), 1);
         assert_eq!(h.active_connections.len(), 0);
     }
+
+    #[test]
+    fn test_handler_ipv6() {
+        let mut buf = [0u8; 100];
+        let mut buf2 = [0u8; 2000];
+
+        let local_ipv4_addr = Ipv4Addr::new(169, 254, 169, 254);
+        let local_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+        let local_port = 80;
+        let remote_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let remote_port = 1012;
+
+        let mut h = TcpIPv4Handler::new(
+            local_ipv4_addr,
+            local_port,
+            NonZeroUsize::new(2).unwrap(),
+            NonZeroUsize::new(2).unwrap(),
+        );
+        assert_eq!(h.local_ipv6_addr(), None);
+
+        let p_len = {
+            let mut p =
+                IPv6Packet::write_header(buf.as_mut(), PROTOCOL_TCP, remote_addr, local_addr)
+                    .unwrap();
+            let s_len = TcpSegment::write_segment::<[u8]>(
+                p.inner_mut().payload_mut(),
+                remote_port,
+                local_port,
+                123,
+                0,
+                TcpFlags::SYN,
+                10000,
+                None,
+                100,
+                None,
+                None,
+            )
+            .unwrap()
+            .len();
+            p.with_payload_len_unchecked(s_len).len()
+        };
+        let p = IPv6Packet::from_bytes(&buf[..p_len]).unwrap();
+
+        // Connections over IPv6 are refused until the handler has a local IPv6 address.
+        assert_eq!(
+            h.receive_ipv6_packet(&p, mock_callback).unwrap_err(),
+            RecvError::Ipv6Disabled
+        );
+        assert_eq!(h.connections.len(), 0);
+
+        h.set_local_ipv6_addr(Some(local_addr));
+        assert_eq!(h.local_ipv6_addr(), Some(local_addr));
+        assert_eq!(
+            h.receive_ipv6_packet(&p, mock_callback),
+            Ok(RecvEvent::NewConnectionSuccessful)
+        );
+        assert!(
+            h.connections
+                .contains_key(&ConnectionTuple::new(IpAddr::V6(remote_addr), remote_port))
+        );
+
+        // The SYNACK is sent over IPv6, with a valid checksum.
+        let (len, event) = h.write_next_packet(buf2.as_mut()).unwrap();
+        assert_eq!(event, WriteEvent::Nothing);
+        let synack = IPv6Packet::from_bytes(&buf2[..len.unwrap().get()]).unwrap();
+        assert_eq!(synack.next_header(), PROTOCOL_TCP);
+        assert_eq!(synack.source_address(), local_addr);
+        assert_eq!(synack.destination_address(), remote_addr);
+        let s = TcpSegment::from_bytes(synack.payload(), None).unwrap();
+        assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(s.source_port(), local_port);
+        assert_eq!(s.destination_port(), remote_port);
+        assert_eq!(s.compute_checksum_ipv6(local_addr, remote_addr), 0);
+
+        // Without a local IPv6 address, the handler cannot write packets to IPv6 connections.
+        h.set_local_ipv6_addr(None);
+        assert_eq!(
+            h.write_next_packet(buf2.as_mut()).unwrap_err(),
+            WriteNextError::Ipv6Disabled
+        );
+    }
 }
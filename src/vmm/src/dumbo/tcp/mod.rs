@@ -91,14 +91,16 @@ pub fn seq_at_or_after(a: Wrapping<u32>, b: Wrapping<u32>) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
     use micro_http::{Request, Response, StatusCode, Version};
 
     use super::*;
 
     // In tcp tests, some of the functions require a callback parameter. Since we do not care,
     // for the purpose of those tests, what that callback does, we need to provide a dummy one.
-    pub fn mock_callback(_request: Request) -> Response {
-        Response::new(Version::Http11, StatusCode::OK)
+    pub fn mock_callback(_request: Request, _waited: Duration) -> Option<Response> {
+        Some(Response::new(Version::Http11, StatusCode::OK))
     }
 
     #[test]
@@ -29,6 +29,8 @@ pub enum OpCode {
     Write = io_uring_op::IORING_OP_WRITE as u8,
     /// Fsync operation.
     Fsync = io_uring_op::IORING_OP_FSYNC as u8,
+    /// Fallocate operation.
+    Fallocate = io_uring_op::IORING_OP_FALLOCATE as u8,
 }
 
 // Useful for outputting errors.
@@ -38,6 +40,7 @@ impl From<OpCode> for &'static str {
             OpCode::Read => "read",
             OpCode::Write => "write",
             OpCode::Fsync => "fsync",
+            OpCode::Fallocate => "fallocate",
         }
     }
 }
@@ -113,6 +116,22 @@ impl<T: Debug> Operation<T> {
         }
     }
 
+    /// Construct a fallocate operation.
+    ///
+    /// The kernel expects the length of the range in the `addr` field of the sqe and the
+    /// fallocate mode in the `len` field.
+    pub fn fallocate(fd: FixedFd, offset: u64, len: usize, mode: u32, user_data: T) -> Self {
+        Self {
+            fd,
+            opcode: OpCode::Fallocate,
+            addr: Some(len),
+            len: Some(mode),
+            flags: 0,
+            offset: Some(offset),
+            user_data,
+        }
+    }
+
     pub(crate) fn fd(&self) -> FixedFd {
         self.fd
     }
@@ -88,6 +88,8 @@ pub mod dumbo;
 pub mod gdb;
 /// Logger
 pub mod logger;
+/// Live migration of a microVM between Firecracker processes.
+pub mod migration;
 /// microVM Metadata Service MMDS
 pub mod mmds;
 /// Save/restore utilities.
@@ -115,6 +117,7 @@ pub mod vstate;
 pub mod initrd;
 
 use std::collections::HashMap;
+use std::fmt::Debug;
 use std::io;
 use std::os::unix::io::AsRawFd;
 use std::sync::mpsc::RecvTimeoutError;
@@ -122,6 +125,7 @@ use std::sync::{Arc, Barrier, Mutex};
 use std::time::Duration;
 
 use device_manager::DeviceManager;
+use device_manager::pci_mngr::PciManagerError;
 use devices::acpi::vmgenid::VmGenIdError;
 use event_manager::{EventManager as BaseEventManager, EventOps, Events, MutEventSubscriber};
 use seccomp::BpfProgram;
@@ -131,17 +135,28 @@ use vmm_sys_util::epoll::EventSet;
 use vmm_sys_util::eventfd::EventFd;
 use vmm_sys_util::terminal::Terminal;
 use vstate::kvm::Kvm;
-use vstate::vcpu::{self, StartThreadedError, VcpuSendEventError};
+use vstate::vcpu::{self, StartThreadedError, VcpuSendEventError, VcpuSlot};
 
 use crate::cpu_config::templates::CpuConfiguration;
-use crate::devices::virtio::balloon::{BALLOON_DEV_ID, Balloon, BalloonConfig, BalloonStats};
+use crate::devices::virtio::balloon::{
+    BALLOON_DEV_ID, Balloon, BalloonConfig, BalloonStats, HintingStatus,
+};
 use crate::devices::virtio::block::device::Block;
+use crate::devices::virtio::device::VirtioDevice;
+use crate::devices::virtio::mem::{MEM_DEV_ID, VirtioMem};
 use crate::devices::virtio::net::Net;
 use crate::logger::{METRICS, MetricsError, error, info, warn};
-use crate::persist::{MicrovmState, MicrovmStateError, VmInfo};
+use crate::migration::MigrationWorker;
+use crate::persist::{LastSnapshot, MicrovmState, MicrovmStateError, VmInfo};
 use crate::rate_limiter::BucketUpdate;
+use crate::utils::{mib_to_bytes, usize_to_u64};
+use crate::vmm_config::balloon::BalloonHintingAction;
 use crate::vmm_config::instance_info::{InstanceInfo, VmState};
-use crate::vstate::memory::{GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
+#[cfg(target_arch = "x86_64")]
+use crate::vmm_config::machine_config::MachineConfig;
+use crate::vmm_config::memory_hotplug::MemoryHotplugStatus;
+use crate::vmm_config::migration::MigrationStatus;
+use crate::vstate::memory::{GuestAddress, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
 use crate::vstate::vcpu::VcpuState;
 pub use crate::vstate::vcpu::{Vcpu, VcpuConfig, VcpuEvent, VcpuHandle, VcpuResponse};
 pub use crate::vstate::vm::Vm;
@@ -238,6 +253,8 @@ pub enum VmmError {
     VcpuMessage,
     /// Cannot spawn Vcpu thread: {0}
     VcpuSpawn(io::Error),
+    /// Cannot start the live migration thread: {0}
+    MigrationWorker(io::Error),
     /// Vm error: {0}
     Vm(#[from] vstate::vm::VmError),
     /// Kvm error: {0}
@@ -270,6 +287,55 @@ pub enum StartVcpusError {
     VcpuHandle(#[from] StartThreadedError),
 }
 
+/// Error type for [`Vmm::hotplug_vcpus`].
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum VcpuHotplugError {
+    /// vCPU hotplug is not enabled, `max_vcpus` must be set in the machine configuration.
+    NotConfigured,
+    /// The number of vCPUs cannot exceed the maximum of {0}.
+    InvalidVcpuCount(u8),
+    /// The number of vCPUs cannot be lower than the current {0}, vCPUs cannot be unplugged.
+    Unplug(u8),
+    /// Error creating the vcpu: {0}
+    VcpuCreate(vstate::vcpu::VcpuError),
+    /// Error getting the CPU template: {0}
+    CpuTemplate(#[from] crate::cpu_config::templates::GetCpuTemplateError),
+    #[cfg(target_arch = "x86_64")]
+    /// Error configuring the vcpu: {0}
+    Configure(#[from] crate::arch::ConfigurationError),
+    /// Cannot clone the vcpu exit eventfd: {0}
+    EventFd(io::Error),
+    /// Failed to resume the hotplugged vCPUs.
+    Resume,
+    /// Failed to notify the guest: {0}
+    Notify(io::Error),
+}
+
+/// Error type for [`Vmm::hotplug_virtio_device`] and [`Vmm::unplug_virtio_device`].
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum DeviceHotplugError {
+    /// Device hotplug is not enabled, it requires PCI support on x86_64.
+    NotConfigured,
+    /// A device with id {0} already exists.
+    DeviceExists(String),
+    /// No hotpluggable device with id {0}.
+    DeviceNotFound(String),
+    /// The removal of device {0} is already in progress.
+    RemovalPending(String),
+    /// The root block device cannot be hotplugged or unplugged.
+    RootDevice,
+    /// vhost-user block devices cannot be hotplugged or unplugged.
+    VhostUser,
+    /// Network interface {0} is used by MMDS and cannot be unplugged.
+    MmdsInterface(String),
+    /// Exactly one of `drive_id` and `iface_id` must be specified.
+    InvalidUnplugConfig,
+    /// Error attaching the device: {0}
+    Attach(#[from] PciManagerError),
+    /// Failed to notify the guest: {0}
+    Notify(io::Error),
+}
+
 /// Error type for [`Vmm::dump_cpu_config()`]
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum DumpCpuConfigError {
@@ -298,10 +364,16 @@ pub struct Vmm {
     #[allow(unused)]
     uffd: Option<Uffd>,
     vcpus_handles: Vec<VcpuHandle>,
+    // Threads waiting for the vcpus that can be hotplugged into the microVM.
+    vcpus_slots: Vec<VcpuSlot>,
     // Used by Vcpus and devices to initiate teardown; Vmm should never write here.
     vcpus_exit_evt: EventFd,
     // Device manager
     device_manager: DeviceManager,
+    // Last snapshot created or loaded, that diff snapshots are based on.
+    last_snapshot: Option<LastSnapshot>,
+    // Thread sending live migrations, only started when dirty page tracking is enabled.
+    migration: Option<MigrationWorker>,
 }
 
 impl Vmm {
@@ -356,6 +428,18 @@ impl Vmm {
             self.vcpus_handles
                 .push(vcpu.start_threaded(vcpu_seccomp_filter.clone(), barrier.clone())?);
         }
+        // The threads of the vcpus that can be hotplugged later on need to be spawned now, as
+        // threads spawned by the VMM thread after its seccomp filters are loaded cannot run vcpus.
+        if let Some(max_vcpus) = self.device_manager.acpi_devices.max_vcpus() {
+            // vcpu_count is at most max_vcpus, which fits in a u8.
+            #[allow(clippy::cast_possible_truncation)]
+            for index in vcpu_count as u8..max_vcpus {
+                self.vcpus_slots.push(VcpuSlot::start_threaded(
+                    index,
+                    vcpu_seccomp_filter.clone(),
+                )?);
+            }
+        }
         self.instance_info.state = VmState::Paused;
         // Wait for vCPUs to initialize their TLS before moving forward.
         barrier.wait();
@@ -363,6 +447,38 @@ impl Vmm {
         Ok(())
     }
 
+    /// Starts the thread sending the guest memory of live migrations, if dirty page tracking is
+    /// enabled. It needs to be spawned before the seccomp filters of the VMM thread are loaded.
+    pub fn start_migration_worker(
+        &mut self,
+        vmm_seccomp_filter: Arc<BpfProgram>,
+    ) -> Result<(), io::Error> {
+        if self
+            .vm
+            .guest_memory()
+            .iter()
+            .all(|region| region.bitmap().is_some())
+        {
+            self.migration = Some(MigrationWorker::start_threaded(vmm_seccomp_filter)?);
+        }
+        Ok(())
+    }
+
+    /// Returns the status of the last live migration sent from this microVM.
+    pub fn migration_status(&self) -> MigrationStatus {
+        self.migration
+            .as_ref()
+            .map(MigrationWorker::status)
+            .unwrap_or_default()
+    }
+
+    /// Whether a live migration is being sent from this microVM.
+    pub fn migration_in_progress(&self) -> bool {
+        self.migration
+            .as_ref()
+            .is_some_and(MigrationWorker::in_progress)
+    }
+
     /// Sends a resume command to the vCPUs.
     pub fn resume_vm(&mut self) -> Result<(), VmmError> {
         self.device_manager.kick_virtio_devices();
@@ -446,6 +562,8 @@ impl Vmm {
             vm_state,
             vcpu_states,
             device_states,
+            parent: None,
+            mem_file_format: None,
         })
     }
 
@@ -591,6 +709,235 @@ impl Vmm {
             .map_err(VmmError::FindDeviceError)
     }
 
+    /// Starts or stops free page hinting on the balloon device.
+    pub fn update_balloon_hinting(&mut self, action: BalloonHintingAction) -> Result<(), VmmError> {
+        self.device_manager
+            .try_with_virtio_device_with_id(BALLOON_DEV_ID, |dev: &mut Balloon| match action {
+                BalloonHintingAction::Start => dev.start_hinting(),
+                BalloonHintingAction::Stop => dev.stop_hinting(),
+            })
+            .map_err(VmmError::FindDeviceError)
+    }
+
+    /// Returns the status of free page hinting on the balloon device.
+    pub fn balloon_hinting_status(&self) -> Result<HintingStatus, VmmError> {
+        self.device_manager
+            .try_with_virtio_device_with_id(BALLOON_DEV_ID, |dev: &mut Balloon| {
+                dev.hinting_status()
+            })
+            .map_err(VmmError::FindDeviceError)
+    }
+
+    /// Returns the guest memory ranges hinted as free during the current free page hinting run.
+    pub fn balloon_free_page_hints(&self) -> Vec<(GuestAddress, u64)> {
+        self.device_manager
+            .with_virtio_device_with_id(BALLOON_DEV_ID, |dev: &mut Balloon| {
+                dev.free_page_hints().to_vec()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the current state of the memory hotplug device.
+    pub fn memory_hotplug_status(&self) -> Result<MemoryHotplugStatus, VmmError> {
+        self.device_manager
+            .with_virtio_device_with_id(MEM_DEV_ID, |dev: &mut VirtioMem| {
+                MemoryHotplugStatus::from(&*dev)
+            })
+            .map_err(VmmError::FindDeviceError)
+    }
+
+    /// Asks the guest to plug or unplug memory until `requested_size_mib` MiB are plugged.
+    pub fn update_memory_hotplug_size(
+        &mut self,
+        requested_size_mib: usize,
+    ) -> Result<(), VmmError> {
+        self.device_manager
+            .try_with_virtio_device_with_id(MEM_DEV_ID, |dev: &mut VirtioMem| {
+                dev.update_requested_size(usize_to_u64(mib_to_bytes(requested_size_mib)))
+            })
+            .map_err(VmmError::FindDeviceError)
+    }
+
+    /// Hotplugs vcpus into the microVM until it has `vcpu_count` vcpus, and notifies the guest.
+    #[cfg(target_arch = "x86_64")]
+    pub fn hotplug_vcpus(
+        &mut self,
+        vcpu_count: u8,
+        machine_config: &MachineConfig,
+    ) -> Result<(), VcpuHotplugError> {
+        let cpu_hotplug = self
+            .device_manager
+            .acpi_devices
+            .cpu_hotplug
+            .clone()
+            .ok_or(VcpuHotplugError::NotConfigured)?;
+        let max_vcpus = cpu_hotplug.lock().expect("Poisoned lock").max_vcpus;
+        // There are at most max_vcpus vcpus, which fits in a u8.
+        #[allow(clippy::cast_possible_truncation)]
+        let current_vcpus = self.vcpus_handles.len() as u8;
+
+        if vcpu_count < current_vcpus {
+            return Err(VcpuHotplugError::Unplug(current_vcpus));
+        }
+        if vcpu_count > max_vcpus {
+            return Err(VcpuHotplugError::InvalidVcpuCount(max_vcpus));
+        }
+        if vcpu_count == current_vcpus {
+            return Ok(());
+        }
+
+        let mut vcpus = (current_vcpus..vcpu_count)
+            .map(|index| {
+                let exit_evt = self
+                    .vcpus_exit_evt
+                    .try_clone()
+                    .map_err(VcpuHotplugError::EventFd)?;
+                Vcpu::new(index, &self.vm, exit_evt).map_err(VcpuHotplugError::VcpuCreate)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let cpu_template = machine_config.cpu_template.get_cpu_template()?;
+        crate::arch::configure_hotplugged_vcpus(
+            &self.kvm,
+            &mut vcpus,
+            machine_config,
+            &cpu_template,
+        )?;
+
+        // Slots are started in order for all the hotpluggable vcpus. They are only taken once all
+        // the vcpus are created and configured, so that they are kept on the error paths above.
+        let slots = self.vcpus_slots.drain(..vcpus.len()).collect::<Vec<_>>();
+        let barrier = Arc::new(Barrier::new(vcpus.len() + 1));
+        for (mut vcpu, slot) in vcpus.into_iter().zip(slots) {
+            vcpu.set_mmio_bus(self.vm.common.mmio_bus.clone());
+            vcpu.kvm_vcpu.set_pio_bus(self.vm.pio_bus.clone());
+            // The started vcpus are tracked right away, so that they are finished along with the
+            // other vcpus even if they fail to resume.
+            self.vcpus_handles
+                .push(slot.start_vcpu(vcpu, barrier.clone()));
+        }
+        // Wait for vCPUs to initialize their TLS before moving forward.
+        barrier.wait();
+
+        if self.instance_info.state == VmState::Running {
+            let handles = &self.vcpus_handles[usize::from(current_vcpus)..];
+            for handle in handles {
+                handle
+                    .send_event(VcpuEvent::Resume)
+                    .map_err(|_| VcpuHotplugError::Resume)?;
+            }
+            if handles
+                .iter()
+                .map(|handle| handle.response_receiver().recv_timeout(RECV_TIMEOUT_SEC))
+                .any(|response| !matches!(response, Ok(VcpuResponse::Resumed)))
+            {
+                return Err(VcpuHotplugError::Resume);
+            }
+        }
+
+        cpu_hotplug
+            .lock()
+            .expect("Poisoned lock")
+            .insert_vcpus(current_vcpus..vcpu_count)
+            .map_err(VcpuHotplugError::Notify)
+    }
+
+    /// Hotplugs a VirtIO device into the PCI segment of the microVM, and notifies the guest.
+    pub fn hotplug_virtio_device<T: 'static + VirtioDevice + MutEventSubscriber + Debug>(
+        &mut self,
+        id: String,
+        device: Arc<Mutex<T>>,
+    ) -> Result<(), DeviceHotplugError> {
+        let pci_hotplug = self
+            .device_manager
+            .acpi_devices
+            .pci_hotplug
+            .clone()
+            .ok_or(DeviceHotplugError::NotConfigured)?;
+        let device_type = device.lock().expect("Poisoned lock").device_type();
+        let pci_devices = &mut self.device_manager.pci_devices;
+        if pci_devices.get_virtio_device(device_type, &id).is_some() {
+            return Err(DeviceHotplugError::DeviceExists(id));
+        }
+
+        let slot = pci_devices.hotplug_pci_virtio_device(&self.vm, id, device)?;
+        pci_hotplug
+            .lock()
+            .expect("Poisoned lock")
+            .insert_device(slot)
+            .map_err(DeviceHotplugError::Notify)
+    }
+
+    /// Asks the guest to eject a VirtIO device from the PCI segment of the microVM. The device is
+    /// torn down once the guest has ejected it.
+    pub fn unplug_virtio_device(
+        &mut self,
+        device_type: u32,
+        id: &str,
+    ) -> Result<(), DeviceHotplugError> {
+        let pci_hotplug = self
+            .device_manager
+            .acpi_devices
+            .pci_hotplug
+            .clone()
+            .ok_or(DeviceHotplugError::NotConfigured)?;
+        let slot = self
+            .device_manager
+            .pci_devices
+            .get_virtio_device(device_type, id)
+            .ok_or_else(|| DeviceHotplugError::DeviceNotFound(id.to_string()))?
+            .lock()
+            .expect("Poisoned lock")
+            .pci_device_bdf()
+            .device();
+
+        let mut pci_hotplug = pci_hotplug.lock().expect("Poisoned lock");
+        if pci_hotplug.is_unplugging(slot) {
+            return Err(DeviceHotplugError::RemovalPending(id.to_string()));
+        }
+        pci_hotplug
+            .remove_device(slot)
+            .map_err(DeviceHotplugError::Notify)
+    }
+
+    /// Adds the hotplugged devices to the event manager, and removes the unplugged ones from it.
+    ///
+    /// Devices are hotplugged and unplugged while the event manager is running, so this must be
+    /// called by its owner in between runs.
+    pub fn update_event_subscribers(&mut self, event_manager: &mut EventManager) {
+        self.device_manager
+            .pci_devices
+            .update_event_subscribers(event_manager);
+    }
+
+    /// Returns the type and id of the devices ejected by the guest since the last call, which
+    /// are not part of the microVM anymore.
+    pub fn take_unplugged_devices(&mut self) -> Vec<(u32, String)> {
+        self.device_manager.pci_devices.take_unplugged_devices()
+    }
+
+    // Tears down the devices ejected by the guest.
+    fn remove_ejected_devices(&mut self) {
+        let Some(pci_hotplug) = self.device_manager.acpi_devices.pci_hotplug.clone() else {
+            return;
+        };
+        let ejected = {
+            let mut pci_hotplug = pci_hotplug.lock().expect("Poisoned lock");
+            let _ = pci_hotplug.eject_evt.read();
+            pci_hotplug.take_ejected()
+        };
+
+        for slot in (0..32u8).filter(|slot| ejected & (1 << slot) != 0) {
+            match self
+                .device_manager
+                .pci_devices
+                .detach_pci_virtio_device(&self.vm, slot)
+            {
+                Ok(id) => info!("Removed device {id} from PCI slot {slot}"),
+                Err(err) => error!("Failed to remove the device of PCI slot {slot}: {err}"),
+            }
+        }
+    }
+
     /// Signals Vmm to stop and exit.
     pub fn stop(&mut self, exit_code: FcExitCode) {
         // To avoid cycles, all teardown paths take the following route:
@@ -625,6 +972,8 @@ impl Vmm {
         // list of handles. Do it here instead of Vmm::Drop to avoid dependency cycles.
         // (Vmm's Drop will also check if this list is empty).
         self.vcpus_handles.clear();
+        // Dropping the slots closes their channels, which ends the threads waiting for a vcpu.
+        self.vcpus_slots.clear();
 
         // Break the main event loop, propagating the Vmm exit-code.
         self.shutdown_exit_code = Some(exit_code);
@@ -705,6 +1054,22 @@ impl MutEventSubscriber for Vmm {
     fn process(&mut self, event: Events, _: &mut EventOps) {
         let source = event.fd();
         let event_set = event.event_set();
+        let pci_eject_fd =
+            self.device_manager
+                .acpi_devices
+                .pci_hotplug
+                .as_ref()
+                .map(|pci_hotplug| {
+                    pci_hotplug
+                        .lock()
+                        .expect("Poisoned lock")
+                        .eject_evt
+                        .as_raw_fd()
+                });
+        let migration_fd = self
+            .migration
+            .as_ref()
+            .map(|worker| worker.event_evt.as_raw_fd());
 
         if source == self.vcpus_exit_evt.as_raw_fd() && event_set == EventSet::IN {
             // Exit event handling should never do anything more than call 'self.stop()'.
@@ -731,6 +1096,10 @@ impl MutEventSubscriber for Vmm {
                 FcExitCode::Ok
             };
             self.stop(exit_code);
+        } else if Some(source) == pci_eject_fd && event_set == EventSet::IN {
+            self.remove_ejected_devices();
+        } else if Some(source) == migration_fd && event_set == EventSet::IN {
+            migration::process_migration_events(self);
         } else {
             error!("Spurious EventManager event for handler: Vmm");
         }
@@ -740,5 +1109,16 @@ impl MutEventSubscriber for Vmm {
         if let Err(err) = ops.add(Events::new(&self.vcpus_exit_evt, EventSet::IN)) {
             error!("Failed to register vmm exit event: {}", err);
         }
+        if let Some(pci_hotplug) = &self.device_manager.acpi_devices.pci_hotplug {
+            let pci_hotplug = pci_hotplug.lock().expect("Poisoned lock");
+            if let Err(err) = ops.add(Events::new(&pci_hotplug.eject_evt, EventSet::IN)) {
+                error!("Failed to register PCI hotplug eject event: {}", err);
+            }
+        }
+        if let Some(worker) = &self.migration {
+            if let Err(err) = ops.add(Events::new(&worker.event_evt, EventSet::IN)) {
+                error!("Failed to register live migration event: {}", err);
+            }
+        }
     }
 }
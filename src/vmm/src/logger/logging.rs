@@ -28,10 +28,15 @@ pub static INSTANCE_ID: OnceLock<String> = OnceLock::new();
 /// Default values matching the swagger specification (`src/firecracker/swagger/firecracker.yaml`).
 pub static LOGGER: Logger = Logger(Mutex::new(LoggerConfiguration {
     target: None,
-    filter: LogFilter { module: None },
+    filter: LogFilter {
+        module: None,
+        level: DEFAULT_LEVEL,
+        module_levels: Vec::new(),
+    },
     format: LogFormat {
         show_level: false,
         show_log_origin: false,
+        output: LogOutputFormat::Text,
     },
 }));
 
@@ -52,14 +57,10 @@ impl Logger {
     }
 
     /// Applies the given logger configuration the logger.
+    ///
+    /// Fields which are not set in `config` keep their current value.
     pub fn update(&self, config: LoggerConfig) -> Result<(), LoggerUpdateError> {
         let mut guard = self.0.lock().unwrap();
-        log::set_max_level(
-            config
-                .level
-                .map(log::LevelFilter::from)
-                .unwrap_or(DEFAULT_LEVEL),
-        );
 
         if let Some(log_path) = config.log_path {
             let file = std::fs::OpenOptions::new()
@@ -79,10 +80,34 @@ impl Logger {
             guard.format.show_log_origin = show_log_origin;
         }
 
+        if let Some(format) = config.format {
+            guard.format.output = format;
+        }
+
         if let Some(module) = config.module {
             guard.filter.module = Some(module);
         }
 
+        if let Some(level) = config.level {
+            guard.filter.level = log::LevelFilter::from(level);
+        }
+
+        if let Some(module_levels) = config.module_levels {
+            guard.filter.module_levels = module_levels
+                .into_iter()
+                .map(|module_level| {
+                    (
+                        module_level.module,
+                        log::LevelFilter::from(module_level.level),
+                    )
+                })
+                .collect();
+        }
+
+        // Records above the maximum level are discarded before reaching the logger, so it needs
+        // to allow the most verbose of the global and per-module levels.
+        log::set_max_level(guard.filter.max_level());
+
         // Ensure we drop the guard before attempting to log, otherwise this
         // would deadlock.
         drop(guard);
@@ -94,11 +119,43 @@ impl Logger {
 #[derive(Debug)]
 pub struct LogFilter {
     pub module: Option<String>,
+    pub level: log::LevelFilter,
+    pub module_levels: Vec<(String, log::LevelFilter)>,
+}
+impl LogFilter {
+    /// Level applying to the logs of the module at `module_path`, given by the override with the
+    /// longest matching module prefix, or by the global level if no override matches.
+    fn level(&self, module_path: &str) -> log::LevelFilter {
+        self.module_levels
+            .iter()
+            .filter(|(module, _)| module_path.starts_with(module.as_str()))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    /// The most verbose level of the global and per-module levels.
+    fn max_level(&self) -> log::LevelFilter {
+        self.module_levels
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, std::cmp::max)
+    }
+
+    /// Whether a record of `level` emitted by the module at `module_path` should be logged.
+    fn enabled(&self, level: log::Level, module_path: Option<&str>) -> bool {
+        let enabled_module = match (&self.module, module_path) {
+            (Some(filter), Some(source)) => source.starts_with(filter.as_str()),
+            (Some(_), None) => false,
+            (None, _) => true,
+        };
+        enabled_module && level <= self.level(module_path.unwrap_or_default())
+    }
 }
 #[derive(Debug)]
 pub struct LogFormat {
     pub show_level: bool,
     pub show_log_origin: bool,
+    pub output: LogOutputFormat,
 }
 #[derive(Debug)]
 pub struct LoggerConfiguration {
@@ -110,9 +167,10 @@ pub struct LoggerConfiguration {
 pub struct Logger(pub Mutex<LoggerConfiguration>);
 
 impl Log for Logger {
-    // No additional filters to <https://docs.rs/log/latest/log/fn.max_level.html>.
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    // Applies the per-module levels on top of <https://docs.rs/log/latest/log/fn.max_level.html>.
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        let guard = self.0.lock().unwrap();
+        metadata.level() <= guard.filter.level(metadata.target())
     }
 
     fn log(&self, record: &Record) {
@@ -120,48 +178,22 @@ impl Log for Logger {
         let mut guard = self.0.lock().unwrap();
 
         // Check if the log message is enabled
-        {
-            let enabled_module = match (&guard.filter.module, record.module_path()) {
-                (Some(filter), Some(source)) => source.starts_with(filter),
-                (Some(_), None) => false,
-                (None, _) => true,
-            };
-            let enabled = enabled_module;
-            if !enabled {
-                return;
-            }
+        if !guard.filter.enabled(record.level(), record.module_path()) {
+            return;
         }
 
         // Prints log message
         {
             let thread = thread::current().name().unwrap_or("-").to_string();
-            let level = match guard.format.show_level {
-                true => format!(":{}", record.level()),
-                false => String::new(),
+            let instance_id = INSTANCE_ID
+                .get()
+                .map(|s| s.as_str())
+                .unwrap_or(DEFAULT_INSTANCE_ID);
+            let message = match guard.format.output {
+                LogOutputFormat::Text => text_line(&guard.format, record, instance_id, &thread),
+                LogOutputFormat::Json => json_line(record, instance_id, &thread),
             };
 
-            let origin = match guard.format.show_log_origin {
-                true => {
-                    let file = record.file().unwrap_or("?");
-                    let line = match record.line() {
-                        Some(x) => x.to_string(),
-                        None => String::from("?"),
-                    };
-                    format!(":{file}:{line}")
-                }
-                false => String::new(),
-            };
-
-            let message = format!(
-                "{} [{}:{thread}{level}{origin}] {}\n",
-                LocalTime::now(),
-                INSTANCE_ID
-                    .get()
-                    .map(|s| s.as_str())
-                    .unwrap_or(DEFAULT_INSTANCE_ID),
-                record.args()
-            );
-
             let result = if let Some(file) = &mut guard.target {
                 file.write_all(message.as_bytes())
             } else {
@@ -179,6 +211,63 @@ impl Log for Logger {
     fn flush(&self) {}
 }
 
+/// Formats `record` as a human readable log line.
+fn text_line(format: &LogFormat, record: &Record, instance_id: &str, thread: &str) -> String {
+    let level = match format.show_level {
+        true => format!(":{}", record.level()),
+        false => String::new(),
+    };
+
+    let origin = match format.show_log_origin {
+        true => {
+            let file = record.file().unwrap_or("?");
+            let line = match record.line() {
+                Some(x) => x.to_string(),
+                None => String::from("?"),
+            };
+            format!(":{file}:{line}")
+        }
+        false => String::new(),
+    };
+
+    format!(
+        "{} [{instance_id}:{thread}{level}{origin}] {}\n",
+        LocalTime::now(),
+        record.args()
+    )
+}
+
+/// A log line in the JSON format.
+#[derive(Debug, Serialize)]
+struct JsonLogLine<'a> {
+    timestamp: String,
+    level: &'a str,
+    thread: &'a str,
+    module: Option<&'a str>,
+    file: Option<&'a str>,
+    line: Option<u32>,
+    message: String,
+    instance_id: &'a str,
+}
+
+/// Formats `record` as a JSON object on a single line.
+fn json_line(record: &Record, instance_id: &str, thread: &str) -> String {
+    let line = JsonLogLine {
+        timestamp: LocalTime::now().to_string(),
+        level: record.level().as_str(),
+        thread,
+        module: record.module_path(),
+        file: record.file(),
+        line: record.line(),
+        message: record.args().to_string(),
+        instance_id,
+    };
+    // Serializing a structure of strings and integers cannot fail.
+    let mut message = serde_json::to_string(&line).unwrap();
+    message.push('\n');
+    message
+}
+
 /// Strongly typed structure used to describe the logger.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
@@ -193,6 +282,30 @@ pub struct LoggerConfig {
     pub show_log_origin: Option<bool>,
     /// The module to filter logs by.
     pub module: Option<String>,
+    /// The format of the log lines.
+    pub format: Option<LogOutputFormat>,
+    /// Log level overrides for the logs of specific modules.
+    pub module_levels: Option<Vec<ModuleLevel>>,
+}
+
+/// Format of the log lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOutputFormat {
+    /// Human readable text, optionally including the level and origin of the log.
+    Text,
+    /// One JSON object per line, always including the level and origin of the log.
+    Json,
+}
+
+/// Log level applying to the logs of a module and its submodules.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModuleLevel {
+    /// Module path prefix the level applies to, e.g. `vmm::devices::virtio`.
+    pub module: String,
+    /// The level of the logs of the module.
+    pub level: LevelFilter,
 }
 
 /// This is required since we originally supported `Warning` and uppercase variants being used as
@@ -367,10 +480,13 @@ mod tests {
             target: Some(target),
             filter: LogFilter {
                 module: Some(String::from("module")),
+                level: log::LevelFilter::Trace,
+                module_levels: Vec::new(),
             },
             format: LogFormat {
                 show_level: true,
                 show_log_origin: true,
+                output: LogOutputFormat::Text,
             },
         }));
 
@@ -403,4 +519,76 @@ mod tests {
 
         std::fs::remove_file(path).unwrap();
     }
+
+    #[test]
+    fn test_module_levels() {
+        let filter = LogFilter {
+            module: None,
+            level: log::LevelFilter::Warn,
+            module_levels: vec![
+                (String::from("vmm::devices"), log::LevelFilter::Debug),
+                (
+                    String::from("vmm::devices::virtio::net"),
+                    log::LevelFilter::Error,
+                ),
+            ],
+        };
+
+        assert_eq!(filter.max_level(), log::LevelFilter::Debug);
+        assert_eq!(filter.level("vmm::builder"), log::LevelFilter::Warn);
+        assert_eq!(
+            filter.level("vmm::devices::legacy"),
+            log::LevelFilter::Debug
+        );
+        assert_eq!(
+            filter.level("vmm::devices::virtio::net::device"),
+            log::LevelFilter::Error
+        );
+
+        assert!(filter.enabled(Level::Warn, Some("vmm::builder")));
+        assert!(!filter.enabled(Level::Info, Some("vmm::builder")));
+        assert!(filter.enabled(Level::Debug, Some("vmm::devices::legacy")));
+        assert!(!filter.enabled(Level::Warn, Some("vmm::devices::virtio::net")));
+        assert!(!filter.enabled(Level::Info, None));
+    }
+
+    #[test]
+    fn test_json_logger() {
+        let file = vmm_sys_util::tempfile::TempFile::new().unwrap();
+        let logger = Logger(Mutex::new(LoggerConfiguration {
+            target: Some(file.as_file().try_clone().unwrap()),
+            filter: LogFilter {
+                module: None,
+                level: log::LevelFilter::Info,
+                module_levels: Vec::new(),
+            },
+            format: LogFormat {
+                show_level: false,
+                show_log_origin: false,
+                output: LogOutputFormat::Json,
+            },
+        }));
+
+        let record = Record::builder()
+            .args(format_args!("Warning \"quoted\""))
+            .level(Level::Warn)
+            .file(Some("dir/app.rs"))
+            .line(Some(200))
+            .module_path(Some("module::server"))
+            .build();
+        logger.log(&record);
+
+        let contents = std::fs::read_to_string(file.as_path()).unwrap();
+        assert_eq!(contents.lines().count(), 1);
+        let line: serde_json::Value = serde_json::from_str(&contents).unwrap();
+        let thread = thread::current().name().unwrap_or("-").to_string();
+        assert!(line["timestamp"].is_string());
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["thread"], thread.as_str());
+        assert_eq!(line["module"], "module::server");
+        assert_eq!(line["file"], "dir/app.rs");
+        assert_eq!(line["line"], 200);
+        assert_eq!(line["message"], "Warning \"quoted\"");
+        assert_eq!(line["instance_id"], DEFAULT_INSTANCE_ID);
+    }
 }
@@ -61,6 +61,7 @@
 //! If if turns out this approach is not really what we want, it's pretty easy to resort to
 //! something else, while working behind the same interface.
 
+use std::cell::Cell;
 use std::fmt::Debug;
 use std::io::Write;
 use std::ops::Deref;
@@ -71,13 +72,22 @@ use serde::{Serialize, Serializer};
 use utils::time::{ClockType, get_time_ns, get_time_us};
 
 use super::FcLineWriter;
+use super::openmetrics::{self, COUNTER_TOKEN};
 use crate::devices::legacy;
 use crate::devices::virtio::balloon::metrics as balloon_metrics;
 use crate::devices::virtio::block::virtio::metrics as block_metrics;
+use crate::devices::virtio::mem::metrics as virtio_mem_metrics;
 use crate::devices::virtio::net::metrics as net_metrics;
 use crate::devices::virtio::rng::metrics as entropy_metrics;
 use crate::devices::virtio::vhost_user_metrics;
 use crate::devices::virtio::vsock::metrics as vsock_metrics;
+use crate::rate_limiter::metrics as rate_limiter_group_metrics;
+
+thread_local! {
+    /// Whether `SharedIncMetric`s serialize their total value, instead of flushing the delta since
+    /// the previous flush.
+    static SERIALIZE_TOTALS: Cell<bool> = const { Cell::new(false) };
+}
 
 /// Static instance used for handling metrics.
 pub static METRICS: Metrics<FirecrackerMetrics, FcLineWriter> =
@@ -152,6 +162,17 @@ impl<T: Serialize + Debug, M: Write + Send + Debug> Metrics<T, M> {
             Ok(false)
         }
     }
+
+    /// Returns the current value of the metrics in the OpenMetrics text format.
+    ///
+    /// Unlike [`Metrics::write`], this does not reset the `SharedIncMetric`s, which are exposed as
+    /// counters holding their total value.
+    pub fn openmetrics(&self) -> Result<String, MetricsError> {
+        SERIALIZE_TOTALS.set(true);
+        let res = openmetrics::encode(&self.app_metrics);
+        SERIALIZE_TOTALS.set(false);
+        res.map_err(|err| MetricsError::Serde(err.to_string()))
+    }
 }
 
 impl<T: Serialize + Debug, M: Write + Send + Debug> Deref for Metrics<T, M> {
@@ -263,6 +284,10 @@ impl Serialize for SharedIncMetric {
     /// flushing of metrics.
     /// !!! Any print of the metrics will also reset them. Use with caution !!!
     fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if SERIALIZE_TOTALS.get() {
+            return serializer.serialize_newtype_struct(COUNTER_TOKEN, &self.count());
+        }
+
         let snapshot = self.0.load(Ordering::Relaxed);
         let res = serializer.serialize_u64(snapshot - self.1.load(Ordering::Relaxed));
 
@@ -361,6 +386,8 @@ pub struct GetRequestsMetrics {
     pub instance_info_count: SharedIncMetric,
     /// Number of GETs for getting status on attaching machine configuration.
     pub machine_cfg_count: SharedIncMetric,
+    /// Number of GETs for getting the metrics in the OpenMetrics format.
+    pub metrics_count: SharedIncMetric,
     /// Number of GETs for getting mmds.
     pub mmds_count: SharedIncMetric,
     /// Number of GETs for getting the VMM version.
@@ -372,6 +399,7 @@ impl GetRequestsMetrics {
         Self {
             instance_info_count: SharedIncMetric::new(),
             machine_cfg_count: SharedIncMetric::new(),
+            metrics_count: SharedIncMetric::new(),
             mmds_count: SharedIncMetric::new(),
             vmm_version_count: SharedIncMetric::new(),
         }
@@ -425,6 +453,10 @@ pub struct PutRequestsMetrics {
     pub serial_count: SharedIncMetric,
     /// Number of failed PUTs to /serial
     pub serial_fails: SharedIncMetric,
+    /// Number of PUTs for creating a rate limiter group.
+    pub rate_limiter_group_count: SharedIncMetric,
+    /// Number of failures in creating a rate limiter group.
+    pub rate_limiter_group_fails: SharedIncMetric,
 }
 impl PutRequestsMetrics {
     /// Const default construction.
@@ -452,6 +484,8 @@ impl PutRequestsMetrics {
             vsock_fails: SharedIncMetric::new(),
             serial_count: SharedIncMetric::new(),
             serial_fails: SharedIncMetric::new(),
+            rate_limiter_group_count: SharedIncMetric::new(),
+            rate_limiter_group_fails: SharedIncMetric::new(),
         }
     }
 }
@@ -475,6 +509,10 @@ pub struct PatchRequestsMetrics {
     pub mmds_count: SharedIncMetric,
     /// Number of failures in PATCHing an mmds.
     pub mmds_fails: SharedIncMetric,
+    /// Number of tries to PATCH a rate limiter group.
+    pub rate_limiter_group_count: SharedIncMetric,
+    /// Number of failures in PATCHing a rate limiter group.
+    pub rate_limiter_group_fails: SharedIncMetric,
 }
 impl PatchRequestsMetrics {
     /// Const default construction.
@@ -488,6 +526,8 @@ impl PatchRequestsMetrics {
             machine_cfg_fails: SharedIncMetric::new(),
             mmds_count: SharedIncMetric::new(),
             mmds_fails: SharedIncMetric::new(),
+            rate_limiter_group_count: SharedIncMetric::new(),
+            rate_limiter_group_fails: SharedIncMetric::new(),
         }
     }
 }
@@ -862,8 +902,13 @@ create_serialize_proxy!(NetMetricsSerializeProxy, net_metrics);
 create_serialize_proxy!(VhostUserMetricsSerializeProxy, vhost_user_metrics);
 create_serialize_proxy!(BalloonMetricsSerializeProxy, balloon_metrics);
 create_serialize_proxy!(EntropyMetricsSerializeProxy, entropy_metrics);
+create_serialize_proxy!(VirtioMemMetricsSerializeProxy, virtio_mem_metrics);
 create_serialize_proxy!(VsockMetricsSerializeProxy, vsock_metrics);
 create_serialize_proxy!(LegacyDevMetricsSerializeProxy, legacy);
+create_serialize_proxy!(
+    RateLimiterGroupMetricsSerializeProxy,
+    rate_limiter_group_metrics
+);
 
 /// Structure storing all metrics while enforcing serialization support on them.
 #[derive(Debug, Default, Serialize)]
@@ -897,6 +942,9 @@ pub struct FirecrackerMetrics {
     pub patch_api_requests: PatchRequestsMetrics,
     /// Metrics related to API PUT requests.
     pub put_api_requests: PutRequestsMetrics,
+    #[serde(flatten)]
+    /// Metrics related to rate limiter groups.
+    pub rate_limiter_group_ser: RateLimiterGroupMetricsSerializeProxy,
     /// Metrics related to seccomp filtering.
     pub seccomp: SeccompMetrics,
     /// Metrics related to a vcpu's functioning.
@@ -912,6 +960,9 @@ pub struct FirecrackerMetrics {
     /// Metrics related to virtio-rng entropy device.
     pub entropy_ser: EntropyMetricsSerializeProxy,
     #[serde(flatten)]
+    /// Metrics related to the virtio-mem memory hotplug device.
+    pub virtio_mem_ser: VirtioMemMetricsSerializeProxy,
+    #[serde(flatten)]
     /// Vhost-user device related metrics.
     pub vhost_user_ser: VhostUserMetricsSerializeProxy,
 }
@@ -932,12 +983,14 @@ impl FirecrackerMetrics {
             net_ser: NetMetricsSerializeProxy {},
             patch_api_requests: PatchRequestsMetrics::new(),
             put_api_requests: PutRequestsMetrics::new(),
+            rate_limiter_group_ser: RateLimiterGroupMetricsSerializeProxy {},
             seccomp: SeccompMetrics::new(),
             vcpu: VcpuMetrics::new(),
             vmm: VmmMetrics::new(),
             signals: SignalMetrics::new(),
             vsock_ser: VsockMetricsSerializeProxy {},
             entropy_ser: EntropyMetricsSerializeProxy {},
+            virtio_mem_ser: VirtioMemMetricsSerializeProxy {},
             vhost_user_ser: VhostUserMetricsSerializeProxy {},
         }
     }
@@ -1028,6 +1081,44 @@ mod tests {
         s.unwrap();
     }
 
+    #[test]
+    fn test_openmetrics() {
+        let m = &Metrics::<_, FcLineWriter>::new(FirecrackerMetrics::new());
+        m.api_server.sync_response_fails.add(2);
+        m.api_server.process_startup_time_us.store(42);
+
+        // Exposing the metrics does not reset the counters.
+        for _ in 0..2 {
+            let text = m.openmetrics().unwrap();
+            assert!(text.contains(
+                "# TYPE firecracker_api_server_sync_response_fails \
+                 counter\nfirecracker_api_server_sync_response_fails_total 2\n"
+            ));
+            assert!(text.contains(
+                "# TYPE firecracker_api_server_process_startup_time_us \
+                 gauge\nfirecracker_api_server_process_startup_time_us 42\n"
+            ));
+            assert!(!text.contains("utc_timestamp_ms"));
+            assert!(text.ends_with("# EOF\n"));
+        }
+        assert_eq!(
+            serde_json::to_string(&m.api_server.sync_response_fails).unwrap(),
+            "2"
+        );
+        assert_eq!(
+            serde_json::to_string(&m.api_server.sync_response_fails).unwrap(),
+            "0"
+        );
+
+        // The counters keep their total value after being flushed.
+        m.api_server.sync_response_fails.inc();
+        assert!(
+            m.openmetrics()
+                .unwrap()
+                .contains("firecracker_api_server_sync_response_fails_total 3\n")
+        );
+    }
+
     #[test]
     fn test_error_messages() {
         assert_eq!(
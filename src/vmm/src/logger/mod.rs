@@ -6,11 +6,12 @@
 
 mod logging;
 mod metrics;
+mod openmetrics;
 
 pub use log::{Level, debug, error, info, log_enabled, trace, warn};
 pub use logging::{
     DEFAULT_INSTANCE_ID, DEFAULT_LEVEL, INSTANCE_ID, LOGGER, LevelFilter, LevelFilterFromStrError,
-    LoggerConfig, LoggerInitError, LoggerUpdateError,
+    LogOutputFormat, LoggerConfig, LoggerInitError, LoggerUpdateError, ModuleLevel,
 };
 pub use metrics::{
     IncMetric, LatencyAggregateMetrics, METRICS, MetricsError, ProcessTimeReporter,
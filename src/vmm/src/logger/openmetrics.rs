@@ -0,0 +1,529 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Exposition of the metrics in the OpenMetrics text format.
+//!
+//! The metrics structures are walked through their `Serialize` implementations, the same way they
+//! are written as JSON. Each leaf metric becomes a metric family named after its path in the JSON
+//! representation, prefixed with `firecracker_`:
+//! * `SharedIncMetric`s are counters holding their total value, rather than the delta since the
+//!   last flush, so exposing the metrics does not interfere with flushing them.
+//! * `SharedStoreMetric`s are gauges.
+//!
+//! The metrics of the devices kept in per-device maps (block, net and vhost-user) are exposed as
+//! series of a single family, labelled with the id of the device. Their aggregates are left out,
+//! since they can be computed by summing the series. The metrics of the rate limiter groups are
+//! labelled with the id of the group in the same way.
+//!
+//! ```text
+//! # TYPE firecracker_net_rx_bytes_count counter
+//! firecracker_net_rx_bytes_count_total{device="eth0"} 1024
+//! firecracker_net_rx_bytes_count_total{device="eth1"} 0
+//! # TYPE firecracker_api_server_process_startup_time_us gauge
+//! firecracker_api_server_process_startup_time_us 6483
+//! # EOF
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+
+use serde::Serialize;
+use serde::ser::{self, Impossible, SerializeMap, SerializeStruct};
+
+/// Name of the newtype struct through which a `SharedIncMetric` serializes its total value.
+pub(super) const COUNTER_TOKEN: &str = "$firecracker::logger::Counter";
+
+/// Prefix of the metric family names.
+const FAMILY_PREFIX: &str = "firecracker";
+
+/// Key prefixes of the per-device metrics, whose keys are formatted as `<prefix>_<id>`, and the
+/// label holding the id. The key equal to the prefix holds the aggregate of all devices.
+const DEVICE_PREFIXES: [(&str, &str); 4] = [
+    ("block", "device"),
+    ("net", "device"),
+    ("vhost_user", "device"),
+    ("rate_limiter_group", "group"),
+];
+
+/// Suffix of the keys of per-queue net metrics, formatted as `net_<device id>_queue<index>`.
+const NET_QUEUE_SUFFIX: &str = "_queue";
+
+/// Errors encountered while encoding the metrics in the OpenMetrics format.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub(super) enum OpenMetricsError {
+    /// {0}
+    Custom(String),
+    /// Unsupported metric type: {0}
+    Unsupported(&'static str),
+}
+
+impl ser::Error for OpenMetricsError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// Encodes `metrics` in the OpenMetrics text format.
+///
+/// `SharedIncMetric`s only serialize their total value when serialized as part of
+/// `Metrics::openmetrics`, which should be used instead of this function.
+pub(super) fn encode<T: Serialize>(metrics: &T) -> Result<String, OpenMetricsError> {
+    let mut encoder = Encoder::default();
+    metrics.serialize(&mut encoder)?;
+    Ok(encoder.render())
+}
+
+/// A series of a metric family.
+#[derive(Debug)]
+struct Sample {
+    labels: Vec<(&'static str, String)>,
+    value: u64,
+}
+
+/// A metric family and its series.
+#[derive(Debug, Default)]
+struct Family {
+    counter: bool,
+    samples: Vec<Sample>,
+}
+
+#[derive(Debug, Default)]
+struct Encoder {
+    /// Families, ordered by name so that the output is stable.
+    families: BTreeMap<String, Family>,
+    /// Path of the metric being serialized.
+    path: Vec<String>,
+    /// Labels of the metric being serialized.
+    labels: Vec<(&'static str, String)>,
+    /// Whether the metric being serialized is a counter.
+    counter: bool,
+}
+
+impl Encoder {
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, family) in &self.families {
+            let (kind, suffix) = match family.counter {
+                true => ("counter", "_total"),
+                false => ("gauge", ""),
+            };
+            // Writing to a `String` cannot fail.
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            for sample in &family.samples {
+                let _ = write!(out, "{name}{suffix}");
+                if !sample.labels.is_empty() {
+                    let labels = sample
+                        .labels
+                        .iter()
+                        .map(|(label, value)| format!("{label}=\"{}\"", escape(value)))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let _ = write!(out, "{{{labels}}}");
+                }
+                let _ = writeln!(out, " {}", sample.value);
+            }
+        }
+        out.push_str("# EOF\n");
+        out
+    }
+
+    /// Determines the path and labels of the top level metrics found at `key`, or `None` if they
+    /// should be left out.
+    fn top_level_entry(key: &str) -> Option<(String, Vec<(&'static str, String)>)> {
+        for (prefix, label) in DEVICE_PREFIXES {
+            if key == prefix {
+                return None;
+            }
+            let Some(id) = key
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix('_'))
+            else {
+                continue;
+            };
+            if prefix == "net" {
+                if let Some((device, queue)) = id.rsplit_once(NET_QUEUE_SUFFIX) {
+                    if !queue.is_empty() && queue.bytes().all(|b| b.is_ascii_digit()) {
+                        return Some((
+                            String::from("net_queue"),
+                            vec![("device", device.to_string()), ("queue", queue.to_string())],
+                        ));
+                    }
+                }
+            }
+            return Some((prefix.to_string(), vec![(label, id.to_string())]));
+        }
+        Some((key.to_string(), Vec::new()))
+    }
+
+    fn encode_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &str,
+        value: &T,
+    ) -> Result<(), OpenMetricsError> {
+        if !self.path.is_empty() {
+            self.path.push(key.to_string());
+            value.serialize(&mut *self)?;
+            self.path.pop();
+            return Ok(());
+        }
+
+        let Some((path, labels)) = Self::top_level_entry(key) else {
+            return Ok(());
+        };
+        self.path.push(path);
+        self.labels = labels;
+        value.serialize(&mut *self)?;
+        self.labels.clear();
+        self.path.pop();
+        Ok(())
+    }
+
+    fn add_sample(&mut self, value: u64) {
+        let name = std::iter::once(FAMILY_PREFIX)
+            .chain(self.path.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join("_");
+        let family = self.families.entry(name).or_default();
+        family.counter = self.counter;
+        family.samples.push(Sample {
+            labels: self.labels.clone(),
+            value,
+        });
+    }
+}
+
+/// Escapes a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl<'a> ser::Serializer for &'a mut Encoder {
+    type Ok = ();
+    type Error = OpenMetricsError;
+    type SerializeSeq = Impossible<(), OpenMetricsError>;
+    type SerializeTuple = Impossible<(), OpenMetricsError>;
+    type SerializeTupleStruct = Impossible<(), OpenMetricsError>;
+    type SerializeTupleVariant = Impossible<(), OpenMetricsError>;
+    type SerializeMap = MapEncoder<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Impossible<(), OpenMetricsError>;
+
+    fn serialize_u64(self, v: u64) -> Result<(), OpenMetricsError> {
+        self.add_sample(v);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), OpenMetricsError> {
+        self.counter = name == COUNTER_TOKEN;
+        let res = value.serialize(&mut *self);
+        self.counter = false;
+        res
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapEncoder<'a>, OpenMetricsError> {
+        Ok(MapEncoder {
+            encoder: self,
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, OpenMetricsError> {
+        Ok(self)
+    }
+
+    // The timestamp of the metrics is the only signed value, and is not a metric.
+    fn serialize_i64(self, _v: i64) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("bool"))
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("i8"))
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("i16"))
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("i32"))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), OpenMetricsError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), OpenMetricsError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), OpenMetricsError> {
+        self.serialize_u64(u64::from(v))
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("f32"))
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("f64"))
+    }
+
+    fn serialize_char(self, _v: char) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("char"))
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("str"))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("bytes"))
+    }
+
+    fn serialize_none(self) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), OpenMetricsError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("enum"))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<(), OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("enum"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("sequence"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("tuple"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("enum"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, OpenMetricsError> {
+        Err(OpenMetricsError::Unsupported("enum"))
+    }
+}
+
+impl SerializeStruct for &mut Encoder {
+    type Ok = ();
+    type Error = OpenMetricsError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), OpenMetricsError> {
+        self.encode_field(key, value)
+    }
+
+    fn end(self) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+}
+
+/// Serializes the entries of a map, whose keys are expected to be strings.
+#[derive(Debug)]
+struct MapEncoder<'a> {
+    encoder: &'a mut Encoder,
+    key: Option<String>,
+}
+
+impl SerializeMap for MapEncoder<'_> {
+    type Ok = ();
+    type Error = OpenMetricsError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), OpenMetricsError> {
+        match serde_json::to_value(key) {
+            Ok(serde_json::Value::String(key)) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(OpenMetricsError::Unsupported("non-string key")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(
+        &mut self,
+        value: &T,
+    ) -> Result<(), OpenMetricsError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| OpenMetricsError::Custom(String::from("Map value without a key")))?;
+        self.encoder.encode_field(&key, value)
+    }
+
+    fn end(self) -> Result<(), OpenMetricsError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serializer;
+
+    use super::*;
+
+    struct Counter(u64);
+
+    impl Serialize for Counter {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_newtype_struct(COUNTER_TOKEN, &self.0)
+        }
+    }
+
+    #[derive(Serialize)]
+    struct DeviceMetrics {
+        rx_count: Counter,
+        latency_us: u64,
+    }
+
+    struct PerDevice;
+
+    impl Serialize for PerDevice {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(None)?;
+            map.serialize_entry(
+                "net_eth0",
+                &DeviceMetrics {
+                    rx_count: Counter(3),
+                    latency_us: 10,
+                },
+            )?;
+            map.serialize_entry(
+                "net_eth\"1",
+                &DeviceMetrics {
+                    rx_count: Counter(4),
+                    latency_us: 20,
+                },
+            )?;
+            map.serialize_entry(
+                "net_eth0_queue1",
+                &DeviceMetrics {
+                    rx_count: Counter(1),
+                    latency_us: 5,
+                },
+            )?;
+            map.serialize_entry(
+                "net",
+                &DeviceMetrics {
+                    rx_count: Counter(7),
+                    latency_us: 20,
+                },
+            )?;
+            map.end()
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Nested {
+        sum_us: Counter,
+    }
+
+    #[derive(Serialize)]
+    struct AppMetrics {
+        timestamp_ms: i64,
+        startup_time_us: u64,
+        exit_agg: Nested,
+        #[serde(flatten)]
+        net: PerDevice,
+    }
+
+    #[test]
+    fn test_encode() {
+        let metrics = AppMetrics {
+            timestamp_ms: 1234,
+            startup_time_us: 42,
+            exit_agg: Nested {
+                sum_us: Counter(100),
+            },
+            net: PerDevice,
+        };
+
+        assert_eq!(
+            encode(&metrics).unwrap(),
+            "# TYPE firecracker_exit_agg_sum_us counter\nfirecracker_exit_agg_sum_us_total 100\n# \
+             TYPE firecracker_net_latency_us gauge\nfirecracker_net_latency_us{device=\"eth0\"} \
+             10\nfirecracker_net_latency_us{device=\"eth\\\"1\"} 20\n# TYPE \
+             firecracker_net_queue_latency_us \
+             gauge\nfirecracker_net_queue_latency_us{device=\"eth0\",queue=\"1\"} 5\n# TYPE \
+             firecracker_net_queue_rx_count \
+             counter\nfirecracker_net_queue_rx_count_total{device=\"eth0\",queue=\"1\"} 1\n# TYPE \
+             firecracker_net_rx_count counter\nfirecracker_net_rx_count_total{device=\"eth0\"} \
+             3\nfirecracker_net_rx_count_total{device=\"eth\\\"1\"} 4\n# TYPE \
+             firecracker_startup_time_us gauge\nfirecracker_startup_time_us 42\n# EOF\n"
+        );
+    }
+
+    #[test]
+    fn test_encode_unsupported() {
+        #[derive(Serialize)]
+        struct Invalid {
+            name: &'static str,
+        }
+
+        assert!(matches!(
+            encode(&Invalid { name: "invalid" }),
+            Err(OpenMetricsError::Unsupported("str"))
+        ));
+    }
+}
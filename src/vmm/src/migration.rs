@@ -0,0 +1,1108 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pre-copy live migration of a microVM between two Firecracker processes.
+//!
+//! The source sends guest memory to the destination over a stream socket while the vCPUs keep
+//! running. A first pass sends all of guest memory, and each following pass resends the pages
+//! dirtied since the previous one, as reported by KVM dirty page tracking. Once a pass is small
+//! enough, or the maximum number of passes is reached, the source pauses the microVM, sends the
+//! last dirty pages together with the [`MicrovmState`] and waits for the destination to confirm
+//! that it restored the microVM.
+//!
+//! The stream is a sequence of frames, each starting with a one byte frame type:
+//! - a setup frame, holding the [`VmInfo`] and the guest memory layout,
+//! - memory frames, holding a guest physical address, a length and the memory contents,
+//! - a state frame, holding the [`MicrovmState`], which ends the stream.
+//!
+//! Setup and state frames carry a length prefixed payload in the snapshot format, so they are
+//! subject to the same version checks as snapshot files.
+
+use std::io::{self, Read, Write};
+use std::net::{AddrParseError, SocketAddr, TcpListener, TcpStream};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use vm_memory::{
+    GuestMemoryError, ReadVolatile, VolatileMemoryError, VolatileSlice, WriteVolatile,
+};
+use vmm_sys_util::eventfd::EventFd;
+
+use crate::logger::{error, info, warn};
+use crate::persist::{
+    MicrovmState, MicrovmStateError, RestoreFromSnapshotError, VmInfo,
+    update_vm_resources_from_state,
+};
+use crate::resources::VmResources;
+use crate::seccomp::{BpfProgram, BpfThreadMap};
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::utils::{get_page_size, mib_to_bytes, u64_to_usize, usize_to_u64};
+use crate::vmm_config::instance_info::{InstanceInfo, VmState};
+use crate::vmm_config::migration::{
+    MigrationSocketConfig, MigrationSocketType, MigrationStatus, ReceiveMigrationParams,
+    SendMigrationParams,
+};
+use crate::vstate::memory::{
+    self, Bitmap, BitmapSlice, GuestMemory, GuestMemoryExtension, GuestMemoryMmap,
+    GuestMemoryRegion, GuestMemoryState, GuestRegionMmap, MemoryError, MemoryRegionAddress,
+};
+use crate::vstate::vm::{Vm, VmError};
+use crate::{DirtyBitmap, EventManager, Vmm, VmmError, builder};
+
+/// Frame holding the [`MigrationSetup`].
+const FRAME_SETUP: u8 = 0;
+/// Frame holding a range of guest memory.
+const FRAME_MEMORY: u8 = 1;
+/// Frame holding the [`MicrovmState`], sent last.
+const FRAME_STATE: u8 = 2;
+
+/// Sent by the destination once the microVM was restored.
+const MIGRATION_ACK: u8 = 0;
+/// Sent by the destination when it failed to restore the microVM.
+const MIGRATION_NACK: u8 = 1;
+
+/// Upper bound for the payload of setup and state frames.
+const MAX_STATE_FRAME_LEN: u64 = mib_to_bytes(16) as u64;
+
+/// Describes the microVM being migrated, sent before any guest memory.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct MigrationSetup {
+    vm_info: VmInfo,
+    memory_state: GuestMemoryState,
+}
+
+/// Errors associated with sending a microVM to another Firecracker process.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SendMigrationError {
+    /// Live migration requires dirty page tracking to be enabled.
+    DirtyPageTrackingDisabled,
+    /// Invalid migration socket address {0}: {1}
+    InvalidAddress(String, AddrParseError),
+    /// Cannot connect to the destination: {0}
+    Connect(io::Error),
+    /// Cannot get dirty bitmap: {0}
+    DirtyBitmap(#[from] VmError),
+    /// Cannot fetch system's page size: {0}
+    PageSize(vmm_sys_util::errno::Error),
+    /// Cannot send guest memory: {0}
+    Memory(GuestMemoryError),
+    /// Cannot send data to the destination: {0}
+    Io(#[from] io::Error),
+    /// Cannot serialize migration data: {0}
+    Serialize(#[from] SnapshotError),
+    /// Cannot pause the microVM: {0}
+    Pause(VmmError),
+    /// Cannot save the microVM state: {0}
+    MicrovmState(MicrovmStateError),
+    /// Cannot get the migration result from the destination: {0}
+    Acknowledge(io::Error),
+    /// The destination failed to restore the microVM.
+    Rejected,
+    /// A live migration is already in progress.
+    InProgress,
+    /// The migration thread is not running.
+    WorkerStopped,
+}
+
+/// Errors associated with receiving a microVM from another Firecracker process.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum ReceiveMigrationError {
+    /// Receiving a microVM is not allowed after configuring boot-specific resources.
+    NotAllowed,
+    /// Invalid migration socket address {0}: {1}
+    InvalidAddress(String, AddrParseError),
+    /// Cannot listen on unspecified address {0}, an explicit IP address is required.
+    UnspecifiedAddress(SocketAddr),
+    /// Cannot accept the migration connection: {0}
+    Listen(io::Error),
+    /// Cannot receive data from the source: {0}
+    Io(#[from] io::Error),
+    /// Cannot deserialize migration data: {0}
+    Deserialize(#[from] SnapshotError),
+    /// Unexpected frame type in the migration stream: {0}
+    UnexpectedFrame(u8),
+    /// Migration stream frame of {0} bytes is too large.
+    FrameTooLarge(u64),
+    /// Cannot create guest memory: {0}
+    GuestMemory(#[from] MemoryError),
+    /// Guest memory range {0:#x}, length {1:#x}, is out of bounds.
+    InvalidMemoryRange(u64, u64),
+    /// Cannot receive guest memory: {0}
+    Memory(GuestMemoryError),
+    /// Failed to restore the microVM: {0}
+    Restore(#[from] RestoreFromSnapshotError),
+    /// Cannot send the migration result to the source: {0}
+    Acknowledge(io::Error),
+    /// Failed to resume microVM: {0}
+    ResumeMicrovm(#[from] VmmError),
+}
+
+impl ReceiveMigrationError {
+    /// Whether the error happened after the microVM resources were modified, in which case the
+    /// process cannot be used for another attempt.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::Restore(_) | Self::Acknowledge(_) | Self::ResumeMicrovm(_)
+        )
+    }
+}
+
+fn parse_tcp_address(address: &str) -> Result<SocketAddr, AddrParseError> {
+    address.parse()
+}
+
+/// Connection to the destination of a live migration.
+#[derive(Debug)]
+enum MigrationStream {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for MigrationStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.read(buf),
+            Self::Tcp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for MigrationStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Unix(stream) => stream.write(buf),
+            Self::Tcp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Unix(stream) => stream.flush(),
+            Self::Tcp(stream) => stream.flush(),
+        }
+    }
+}
+
+impl WriteVolatile for MigrationStream {
+    fn write_volatile<B: BitmapSlice>(
+        &mut self,
+        buf: &VolatileSlice<B>,
+    ) -> Result<usize, VolatileMemoryError> {
+        match self {
+            Self::Unix(stream) => stream.write_volatile(buf),
+            Self::Tcp(stream) => stream.write_volatile(buf),
+        }
+    }
+}
+
+/// Work handed over to the migration thread.
+#[derive(Debug)]
+enum MigrationRequest {
+    /// Sends the setup frame and the pre-copy passes of a new live migration.
+    PreCopy {
+        vm: Arc<Vm>,
+        vm_info: VmInfo,
+        max_iterations: u32,
+        dirty_threshold_mib: u64,
+        stream: MigrationStream,
+    },
+    /// Waits for the destination to restore the microVM, once all of its state was sent.
+    Acknowledge(MigrationStream),
+}
+
+/// Progress of a live migration, reported by the migration thread to the VMM thread.
+#[derive(Debug)]
+enum MigrationEvent {
+    /// The pre-copy passes were sent, the microVM can be paused to send the rest of its state.
+    PreCopied(MigrationStream),
+    /// The live migration ended.
+    Done(Result<(), SendMigrationError>),
+}
+
+/// Live migration handed over to the migration thread.
+#[derive(Debug)]
+struct OngoingMigration {
+    vm_info: VmInfo,
+    /// Whether the microVM was paused by the live migration, and must be resumed if it fails.
+    paused: bool,
+}
+
+/// Thread sending the guest memory of live migrations while the vCPUs keep running.
+///
+/// Threads spawned by the VMM thread inherit its seccomp filters, which do not allow spawning
+/// threads. The migration thread is thus started along with the vcpus, before the VMM thread
+/// filters are loaded, and waits with the VMM filters loaded until a live migration is handed to
+/// it. The VMM thread only takes over to send the part of the microVM state that requires the
+/// vCPUs to be paused.
+#[derive(Debug)]
+pub struct MigrationWorker {
+    request_sender: Sender<MigrationRequest>,
+    event_receiver: Receiver<MigrationEvent>,
+    /// Written by the migration thread each time it reports an event.
+    pub(crate) event_evt: EventFd,
+    ongoing: Option<OngoingMigration>,
+    status: MigrationStatus,
+}
+
+impl MigrationWorker {
+    /// Starts the migration thread.
+    pub fn start_threaded(seccomp_filter: Arc<BpfProgram>) -> io::Result<Self> {
+        let (request_sender, request_receiver) = channel();
+        let (event_sender, event_receiver) = channel();
+        let event_evt = EventFd::new(libc::EFD_NONBLOCK)?;
+        let thread_event_evt = event_evt.try_clone()?;
+
+        thread::Builder::new()
+            .name("fc_migration".to_string())
+            .spawn(move || {
+                // Execution panics if filters cannot be loaded, use --no-seccomp if skipping
+                // filters altogether is the desired behaviour.
+                if let Err(err) = crate::seccomp::apply_filter(&seccomp_filter) {
+                    panic!(
+                        "Failed to set the requested seccomp filters on the migration thread: \
+                         {err}"
+                    );
+                }
+                // The sender is dropped when the microVM stops.
+                for request in request_receiver.iter() {
+                    let event = match request {
+                        MigrationRequest::PreCopy {
+                            vm,
+                            vm_info,
+                            max_iterations,
+                            dirty_threshold_mib,
+                            mut stream,
+                        } => match pre_copy(
+                            &vm,
+                            &vm_info,
+                            max_iterations,
+                            dirty_threshold_mib,
+                            &mut stream,
+                        ) {
+                            Ok(()) => MigrationEvent::PreCopied(stream),
+                            Err(err) => MigrationEvent::Done(Err(err)),
+                        },
+                        MigrationRequest::Acknowledge(mut stream) => {
+                            MigrationEvent::Done(wait_acknowledge(&mut stream))
+                        }
+                    };
+                    if event_sender.send(event).is_err() {
+                        return;
+                    }
+                    if let Err(err) = thread_event_evt.write(1) {
+                        error!("Failed to signal live migration progress: {err}");
+                    }
+                }
+            })?;
+
+        Ok(MigrationWorker {
+            request_sender,
+            event_receiver,
+            event_evt,
+            ongoing: None,
+            status: MigrationStatus::default(),
+        })
+    }
+
+    /// Returns the status of the last live migration.
+    pub fn status(&self) -> MigrationStatus {
+        self.status.clone()
+    }
+
+    /// Whether a live migration is being sent.
+    pub fn in_progress(&self) -> bool {
+        self.ongoing.is_some()
+    }
+}
+
+/// Starts the live migration of the microVM to the Firecracker process listening on the
+/// configured socket.
+///
+/// The guest memory is sent by the migration thread while the microVM keeps running, and the
+/// progress is reported by [`MigrationWorker::status`]. On success, the microVM is left paused.
+/// On failure, a microVM paused by the live migration is resumed, unless it is unknown whether
+/// the destination took over.
+pub fn send_migration(
+    vmm: &mut Vmm,
+    vm_info: &VmInfo,
+    params: &SendMigrationParams,
+) -> Result<(), SendMigrationError> {
+    let vm = vmm.vm.clone();
+    // The migration thread is only started when dirty page tracking is enabled.
+    let worker = vmm
+        .migration
+        .as_mut()
+        .ok_or(SendMigrationError::DirtyPageTrackingDisabled)?;
+    if worker.in_progress() {
+        return Err(SendMigrationError::InProgress);
+    }
+
+    let MigrationSocketConfig {
+        socket_type,
+        address,
+    } = &params.socket;
+    let stream = match socket_type {
+        MigrationSocketType::Unix => MigrationStream::Unix(
+            UnixStream::connect(address).map_err(SendMigrationError::Connect)?,
+        ),
+        MigrationSocketType::Tcp => {
+            let address = parse_tcp_address(address)
+                .map_err(|err| SendMigrationError::InvalidAddress(address.clone(), err))?;
+            MigrationStream::Tcp(TcpStream::connect(address).map_err(SendMigrationError::Connect)?)
+        }
+    };
+
+    worker
+        .request_sender
+        .send(MigrationRequest::PreCopy {
+            vm,
+            vm_info: vm_info.clone(),
+            max_iterations: params.max_iterations,
+            dirty_threshold_mib: params.dirty_threshold_mib,
+            stream,
+        })
+        .map_err(|_| SendMigrationError::WorkerStopped)?;
+    worker.ongoing = Some(OngoingMigration {
+        vm_info: vm_info.clone(),
+        paused: false,
+    });
+    worker.status = MigrationStatus::InProgress;
+    Ok(())
+}
+
+/// Handles the events reported by the migration thread. Runs on the VMM thread.
+pub(crate) fn process_migration_events(vmm: &mut Vmm) {
+    let Some(worker) = vmm.migration.as_mut() else {
+        return;
+    };
+    let _ = worker.event_evt.read();
+    let events: Vec<MigrationEvent> = worker.event_receiver.try_iter().collect();
+
+    for event in events {
+        match event {
+            MigrationEvent::PreCopied(mut stream) => match send_paused_state(vmm, &mut stream) {
+                Ok(()) => {
+                    // Safe to unwrap because the migration thread is running.
+                    let worker = vmm.migration.as_mut().unwrap();
+                    if worker
+                        .request_sender
+                        .send(MigrationRequest::Acknowledge(stream))
+                        .is_err()
+                    {
+                        finish_migration(vmm, Err(SendMigrationError::WorkerStopped));
+                    }
+                }
+                Err(err) => finish_migration(vmm, Err(err)),
+            },
+            MigrationEvent::Done(result) => finish_migration(vmm, result),
+        }
+    }
+}
+
+/// Sends the setup frame, all of guest memory, and then the pages dirtied in the meantime until
+/// few enough of them are left. Runs on the migration thread, while the vCPUs keep running.
+fn pre_copy<S: Write + WriteVolatile>(
+    vm: &Vm,
+    vm_info: &VmInfo,
+    max_iterations: u32,
+    dirty_threshold_mib: u64,
+    stream: &mut S,
+) -> Result<(), SendMigrationError> {
+    let guest_memory = vm.guest_memory();
+    let page_size = get_page_size().map_err(SendMigrationError::PageSize)?;
+    let dirty_threshold = dirty_threshold_mib.saturating_mul(1024 * 1024);
+
+    let setup = MigrationSetup {
+        vm_info: vm_info.clone(),
+        memory_state: guest_memory.describe(),
+    };
+    write_state_frame(stream, FRAME_SETUP, &setup)?;
+
+    // Only pages written after this point need to be sent again.
+    vm.reset_dirty_bitmap();
+    guest_memory.reset_dirty();
+    for region in guest_memory.iter() {
+        send_memory_range(stream, region, 0, u64_to_usize(region.len()))?;
+    }
+
+    for iteration in 1..=max_iterations {
+        let dirty_bitmap = vm.get_dirty_bitmap()?;
+        let sent = send_dirty_memory(stream, guest_memory, &dirty_bitmap, page_size)?;
+        info!("Live migration pass {iteration} sent {sent} bytes of dirty memory.");
+        if sent <= dirty_threshold {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Pauses the microVM, and sends the last dirty pages together with the [`MicrovmState`]. Runs
+/// on the VMM thread.
+fn send_paused_state<S: Write + WriteVolatile>(
+    vmm: &mut Vmm,
+    stream: &mut S,
+) -> Result<(), SendMigrationError> {
+    let vm = vmm.vm.clone();
+    let guest_memory = vm.guest_memory();
+    let page_size = get_page_size().map_err(SendMigrationError::PageSize)?;
+
+    if vmm.instance_info.state == VmState::Running {
+        vmm.pause_vm().map_err(SendMigrationError::Pause)?;
+        if let Some(ongoing) = vmm
+            .migration
+            .as_mut()
+            .and_then(|worker| worker.ongoing.as_mut())
+        {
+            ongoing.paused = true;
+        }
+    }
+
+    // Devices do not mark the pages of their queues as dirty when updating them.
+    vmm.device_manager
+        .mark_virtio_queue_memory_dirty(guest_memory);
+    let dirty_bitmap = vm.get_dirty_bitmap()?;
+    let sent = send_dirty_memory(stream, guest_memory, &dirty_bitmap, page_size)?;
+    info!("Live migration sent {sent} bytes of dirty memory with the microVM paused.");
+
+    // Safe to unwrap because the VMM thread only sends the state of an ongoing migration.
+    let vm_info = vmm
+        .migration
+        .as_ref()
+        .and_then(|worker| worker.ongoing.as_ref())
+        .map(|ongoing| ongoing.vm_info.clone())
+        .unwrap();
+    let microvm_state = vmm
+        .save_state(&vm_info)
+        .map_err(SendMigrationError::MicrovmState)?;
+    write_state_frame(stream, FRAME_STATE, &microvm_state)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Waits for the destination to report whether it restored the microVM. Runs on the migration
+/// thread.
+fn wait_acknowledge<S: Read>(stream: &mut S) -> Result<(), SendMigrationError> {
+    let mut ack = [0u8];
+    stream
+        .read_exact(&mut ack)
+        .map_err(SendMigrationError::Acknowledge)?;
+    match ack[0] {
+        MIGRATION_ACK => Ok(()),
+        _ => Err(SendMigrationError::Rejected),
+    }
+}
+
+/// Records the result of the ongoing live migration. Runs on the VMM thread.
+fn finish_migration(vmm: &mut Vmm, result: Result<(), SendMigrationError>) {
+    // The migration consumed the dirty page information, so the next diff snapshot has to
+    // contain all of guest memory.
+    let guest_memory = vmm.vm.guest_memory();
+    guest_memory.iter().for_each(|region| {
+        guest_memory.mark_dirty(region.start_addr(), u64_to_usize(region.len()))
+    });
+
+    // Safe to unwrap because events are only reported by a running migration thread.
+    let worker = vmm.migration.as_mut().unwrap();
+    let paused = worker.ongoing.take().is_some_and(|ongoing| ongoing.paused);
+    worker.status = match &result {
+        Ok(()) => MigrationStatus::Completed,
+        Err(err) => MigrationStatus::Failed {
+            error: err.to_string(),
+        },
+    };
+
+    match result {
+        Ok(()) => info!("Live migration completed."),
+        // The destination may already be running the microVM, so we cannot resume it here.
+        Err(err @ SendMigrationError::Acknowledge(_)) => error!("Live migration failed: {err}"),
+        Err(err) => {
+            error!("Live migration failed: {err}");
+            if paused && vmm.instance_info.state == VmState::Paused {
+                if let Err(resume_err) = vmm.resume_vm() {
+                    warn!("Failed to resume microVM after failed migration: {resume_err}");
+                }
+            }
+        }
+    }
+}
+
+fn write_state_frame<S: Write, T: Serialize>(
+    stream: &mut S,
+    frame_type: u8,
+    data: &T,
+) -> Result<(), SendMigrationError> {
+    let mut payload = Vec::new();
+    Snapshot::new(data).save(&mut payload)?;
+
+    stream.write_all(&[frame_type])?;
+    stream.write_all(&usize_to_u64(payload.len()).to_le_bytes())?;
+    stream.write_all(&payload)?;
+    Ok(())
+}
+
+fn send_memory_range<S: Write + WriteVolatile>(
+    stream: &mut S,
+    region: &GuestRegionMmap,
+    offset: usize,
+    len: usize,
+) -> Result<(), SendMigrationError> {
+    let guest_addr = region.start_addr().0 + usize_to_u64(offset);
+    let mut header = [0u8; 17];
+    header[0] = FRAME_MEMORY;
+    header[1..9].copy_from_slice(&guest_addr.to_le_bytes());
+    header[9..].copy_from_slice(&usize_to_u64(len).to_le_bytes());
+    stream.write_all(&header)?;
+
+    region
+        .get_slice(MemoryRegionAddress(usize_to_u64(offset)), len)
+        .and_then(|slice| Ok(stream.write_all_volatile(&slice)?))
+        .map_err(SendMigrationError::Memory)
+}
+
+/// Sends the pages that are dirty in either the KVM or the Firecracker bitmap, coalescing
+/// contiguous pages into a single frame. Returns the number of bytes sent.
+///
+/// The Firecracker bitmap of a region is cleared before any of its pages is read, so that the
+/// pages written by the devices while they are sent are marked dirty again, and resent by the
+/// next pass.
+fn send_dirty_memory<S: Write + WriteVolatile>(
+    stream: &mut S,
+    guest_memory: &GuestMemoryMmap,
+    dirty_bitmap: &DirtyBitmap,
+    page_size: usize,
+) -> Result<u64, SendMigrationError> {
+    let mut sent = 0;
+
+    for (region, slot) in guest_memory.iter().zip(0u32..) {
+        let kvm_bitmap = dirty_bitmap.get(&slot).map(Vec::as_slice).unwrap_or(&[]);
+        let num_pages = u64_to_usize(region.len()) / page_size;
+        let dirty_pages: Vec<bool> = (0..num_pages)
+            .map(|page| {
+                let is_kvm_dirty = kvm_bitmap
+                    .get(page / 64)
+                    .is_some_and(|word| (word >> (page % 64)) & 1 != 0);
+                let is_firecracker_dirty = region.bitmap().as_ref().is_some_and(|bitmap| {
+                    let is_dirty = bitmap.dirty_at(page * page_size);
+                    if is_dirty {
+                        bitmap.reset_addr_range(page * page_size, page_size);
+                    }
+                    is_dirty
+                });
+                is_kvm_dirty || is_firecracker_dirty
+            })
+            .collect();
+        let mut batch_start = None;
+
+        // Iterate one page past the end, so that a batch reaching the end of the region is sent.
+        for page in 0..=num_pages {
+            let is_dirty = dirty_pages.get(page).copied().unwrap_or(false);
+
+            match (is_dirty, batch_start) {
+                (true, None) => batch_start = Some(page),
+                (false, Some(start)) => {
+                    let len = (page - start) * page_size;
+                    send_memory_range(stream, region, start * page_size, len)?;
+                    sent += usize_to_u64(len);
+                    batch_start = None;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+/// Waits for a microVM migrated from another Firecracker process on the configured socket, and
+/// builds it.
+pub fn receive_migration(
+    instance_info: &InstanceInfo,
+    event_manager: &mut EventManager,
+    seccomp_filters: &BpfThreadMap,
+    params: &ReceiveMigrationParams,
+    vm_resources: &mut VmResources,
+) -> Result<Arc<Mutex<Vmm>>, ReceiveMigrationError> {
+    let MigrationSocketConfig {
+        socket_type,
+        address,
+    } = &params.socket;
+    match socket_type {
+        MigrationSocketType::Unix => {
+            let listener = UnixListener::bind(address).map_err(ReceiveMigrationError::Listen)?;
+            let accepted = listener.accept();
+            // Only a single connection is accepted, so the socket file is not needed anymore.
+            if let Err(err) = std::fs::remove_file(address) {
+                warn!("Failed to remove migration socket {address}: {err}");
+            }
+            let (mut stream, _) = accepted.map_err(ReceiveMigrationError::Listen)?;
+            receive(
+                &mut stream,
+                instance_info,
+                event_manager,
+                seccomp_filters,
+                params,
+                vm_resources,
+            )
+        }
+        MigrationSocketType::Tcp => {
+            let address = parse_tcp_address(address)
+                .map_err(|err| ReceiveMigrationError::InvalidAddress(address.clone(), err))?;
+            // The stream is not authenticated and the first peer is accepted, so only listen on
+            // the interface of the network that the source is reached through.
+            if address.ip().is_unspecified() {
+                return Err(ReceiveMigrationError::UnspecifiedAddress(address));
+            }
+            let listener = TcpListener::bind(address).map_err(ReceiveMigrationError::Listen)?;
+            let (mut stream, peer) = listener.accept().map_err(ReceiveMigrationError::Listen)?;
+            info!("Receiving microVM from {peer}.");
+            receive(
+                &mut stream,
+                instance_info,
+                event_manager,
+                seccomp_filters,
+                params,
+                vm_resources,
+            )
+        }
+    }
+}
+
+fn receive<S: Read + Write + ReadVolatile>(
+    stream: &mut S,
+    instance_info: &InstanceInfo,
+    event_manager: &mut EventManager,
+    seccomp_filters: &BpfThreadMap,
+    params: &ReceiveMigrationParams,
+    vm_resources: &mut VmResources,
+) -> Result<Arc<Mutex<Vmm>>, ReceiveMigrationError> {
+    let frame_type = read_frame_type(stream)?;
+    if frame_type != FRAME_SETUP {
+        return Err(ReceiveMigrationError::UnexpectedFrame(frame_type));
+    }
+    let setup: MigrationSetup = read_state_payload(stream)?;
+    let guest_memory = memory::anonymous(
+        setup.memory_state.regions(),
+        params.track_dirty_pages,
+        setup.vm_info.huge_pages,
+    )?;
+    let microvm_state = receive_memory(stream, &guest_memory)?;
+
+    let result =
+        update_vm_resources_from_state(&microvm_state, params.track_dirty_pages, vm_resources)
+            .and_then(|()| {
+                builder::build_microvm_from_snapshot(
+                    instance_info,
+                    event_manager,
+                    microvm_state,
+                    guest_memory,
+                    None,
+                    seccomp_filters,
+                    vm_resources,
+                )
+                .map_err(RestoreFromSnapshotError::Build)
+            });
+
+    let ack = match result {
+        Ok(_) => MIGRATION_ACK,
+        Err(_) => MIGRATION_NACK,
+    };
+    let vmm = result?;
+    stream
+        .write_all(&[ack])
+        .and_then(|()| stream.flush())
+        .map_err(ReceiveMigrationError::Acknowledge)?;
+    Ok(vmm)
+}
+
+/// Writes the received memory frames into `guest_memory`, until the state frame ending the
+/// stream is received.
+fn receive_memory<S: Read + ReadVolatile>(
+    stream: &mut S,
+    guest_memory: &[GuestRegionMmap],
+) -> Result<MicrovmState, ReceiveMigrationError> {
+    loop {
+        match read_frame_type(stream)? {
+            FRAME_MEMORY => receive_memory_range(stream, guest_memory)?,
+            FRAME_STATE => return read_state_payload(stream),
+            frame_type => return Err(ReceiveMigrationError::UnexpectedFrame(frame_type)),
+        }
+    }
+}
+
+fn read_frame_type<S: Read>(stream: &mut S) -> Result<u8, ReceiveMigrationError> {
+    let mut frame_type = [0u8];
+    stream.read_exact(&mut frame_type)?;
+    Ok(frame_type[0])
+}
+
+fn read_u64<S: Read>(stream: &mut S) -> Result<u64, ReceiveMigrationError> {
+    let mut bytes = [0u8; 8];
+    stream.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_state_payload<S: Read, T: DeserializeOwned>(
+    stream: &mut S,
+) -> Result<T, ReceiveMigrationError> {
+    let len = read_u64(stream)?;
+    if len > MAX_STATE_FRAME_LEN {
+        return Err(ReceiveMigrationError::FrameTooLarge(len));
+    }
+    let mut payload = vec![0u8; u64_to_usize(len)];
+    stream.read_exact(&mut payload)?;
+    Ok(Snapshot::load(&mut payload.as_slice())?.data)
+}
+
+fn receive_memory_range<S: Read + ReadVolatile>(
+    stream: &mut S,
+    guest_memory: &[GuestRegionMmap],
+) -> Result<(), ReceiveMigrationError> {
+    let guest_addr = read_u64(stream)?;
+    let len = read_u64(stream)?;
+
+    let region = guest_memory
+        .iter()
+        .find(|region| {
+            let start = region.start_addr().0;
+            guest_addr >= start
+                && (guest_addr - start)
+                    .checked_add(len)
+                    .is_some_and(|end| end <= region.len())
+        })
+        .ok_or(ReceiveMigrationError::InvalidMemoryRange(guest_addr, len))?;
+
+    region
+        .get_slice(
+            MemoryRegionAddress(guest_addr - region.start_addr().0),
+            u64_to_usize(len),
+        )
+        .and_then(|mut slice| Ok(stream.read_exact_volatile(&mut slice)?))
+        .map_err(ReceiveMigrationError::Memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::thread;
+
+    use vmm_sys_util::tempdir::TempDir;
+
+    use super::*;
+    use crate::builder::tests::default_vmm;
+    use crate::seccomp::get_empty_filters;
+    use crate::vmm_config::machine_config::HugePageConfig;
+    use crate::vstate::memory::{Bytes, GuestAddress};
+
+    fn guest_memory(track_dirty_pages: bool) -> Vec<GuestRegionMmap> {
+        let page_size = get_page_size().unwrap();
+        let regions = [
+            (GuestAddress(0), 4 * page_size),
+            (GuestAddress(0x10_0000), 4 * page_size),
+        ];
+        memory::anonymous(regions.into_iter(), track_dirty_pages, HugePageConfig::None).unwrap()
+    }
+
+    fn microvm_state_frame(stream: &mut UnixStream) {
+        write_state_frame(stream, FRAME_STATE, &MicrovmState::default()).unwrap();
+    }
+
+    #[test]
+    fn test_transfer_memory() {
+        let page_size = get_page_size().unwrap();
+        let src = GuestMemoryMmap::from_regions(guest_memory(true)).unwrap();
+        let dst = guest_memory(false);
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+
+        let first_region: Vec<u8> = (0..4 * page_size)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect();
+        src.write_slice(&first_region, GuestAddress(0)).unwrap();
+        src.write_slice(&[0xaa; 16], GuestAddress(0x10_0000 + page_size as u64))
+            .unwrap();
+
+        let sender = thread::spawn(move || {
+            // Full copy of the memory, then only the pages dirtied afterwards.
+            src.reset_dirty();
+            for region in src.iter() {
+                send_memory_range(&mut sender, region, 0, u64_to_usize(region.len())).unwrap();
+            }
+
+            // One page is dirty in the Firecracker bitmap, the other in the KVM bitmap.
+            src.write_slice(&[0xbb; 8], GuestAddress(0x10_0000 + 3 * page_size as u64))
+                .unwrap();
+            src.write_slice(&[0xcc; 8], GuestAddress(0)).unwrap();
+            src.reset_dirty();
+            src.mark_dirty(GuestAddress(0x10_0000 + 3 * page_size as u64), 1);
+            let dirty_bitmap: DirtyBitmap = HashMap::from([(0, vec![0b1]), (1, vec![0])]);
+            let sent = send_dirty_memory(&mut sender, &src, &dirty_bitmap, page_size).unwrap();
+            assert_eq!(sent, 2 * page_size as u64);
+            // The Firecracker bitmap is reset after sending dirty pages.
+            assert!(!src.iter().any(|region| region.bitmap().dirty_at(0)));
+
+            microvm_state_frame(&mut sender);
+            src
+        });
+
+        receive_memory(&mut receiver, &dst).unwrap();
+        let src = sender.join().unwrap();
+
+        let dst = GuestMemoryMmap::from_regions(dst).unwrap();
+        for region in src.iter() {
+            let mut expected = vec![0u8; u64_to_usize(region.len())];
+            let mut actual = vec![0u8; u64_to_usize(region.len())];
+            src.read_slice(&mut expected, region.start_addr()).unwrap();
+            dst.read_slice(&mut actual, region.start_addr()).unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    /// Stream writing to guest memory the first time some of it is sent, like a device emulated
+    /// while a pre-copy pass runs.
+    #[derive(Debug)]
+    struct DirtyingStream {
+        stream: UnixStream,
+        guest_memory: GuestMemoryMmap,
+        writes: Vec<(GuestAddress, u8)>,
+    }
+
+    impl Write for DirtyingStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.stream.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.stream.flush()
+        }
+    }
+
+    impl WriteVolatile for DirtyingStream {
+        fn write_volatile<B: BitmapSlice>(
+            &mut self,
+            buf: &VolatileSlice<B>,
+        ) -> Result<usize, VolatileMemoryError> {
+            let written = self.stream.write_volatile(buf)?;
+            for (addr, value) in self.writes.drain(..) {
+                self.guest_memory.write_slice(&[value; 8], addr).unwrap();
+            }
+            Ok(written)
+        }
+    }
+
+    #[test]
+    fn test_send_dirty_memory_while_dirtied() {
+        let page_size = get_page_size().unwrap();
+        let src = GuestMemoryMmap::from_regions(guest_memory(true)).unwrap();
+        let dst = guest_memory(false);
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+
+        let sender = thread::spawn(move || {
+            let mut stream = DirtyingStream {
+                stream: sender,
+                guest_memory: src.clone(),
+                writes: Vec::new(),
+            };
+            src.reset_dirty();
+            for region in src.iter() {
+                send_memory_range(&mut stream, region, 0, u64_to_usize(region.len())).unwrap();
+            }
+
+            // While the dirty page is sent, it is written again, along with a page that was
+            // clean when the pass started.
+            src.write_slice(&[0xaa; 8], GuestAddress(0)).unwrap();
+            let clean_page = GuestAddress(0x10_0000 + 2 * page_size as u64);
+            stream.writes = vec![(GuestAddress(8), 0xbb), (clean_page, 0xcc)];
+            let no_kvm_dirty_pages: DirtyBitmap = HashMap::from([(0, vec![0]), (1, vec![0])]);
+            let sent =
+                send_dirty_memory(&mut stream, &src, &no_kvm_dirty_pages, page_size).unwrap();
+            assert_eq!(sent, page_size as u64);
+
+            // Both pages are still dirty, so the next pass sends them.
+            let regions: Vec<_> = src.iter().collect();
+            assert!(regions[0].bitmap().dirty_at(0));
+            assert!(regions[1].bitmap().dirty_at(2 * page_size));
+            let sent =
+                send_dirty_memory(&mut stream, &src, &no_kvm_dirty_pages, page_size).unwrap();
+            assert_eq!(sent, 2 * page_size as u64);
+
+            microvm_state_frame(&mut stream.stream);
+            src
+        });
+
+        receive_memory(&mut receiver, &dst).unwrap();
+        let src = sender.join().unwrap();
+
+        let dst = GuestMemoryMmap::from_regions(dst).unwrap();
+        for region in src.iter() {
+            let mut expected = vec![0u8; u64_to_usize(region.len())];
+            let mut actual = vec![0u8; u64_to_usize(region.len())];
+            src.read_slice(&mut expected, region.start_addr()).unwrap();
+            dst.read_slice(&mut actual, region.start_addr()).unwrap();
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_receive_invalid_stream() {
+        let dst = guest_memory(false);
+
+        // Unknown frame type.
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        sender.write_all(&[0xff]).unwrap();
+        assert!(matches!(
+            receive_memory(&mut receiver, &dst),
+            Err(ReceiveMigrationError::UnexpectedFrame(0xff))
+        ));
+
+        // Memory range outside of guest memory.
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        sender.write_all(&[FRAME_MEMORY]).unwrap();
+        sender.write_all(&0x8000_0000u64.to_le_bytes()).unwrap();
+        sender.write_all(&0x1000u64.to_le_bytes()).unwrap();
+        assert!(matches!(
+            receive_memory(&mut receiver, &dst),
+            Err(ReceiveMigrationError::InvalidMemoryRange(
+                0x8000_0000,
+                0x1000
+            ))
+        ));
+
+        // Memory range crossing the end of a region.
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        sender.write_all(&[FRAME_MEMORY]).unwrap();
+        sender.write_all(&0x10_0000u64.to_le_bytes()).unwrap();
+        sender.write_all(&u64::MAX.to_le_bytes()).unwrap();
+        assert!(matches!(
+            receive_memory(&mut receiver, &dst),
+            Err(ReceiveMigrationError::InvalidMemoryRange(
+                0x10_0000,
+                u64::MAX
+            ))
+        ));
+
+        // State frame larger than allowed.
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        sender.write_all(&[FRAME_STATE]).unwrap();
+        sender
+            .write_all(&(MAX_STATE_FRAME_LEN + 1).to_le_bytes())
+            .unwrap();
+        assert!(matches!(
+            receive_memory(&mut receiver, &dst),
+            Err(ReceiveMigrationError::FrameTooLarge(_))
+        ));
+
+        // Stream closed before the state frame.
+        let (sender, mut receiver) = UnixStream::pair().unwrap();
+        drop(sender);
+        assert!(matches!(
+            receive_memory(&mut receiver, &dst),
+            Err(ReceiveMigrationError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_state_frame() {
+        let (mut sender, mut receiver) = UnixStream::pair().unwrap();
+        let setup = MigrationSetup {
+            vm_info: VmInfo {
+                mem_size_mib: 128,
+                ..Default::default()
+            },
+            memory_state: GuestMemoryMmap::from_regions(guest_memory(false))
+                .unwrap()
+                .describe(),
+        };
+        write_state_frame(&mut sender, FRAME_SETUP, &setup).unwrap();
+
+        assert_eq!(read_frame_type(&mut receiver).unwrap(), FRAME_SETUP);
+        let received: MigrationSetup = read_state_payload(&mut receiver).unwrap();
+        assert_eq!(received, setup);
+    }
+
+    #[test]
+    fn test_send_migration_errors() {
+        let mut vmm = default_vmm();
+        let tmp_dir = TempDir::new().unwrap();
+        let params = SendMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Unix,
+                address: tmp_dir
+                    .as_path()
+                    .join("migration.sock")
+                    .to_str()
+                    .unwrap()
+                    .to_string(),
+            },
+            max_iterations: 1,
+            dirty_threshold_mib: 0,
+        };
+
+        // The default microVM does not track dirty pages.
+        assert!(matches!(
+            send_migration(&mut vmm, &VmInfo::default(), &params),
+            Err(SendMigrationError::DirtyPageTrackingDisabled)
+        ));
+    }
+
+    #[test]
+    fn test_parse_tcp_address() {
+        assert_eq!(
+            parse_tcp_address("127.0.0.1:4242").unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 4242))
+        );
+        assert_eq!(
+            parse_tcp_address("[::1]:4242").unwrap(),
+            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], 4242))
+        );
+        // Host names are not resolved.
+        parse_tcp_address("localhost:4242").unwrap_err();
+        parse_tcp_address("/tmp/migration.sock").unwrap_err();
+    }
+
+    #[test]
+    fn test_receive_unspecified_address() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mut vm_resources = VmResources::default();
+        for address in ["0.0.0.0:4242", "[::]:4242"] {
+            let params = ReceiveMigrationParams {
+                socket: MigrationSocketConfig {
+                    socket_type: MigrationSocketType::Tcp,
+                    address: address.to_string(),
+                },
+                track_dirty_pages: false,
+                resume_vm: false,
+            };
+            let res = receive_migration(
+                &InstanceInfo::default(),
+                &mut event_manager,
+                &get_empty_filters(),
+                &params,
+                &mut vm_resources,
+            );
+            assert!(
+                matches!(res, Err(ReceiveMigrationError::UnspecifiedAddress(_))),
+                "{:?}",
+                res.map(|_| ())
+            );
+        }
+    }
+
+    #[test]
+    fn test_receive_error_is_fatal() {
+        assert!(!ReceiveMigrationError::NotAllowed.is_fatal());
+        assert!(!ReceiveMigrationError::UnexpectedFrame(0).is_fatal());
+        assert!(!ReceiveMigrationError::Listen(io::Error::other("")).is_fatal());
+        assert!(ReceiveMigrationError::Acknowledge(io::Error::other("")).is_fatal());
+        assert!(ReceiveMigrationError::ResumeMicrovm(VmmError::VcpuMessage).is_fatal());
+        assert!(
+            ReceiveMigrationError::Restore(RestoreFromSnapshotError::Invalid(
+                crate::persist::SnapShotStateSanityCheckError::NoMemory
+            ))
+            .is_fatal()
+        );
+    }
+}
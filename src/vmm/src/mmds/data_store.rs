@@ -1,12 +1,14 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::borrow::Cow;
 use std::fmt;
 use std::fmt::{Display, Formatter};
 
 use serde::{Deserialize, Serialize};
-use serde_json::{Value, to_vec};
+use serde_json::{Map, Value, to_vec};
 
+use crate::mmds::policy::{MmdsPolicy, unescape_token};
 use crate::mmds::token::{MmdsTokenError as TokenError, TokenAuthority};
 
 /// The Mmds is the Microvm Metadata Service represented as an untyped json.
@@ -18,6 +20,8 @@ pub struct Mmds {
     is_initialized: bool,
     data_store_limit: usize,
     imds_compat: bool,
+    policy: MmdsPolicy,
+    data_version: u64,
 }
 
 /// MMDS version.
@@ -53,12 +57,16 @@ pub enum OutputFormat {
 pub enum MmdsDatastoreError {
     /// The MMDS patch request doesn't fit.
     DataStoreLimitExceeded,
+    /// The MMDS resource is not writable by the guest.
+    NotGuestWritable,
     /// The MMDS resource does not exist.
     NotFound,
     /// The MMDS data store is not initialized.
     NotInitialized,
     /// Token Authority error: {0}
     TokenAuthority(#[from] TokenError),
+    /// The MMDS resource requires a valid session token.
+    TokenRequired,
     /// Cannot retrieve value. The value has an unsupported type.
     UnsupportedValueType,
 }
@@ -80,6 +88,8 @@ impl Mmds {
             is_initialized: false,
             data_store_limit,
             imds_compat: false,
+            policy: MmdsPolicy::default(),
+            data_version: 0,
         })
     }
 
@@ -114,6 +124,16 @@ impl Mmds {
         self.imds_compat
     }
 
+    /// Set the per-path access policy applied to guest requests.
+    pub fn set_policy(&mut self, policy: MmdsPolicy) {
+        self.policy = policy;
+    }
+
+    /// Get the per-path access policy applied to guest requests.
+    pub fn policy(&self) -> &MmdsPolicy {
+        &self.policy
+    }
+
     /// Sets the Additional Authenticated Data to be used for encryption and
     /// decryption of the session token.
     pub fn set_aad(&mut self, instance_id: &str) {
@@ -130,6 +150,12 @@ impl Mmds {
         self.token_authority.generate_token_secret(ttl_seconds)
     }
 
+    /// Get the version of the data store contents, which is increased by every successful update
+    /// of the data store.
+    pub fn data_version(&self) -> u64 {
+        self.data_version
+    }
+
     /// set MMDS data store limit to `data_store_limit`
     pub fn set_data_store_limit(&mut self, data_store_limit: usize) {
         self.data_store_limit = data_store_limit;
@@ -144,6 +170,7 @@ impl Mmds {
         } else {
             self.data_store = data;
             self.is_initialized = true;
+            self.data_version += 1;
 
             Ok(())
         }
@@ -161,6 +188,66 @@ impl Mmds {
             return Err(MmdsDatastoreError::DataStoreLimitExceeded);
         }
         self.data_store = data_store_clone;
+        self.data_version += 1;
+        Ok(())
+    }
+
+    /// Returns whether the guest can write the subtree located at `path`.
+    pub fn is_guest_writable(&self, path: &str) -> bool {
+        self.policy
+            .is_writable(path.strip_suffix('/').unwrap_or(path))
+    }
+
+    /// Replaces the subtree located at `path` with `value`, on behalf of the guest. The missing
+    /// objects along the path are created.
+    pub fn put_guest_value(&mut self, path: &str, value: Value) -> Result<(), MmdsDatastoreError> {
+        self.update_guest_value(path, |target| *target = value)
+    }
+
+    /// Patch updates the subtree located at `path` with `patch_data`, on behalf of the guest.
+    pub fn patch_guest_value(
+        &mut self,
+        path: &str,
+        patch_data: Value,
+    ) -> Result<(), MmdsDatastoreError> {
+        self.update_guest_value(path, |target| super::json_patch(target, &patch_data))
+    }
+
+    fn update_guest_value<F: FnOnce(&mut Value)>(
+        &mut self,
+        path: &str,
+        update: F,
+    ) -> Result<(), MmdsDatastoreError> {
+        self.check_data_store_initialized()?;
+        let path = path.strip_suffix('/').unwrap_or(path);
+        if !self.policy.is_writable(path) {
+            return Err(MmdsDatastoreError::NotGuestWritable);
+        }
+
+        let mut data_store_clone = self.data_store.clone();
+        // Walk down the path, creating the missing objects. Values which are not objects are
+        // never replaced, as they may be outside of the guest writable subtree.
+        let target = path
+            .split('/')
+            .skip(1)
+            .try_fold(&mut data_store_clone, |value, token| {
+                if value.is_null() {
+                    *value = Value::Object(Map::new());
+                }
+                value
+                    .as_object_mut()
+                    .map(|map| map.entry(unescape_token(token)).or_insert(Value::Null))
+            })
+            .ok_or(MmdsDatastoreError::NotGuestWritable)?;
+        update(target);
+
+        // It is safe to unwrap because our data store keys are all strings and
+        // we are using default serializer which does not return error.
+        if to_vec(&data_store_clone).unwrap().len() > self.data_store_limit {
+            return Err(MmdsDatastoreError::DataStoreLimitExceeded);
+        }
+        self.data_store = data_store_clone;
+        self.data_version += 1;
         Ok(())
     }
 
@@ -240,28 +327,43 @@ impl Mmds {
         }
     }
 
-    /// Returns the subtree located at path. When the path corresponds to a leaf, it returns the
-    /// value. Returns Error::NotFound when the path is invalid.
+    /// Returns the subtree located at path, as seen by the guest. When the path corresponds to a
+    /// leaf, it returns the value. Returns Error::NotFound when the path is invalid or hidden by
+    /// the policy, and Error::TokenRequired when the policy requires a valid session token.
     pub fn get_value(
         &self,
         path: String,
         format: OutputFormat,
+        has_valid_token: bool,
     ) -> Result<String, MmdsDatastoreError> {
         // The pointer function splits the input by "/". With a trailing "/", pointer does not
         // know how to get the object.
-        let value = if path.ends_with('/') {
-            self.data_store.pointer(&path.as_str()[..(path.len() - 1)])
-        } else {
-            self.data_store.pointer(path.as_str())
-        };
+        let path = path.strip_suffix('/').unwrap_or(&path);
+        let value = self.data_store.pointer(path);
 
         if let Some(json) = value {
+            if !self.policy.is_readable(path, has_valid_token) {
+                // Only reveal that a token would grant access if the path is not hidden.
+                return match !has_valid_token && self.policy.is_readable(path, true) {
+                    true => Err(MmdsDatastoreError::TokenRequired),
+                    false => Err(MmdsDatastoreError::NotFound),
+                };
+            }
+            // Drop the members of the subtree which the guest cannot read.
+            let json = if self.policy.is_empty() {
+                Cow::Borrowed(json)
+            } else {
+                let mut json = json.clone();
+                self.policy.filter(path, &mut json, has_valid_token);
+                Cow::Owned(json)
+            };
+
             match self.imds_compat {
                 // EC2 IMDS ignores the Accept header.
-                true => Mmds::format_imds(json),
+                true => Mmds::format_imds(&json),
                 false => match format {
                     OutputFormat::Json => Ok(json.to_string()),
-                    OutputFormat::Imds => Mmds::format_imds(json),
+                    OutputFormat::Imds => Mmds::format_imds(&json),
                 },
             }
         } else {
@@ -357,7 +459,7 @@ mod tests {
             for format in [OutputFormat::Imds, OutputFormat::Json] {
                 // Test invalid path.
                 assert_eq!(
-                    mmds.get_value("/invalid_path".to_string(), format)
+                    mmds.get_value("/invalid_path".to_string(), format, false)
                         .unwrap_err()
                         .to_string(),
                     MmdsDatastoreError::NotFound.to_string()
@@ -369,21 +471,24 @@ mod tests {
                     (false, OutputFormat::Json) => r#"{"first":"John","second":"Doe"}"#,
                 };
                 assert_eq!(
-                    mmds.get_value("/name".to_string(), format).unwrap(),
+                    mmds.get_value("/name".to_string(), format, false).unwrap(),
                     expected
                 );
 
                 // Retrieve an integer.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/age".to_string(), format)
+                        mmds.get_value("/age".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string()
                     ),
                     (false, OutputFormat::Json) => {
-                        assert_eq!(mmds.get_value("/age".to_string(), format).unwrap(), "43")
+                        assert_eq!(
+                            mmds.get_value("/age".to_string(), format, false).unwrap(),
+                            "43"
+                        )
                     }
                 };
 
@@ -391,14 +496,15 @@ mod tests {
                 // Retrieve an array.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/phones/".to_string(), format)
+                        mmds.get_value("/phones/".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string()
                     ),
                     (false, OutputFormat::Json) => assert_eq!(
-                        mmds.get_value("/phones/".to_string(), format).unwrap(),
+                        mmds.get_value("/phones/".to_string(), format, false)
+                            .unwrap(),
                         r#"["+401234567","+441234567"]"#
                     ),
                 }
@@ -406,14 +512,15 @@ mod tests {
                 // Test path does NOT end with /; Value is a dictionary.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/phones".to_string(), format)
+                        mmds.get_value("/phones".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string()
                     ),
                     (false, OutputFormat::Json) => assert_eq!(
-                        mmds.get_value("/phones".to_string(), format).unwrap(),
+                        mmds.get_value("/phones".to_string(), format, false)
+                            .unwrap(),
                         r#"["+401234567","+441234567"]"#
                     ),
                 }
@@ -424,21 +531,23 @@ mod tests {
                     (false, OutputFormat::Json) => "\"+401234567\"",
                 };
                 assert_eq!(
-                    mmds.get_value("/phones/0/".to_string(), format).unwrap(),
+                    mmds.get_value("/phones/0/".to_string(), format, false)
+                        .unwrap(),
                     expected
                 );
 
                 // Retrieve a boolean.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/member".to_string(), format)
+                        mmds.get_value("/member".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string()
                     ),
                     (false, OutputFormat::Json) => assert_eq!(
-                        mmds.get_value("/member".to_string(), format).unwrap(),
+                        mmds.get_value("/member".to_string(), format, false)
+                            .unwrap(),
                         "false"
                     ),
                 }
@@ -446,14 +555,14 @@ mod tests {
                 // Retrieve a float.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/shares_percentage".to_string(), format)
+                        mmds.get_value("/shares_percentage".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string()
                     ),
                     (false, OutputFormat::Json) => assert_eq!(
-                        mmds.get_value("/shares_percentage".to_string(), format)
+                        mmds.get_value("/shares_percentage".to_string(), format, false)
                             .unwrap(),
                         "12.12"
                     ),
@@ -462,14 +571,15 @@ mod tests {
                 // Retrieve a negative integer.
                 match (imds_compat, format) {
                     (false, OutputFormat::Imds) | (true, _) => assert_eq!(
-                        mmds.get_value("/balance".to_string(), format)
+                        mmds.get_value("/balance".to_string(), format, false)
                             .err()
                             .unwrap()
                             .to_string(),
                         MmdsDatastoreError::UnsupportedValueType.to_string(),
                     ),
                     (false, OutputFormat::Json) => assert_eq!(
-                        mmds.get_value("/balance".to_string(), format).unwrap(),
+                        mmds.get_value("/balance".to_string(), format, false)
+                            .unwrap(),
                         "-24"
                     ),
                 }
@@ -480,16 +590,159 @@ mod tests {
                     (false, OutputFormat::Json) => r#""{\n  \"hello\": \"world\"\n}""#,
                 };
                 assert_eq!(
-                    mmds.get_value("/json_string".to_string(), format).unwrap(),
+                    mmds.get_value("/json_string".to_string(), format, false)
+                        .unwrap(),
                     expected
                 );
             }
         }
     }
 
+    #[test]
+    fn test_get_value_policy() {
+        let mut mmds = Mmds::default();
+        let data = r#"{
+            "name": "vm",
+            "secrets": {
+                "key": "value"
+            },
+            "internal": "value"
+        }"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+        mmds.set_policy(
+            serde_json::from_str(r#"{"/secrets": "token_required", "/internal": "hidden"}"#)
+                .unwrap(),
+        );
+
+        // Restricted members are not listed.
+        assert_eq!(
+            mmds.get_value("/".to_string(), OutputFormat::Imds, false)
+                .unwrap(),
+            "name"
+        );
+        assert_eq!(
+            mmds.get_value("/".to_string(), OutputFormat::Json, true)
+                .unwrap(),
+            r#"{"name":"vm","secrets":{"key":"value"}}"#
+        );
+
+        // Token required subtrees can only be read with a valid token.
+        assert_eq!(
+            mmds.get_value("/secrets/key".to_string(), OutputFormat::Imds, false)
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::TokenRequired.to_string()
+        );
+        assert_eq!(
+            mmds.get_value("/secrets/key".to_string(), OutputFormat::Imds, true)
+                .unwrap(),
+            "value"
+        );
+
+        // Hidden subtrees cannot be read at all.
+        for has_valid_token in [false, true] {
+            assert_eq!(
+                mmds.get_value("/internal".to_string(), OutputFormat::Imds, has_valid_token)
+                    .unwrap_err()
+                    .to_string(),
+                MmdsDatastoreError::NotFound.to_string()
+            );
+        }
+
+        // Restricted array elements are not listed in their parent either.
+        mmds.put_data(serde_json::from_str(r#"{"keys": ["public", "private"]}"#).unwrap())
+            .unwrap();
+        mmds.set_policy(serde_json::from_str(r#"{"/keys/1": "token_required"}"#).unwrap());
+        for path in ["/", "/keys"] {
+            let value = mmds
+                .get_value(path.to_string(), OutputFormat::Json, false)
+                .unwrap();
+            assert!(!value.contains("private"), "{value}");
+        }
+        assert_eq!(
+            mmds.get_value("/keys".to_string(), OutputFormat::Json, true)
+                .unwrap(),
+            r#"["public","private"]"#
+        );
+    }
+
+    #[test]
+    fn test_guest_writes() {
+        let mut mmds = Mmds::default();
+
+        // The data store must be initialized.
+        assert_eq!(
+            mmds.put_guest_value("/status", Value::Null)
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::NotInitialized.to_string()
+        );
+
+        let data = r#"{
+            "name": "vm",
+            "status": "booting"
+        }"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+        mmds.set_policy(
+            serde_json::from_str(
+                r#"{"/status": "guest_writable", "/name/first": "guest_writable"}"#,
+            )
+            .unwrap(),
+        );
+        assert!(mmds.is_guest_writable("/status/"));
+        assert!(!mmds.is_guest_writable("/name"));
+
+        // Only guest writable subtrees can be written.
+        assert_eq!(
+            mmds.put_guest_value("/name", Value::Null)
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::NotGuestWritable.to_string()
+        );
+        // Values which are not objects are not replaced along the path.
+        assert_eq!(
+            mmds.put_guest_value("/name/first", Value::Null)
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::NotGuestWritable.to_string()
+        );
+
+        // Replace and create values.
+        mmds.put_guest_value("/status", serde_json::json!({"ready": false}))
+            .unwrap();
+        mmds.put_guest_value("/status/services/sshd", serde_json::json!("up"))
+            .unwrap();
+        mmds.patch_guest_value("/status", serde_json::json!({"ready": true}))
+            .unwrap();
+        assert_eq!(
+            mmds.data_store_value(),
+            serde_json::json!({
+                "name": "vm",
+                "status": {
+                    "ready": true,
+                    "services": {
+                        "sshd": "up"
+                    }
+                }
+            })
+        );
+
+        // Guest writes are bound by the data store limit.
+        mmds.set_data_store_limit(80);
+        let filling = (0..80).map(|_| "X").collect::<String>();
+        assert_eq!(
+            mmds.patch_guest_value("/status", serde_json::json!({ "log": filling }))
+                .unwrap_err()
+                .to_string(),
+            MmdsDatastoreError::DataStoreLimitExceeded.to_string()
+        );
+        assert!(!mmds.get_data_str().contains("log"));
+    }
+
     #[test]
     fn test_update_data_store() {
         let mut mmds = Mmds::default();
+        assert_eq!(mmds.data_version(), 0);
 
         let data = r#"{
             "name": {
@@ -544,6 +797,8 @@ mod tests {
             MmdsDatastoreError::DataStoreLimitExceeded.to_string()
         );
         assert!(!mmds.get_data_str().contains("smth"));
+        // Failed updates leave the data store version unchanged.
+        assert_eq!(mmds.data_version(), 5);
 
         let data = "{\"new_key\" : \"smth\"}";
         let data_store: Value = serde_json::from_str(data).unwrap();
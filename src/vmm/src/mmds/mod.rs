@@ -7,11 +7,14 @@ pub mod data_store;
 pub mod ns;
 /// Defines the structures needed for saving/restoring MmdsNetworkStack.
 pub mod persist;
+/// MMDS per-path access policies
+pub mod policy;
 mod token;
 /// MMDS token headers
 pub mod token_headers;
 
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use micro_http::{
     Body, HttpHeaderError, MediaType, Method, Request, RequestError, Response, StatusCode, Version,
@@ -27,6 +30,14 @@ use crate::mmds::token_headers::{
     get_header_value_pair,
 };
 
+// Response header carrying the version of the data store contents.
+const ETAG_HEADER: &str = "ETag";
+// How long a request waiting for the data store contents to change is kept at most, unless the
+// guest asks for a different timeout.
+const DEFAULT_WAIT_TIMEOUT_SECONDS: u64 = 60;
+// The longest timeout the guest can ask for when waiting for the data store contents to change.
+const MAX_WAIT_TIMEOUT_SECONDS: u64 = 300;
+
 #[rustfmt::skip]
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 /// MMDS token errors
@@ -35,14 +46,27 @@ pub enum VmmMmdsError {
     InvalidToken,
     /// Invalid URI.
     InvalidURI,
-    /// Not allowed HTTP method.
-    MethodNotAllowed,
+    /// Invalid JSON body: {0}
+    InvalidBody(String),
+    /// Invalid query parameter: {0}
+    InvalidQueryParameter(String),
     /// No MMDS token provided. Use `X-metadata-token` or `X-aws-ec2-metadata-token` header to specify the session token.
     NoTokenProvided,
     /// Token time to live value not found. Use `X-metadata-token-ttl-seconds` or `X-aws-ec2-metadata-token-ttl-seconds` header to specify the token's lifetime.
     NoTtlProvided,
     /// Resource not found: {0}.
     ResourceNotFound(String),
+    /// The `wait` query parameter requires a `version` query parameter.
+    WaitWithoutVersion,
+}
+
+// Parameters of a `GET` request waiting for the data store contents to change.
+#[derive(Debug, PartialEq, Eq)]
+struct WaitQuery {
+    // The request waits as long as the data store version is equal to this one.
+    version: u64,
+    // How long the request waits at most.
+    timeout: Duration,
 }
 
 impl From<MediaType> for OutputFormat {
@@ -108,8 +132,57 @@ fn sanitize_uri(mut uri: String) -> String {
     uri
 }
 
+// Splits the URI of a request into the path and the query string, if any.
+fn split_query(uri: &str) -> (&str, Option<&str>) {
+    match uri.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (uri, None),
+    }
+}
+
+// Parses the query string of a `GET` request. Returns `None` if the request does not wait for the
+// data store contents to change.
+fn parse_wait_query(query: &str) -> Result<Option<WaitQuery>, VmmMmdsError> {
+    let mut wait = false;
+    let mut version = None;
+    let mut timeout = DEFAULT_WAIT_TIMEOUT_SECONDS;
+
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let invalid = || VmmMmdsError::InvalidQueryParameter(param.to_string());
+        let (name, value) = param.split_once('=').ok_or_else(invalid)?;
+        match name {
+            "wait" => wait = value.parse().map_err(|_| invalid())?,
+            "version" => version = Some(value.parse().map_err(|_| invalid())?),
+            "timeout" => {
+                timeout = value
+                    .parse()
+                    .ok()
+                    .filter(|timeout| (1..=MAX_WAIT_TIMEOUT_SECONDS).contains(timeout))
+                    .ok_or_else(invalid)?
+            }
+            _ => return Err(invalid()),
+        }
+    }
+
+    match (wait, version) {
+        (false, _) => Ok(None),
+        (true, Some(version)) => Ok(Some(WaitQuery {
+            version,
+            timeout: Duration::from_secs(timeout),
+        })),
+        (true, None) => Err(VmmMmdsError::WaitWithoutVersion),
+    }
+}
+
 /// Build a response for `request` and return response based on MMDS version
 pub fn convert_to_response(mmds: Arc<Mutex<Mmds>>, request: Request) -> Response {
+    respond_to_request(mmds, request, true)
+}
+
+// Builds a response for `request`. The session token of GET requests is only checked, and
+// counted in the metrics, on their first pass, as waiting requests are converted again on every
+// poll.
+fn respond_to_request(mmds: Arc<Mutex<Mmds>>, request: Request, first_pass: bool) -> Response {
     // Check URI is not empty
     let uri = request.uri().get_abs_path();
     if uri.is_empty() {
@@ -123,46 +196,78 @@ pub fn convert_to_response(mmds: Arc<Mutex<Mmds>>, request: Request) -> Response
 
     let mut mmds_guard = mmds.lock().expect("Poisoned lock");
 
-    // Allow only GET and PUT requests
+    // GET requests read the data store, while PUT and PATCH requests write to the guest writable
+    // paths, except for PUT requests generating a session token.
     match request.method() {
         Method::Get => match mmds_guard.version() {
-            MmdsVersion::V1 => respond_to_get_request_v1(&mmds_guard, request),
-            MmdsVersion::V2 => respond_to_get_request_v2(&mmds_guard, request),
+            MmdsVersion::V1 => respond_to_get_request_v1(&mmds_guard, request, first_pass),
+            MmdsVersion::V2 => respond_to_get_request_v2(&mmds_guard, request, first_pass),
         },
-        Method::Put => respond_to_put_request(&mut mmds_guard, request),
-        _ => {
-            let mut response = build_response(
-                request.http_version(),
-                StatusCode::MethodNotAllowed,
-                MediaType::PlainText,
-                Body::new(VmmMmdsError::MethodNotAllowed.to_string()),
-            );
-            response.allow_method(Method::Get);
-            response.allow_method(Method::Put);
-            response
+        Method::Put if sanitize_uri(split_query(uri).0.to_string()) == PATH_TO_TOKEN => {
+            respond_to_put_request(&mut mmds_guard, request)
         }
+        Method::Put | Method::Patch => respond_to_write_request(&mut mmds_guard, request),
     }
 }
 
-fn respond_to_get_request_v1(mmds: &Mmds, request: Request) -> Response {
+/// Build a response for `request` like [`convert_to_response`], unless `request` waits for the
+/// data store contents to change, which have not changed yet, and its timeout is longer than
+/// `waited`, the time it has already spent waiting. In that case, no response is returned, and
+/// the request has to be converted again later.
+///
+/// `waited` must only be zero the first time `request` is converted, since the session token is
+/// not checked again afterwards.
+pub fn convert_to_response_or_wait(
+    mmds: Arc<Mutex<Mmds>>,
+    request: Request,
+    waited: Duration,
+) -> Option<Response> {
+    let wait_query = match request.method() {
+        Method::Get => split_query(request.uri().get_abs_path())
+            .1
+            .and_then(|query| parse_wait_query(query).ok().flatten()),
+        _ => None,
+    };
+    let data_version = mmds.lock().expect("Poisoned lock").data_version();
+
+    // The response is built anyway, so that requests which cannot succeed do not wait.
+    let response = respond_to_request(mmds, request, waited.is_zero());
+    match wait_query {
+        Some(wait_query)
+            if response.status() == StatusCode::OK
+                && wait_query.version == data_version
+                && waited < wait_query.timeout =>
+        {
+            None
+        }
+        _ => Some(response),
+    }
+}
+
+// Checks the session token of a MMDS V1 request. The token is optional, so this only updates
+// the metrics and returns whether the request carries a valid token.
+fn check_token_v1(mmds: &Mmds, request: &Request) -> bool {
     match get_header_value_pair(
         request.headers.custom_entries(),
         &[X_METADATA_TOKEN_HEADER, X_AWS_EC2_METADATA_TOKEN_HEADER],
     ) {
         Some((_, token)) => {
-            if !mmds.is_valid_token(token) {
+            let is_valid = mmds.is_valid_token(token);
+            if !is_valid {
                 METRICS.mmds.rx_invalid_token.inc();
             }
+            is_valid
         }
         None => {
             METRICS.mmds.rx_no_token.inc();
+            false
         }
     }
-
-    respond_to_get_request(mmds, request)
 }
 
-fn respond_to_get_request_v2(mmds: &Mmds, request: Request) -> Response {
+// Checks the session token of a MMDS V2 request, returning the response to send back when the
+// token is missing or not valid.
+fn check_token_v2(mmds: &Mmds, request: &Request) -> Result<(), Response> {
     // Check whether a token exists.
     let token = match get_header_value_pair(
         request.headers.custom_entries(),
@@ -172,32 +277,64 @@ fn respond_to_get_request_v2(mmds: &Mmds, request: Request) -> Response {
         None => {
             METRICS.mmds.rx_no_token.inc();
             let error_msg = VmmMmdsError::NoTokenProvided.to_string();
-            return build_response(
+            return Err(build_response(
                 request.http_version(),
                 StatusCode::Unauthorized,
                 MediaType::PlainText,
                 Body::new(error_msg),
-            );
+            ));
         }
     };
 
     // Validate the token.
     match mmds.is_valid_token(token) {
-        true => respond_to_get_request(mmds, request),
+        true => Ok(()),
         false => {
             METRICS.mmds.rx_invalid_token.inc();
-            build_response(
+            Err(build_response(
                 request.http_version(),
                 StatusCode::Unauthorized,
                 MediaType::PlainText,
                 Body::new(VmmMmdsError::InvalidToken.to_string()),
-            )
+            ))
         }
     }
 }
 
-fn respond_to_get_request(mmds: &Mmds, request: Request) -> Response {
-    let uri = request.uri().get_abs_path();
+fn respond_to_get_request_v1(mmds: &Mmds, request: Request, first_pass: bool) -> Response {
+    let has_valid_token = match first_pass {
+        true => check_token_v1(mmds, &request),
+        // The token still decides which paths are readable, but it is not counted again.
+        false => get_header_value_pair(
+            request.headers.custom_entries(),
+            &[X_METADATA_TOKEN_HEADER, X_AWS_EC2_METADATA_TOKEN_HEADER],
+        )
+        .is_some_and(|(_, token)| mmds.is_valid_token(token)),
+    };
+    respond_to_get_request(mmds, request, has_valid_token)
+}
+
+fn respond_to_get_request_v2(mmds: &Mmds, request: Request, first_pass: bool) -> Response {
+    let token_check = match first_pass {
+        true => check_token_v2(mmds, &request),
+        false => Ok(()),
+    };
+    match token_check {
+        Ok(()) => respond_to_get_request(mmds, request, true),
+        Err(response) => response,
+    }
+}
+
+fn respond_to_get_request(mmds: &Mmds, request: Request, has_valid_token: bool) -> Response {
+    let (uri, query) = split_query(request.uri().get_abs_path());
+    if let Err(err) = query.map_or(Ok(None), parse_wait_query) {
+        return build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            MediaType::PlainText,
+            Body::new(err.to_string()),
+        );
+    }
 
     // The data store expects a strict json path, so we need to
     // sanitize the URI.
@@ -205,13 +342,20 @@ fn respond_to_get_request(mmds: &Mmds, request: Request) -> Response {
 
     let content_type = request.headers.accept();
 
-    match mmds.get_value(json_path, content_type.into()) {
-        Ok(response_body) => build_response(
-            request.http_version(),
-            StatusCode::OK,
-            content_type,
-            Body::new(response_body),
-        ),
+    match mmds.get_value(json_path, content_type.into(), has_valid_token) {
+        Ok(response_body) => {
+            let mut response = build_response(
+                request.http_version(),
+                StatusCode::OK,
+                content_type,
+                Body::new(response_body),
+            );
+            let custom_headers =
+                [(ETAG_HEADER.into(), format!("\"{}\"", mmds.data_version()))].into();
+            // Safe to unwrap because the header name and the value are valid as US-ASCII.
+            response.set_custom_headers(&custom_headers).unwrap();
+            response
+        }
         Err(err) => match err {
             MmdsError::NotFound => {
                 let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
@@ -234,33 +378,31 @@ fn respond_to_get_request(mmds: &Mmds, request: Request) -> Response {
                 MediaType::PlainText,
                 Body::new(err.to_string()),
             ),
+            MmdsError::TokenRequired => build_response(
+                request.http_version(),
+                StatusCode::Unauthorized,
+                MediaType::PlainText,
+                Body::new(err.to_string()),
+            ),
             _ => unreachable!(),
         },
     }
 }
 
-fn respond_to_put_request(mmds: &mut Mmds, request: Request) -> Response {
-    let custom_headers = request.headers.custom_entries();
-
-    // Reject `PUT` requests that contain `X-Forwarded-For` header.
-    if let Some((header, _)) = get_header_value_pair(custom_headers, &[X_FORWARDED_FOR_HEADER]) {
-        let error_msg =
-            RequestError::HeaderError(HttpHeaderError::UnsupportedName(header.to_string()))
-                .to_string();
-        return build_response(
-            request.http_version(),
-            StatusCode::BadRequest,
-            MediaType::PlainText,
-            Body::new(error_msg),
-        );
+// Handles the `PUT` and `PATCH` requests writing to the guest writable paths of the data store.
+fn respond_to_write_request(mmds: &mut Mmds, request: Request) -> Response {
+    if let Err(response) = check_forwarded_header(&request) {
+        return response;
     }
 
-    let uri = request.uri().get_abs_path();
+    // The query string is not part of the path written to.
+    let (uri, _) = split_query(request.uri().get_abs_path());
     // Sanitize the URI into a strict json path.
     let json_path = sanitize_uri(uri.to_string());
 
-    // Only accept PUT requests towards TOKEN_PATH.
-    if json_path != PATH_TO_TOKEN {
+    // Paths which are not guest writable are reported as missing, so that the guest cannot
+    // learn about hidden paths.
+    if !mmds.is_guest_writable(&json_path) {
         let error_msg = VmmMmdsError::ResourceNotFound(String::from(uri)).to_string();
         return build_response(
             request.http_version(),
@@ -270,6 +412,78 @@ fn respond_to_put_request(mmds: &mut Mmds, request: Request) -> Response {
         );
     }
 
+    // Writes are subject to the same token checks as reads.
+    match mmds.version() {
+        MmdsVersion::V1 => {
+            check_token_v1(mmds, &request);
+        }
+        MmdsVersion::V2 => {
+            if let Err(response) = check_token_v2(mmds, &request) {
+                return response;
+            }
+        }
+    }
+
+    let body = request.body.as_ref().map_or(&[][..], |body| body.raw());
+    let value = match serde_json::from_slice(body) {
+        Ok(value) => value,
+        Err(err) => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                MediaType::PlainText,
+                Body::new(VmmMmdsError::InvalidBody(err.to_string()).to_string()),
+            );
+        }
+    };
+
+    let result = match request.method() {
+        Method::Patch => mmds.patch_guest_value(&json_path, value),
+        _ => mmds.put_guest_value(&json_path, value),
+    };
+    match result {
+        Ok(()) => Response::new(request.http_version(), StatusCode::NoContent),
+        Err(err) => {
+            let status_code = match err {
+                MmdsError::DataStoreLimitExceeded => StatusCode::PayloadTooLarge,
+                _ => StatusCode::BadRequest,
+            };
+            build_response(
+                request.http_version(),
+                status_code,
+                MediaType::PlainText,
+                Body::new(err.to_string()),
+            )
+        }
+    }
+}
+
+// Rejects the `PUT` and `PATCH` requests that contain `X-Forwarded-For` header, returning the
+// response to send back.
+fn check_forwarded_header(request: &Request) -> Result<(), Response> {
+    match get_header_value_pair(request.headers.custom_entries(), &[X_FORWARDED_FOR_HEADER]) {
+        Some((header, _)) => {
+            let error_msg =
+                RequestError::HeaderError(HttpHeaderError::UnsupportedName(header.to_string()))
+                    .to_string();
+            Err(build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                MediaType::PlainText,
+                Body::new(error_msg),
+            ))
+        }
+        None => Ok(()),
+    }
+}
+
+fn respond_to_put_request(mmds: &mut Mmds, request: Request) -> Response {
+    if let Err(response) = check_forwarded_header(&request) {
+        return response;
+    }
+
+    let custom_headers = request.headers.custom_entries();
+
     // Get token lifetime value.
     let (header, ttl_seconds) = match get_header_value_pair(
         custom_headers,
@@ -404,6 +618,9 @@ mod tests {
             MediaType::PlainText => get_plain_text_data().to_string(),
         };
         response.set_body(Body::new(body));
+        response
+            .set_custom_headers(&[(ETAG_HEADER.into(), "\"1\"".into())].into())
+            .unwrap();
 
         (request, response)
     }
@@ -502,15 +719,14 @@ mod tests {
             let actual_response = convert_to_response(mmds.clone(), request);
             assert_eq!(actual_response, expected_response);
 
-            // Test MethodNotAllowed (PATCH method).
+            // Test writing to a path which is not guest writable (PATCH method).
             let request =
                 Request::try_from(b"PATCH http://169.254.169.255/ HTTP/1.0\r\n\r\n", None).unwrap();
-            let mut expected_response =
-                Response::new(Version::Http10, StatusCode::MethodNotAllowed);
+            let mut expected_response = Response::new(Version::Http10, StatusCode::NotFound);
             expected_response.set_content_type(MediaType::PlainText);
-            expected_response.set_body(Body::new(VmmMmdsError::MethodNotAllowed.to_string()));
-            expected_response.allow_method(Method::Get);
-            expected_response.allow_method(Method::Put);
+            expected_response.set_body(Body::new(
+                VmmMmdsError::ResourceNotFound(String::from("/")).to_string(),
+            ));
             let actual_response = convert_to_response(mmds.clone(), request);
             assert_eq!(actual_response, expected_response);
         }
@@ -863,6 +1079,295 @@ mod tests {
         }
     }
 
+    // Test the requests subject to the per-path access policy.
+    #[test]
+    fn test_respond_to_request_policy() {
+        for version in [MmdsVersion::V1, MmdsVersion::V2] {
+            let mmds = populate_mmds();
+            {
+                let mut mmds_guard = mmds.lock().expect("Poisoned lock");
+                mmds_guard.set_version(version);
+                mmds_guard.set_policy(
+                    serde_json::from_str(
+                        r#"{"/phones": "token_required", "/status": "guest_writable"}"#,
+                    )
+                    .unwrap(),
+                );
+            }
+
+            // Generate a token
+            let request = Request::try_from(
+                b"PUT http://169.254.169.254/latest/api/token HTTP/1.0\r\n\
+                  X-metadata-token-ttl-seconds: 60\r\n\r\n",
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response.status(), StatusCode::OK);
+            let valid_token = String::from_utf8(actual_response.body().unwrap().body).unwrap();
+
+            // Write to a guest writable path.
+            #[rustfmt::skip]
+            let request = Request::try_from(
+                format!(
+                    "PUT http://169.254.169.254/status HTTP/1.0\r\n\
+                     X-metadata-token: {valid_token}\r\n\
+                     Content-Length: 15\r\n\r\n\
+                     {{\"ready\":false}}",
+                )
+                .as_bytes(),
+                None,
+            )
+            .unwrap();
+            let expected_response = Response::new(Version::Http10, StatusCode::NoContent);
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response, expected_response);
+
+            #[rustfmt::skip]
+            let request = Request::try_from(
+                format!(
+                    "PATCH http://169.254.169.254/status/ HTTP/1.0\r\n\
+                     X-metadata-token: {valid_token}\r\n\
+                     Content-Length: 14\r\n\r\n\
+                     {{\"ready\":true}}",
+                )
+                .as_bytes(),
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response, expected_response);
+            assert_eq!(
+                mmds.lock().expect("Poisoned lock").data_store_value()["status"],
+                serde_json::json!({"ready": true})
+            );
+
+            // The query string is not part of the path written to.
+            #[rustfmt::skip]
+            let request = Request::try_from(
+                format!(
+                    "PUT http://169.254.169.254/status?version=1 HTTP/1.0\r\n\
+                     X-metadata-token: {valid_token}\r\n\
+                     Content-Length: 15\r\n\r\n\
+                     {{\"ready\":false}}",
+                )
+                .as_bytes(),
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response, expected_response);
+            assert_eq!(
+                mmds.lock().expect("Poisoned lock").data_store_value()["status"],
+                serde_json::json!({"ready": false})
+            );
+
+            // Write an invalid JSON body.
+            #[rustfmt::skip]
+            let request = Request::try_from(
+                format!(
+                    "PUT http://169.254.169.254/status HTTP/1.0\r\n\
+                     X-metadata-token: {valid_token}\r\n\
+                     Content-Length: 5\r\n\r\n\
+                     ready",
+                )
+                .as_bytes(),
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response.status(), StatusCode::BadRequest);
+
+            // Write without a token.
+            let request = Request::try_from(
+                b"PATCH http://169.254.169.254/status HTTP/1.0\r\n\
+                  Content-Length: 2\r\n\r\n{}",
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            match version {
+                MmdsVersion::V1 => assert_eq!(actual_response.status(), StatusCode::NoContent),
+                MmdsVersion::V2 => assert_eq!(actual_response.status(), StatusCode::Unauthorized),
+            }
+
+            // Read a token required path without a token, which is only possible in V1.
+            if version == MmdsVersion::V1 {
+                let request =
+                    Request::try_from(b"GET http://169.254.169.254/phones HTTP/1.0\r\n\r\n", None)
+                        .unwrap();
+                let mut expected_response =
+                    Response::new(Version::Http10, StatusCode::Unauthorized);
+                expected_response.set_content_type(MediaType::PlainText);
+                expected_response.set_body(Body::new(MmdsError::TokenRequired.to_string()));
+                let actual_response = convert_to_response(mmds.clone(), request);
+                assert_eq!(actual_response, expected_response);
+
+                let request =
+                    Request::try_from(b"GET http://169.254.169.254/ HTTP/1.0\r\n\r\n", None)
+                        .unwrap();
+                let actual_response = convert_to_response(mmds.clone(), request);
+                assert_eq!(
+                    actual_response.body().unwrap().body,
+                    b"age\nname/\nstatus/".to_vec()
+                );
+            }
+
+            // Read a token required path with a valid token.
+            #[rustfmt::skip]
+            let request = Request::try_from(
+                format!(
+                    "GET http://169.254.169.254/phones/mobile HTTP/1.0\r\n\
+                     X-metadata-token: {valid_token}\r\n\r\n",
+                )
+                .as_bytes(),
+                None,
+            )
+            .unwrap();
+            let actual_response = convert_to_response(mmds.clone(), request);
+            assert_eq!(actual_response.status(), StatusCode::OK);
+            assert_eq!(actual_response.body().unwrap().body, b"+442345678".to_vec());
+        }
+    }
+
+    #[test]
+    fn test_parse_wait_query() {
+        assert_eq!(split_query("/a/b"), ("/a/b", None));
+        assert_eq!(split_query("/a/b?"), ("/a/b", Some("")));
+        assert_eq!(split_query("/a?wait=true"), ("/a", Some("wait=true")));
+
+        assert_eq!(parse_wait_query("").unwrap(), None);
+        assert_eq!(parse_wait_query("wait=false&version=3").unwrap(), None);
+        assert_eq!(
+            parse_wait_query("wait=true&version=3").unwrap(),
+            Some(WaitQuery {
+                version: 3,
+                timeout: Duration::from_secs(DEFAULT_WAIT_TIMEOUT_SECONDS),
+            })
+        );
+        assert_eq!(
+            parse_wait_query("version=3&&timeout=10&wait=true").unwrap(),
+            Some(WaitQuery {
+                version: 3,
+                timeout: Duration::from_secs(10),
+            })
+        );
+
+        assert_eq!(
+            parse_wait_query("wait=true").unwrap_err().to_string(),
+            VmmMmdsError::WaitWithoutVersion.to_string()
+        );
+        for param in [
+            "wait",
+            "wait=yes",
+            "version=-1",
+            "timeout=0",
+            "timeout=301",
+            "other=1",
+        ] {
+            assert_eq!(
+                parse_wait_query(&format!("version=1&{param}"))
+                    .unwrap_err()
+                    .to_string(),
+                VmmMmdsError::InvalidQueryParameter(param.to_string()).to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_convert_to_response_or_wait() {
+        let mmds = populate_mmds();
+        let request = |uri: &str| {
+            Request::try_from(
+                format!("GET http://169.254.169.254{uri} HTTP/1.0\r\n\r\n").as_bytes(),
+                None,
+            )
+            .unwrap()
+        };
+
+        // Requests which do not wait are answered right away.
+        let response = convert_to_response_or_wait(mmds.clone(), request("/age"), Duration::ZERO);
+        assert_eq!(
+            response,
+            Some(convert_to_response(mmds.clone(), request("/age")))
+        );
+
+        // Requests wait while the data store version is the expected one.
+        let uri = "/age?wait=true&version=1&timeout=5";
+        assert_eq!(
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::ZERO),
+            None
+        );
+        assert_eq!(
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::from_millis(4999)),
+            None
+        );
+
+        // Requests stop waiting when their timeout expires.
+        let response =
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::from_secs(5))
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().unwrap().body, b"43".to_vec());
+        assert_eq!(response, convert_to_response(mmds.clone(), request(uri)));
+
+        // Requests which cannot succeed do not wait.
+        let response = convert_to_response_or_wait(
+            mmds.clone(),
+            request("/missing?wait=true&version=1"),
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::NotFound);
+        let response =
+            convert_to_response_or_wait(mmds.clone(), request("/age?wait=true"), Duration::ZERO)
+                .unwrap();
+        assert_eq!(response.status(), StatusCode::BadRequest);
+        assert_eq!(
+            response.body().unwrap().body,
+            VmmMmdsError::WaitWithoutVersion.to_string().into_bytes()
+        );
+
+        // The session token is only counted in the metrics on the first pass.
+        let prev_rx_no_token = METRICS.mmds.rx_no_token.count();
+        assert_eq!(
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::ZERO),
+            None
+        );
+        assert_eq!(prev_rx_no_token + 1, METRICS.mmds.rx_no_token.count());
+        assert_eq!(
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::from_millis(100)),
+            None
+        );
+        assert_eq!(prev_rx_no_token + 1, METRICS.mmds.rx_no_token.count());
+
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_version(MmdsVersion::V2);
+        let response =
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::ZERO).unwrap();
+        assert_eq!(response.status(), StatusCode::Unauthorized);
+        mmds.lock()
+            .expect("Poisoned lock")
+            .set_version(MmdsVersion::V1);
+
+        // Requests stop waiting when the data store contents change.
+        mmds.lock()
+            .expect("Poisoned lock")
+            .patch_data(serde_json::json!({"age": 44}))
+            .unwrap();
+        let response =
+            convert_to_response_or_wait(mmds.clone(), request(uri), Duration::ZERO).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().unwrap().body, b"44".to_vec());
+        let response = convert_to_response_or_wait(
+            mmds.clone(),
+            request("/age?wait=true&version=2"),
+            Duration::ZERO,
+        );
+        assert_eq!(response, None);
+    }
+
     #[test]
     fn test_json_patch() {
         let mut data = serde_json::json!({
@@ -927,8 +1432,8 @@ mod tests {
         assert_eq!(VmmMmdsError::InvalidURI.to_string(), "Invalid URI.");
 
         assert_eq!(
-            VmmMmdsError::MethodNotAllowed.to_string(),
-            "Not allowed HTTP method."
+            VmmMmdsError::InvalidBody(String::from("EOF")).to_string(),
+            "Invalid JSON body: EOF"
         );
 
         assert_eq!(
@@ -943,9 +1448,19 @@ mod tests {
              `X-aws-ec2-metadata-token-ttl-seconds` header to specify the token's lifetime."
         );
 
+        assert_eq!(
+            VmmMmdsError::InvalidQueryParameter(String::from("wait=yes")).to_string(),
+            "Invalid query parameter: wait=yes"
+        );
+
         assert_eq!(
             VmmMmdsError::ResourceNotFound(String::from("invalid/")).to_string(),
             "Resource not found: invalid/."
+        );
+
+        assert_eq!(
+            VmmMmdsError::WaitWithoutVersion.to_string(),
+            "The `wait` query parameter requires a `version` query parameter."
         )
     }
 }
@@ -5,7 +5,7 @@
 #![allow(missing_docs)]
 
 use std::convert::From;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::result::Result;
 use std::str::FromStr;
@@ -18,14 +18,24 @@ use crate::dumbo::pdu::arp::{
     ArpError as ArpFrameError, ETH_IPV4_FRAME_LEN, EthIPv4ArpFrame, test_speculative_tpa,
 };
 use crate::dumbo::pdu::ethernet::{
-    ETHERTYPE_ARP, ETHERTYPE_IPV4, EthernetError as EthernetFrameError, EthernetFrame,
+    ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6, EthernetError as EthernetFrameError,
+    EthernetFrame,
+};
+use crate::dumbo::pdu::icmpv6::{
+    AdvertisementFlags, NDP_MESSAGE_LEN, NdpError, NdpMessage, test_speculative_target_addr,
 };
 use crate::dumbo::pdu::ipv4::{
     IPv4Packet, Ipv4Error as IPv4PacketError, PROTOCOL_TCP, test_speculative_dst_addr,
 };
+use crate::dumbo::pdu::ipv6::{
+    IPV6_VERSION, IPv6Packet, Ipv6Error as IPv6PacketError, PROTOCOL_ICMPV6,
+    test_speculative_dst_addr as test_speculative_ipv6_dst_addr,
+};
 use crate::dumbo::pdu::tcp::TcpError as TcpSegmentError;
 use crate::dumbo::tcp::NextSegmentStatus;
-use crate::dumbo::tcp::handler::{RecvEvent, TcpIPv4Handler, WriteEvent, WriteNextError};
+use crate::dumbo::tcp::handler::{
+    RecvError, RecvEvent, TcpIPv4Handler, WriteEvent, WriteNextError,
+};
 use crate::logger::{IncMetric, METRICS};
 use crate::mmds::data_store::Mmds;
 use crate::utils::net::mac::MacAddr;
@@ -35,6 +45,9 @@ const DEFAULT_IPV4_ADDR: [u8; 4] = [169, 254, 169, 254];
 const DEFAULT_TCP_PORT: u16 = 80;
 const DEFAULT_MAX_CONNECTIONS: usize = 30;
 const DEFAULT_MAX_PENDING_RESETS: usize = 100;
+// Destination of the Neighbor Advertisements answering solicitations sent from the unspecified
+// address, which are used for duplicate address detection.
+const IPV6_ALL_NODES_ADDR: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
 
 #[derive(Debug, PartialEq, thiserror::Error, displaydoc::Display)]
 enum WriteArpFrameError {
@@ -46,6 +59,20 @@ enum WriteArpFrameError {
     Ethernet(#[from] EthernetFrameError),
 }
 
+#[derive(Debug, PartialEq, thiserror::Error, displaydoc::Display)]
+enum WriteNdpFrameError {
+    /// NoPendingNdpReply
+    NoPendingNdpReply,
+    /// The MMDS network stack has no IPv6 address.
+    Ipv6Disabled,
+    /// NDP error: {0}
+    Ndp(#[from] NdpError),
+    /// IPv6Packet error: {0}
+    IPv6Packet(#[from] IPv6PacketError),
+    /// Ethernet error: {0}
+    Ethernet(#[from] EthernetFrameError),
+}
+
 #[derive(Debug, PartialEq, thiserror::Error, displaydoc::Display)]
 enum WritePacketError {
     /// IPv4Packet error: {0}
@@ -66,10 +93,15 @@ pub struct MmdsNetworkStack {
     pub(crate) mac_addr: MacAddr,
     // MMDS server IPv4 address.
     pub ipv4_addr: Ipv4Addr,
+    // MMDS server IPv6 address, if the MMDS is reachable over IPv6.
+    pub ipv6_addr: Option<Ipv6Addr>,
     // ARP reply destination IPv4 address (requester of address resolution reply).
     // It is the Ipv4Addr of the network interface for which the MmdsNetworkStack
     // routes the packets.
     pending_arp_reply_dest: Option<Ipv4Addr>,
+    // Neighbor Advertisement destination IPv6 address and flags, the IPv6 counterpart of
+    // `pending_arp_reply_dest`.
+    pending_ndp_reply: Option<(Ipv6Addr, AdvertisementFlags)>,
     // This handles MMDS<->guest interaction at the TCP level.
     pub(crate) tcp_handler: TcpIPv4Handler,
     // Data store reference shared across all MmdsNetworkStack instances.
@@ -87,7 +119,9 @@ impl MmdsNetworkStack {
             remote_mac_addr: mac_addr,
             mac_addr,
             ipv4_addr,
+            ipv6_addr: None,
             pending_arp_reply_dest: None,
+            pending_ndp_reply: None,
             tcp_handler: TcpIPv4Handler::new(
                 ipv4_addr,
                 tcp_port,
@@ -119,15 +153,36 @@ impl MmdsNetworkStack {
         Ipv4Addr::from(DEFAULT_IPV4_ADDR)
     }
 
+    /// Sets the IPv6 address of the MMDS server. The MMDS is only reachable over IPv6 when it
+    /// has an IPv6 address.
+    pub fn set_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.ipv6_addr = ipv6_addr;
+        self.tcp_handler.set_local_ipv6_addr(ipv6_addr);
+        if ipv6_addr.is_none() {
+            self.pending_ndp_reply = None;
+        }
+    }
+
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.ipv6_addr
+    }
+
     /// Check if a frame is destined for `mmds`
     ///
-    /// This returns `true` if the frame is an ARP or IPv4 frame destined for
-    /// the `mmds` service, or `false` otherwise. It does not consume the frame.
+    /// This returns `true` if the frame is an ARP, IPv4 or IPv6 frame destined for
+    /// the `mmds` service, or a Neighbor Solicitation for the `mmds` IPv6 address,
+    /// or `false` otherwise. It does not consume the frame.
     pub fn is_mmds_frame(&self, src: &[u8]) -> bool {
         if let Ok(eth) = EthernetFrame::from_bytes(src) {
             match eth.ethertype() {
                 ETHERTYPE_ARP => test_speculative_tpa(src, self.ipv4_addr),
                 ETHERTYPE_IPV4 => test_speculative_dst_addr(src, self.ipv4_addr),
+                // Neighbor Solicitations are usually sent to a multicast address, so they are
+                // recognized by their target address instead.
+                ETHERTYPE_IPV6 => self.ipv6_addr.is_some_and(|addr| {
+                    test_speculative_ipv6_dst_addr(src, addr)
+                        || test_speculative_target_addr(src, addr)
+                }),
                 _ => false,
             }
         } else {
@@ -148,6 +203,7 @@ impl MmdsNetworkStack {
             match eth.ethertype() {
                 ETHERTYPE_ARP => return self.detour_arp(eth),
                 ETHERTYPE_IPV4 => return self.detour_ipv4(eth),
+                ETHERTYPE_IPV6 => return self.detour_ipv6(eth),
                 _ => (),
             }
         } else {
@@ -179,27 +235,12 @@ impl MmdsNetworkStack {
                 // each MmdsNetworkStack routes packets for only one network device.
                 self.remote_mac_addr = eth.src_mac();
                 let mmds_instance = self.mmds.clone();
-                match &mut self.tcp_handler.receive_packet(&ip, move |request| {
-                    super::convert_to_response(mmds_instance, request)
-                }) {
-                    Ok(event) => {
-                        METRICS.mmds.rx_count.inc();
-                        match event {
-                            RecvEvent::NewConnectionSuccessful => {
-                                METRICS.mmds.connections_created.inc()
-                            }
-                            RecvEvent::NewConnectionReplacing => {
-                                METRICS.mmds.connections_created.inc();
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            RecvEvent::EndpointDone => {
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            _ => (),
-                        }
-                    }
-                    Err(_) => METRICS.mmds.rx_accepted_err.inc(),
-                }
+                let result = self
+                    .tcp_handler
+                    .receive_packet(&ip, move |request, waited| {
+                        super::convert_to_response_or_wait(mmds_instance, request, waited)
+                    });
+                Self::update_recv_metrics(result);
             } else {
                 // A non-TCP IPv4 packet heading towards the MMDS; we consider it unusual.
                 METRICS.mmds.rx_accepted_unusual.inc();
@@ -210,12 +251,98 @@ impl MmdsNetworkStack {
         false
     }
 
+    fn detour_ipv6(&mut self, eth: EthernetFrame<&[u8]>) -> bool {
+        let Some(ipv6_addr) = self.ipv6_addr else {
+            return false;
+        };
+
+        // Like for IPv4, we skip verifying the checksums of the TCP segments and NDP messages.
+        if let Ok(ip) = IPv6Packet::from_bytes(eth.payload()) {
+            match ip.next_header() {
+                PROTOCOL_ICMPV6 => {
+                    match NdpMessage::solicitation_from_bytes(ip.payload(), None) {
+                        Ok(ns) if ns.target_address() == ipv6_addr => {
+                            // The solicitation usually carries the MAC address of the requester,
+                            // otherwise we use the one of the network device.
+                            self.remote_mac_addr = ns
+                                .source_link_layer_address()
+                                .unwrap_or_else(|| eth.src_mac());
+                            // Solicitations sent from the unspecified address are answered to all
+                            // the nodes, as unsolicited advertisements (see RFC 4861, 7.2.4).
+                            self.pending_ndp_reply = if ip.source_address().is_unspecified() {
+                                Some((IPV6_ALL_NODES_ADDR, AdvertisementFlags::OVERRIDE))
+                            } else {
+                                Some((
+                                    ip.source_address(),
+                                    AdvertisementFlags::SOLICITED | AdvertisementFlags::OVERRIDE,
+                                ))
+                            };
+                        }
+                        // Any other ICMPv6 message heading towards the MMDS is unusual.
+                        _ => METRICS.mmds.rx_accepted_unusual.inc(),
+                    }
+                }
+                PROTOCOL_TCP => {
+                    // See the notes in detour_ipv4() about `remote_mac_addr`.
+                    self.remote_mac_addr = eth.src_mac();
+                    let mmds_instance = self.mmds.clone();
+                    let result =
+                        self.tcp_handler
+                            .receive_ipv6_packet(&ip, move |request, waited| {
+                                super::convert_to_response_or_wait(mmds_instance, request, waited)
+                            });
+                    Self::update_recv_metrics(result);
+                }
+                // A non-TCP, non-ICMPv6 IPv6 packet heading towards the MMDS; we consider it
+                // unusual.
+                _ => METRICS.mmds.rx_accepted_unusual.inc(),
+            }
+            return true;
+        }
+
+        false
+    }
+
+    /// Returns whether some requests are waiting for the MMDS data store contents to change.
+    pub fn has_waiting_requests(&self) -> bool {
+        self.tcp_handler.has_deferred_requests()
+    }
+
+    /// Handles the requests waiting for the MMDS data store contents to change again. The
+    /// responses of the requests which stop waiting are then available from `write_next_frame`.
+    pub fn poll_waiting_requests(&mut self) {
+        let mmds = &self.mmds;
+        self.tcp_handler.poll_deferred_requests(|request, waited| {
+            super::convert_to_response_or_wait(mmds.clone(), request, waited)
+        });
+    }
+
+    fn update_recv_metrics(result: Result<RecvEvent, RecvError>) {
+        match result {
+            Ok(event) => {
+                METRICS.mmds.rx_count.inc();
+                match event {
+                    RecvEvent::NewConnectionSuccessful => METRICS.mmds.connections_created.inc(),
+                    RecvEvent::NewConnectionReplacing => {
+                        METRICS.mmds.connections_created.inc();
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    RecvEvent::EndpointDone => {
+                        METRICS.mmds.connections_destroyed.inc();
+                    }
+                    _ => (),
+                }
+            }
+            Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+        }
+    }
+
     // Allows the MMDS network stack to write a frame to the specified buffer. Will return:
     // - None, if the MMDS network stack has no frame to send at this point. The buffer can be
     // used for something else by the device model.
     // - Some(len), if a frame of the given length has been written to the specified buffer.
     pub fn write_next_frame(&mut self, buf: &mut [u8]) -> Option<NonZeroUsize> {
-        // We try to send ARP replies first.
+        // We try to send ARP replies first, then NDP replies.
         if self.pending_arp_reply_dest.is_some() {
             return match self.write_arp_reply(buf) {
                 Ok(something) => {
@@ -228,6 +355,18 @@ impl MmdsNetworkStack {
                     None
                 }
             };
+        } else if self.pending_ndp_reply.is_some() {
+            return match self.write_ndp_reply(buf) {
+                Ok(something) => {
+                    METRICS.mmds.tx_count.inc();
+                    self.pending_ndp_reply = None;
+                    something
+                }
+                Err(_) => {
+                    METRICS.mmds.tx_errors.inc();
+                    None
+                }
+            };
         } else {
             let call_write = match self.tcp_handler.next_segment_status() {
                 NextSegmentStatus::Available => true,
@@ -285,7 +424,45 @@ impl MmdsNetworkStack {
         ))
     }
 
+    fn write_ndp_reply(&self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WriteNdpFrameError> {
+        let (ndp_reply_dest, flags) = self
+            .pending_ndp_reply
+            .ok_or(WriteNdpFrameError::NoPendingNdpReply)?;
+        let ipv6_addr = self.ipv6_addr.ok_or(WriteNdpFrameError::Ipv6Disabled)?;
+
+        let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6)?;
+        let packet_len = {
+            let mut packet = IPv6Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_ICMPV6,
+                ipv6_addr,
+                ndp_reply_dest,
+            )?;
+
+            NdpMessage::write_advertisement(
+                packet.inner_mut().payload_mut(),
+                ipv6_addr,
+                ndp_reply_dest,
+                flags,
+                ipv6_addr,
+                self.mac_addr,
+            )?;
+
+            // The unwrap() is safe because an NDP message is way shorter than u16::MAX.
+            packet
+                .with_payload_len_unchecked(u16::try_from(NDP_MESSAGE_LEN).unwrap())
+                .len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
     fn write_packet(&mut self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WritePacketError> {
+        // The TCP handler writes either IPv4 or IPv6 packets, depending on the connection, so
+        // the ethertype is fixed up below when needed.
         let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV4)?;
 
         let (maybe_len, event) = self
@@ -297,6 +474,11 @@ impl MmdsNetworkStack {
         }
 
         if let Some(packet_len) = maybe_len {
+            if IPv6Packet::from_bytes_unchecked(eth_unsized.inner().payload()).version()
+                == IPV6_VERSION
+            {
+                eth_unsized.inner_mut().set_ethertype(ETHERTYPE_IPV6);
+            }
             return Ok(Some(
                 // The unwrap() is safe because packet_len > 0.
                 NonZeroUsize::new(
@@ -317,6 +499,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::dumbo::pdu::icmpv6::TYPE_NEIGHBOR_ADVERTISEMENT;
     use crate::dumbo::pdu::tcp::{Flags as TcpFlags, TcpSegment};
 
     // We use LOCALHOST here because const new() is not stable yet, so just reuse this const, since
@@ -326,6 +509,8 @@ mod tests {
     const MMDS_PORT: u16 = 80;
     const REMOTE_PORT: u16 = 1235;
     const SEQ_NUMBER: u32 = 123;
+    const MMDS_IPV6_ADDR: Ipv6Addr = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe);
+    const REMOTE_IPV6_ADDR: Ipv6Addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
 
     // Helper methods which only make sense for testing.
     impl MmdsNetworkStack {
@@ -386,6 +571,80 @@ mod tests {
             eth_unsized.with_payload_len_unchecked(packet_len).len()
         }
 
+        fn write_ndp_solicitation(
+            &self,
+            buf: &mut [u8],
+            src_addr: Ipv6Addr,
+            target: Ipv6Addr,
+        ) -> usize {
+            // Solicitations are sent to the solicited-node multicast address of the target.
+            let mut dst_segments = [0xff02, 0, 0, 0, 0, 1, 0xff00, 0];
+            dst_segments[6] |= target.segments()[6] & 0xff;
+            dst_segments[7] = target.segments()[7];
+            let dst_addr = Ipv6Addr::from(dst_segments);
+
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_ICMPV6,
+                    src_addr,
+                    dst_addr,
+                )
+                .unwrap();
+                let ns_len = NdpMessage::write_solicitation(
+                    packet.inner_mut().payload_mut(),
+                    src_addr,
+                    dst_addr,
+                    target,
+                    MacAddr::from_str(REMOTE_MAC_STR).unwrap(),
+                )
+                .unwrap()
+                .len();
+                packet
+                    .with_payload_len_unchecked(u16::try_from(ns_len).unwrap())
+                    .len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_incoming_tcp_segment_ipv6(
+            &self,
+            buf: &mut [u8],
+            addr: Ipv6Addr,
+            flags: TcpFlags,
+        ) -> usize {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_TCP,
+                    REMOTE_IPV6_ADDR,
+                    addr,
+                )
+                .unwrap();
+
+                let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
+                    packet.inner_mut().payload_mut(),
+                    SEQ_NUMBER,
+                    1234,
+                    flags,
+                    10000,
+                    None,
+                    0,
+                    None,
+                )
+                .unwrap()
+                .finalize_ipv6(REMOTE_PORT, MMDS_PORT, Some((REMOTE_IPV6_ADDR, addr)))
+                .len();
+
+                packet.with_payload_len_unchecked(segment_len).len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
         fn next_frame_as_ipv4_packet<'a>(&mut self, buf: &'a mut [u8]) -> IPv4Packet<&'a [u8]> {
             let len = self.write_next_frame(buf).unwrap().get();
             let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
@@ -624,4 +883,107 @@ mod tests {
         assert!(ns.detour_arp(EthernetFrame::from_bytes(&buf[..len]).unwrap()));
         assert!(!ns.detour_ipv4(EthernetFrame::from_bytes(&buf[..len]).unwrap()));
     }
+
+    #[test]
+    fn test_ns_ipv6() {
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut buf = [0u8; 2000];
+        let remote_mac = MacAddr::from_str(REMOTE_MAC_STR).unwrap();
+
+        // The MMDS is not reachable over IPv6 by default.
+        assert_eq!(ns.ipv6_addr(), None);
+        let len = ns.write_ndp_solicitation(buf.as_mut(), REMOTE_IPV6_ADDR, MMDS_IPV6_ADDR);
+        assert!(!ns.is_mmds_frame(&buf[..len]));
+        let len = ns.write_incoming_tcp_segment_ipv6(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+        assert!(!ns.is_mmds_frame(&buf[..len]));
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        ns.set_ipv6_addr(Some(MMDS_IPV6_ADDR));
+        assert_eq!(ns.ipv6_addr(), Some(MMDS_IPV6_ADDR));
+        assert_eq!(ns.tcp_handler.local_ipv6_addr(), Some(MMDS_IPV6_ADDR));
+
+        // Solicitations for other addresses are not for the MMDS.
+        let len = ns.write_ndp_solicitation(buf.as_mut(), REMOTE_IPV6_ADDR, REMOTE_IPV6_ADDR);
+        assert!(!ns.is_mmds_frame(&buf[..len]));
+
+        // Asking for the MMDS MAC address.
+        let len = ns.write_ndp_solicitation(buf.as_mut(), REMOTE_IPV6_ADDR, MMDS_IPV6_ADDR);
+        assert!(ns.is_mmds_frame(&buf[..len]));
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(ns.remote_mac_addr, remote_mac);
+
+        // There should be a Neighbor Advertisement to send.
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+            assert_eq!(eth.dst_mac(), remote_mac);
+
+            let ip = IPv6Packet::from_bytes(eth.payload()).unwrap();
+            assert_eq!(ip.next_header(), PROTOCOL_ICMPV6);
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+
+            let na = NdpMessage::from_bytes_unchecked(ip.payload());
+            assert_eq!(na.msg_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+            assert_eq!(
+                na.flags(),
+                AdvertisementFlags::SOLICITED | AdvertisementFlags::OVERRIDE
+            );
+            assert_eq!(na.target_address(), MMDS_IPV6_ADDR);
+            assert_eq!(na.target_link_layer_address(), Some(ns.mac_addr));
+            assert_eq!(na.compute_checksum(MMDS_IPV6_ADDR, REMOTE_IPV6_ADDR), 0);
+        }
+
+        // Nothing to send anymore.
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Duplicate address detection solicitations are answered to all the nodes.
+        {
+            let len =
+                ns.write_ndp_solicitation(buf.as_mut(), Ipv6Addr::UNSPECIFIED, MMDS_IPV6_ADDR);
+            assert!(ns.detour_frame(&buf[..len]));
+
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            let ip = IPv6Packet::from_bytes(eth.payload()).unwrap();
+            assert_eq!(ip.destination_address(), IPV6_ALL_NODES_ADDR);
+            let na = NdpMessage::from_bytes_unchecked(ip.payload());
+            assert_eq!(na.flags(), AdvertisementFlags::OVERRIDE);
+        }
+
+        // Let's send a TCP SYN over IPv6 into the ns.
+        {
+            let len =
+                ns.write_incoming_tcp_segment_ipv6(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+            assert!(ns.is_mmds_frame(&buf[..len]));
+            let curr_rx_count = METRICS.mmds.rx_count.count();
+            assert!(ns.detour_frame(&buf[..len]));
+            assert_eq!(curr_rx_count + 1, METRICS.mmds.rx_count.count());
+        }
+
+        // We should be getting a SYNACK over IPv6 out of the ns in response.
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+
+            let ip = IPv6Packet::from_bytes(eth.payload()).unwrap();
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+
+            let s = TcpSegment::from_bytes(ip.payload(), None).unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.source_port(), MMDS_PORT);
+            assert_eq!(s.destination_port(), REMOTE_PORT);
+            assert_eq!(s.ack_number(), SEQ_NUMBER.wrapping_add(1));
+            assert_eq!(s.compute_checksum_ipv6(MMDS_IPV6_ADDR, REMOTE_IPV6_ADDR), 0);
+        }
+
+        // Disabling IPv6 makes the MMDS unreachable over IPv6 again.
+        ns.set_ipv6_addr(None);
+        let len = ns.write_incoming_tcp_segment_ipv6(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+        assert!(!ns.is_mmds_frame(&buf[..len]));
+    }
 }
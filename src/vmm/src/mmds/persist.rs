@@ -3,7 +3,7 @@
 
 //! Defines the structures needed for saving/restoring MmdsNetworkStack.
 
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
@@ -18,6 +18,7 @@ use crate::utils::net::mac::{MAC_ADDR_LEN, MacAddr};
 pub struct MmdsNetworkStackState {
     mac_addr: [u8; MAC_ADDR_LEN as usize],
     ipv4_addr: u32,
+    ipv6_addr: Option<u128>,
     tcp_port: u16,
 }
 
@@ -33,6 +34,7 @@ impl Persist<'_> for MmdsNetworkStack {
         MmdsNetworkStackState {
             mac_addr,
             ipv4_addr: self.ipv4_addr.into(),
+            ipv6_addr: self.ipv6_addr.map(u128::from),
             tcp_port: self.tcp_handler.local_port(),
         }
     }
@@ -41,12 +43,14 @@ impl Persist<'_> for MmdsNetworkStack {
         mmds: Self::ConstructorArgs,
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
-        Ok(MmdsNetworkStack::new(
+        let mut ns = MmdsNetworkStack::new(
             MacAddr::from_bytes_unchecked(&state.mac_addr),
             Ipv4Addr::from(state.ipv4_addr),
             state.tcp_port,
             mmds,
-        ))
+        );
+        ns.set_ipv6_addr(state.ipv6_addr.map(Ipv6Addr::from));
+        Ok(ns)
     }
 }
 
@@ -58,7 +62,9 @@ mod tests {
 
     #[test]
     fn test_persistence() {
-        let ns = MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        ns.set_ipv6_addr(Some(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe)));
 
         let mut mem = vec![0; 4096];
 
@@ -76,6 +82,11 @@ mod tests {
 
         assert_eq!(restored_ns.mac_addr, ns.mac_addr);
         assert_eq!(restored_ns.ipv4_addr, ns.ipv4_addr);
+        assert_eq!(restored_ns.ipv6_addr, ns.ipv6_addr);
+        assert_eq!(
+            restored_ns.tcp_handler.local_ipv6_addr(),
+            ns.tcp_handler.local_ipv6_addr()
+        );
         assert_eq!(
             restored_ns.tcp_handler.local_port(),
             ns.tcp_handler.local_port()
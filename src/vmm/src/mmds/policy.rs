@@ -0,0 +1,272 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Access restrictions applied to an MMDS subtree, as seen from the guest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MmdsPathAccess {
+    /// The subtree can only be read with a valid session token, even with MMDS V1.
+    TokenRequired,
+    /// The subtree is not visible to the guest.
+    Hidden,
+    /// The subtree can be read and also written by the guest through `PUT` and `PATCH` requests.
+    GuestWritable,
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+/// MMDS policy errors
+pub enum MmdsPolicyError {
+    /// The MMDS policy path `{0}` is not a JSON pointer to a subtree of the data store.
+    InvalidPath(String),
+}
+
+/// Per-path access policy of the MMDS data store.
+///
+/// The policy maps JSON pointers, such as `/latest/meta-data/secrets`, to the access allowed to
+/// the guest for the subtree they point to. When several paths of the policy contain a subtree,
+/// the most specific one applies. Subtrees not covered by the policy are read-only.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct MmdsPolicy(BTreeMap<String, MmdsPathAccess>);
+
+// Escapes a JSON object key to be used as a JSON pointer reference token.
+fn escape_token(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+// Unescapes a JSON pointer reference token into a JSON object key.
+pub(crate) fn unescape_token(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+// Checks whether `path` points inside the subtree pointed to by `ancestor`, excluding `ancestor`
+// itself.
+fn is_strict_descendant(path: &str, ancestor: &str) -> bool {
+    path.strip_prefix(ancestor)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl MmdsPolicy {
+    /// Checks that the policy only contains JSON pointers to subtrees of the data store.
+    pub fn validate(&self) -> Result<(), MmdsPolicyError> {
+        match self
+            .0
+            .keys()
+            .find(|path| !path.starts_with('/') || path.split('/').skip(1).any(str::is_empty))
+        {
+            Some(path) => Err(MmdsPolicyError::InvalidPath(path.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns whether the policy has no paths.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the access allowed to the guest for the subtree at `path`, or None if the path
+    /// is not covered by the policy. `path` is a JSON pointer without a trailing slash.
+    pub fn access(&self, path: &str) -> Option<MmdsPathAccess> {
+        self.0
+            .iter()
+            .filter(|(policy_path, _)| {
+                path == policy_path.as_str() || is_strict_descendant(path, policy_path)
+            })
+            .max_by_key(|(policy_path, _)| policy_path.len())
+            .map(|(_, access)| *access)
+    }
+
+    /// Returns whether the guest can read the subtree at `path`.
+    pub fn is_readable(&self, path: &str, has_valid_token: bool) -> bool {
+        match self.access(path) {
+            Some(MmdsPathAccess::Hidden) => false,
+            Some(MmdsPathAccess::TokenRequired) => has_valid_token,
+            Some(MmdsPathAccess::GuestWritable) | None => true,
+        }
+    }
+
+    /// Returns whether the guest can write the subtree at `path`. The whole subtree must be
+    /// guest writable, so the guest cannot overwrite subtrees which have a different access.
+    pub fn is_writable(&self, path: &str) -> bool {
+        self.access(path) == Some(MmdsPathAccess::GuestWritable)
+            && self.0.iter().all(|(policy_path, access)| {
+                *access == MmdsPathAccess::GuestWritable || !is_strict_descendant(policy_path, path)
+            })
+    }
+
+    /// Removes the object members and array elements the guest cannot read from `value`, which
+    /// is the subtree located at `path`. Array elements are matched by their index in `value`.
+    pub fn filter(&self, path: &str, value: &mut Value, has_valid_token: bool) {
+        if !self
+            .0
+            .keys()
+            .any(|policy_path| is_strict_descendant(policy_path, path))
+        {
+            return;
+        }
+
+        match value {
+            Value::Object(map) => map.retain(|key, member| {
+                let member_path = format!("{}/{}", path, escape_token(key));
+                if !self.is_readable(&member_path, has_valid_token) {
+                    return false;
+                }
+                self.filter(&member_path, member, has_valid_token);
+                true
+            }),
+            Value::Array(elements) => {
+                let mut index = 0;
+                elements.retain_mut(|element| {
+                    let element_path = format!("{}/{}", path, index);
+                    index += 1;
+                    if !self.is_readable(&element_path, has_valid_token) {
+                        return false;
+                    }
+                    self.filter(&element_path, element, has_valid_token);
+                    true
+                });
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn policy() -> MmdsPolicy {
+        serde_json::from_value(json!({
+            "/secrets": "token_required",
+            "/secrets/status": "guest_writable",
+            "/internal": "hidden",
+            "/status": "guest_writable",
+            "/status/host": "hidden",
+            "/a~1b": "hidden",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate() {
+        policy().validate().unwrap();
+        for path in ["", "/", "secrets", "/secrets/", "/secrets//status"] {
+            let policy: MmdsPolicy = serde_json::from_value(json!({ path: "hidden" })).unwrap();
+            assert_eq!(
+                policy.validate().unwrap_err(),
+                MmdsPolicyError::InvalidPath(path.to_string())
+            );
+        }
+        serde_json::from_value::<MmdsPolicy>(json!({ "/secrets": "invalid" })).unwrap_err();
+    }
+
+    #[test]
+    fn test_access() {
+        let policy = policy();
+        assert!(!policy.is_empty());
+        assert!(MmdsPolicy::default().is_empty());
+
+        assert_eq!(policy.access(""), None);
+        assert_eq!(policy.access("/name"), None);
+        assert_eq!(policy.access("/secretsx"), None);
+        assert_eq!(
+            policy.access("/secrets/key"),
+            Some(MmdsPathAccess::TokenRequired)
+        );
+        assert_eq!(
+            policy.access("/secrets/status/0"),
+            Some(MmdsPathAccess::GuestWritable)
+        );
+        assert_eq!(policy.access("/internal"), Some(MmdsPathAccess::Hidden));
+
+        assert!(policy.is_readable("/name", false));
+        assert!(!policy.is_readable("/secrets", false));
+        assert!(policy.is_readable("/secrets", true));
+        assert!(policy.is_readable("/secrets/status", false));
+        assert!(!policy.is_readable("/internal/key", true));
+
+        assert!(!policy.is_writable(""));
+        assert!(!policy.is_writable("/name"));
+        assert!(!policy.is_writable("/secrets"));
+        assert!(policy.is_writable("/secrets/status"));
+        assert!(policy.is_writable("/status/ready"));
+        // The hidden `/status/host` subtree cannot be overwritten by the guest.
+        assert!(!policy.is_writable("/status"));
+        assert!(!policy.is_writable("/status/host"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let policy = policy();
+        let data = json!({
+            "name": "vm",
+            "secrets": {
+                "key": "value",
+                "status": "ok"
+            },
+            "internal": "value",
+            "status": {
+                "ready": true,
+                "host": "value"
+            },
+            "a/b": "value"
+        });
+
+        let mut value = data.clone();
+        policy.filter("", &mut value, false);
+        assert_eq!(
+            value,
+            json!({
+                "name": "vm",
+                "status": {
+                    "ready": true
+                }
+            })
+        );
+
+        let mut value = data.clone();
+        policy.filter("", &mut value, true);
+        assert_eq!(
+            value,
+            json!({
+                "name": "vm",
+                "secrets": {
+                    "key": "value",
+                    "status": "ok"
+                },
+                "status": {
+                    "ready": true
+                }
+            })
+        );
+
+        let mut value = data["secrets"].clone();
+        policy.filter("/secrets", &mut value, false);
+        assert_eq!(value, json!({ "status": "ok" }));
+
+        // Array elements are matched by their index.
+        let policy: MmdsPolicy = serde_json::from_value(json!({
+            "/items/0": "hidden",
+            "/items/2": "token_required",
+            "/items/3/key": "hidden",
+        }))
+        .unwrap();
+        let data = json!({ "items": ["a", "b", "c", { "key": "value", "name": "d" }] });
+        let mut value = data.clone();
+        policy.filter("", &mut value, false);
+        assert_eq!(value, json!({ "items": ["b", { "name": "d" }] }));
+        let mut value = data["items"].clone();
+        policy.filter("/items", &mut value, true);
+        assert_eq!(value, json!(["b", "c", { "name": "d" }]));
+
+        assert_eq!(unescape_token("a~1b~0c"), "a/b~c");
+        assert_eq!(escape_token("a/b~c"), "a~1b~0c");
+    }
+}
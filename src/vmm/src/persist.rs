@@ -5,11 +5,11 @@
 
 use std::fmt::Debug;
 use std::fs::{File, OpenOptions};
-use std::io::{self, Write};
+use std::io::{self, BufReader, Write};
 use std::mem::forget;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::net::UnixStream;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
 use semver::Version;
@@ -27,17 +27,22 @@ use crate::cpu_config::x86_64::cpuid::CpuidTrait;
 use crate::cpu_config::x86_64::cpuid::common::get_vendor_id_from_host;
 use crate::device_manager::{DevicePersistError, DevicesState};
 use crate::logger::{info, warn};
+use crate::rate_limiter::persist::RateLimiterGroupState;
 use crate::resources::VmResources;
 use crate::seccomp::BpfThreadMap;
 use crate::snapshot::Snapshot;
+use crate::snapshot::mem_file::{self, MemFileError, MemFileFormat};
 use crate::utils::u64_to_usize;
 use crate::vmm_config::boot_source::BootSourceConfig;
 use crate::vmm_config::instance_info::InstanceInfo;
 use crate::vmm_config::machine_config::{HugePageConfig, MachineConfigError, MachineConfigUpdate};
-use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, MemBackendType};
+use crate::vmm_config::snapshot::{
+    CreateSnapshotParams, LoadSnapshotParams, MemBackendType, MemFileKey, SnapshotLayer,
+    SnapshotType,
+};
 use crate::vstate::kvm::KvmState;
 use crate::vstate::memory;
-use crate::vstate::memory::{GuestMemoryState, GuestRegionMmap, MemoryError};
+use crate::vstate::memory::{GuestMemoryRegion, GuestMemoryState, GuestRegionMmap, MemoryError};
 use crate::vstate::vcpu::{VcpuSendEventError, VcpuState};
 use crate::vstate::vm::{VmError, VmState};
 use crate::{EventManager, Vmm, vstate};
@@ -55,6 +60,10 @@ pub struct VmInfo {
     pub boot_source: BootSourceConfig,
     /// Huge page configuration
     pub huge_pages: HugePageConfig,
+    /// Maximum number of vCPUs, if vCPU hotplug is configured
+    pub max_vcpus: Option<u8>,
+    /// Rate limiter groups shared by the devices.
+    pub rate_limiter_groups: Vec<RateLimiterGroupState>,
 }
 
 impl From<&VmResources> for VmInfo {
@@ -65,6 +74,8 @@ impl From<&VmResources> for VmInfo {
             cpu_template: StaticCpuTemplate::from(&value.machine_config.cpu_template),
             boot_source: value.boot_source.config.clone(),
             huge_pages: value.machine_config.huge_pages,
+            max_vcpus: value.machine_config.max_vcpus,
+            rate_limiter_groups: value.rate_limiter_groups.save(),
         }
     }
 }
@@ -82,6 +93,31 @@ pub struct MicrovmState {
     pub vcpu_states: Vec<VcpuState>,
     /// Device states.
     pub device_states: DevicesState,
+    /// Snapshot the memory file of a diff snapshot applies on top of. `None` for full snapshots,
+    /// and for diff snapshots that do not depend on another snapshot.
+    pub parent: Option<SnapshotParent>,
+    /// Format of the memory file, if it is encoded rather than a raw dump of the guest memory.
+    pub mem_file_format: Option<MemFileFormat>,
+}
+
+/// Reference from a diff snapshot to its parent snapshot.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotParent {
+    /// Path of the microVM state file of the parent snapshot, when it was created or loaded.
+    pub snapshot_path: PathBuf,
+    /// CRC64 checksum of the microVM state file of the parent snapshot.
+    pub checksum: u64,
+}
+
+/// The last snapshot created or loaded by a microVM, that its diff snapshots are based on.
+#[derive(Debug)]
+pub(crate) struct LastSnapshot {
+    /// Reference to the snapshot.
+    reference: SnapshotParent,
+    /// Path of the memory file of the snapshot, if known and not encoded.
+    mem_file_path: Option<PathBuf>,
+    /// Parent of the snapshot.
+    parent: Option<SnapshotParent>,
 }
 
 /// This describes the mapping between Firecracker base virtual address and
@@ -135,6 +171,12 @@ pub enum CreateSnapshotError {
     DirtyBitmap(#[from] VmError),
     /// Cannot write memory file: {0}
     Memory(#[from] MemoryError),
+    /// Cannot write encoded memory file: {0}
+    MemFile(#[from] MemFileError),
+    /// Diff snapshots cannot have an encoded memory file.
+    EncodedDiff,
+    /// Cannot write an encoded memory file over a file backing the guest memory.
+    MemFileInUse,
     /// Cannot perform {0} on the memory backing file: {1}
     MemoryBackingFile(&'static str, io::Error),
     /// Cannot save the microVM state: {0}
@@ -143,10 +185,12 @@ pub enum CreateSnapshotError {
     SerializeMicrovmState(#[from] crate::snapshot::SnapshotError),
     /// Cannot perform {0} on the snapshot backing file: {1}
     SnapshotBackingFile(&'static str, io::Error),
+    /// Cannot create a snapshot while a live migration is in progress.
+    MigrationInProgress,
 }
 
 /// Snapshot version
-pub const SNAPSHOT_VERSION: Version = Version::new(8, 0, 0);
+pub const SNAPSHOT_VERSION: Version = Version::new(9, 0, 0);
 
 /// Creates a Microvm snapshot.
 pub fn create_snapshot(
@@ -154,14 +198,60 @@ pub fn create_snapshot(
     vm_info: &VmInfo,
     params: &CreateSnapshotParams,
 ) -> Result<(), CreateSnapshotError> {
-    let microvm_state = vmm
+    // Both would consume the dirty page information.
+    if vmm.migration_in_progress() {
+        return Err(CreateSnapshotError::MigrationInProgress);
+    }
+    if let Some(encoding) = &params.mem_file_encoding {
+        if params.snapshot_type == SnapshotType::Diff {
+            return Err(CreateSnapshotError::EncodedDiff);
+        }
+        if let Some(key) = &encoding.encryption_key {
+            mem_file::validate_key(key)?;
+        }
+    }
+
+    let mut microvm_state = vmm
         .save_state(vm_info)
         .map_err(CreateSnapshotError::MicrovmState)?;
+    microvm_state.parent = match (params.snapshot_type, &vmm.last_snapshot) {
+        (SnapshotType::Full, _) | (SnapshotType::Diff, None) => None,
+        // A diff memory file written over the memory file of the last snapshot is merged into
+        // it, so it has the same parent.
+        (SnapshotType::Diff, Some(last))
+            if last.mem_file_path.as_ref() == Some(&params.mem_file_path) =>
+        {
+            last.parent.clone()
+        }
+        (SnapshotType::Diff, Some(last)) => Some(last.reference.clone()),
+    };
+    microvm_state.mem_file_format = params.mem_file_encoding.as_ref().map(MemFileFormat::from);
+
+    let checksum = snapshot_state_to_file(&microvm_state, &params.snapshot_path)?;
+
+    match &params.mem_file_encoding {
+        Some(encoding) => vmm
+            .vm
+            .snapshot_memory_to_encoded_file(&params.mem_file_path, encoding)?,
+        None => vmm.vm.snapshot_memory_to_file(
+            &params.mem_file_path,
+            params.snapshot_type,
+            &vmm.balloon_free_page_hints(),
+        )?,
+    }
 
-    snapshot_state_to_file(&microvm_state, &params.snapshot_path)?;
-
-    vmm.vm
-        .snapshot_memory_to_file(&params.mem_file_path, params.snapshot_type)?;
+    vmm.last_snapshot = Some(LastSnapshot {
+        reference: SnapshotParent {
+            snapshot_path: params.snapshot_path.clone(),
+            checksum,
+        },
+        // Diff snapshots are never merged into encoded memory files.
+        mem_file_path: match microvm_state.mem_file_format {
+            Some(_) => None,
+            None => Some(params.mem_file_path.clone()),
+        },
+        parent: microvm_state.parent,
+    });
 
     // We need to mark queues as dirty again for all activated devices. The reason we
     // do it here is that we don't mark pages as dirty during runtime
@@ -175,7 +265,7 @@ pub fn create_snapshot(
 fn snapshot_state_to_file(
     microvm_state: &MicrovmState,
     snapshot_path: &Path,
-) -> Result<(), CreateSnapshotError> {
+) -> Result<u64, CreateSnapshotError> {
     use self::CreateSnapshotError::*;
     let mut snapshot_file = OpenOptions::new()
         .create(true)
@@ -185,13 +275,14 @@ fn snapshot_state_to_file(
         .map_err(|err| SnapshotBackingFile("open", err))?;
 
     let snapshot = Snapshot::new(microvm_state);
-    snapshot.save(&mut snapshot_file)?;
+    let checksum = snapshot.save(&mut snapshot_file)?;
     snapshot_file
         .flush()
         .map_err(|err| SnapshotBackingFile("flush", err))?;
     snapshot_file
         .sync_all()
-        .map_err(|err| SnapshotBackingFile("sync_all", err))
+        .map_err(|err| SnapshotBackingFile("sync_all", err))?;
+    Ok(checksum)
 }
 
 /// Validates that snapshot CPU vendor matches the host CPU vendor.
@@ -296,6 +387,8 @@ pub enum RestoreFromSnapshotError {
     File(#[from] SnapshotStateFromFileError),
     /// Invalid snapshot state: {0}
     Invalid(#[from] SnapShotStateSanityCheckError),
+    /// Invalid parent layers: {0}
+    ParentLayers(#[from] SnapshotLayersError),
     /// Failed to load guest memory: {0}
     GuestMemory(#[from] RestoreFromSnapshotGuestMemoryError),
     /// Failed to build microVM from snapshot: {0}
@@ -319,7 +412,7 @@ pub fn restore_from_snapshot(
     params: &LoadSnapshotParams,
     vm_resources: &mut VmResources,
 ) -> Result<Arc<Mutex<Vmm>>, RestoreFromSnapshotError> {
-    let mut microvm_state = snapshot_state_from_file(&params.snapshot_path)?;
+    let (mut microvm_state, checksum) = snapshot_state_from_file(&params.snapshot_path)?;
     for entry in &params.network_overrides {
         microvm_state
             .device_states
@@ -341,46 +434,47 @@ pub fn restore_from_snapshot(
     }
     let track_dirty_pages = params.track_dirty_pages;
 
-    let vcpu_count = microvm_state
-        .vcpu_states
-        .len()
-        .try_into()
-        .map_err(|_| MachineConfigError::InvalidVcpuCount)
-        .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
-
-    vm_resources
-        .update_machine_config(&MachineConfigUpdate {
-            vcpu_count: Some(vcpu_count),
-            mem_size_mib: Some(u64_to_usize(microvm_state.vm_info.mem_size_mib)),
-            smt: Some(microvm_state.vm_info.smt),
-            cpu_template: Some(microvm_state.vm_info.cpu_template),
-            track_dirty_pages: Some(track_dirty_pages),
-            huge_pages: Some(microvm_state.vm_info.huge_pages),
-            #[cfg(feature = "gdb")]
-            gdb_socket_path: None,
-        })
-        .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
-
-    // Some sanity checks before building the microvm.
-    snapshot_state_sanity_check(&microvm_state)?;
+    update_vm_resources_from_state(&microvm_state, track_dirty_pages, vm_resources)?;
+    let base_mem_file_format = validate_parent_layers(&microvm_state, &params.parent_layers)?;
 
     let mem_backend_path = &params.mem_backend.backend_path;
     let mem_state = &microvm_state.vm_state.memory;
 
     let (guest_memory, uffd) = match params.mem_backend.backend_type {
         MemBackendType::File => {
-            if vm_resources.machine_config.huge_pages.is_hugetlbfs() {
-                return Err(RestoreFromSnapshotGuestMemoryError::File(
-                    GuestMemoryFromFileError::HugetlbfsSnapshot,
-                )
-                .into());
-            }
+            // The memory of the snapshot is the memory file of its first parent layer, overlaid
+            // by the memory files of the next layers, and finally by its own memory file.
+            let mut mem_file_paths = params
+                .parent_layers
+                .iter()
+                .map(|layer| layer.mem_file_path.as_path())
+                .chain(std::iter::once(mem_backend_path.as_path()));
+            // The iterator yields at least the memory file of the snapshot.
+            let base_path = mem_file_paths.next().unwrap();
+            let diff_layer_paths = mem_file_paths.collect::<Vec<_>>();
             (
-                guest_memory_from_file(mem_backend_path, mem_state, track_dirty_pages)
-                    .map_err(RestoreFromSnapshotGuestMemoryError::File)?,
+                guest_memory_from_file(
+                    base_path,
+                    base_mem_file_format,
+                    params.mem_file_encryption_key.as_ref(),
+                    &diff_layer_paths,
+                    mem_state,
+                    track_dirty_pages,
+                    vm_resources.machine_config.huge_pages,
+                )
+                .map_err(RestoreFromSnapshotGuestMemoryError::File)?,
                 None,
             )
         }
+        MemBackendType::Uffd if !params.parent_layers.is_empty() => {
+            return Err(SnapshotLayersError::Uffd.into());
+        }
+        MemBackendType::Uffd if params.mem_file_encryption_key.is_some() => {
+            return Err(RestoreFromSnapshotGuestMemoryError::Uffd(
+                GuestMemoryFromUffdError::EncryptionKey,
+            )
+            .into());
+        }
         MemBackendType::Uffd => guest_memory_from_uffd(
             mem_backend_path,
             mem_state,
@@ -389,7 +483,17 @@ pub fn restore_from_snapshot(
         )
         .map_err(RestoreFromSnapshotGuestMemoryError::Uffd)?,
     };
-    builder::build_microvm_from_snapshot(
+    // Diff snapshots are never merged into encoded memory files.
+    let mem_file_path = match params.mem_backend.backend_type {
+        MemBackendType::File
+            if base_mem_file_format.is_none() || !params.parent_layers.is_empty() =>
+        {
+            Some(mem_backend_path.clone())
+        }
+        _ => None,
+    };
+    let parent = microvm_state.parent.clone();
+    let vmm = builder::build_microvm_from_snapshot(
         instance_info,
         event_manager,
         microvm_state,
@@ -398,7 +502,108 @@ pub fn restore_from_snapshot(
         seccomp_filters,
         vm_resources,
     )
-    .map_err(RestoreFromSnapshotError::Build)
+    .map_err(RestoreFromSnapshotError::Build)?;
+
+    vmm.lock().expect("Poisoned lock").last_snapshot = Some(LastSnapshot {
+        reference: SnapshotParent {
+            snapshot_path: params.snapshot_path.clone(),
+            checksum,
+        },
+        mem_file_path,
+        parent,
+    });
+    Ok(vmm)
+}
+
+/// Errors related to the parent layers of a diff snapshot being loaded.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SnapshotLayersError {
+    /// Failed to load the microVM state of parent layer {0}: {1}
+    Load(PathBuf, SnapshotStateFromFileError),
+    /// The snapshot of layer {0} is not the parent of the next layer, its checksum differs.
+    ChecksumMismatch(PathBuf),
+    /// The snapshot of layer {0} has a different guest memory layout than the loaded snapshot.
+    MemoryLayout(PathBuf),
+    /// The chain of parent layers is incomplete, it must start with a full snapshot.
+    Incomplete,
+    /// Parent layers are only supported with the `File` memory backend.
+    Uffd,
+}
+
+/// Checks that `parent_layers` are the chain of parents of the snapshot of `microvm_state`,
+/// ordered from the full snapshot at the base of the chain to its direct parent, and returns the
+/// format of the memory file at the base of the chain.
+///
+/// Without parent layers, the memory file of the snapshot is expected to hold the whole guest
+/// memory, like for diff snapshots merged into their parents.
+fn validate_parent_layers(
+    microvm_state: &MicrovmState,
+    parent_layers: &[SnapshotLayer],
+) -> Result<Option<MemFileFormat>, SnapshotLayersError> {
+    if parent_layers.is_empty() {
+        if microvm_state.parent.is_some() {
+            info!("Loading a diff snapshot without its parent layers");
+        }
+        return Ok(microvm_state.mem_file_format);
+    }
+
+    let mut expected_parent = microvm_state.parent.clone();
+    let mut base_mem_file_format = None;
+    for layer in parent_layers.iter().rev() {
+        let parent = expected_parent.ok_or(SnapshotLayersError::Incomplete)?;
+        let (layer_state, checksum) = snapshot_state_from_file(&layer.snapshot_path)
+            .map_err(|err| SnapshotLayersError::Load(layer.snapshot_path.clone(), err))?;
+        if checksum != parent.checksum {
+            return Err(SnapshotLayersError::ChecksumMismatch(
+                layer.snapshot_path.clone(),
+            ));
+        }
+        if layer_state.vm_state.memory != microvm_state.vm_state.memory {
+            return Err(SnapshotLayersError::MemoryLayout(
+                layer.snapshot_path.clone(),
+            ));
+        }
+        expected_parent = layer_state.parent;
+        base_mem_file_format = layer_state.mem_file_format;
+    }
+
+    match expected_parent {
+        None => Ok(base_mem_file_format),
+        Some(_) => Err(SnapshotLayersError::Incomplete),
+    }
+}
+
+/// Updates the machine configuration in `vm_resources` to match the one of the saved microVM,
+/// and performs sanity checks on the saved state before the microVM is built from it.
+pub(crate) fn update_vm_resources_from_state(
+    microvm_state: &MicrovmState,
+    track_dirty_pages: bool,
+    vm_resources: &mut VmResources,
+) -> Result<(), RestoreFromSnapshotError> {
+    let vcpu_count = microvm_state
+        .vcpu_states
+        .len()
+        .try_into()
+        .map_err(|_| MachineConfigError::InvalidVcpuCount)
+        .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
+
+    vm_resources
+        .update_machine_config(&MachineConfigUpdate {
+            vcpu_count: Some(vcpu_count),
+            max_vcpus: microvm_state.vm_info.max_vcpus,
+            mem_size_mib: Some(u64_to_usize(microvm_state.vm_info.mem_size_mib)),
+            smt: Some(microvm_state.vm_info.smt),
+            cpu_template: Some(microvm_state.vm_info.cpu_template),
+            track_dirty_pages: Some(track_dirty_pages),
+            huge_pages: Some(microvm_state.vm_info.huge_pages),
+            #[cfg(feature = "gdb")]
+            gdb_socket_path: None,
+        })
+        .map_err(BuildMicrovmFromSnapshotError::VmUpdateConfig)?;
+
+    // Some sanity checks before building the microvm.
+    snapshot_state_sanity_check(microvm_state)?;
+    Ok(())
 }
 
 /// Error type for [`snapshot_state_from_file`]
@@ -414,11 +619,11 @@ pub enum SnapshotStateFromFileError {
 
 fn snapshot_state_from_file(
     snapshot_path: &Path,
-) -> Result<MicrovmState, SnapshotStateFromFileError> {
+) -> Result<(MicrovmState, u64), SnapshotStateFromFileError> {
     let mut snapshot_reader = File::open(snapshot_path)?;
-    let snapshot = Snapshot::load(&mut snapshot_reader)?;
+    let (snapshot, checksum) = Snapshot::load_with_checksum(&mut snapshot_reader)?;
 
-    Ok(snapshot.data)
+    Ok((snapshot.data, checksum))
 }
 
 /// Error type for [`guest_memory_from_file`].
@@ -428,17 +633,50 @@ pub enum GuestMemoryFromFileError {
     File(#[from] std::io::Error),
     /// Failed to restore guest memory: {0}
     Restore(#[from] MemoryError),
+    /// Failed to decode the memory file: {0}
+    MemFile(#[from] MemFileError),
     /// Cannot restore hugetlbfs backed snapshot by mapping the memory file. Please use uffd.
     HugetlbfsSnapshot,
 }
 
 fn guest_memory_from_file(
     mem_file_path: &Path,
+    mem_file_format: Option<MemFileFormat>,
+    key: Option<&MemFileKey>,
+    diff_layer_paths: &[&Path],
     mem_state: &GuestMemoryState,
     track_dirty_pages: bool,
+    huge_pages: HugePageConfig,
 ) -> Result<Vec<GuestRegionMmap>, GuestMemoryFromFileError> {
+    // Encoded memory files are decoded into anonymous memory, which can be backed by hugetlbfs as
+    // long as no diff layer has to be mapped over it.
+    if huge_pages.is_hugetlbfs() && (mem_file_format.is_none() || !diff_layer_paths.is_empty()) {
+        return Err(GuestMemoryFromFileError::HugetlbfsSnapshot);
+    }
+
     let mem_file = File::open(mem_file_path)?;
-    let guest_mem = memory::snapshot_file(mem_file, mem_state.regions(), track_dirty_pages)?;
+    let guest_mem = match mem_file_format {
+        None if key.is_some() => return Err(MemFileError::NotEncrypted.into()),
+        None => memory::snapshot_file(mem_file, mem_state.regions(), track_dirty_pages)?,
+        Some(format) if format.encrypted && key.is_none() => {
+            return Err(MemFileError::MissingKey.into());
+        }
+        Some(_) => {
+            let guest_mem = memory::anonymous(mem_state.regions(), track_dirty_pages, huge_pages)?;
+            mem_file::decode_guest_memory(&guest_mem, BufReader::new(mem_file), key)?;
+            // Loading the memory file does not dirty the guest memory.
+            for region in &guest_mem {
+                if let Some(bitmap) = region.bitmap() {
+                    bitmap.reset();
+                }
+            }
+            guest_mem
+        }
+    };
+    for diff_layer_path in diff_layer_paths {
+        let mut diff_file = File::open(diff_layer_path)?;
+        memory::map_diff_layer(&guest_mem, &mut diff_file)?;
+    }
     Ok(guest_mem)
 }
 
@@ -455,6 +693,8 @@ pub enum GuestMemoryFromUffdError {
     Connect(#[from] std::io::Error),
     /// Failed to sends file descriptor: {0}
     Send(#[from] vmm_sys_util::errno::Error),
+    /// Encrypted memory files must be decrypted by the page fault handler when using UFFD.
+    EncryptionKey,
 }
 
 fn guest_memory_from_uffd(
@@ -585,10 +825,14 @@ mod tests {
     use crate::construct_kvm_mpidrs;
     use crate::devices::virtio::block::CacheType;
     use crate::snapshot::Persist;
+    use crate::test_utils::multi_region_mem;
     use crate::vmm_config::balloon::BalloonDeviceConfig;
     use crate::vmm_config::net::NetworkInterfaceConfig;
+    use crate::vmm_config::snapshot::{MemFileCompression, MemFileEncoding};
     use crate::vmm_config::vsock::tests::default_config;
-    use crate::vstate::memory::GuestMemoryRegionState;
+    use crate::vstate::memory::{
+        Bitmap, Bytes, GuestAddress, GuestMemoryRegionState, MemoryRegionAddress,
+    };
 
     fn default_vmm_with_devices() -> Vmm {
         let mut event_manager = EventManager::new().expect("Cannot create EventManager");
@@ -600,6 +844,9 @@ mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         };
         insert_balloon_device(&mut vmm, &mut cmdline, &mut event_manager, balloon_config);
 
@@ -621,6 +868,9 @@ mod tests {
             guest_mac: None,
             rx_rate_limiter: None,
             tx_rate_limiter: None,
+            rx_rate_limiter_group: None,
+            tx_rate_limiter_group: None,
+            num_queues: None,
         };
         insert_net_device(
             &mut vmm,
@@ -669,6 +919,8 @@ mod tests {
             vm_state: vmm.vm.save_state(&mpidrs).unwrap(),
             #[cfg(target_arch = "x86_64")]
             vm_state: vmm.vm.save_state().unwrap(),
+            parent: None,
+            mem_file_format: None,
         };
 
         let mut buf = vec![0; 10000];
@@ -687,6 +939,162 @@ mod tests {
         )
     }
 
+    fn state_to_file(microvm_state: &MicrovmState) -> (TempFile, SnapshotParent) {
+        let file = TempFile::new().unwrap();
+        let checksum = snapshot_state_to_file(microvm_state, file.as_path()).unwrap();
+        let reference = SnapshotParent {
+            snapshot_path: file.as_path().to_path_buf(),
+            checksum,
+        };
+        (file, reference)
+    }
+
+    fn layer(snapshot_file: &TempFile) -> SnapshotLayer {
+        SnapshotLayer {
+            snapshot_path: snapshot_file.as_path().to_path_buf(),
+            mem_file_path: PathBuf::from("mem"),
+        }
+    }
+
+    #[test]
+    fn test_validate_parent_layers() {
+        // A full snapshot with an encoded memory file, and a diff snapshot on top of it.
+        let base_mem_file_format = MemFileFormat {
+            compression: Some(MemFileCompression::Lz4),
+            encrypted: true,
+        };
+        let base_state = MicrovmState {
+            mem_file_format: Some(base_mem_file_format),
+            ..Default::default()
+        };
+        let (base_file, base_reference) = state_to_file(&base_state);
+        let diff_state = MicrovmState {
+            parent: Some(base_reference.clone()),
+            ..Default::default()
+        };
+        let (diff_file, diff_reference) = state_to_file(&diff_state);
+
+        // The diff snapshot being loaded.
+        let state = MicrovmState {
+            parent: Some(diff_reference),
+            ..Default::default()
+        };
+        assert_eq!(
+            validate_parent_layers(&state, &[layer(&base_file), layer(&diff_file)]).unwrap(),
+            Some(base_mem_file_format)
+        );
+        // The memory file is expected to hold all of the memory without parent layers.
+        assert_eq!(validate_parent_layers(&state, &[]).unwrap(), None);
+
+        assert!(matches!(
+            validate_parent_layers(&state, &[layer(&diff_file)]),
+            Err(SnapshotLayersError::Incomplete)
+        ));
+        assert!(matches!(
+            validate_parent_layers(&state, &[layer(&diff_file), layer(&base_file)]),
+            Err(SnapshotLayersError::ChecksumMismatch(path)) if path == base_file.as_path()
+        ));
+        assert!(matches!(
+            validate_parent_layers(&base_state, &[layer(&base_file)]),
+            Err(SnapshotLayersError::Incomplete)
+        ));
+
+        let mut resized_state = MicrovmState {
+            parent: Some(base_reference),
+            ..Default::default()
+        };
+        resized_state
+            .vm_state
+            .memory
+            .regions
+            .push(GuestMemoryRegionState {
+                base_address: 0,
+                size: 0x1000,
+            });
+        assert!(matches!(
+            validate_parent_layers(&resized_state, &[layer(&base_file)]),
+            Err(SnapshotLayersError::MemoryLayout(_))
+        ));
+
+        let missing_file = TempFile::new().unwrap();
+        let missing_layer = layer(&missing_file);
+        drop(missing_file);
+        assert!(matches!(
+            validate_parent_layers(&state, &[layer(&base_file), missing_layer]),
+            Err(SnapshotLayersError::Load(..))
+        ));
+    }
+
+    #[test]
+    fn test_guest_memory_from_encoded_file() {
+        let mem_state = GuestMemoryState {
+            regions: vec![GuestMemoryRegionState {
+                base_address: 0,
+                size: 0x20000,
+            }],
+        };
+        let mem = multi_region_mem(&[(GuestAddress(0), 0x20000)]);
+        mem.write_slice(&[0xab; 0x1000], GuestAddress(0x8000))
+            .unwrap();
+        let key = MemFileKey("AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=".to_string());
+        let encoding = MemFileEncoding {
+            compression: Some(MemFileCompression::Zstd),
+            encryption_key: Some(key.clone()),
+        };
+        let format = MemFileFormat {
+            compression: Some(MemFileCompression::Zstd),
+            encrypted: true,
+        };
+        let mem_file = TempFile::new().unwrap();
+        mem_file::encode_guest_memory(&mem, mem_file.as_file(), &encoding).unwrap();
+
+        let restored = guest_memory_from_file(
+            mem_file.as_path(),
+            Some(format),
+            Some(&key),
+            &[],
+            &mem_state,
+            true,
+            HugePageConfig::None,
+        )
+        .unwrap();
+        let mut buf = [0u8; 0x2000];
+        restored[0]
+            .read_slice(&mut buf, MemoryRegionAddress(0x7000))
+            .unwrap();
+        assert_eq!(buf[..0x1000], [0; 0x1000]);
+        assert_eq!(buf[0x1000..], [0xab; 0x1000]);
+        // Decoding does not dirty the guest memory.
+        assert!(!restored[0].bitmap().dirty_at(0x8000));
+
+        assert!(matches!(
+            guest_memory_from_file(
+                mem_file.as_path(),
+                Some(format),
+                None,
+                &[],
+                &mem_state,
+                false,
+                HugePageConfig::None,
+            ),
+            Err(GuestMemoryFromFileError::MemFile(MemFileError::MissingKey))
+        ));
+        assert!(matches!(
+            guest_memory_from_file(
+                mem_file.as_path(),
+                None,
+                Some(&key),
+                &[],
+                &mem_state,
+                false,
+                HugePageConfig::None,
+            ),
+            Err(GuestMemoryFromFileError::MemFile(
+                MemFileError::NotEncrypted
+            ))
+        ));
+    }
+
     #[test]
     fn test_create_guest_memory() {
         let mem_state = GuestMemoryState {
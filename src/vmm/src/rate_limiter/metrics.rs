@@ -0,0 +1,124 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Defines the metrics system for rate limiter groups.
+//!
+//! # Metrics format
+//! The metrics are flushed in JSON when requested by vmm::logger::metrics::METRICS.write().
+//!
+//! ## JSON example with metrics:
+//! ```json
+//! {
+//!  "rate_limiter_group_grp0": {
+//!     "bytes_count": "SharedIncMetric",
+//!     "ops_count": "SharedIncMetric",
+//!     "throttled_count": "SharedIncMetric",
+//!  }
+//!  ...
+//!  "rate_limiter_group_grpN": {
+//!     "bytes_count": "SharedIncMetric",
+//!     "ops_count": "SharedIncMetric",
+//!     "throttled_count": "SharedIncMetric",
+//!  }
+//! }
+//! ```
+//! Each `rate_limiter_group` field in the example above is a serializable
+//! `RateLimiterGroupMetrics` structure collecting the metrics of the rate limiter group created
+//! through the endpoint "/rate-limiter-groups/{group_id}".
+//! The devices of a group keep reporting their own throttling metrics, so no aggregate is emitted
+//! for the groups.
+//!
+//! # Design
+//! Follows the design of the vhost-user device metrics: the metrics are kept in a map of group id
+//! and corresponding metrics, from which `RateLimiterGroup`s get their entry when created. The
+//! map is accessible from signal handlers to flush the metrics, while the groups are not.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+
+use crate::logger::SharedIncMetric;
+
+/// map of rate limiter group id and metrics
+/// this should be protected by a lock before accessing.
+#[derive(Debug)]
+pub struct RateLimiterGroupMetricsPerGroup {
+    /// used to access per rate limiter group metrics
+    pub metrics: BTreeMap<String, Arc<RateLimiterGroupMetrics>>,
+}
+
+impl RateLimiterGroupMetricsPerGroup {
+    /// Allocate `RateLimiterGroupMetrics` for rate limiter group having
+    /// id `group_id`. Also, allocate only if it doesn't
+    /// exist to avoid overwriting previously allocated data.
+    /// lock is always initialized so it is safe the unwrap
+    /// the lock without a check.
+    pub fn alloc(group_id: String) -> Arc<RateLimiterGroupMetrics> {
+        Arc::clone(
+            METRICS
+                .write()
+                .unwrap()
+                .metrics
+                .entry(group_id)
+                .or_insert_with(|| Arc::new(RateLimiterGroupMetrics::default())),
+        )
+    }
+}
+
+/// Pool of rate limiter group metrics behind a lock to
+/// keep things thread safe. Since the lock is initialized here
+/// it is safe to unwrap it without any check.
+static METRICS: RwLock<RateLimiterGroupMetricsPerGroup> =
+    RwLock::new(RateLimiterGroupMetricsPerGroup {
+        metrics: BTreeMap::new(),
+    });
+
+/// This function facilitates serialization of rate limiter group metrics.
+pub fn flush_metrics<S: Serializer>(serializer: S) -> Result<S::Ok, S::Error> {
+    let group_metrics = METRICS.read().unwrap();
+    let metrics_len = group_metrics.metrics.len();
+    let mut seq = serializer.serialize_map(Some(metrics_len))?;
+
+    for (name, metrics) in group_metrics.metrics.iter() {
+        let groupn = format!("rate_limiter_group_{}", name);
+        seq.serialize_entry(&groupn, metrics)?;
+    }
+    seq.end()
+}
+
+/// Rate limiter group associated metrics.
+#[derive(Debug, Default, Serialize)]
+pub struct RateLimiterGroupMetrics {
+    /// Number of bytes consumed from the bandwidth bucket of the group.
+    pub bytes_count: SharedIncMetric,
+    /// Number of operations consumed from the ops bucket of the group.
+    pub ops_count: SharedIncMetric,
+    /// Number of times a device of the group was throttled because the group ran out of tokens.
+    pub throttled_count: SharedIncMetric,
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::logger::IncMetric;
+
+    #[test]
+    fn test_rate_limiter_group_metrics() {
+        let group_metrics = RateLimiterGroupMetricsPerGroup::alloc(String::from("grpN"));
+        group_metrics.throttled_count.inc();
+        assert_eq!(group_metrics.throttled_count.count(), 1);
+
+        // Allocating the metrics of an existing group returns the same metrics.
+        let group_metrics_again = RateLimiterGroupMetricsPerGroup::alloc(String::from("grpN"));
+        assert!(Arc::ptr_eq(&group_metrics, &group_metrics_again));
+
+        let group_metrics_backup = RateLimiterGroupMetrics::default();
+        group_metrics_backup.throttled_count.inc();
+        let group_metrics_global =
+            serde_json::to_string(&METRICS.read().unwrap().metrics.get("grpN")).unwrap();
+        let group_metrics_local = serde_json::to_string(&group_metrics_backup).unwrap();
+        assert_eq!(group_metrics_local, group_metrics_global);
+    }
+}
@@ -2,11 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::{fmt, io};
 
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 
+use self::metrics::{RateLimiterGroupMetrics, RateLimiterGroupMetricsPerGroup};
+use crate::logger::IncMetric;
+
+pub mod metrics;
 pub mod persist;
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -272,7 +277,7 @@ pub enum TokenType {
 }
 
 /// Enum that describes the type of token bucket update.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum BucketUpdate {
     /// No Update - same as before.
     None,
@@ -282,6 +287,91 @@ pub enum BucketUpdate {
     Update(TokenBucket),
 }
 
+/// Token buckets shared by the rate limiters of all the devices that belong to the same rate
+/// limiter group, so that the devices draw from a common budget.
+///
+/// Each rate limiter of the group keeps its own timer, which it arms when the shared buckets run
+/// out of tokens, so the devices of the group are unblocked independently of each other.
+#[derive(Debug)]
+pub struct RateLimiterGroup {
+    id: String,
+    bandwidth: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+    metrics: Arc<RateLimiterGroupMetrics>,
+}
+
+impl RateLimiterGroup {
+    /// Creates a new rate limiter group with the given id and token buckets. A group without a
+    /// bucket for a token type does not limit that token type.
+    pub fn new(id: String, bandwidth: Option<TokenBucket>, ops: Option<TokenBucket>) -> Self {
+        let metrics = RateLimiterGroupMetricsPerGroup::alloc(id.clone());
+        RateLimiterGroup {
+            id,
+            bandwidth,
+            ops,
+            metrics,
+        }
+    }
+
+    /// Returns the id of the group.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Attempts to consume tokens from the bucket of `token_type`. Returns `None` if the group
+    /// does not limit `token_type`, or the refill time of the bucket and the outcome of the
+    /// reduction.
+    fn reduce(&mut self, tokens: u64, token_type: &TokenType) -> Option<(u64, BucketReduction)> {
+        let bucket = match token_type {
+            TokenType::Bytes => self.bandwidth.as_mut()?,
+            TokenType::Ops => self.ops.as_mut()?,
+        };
+        let reduction = bucket.reduce(tokens);
+        match (&reduction, token_type) {
+            (BucketReduction::Failure, _) => self.metrics.throttled_count.inc(),
+            (_, TokenType::Bytes) => self.metrics.bytes_count.add(tokens),
+            (_, TokenType::Ops) => self.metrics.ops_count.add(tokens),
+        }
+        Some((bucket.refill_time_ms(), reduction))
+    }
+
+    /// Adds tokens to the bucket of `token_type`, if the group limits `token_type`.
+    fn replenish(&mut self, tokens: u64, token_type: &TokenType) {
+        let bucket = match token_type {
+            TokenType::Bytes => self.bandwidth.as_mut(),
+            TokenType::Ops => self.ops.as_mut(),
+        };
+        if let Some(bucket) = bucket {
+            bucket.force_replenish(tokens);
+        }
+    }
+
+    /// Updates the parameters of the token buckets of the group. The update applies to all the
+    /// devices of the group.
+    pub fn update_buckets(&mut self, bytes: BucketUpdate, ops: BucketUpdate) {
+        match bytes {
+            BucketUpdate::Disabled => self.bandwidth = None,
+            BucketUpdate::Update(tb) => self.bandwidth = Some(tb),
+            BucketUpdate::None => (),
+        };
+        match ops {
+            BucketUpdate::Disabled => self.ops = None,
+            BucketUpdate::Update(tb) => self.ops = Some(tb),
+            BucketUpdate::None => (),
+        };
+    }
+
+    /// Returns an immutable view of the bandwidth token bucket of the group.
+    pub fn bandwidth(&self) -> Option<&TokenBucket> {
+        self.bandwidth.as_ref()
+    }
+
+    /// Returns an immutable view of the ops token bucket of the group.
+    pub fn ops(&self) -> Option<&TokenBucket> {
+        self.ops.as_ref()
+    }
+}
+
 /// Rate Limiter that works on both bandwidth and ops/s limiting.
 ///
 /// Bandwidth (bytes/s) and ops/s limiting can be used at the same time or individually.
@@ -297,9 +387,13 @@ pub enum BucketUpdate {
 /// RateLimiters will generate events on the FDs provided by their `AsRawFd` trait
 /// implementation. These events are meant to be consumed by the user of this struct.
 /// On each such event, the user must call the `event_handler()` method.
+///
+/// A RateLimiter that belongs to a `RateLimiterGroup` consumes tokens from the buckets of the
+/// group instead of its own.
 pub struct RateLimiter {
     bandwidth: Option<TokenBucket>,
     ops: Option<TokenBucket>,
+    group: Option<Arc<Mutex<RateLimiterGroup>>>,
 
     timer_fd: TimerFd,
     // Internal flag that quickly determines timer state.
@@ -308,7 +402,9 @@ pub struct RateLimiter {
 
 impl PartialEq for RateLimiter {
     fn eq(&self, other: &RateLimiter) -> bool {
-        self.bandwidth == other.bandwidth && self.ops == other.ops
+        self.bandwidth == other.bandwidth
+            && self.ops == other.ops
+            && self.group_id() == other.group_id()
     }
 }
 
@@ -316,8 +412,10 @@ impl fmt::Debug for RateLimiter {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
             f,
-            "RateLimiter {{ bandwidth: {:?}, ops: {:?} }}",
-            self.bandwidth, self.ops
+            "RateLimiter {{ bandwidth: {:?}, ops: {:?}, group: {:?} }}",
+            self.bandwidth,
+            self.ops,
+            self.group_id()
         )
     }
 }
@@ -372,11 +470,24 @@ impl RateLimiter {
         Ok(RateLimiter {
             bandwidth: bytes_token_bucket,
             ops: ops_token_bucket,
+            group: None,
             timer_fd,
             timer_active: false,
         })
     }
 
+    /// Creates a new Rate Limiter that consumes tokens from the buckets of `group`, which are
+    /// shared with the other rate limiters of the group.
+    ///
+    /// # Errors
+    ///
+    /// If the timerfd creation fails, an error is returned.
+    pub fn with_group(group: Arc<Mutex<RateLimiterGroup>>) -> io::Result<Self> {
+        let mut rate_limiter = RateLimiter::new(0, 0, 0, 0, 0, 0)?;
+        rate_limiter.group = Some(group);
+        Ok(rate_limiter)
+    }
+
     // Arm the timer of the rate limiter with the provided `TimerState`.
     fn activate_timer(&mut self, timer_state: TimerState) {
         // Register the timer; don't care about its previous state
@@ -393,15 +504,23 @@ impl RateLimiter {
             return false;
         }
 
-        // Identify the required token bucket.
-        let token_bucket = match token_type {
-            TokenType::Bytes => self.bandwidth.as_mut(),
-            TokenType::Ops => self.ops.as_mut(),
+        // Try to consume from the required token bucket, which is the one of the group if this
+        // limiter belongs to a group.
+        let reduction = match &self.group {
+            Some(group) => group
+                .lock()
+                .expect("Poisoned lock")
+                .reduce(tokens, &token_type),
+            None => {
+                let token_bucket = match token_type {
+                    TokenType::Bytes => self.bandwidth.as_mut(),
+                    TokenType::Ops => self.ops.as_mut(),
+                };
+                token_bucket.map(|bucket| (bucket.refill_time_ms(), bucket.reduce(tokens)))
+            }
         };
-        // Try to consume from the token bucket.
-        if let Some(bucket) = token_bucket {
-            let refill_time = bucket.refill_time_ms();
-            match bucket.reduce(tokens) {
+        if let Some((refill_time, reduction)) = reduction {
+            match reduction {
                 // When we report budget is over, there will be no further calls here,
                 // register a timer to replenish the bucket and resume processing;
                 // make sure there is only one running timer for this limiter.
@@ -442,6 +561,13 @@ impl RateLimiter {
     /// Can be used to *manually* add tokens to a bucket. Useful for reverting a
     /// `consume()` if needed.
     pub fn manual_replenish(&mut self, tokens: u64, token_type: TokenType) {
+        if let Some(group) = &self.group {
+            group
+                .lock()
+                .expect("Poisoned lock")
+                .replenish(tokens, &token_type);
+            return;
+        }
         // Identify the required token bucket.
         let token_bucket = match token_type {
             TokenType::Bytes => self.bandwidth.as_mut(),
@@ -504,6 +630,18 @@ impl RateLimiter {
     pub fn ops(&self) -> Option<&TokenBucket> {
         self.ops.as_ref()
     }
+
+    /// Returns the group this rate limiter belongs to, if any.
+    pub fn group(&self) -> Option<&Arc<Mutex<RateLimiterGroup>>> {
+        self.group.as_ref()
+    }
+
+    /// Returns the id of the group this rate limiter belongs to, if any.
+    pub fn group_id(&self) -> Option<String> {
+        self.group
+            .as_ref()
+            .map(|group| group.lock().expect("Poisoned lock").id().to_string())
+    }
 }
 
 impl AsRawFd for RateLimiter {
@@ -1193,7 +1331,7 @@ pub(crate) mod tests {
         assert_eq!(
             format!("{:?}", l),
             format!(
-                "RateLimiter {{ bandwidth: {:?}, ops: {:?} }}",
+                "RateLimiter {{ bandwidth: {:?}, ops: {:?}, group: None }}",
                 l.bandwidth(),
                 l.ops()
             ),
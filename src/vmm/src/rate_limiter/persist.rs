@@ -9,7 +9,7 @@ use super::*;
 use crate::snapshot::Persist;
 
 /// State for saving a TokenBucket.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TokenBucketState {
     size: u64,
     one_time_burst: u64,
@@ -56,21 +56,34 @@ impl Persist<'_> for TokenBucket {
 pub struct RateLimiterState {
     ops: Option<TokenBucketState>,
     bandwidth: Option<TokenBucketState>,
+    group_id: Option<String>,
 }
 
-impl Persist<'_> for RateLimiter {
+impl<'a> Persist<'a> for RateLimiter {
     type State = RateLimiterState;
-    type ConstructorArgs = ();
+    /// The rate limiter groups, one of which the restored rate limiter may belong to.
+    type ConstructorArgs = &'a [Arc<Mutex<RateLimiterGroup>>];
     type Error = io::Error;
 
     fn save(&self) -> Self::State {
         RateLimiterState {
             ops: self.ops.as_ref().map(|ops| ops.save()),
             bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+            group_id: self.group_id(),
         }
     }
 
-    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+    fn restore(groups: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+        let group = match state.group_id.as_deref() {
+            Some(group_id) => Some(
+                groups
+                    .iter()
+                    .find(|group| group.lock().expect("Poisoned lock").id() == group_id)
+                    .cloned()
+                    .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?,
+            ),
+            None => None,
+        };
         let rate_limiter = RateLimiter {
             ops: if let Some(ops) = state.ops.as_ref() {
                 Some(TokenBucket::restore((), ops)?)
@@ -82,6 +95,7 @@ impl Persist<'_> for RateLimiter {
             } else {
                 None
             },
+            group,
             timer_fd: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
             timer_active: false,
         };
@@ -90,6 +104,44 @@ impl Persist<'_> for RateLimiter {
     }
 }
 
+/// State for saving a RateLimiterGroup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimiterGroupState {
+    id: String,
+    ops: Option<TokenBucketState>,
+    bandwidth: Option<TokenBucketState>,
+}
+
+impl Persist<'_> for RateLimiterGroup {
+    type State = RateLimiterGroupState;
+    type ConstructorArgs = ();
+    type Error = io::Error;
+
+    fn save(&self) -> Self::State {
+        RateLimiterGroupState {
+            id: self.id.clone(),
+            ops: self.ops.as_ref().map(|ops| ops.save()),
+            bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+        }
+    }
+
+    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+        Ok(RateLimiterGroup::new(
+            state.id.clone(),
+            state
+                .bandwidth
+                .as_ref()
+                .map(|bw| TokenBucket::restore((), bw))
+                .transpose()?,
+            state
+                .ops
+                .as_ref()
+                .map(|ops| TokenBucket::restore((), ops))
+                .transpose()?,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -136,8 +188,8 @@ mod tests {
         let mut rate_limiter = RateLimiter::new(100, 0, refill_time, 10, 0, refill_time).unwrap();
 
         // Check that RateLimiter restores correctly if untouched.
-        let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+        let restored_rate_limiter = RateLimiter::restore(&[], &rate_limiter.save())
+            .expect("Unable to restore rate limiter");
 
         assert!(
             rate_limiter
@@ -159,8 +211,8 @@ mod tests {
         // Check that RateLimiter restores correctly after partially consuming tokens.
         rate_limiter.consume(10, TokenType::Bytes);
         rate_limiter.consume(10, TokenType::Ops);
-        let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+        let restored_rate_limiter = RateLimiter::restore(&[], &rate_limiter.save())
+            .expect("Unable to restore rate limiter");
 
         assert!(
             rate_limiter
@@ -181,8 +233,8 @@ mod tests {
 
         // Check that RateLimiter restores correctly after totally consuming tokens.
         rate_limiter.consume(1000, TokenType::Bytes);
-        let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+        let restored_rate_limiter = RateLimiter::restore(&[], &rate_limiter.save())
+            .expect("Unable to restore rate limiter");
 
         assert!(
             rate_limiter
@@ -203,7 +255,7 @@ mod tests {
             .save(&mut mem.as_mut_slice())
             .unwrap();
         let restored_rate_limiter = RateLimiter::restore(
-            (),
+            &[],
             &Snapshot::load_without_crc_check(mem.as_slice())
                 .unwrap()
                 .data,
@@ -223,4 +275,46 @@ mod tests {
                 .partial_eq(restored_rate_limiter.bandwidth().unwrap())
         );
     }
+
+    #[test]
+    fn test_rate_limiter_group_persistence() {
+        let group = Arc::new(Mutex::new(RateLimiterGroup::new(
+            String::from("persist_grp"),
+            TokenBucket::new(100, 0, 100_000),
+            None,
+        )));
+        let mut rate_limiter = RateLimiter::with_group(group.clone()).unwrap();
+        rate_limiter.consume(10, TokenType::Bytes);
+
+        // Check that RateLimiterGroup restores correctly after partially consuming tokens.
+        let restored_group = RateLimiterGroup::restore((), &group.lock().unwrap().save()).unwrap();
+        assert_eq!(restored_group.id(), "persist_grp");
+        assert!(
+            group
+                .lock()
+                .unwrap()
+                .bandwidth()
+                .unwrap()
+                .partial_eq(restored_group.bandwidth().unwrap())
+        );
+        assert!(restored_group.ops().is_none());
+
+        // Check that the RateLimiter is attached to the restored group.
+        let restored_group = Arc::new(Mutex::new(restored_group));
+        let restored_rate_limiter =
+            RateLimiter::restore(&[restored_group.clone()], &rate_limiter.save()).unwrap();
+        assert!(Arc::ptr_eq(
+            restored_rate_limiter.group().unwrap(),
+            &restored_group
+        ));
+        assert!(restored_rate_limiter.bandwidth().is_none());
+
+        // Restoring fails if the group of the RateLimiter is missing.
+        assert_eq!(
+            RateLimiter::restore(&[], &rate_limiter.save())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::NotFound
+        );
+    }
 }
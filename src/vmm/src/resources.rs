@@ -9,12 +9,16 @@ use serde::{Deserialize, Serialize};
 
 use crate::cpu_config::templates::CustomCpuTemplate;
 use crate::device_manager::persist::SharedDeviceType;
+use crate::devices::virtio::net::Net;
 use crate::logger::info;
 use crate::mmds;
 use crate::mmds::data_store::{Mmds, MmdsVersion};
 use crate::mmds::ns::MmdsNetworkStack;
+use crate::mmds::policy::MmdsPolicy;
+use crate::rate_limiter::{RateLimiter, RateLimiterGroup};
 use crate::utils::mib_to_bytes;
 use crate::utils::net::ipv4addr::is_link_local_valid;
+use crate::utils::net::ipv6addr::is_link_local_or_unique_local;
 use crate::vmm_config::balloon::*;
 use crate::vmm_config::boot_source::{
     BootConfig, BootSource, BootSourceConfig, BootSourceConfigError,
@@ -25,13 +29,17 @@ use crate::vmm_config::instance_info::InstanceInfo;
 use crate::vmm_config::machine_config::{
     HugePageConfig, MachineConfig, MachineConfigError, MachineConfigUpdate,
 };
+use crate::vmm_config::memory_hotplug::{MemoryHotplugConfig, MemoryHotplugConfigError};
 use crate::vmm_config::metrics::{MetricsConfig, MetricsConfigError, init_metrics};
 use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::*;
-use crate::vmm_config::serial::SerialConfig;
+use crate::vmm_config::rate_limiter_group::{
+    RateLimiterGroupBuilder, RateLimiterGroupConfig, RateLimiterGroupError,
+};
+use crate::vmm_config::serial::{SerialConfig, SerialConfigError};
 use crate::vmm_config::vsock::*;
 use crate::vstate::memory;
-use crate::vstate::memory::{GuestRegionMmap, MemoryError};
+use crate::vstate::memory::{GuestAddress, GuestRegionMmap, MemoryError};
 
 /// Errors encountered when configuring microVM resources.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -62,6 +70,10 @@ pub enum ResourcesError {
     VsockDevice(#[from] VsockConfigError),
     /// Entropy device error: {0}
     EntropyDevice(#[from] EntropyDeviceError),
+    /// Memory hotplug config error: {0}
+    MemoryHotplugConfig(#[from] MemoryHotplugConfigError),
+    /// Serial config error: {0}
+    SerialConfig(#[from] SerialConfigError),
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -85,8 +97,11 @@ pub struct VmmConfig {
     mmds_config: Option<MmdsConfig>,
     #[serde(default)]
     network_interfaces: Vec<NetworkInterfaceConfig>,
+    #[serde(default)]
+    rate_limiter_groups: Vec<RateLimiterGroupConfig>,
     vsock: Option<VsockDeviceConfig>,
     entropy: Option<EntropyDeviceConfig>,
+    memory_hotplug: Option<MemoryHotplugConfig>,
     #[serde(skip)]
     serial_config: Option<SerialConfig>,
 }
@@ -99,6 +114,8 @@ pub struct VmResources {
     pub machine_config: MachineConfig,
     /// The boot source spec (contains both config and builder) for this microVM.
     pub boot_source: BootSource,
+    /// The rate limiter groups shared by the block and network devices.
+    pub rate_limiter_groups: RateLimiterGroupBuilder,
     /// The block devices.
     pub block: BlockBuilder,
     /// The vsock device.
@@ -109,6 +126,8 @@ pub struct VmResources {
     pub net_builder: NetBuilder,
     /// The entropy device builder.
     pub entropy: EntropyDeviceBuilder,
+    /// The memory hotplug configuration.
+    pub memory_hotplug: Option<MemoryHotplugConfig>,
     /// The optional Mmds data store.
     // This is initialised on demand (if ever used), so that we don't allocate it unless it's
     // actually used.
@@ -119,8 +138,8 @@ pub struct VmResources {
     pub boot_timer: bool,
     /// Whether or not to use PCIe transport for VirtIO devices.
     pub pci_enabled: bool,
-    /// Where serial console output should be written to
-    pub serial_out_path: Option<PathBuf>,
+    /// Where serial console output should be written to, or read from.
+    pub serial_config: SerialConfig,
 }
 
 impl VmResources {
@@ -166,6 +185,11 @@ impl VmResources {
 
         resources.build_boot_source(vmm_config.boot_source)?;
 
+        // The rate limiter groups need to exist before the devices referring to them.
+        for group_config in vmm_config.rate_limiter_groups.into_iter() {
+            resources.set_rate_limiter_group(group_config);
+        }
+
         for drive_config in vmm_config.drives.into_iter() {
             resources.set_block_device(drive_config)?;
         }
@@ -198,8 +222,12 @@ impl VmResources {
             resources.build_entropy_device(entropy_device_config)?;
         }
 
+        if let Some(memory_hotplug_config) = vmm_config.memory_hotplug {
+            resources.set_memory_hotplug_config(memory_hotplug_config)?;
+        }
+
         if let Some(serial_cfg) = vmm_config.serial_config {
-            resources.serial_out_path = serial_cfg.serial_out_path;
+            resources.set_serial_config(serial_cfg)?;
         }
 
         Ok(resources)
@@ -247,6 +275,17 @@ impl VmResources {
             SharedDeviceType::Entropy(entropy) => {
                 self.entropy.set_device(entropy);
             }
+            SharedDeviceType::VirtioMem(virtio_mem) => {
+                self.memory_hotplug = Some(MemoryHotplugConfig::from(
+                    &*virtio_mem.lock().expect("Poisoned lock"),
+                ));
+
+                if self.machine_config.huge_pages != HugePageConfig::None {
+                    return Err(ResourcesError::MemoryHotplugConfig(
+                        MemoryHotplugConfigError::HugePages,
+                    ));
+                }
+            }
         }
 
         Ok(())
@@ -281,6 +320,10 @@ impl VmResources {
         if self.balloon.get().is_some() && updated.huge_pages != HugePageConfig::None {
             return Err(MachineConfigError::BalloonAndHugePages);
         }
+
+        if self.memory_hotplug.is_some() && updated.huge_pages != HugePageConfig::None {
+            return Err(MachineConfigError::MemoryHotplugAndHugePages);
+        }
         self.machine_config = updated;
 
         Ok(())
@@ -306,7 +349,9 @@ impl VmResources {
                 version: mmds_guard.version(),
                 network_interfaces: vec![],
                 ipv4_address: None,
+                ipv6_address: None,
                 imds_compat: mmds_guard.imds_compat(),
+                policy: mmds_guard.policy().clone(),
             };
 
             for net_dev in net_devs_with_mmds {
@@ -316,7 +361,9 @@ impl VmResources {
                 if inner_mmds_config.ipv4_address.is_none() {
                     // Safe to unwrap the mmds_ns as the filter() explicitly checks for
                     // its existence.
-                    inner_mmds_config.ipv4_address = Some(net.mmds_ns().unwrap().ipv4_addr());
+                    let mmds_ns = net.mmds_ns().unwrap();
+                    inner_mmds_config.ipv4_address = Some(mmds_ns.ipv4_addr());
+                    inner_mmds_config.ipv6_address = mmds_ns.ipv6_addr();
                 }
             }
 
@@ -344,6 +391,28 @@ impl VmResources {
         self.balloon.set(config)
     }
 
+    /// Sets the memory hotplug configuration used when the VM starts.
+    pub fn set_memory_hotplug_config(
+        &mut self,
+        config: MemoryHotplugConfig,
+    ) -> Result<(), MemoryHotplugConfigError> {
+        config.validate()?;
+
+        if self.machine_config.huge_pages != HugePageConfig::None {
+            return Err(MemoryHotplugConfigError::HugePages);
+        }
+
+        self.memory_hotplug = Some(config);
+        Ok(())
+    }
+
+    /// Sets the serial console configuration.
+    pub fn set_serial_config(&mut self, config: SerialConfig) -> Result<(), SerialConfigError> {
+        config.validate()?;
+        self.serial_config = config;
+        Ok(())
+    }
+
     /// Obtains the boot source hooks (kernel fd, command line creation and validation).
     pub fn build_boot_source(
         &mut self,
@@ -357,6 +426,26 @@ impl VmResources {
         Ok(())
     }
 
+    /// Inserts a rate limiter group that block and network devices can share.
+    // If a group with the same id already exists, the devices in it get the new token buckets.
+    pub fn set_rate_limiter_group(&mut self, config: RateLimiterGroupConfig) {
+        self.rate_limiter_groups.insert(config)
+    }
+
+    // Looks up the rate limiter group a device refers to, if any. A device cannot be part of a
+    // rate limiter group and have its own rate limiter at the same time.
+    fn device_rate_limiter_group(
+        &self,
+        group_id: Option<&str>,
+        has_rate_limiter: bool,
+    ) -> Result<Option<Arc<Mutex<RateLimiterGroup>>>, RateLimiterGroupError> {
+        match group_id {
+            Some(_) if has_rate_limiter => Err(RateLimiterGroupError::ConflictingRateLimiters),
+            Some(group_id) => self.rate_limiter_groups.get(group_id).map(Some),
+            None => Ok(None),
+        }
+    }
+
     /// Inserts a block to be attached when the VM starts.
     // Only call this function as part of user configuration.
     // If the drive_id does not exist, a new Block Device Config is added to the list.
@@ -364,16 +453,62 @@ impl VmResources {
         &mut self,
         block_device_config: BlockDeviceConfig,
     ) -> Result<(), DriveError> {
-        self.block.insert(block_device_config)
+        let rate_limiter = self
+            .device_rate_limiter_group(
+                block_device_config.rate_limiter_group.as_deref(),
+                block_device_config.rate_limiter.is_some(),
+            )
+            .map_err(DriveError::RateLimiterGroup)?
+            .map(RateLimiter::with_group)
+            .transpose()
+            .map_err(DriveError::CreateRateLimiter)?;
+        let drive_id = block_device_config.drive_id.clone();
+
+        self.block.insert(block_device_config)?;
+        if let Some(rate_limiter) = rate_limiter {
+            // Safe to unwrap because we've just inserted the device.
+            self.block
+                .get(&drive_id)
+                .unwrap()
+                .lock()
+                .expect("Poisoned lock")
+                .set_rate_limiter(rate_limiter)
+                .map_err(DriveError::CreateBlockDevice)?;
+        }
+        Ok(())
     }
 
     /// Builds a network device to be attached when the VM starts.
     pub fn build_net_device(
         &mut self,
         body: NetworkInterfaceConfig,
-    ) -> Result<(), NetworkInterfaceError> {
-        let _ = self.net_builder.build(body)?;
-        Ok(())
+    ) -> Result<Arc<Mutex<Net>>, NetworkInterfaceError> {
+        let rx_group = self
+            .device_rate_limiter_group(
+                body.rx_rate_limiter_group.as_deref(),
+                body.rx_rate_limiter.is_some(),
+            )
+            .map_err(NetworkInterfaceError::RateLimiterGroup)?;
+        let tx_group = self
+            .device_rate_limiter_group(
+                body.tx_rate_limiter_group.as_deref(),
+                body.tx_rate_limiter.is_some(),
+            )
+            .map_err(NetworkInterfaceError::RateLimiterGroup)?;
+
+        let net = self.net_builder.build(body)?;
+        if rx_group.is_some() || tx_group.is_some() {
+            let result = net
+                .lock()
+                .expect("Poisoned lock")
+                .set_rate_limiter_groups(rx_group, tx_group);
+            if let Err(err) = result {
+                let iface_id = net.lock().expect("Poisoned lock").id().clone();
+                self.net_builder.remove(&iface_id);
+                return Err(NetworkInterfaceError::CreateRateLimiter(err));
+            }
+        }
+        Ok(net)
     }
 
     /// Sets a vsock device to be attached when the VM starts.
@@ -395,8 +530,14 @@ impl VmResources {
         config: MmdsConfig,
         instance_id: &str,
     ) -> Result<(), MmdsConfigError> {
+        config.policy.validate()?;
         self.set_mmds_network_stack_config(&config)?;
-        self.set_mmds_basic_config(config.version, config.imds_compat, instance_id)?;
+        self.set_mmds_basic_config(
+            config.version,
+            config.imds_compat,
+            config.policy,
+            instance_id,
+        )?;
 
         Ok(())
     }
@@ -406,11 +547,13 @@ impl VmResources {
         &mut self,
         version: MmdsVersion,
         imds_compat: bool,
+        policy: MmdsPolicy,
         instance_id: &str,
     ) -> Result<(), MmdsConfigError> {
         let mut mmds_guard = self.locked_mmds_or_default()?;
         mmds_guard.set_version(version);
         mmds_guard.set_imds_compat(imds_compat);
+        mmds_guard.set_policy(policy);
         mmds_guard.set_aad(instance_id);
 
         Ok(())
@@ -429,6 +572,15 @@ impl VmResources {
             _ => Err(MmdsConfigError::InvalidIpv4Addr),
         }?;
 
+        // Check IPv6 address validity. There is no default IPv6 address, as the MMDS is only
+        // reachable over IPv6 when explicitly requested.
+        let ipv6_addr = match config.ipv6_addr() {
+            Some(ipv6_addr) if !is_link_local_or_unique_local(ipv6_addr) => {
+                Err(MmdsConfigError::InvalidIpv6Addr)
+            }
+            ipv6_addr => Ok(ipv6_addr),
+        }?;
+
         let network_interfaces = config.network_interfaces();
         // Ensure that at least one network ID is specified.
         if network_interfaces.is_empty() {
@@ -448,13 +600,13 @@ impl VmResources {
         // Safe to unwrap because we've just made sure that it's initialised.
         let mmds = self.mmds_or_default()?.clone();
 
-        // Create `MmdsNetworkStack` and configure the IPv4 address for
+        // Create `MmdsNetworkStack` and configure the IP addresses for
         // existing built network devices whose names are defined in the
         // network interface ID list.
         for net_device in self.net_builder.iter_mut() {
             let mut net_device_lock = net_device.lock().expect("Poisoned lock");
             if network_interfaces.contains(net_device_lock.id()) {
-                net_device_lock.configure_mmds_network_stack(ipv4_addr, mmds.clone());
+                net_device_lock.configure_mmds_network_stack(ipv4_addr, ipv6_addr, mmds.clone());
             } else {
                 net_device_lock.disable_mmds_network_stack();
             }
@@ -468,6 +620,30 @@ impl VmResources {
     /// If vhost-user-blk devices are in use, allocates memfd-backed shared memory, otherwise
     /// prefers anonymous memory for performance reasons.
     pub fn allocate_guest_memory(&self) -> Result<Vec<GuestRegionMmap>, MemoryError> {
+        let regions =
+            crate::arch::arch_memory_regions(mib_to_bytes(self.machine_config.mem_size_mib));
+        self.allocate_memory_regions(&regions)
+    }
+
+    /// Allocates the guest memory backing the memory hotplug region, if memory hotplug is
+    /// configured. The region starts after the memory returned by [`Self::allocate_guest_memory`].
+    pub fn allocate_memory_hotplug_region(&self) -> Result<Option<GuestRegionMmap>, MemoryError> {
+        let Some(config) = self.memory_hotplug.as_ref() else {
+            return Ok(None);
+        };
+
+        let boot_regions =
+            crate::arch::arch_memory_regions(mib_to_bytes(self.machine_config.mem_size_mib));
+        let start = crate::arch::memory_hotplug_start(&boot_regions);
+        let mut regions =
+            self.allocate_memory_regions(&[(start, mib_to_bytes(config.total_size_mib))])?;
+        Ok(regions.pop())
+    }
+
+    fn allocate_memory_regions(
+        &self,
+        regions: &[(GuestAddress, usize)],
+    ) -> Result<Vec<GuestRegionMmap>, MemoryError> {
         let vhost_user_device_used = self
             .block
             .devices
@@ -483,17 +659,15 @@ impl VmResources {
         // because that would require running a backend process. If in the future we converge to
         // a single way of backing guest memory for vhost-user and non-vhost-user cases,
         // that would not be worth the effort.
-        let regions =
-            crate::arch::arch_memory_regions(mib_to_bytes(self.machine_config.mem_size_mib));
         if vhost_user_device_used {
             memory::memfd_backed(
-                regions.as_ref(),
+                regions,
                 self.machine_config.track_dirty_pages,
                 self.machine_config.huge_pages,
             )
         } else {
             memory::anonymous(
-                regions.into_iter(),
+                regions.iter().copied(),
                 self.machine_config.track_dirty_pages,
                 self.machine_config.huge_pages,
             )
@@ -513,8 +687,10 @@ impl From<&VmResources> for VmmConfig {
             metrics: None,
             mmds_config: resources.mmds_config(),
             network_interfaces: resources.net_builder.configs(),
+            rate_limiter_groups: resources.rate_limiter_groups.configs(),
             vsock: resources.vsock.config(),
             entropy: resources.entropy.config(),
+            memory_hotplug: resources.memory_hotplug.clone(),
             // serial_config is marked serde(skip) so that it doesnt end up in snapshots.
             serial_config: None,
         }
@@ -564,6 +740,9 @@ mod tests {
             guest_mac: Some(MacAddr::from_str("01:23:45:67:89:0a").unwrap()),
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
+            rx_rate_limiter_group: None,
+            tx_rate_limiter_group: None,
+            num_queues: None,
         }
     }
 
@@ -586,7 +765,10 @@ mod tests {
                 is_read_only: Some(false),
                 path_on_host: Some(tmp_file.as_path().to_str().unwrap().to_string()),
                 rate_limiter: Some(RateLimiterConfig::default()),
+                rate_limiter_group: None,
                 file_engine_type: None,
+                discard: None,
+                format: None,
 
                 socket: None,
             },
@@ -619,6 +801,7 @@ mod tests {
         VmResources {
             machine_config: MachineConfig::default(),
             boot_source: default_boot_cfg(),
+            rate_limiter_groups: Default::default(),
             block: default_blocks(),
             vsock: Default::default(),
             balloon: Default::default(),
@@ -627,8 +810,9 @@ mod tests {
             boot_timer: false,
             mmds_size_limit: HTTP_MAX_PAYLOAD_SIZE,
             entropy: Default::default(),
+            memory_hotplug: None,
             pci_enabled: false,
-            serial_out_path: None,
+            serial_config: SerialConfig::default(),
         }
     }
 
@@ -1189,17 +1373,20 @@ mod tests {
                             "path_on_host": "{}",
                             "is_root_device": true,
                             "is_read_only": false,
-                            "io_engine": "Sync"
+                            "io_engine": "Sync",
+                            "format": "Raw"
                         }}
                     ],
                     "network-interfaces": [
                         {{
                             "iface_id": "netif1",
-                            "host_dev_name": "hostname9"
+                            "host_dev_name": "hostname9",
+                            "num_queues": 1
                         }},
                         {{
                             "iface_id": "netif2",
-                            "host_dev_name": "hostname10"
+                            "host_dev_name": "hostname10",
+                            "num_queues": 1
                         }}
                     ],
                     "machine-config": {{
@@ -1264,17 +1451,20 @@ mod tests {
                             "path_on_host": "{}",
                             "is_root_device": true,
                             "is_read_only": false,
-                            "io_engine": "Sync"
+                            "io_engine": "Sync",
+                            "format": "Raw"
                         }}
                     ],
                     "network-interfaces": [
                         {{
                             "iface_id": "netif1",
-                            "host_dev_name": "hostname9"
+                            "host_dev_name": "hostname9",
+                            "num_queues": 1
                         }},
                         {{
                             "iface_id": "netif2",
-                            "host_dev_name": "hostname10"
+                            "host_dev_name": "hostname10",
+                            "num_queues": 1
                         }}
                     ],
                     "machine-config": {{
@@ -1324,17 +1514,20 @@ mod tests {
                             "path_on_host": "{}",
                             "is_root_device": true,
                             "is_read_only": false,
-                            "io_engine": "Sync"
+                            "io_engine": "Sync",
+                            "format": "Raw"
                         }}
                     ],
                     "network-interfaces": [
                         {{
                             "iface_id": "netif1",
-                            "host_dev_name": "hostname9"
+                            "host_dev_name": "hostname9",
+                            "num_queues": 1
                         }},
                         {{
                             "iface_id": "netif2",
-                            "host_dev_name": "hostname10"
+                            "host_dev_name": "hostname10",
+                            "num_queues": 1
                         }}
                     ],
                     "machine-config": {{
@@ -1369,6 +1562,7 @@ mod tests {
         let mut vm_resources = default_vm_resources();
         let mut aux_vm_config = MachineConfigUpdate {
             vcpu_count: Some(32),
+            max_vcpus: None,
             mem_size_mib: Some(512),
             smt: Some(false),
             #[cfg(target_arch = "x86_64")]
@@ -1436,6 +1630,9 @@ mod tests {
                 amount_mib: 100,
                 deflate_on_oom: false,
                 stats_polling_interval_s: 0,
+                free_page_hinting: false,
+                free_page_reporting: false,
+                policy: None,
             })
             .unwrap();
         aux_vm_config.mem_size_mib = Some(90);
@@ -1474,6 +1671,9 @@ mod tests {
             amount_mib: 100,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         };
         assert!(vm_resources.balloon.get().is_none());
         vm_resources
@@ -1511,7 +1711,7 @@ mod tests {
             .unwrap();
         let err = vm_resources
             .update_from_restored_device(SharedDeviceType::Balloon(Arc::new(Mutex::new(
-                Balloon::new(128, false, 0, true).unwrap(),
+                Balloon::new(128, false, 0, false, false, true).unwrap(),
             ))))
             .unwrap_err();
         assert!(
@@ -1614,6 +1814,39 @@ mod tests {
         assert_eq!(vm_resources.block.devices.len(), 2);
     }
 
+    #[test]
+    fn test_set_block_device_rate_limiter_group() {
+        let mut vm_resources = default_vm_resources();
+        let (mut block_device_cfg, _file) = default_block_cfg();
+        block_device_cfg.rate_limiter_group = Some("grp0".to_string());
+
+        // The drive cannot have its own rate limiter and be in a group.
+        assert!(matches!(
+            vm_resources.set_block_device(block_device_cfg.clone()),
+            Err(DriveError::RateLimiterGroup(
+                RateLimiterGroupError::ConflictingRateLimiters
+            ))
+        ));
+
+        block_device_cfg.rate_limiter = None;
+        assert!(matches!(
+            vm_resources.set_block_device(block_device_cfg.clone()),
+            Err(DriveError::RateLimiterGroup(
+                RateLimiterGroupError::GroupNotFound(_)
+            ))
+        ));
+
+        vm_resources.set_rate_limiter_group(RateLimiterGroupConfig {
+            group_id: "grp0".to_string(),
+            ..Default::default()
+        });
+        vm_resources.set_block_device(block_device_cfg).unwrap();
+        assert_eq!(
+            vm_resources.block.configs()[0].rate_limiter_group,
+            Some("grp0".to_string())
+        );
+    }
+
     #[test]
     fn test_set_vsock_device() {
         let mut vm_resources = default_vm_resources();
@@ -10,30 +10,42 @@ use utils::time::{ClockType, get_time_us};
 use super::builder::build_and_boot_microvm;
 use super::persist::{create_snapshot, restore_from_snapshot};
 use super::resources::VmResources;
-use super::{Vmm, VmmError};
+use super::{DeviceHotplugError, VcpuHotplugError, Vmm, VmmError};
 use crate::EventManager;
 use crate::builder::StartMicrovmError;
 use crate::cpu_config::templates::{CustomCpuTemplate, GuestConfigError};
+use crate::devices::virtio::generated::virtio_ids;
 use crate::logger::{LoggerConfig, info, warn, *};
+use crate::migration::{
+    ReceiveMigrationError, SendMigrationError, receive_migration, send_migration,
+};
 use crate::mmds::data_store::{self, Mmds};
 use crate::persist::{CreateSnapshotError, RestoreFromSnapshotError, VmInfo};
 use crate::resources::VmmConfig;
 use crate::seccomp::BpfThreadMap;
 use crate::vmm_config::balloon::{
-    BalloonConfigError, BalloonDeviceConfig, BalloonStats, BalloonUpdateConfig,
-    BalloonUpdateStatsConfig,
+    BalloonConfigError, BalloonDeviceConfig, BalloonHintingUpdate, BalloonStats,
+    BalloonUpdateConfig, BalloonUpdateStatsConfig, HintingStatus,
 };
 use crate::vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
+use crate::vmm_config::device_hotplug::DeviceUnplugConfig;
 use crate::vmm_config::drive::{BlockDeviceConfig, BlockDeviceUpdateConfig, DriveError};
 use crate::vmm_config::entropy::{EntropyDeviceConfig, EntropyDeviceError};
 use crate::vmm_config::instance_info::InstanceInfo;
-use crate::vmm_config::machine_config::{MachineConfig, MachineConfigError, MachineConfigUpdate};
+use crate::vmm_config::machine_config::{
+    MachineConfig, MachineConfigError, MachineConfigUpdate, VcpuHotplugUpdate,
+};
+use crate::vmm_config::memory_hotplug::{
+    MemoryHotplugConfig, MemoryHotplugConfigError, MemoryHotplugSizeUpdate, MemoryHotplugStatus,
+};
 use crate::vmm_config::metrics::{MetricsConfig, MetricsConfigError};
+use crate::vmm_config::migration::{MigrationStatus, ReceiveMigrationParams, SendMigrationParams};
 use crate::vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use crate::vmm_config::net::{
     NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
 };
-use crate::vmm_config::serial::SerialConfig;
+use crate::vmm_config::rate_limiter_group::{RateLimiterGroupConfig, RateLimiterGroupError};
+use crate::vmm_config::serial::{SerialConfig, SerialConfigError};
 use crate::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, SnapshotType};
 use crate::vmm_config::vsock::{VsockConfigError, VsockDeviceConfig};
 use crate::vmm_config::{self, RateLimiterUpdate};
@@ -45,8 +57,8 @@ pub enum VmmAction {
     /// Configure the boot source of the microVM using as input the `ConfigureBootSource`. This
     /// action can only be called before the microVM has booted.
     ConfigureBootSource(BootSourceConfig),
-    /// Configure the logger using as input the `LoggerConfig`. This action can only be called
-    /// before the microVM has booted.
+    /// Configure the logger using as input the `LoggerConfig`. After the microVM has booted, this
+    /// action can only update the format and filters of the logger, not its output path.
     ConfigureLogger(LoggerConfig),
     /// Configure the metrics using as input the `MetricsConfig`. This action can only be called
     /// before the microVM has booted.
@@ -60,10 +72,19 @@ pub enum VmmAction {
     GetBalloonConfig,
     /// Get the ballon device latest statistics.
     GetBalloonStats,
+    /// Get the status of free page hinting on the balloon device.
+    GetBalloonHintingStatus,
     /// Get complete microVM configuration in JSON format.
     GetFullVmConfig,
     /// Get MMDS contents.
     GetMMDS,
+    /// Get the current value of the metrics in the OpenMetrics text format.
+    GetMetrics,
+    /// Get the memory hotplug configuration and, after microVM start, the amount of plugged
+    /// memory.
+    GetMemoryHotplugStatus,
+    /// Get the status of the last live migration sent from the microVM.
+    GetMigrationStatus,
     /// Get the machine configuration of the microVM.
     GetVmMachineConfig,
     /// Get microVM instance information.
@@ -73,11 +94,11 @@ pub enum VmmAction {
     /// Flush the metrics. This action can only be called after the logger has been configured.
     FlushMetrics,
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
-    /// input. This action can only be called before the microVM has booted.
+    /// input. After the microVM has booted, this action hotplugs a new block device.
     InsertBlockDevice(BlockDeviceConfig),
     /// Add a new network interface config or update one that already exists using the
-    /// `NetworkInterfaceConfig` as input. This action can only be called before the microVM has
-    /// booted.
+    /// `NetworkInterfaceConfig` as input. After the microVM has booted, this action hotplugs a new
+    /// network interface.
     InsertNetworkDevice(NetworkInterfaceConfig),
     /// Load the microVM state using as input the `LoadSnapshotParams`. This action can only be
     /// called before the microVM has booted. If this action is successful, the loaded microVM will
@@ -91,14 +112,32 @@ pub enum VmmAction {
     PutMMDS(Value),
     /// Configure the guest vCPU features.
     PutCpuConfiguration(CustomCpuTemplate),
+    /// Wait for a microVM migrated from another Firecracker process, using as input the
+    /// `ReceiveMigrationParams`. This action can only be called before the microVM has booted.
+    /// If this action is successful, the received microVM will be in `Paused` state, unless
+    /// resuming it was requested.
+    ReceiveMigration(ReceiveMigrationParams),
     /// Resume the guest, by resuming the microVM VCPUs.
     Resume,
+    /// Live migrate the microVM to another Firecracker process using as input the
+    /// `SendMigrationParams`. This action can only be called after the microVM has booted. If
+    /// this action is successful, the guest memory is sent in the background and the progress
+    /// is reported by `GetMigrationStatus`. Once the migration completes, the microVM is left in
+    /// `Paused` state.
+    SendMigration(SendMigrationParams),
     /// Set the balloon device or update the one that already exists using the
     /// `BalloonDeviceConfig` as input. This action can only be called before the microVM
     /// has booted.
     SetBalloonDevice(BalloonDeviceConfig),
+    /// Set the memory hotplug configuration using `MemoryHotplugConfig` as input. This action can
+    /// only be called before the microVM has booted.
+    SetMemoryHotplugDevice(MemoryHotplugConfig),
     /// Set the MMDS configuration.
     SetMmdsConfiguration(MmdsConfig),
+    /// Set a rate limiter group or update the token buckets of one that already exists using the
+    /// `RateLimiterGroupConfig` as input. This action can only be called before the microVM has
+    /// booted.
+    SetRateLimiterGroup(RateLimiterGroupConfig),
     /// Set the vsock device or update the one that already exists using the
     /// `VsockDeviceConfig` as input. This action can only be called before the microVM has
     /// booted.
@@ -112,15 +151,26 @@ pub enum VmmAction {
     /// driver is listening on the guest end, this can be used to shut down the microVM gracefully.
     #[cfg(target_arch = "x86_64")]
     SendCtrlAltDel,
+    /// Unplug a hotpluggable block device or network interface, after microVM start.
+    UnplugDevice(DeviceUnplugConfig),
     /// Update the balloon size, after microVM start.
     UpdateBalloon(BalloonUpdateConfig),
     /// Update the balloon statistics polling interval, after microVM start.
     UpdateBalloonStatistics(BalloonUpdateStatsConfig),
+    /// Start or stop free page hinting on the balloon device, after microVM start.
+    UpdateBalloonHinting(BalloonHintingUpdate),
     /// Update existing block device properties such as `path_on_host` or `rate_limiter`.
     UpdateBlockDevice(BlockDeviceUpdateConfig),
+    /// Hotplug vCPUs into the microVM, after microVM start.
+    HotplugVcpus(VcpuHotplugUpdate),
+    /// Update the amount of hotpluggable memory the guest should use, after microVM start.
+    UpdateMemoryHotplugSize(MemoryHotplugSizeUpdate),
     /// Update a network interface, after microVM start. Currently, the only updatable properties
     /// are the RX and TX rate limiters.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
+    /// Update the token buckets of an existing rate limiter group. Only the token buckets present
+    /// in the `RateLimiterGroupConfig` are updated.
+    UpdateRateLimiterGroup(RateLimiterGroupConfig),
     /// Update the microVM configuration (memory & vcpu) using `VmUpdateConfig` as input. This
     /// action can only be called before the microVM has booted.
     UpdateMachineConfiguration(MachineConfigUpdate),
@@ -139,6 +189,8 @@ pub enum VmmActionError {
     CreateSnapshot(#[from] CreateSnapshotError),
     /// Configure CPU error: {0}
     ConfigureCpu(#[from] GuestConfigError),
+    /// Device hotplug error: {0}
+    DeviceHotplug(#[from] DeviceHotplugError),
     /// Drive config error: {0}
     DriveConfig(#[from] DriveError),
     /// Entropy device error: {0}
@@ -151,8 +203,14 @@ pub enum VmmActionError {
     Logger(#[from] crate::logger::LoggerUpdateError),
     /// Machine config error: {0}
     MachineConfig(#[from] MachineConfigError),
+    /// Memory hotplug config error: {0}
+    MemoryHotplugConfig(#[from] MemoryHotplugConfigError),
+    /// Memory hotplug update error: {0}
+    MemoryHotplugUpdate(VmmError),
     /// Metrics error: {0}
     Metrics(#[from] MetricsConfigError),
+    /// vCPU hotplug error: {0}
+    VcpuHotplug(#[from] VcpuHotplugError),
     #[from(ignore)]
     /// MMDS error: {0}
     Mmds(#[from] data_store::MmdsDatastoreError),
@@ -163,6 +221,14 @@ pub enum VmmActionError {
     MmdsLimitExceeded(data_store::MmdsDatastoreError),
     /// Network config error: {0}
     NetworkConfig(#[from] NetworkInterfaceError),
+    /// Rate limiter group error: {0}
+    RateLimiterGroup(#[from] RateLimiterGroupError),
+    /// Receive migration error: {0}
+    ReceiveMigration(#[from] ReceiveMigrationError),
+    /// Send migration error: {0}
+    SendMigration(#[from] SendMigrationError),
+    /// Serial config error: {0}
+    SerialConfig(#[from] SerialConfigError),
     /// The requested operation is not supported: {0}
     NotSupported(String),
     /// The requested operation is not supported after starting the microVM.
@@ -184,12 +250,20 @@ pub enum VmmData {
     BalloonConfig(BalloonDeviceConfig),
     /// The latest balloon device statistics.
     BalloonStats(BalloonStats),
+    /// The status of free page hinting on the balloon device.
+    BalloonHintingStatus(HintingStatus),
     /// No data is sent on the channel.
     Empty,
     /// The complete microVM configuration in JSON format.
     FullVmConfig(VmmConfig),
     /// The microVM configuration represented by `VmConfig`.
     MachineConfiguration(MachineConfig),
+    /// The memory hotplug configuration and state.
+    MemoryHotplugStatus(MemoryHotplugStatus),
+    /// The metrics in the OpenMetrics text format.
+    Metrics(String),
+    /// The status of the last live migration.
+    MigrationStatus(MigrationStatus),
     /// Mmds contents.
     MmdsValue(serde_json::Value),
     /// The microVM instance information.
@@ -198,6 +272,16 @@ pub enum VmmData {
     VmmVersion(String),
 }
 
+/// Returns the current value of the metrics in the OpenMetrics text format. Shared by the two
+/// ApiControllers, since the metrics can be scraped both before and after the microVM has booted.
+fn get_openmetrics() -> Result<VmmData, VmmActionError> {
+    METRICS
+        .openmetrics()
+        .map(VmmData::Metrics)
+        .map_err(VmmError::Metrics)
+        .map_err(VmmActionError::InternalVmm)
+}
+
 /// Trait used for deduplicating the MMDS request handling across the two ApiControllers.
 /// The methods get a mutable reference to self because the methods should initialise the data
 /// store with the defaults if it's not already initialised.
@@ -246,7 +330,8 @@ pub struct PrebootApiController<'a> {
     /// The [`Vmm`] object constructed through requests
     pub built_vmm: Option<Arc<Mutex<Vmm>>>,
     // Configuring boot specific resources will set this to true.
-    // Loading from snapshot will not be allowed once this is true.
+    // Loading from snapshot or receiving a migrated microVM will not be allowed once this is
+    // true.
     boot_path: bool,
     // Some PrebootApiRequest errors are irrecoverable and Firecracker
     // should cleanly teardown if they occur.
@@ -301,6 +386,8 @@ pub enum BuildMicrovmFromRequestsError {
     PopulateMmds(#[from] data_store::MmdsDatastoreError),
     /// Loading snapshot failed.
     Restore,
+    /// Receiving migrated microVM failed.
+    ReceiveMigration,
     /// Resuming MicroVM after loading snapshot failed.
     Resume,
 }
@@ -413,10 +500,11 @@ impl<'a> PrebootApiController<'a> {
             ConfigureMetrics(metrics_cfg) => vmm_config::metrics::init_metrics(metrics_cfg)
                 .map(|()| VmmData::Empty)
                 .map_err(VmmActionError::Metrics),
-            ConfigureSerial(serial_cfg) => {
-                self.vm_resources.serial_out_path = serial_cfg.serial_out_path;
-                Ok(VmmData::Empty)
-            }
+            ConfigureSerial(serial_cfg) => self
+                .vm_resources
+                .set_serial_config(serial_cfg)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::SerialConfig),
             GetBalloonConfig => self.balloon_config(),
             GetFullVmConfig => {
                 warn!(
@@ -426,6 +514,8 @@ impl<'a> PrebootApiController<'a> {
                 Ok(VmmData::FullVmConfig((&*self.vm_resources).into()))
             }
             GetMMDS => self.get_mmds(),
+            GetMetrics => get_openmetrics(),
+            GetMemoryHotplugStatus => self.memory_hotplug_status(),
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.machine_config.clone(),
             )),
@@ -441,21 +531,39 @@ impl<'a> PrebootApiController<'a> {
                 self.set_custom_cpu_template(custom_cpu_template)
             }
             PutMMDS(value) => self.put_mmds(value),
+            ReceiveMigration(config) => self
+                .receive_migration(&config)
+                .map_err(VmmActionError::ReceiveMigration),
             SetBalloonDevice(config) => self.set_balloon_device(config),
+            SetMemoryHotplugDevice(config) => self.set_memory_hotplug_device(config),
             SetVsockDevice(config) => self.set_vsock_device(config),
             SetMmdsConfiguration(config) => self.set_mmds_config(config),
+            SetRateLimiterGroup(config) => self.set_rate_limiter_group(config),
             StartMicroVm => self.start_microvm(),
             UpdateMachineConfiguration(config) => self.update_machine_config(config),
+            UpdateRateLimiterGroup(config) => self
+                .vm_resources
+                .rate_limiter_groups
+                .update(&config)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::RateLimiterGroup),
             SetEntropyDevice(config) => self.set_entropy_device(config),
             // Operations not allowed pre-boot.
             CreateSnapshot(_)
             | FlushMetrics
             | Pause
             | Resume
+            | SendMigration(_)
+            | GetMigrationStatus
             | GetBalloonStats
+            | GetBalloonHintingStatus
+            | UnplugDevice(_)
             | UpdateBalloon(_)
             | UpdateBalloonStatistics(_)
+            | UpdateBalloonHinting(_)
             | UpdateBlockDevice(_)
+            | HotplugVcpus(_)
+            | UpdateMemoryHotplugSize(_)
             | UpdateNetworkInterface(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => Err(VmmActionError::OperationNotSupportedPreBoot),
@@ -470,6 +578,16 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::BalloonConfig)
     }
 
+    fn memory_hotplug_status(&mut self) -> Result<VmmData, VmmActionError> {
+        self.vm_resources
+            .memory_hotplug
+            .as_ref()
+            .map(|config| VmmData::MemoryHotplugStatus(MemoryHotplugStatus::from(config)))
+            .ok_or(VmmActionError::MemoryHotplugConfig(
+                MemoryHotplugConfigError::DeviceNotFound,
+            ))
+    }
+
     fn insert_block_device(&mut self, cfg: BlockDeviceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -485,10 +603,19 @@ impl<'a> PrebootApiController<'a> {
         self.boot_path = true;
         self.vm_resources
             .build_net_device(cfg)
-            .map(|()| VmmData::Empty)
+            .map(|_| VmmData::Empty)
             .map_err(VmmActionError::NetworkConfig)
     }
 
+    fn set_rate_limiter_group(
+        &mut self,
+        cfg: RateLimiterGroupConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources.set_rate_limiter_group(cfg);
+        Ok(VmmData::Empty)
+    }
+
     fn set_balloon_device(&mut self, cfg: BalloonDeviceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -497,6 +624,17 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::BalloonConfig)
     }
 
+    fn set_memory_hotplug_device(
+        &mut self,
+        cfg: MemoryHotplugConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        self.boot_path = true;
+        self.vm_resources
+            .set_memory_hotplug_config(cfg)
+            .map(|()| VmmData::Empty)
+            .map_err(VmmActionError::MemoryHotplugConfig)
+    }
+
     fn set_boot_source(&mut self, cfg: BootSourceConfig) -> Result<VmmData, VmmActionError> {
         self.boot_path = true;
         self.vm_resources
@@ -608,6 +746,50 @@ impl<'a> PrebootApiController<'a> {
 
         Ok(VmmData::Empty)
     }
+
+    // On success, this command will end the pre-boot stage and this controller
+    // will be replaced by a runtime controller.
+    fn receive_migration(
+        &mut self,
+        params: &ReceiveMigrationParams,
+    ) -> Result<VmmData, ReceiveMigrationError> {
+        let receive_start_us = get_time_us(ClockType::Monotonic);
+
+        if self.boot_path {
+            let err = ReceiveMigrationError::NotAllowed;
+            info!("{}", err);
+            return Err(err);
+        }
+
+        let vmm = receive_migration(
+            &self.instance_info,
+            self.event_manager,
+            self.seccomp_filters,
+            params,
+            self.vm_resources,
+        )
+        .inspect_err(|err| {
+            if err.is_fatal() {
+                self.fatal_error = Some(BuildMicrovmFromRequestsError::ReceiveMigration);
+            }
+        })?;
+        if params.resume_vm {
+            vmm.lock()
+                .expect("Poisoned lock")
+                .resume_vm()
+                .inspect_err(|_| {
+                    self.fatal_error = Some(BuildMicrovmFromRequestsError::Resume);
+                })?;
+        }
+        self.built_vmm = Some(vmm);
+
+        info!(
+            "'receive migration' VMM action took {} us.",
+            get_time_us(ClockType::Monotonic) - receive_start_us
+        );
+
+        Ok(VmmData::Empty)
+    }
 }
 
 /// Enables RPC interaction with a running Firecracker VMM.
@@ -629,8 +811,13 @@ impl RuntimeApiController {
     /// Handles the incoming runtime `VmmAction` request and provides a response for it.
     pub fn handle_request(&mut self, request: VmmAction) -> Result<VmmData, VmmActionError> {
         use self::VmmAction::*;
+        self.remove_unplugged_devices();
         match request {
             // Supported operations allowed post-boot.
+            ConfigureLogger(logger_cfg) if logger_cfg.log_path.is_none() => crate::logger::LOGGER
+                .update(logger_cfg)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::Logger),
             CreateSnapshot(snapshot_create_cfg) => self.create_snapshot(&snapshot_create_cfg),
             FlushMetrics => self.flush_metrics(),
             GetBalloonConfig => self
@@ -647,8 +834,26 @@ impl RuntimeApiController {
                 .latest_balloon_stats()
                 .map(VmmData::BalloonStats)
                 .map_err(VmmActionError::InternalVmm),
+            GetBalloonHintingStatus => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .balloon_hinting_status()
+                .map(VmmData::BalloonHintingStatus)
+                .map_err(VmmActionError::InternalVmm),
             GetFullVmConfig => Ok(VmmData::FullVmConfig((&self.vm_resources).into())),
             GetMMDS => self.get_mmds(),
+            GetMetrics => get_openmetrics(),
+            GetMemoryHotplugStatus => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .memory_hotplug_status()
+                .map(VmmData::MemoryHotplugStatus)
+                .map_err(VmmActionError::InternalVmm),
+            GetMigrationStatus => Ok(VmmData::MigrationStatus(
+                self.vmm.lock().expect("Poisoned lock").migration_status(),
+            )),
             GetVmMachineConfig => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.machine_config.clone(),
             )),
@@ -658,12 +863,16 @@ impl RuntimeApiController {
             GetVmmVersion => Ok(VmmData::VmmVersion(
                 self.vmm.lock().expect("Poisoned lock").version(),
             )),
+            InsertBlockDevice(config) => self.hotplug_block_device(config),
+            InsertNetworkDevice(config) => self.hotplug_net_device(config),
             PatchMMDS(value) => self.patch_mmds(value),
             Pause => self.pause(),
             PutMMDS(value) => self.put_mmds(value),
             Resume => self.resume(),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.send_ctrl_alt_del(),
+            SendMigration(params) => self.send_migration(&params),
+            UnplugDevice(config) => self.unplug_device(&config),
             UpdateBalloon(balloon_update) => self
                 .vmm
                 .lock()
@@ -678,22 +887,44 @@ impl RuntimeApiController {
                 .update_balloon_stats_config(balloon_stats_update.stats_polling_interval_s)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::BalloonUpdate),
+            UpdateBalloonHinting(hinting_update) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .update_balloon_hinting(hinting_update.action)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::BalloonUpdate),
             UpdateBlockDevice(new_cfg) => self.update_block_device(new_cfg),
+            HotplugVcpus(update) => self.hotplug_vcpus(update),
+            UpdateMemoryHotplugSize(update) => self
+                .vmm
+                .lock()
+                .expect("Poisoned lock")
+                .update_memory_hotplug_size(update.requested_size_mib)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::MemoryHotplugUpdate),
             UpdateNetworkInterface(netif_update) => self.update_net_rate_limiters(netif_update),
+            UpdateRateLimiterGroup(config) => self
+                .vm_resources
+                .rate_limiter_groups
+                .update(&config)
+                .map(|()| VmmData::Empty)
+                .map_err(VmmActionError::RateLimiterGroup),
 
             // Operations not allowed post-boot.
             ConfigureBootSource(_)
             | ConfigureLogger(_)
             | ConfigureMetrics(_)
             | ConfigureSerial(_)
-            | InsertBlockDevice(_)
-            | InsertNetworkDevice(_)
             | LoadSnapshot(_)
             | PutCpuConfiguration(_)
+            | ReceiveMigration(_)
             | SetBalloonDevice(_)
+            | SetMemoryHotplugDevice(_)
             | SetVsockDevice(_)
             | SetMmdsConfiguration(_)
             | SetEntropyDevice(_)
+            | SetRateLimiterGroup(_)
             | StartMicroVm
             | UpdateMachineConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
         }
@@ -755,6 +986,141 @@ impl RuntimeApiController {
             .map_err(VmmActionError::InternalVmm)
     }
 
+    /// Hotplugs vCPUs into the inner Vmm, and records the new vCPU count in the microVM
+    /// configuration.
+    fn hotplug_vcpus(&mut self, update: VcpuHotplugUpdate) -> Result<VmmData, VmmActionError> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            self.vmm
+                .lock()
+                .expect("Poisoned lock")
+                .hotplug_vcpus(update.vcpu_count, &self.vm_resources.machine_config)?;
+            self.vm_resources.machine_config.vcpu_count = update.vcpu_count;
+            Ok(VmmData::Empty)
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            let _ = update;
+            Err(VcpuHotplugError::NotConfigured.into())
+        }
+    }
+
+    /// Hotplugs a new block device into the inner Vmm, and adds it to the microVM configuration.
+    fn hotplug_block_device(&mut self, cfg: BlockDeviceConfig) -> Result<VmmData, VmmActionError> {
+        let drive_id = cfg.drive_id.clone();
+        if self.vm_resources.block.get(&drive_id).is_some() {
+            return Err(DeviceHotplugError::DeviceExists(drive_id).into());
+        }
+        if cfg.is_root_device {
+            return Err(DeviceHotplugError::RootDevice.into());
+        }
+        if cfg.socket.is_some() {
+            return Err(DeviceHotplugError::VhostUser.into());
+        }
+
+        self.vm_resources.set_block_device(cfg)?;
+        // Safe to unwrap because we've just inserted the device.
+        let block = self.vm_resources.block.get(&drive_id).unwrap().clone();
+        if let Err(err) = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .hotplug_virtio_device(drive_id.clone(), block)
+        {
+            self.vm_resources.block.remove(&drive_id);
+            return Err(err.into());
+        }
+        Ok(VmmData::Empty)
+    }
+
+    /// Hotplugs a new network interface into the inner Vmm, and adds it to the microVM
+    /// configuration.
+    fn hotplug_net_device(
+        &mut self,
+        cfg: NetworkInterfaceConfig,
+    ) -> Result<VmmData, VmmActionError> {
+        let iface_id = cfg.iface_id.clone();
+        if self
+            .vm_resources
+            .net_builder
+            .iter()
+            .any(|net| net.lock().expect("Poisoned lock").id() == &iface_id)
+        {
+            return Err(DeviceHotplugError::DeviceExists(iface_id).into());
+        }
+
+        let net = self.vm_resources.build_net_device(cfg)?;
+        if let Err(err) = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .hotplug_virtio_device(iface_id.clone(), net)
+        {
+            self.vm_resources.net_builder.remove(&iface_id);
+            return Err(err.into());
+        }
+        Ok(VmmData::Empty)
+    }
+
+    /// Asks the guest to eject a block device or a network interface, and removes it from the
+    /// microVM configuration.
+    fn unplug_device(&mut self, cfg: &DeviceUnplugConfig) -> Result<VmmData, VmmActionError> {
+        let (device_type, id) = cfg.device()?;
+        match device_type {
+            virtio_ids::VIRTIO_ID_BLOCK => {
+                let block = self
+                    .vm_resources
+                    .block
+                    .get(id)
+                    .ok_or_else(|| DeviceHotplugError::DeviceNotFound(id.to_string()))?
+                    .lock()
+                    .expect("Poisoned lock");
+                if block.root_device() {
+                    return Err(DeviceHotplugError::RootDevice.into());
+                }
+                if block.is_vhost_user() {
+                    return Err(DeviceHotplugError::VhostUser.into());
+                }
+            }
+            _ => {
+                let net = self
+                    .vm_resources
+                    .net_builder
+                    .iter()
+                    .find(|net| net.lock().expect("Poisoned lock").id() == id)
+                    .ok_or_else(|| DeviceHotplugError::DeviceNotFound(id.to_string()))?
+                    .lock()
+                    .expect("Poisoned lock");
+                if net.mmds_ns().is_some() {
+                    return Err(DeviceHotplugError::MmdsInterface(id.to_string()).into());
+                }
+            }
+        }
+
+        // The device is removed from the configuration once the guest ejected it.
+        self.vmm
+            .lock()
+            .expect("Poisoned lock")
+            .unplug_virtio_device(device_type, id)?;
+        Ok(VmmData::Empty)
+    }
+
+    /// Removes the devices ejected by the guest from the microVM configuration.
+    fn remove_unplugged_devices(&mut self) {
+        let unplugged = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .take_unplugged_devices();
+        for (device_type, id) in unplugged {
+            if device_type == virtio_ids::VIRTIO_ID_BLOCK {
+                self.vm_resources.block.remove(&id);
+            } else {
+                self.vm_resources.net_builder.remove(&id);
+            }
+        }
+    }
+
     fn create_snapshot(
         &mut self,
         create_params: &CreateSnapshotParams,
@@ -794,6 +1160,14 @@ impl RuntimeApiController {
         Ok(VmmData::Empty)
     }
 
+    fn send_migration(&mut self, params: &SendMigrationParams) -> Result<VmmData, VmmActionError> {
+        let mut locked_vmm = self.vmm.lock().expect("Poisoned lock");
+        let vm_info = VmInfo::from(&self.vm_resources);
+
+        send_migration(&mut locked_vmm, &vm_info, params)?;
+        Ok(VmmData::Empty)
+    }
+
     /// Updates block device properties:
     ///  - path of the host file backing the emulated block device, update the disk image on the
     ///    device and its virtio configuration
@@ -802,6 +1176,24 @@ impl RuntimeApiController {
         &mut self,
         new_cfg: BlockDeviceUpdateConfig,
     ) -> Result<VmmData, VmmActionError> {
+        // The rate limiter of a drive in a rate limiter group is updated through the group.
+        if new_cfg.rate_limiter.is_some() {
+            let group_id = self
+                .vm_resources
+                .block
+                .get(&new_cfg.drive_id)
+                .and_then(|block| {
+                    block
+                        .lock()
+                        .expect("Poisoned lock")
+                        .config()
+                        .rate_limiter_group
+                });
+            if let Some(group_id) = group_id {
+                return Err(RateLimiterGroupError::DeviceInGroup(group_id).into());
+            }
+        }
+
         let mut vmm = self.vmm.lock().expect("Poisoned lock");
 
         // vhost-user-block updates
@@ -831,6 +1223,25 @@ impl RuntimeApiController {
         &mut self,
         new_cfg: NetworkInterfaceUpdateConfig,
     ) -> Result<VmmData, VmmActionError> {
+        // The rate limiters of an interface in a rate limiter group are updated through the group.
+        if let Some(net) = self
+            .vm_resources
+            .net_builder
+            .iter()
+            .find(|net| net.lock().expect("Poisoned lock").id() == &new_cfg.iface_id)
+        {
+            let net = net.lock().expect("Poisoned lock");
+            let rx_group_id = new_cfg
+                .rx_rate_limiter
+                .and(net.rx_rate_limiter().group_id());
+            let tx_group_id = new_cfg
+                .tx_rate_limiter
+                .and(net.tx_rate_limiter().group_id());
+            if let Some(group_id) = rx_group_id.or(tx_group_id) {
+                return Err(RateLimiterGroupError::DeviceInGroup(group_id).into());
+            }
+        }
+
         self.vmm
             .lock()
             .expect("Poisoned lock")
@@ -851,12 +1262,17 @@ impl RuntimeApiController {
 mod tests {
     use std::path::PathBuf;
 
+    use vmm_sys_util::tempfile::TempFile;
+
     use super::*;
     use crate::HTTP_MAX_PAYLOAD_SIZE;
     use crate::builder::tests::default_vmm;
     use crate::devices::virtio::block::CacheType;
     use crate::mmds::data_store::MmdsVersion;
+    use crate::mmds::policy::MmdsPolicy;
     use crate::seccomp::BpfThreadMap;
+    use crate::vmm_config::balloon::BalloonHintingAction;
+    use crate::vmm_config::migration::{MigrationSocketConfig, MigrationSocketType};
     use crate::vmm_config::snapshot::{MemBackendConfig, MemBackendType};
 
     fn default_preboot<'a>(
@@ -899,6 +1315,216 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_preboot_memory_hotplug() {
+        let mut vm_resources = VmResources::default();
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+
+        assert!(matches!(
+            preboot.handle_preboot_request(VmmAction::GetMemoryHotplugStatus),
+            Err(VmmActionError::MemoryHotplugConfig(
+                MemoryHotplugConfigError::DeviceNotFound
+            ))
+        ));
+
+        let config = MemoryHotplugConfig {
+            total_size_mib: 1024,
+            block_size_mib: 3,
+        };
+        assert!(matches!(
+            preboot.handle_preboot_request(VmmAction::SetMemoryHotplugDevice(config)),
+            Err(VmmActionError::MemoryHotplugConfig(
+                MemoryHotplugConfigError::InvalidBlockSize
+            ))
+        ));
+
+        let config = MemoryHotplugConfig {
+            total_size_mib: 1024,
+            block_size_mib: 2,
+        };
+        assert_eq!(
+            preboot
+                .handle_preboot_request(VmmAction::SetMemoryHotplugDevice(config))
+                .unwrap(),
+            VmmData::Empty
+        );
+        assert_eq!(
+            preboot
+                .handle_preboot_request(VmmAction::GetMemoryHotplugStatus)
+                .unwrap(),
+            VmmData::MemoryHotplugStatus(MemoryHotplugStatus {
+                total_size_mib: 1024,
+                block_size_mib: 2,
+                plugged_size_mib: 0,
+                requested_size_mib: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_preboot_rate_limiter_group() {
+        let mut vm_resources = VmResources::default();
+        let mut evmgr = EventManager::new().unwrap();
+        let seccomp_filters = BpfThreadMap::new();
+        let mut preboot = default_preboot(&mut vm_resources, &mut evmgr, &seccomp_filters);
+        let config = RateLimiterGroupConfig {
+            group_id: String::from("grp0"),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            preboot.handle_preboot_request(VmmAction::UpdateRateLimiterGroup(config.clone())),
+            Err(VmmActionError::RateLimiterGroup(
+                RateLimiterGroupError::GroupNotFound(_)
+            ))
+        ));
+        preboot
+            .handle_preboot_request(VmmAction::SetRateLimiterGroup(config.clone()))
+            .unwrap();
+        preboot
+            .handle_preboot_request(VmmAction::UpdateRateLimiterGroup(config.clone()))
+            .unwrap();
+        assert_eq!(vm_resources.rate_limiter_groups.configs(), vec![config]);
+    }
+
+    #[test]
+    fn test_runtime_memory_hotplug_without_device() {
+        assert!(matches!(
+            runtime_request(VmmAction::GetMemoryHotplugStatus),
+            Err(VmmActionError::InternalVmm(_))
+        ));
+        assert!(matches!(
+            runtime_request(VmmAction::UpdateMemoryHotplugSize(
+                MemoryHotplugSizeUpdate {
+                    requested_size_mib: 128,
+                }
+            )),
+            Err(VmmActionError::MemoryHotplugUpdate(_))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_vcpu_hotplug_without_controller() {
+        assert!(matches!(
+            runtime_request(VmmAction::HotplugVcpus(VcpuHotplugUpdate { vcpu_count: 2 })),
+            Err(VmmActionError::VcpuHotplug(VcpuHotplugError::NotConfigured))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_device_hotplug_without_controller() {
+        let backing_file = TempFile::new().unwrap();
+        let block_config = |drive_id: &str, is_root_device: bool| BlockDeviceConfig {
+            drive_id: drive_id.to_string(),
+            partuuid: None,
+            is_root_device,
+            cache_type: CacheType::Unsafe,
+
+            is_read_only: Some(false),
+            path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+            rate_limiter: None,
+            rate_limiter_group: None,
+            file_engine_type: None,
+            discard: None,
+            format: None,
+
+            socket: None,
+        };
+        let vmm = Arc::new(Mutex::new(default_vmm()));
+        let mut runtime = RuntimeApiController::new(VmResources::default(), vmm);
+
+        assert!(matches!(
+            runtime.handle_request(VmmAction::InsertBlockDevice(block_config("root", true))),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::RootDevice
+            ))
+        ));
+        // The device is not kept in the configuration if it cannot be hotplugged.
+        assert!(matches!(
+            runtime.handle_request(VmmAction::InsertBlockDevice(block_config("scratch", false))),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::NotConfigured
+            ))
+        ));
+        assert!(runtime.vm_resources.block.get("scratch").is_none());
+
+        runtime
+            .vm_resources
+            .set_block_device(block_config("scratch", false))
+            .unwrap();
+        assert!(matches!(
+            runtime.handle_request(VmmAction::InsertBlockDevice(block_config("scratch", false))),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::DeviceExists(_)
+            ))
+        ));
+        assert!(matches!(
+            runtime.handle_request(VmmAction::UnplugDevice(DeviceUnplugConfig {
+                drive_id: Some("scratch".to_string()),
+                iface_id: None,
+            })),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::NotConfigured
+            ))
+        ));
+        assert!(runtime.vm_resources.block.get("scratch").is_some());
+        assert!(matches!(
+            runtime.handle_request(VmmAction::UnplugDevice(DeviceUnplugConfig {
+                drive_id: None,
+                iface_id: Some("eth0".to_string()),
+            })),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::DeviceNotFound(_)
+            ))
+        ));
+        assert!(matches!(
+            runtime.handle_request(VmmAction::UnplugDevice(DeviceUnplugConfig::default())),
+            Err(VmmActionError::DeviceHotplug(
+                DeviceHotplugError::InvalidUnplugConfig
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_runtime_remove_unplugged_devices() {
+        let backing_file = TempFile::new().unwrap();
+        let vmm = Arc::new(Mutex::new(default_vmm()));
+        let mut runtime = RuntimeApiController::new(VmResources::default(), vmm.clone());
+        runtime
+            .vm_resources
+            .set_block_device(BlockDeviceConfig {
+                drive_id: "scratch".to_string(),
+                partuuid: None,
+                is_root_device: false,
+                cache_type: CacheType::Unsafe,
+
+                is_read_only: Some(false),
+                path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+                rate_limiter: None,
+                rate_limiter_group: None,
+                file_engine_type: None,
+                discard: None,
+                format: None,
+
+                socket: None,
+            })
+            .unwrap();
+
+        // The device stays in the configuration until the guest ejects it.
+        runtime.handle_request(VmmAction::GetVmmVersion).unwrap();
+        assert!(runtime.vm_resources.block.get("scratch").is_some());
+        vmm.lock()
+            .unwrap()
+            .device_manager
+            .pci_devices
+            .unplugged_devices
+            .push((virtio_ids::VIRTIO_ID_BLOCK, "scratch".to_string()));
+        runtime.handle_request(VmmAction::GetVmmVersion).unwrap();
+        assert!(runtime.vm_resources.block.get("scratch").is_none());
+    }
+
     #[test]
     fn test_preboot_get_mmds() {
         assert_eq!(
@@ -1132,9 +1758,26 @@ mod tests {
                 stats_polling_interval_s: 0,
             },
         )));
+        check_unsupported(preboot_request(VmmAction::GetBalloonHintingStatus));
+        check_unsupported(preboot_request(VmmAction::UpdateBalloonHinting(
+            BalloonHintingUpdate {
+                action: BalloonHintingAction::Start,
+            },
+        )));
         check_unsupported(preboot_request(VmmAction::UpdateBlockDevice(
             BlockDeviceUpdateConfig::default(),
         )));
+        check_unsupported(preboot_request(VmmAction::UnplugDevice(
+            DeviceUnplugConfig::default(),
+        )));
+        check_unsupported(preboot_request(VmmAction::HotplugVcpus(
+            VcpuHotplugUpdate { vcpu_count: 2 },
+        )));
+        check_unsupported(preboot_request(VmmAction::UpdateMemoryHotplugSize(
+            MemoryHotplugSizeUpdate {
+                requested_size_mib: 0,
+            },
+        )));
         check_unsupported(preboot_request(VmmAction::UpdateNetworkInterface(
             NetworkInterfaceUpdateConfig {
                 iface_id: String::new(),
@@ -1147,6 +1790,18 @@ mod tests {
                 snapshot_type: SnapshotType::Full,
                 snapshot_path: PathBuf::new(),
                 mem_file_path: PathBuf::new(),
+                mem_file_encoding: None,
+            },
+        )));
+        check_unsupported(preboot_request(VmmAction::GetMigrationStatus));
+        check_unsupported(preboot_request(VmmAction::SendMigration(
+            SendMigrationParams {
+                socket: MigrationSocketConfig {
+                    socket_type: MigrationSocketType::Unix,
+                    address: String::new(),
+                },
+                max_iterations: 0,
+                dirty_threshold_mib: 0,
             },
         )));
         #[cfg(target_arch = "x86_64")]
@@ -1180,6 +1835,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_metrics() {
+        for res in [
+            preboot_request(VmmAction::GetMetrics),
+            runtime_request(VmmAction::GetMetrics),
+        ] {
+            match res.unwrap() {
+                VmmData::Metrics(text) => assert!(text.ends_with("# EOF\n")),
+                data => panic!("unexpected response: {data:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_runtime_configure_logger() {
+        // The filters of the logger can be updated at runtime.
+        runtime_request(VmmAction::ConfigureLogger(LoggerConfig {
+            log_path: None,
+            level: None,
+            show_level: None,
+            show_log_origin: None,
+            module: None,
+            format: None,
+            module_levels: Some(vec![]),
+        }))
+        .unwrap();
+    }
+
     #[test]
     fn test_runtime_disallowed() {
         fn check_unsupported(res: Result<VmmData, VmmActionError>) {
@@ -1199,59 +1882,47 @@ mod tests {
             show_level: Some(false),
             show_log_origin: Some(false),
             module: None,
+            format: None,
+            module_levels: None,
         })));
         check_unsupported(runtime_request(VmmAction::ConfigureMetrics(
             MetricsConfig {
                 metrics_path: PathBuf::new(),
             },
         )));
-        check_unsupported(runtime_request(VmmAction::InsertBlockDevice(
-            BlockDeviceConfig {
-                drive_id: String::new(),
-                partuuid: None,
-                is_root_device: false,
-                cache_type: CacheType::Unsafe,
-
-                is_read_only: Some(false),
-                path_on_host: Some(String::new()),
-                rate_limiter: None,
-                file_engine_type: None,
-
-                socket: None,
-            },
-        )));
-        check_unsupported(runtime_request(VmmAction::InsertNetworkDevice(
-            NetworkInterfaceConfig {
-                iface_id: String::new(),
-                host_dev_name: String::new(),
-                guest_mac: None,
-                rx_rate_limiter: None,
-                tx_rate_limiter: None,
-            },
-        )));
         check_unsupported(runtime_request(VmmAction::SetVsockDevice(
             VsockDeviceConfig {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
+                port_forwards: Default::default(),
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetBalloonDevice(
             BalloonDeviceConfig::default(),
         )));
+        check_unsupported(runtime_request(VmmAction::SetMemoryHotplugDevice(
+            MemoryHotplugConfig {
+                total_size_mib: 1024,
+                block_size_mib: 2,
+            },
+        )));
         check_unsupported(runtime_request(VmmAction::SetVsockDevice(
             VsockDeviceConfig {
                 vsock_id: Some(String::new()),
                 guest_cid: 0,
                 uds_path: String::new(),
+                port_forwards: Default::default(),
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetMmdsConfiguration(
             MmdsConfig {
                 ipv4_address: None,
+                ipv6_address: None,
                 version: MmdsVersion::default(),
                 network_interfaces: Vec::new(),
                 imds_compat: false,
+                policy: MmdsPolicy::default(),
             },
         )));
         check_unsupported(runtime_request(VmmAction::UpdateMachineConfiguration(
@@ -1267,10 +1938,56 @@ mod tests {
                 track_dirty_pages: false,
                 resume_vm: false,
                 network_overrides: vec![],
+                parent_layers: vec![],
+                mem_file_encryption_key: None,
             },
         )));
         check_unsupported(runtime_request(VmmAction::SetEntropyDevice(
             EntropyDeviceConfig::default(),
         )));
+        check_unsupported(runtime_request(VmmAction::SetRateLimiterGroup(
+            RateLimiterGroupConfig::default(),
+        )));
+        check_unsupported(runtime_request(VmmAction::ReceiveMigration(
+            ReceiveMigrationParams {
+                socket: MigrationSocketConfig {
+                    socket_type: MigrationSocketType::Unix,
+                    address: String::new(),
+                },
+                track_dirty_pages: false,
+                resume_vm: false,
+            },
+        )));
+    }
+
+    #[test]
+    fn test_runtime_get_migration_status() {
+        assert_eq!(
+            runtime_request(VmmAction::GetMigrationStatus).unwrap(),
+            VmmData::MigrationStatus(MigrationStatus::NotStarted)
+        );
+    }
+
+    #[test]
+    fn test_runtime_send_migration() {
+        // The default microVM does not track dirty pages.
+        let res = runtime_request(VmmAction::SendMigration(SendMigrationParams {
+            socket: MigrationSocketConfig {
+                socket_type: MigrationSocketType::Unix,
+                address: String::new(),
+            },
+            max_iterations: 0,
+            dirty_threshold_mib: 0,
+        }));
+        assert!(
+            matches!(
+                res,
+                Err(VmmActionError::SendMigration(
+                    SendMigrationError::DirtyPageTrackingDisabled
+                ))
+            ),
+            "{:?}",
+            res
+        );
     }
 }
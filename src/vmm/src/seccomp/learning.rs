@@ -0,0 +1,252 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Seccomp learning mode.
+//!
+//! In learning mode, the filters are installed along with a seccomp user notification
+//! listener. The syscalls which they don't allow are recorded to a log by an unfiltered thread,
+//! and then let through. `seccompiler-bin learn` turns the log into the rules missing from
+//! the JSON filters.
+
+use std::collections::HashSet;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+
+use log::{error, warn};
+use serde::Serialize;
+use vmm_sys_util::ioctl::{ioctl_with_mut_ref, ioctl_with_ref};
+use vmm_sys_util::ioctl_iowr_nr;
+
+// As defined in the Linux UAPI:
+// https://elixir.bootlin.com/linux/v5.10/source/include/uapi/linux/seccomp.h#L128
+const SECCOMP_IOC_MAGIC: ::std::os::raw::c_uint = 0x21;
+ioctl_iowr_nr!(
+    SECCOMP_IOCTL_NOTIF_RECV,
+    SECCOMP_IOC_MAGIC,
+    0,
+    libc::seccomp_notif
+);
+ioctl_iowr_nr!(
+    SECCOMP_IOCTL_NOTIF_SEND,
+    SECCOMP_IOC_MAGIC,
+    1,
+    libc::seccomp_notif_resp
+);
+
+/// How often, in milliseconds, the recording thread looks for new listeners.
+const LISTENER_POLL_TIMEOUT_MS: libc::c_int = 100;
+
+/// Slot for the listener of a filter, which only exists once the filter is installed.
+pub(crate) type ListenerSlot = Arc<OnceLock<OwnedFd>>;
+
+/// Sends the listener slots and the thread category of their filter to the recording thread.
+static LISTENER_SENDER: OnceLock<Sender<(&'static str, ListenerSlot)>> = OnceLock::new();
+
+/// Seccomp learning mode errors.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum LearningError {
+    /// Seccomp learning mode is already enabled
+    AlreadyEnabled,
+    /// Cannot open the seccomp learning log: {0}
+    LogOpen(std::io::Error),
+    /// Cannot spawn the seccomp learning thread: {0}
+    Spawn(std::io::Error),
+}
+
+/// Syscall not allowed by a filter, as recorded in the learning log.
+#[derive(Debug, PartialEq, Eq, Hash, Serialize)]
+struct SyscallRecord {
+    thread: &'static str,
+    arch: u32,
+    nr: i32,
+    args: [u64; 6],
+}
+
+/// Enables the seccomp learning mode: the syscalls not allowed by the filters installed
+/// afterwards are appended to the log at `log_path`, instead of triggering the default action.
+///
+/// The filters must be compiled with `seccompiler-bin --learning`, and this must be called
+/// before installing any filter, so that the recording thread is not filtered.
+pub fn enable(log_path: &Path) -> Result<(), LearningError> {
+    if LISTENER_SENDER.get().is_some() {
+        return Err(LearningError::AlreadyEnabled);
+    }
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+        .map_err(LearningError::LogOpen)?;
+
+    let (sender, receiver) = channel();
+    thread::Builder::new()
+        .name("fc_seccomp".to_string())
+        .spawn(move || record(log, &receiver))
+        .map_err(LearningError::Spawn)?;
+    LISTENER_SENDER
+        .set(sender)
+        .map_err(|_| LearningError::AlreadyEnabled)
+}
+
+/// Returns the slot for the listener of the filter that the calling thread is about to
+/// install, if the seccomp learning mode is enabled.
+pub(crate) fn listener_slot() -> Option<ListenerSlot> {
+    let sender = LISTENER_SENDER.get()?;
+    let slot = ListenerSlot::default();
+    sender.send((thread_category(), slot.clone())).ok()?;
+    Some(slot)
+}
+
+/// Thread category of the calling thread, derived from the names of the Firecracker threads.
+fn thread_category() -> &'static str {
+    match thread::current().name() {
+        Some(name) if name.starts_with("fc_vcpu") => "vcpu",
+        Some("fc_api") => "api",
+        _ => "vmm",
+    }
+}
+
+fn record(mut log: File, listener_receiver: &Receiver<(&'static str, ListenerSlot)>) {
+    // A thread cannot tell when its listener is ready, since the syscalls needed to do so may
+    // not be allowed by its filter, which would block the thread before the listener is
+    // watched. Instead, the slots of the new listeners are checked periodically.
+    let mut pending: Vec<(&'static str, ListenerSlot)> = Vec::new();
+    let mut listeners: Vec<(&'static str, ListenerSlot)> = Vec::new();
+    let mut recorded = HashSet::new();
+
+    loop {
+        pending.extend(listener_receiver.try_iter());
+        let (ready, not_ready): (Vec<_>, Vec<_>) = pending
+            .into_iter()
+            .partition(|(_, slot)| slot.get().is_some());
+        pending = not_ready;
+        listeners.extend(ready);
+
+        let mut pollfds: Vec<libc::pollfd> = listeners
+            .iter()
+            .map(|(_, slot)| libc::pollfd {
+                // Safe to unwrap because only the slots holding a listener are watched.
+                fd: slot.get().unwrap().as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        // SAFETY: Safe because `pollfds` is valid for `pollfds.len()` entries and the return
+        // value is checked.
+        let ret = unsafe {
+            libc::poll(
+                pollfds.as_mut_ptr(),
+                pollfds.len() as libc::nfds_t,
+                LISTENER_POLL_TIMEOUT_MS,
+            )
+        };
+        if ret < 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == ErrorKind::Interrupted {
+                continue;
+            }
+            error!("Seccomp learning thread failed to poll the listeners: {err}");
+            return;
+        }
+
+        for ((category, slot), pollfd) in listeners.iter().zip(&pollfds) {
+            if pollfd.revents & libc::POLLIN != 0 {
+                // Safe to unwrap because only the slots holding a listener are watched.
+                handle_notification(category, slot.get().unwrap(), &mut log, &mut recorded);
+            }
+        }
+
+        // A listener hangs up once all the threads using its filter have exited.
+        let mut hung_up = pollfds.iter().map(|pollfd| {
+            pollfd.revents & libc::POLLIN == 0
+                && pollfd.revents & (libc::POLLHUP | libc::POLLERR | libc::POLLNVAL) != 0
+        });
+        listeners.retain(|_| !hung_up.next().unwrap_or(false));
+    }
+}
+
+fn handle_notification(
+    category: &'static str,
+    listener: &OwnedFd,
+    log: &mut File,
+    recorded: &mut HashSet<SyscallRecord>,
+) {
+    // SAFETY: Safe because `seccomp_notif` is a plain C struct, which the kernel requires to
+    // be zeroed.
+    let mut notif: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+    // SAFETY: Safe because the listener is a valid seccomp listener, `notif` has the type
+    // expected by the ioctl and the return value is checked.
+    if unsafe { ioctl_with_mut_ref(listener, SECCOMP_IOCTL_NOTIF_RECV(), &mut notif) } < 0 {
+        // The notification is dropped if the calling thread is killed in the meantime.
+        return;
+    }
+
+    let record = SyscallRecord {
+        thread: category,
+        arch: notif.data.arch,
+        nr: notif.data.nr,
+        args: notif.data.args,
+    };
+    // Syscalls are usually repeated, so each of them is only recorded once.
+    if !recorded.contains(&record) {
+        // Safe to unwrap because the record only holds plain values.
+        let line = serde_json::to_string(&record).unwrap();
+        if let Err(err) = writeln!(log, "{line}") {
+            warn!("Cannot write to the seccomp learning log: {err}");
+        }
+        recorded.insert(record);
+    }
+
+    // Cast is safe because the flag fits in the 32 bits of the `flags` field.
+    #[allow(clippy::cast_possible_truncation)]
+    let resp = libc::seccomp_notif_resp {
+        id: notif.id,
+        val: 0,
+        error: 0,
+        flags: libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+    };
+    // SAFETY: Safe because the listener is a valid seccomp listener and `resp` has the type
+    // expected by the ioctl. An error only means that the calling thread is gone.
+    unsafe { ioctl_with_ref(listener, SECCOMP_IOCTL_NOTIF_SEND(), &resp) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thread_category() {
+        let category = |name: &str| {
+            thread::Builder::new()
+                .name(name.to_string())
+                .spawn(thread_category)
+                .unwrap()
+                .join()
+                .unwrap()
+        };
+        assert_eq!(category("fc_vcpu 0"), "vcpu");
+        assert_eq!(category("fc_vcpu 12"), "vcpu");
+        assert_eq!(category("fc_api"), "api");
+        assert_eq!(category("main"), "vmm");
+        assert_eq!(category("fc_api_extra"), "vmm");
+    }
+
+    #[test]
+    fn test_syscall_record_format() {
+        let record = SyscallRecord {
+            thread: "vcpu",
+            arch: 0xc000003e,
+            nr: 16,
+            args: [12, 0xc008ae8a, 0, 0, 0, u64::MAX],
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            "{\"thread\":\"vcpu\",\"arch\":3221225534,\"nr\":16,\"args\":[12,3221794442,0,0,0,\
+             18446744073709551615]}"
+        );
+    }
+}
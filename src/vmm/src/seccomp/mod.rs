@@ -3,11 +3,14 @@
 
 use std::collections::HashMap;
 use std::io::Read;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
 use std::sync::Arc;
 
 use bincode::config;
 use bincode::config::{Configuration, Fixint, Limit, LittleEndian};
 
+pub mod learning;
+
 // This byte limit is passed to `bincode` to guard against a potential memory
 // allocation DOS caused by binary filters that are too large.
 // This limit can be safely determined since the maximum length of a BPF
@@ -93,6 +96,14 @@ pub fn apply_filter(bpf_filter: BpfProgramRef) -> Result<(), InstallationError>
     let bpf_filter_len =
         u16::try_from(bpf_filter.len()).map_err(|_| InstallationError::FilterTooLarge)?;
 
+    // In learning mode, the syscalls not allowed by the filter are reported to its listener.
+    let listener_slot = learning::listener_slot();
+    let flags = if listener_slot.is_some() {
+        libc::SECCOMP_FILTER_FLAG_NEW_LISTENER
+    } else {
+        0
+    };
+
     // SAFETY: Safe because the parameters are valid.
     unsafe {
         {
@@ -111,12 +122,21 @@ pub fn apply_filter(bpf_filter: BpfProgramRef) -> Result<(), InstallationError>
             let rc = libc::syscall(
                 libc::SYS_seccomp,
                 libc::SECCOMP_SET_MODE_FILTER,
-                0,
+                flags,
                 bpf_prog_ptr,
             );
-            if rc != 0 {
+            if rc < 0 {
                 return Err(InstallationError::Prctl(std::io::Error::last_os_error()));
             }
+            // The filter is now installed: until the listener is in its slot, any syscall that
+            // the filter doesn't allow would block forever.
+            if let Some(slot) = listener_slot {
+                // Cast is safe because `rc` is the file descriptor of the listener.
+                #[allow(clippy::cast_possible_truncation)]
+                let listener = OwnedFd::from_raw_fd(rc as RawFd);
+                // The slot is only ever filled here.
+                let _ = slot.set(listener);
+            }
         }
     }
 
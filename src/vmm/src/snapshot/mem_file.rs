@@ -0,0 +1,634 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoded format of snapshot memory files.
+//!
+//! Instead of a raw dump of the guest memory, an encoded memory file uses the following layout:
+//!
+//!  |-----------------------------|
+//!  |     32 bytes of header      |
+//!  |-----------------------------|
+//!  |   u32 length of chunk 0     |
+//!  |-----------------------------|
+//!  |      payload of chunk 0     |
+//!  |-----------------------------|
+//!  |             ...             |
+//!  |-----------------------------|
+//!
+//! The guest memory is split in chunks of at most `chunk_size` bytes, which never cross the
+//! boundaries of memory regions. The payload of a chunk is the chunk compressed on its own with
+//! zstd or lz4, if compression is enabled, and then encrypted with AES-256-GCM, if a key is
+//! provided. The nonce of a chunk is made of a random salt stored in the header and of the index
+//! of the chunk, while the header is authenticated as the additional data of every chunk. This
+//! way, chunks can be neither tampered with, reordered, nor moved to another memory file.
+
+use std::cmp::min;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+use aes_gcm::{AeadInPlace, Aes256Gcm, Key, KeyInit, Nonce};
+use aws_lc_rs::error::Unspecified as RandError;
+use aws_lc_rs::rand;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::u64_to_usize;
+use crate::vmm_config::snapshot::{MemFileCompression, MemFileEncoding, MemFileKey};
+use crate::vstate::memory::{
+    Bytes, GuestMemory, GuestMemoryMmap, GuestMemoryRegion, GuestRegionMmap, MemoryRegionAddress,
+};
+
+/// Magic number at the start of encoded memory files.
+pub const MEM_FILE_MAGIC: [u8; 8] = *b"FCMEMENC";
+/// Length of the header of encoded memory files.
+pub const HEADER_LEN: usize = 32;
+/// Version of the encoded memory file format.
+const FORMAT_VERSION: u8 = 1;
+/// Length of the chunks of guest memory written to encoded memory files.
+const CHUNK_SIZE: u32 = 1 << 20;
+/// Maximum length of the chunks of guest memory accepted in encoded memory files.
+const MAX_CHUNK_SIZE: u32 = 64 << 20;
+/// Length of the key used for encryption.
+const KEY_LEN: usize = 32;
+/// Length of the initialization vector of a chunk.
+const IV_LEN: usize = 12;
+/// Length of the random salt of the initialization vectors.
+const SALT_LEN: usize = 8;
+/// Length of the encryption tag appended to the payload of a chunk.
+const TAG_LEN: usize = 16;
+
+/// Errors related to encoded memory files.
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum MemFileError {
+    /// Invalid memory file encryption key, expected {KEY_LEN} base64 encoded bytes.
+    InvalidKey,
+    /// Failed to generate the salt of the memory file: {0}
+    Salt(#[from] RandError),
+    /// Failed to access the memory file: {0}
+    Io(#[from] io::Error),
+    /// Failed to access guest memory: {0}
+    Memory(#[from] vm_memory::GuestMemoryError),
+    /// The file is not an encoded memory file.
+    Magic,
+    /// Unsupported encoded memory file version: {0}
+    Version(u8),
+    /// Invalid header of the encoded memory file.
+    Header,
+    /// The memory file is encrypted, but no key was provided.
+    MissingKey,
+    /// An encryption key was provided, but the memory file is not encrypted.
+    NotEncrypted,
+    /// Failed to compress chunk {0} of the memory file: {1}
+    Compress(u32, io::Error),
+    /// Failed to encrypt chunk {0} of the memory file.
+    Encrypt(u32),
+    /// Chunk {0} of the memory file is corrupted.
+    CorruptedChunk(u32),
+    /// The memory file holds more chunks than supported.
+    TooManyChunks,
+    /// The memory file holds {0} bytes of guest memory instead of {1}.
+    Size(u64, u64),
+}
+
+/// Format of an encoded memory file, recorded in the microVM state of its snapshot.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemFileFormat {
+    /// Algorithm used to compress the chunks of guest memory.
+    pub compression: Option<MemFileCompression>,
+    /// Whether the chunks of guest memory are encrypted.
+    pub encrypted: bool,
+}
+
+impl From<&MemFileEncoding> for MemFileFormat {
+    fn from(encoding: &MemFileEncoding) -> Self {
+        MemFileFormat {
+            compression: encoding.compression,
+            encrypted: encoding.encryption_key.is_some(),
+        }
+    }
+}
+
+/// Header of an encoded memory file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemFileHeader {
+    /// Format of the chunks of guest memory.
+    pub format: MemFileFormat,
+    /// Maximum length of the chunks of guest memory.
+    pub chunk_size: u32,
+    /// Length of the guest memory.
+    pub mem_size: u64,
+    /// Random salt of the initialization vectors of the chunks.
+    salt: [u8; SALT_LEN],
+}
+
+impl MemFileHeader {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[0..8].copy_from_slice(&MEM_FILE_MAGIC);
+        bytes[8] = FORMAT_VERSION;
+        bytes[9] = match self.format.compression {
+            None => 0,
+            Some(MemFileCompression::Zstd) => 1,
+            Some(MemFileCompression::Lz4) => 2,
+        };
+        bytes[10] = u8::from(self.format.encrypted);
+        bytes[12..16].copy_from_slice(&self.chunk_size.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.mem_size.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.salt);
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; HEADER_LEN]) -> Result<Self, MemFileError> {
+        if bytes[0..8] != MEM_FILE_MAGIC {
+            return Err(MemFileError::Magic);
+        }
+        if bytes[8] != FORMAT_VERSION {
+            return Err(MemFileError::Version(bytes[8]));
+        }
+        let compression = match bytes[9] {
+            0 => None,
+            1 => Some(MemFileCompression::Zstd),
+            2 => Some(MemFileCompression::Lz4),
+            _ => return Err(MemFileError::Header),
+        };
+        let encrypted = match bytes[10] {
+            0 => false,
+            1 => true,
+            _ => return Err(MemFileError::Header),
+        };
+        let chunk_size = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+        if bytes[11] != 0 || chunk_size == 0 || chunk_size > MAX_CHUNK_SIZE {
+            return Err(MemFileError::Header);
+        }
+        Ok(MemFileHeader {
+            format: MemFileFormat {
+                compression,
+                encrypted,
+            },
+            chunk_size,
+            mem_size: u64::from_le_bytes(bytes[16..24].try_into().unwrap()),
+            salt: bytes[24..32].try_into().unwrap(),
+        })
+    }
+
+    /// Initialization vector of the chunk with index `chunk_index`.
+    fn iv(&self, chunk_index: u32) -> [u8; IV_LEN] {
+        let mut iv = [0u8; IV_LEN];
+        iv[..SALT_LEN].copy_from_slice(&self.salt);
+        iv[SALT_LEN..].copy_from_slice(&chunk_index.to_le_bytes());
+        iv
+    }
+
+    /// Upper bound of the length of the payload of a chunk.
+    fn max_payload_len(&self) -> usize {
+        // Both zstd and lz4 expand incompressible data by less than 1/128 of its length.
+        let chunk_size = self.chunk_size as usize;
+        chunk_size + chunk_size / 128 + 1024 + TAG_LEN
+    }
+}
+
+fn cipher(key: &MemFileKey) -> Result<Aes256Gcm, MemFileError> {
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(&key.0)
+        .map_err(|_| MemFileError::InvalidKey)?;
+    if key.len() != KEY_LEN {
+        return Err(MemFileError::InvalidKey);
+    }
+    Ok(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+}
+
+/// Checks that `key` is a valid encryption key for memory files.
+pub fn validate_key(key: &MemFileKey) -> Result<(), MemFileError> {
+    cipher(key).map(|_| ())
+}
+
+/// Writes chunks of guest memory to an encoded memory file.
+struct MemFileEncoder<W: Write> {
+    writer: W,
+    header: MemFileHeader,
+    header_bytes: [u8; HEADER_LEN],
+    cipher: Option<Aes256Gcm>,
+    chunk_index: u32,
+    written: u64,
+}
+
+impl<W: Write> MemFileEncoder<W> {
+    fn new(mut writer: W, encoding: &MemFileEncoding, mem_size: u64) -> Result<Self, MemFileError> {
+        let cipher = encoding.encryption_key.as_ref().map(cipher).transpose()?;
+        let mut salt = [0u8; SALT_LEN];
+        rand::fill(&mut salt)?;
+        let header = MemFileHeader {
+            format: MemFileFormat::from(encoding),
+            chunk_size: CHUNK_SIZE,
+            mem_size,
+            salt,
+        };
+        let header_bytes = header.to_bytes();
+        writer.write_all(&header_bytes)?;
+
+        Ok(MemFileEncoder {
+            writer,
+            header,
+            header_bytes,
+            cipher,
+            chunk_index: 0,
+            written: 0,
+        })
+    }
+
+    /// Encodes a chunk of at most `chunk_size` bytes of guest memory.
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), MemFileError> {
+        debug_assert!(chunk.len() <= self.header.chunk_size as usize);
+        let chunk_index = self.chunk_index;
+
+        let mut payload = match self.header.format.compression {
+            None => chunk.to_vec(),
+            Some(MemFileCompression::Zstd) => {
+                zstd::bulk::compress(chunk, zstd::DEFAULT_COMPRESSION_LEVEL)
+                    .map_err(|err| MemFileError::Compress(chunk_index, err))?
+            }
+            Some(MemFileCompression::Lz4) => lz4::block::compress(chunk, None, true)
+                .map_err(|err| MemFileError::Compress(chunk_index, err))?,
+        };
+        if let Some(cipher) = &self.cipher {
+            let tag = cipher
+                .encrypt_in_place_detached(
+                    Nonce::from_slice(&self.header.iv(chunk_index)),
+                    &self.header_bytes,
+                    &mut payload,
+                )
+                .map_err(|_| MemFileError::Encrypt(chunk_index))?;
+            payload.extend_from_slice(&tag);
+        }
+
+        // Payloads are bounded by `max_payload_len`, far below 4 GiB.
+        let payload_len = u32::try_from(payload.len()).unwrap();
+        self.writer.write_all(&payload_len.to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+
+        self.chunk_index = chunk_index
+            .checked_add(1)
+            .ok_or(MemFileError::TooManyChunks)?;
+        self.written += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Checks that the whole guest memory was encoded and returns the writer.
+    fn finish(self) -> Result<W, MemFileError> {
+        if self.written != self.header.mem_size {
+            return Err(MemFileError::Size(self.written, self.header.mem_size));
+        }
+        Ok(self.writer)
+    }
+}
+
+/// Reads guest memory from an encoded memory file.
+pub struct MemFileDecoder<R: Read> {
+    reader: R,
+    header: MemFileHeader,
+    header_bytes: [u8; HEADER_LEN],
+    cipher: Option<Aes256Gcm>,
+    chunk_index: u32,
+    read: u64,
+    payload: Vec<u8>,
+    chunk: Vec<u8>,
+    chunk_pos: usize,
+}
+
+impl<R: Read + fmt::Debug> fmt::Debug for MemFileDecoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemFileDecoder")
+            .field("reader", &self.reader)
+            .field("header", &self.header)
+            .field("chunk_index", &self.chunk_index)
+            .field("read", &self.read)
+            .finish()
+    }
+}
+
+impl<R: Read> MemFileDecoder<R> {
+    /// Reads the header of an encoded memory file. The key must be provided if, and only if, the
+    /// memory file is encrypted.
+    pub fn new(mut reader: R, key: Option<&MemFileKey>) -> Result<Self, MemFileError> {
+        let mut header_bytes = [0u8; HEADER_LEN];
+        reader.read_exact(&mut header_bytes)?;
+        let header = MemFileHeader::from_bytes(&header_bytes)?;
+        let cipher = match (header.format.encrypted, key) {
+            (true, Some(key)) => Some(cipher(key)?),
+            (true, None) => return Err(MemFileError::MissingKey),
+            (false, Some(_)) => return Err(MemFileError::NotEncrypted),
+            (false, None) => None,
+        };
+
+        Ok(MemFileDecoder {
+            reader,
+            header,
+            header_bytes,
+            cipher,
+            chunk_index: 0,
+            read: 0,
+            payload: Vec::new(),
+            chunk: vec![0; header.chunk_size as usize],
+            chunk_pos: header.chunk_size as usize,
+        })
+    }
+
+    /// Header of the encoded memory file.
+    pub fn header(&self) -> &MemFileHeader {
+        &self.header
+    }
+
+    /// Reads and decodes the next chunk of guest memory.
+    fn read_chunk(&mut self) -> Result<(), MemFileError> {
+        let chunk_index = self.chunk_index;
+        let corrupted = || MemFileError::CorruptedChunk(chunk_index);
+        if self.read == self.header.mem_size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let mut payload_len = [0u8; 4];
+        self.reader.read_exact(&mut payload_len)?;
+        let payload_len = u32::from_le_bytes(payload_len) as usize;
+        if payload_len > self.header.max_payload_len() {
+            return Err(corrupted());
+        }
+        self.payload.resize(payload_len, 0);
+        self.reader.read_exact(&mut self.payload)?;
+
+        let mut data = self.payload.as_mut_slice();
+        if let Some(cipher) = &self.cipher {
+            let data_len = data.len().checked_sub(TAG_LEN).ok_or_else(corrupted)?;
+            let (ciphertext, tag) = data.split_at_mut(data_len);
+            cipher
+                .decrypt_in_place_detached(
+                    Nonce::from_slice(&self.header.iv(chunk_index)),
+                    &self.header_bytes,
+                    ciphertext,
+                    aes_gcm::Tag::from_slice(tag),
+                )
+                .map_err(|_| corrupted())?;
+            data = ciphertext;
+        }
+
+        let chunk_len = match self.header.format.compression {
+            None if data.len() <= self.chunk.len() => {
+                self.chunk[..data.len()].copy_from_slice(data);
+                data.len()
+            }
+            None => return Err(corrupted()),
+            Some(MemFileCompression::Zstd) => {
+                zstd::bulk::decompress_to_buffer(data, &mut self.chunk).map_err(|_| corrupted())?
+            }
+            Some(MemFileCompression::Lz4) => {
+                lz4::block::decompress_to_buffer(data, None, &mut self.chunk)
+                    .map_err(|_| corrupted())?
+            }
+        };
+        if chunk_len == 0 || self.read + chunk_len as u64 > self.header.mem_size {
+            return Err(corrupted());
+        }
+
+        self.chunk.truncate(chunk_len);
+        self.chunk_pos = 0;
+        self.chunk_index = chunk_index
+            .checked_add(1)
+            .ok_or(MemFileError::TooManyChunks)?;
+        self.read += chunk_len as u64;
+        Ok(())
+    }
+
+    /// Fills `buf` with the next bytes of guest memory.
+    pub fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), MemFileError> {
+        while !buf.is_empty() {
+            if self.chunk_pos == self.chunk.len() {
+                self.chunk.resize(self.header.chunk_size as usize, 0);
+                self.read_chunk()?;
+            }
+            let len = min(buf.len(), self.chunk.len() - self.chunk_pos);
+            buf[..len].copy_from_slice(&self.chunk[self.chunk_pos..self.chunk_pos + len]);
+            self.chunk_pos += len;
+            buf = &mut buf[len..];
+        }
+        Ok(())
+    }
+}
+
+/// Writes the whole guest memory to `writer` as an encoded memory file, and returns the writer.
+pub fn encode_guest_memory<W: Write>(
+    mem: &GuestMemoryMmap,
+    writer: W,
+    encoding: &MemFileEncoding,
+) -> Result<W, MemFileError> {
+    let mem_size = mem.iter().map(|region| region.len()).sum();
+    let mut encoder = MemFileEncoder::new(writer, encoding, mem_size)?;
+    let mut chunk = vec![0u8; encoder.header.chunk_size as usize];
+
+    for region in mem.iter() {
+        let mut offset = 0;
+        while offset < region.len() {
+            let len = min(chunk.len(), u64_to_usize(region.len() - offset));
+            region.read_slice(&mut chunk[..len], MemoryRegionAddress(offset))?;
+            encoder.write_chunk(&chunk[..len])?;
+            offset += len as u64;
+        }
+    }
+    encoder.finish()
+}
+
+/// Fills the guest memory `regions` with the contents of the encoded memory file of `reader`.
+pub fn decode_guest_memory<R: Read>(
+    regions: &[GuestRegionMmap],
+    reader: R,
+    key: Option<&MemFileKey>,
+) -> Result<(), MemFileError> {
+    let mut decoder = MemFileDecoder::new(reader, key)?;
+    let mem_size = regions.iter().map(|region| region.len()).sum();
+    if decoder.header.mem_size != mem_size {
+        return Err(MemFileError::Size(decoder.header.mem_size, mem_size));
+    }
+    let mut chunk = vec![0u8; decoder.header.chunk_size as usize];
+
+    for region in regions {
+        let mut offset = 0;
+        while offset < region.len() {
+            let len = min(chunk.len(), u64_to_usize(region.len() - offset));
+            decoder.read_exact(&mut chunk[..len])?;
+            region.write_slice(&chunk[..len], MemoryRegionAddress(offset))?;
+            offset += len as u64;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{multi_region_mem, multi_region_mem_raw};
+    use crate::vstate::memory::GuestAddress;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+    const OTHER_KEY: &str = "HwAeAB0AHAAbABoAGQAYABcAFgAVABQAEwASABEAEAA=";
+
+    fn regions() -> [(GuestAddress, usize); 2] {
+        [
+            (GuestAddress(0), 0x28_0000),
+            (GuestAddress(0x100_0000), 0x1000),
+        ]
+    }
+
+    fn source_mem() -> GuestMemoryMmap {
+        let mem = multi_region_mem(&regions());
+        // Half of the memory holds a repeating pattern, and half of it stays zeroed.
+        let pattern = (0..0x14_0000u32)
+            .map(|i| u8::try_from(i % 251).unwrap())
+            .collect::<Vec<_>>();
+        mem.write_slice(&pattern, GuestAddress(0x8_0000)).unwrap();
+        mem.write_slice(&[0xab; 0x1000], GuestAddress(0x100_0000))
+            .unwrap();
+        mem
+    }
+
+    fn encoding(compression: Option<MemFileCompression>, key: Option<&str>) -> MemFileEncoding {
+        MemFileEncoding {
+            compression,
+            encryption_key: key.map(|key| MemFileKey(key.to_string())),
+        }
+    }
+
+    fn decode(file: &[u8], key: Option<&str>) -> Result<GuestMemoryMmap, MemFileError> {
+        let regions = multi_region_mem_raw(&regions());
+        let key = key.map(|key| MemFileKey(key.to_string()));
+        decode_guest_memory(&regions, file, key.as_ref())?;
+        Ok(GuestMemoryMmap::from_regions(regions).unwrap())
+    }
+
+    fn assert_same_mem(a: &GuestMemoryMmap, b: &GuestMemoryMmap) {
+        for (region_a, region_b) in a.iter().zip(b.iter()) {
+            let mut buf_a = vec![0u8; u64_to_usize(region_a.len())];
+            let mut buf_b = vec![0u8; u64_to_usize(region_b.len())];
+            region_a
+                .read_slice(&mut buf_a, MemoryRegionAddress(0))
+                .unwrap();
+            region_b
+                .read_slice(&mut buf_b, MemoryRegionAddress(0))
+                .unwrap();
+            assert!(buf_a == buf_b);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mem = source_mem();
+        for compression in [
+            None,
+            Some(MemFileCompression::Zstd),
+            Some(MemFileCompression::Lz4),
+        ] {
+            for key in [None, Some(KEY)] {
+                let file =
+                    encode_guest_memory(&mem, Vec::new(), &encoding(compression, key)).unwrap();
+                if compression.is_some() {
+                    assert!(file.len() < 0x28_1000 / 2);
+                }
+                if key.is_some() {
+                    // The pattern does not appear in clear text.
+                    let pattern = (0..64u8).collect::<Vec<_>>();
+                    assert!(!file.windows(pattern.len()).any(|w| w == pattern));
+                }
+
+                let mem_file_key = key.map(|key| MemFileKey(key.to_string()));
+                let decoder = MemFileDecoder::new(file.as_slice(), mem_file_key.as_ref()).unwrap();
+                assert_eq!(
+                    decoder.header().format,
+                    MemFileFormat {
+                        compression,
+                        encrypted: key.is_some()
+                    }
+                );
+                assert_eq!(decoder.header().mem_size, 0x28_1000);
+
+                assert_same_mem(&mem, &decode(&file, key).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys() {
+        let mem = source_mem();
+        for invalid_key in ["not base64!", "AAECAwQFBgcICQoLDA0ODw=="] {
+            assert!(matches!(
+                validate_key(&MemFileKey(invalid_key.to_string())),
+                Err(MemFileError::InvalidKey)
+            ));
+            assert!(matches!(
+                encode_guest_memory(&mem, Vec::new(), &encoding(None, Some(invalid_key))),
+                Err(MemFileError::InvalidKey)
+            ));
+        }
+        validate_key(&MemFileKey(KEY.to_string())).unwrap();
+
+        let encrypted = encode_guest_memory(&mem, Vec::new(), &encoding(None, Some(KEY))).unwrap();
+        let clear = encode_guest_memory(&mem, Vec::new(), &encoding(None, None)).unwrap();
+        assert!(matches!(
+            decode(&encrypted, None),
+            Err(MemFileError::MissingKey)
+        ));
+        assert!(matches!(
+            decode(&encrypted, Some(OTHER_KEY)),
+            Err(MemFileError::CorruptedChunk(0))
+        ));
+        assert!(matches!(
+            decode(&clear, Some(KEY)),
+            Err(MemFileError::NotEncrypted)
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_file() {
+        let mem = source_mem();
+        let file = encode_guest_memory(
+            &mem,
+            Vec::new(),
+            &encoding(Some(MemFileCompression::Zstd), Some(KEY)),
+        )
+        .unwrap();
+
+        // Not an encoded memory file.
+        assert!(matches!(
+            decode(&vec![0u8; file.len()], Some(KEY)),
+            Err(MemFileError::Magic)
+        ));
+
+        // Tampered header, which is authenticated with every chunk.
+        let mut tampered = file.clone();
+        tampered[31] ^= 1;
+        assert!(matches!(
+            decode(&tampered, Some(KEY)),
+            Err(MemFileError::CorruptedChunk(0))
+        ));
+
+        // Tampered payload.
+        let mut tampered = file.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(matches!(
+            decode(&tampered, Some(KEY)),
+            Err(MemFileError::CorruptedChunk(3))
+        ));
+
+        // Truncated file.
+        assert!(matches!(
+            decode(&file[..file.len() - 1], Some(KEY)),
+            Err(MemFileError::Io(_))
+        ));
+
+        // Guest memory of a different size.
+        let small_mem = multi_region_mem(&[(GuestAddress(0), 0x1000)]);
+        let small_file =
+            encode_guest_memory(&small_mem, Vec::new(), &encoding(None, Some(KEY))).unwrap();
+        assert!(matches!(
+            decode(&small_file, Some(KEY)),
+            Err(MemFileError::Size(0x1000, 0x28_1000))
+        ));
+    }
+}
@@ -23,7 +23,11 @@
 //!
 //! The snapshot format uses a version value in the form of `MAJOR.MINOR.PATCH`. The version is
 //! provided by the library clients (it is not tied to this crate).
+//!
+//! The [`mem_file`] module implements the optionally compressed and encrypted format of the
+//! guest memory files of snapshots.
 pub mod crc;
+pub mod mem_file;
 mod persist;
 use std::fmt::Debug;
 use std::io::{Read, Write};
@@ -152,6 +156,12 @@ impl<Data: DeserializeOwned> Snapshot<Data> {
     /// Loads a snapshot from the given [`Read`] instance, performing all validations
     /// (CRC, snapshot magic value, snapshot version).
     pub fn load<R: Read>(reader: &mut R) -> Result<Self, SnapshotError> {
+        Self::load_with_checksum(reader).map(|(snapshot, _)| snapshot)
+    }
+
+    /// Loads a snapshot like [`Snapshot::load`], and also returns the CRC64 checksum stored
+    /// in it.
+    pub fn load_with_checksum<R: Read>(reader: &mut R) -> Result<(Self, u64), SnapshotError> {
         // read_to_end internally right-sizes the buffer, so no reallocations due to growing buffers
         // will happen.
         let mut buf = Vec::new();
@@ -164,17 +174,20 @@ impl<Data: DeserializeOwned> Snapshot<Data> {
         if computed_checksum != 0 {
             return Err(SnapshotError::Crc64);
         }
-        Ok(snapshot)
+        let checksum = buf.last_chunk().ok_or(SnapshotError::Crc64)?;
+        Ok((snapshot, u64::from_le_bytes(*checksum)))
     }
 }
 
 impl<Data: Serialize> Snapshot<Data> {
     /// Saves `self` to the given [`Write`] instance, computing the CRC of the written data,
-    /// and then writing the CRC into the `Write` instance, too.
-    pub fn save<W: Write>(&self, writer: &mut W) -> Result<(), SnapshotError> {
+    /// and then writing the CRC into the `Write` instance, too. Returns the CRC.
+    pub fn save<W: Write>(&self, writer: &mut W) -> Result<u64, SnapshotError> {
         let mut crc_writer = CRC64Writer::new(writer);
         serialize(self, &mut crc_writer)?;
-        serialize(&crc_writer.checksum(), crc_writer.writer)
+        let checksum = crc_writer.checksum();
+        serialize(&checksum, crc_writer.writer)?;
+        Ok(checksum)
     }
 }
 
@@ -192,6 +205,21 @@ mod tests {
         Snapshot::<MicrovmState>::load(&mut buf.as_slice()).unwrap();
     }
 
+    #[test]
+    fn test_snapshot_checksum() {
+        let mut buf = Vec::new();
+
+        let checksum = Snapshot::new(42u64).save(&mut buf).unwrap();
+        let (snapshot, loaded_checksum) =
+            Snapshot::<u64>::load_with_checksum(&mut buf.as_slice()).unwrap();
+        assert_eq!(snapshot.data, 42);
+        assert_eq!(loaded_checksum, checksum);
+
+        // Different data gives a different checksum.
+        let mut other_buf = Vec::new();
+        assert_ne!(Snapshot::new(43u64).save(&mut other_buf).unwrap(), checksum);
+    }
+
     #[test]
     fn test_parse_version_from_file() {
         let snapshot = Snapshot::new(42);
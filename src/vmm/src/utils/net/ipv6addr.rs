@@ -0,0 +1,53 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::net::Ipv6Addr;
+
+/// Checks if an IPv6 address is a unicast link-local (RFC 4291) or a unique local (RFC 4193)
+/// address, which are the only addresses the MMDS may use over IPv6.
+pub fn is_link_local_or_unique_local(ipv6_addr: Ipv6Addr) -> bool {
+    ipv6_addr.is_unicast_link_local() || ipv6_addr.is_unique_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv6Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_is_link_local_or_unique_local() {
+        // Global, loopback, unspecified and multicast addresses are not valid.
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::new(
+            0x2001, 0xdb8, 0, 0, 0, 0, 0, 1
+        )));
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::LOCALHOST));
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::UNSPECIFIED));
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::new(
+            0xff02, 0, 0, 0, 0, 0, 0, 1
+        )));
+        // Just outside of fe80::/10 and fc00::/7.
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfec0, 0, 0, 0, 0, 0, 0, 1
+        )));
+        assert!(!is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfe00, 0, 0, 0, 0, 0, 0, 1
+        )));
+
+        // Link-local addresses.
+        assert!(is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfe80, 0, 0, 0, 0, 0, 0xa9fe, 0xa9fe
+        )));
+        assert!(is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfebf, 0xffff, 0, 0, 0, 0, 0, 1
+        )));
+
+        // Unique local addresses.
+        assert!(is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfc00, 0, 0, 0, 0, 0, 0, 1
+        )));
+        assert!(is_link_local_or_unique_local(Ipv6Addr::new(
+            0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254
+        )));
+    }
+}
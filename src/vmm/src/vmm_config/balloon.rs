@@ -6,7 +6,9 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 pub use crate::devices::virtio::balloon::BALLOON_DEV_ID;
-pub use crate::devices::virtio::balloon::device::BalloonStats;
+pub use crate::devices::virtio::balloon::device::{
+    BalloonPolicy, BalloonStats, HintingState, HintingStatus,
+};
 use crate::devices::virtio::balloon::{Balloon, BalloonConfig};
 
 type MutexBalloon = Arc<Mutex<Balloon>>;
@@ -36,6 +38,16 @@ pub struct BalloonDeviceConfig {
     /// Interval in seconds between refreshing statistics.
     #[serde(default)]
     pub stats_polling_interval_s: u16,
+    /// Option to let the guest hint its free pages, for snapshots to skip them.
+    #[serde(default)]
+    pub free_page_hinting: bool,
+    /// Option to let the guest report its free pages, for the host to reclaim them.
+    #[serde(default)]
+    pub free_page_reporting: bool,
+    /// Policy adjusting the target balloon size to keep an amount of guest memory free. Needs
+    /// the statistics to be enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<BalloonPolicy>,
 }
 
 impl From<BalloonConfig> for BalloonDeviceConfig {
@@ -44,6 +56,9 @@ impl From<BalloonConfig> for BalloonDeviceConfig {
             amount_mib: state.amount_mib,
             deflate_on_oom: state.deflate_on_oom,
             stats_polling_interval_s: state.stats_polling_interval_s,
+            free_page_hinting: state.free_page_hinting,
+            free_page_reporting: state.free_page_reporting,
+            policy: state.policy,
         }
     }
 }
@@ -68,6 +83,24 @@ pub struct BalloonUpdateStatsConfig {
     pub stats_polling_interval_s: u16,
 }
 
+/// The actions of a free page hinting request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BalloonHintingAction {
+    /// Ask the guest to hint its free pages, starting a new run.
+    Start,
+    /// Stop the current run, and let the guest reuse the hinted pages.
+    Stop,
+}
+
+/// The data fed into a free page hinting request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BalloonHintingUpdate {
+    /// Whether to start or stop free page hinting.
+    pub action: BalloonHintingAction,
+}
+
 /// A builder for `Balloon` devices from 'BalloonDeviceConfig'.
 #[cfg_attr(not(test), derive(Default))]
 #[derive(Debug)]
@@ -84,14 +117,18 @@ impl BalloonBuilder {
     /// Inserts a Balloon device in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn set(&mut self, cfg: BalloonDeviceConfig) -> Result<(), BalloonConfigError> {
-        self.inner = Some(Arc::new(Mutex::new(Balloon::new(
+        let mut balloon = Balloon::new(
             cfg.amount_mib,
             cfg.deflate_on_oom,
             cfg.stats_polling_interval_s,
+            cfg.free_page_hinting,
+            cfg.free_page_reporting,
             // `restored` flag is false because this code path
             // is never called by snapshot restore functionality.
             false,
-        )?)));
+        )?;
+        balloon.set_policy(cfg.policy)?;
+        self.inner = Some(Arc::new(Mutex::new(balloon)));
 
         Ok(())
     }
@@ -133,6 +170,9 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         }
     }
 
@@ -143,6 +183,9 @@ pub(crate) mod tests {
             amount_mib: 0,
             deflate_on_oom: false,
             stats_polling_interval_s: 0,
+            free_page_hinting: false,
+            free_page_reporting: false,
+            policy: None,
         };
         assert_eq!(default_balloon_config, balloon_config);
         let mut builder = BalloonBuilder::new();
@@ -158,18 +201,50 @@ pub(crate) mod tests {
         };
     }
 
+    #[test]
+    fn test_balloon_policy() {
+        let policy = BalloonPolicy {
+            target_free_mib: 64,
+            min_mib: 0,
+            max_mib: 256,
+            hysteresis_mib: 16,
+        };
+        let mut builder = BalloonBuilder::new();
+        // The policy needs the statistics.
+        builder
+            .set(BalloonDeviceConfig {
+                policy: Some(policy),
+                ..default_config()
+            })
+            .unwrap_err();
+
+        let balloon_config = BalloonDeviceConfig {
+            stats_polling_interval_s: 1,
+            policy: Some(policy),
+            ..default_config()
+        };
+        builder.set(balloon_config.clone()).unwrap();
+        assert_eq!(builder.get_config().unwrap(), balloon_config);
+    }
+
     #[test]
     fn test_from_balloon_state() {
         let expected_balloon_config = BalloonDeviceConfig {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            free_page_hinting: true,
+            free_page_reporting: false,
+            policy: None,
         };
 
         let actual_balloon_config = BalloonDeviceConfig::from(BalloonConfig {
             amount_mib: 5,
             deflate_on_oom: false,
             stats_polling_interval_s: 3,
+            free_page_hinting: true,
+            free_page_reporting: false,
+            policy: None,
         });
 
         assert_eq!(expected_balloon_config, actual_balloon_config);
@@ -178,7 +253,7 @@ pub(crate) mod tests {
     #[test]
     fn test_set_device() {
         let mut builder = BalloonBuilder::new();
-        let balloon = Balloon::new(0, true, 0, true).unwrap();
+        let balloon = Balloon::new(0, true, 0, false, false, true).unwrap();
         builder.set_device(Arc::new(Mutex::new(balloon)));
         assert!(builder.inner.is_some());
     }
@@ -0,0 +1,61 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+use crate::DeviceHotplugError;
+use crate::devices::virtio::generated::virtio_ids::{VIRTIO_ID_BLOCK, VIRTIO_ID_NET};
+
+/// The data fed into a device unplug request. Exactly one of the ids must be set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceUnplugConfig {
+    /// Id of the block device to unplug.
+    pub drive_id: Option<String>,
+    /// Id of the network interface to unplug.
+    pub iface_id: Option<String>,
+}
+
+impl DeviceUnplugConfig {
+    /// Returns the VirtIO device type and the id of the device to unplug.
+    pub fn device(&self) -> Result<(u32, &str), DeviceHotplugError> {
+        match (&self.drive_id, &self.iface_id) {
+            (Some(drive_id), None) => Ok((VIRTIO_ID_BLOCK, drive_id)),
+            (None, Some(iface_id)) => Ok((VIRTIO_ID_NET, iface_id)),
+            _ => Err(DeviceHotplugError::InvalidUnplugConfig),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_unplug_config() {
+        let config = DeviceUnplugConfig {
+            drive_id: Some("scratch".to_string()),
+            iface_id: None,
+        };
+        assert_eq!(config.device().unwrap(), (VIRTIO_ID_BLOCK, "scratch"));
+
+        let config = DeviceUnplugConfig {
+            drive_id: None,
+            iface_id: Some("eth1".to_string()),
+        };
+        assert_eq!(config.device().unwrap(), (VIRTIO_ID_NET, "eth1"));
+
+        config_error(DeviceUnplugConfig::default());
+        config_error(DeviceUnplugConfig {
+            drive_id: Some("scratch".to_string()),
+            iface_id: Some("eth1".to_string()),
+        });
+    }
+
+    fn config_error(config: DeviceUnplugConfig) {
+        assert!(matches!(
+            config.device(),
+            Err(DeviceHotplugError::InvalidUnplugConfig)
+        ));
+    }
+}
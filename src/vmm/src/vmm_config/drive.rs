@@ -8,9 +8,12 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 use super::RateLimiterConfig;
+use super::rate_limiter_group::RateLimiterGroupError;
 use crate::VmmError;
 use crate::devices::virtio::block::device::Block;
-pub use crate::devices::virtio::block::virtio::device::FileEngineType;
+pub use crate::devices::virtio::block::virtio::device::{
+    DiscardConfig, FileEngineType, ImageFormat,
+};
 use crate::devices::virtio::block::{BlockError, CacheType};
 
 /// Errors associated with the operations allowed on a drive.
@@ -22,6 +25,8 @@ pub enum DriveError {
     CreateRateLimiter(io::Error),
     /// Unable to patch the block device: {0} Please verify the request arguments.
     DeviceUpdate(VmmError),
+    /// Invalid rate limiter group: {0}
+    RateLimiterGroup(RateLimiterGroupError),
     /// A root block device already exists!
     RootBlockDeviceAlreadyAdded,
 }
@@ -52,12 +57,19 @@ pub struct BlockDeviceConfig {
     pub path_on_host: Option<String>,
     /// Rate Limiter for I/O operations.
     pub rate_limiter: Option<RateLimiterConfig>,
+    /// Id of the rate limiter group the drive shares its I/O budget with, as an alternative to
+    /// its own rate limiter.
+    pub rate_limiter_group: Option<String>,
     /// The type of IO engine used by the device.
     // #[serde(default)]
     // #[serde(rename = "io_engine")]
     // pub file_engine_type: FileEngineType,
     #[serde(rename = "io_engine")]
     pub file_engine_type: Option<FileEngineType>,
+    /// Enables discard and write zeroes requests with the given limits.
+    pub discard: Option<DiscardConfig>,
+    /// The format of the disk image.
+    pub format: Option<ImageFormat>,
 
     // VhostUserBlock specific fields
     /// Path to the vhost-user socket.
@@ -166,6 +178,18 @@ impl BlockBuilder {
         Ok(())
     }
 
+    /// Returns the block device with the specified `drive_id`, if it exists.
+    pub fn get(&self, drive_id: &str) -> Option<&Arc<Mutex<Block>>> {
+        self.get_index_of_drive_id(drive_id)
+            .map(|index| &self.devices[index])
+    }
+
+    /// Removes the block device with the specified `drive_id` from the list, and returns it.
+    pub fn remove(&mut self, drive_id: &str) -> Option<Arc<Mutex<Block>>> {
+        self.get_index_of_drive_id(drive_id)
+            .and_then(|index| self.devices.remove(index))
+    }
+
     /// Returns a vec with the structures used to configure the devices.
     pub fn configs(&self) -> Vec<BlockDeviceConfig> {
         self.devices
@@ -201,7 +225,10 @@ mod tests {
 
                 path_on_host: self.path_on_host.clone(),
                 rate_limiter: self.rate_limiter,
+                rate_limiter_group: self.rate_limiter_group.clone(),
                 file_engine_type: self.file_engine_type,
+                discard: self.discard,
+                format: self.format,
 
                 socket: self.socket.clone(),
             }
@@ -228,7 +255,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -260,7 +290,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(dummy_path),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -289,7 +322,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -305,7 +341,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -332,7 +371,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -348,7 +390,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -364,7 +409,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -405,7 +453,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -421,7 +472,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -437,7 +491,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_3),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -479,7 +536,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1.clone()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -495,7 +555,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2.clone()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -563,7 +626,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_1),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -579,7 +645,10 @@ mod tests {
             is_read_only: Some(false),
             path_on_host: Some(dummy_path_2),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -605,7 +674,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(dummy_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: Some(FileEngineType::Sync),
+            discard: None,
+            format: Some(ImageFormat::Raw),
 
             socket: None,
         };
@@ -633,7 +705,10 @@ mod tests {
             is_read_only: Some(true),
             path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
             rate_limiter: None,
+            rate_limiter_group: None,
             file_engine_type: None,
+            discard: None,
+            format: None,
 
             socket: None,
         };
@@ -647,4 +722,44 @@ mod tests {
             block_id
         );
     }
+
+    #[test]
+    fn test_get_remove_device() {
+        let mut block_devs = BlockBuilder::new();
+        let backing_file = TempFile::new().unwrap();
+
+        for drive_id in ["first", "second"] {
+            block_devs
+                .insert(BlockDeviceConfig {
+                    drive_id: drive_id.to_string(),
+                    partuuid: None,
+                    is_root_device: false,
+                    cache_type: CacheType::default(),
+
+                    is_read_only: Some(true),
+                    path_on_host: Some(backing_file.as_path().to_str().unwrap().to_string()),
+                    rate_limiter: None,
+                    rate_limiter_group: None,
+                    file_engine_type: None,
+                    discard: None,
+                    format: None,
+
+                    socket: None,
+                })
+                .unwrap();
+        }
+
+        assert_eq!(
+            block_devs.get("first").unwrap().lock().unwrap().id(),
+            "first"
+        );
+        assert!(block_devs.get("third").is_none());
+        assert!(block_devs.remove("third").is_none());
+
+        let removed = block_devs.remove("first").unwrap();
+        assert_eq!(removed.lock().unwrap().id(), "first");
+        assert!(block_devs.get("first").is_none());
+        assert_eq!(block_devs.devices.len(), 1);
+        assert_eq!(block_devs.get_index_of_drive_id("second"), Some(0));
+    }
 }
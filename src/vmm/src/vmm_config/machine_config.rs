@@ -22,15 +22,22 @@ pub enum MachineConfigError {
     InvalidMemorySize,
     /// The number of vCPUs must be greater than 0, less than {MAX_SUPPORTED_VCPUS:} and must be 1 or an even number if SMT is enabled.
     InvalidVcpuCount,
+    /// The maximum number of vCPUs must be at least the number of vCPUs, at most {MAX_SUPPORTED_VCPUS:} and must be 1 or an even number if SMT is enabled.
+    InvalidMaxVcpus,
     /// Could not get the configuration of the previously installed balloon device to validate the memory size.
     InvalidVmState,
     /// Enabling simultaneous multithreading is not supported on aarch64.
     #[cfg(target_arch = "aarch64")]
     SmtNotSupported,
+    /// vCPU hotplug is not supported on aarch64.
+    #[cfg(target_arch = "aarch64")]
+    VcpuHotplugNotSupported,
     /// Could not determine host kernel version when checking hugetlbfs compatibility
     KernelVersion,
     /// Firecracker's huge pages support is incompatible with memory ballooning.
     BalloonAndHugePages,
+    /// Firecracker's huge pages support is incompatible with memory hotplug.
+    MemoryHotplugAndHugePages,
 }
 
 /// Describes the possible (huge)page configurations for a microVM's memory.
@@ -95,6 +102,9 @@ impl From<HugePageConfig> for Option<memfd::HugetlbSize> {
 pub struct MachineConfig {
     /// Number of vcpu to start.
     pub vcpu_count: u8,
+    /// Maximum number of vcpus the microVM can have through vCPU hotplug.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_vcpus: Option<u8>,
     /// The memory size in MiB.
     pub mem_size_mib: usize,
     /// Enables or disabled SMT.
@@ -152,6 +162,7 @@ impl Default for MachineConfig {
     fn default() -> Self {
         Self {
             vcpu_count: 1,
+            max_vcpus: None,
             mem_size_mib: DEFAULT_MEM_SIZE_MIB,
             smt: false,
             cpu_template: None,
@@ -175,6 +186,9 @@ pub struct MachineConfigUpdate {
     /// Number of vcpu to start.
     #[serde(default)]
     pub vcpu_count: Option<u8>,
+    /// Maximum number of vcpus the microVM can have through vCPU hotplug.
+    #[serde(default)]
+    pub max_vcpus: Option<u8>,
     /// The memory size in MiB.
     #[serde(default)]
     pub mem_size_mib: Option<usize>,
@@ -209,6 +223,7 @@ impl From<MachineConfig> for MachineConfigUpdate {
     fn from(cfg: MachineConfig) -> Self {
         MachineConfigUpdate {
             vcpu_count: Some(cfg.vcpu_count),
+            max_vcpus: cfg.max_vcpus,
             mem_size_mib: Some(cfg.mem_size_mib),
             smt: Some(cfg.smt),
             cpu_template: cfg.static_template(),
@@ -220,12 +235,26 @@ impl From<MachineConfig> for MachineConfigUpdate {
     }
 }
 
+/// The data fed into a vCPU hotplug request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VcpuHotplugUpdate {
+    /// Number of vCPUs the microVM should have.
+    pub vcpu_count: u8,
+}
+
 impl MachineConfig {
     /// Sets cpu tempalte field to `CpuTemplateType::Custom(cpu_template)`.
     pub fn set_custom_cpu_template(&mut self, cpu_template: CustomCpuTemplate) {
         self.cpu_template = Some(CpuTemplateType::Custom(cpu_template));
     }
 
+    /// Returns the maximum number of vCPUs of the microVM, which is the number of vCPUs it
+    /// boots with if vCPU hotplug is not configured.
+    pub fn max_vcpus(&self) -> u8 {
+        self.max_vcpus.unwrap_or(self.vcpu_count)
+    }
+
     fn static_template(&self) -> Option<StaticCpuTemplate> {
         match self.cpu_template {
             Some(CpuTemplateType::Static(template)) => Some(template),
@@ -261,6 +290,22 @@ impl MachineConfig {
             return Err(MachineConfigError::InvalidVcpuCount);
         }
 
+        let max_vcpus = update.max_vcpus.or(self.max_vcpus);
+        if let Some(max_vcpus) = max_vcpus {
+            if max_vcpus < vcpu_count || max_vcpus > MAX_SUPPORTED_VCPUS {
+                return Err(MachineConfigError::InvalidMaxVcpus);
+            }
+
+            if smt && max_vcpus > 1 && max_vcpus % 2 == 1 {
+                return Err(MachineConfigError::InvalidMaxVcpus);
+            }
+
+            #[cfg(target_arch = "aarch64")]
+            if max_vcpus > vcpu_count {
+                return Err(MachineConfigError::VcpuHotplugNotSupported);
+            }
+        }
+
         let mem_size_mib = update.mem_size_mib.unwrap_or(self.mem_size_mib);
         let page_config = update.huge_pages.unwrap_or(self.huge_pages);
 
@@ -276,6 +321,7 @@ impl MachineConfig {
 
         Ok(MachineConfig {
             vcpu_count,
+            max_vcpus,
             mem_size_mib,
             smt,
             cpu_template,
@@ -290,7 +336,9 @@ impl MachineConfig {
 #[cfg(test)]
 mod tests {
     use crate::cpu_config::templates::{CpuTemplateType, CustomCpuTemplate, StaticCpuTemplate};
-    use crate::vmm_config::machine_config::MachineConfig;
+    use crate::vmm_config::machine_config::{
+        MachineConfig, MachineConfigError, MachineConfigUpdate,
+    };
 
     // Ensure the special (de)serialization logic for the cpu_template field works:
     // only static cpu templates can be specified via the machine-config endpoint, but
@@ -337,4 +385,73 @@ mod tests {
 
         assert!(deserialized.cpu_template.is_none());
     }
+
+    #[test]
+    fn test_update_max_vcpus() {
+        let mconfig = MachineConfig::default();
+        assert_eq!(mconfig.max_vcpus(), mconfig.vcpu_count);
+
+        // The maximum cannot be lower than the number of vCPUs at boot, nor exceed the number of
+        // supported vCPUs.
+        let update = MachineConfigUpdate {
+            vcpu_count: Some(4),
+            max_vcpus: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            mconfig.update(&update),
+            Err(MachineConfigError::InvalidMaxVcpus)
+        );
+        let update = MachineConfigUpdate {
+            max_vcpus: Some(33),
+            ..Default::default()
+        };
+        assert_eq!(
+            mconfig.update(&update),
+            Err(MachineConfigError::InvalidMaxVcpus)
+        );
+
+        // The maximum is kept when the number of vCPUs is updated.
+        let update = MachineConfigUpdate {
+            vcpu_count: Some(2),
+            max_vcpus: Some(2),
+            ..Default::default()
+        };
+        let mconfig = mconfig.update(&update).unwrap();
+        let update = MachineConfigUpdate {
+            vcpu_count: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(
+            mconfig.update(&update),
+            Err(MachineConfigError::InvalidMaxVcpus)
+        );
+
+        let update = MachineConfigUpdate {
+            max_vcpus: Some(8),
+            ..Default::default()
+        };
+        #[cfg(target_arch = "x86_64")]
+        {
+            let mconfig = mconfig.update(&update).unwrap();
+            assert_eq!(mconfig.vcpu_count, 2);
+            assert_eq!(mconfig.max_vcpus(), 8);
+
+            // With SMT, the maximum must be even.
+            let update = MachineConfigUpdate {
+                smt: Some(true),
+                max_vcpus: Some(7),
+                ..Default::default()
+            };
+            assert_eq!(
+                mconfig.update(&update),
+                Err(MachineConfigError::InvalidMaxVcpus)
+            );
+        }
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(
+            mconfig.update(&update),
+            Err(MachineConfigError::VcpuHotplugNotSupported)
+        );
+    }
 }
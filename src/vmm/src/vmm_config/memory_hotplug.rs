@@ -0,0 +1,148 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use serde::{Deserialize, Serialize};
+
+use crate::devices::virtio::mem::VirtioMem;
+use crate::utils::{bytes_to_mib, u64_to_usize};
+
+/// Default granularity, in MiB, at which memory is hotplugged.
+pub const DEFAULT_BLOCK_SIZE_MIB: usize = 2;
+/// Largest supported block size, in MiB, bound by the alignment of the hotplug region.
+pub const MAX_BLOCK_SIZE_MIB: usize = 1024;
+
+/// Errors associated with configuring memory hotplug.
+#[derive(Debug, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+pub enum MemoryHotplugConfigError {
+    /// Memory hotplug is not configured.
+    DeviceNotFound,
+    /// Block size must be a power of two between 2 and 1024 MiB.
+    InvalidBlockSize,
+    /// Total size must be a non-zero multiple of the block size.
+    InvalidTotalSize,
+    /// Requested size must be a multiple of the block size and not exceed the total size.
+    InvalidRequestedSize,
+    /// Firecracker's huge pages support is incompatible with memory hotplug.
+    HugePages,
+}
+
+/// This struct represents the strongly typed equivalent of the json body
+/// from memory hotplug configuration requests.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryHotplugConfig {
+    /// Maximum amount of memory, in MiB, that can be hotplugged into the guest.
+    pub total_size_mib: usize,
+    /// Granularity, in MiB, at which memory is plugged and unplugged.
+    #[serde(default = "default_block_size_mib")]
+    pub block_size_mib: usize,
+}
+
+fn default_block_size_mib() -> usize {
+    DEFAULT_BLOCK_SIZE_MIB
+}
+
+impl MemoryHotplugConfig {
+    /// Checks that the sizes in this configuration are consistent.
+    pub fn validate(&self) -> Result<(), MemoryHotplugConfigError> {
+        if !self.block_size_mib.is_power_of_two()
+            || self.block_size_mib < DEFAULT_BLOCK_SIZE_MIB
+            || self.block_size_mib > MAX_BLOCK_SIZE_MIB
+        {
+            return Err(MemoryHotplugConfigError::InvalidBlockSize);
+        }
+        if self.total_size_mib == 0 || self.total_size_mib % self.block_size_mib != 0 {
+            return Err(MemoryHotplugConfigError::InvalidTotalSize);
+        }
+        Ok(())
+    }
+}
+
+impl From<&VirtioMem> for MemoryHotplugConfig {
+    fn from(dev: &VirtioMem) -> Self {
+        MemoryHotplugConfig {
+            total_size_mib: bytes_to_mib(u64_to_usize(dev.region_size())),
+            block_size_mib: bytes_to_mib(u64_to_usize(dev.block_size())),
+        }
+    }
+}
+
+/// The data fed into a memory hotplug update request.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemoryHotplugSizeUpdate {
+    /// Amount of hotpluggable memory, in MiB, the guest is asked to use.
+    pub requested_size_mib: usize,
+}
+
+/// The current state of memory hotplug, as reported by the API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MemoryHotplugStatus {
+    /// Maximum amount of memory, in MiB, that can be hotplugged into the guest.
+    pub total_size_mib: usize,
+    /// Granularity, in MiB, at which memory is plugged and unplugged.
+    pub block_size_mib: usize,
+    /// Amount of memory, in MiB, currently plugged by the guest.
+    pub plugged_size_mib: usize,
+    /// Amount of memory, in MiB, the guest was asked to plug.
+    pub requested_size_mib: usize,
+}
+
+impl From<&MemoryHotplugConfig> for MemoryHotplugStatus {
+    fn from(config: &MemoryHotplugConfig) -> Self {
+        MemoryHotplugStatus {
+            total_size_mib: config.total_size_mib,
+            block_size_mib: config.block_size_mib,
+            plugged_size_mib: 0,
+            requested_size_mib: 0,
+        }
+    }
+}
+
+impl From<&VirtioMem> for MemoryHotplugStatus {
+    fn from(dev: &VirtioMem) -> Self {
+        MemoryHotplugStatus {
+            plugged_size_mib: bytes_to_mib(u64_to_usize(dev.plugged_size())),
+            requested_size_mib: bytes_to_mib(u64_to_usize(dev.requested_size())),
+            ..MemoryHotplugStatus::from(&MemoryHotplugConfig::from(dev))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_validation() {
+        let config: MemoryHotplugConfig =
+            serde_json::from_str(r#"{"total_size_mib": 1024}"#).unwrap();
+        assert_eq!(config.block_size_mib, DEFAULT_BLOCK_SIZE_MIB);
+        config.validate().unwrap();
+
+        for block_size_mib in [0, 1, 3, 2048] {
+            let config = MemoryHotplugConfig {
+                total_size_mib: 4096,
+                block_size_mib,
+            };
+            assert_eq!(
+                config.validate(),
+                Err(MemoryHotplugConfigError::InvalidBlockSize)
+            );
+        }
+
+        for total_size_mib in [0, 130] {
+            let config = MemoryHotplugConfig {
+                total_size_mib,
+                block_size_mib: 128,
+            };
+            assert_eq!(
+                config.validate(),
+                Err(MemoryHotplugConfigError::InvalidTotalSize)
+            );
+        }
+
+        serde_json::from_str::<MemoryHotplugConfig>(r#"{"total_size_mib": 1024, "foo": 1}"#)
+            .unwrap_err();
+    }
+}
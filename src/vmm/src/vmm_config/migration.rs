@@ -0,0 +1,84 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configurations used in the live migration context.
+
+use serde::{Deserialize, Serialize};
+
+/// Default number of pre-copy passes over dirty guest memory, after the initial full copy.
+pub const DEFAULT_MAX_ITERATIONS: u32 = 10;
+/// Default amount of dirty guest memory, in MiB, below which the pre-copy phase stops.
+pub const DEFAULT_DIRTY_THRESHOLD_MIB: u64 = 16;
+
+/// The kind of socket used to transfer the microVM between hosts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+pub enum MigrationSocketType {
+    /// Unix domain stream socket. The address is a path on the host.
+    Unix,
+    /// TCP socket. The address is an `IP:port` pair.
+    Tcp,
+}
+
+/// Describes the socket a microVM is migrated through.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MigrationSocketConfig {
+    /// Specifies the socket type.
+    pub socket_type: MigrationSocketType,
+    /// Unix socket path or `IP:port` pair, depending on the socket type.
+    pub address: String,
+}
+
+/// Stores the configuration used for sending a running microVM to another Firecracker process.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SendMigrationParams {
+    /// Socket the destination Firecracker process is listening on.
+    pub socket: MigrationSocketConfig,
+    /// Maximum number of passes over dirty guest memory while the vCPUs are running.
+    #[serde(default = "default_max_iterations")]
+    pub max_iterations: u32,
+    /// The pre-copy phase stops once a pass sends at most this many MiB of dirty memory.
+    #[serde(default = "default_dirty_threshold_mib")]
+    pub dirty_threshold_mib: u64,
+}
+
+/// Stores the configuration used for receiving a microVM from another Firecracker process.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReceiveMigrationParams {
+    /// Socket to listen on for the incoming migration.
+    pub socket: MigrationSocketConfig,
+    /// Whether KVM dirty page tracking should be enabled on the received microVM.
+    #[serde(default)]
+    pub track_dirty_pages: bool,
+    /// Whether the received microVM should be resumed once the migration completes.
+    #[serde(default)]
+    pub resume_vm: bool,
+}
+
+/// Status of the last live migration sent by this Firecracker process, as reported by the API.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum MigrationStatus {
+    /// No live migration was sent.
+    #[default]
+    NotStarted,
+    /// A live migration is being sent.
+    InProgress,
+    /// The live migration completed, the microVM now runs on the destination.
+    Completed,
+    /// The live migration failed.
+    Failed {
+        /// Description of the failure.
+        error: String,
+    },
+}
+
+fn default_max_iterations() -> u32 {
+    DEFAULT_MAX_ITERATIONS
+}
+
+fn default_dirty_threshold_mib() -> u64 {
+    DEFAULT_DIRTY_THRESHOLD_MIB
+}
@@ -1,11 +1,12 @@
 // Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use serde::{Deserialize, Serialize};
 
 use crate::mmds::data_store;
 use crate::mmds::data_store::MmdsVersion;
+use crate::mmds::policy::{MmdsPolicy, MmdsPolicyError};
 
 /// Keeps the MMDS configuration.
 #[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
@@ -18,9 +19,15 @@ pub struct MmdsConfig {
     pub network_interfaces: Vec<String>,
     /// MMDS IPv4 configured address.
     pub ipv4_address: Option<Ipv4Addr>,
+    /// MMDS IPv6 configured address. The MMDS is only reachable over IPv6 if one is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_address: Option<Ipv6Addr>,
     /// Compatibility with EC2 IMDS.
     #[serde(default)]
     pub imds_compat: bool,
+    /// Per-path access policy applied to guest requests.
+    #[serde(default, skip_serializing_if = "MmdsPolicy::is_empty")]
+    pub policy: MmdsPolicy,
 }
 
 impl MmdsConfig {
@@ -39,6 +46,12 @@ impl MmdsConfig {
     pub fn ipv4_addr(&self) -> Option<Ipv4Addr> {
         self.ipv4_address
     }
+
+    /// Returns the MMDS IPv6 address if one was configured.
+    /// Otherwise returns None.
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.ipv6_address
+    }
 }
 
 /// MMDS configuration related errors.
@@ -49,8 +62,12 @@ pub enum MmdsConfigError {
     EmptyNetworkIfaceList,
     /// The MMDS IPv4 address is not link local.
     InvalidIpv4Addr,
+    /// The MMDS IPv6 address is neither link local nor unique local.
+    InvalidIpv6Addr,
     /// The list of network interface IDs provided contains at least one ID that does not correspond to any existing network interface.
     InvalidNetworkInterfaceId,
     /// Failed to initialize MMDS data store: {0}
     InitMmdsDatastore(#[from] data_store::MmdsDatastoreError),
+    /// Invalid MMDS policy: {0}
+    InvalidPolicy(#[from] MmdsPolicyError),
 }
@@ -8,6 +8,7 @@ use std::sync::{Arc, Mutex};
 use serde::{Deserialize, Serialize};
 
 use super::RateLimiterConfig;
+use super::rate_limiter_group::RateLimiterGroupError;
 use crate::VmmError;
 use crate::devices::virtio::net::{Net, TapError};
 use crate::utils::net::mac::MacAddr;
@@ -27,6 +28,15 @@ pub struct NetworkInterfaceConfig {
     pub rx_rate_limiter: Option<RateLimiterConfig>,
     /// Rate Limiter for transmitted packages.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// Id of the rate limiter group shared for received packages, as an alternative to
+    /// `rx_rate_limiter`.
+    pub rx_rate_limiter_group: Option<String>,
+    /// Id of the rate limiter group shared for transmitted packages, as an alternative to
+    /// `tx_rate_limiter`.
+    pub tx_rate_limiter_group: Option<String>,
+    /// Number of RX/TX queue pairs. Each queue pair is backed by its own tap queue and
+    /// rate limited independently. Defaults to a single queue pair.
+    pub num_queues: Option<u16>,
 }
 
 impl From<&Net> for NetworkInterfaceConfig {
@@ -39,6 +49,9 @@ impl From<&Net> for NetworkInterfaceConfig {
             guest_mac: net.guest_mac().copied(),
             rx_rate_limiter: rx_rl.into_option(),
             tx_rate_limiter: tx_rl.into_option(),
+            rx_rate_limiter_group: net.rx_rate_limiter().group_id(),
+            tx_rate_limiter_group: net.tx_rate_limiter().group_id(),
+            num_queues: Some(net.num_queue_pairs()),
         }
     }
 }
@@ -71,6 +84,8 @@ pub enum NetworkInterfaceError {
     GuestMacAddressInUse(String),
     /// Cannot open/create the tap device: {0}
     OpenTap(#[from] TapError),
+    /// Invalid rate limiter group: {0}
+    RateLimiterGroup(#[from] RateLimiterGroupError),
 }
 
 /// Builder for a list of network devices.
@@ -103,6 +118,15 @@ impl NetBuilder {
         self.net_devices.push(device);
     }
 
+    /// Removes the network device with the specified `iface_id` from the builder, and returns it.
+    pub fn remove(&mut self, iface_id: &str) -> Option<Arc<Mutex<Net>>> {
+        let index = self
+            .net_devices
+            .iter()
+            .position(|net| net.lock().expect("Poisoned lock").id() == iface_id)?;
+        Some(self.net_devices.remove(index))
+    }
+
     /// Builds a network device based on a network interface config. Keeps a device reference
     /// in the builder's internal list.
     pub fn build(
@@ -161,6 +185,7 @@ impl NetBuilder {
             cfg.guest_mac,
             rx_rate_limiter.unwrap_or_default(),
             tx_rate_limiter.unwrap_or_default(),
+            cfg.num_queues.unwrap_or(1),
         )
         .map_err(NetworkInterfaceError::CreateNetworkDevice)
     }
@@ -180,6 +205,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use crate::devices::virtio::net::{NET_MAX_QUEUE_PAIRS, NetError};
     use crate::rate_limiter::RateLimiter;
 
     impl NetBuilder {
@@ -195,6 +221,9 @@ mod tests {
             guest_mac: Some(MacAddr::from_str(mac).unwrap()),
             rx_rate_limiter: RateLimiterConfig::default().into_option(),
             tx_rate_limiter: RateLimiterConfig::default().into_option(),
+            rx_rate_limiter_group: None,
+            tx_rate_limiter_group: None,
+            num_queues: Some(1),
         }
     }
 
@@ -206,6 +235,9 @@ mod tests {
                 guest_mac: self.guest_mac,
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
+                rx_rate_limiter_group: self.rx_rate_limiter_group.clone(),
+                tx_rate_limiter_group: self.tx_rate_limiter_group.clone(),
+                num_queues: self.num_queues,
             }
         }
     }
@@ -235,6 +267,12 @@ mod tests {
         let netif_1 = create_netif(id_1, host_dev_name_1, guest_mac_1);
         net_builder.build(netif_1).unwrap();
         assert_eq!(net_builder.net_devices.len(), 1);
+
+        // Test remove.
+        assert!(net_builder.remove("id_2").is_none());
+        let removed = net_builder.remove(id_1).unwrap();
+        assert_eq!(removed.lock().unwrap().id(), id_1);
+        assert_eq!(net_builder.net_devices.len(), 0);
     }
 
     #[test]
@@ -325,6 +363,34 @@ mod tests {
         assert_eq!(configs.first().unwrap(), &net_if_cfg);
     }
 
+    #[test]
+    fn test_net_num_queues() {
+        let mut net_builder = NetBuilder::new();
+
+        let mut net_if_cfg = create_netif("mq_id", "mqdev%d", "01:23:45:67:89:0c");
+        net_if_cfg.num_queues = Some(4);
+        net_builder.build(net_if_cfg).unwrap();
+        let configs = net_builder.configs();
+        assert_eq!(configs.first().unwrap().num_queues, Some(4));
+
+        // The default is a single queue pair.
+        let mut net_if_cfg = create_netif("mq_id", "mqdev%d", "01:23:45:67:89:0c");
+        net_if_cfg.num_queues = None;
+        net_builder.build(net_if_cfg).unwrap();
+        let configs = net_builder.configs();
+        assert_eq!(configs.first().unwrap().num_queues, Some(1));
+
+        for num_queues in [0, NET_MAX_QUEUE_PAIRS + 1] {
+            let mut net_if_cfg = create_netif("mq_id", "mqdev%d", "01:23:45:67:89:0c");
+            net_if_cfg.num_queues = Some(num_queues);
+            assert_eq!(
+                net_builder.build(net_if_cfg).err().unwrap().to_string(),
+                NetworkInterfaceError::CreateNetworkDevice(NetError::InvalidQueuePairs(num_queues))
+                    .to_string()
+            );
+        }
+    }
+
     #[test]
     fn test_add_device() {
         let mut net_builder = NetBuilder::new();
@@ -338,6 +404,7 @@ mod tests {
             Some(MacAddr::from_str(guest_mac).unwrap()),
             RateLimiter::default(),
             RateLimiter::default(),
+            1,
         )
         .unwrap();
 
@@ -0,0 +1,223 @@
+// Copyright 2025 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use super::{RateLimiterConfig, RateLimiterUpdate, TokenBucketConfig};
+use crate::rate_limiter::persist::RateLimiterGroupState;
+use crate::rate_limiter::{BucketUpdate, RateLimiterGroup, TokenBucket};
+use crate::snapshot::Persist;
+
+/// Errors associated with the operations allowed on a rate limiter group.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum RateLimiterGroupError {
+    /// A device cannot have both its own rate limiter and a rate limiter group.
+    ConflictingRateLimiters,
+    /// The rate limiter of a device of rate limiter group {0} cannot be updated, update the group
+    /// instead.
+    DeviceInGroup(String),
+    /// Rate limiter group {0} does not exist.
+    GroupNotFound(String),
+}
+
+/// The body of PUT and PATCH requests on `/rate-limiter-groups/{group_id}`. On PATCH requests,
+/// only the provided token buckets are updated.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimiterGroupConfig {
+    /// Unique identifier of the rate limiter group.
+    pub group_id: String,
+    /// Token bucket limiting the bandwidth shared by the devices of the group.
+    pub bandwidth: Option<TokenBucketConfig>,
+    /// Token bucket limiting the operations shared by the devices of the group.
+    pub ops: Option<TokenBucketConfig>,
+}
+
+impl From<&RateLimiterGroup> for RateLimiterGroupConfig {
+    fn from(group: &RateLimiterGroup) -> Self {
+        RateLimiterGroupConfig {
+            group_id: group.id().to_string(),
+            bandwidth: group.bandwidth().map(TokenBucketConfig::from),
+            ops: group.ops().map(TokenBucketConfig::from),
+        }
+    }
+}
+
+impl RateLimiterGroupConfig {
+    fn rate_limiter_update(&self) -> RateLimiterUpdate {
+        RateLimiterUpdate::from(Some(RateLimiterConfig {
+            bandwidth: self.bandwidth,
+            ops: self.ops,
+        }))
+    }
+}
+
+/// Builds a token bucket out of its configuration, or `None` if the bucket is not limiting.
+fn token_bucket(config: Option<TokenBucketConfig>) -> Option<TokenBucket> {
+    config.and_then(|config| {
+        TokenBucket::new(
+            config.size,
+            config.one_time_burst.unwrap_or(0),
+            config.refill_time,
+        )
+    })
+}
+
+/// Wrapper for the collection that holds all the rate limiter groups.
+#[derive(Debug, Default)]
+pub struct RateLimiterGroupBuilder {
+    groups: Vec<Arc<Mutex<RateLimiterGroup>>>,
+}
+
+impl RateLimiterGroupBuilder {
+    /// Creates an empty list of rate limiter groups.
+    pub fn new() -> Self {
+        Self { groups: Vec::new() }
+    }
+
+    /// Inserts a rate limiter group using the specified configuration. If a group with the same
+    /// id already exists, its token buckets are replaced, so the devices that already belong to
+    /// it share the new buckets.
+    pub fn insert(&mut self, config: RateLimiterGroupConfig) {
+        let bandwidth = token_bucket(config.bandwidth);
+        let ops = token_bucket(config.ops);
+        match self.get(&config.group_id) {
+            Ok(group) => group.lock().expect("Poisoned lock").update_buckets(
+                bandwidth.map_or(BucketUpdate::Disabled, BucketUpdate::Update),
+                ops.map_or(BucketUpdate::Disabled, BucketUpdate::Update),
+            ),
+            Err(_) => self.groups.push(Arc::new(Mutex::new(RateLimiterGroup::new(
+                config.group_id,
+                bandwidth,
+                ops,
+            )))),
+        }
+    }
+
+    /// Updates the token buckets of an existing rate limiter group. Only the token buckets
+    /// present in `config` are updated.
+    pub fn update(&self, config: &RateLimiterGroupConfig) -> Result<(), RateLimiterGroupError> {
+        let update = config.rate_limiter_update();
+        self.get(&config.group_id)?
+            .lock()
+            .expect("Poisoned lock")
+            .update_buckets(update.bandwidth, update.ops);
+        Ok(())
+    }
+
+    /// Returns the rate limiter group with the specified `group_id`.
+    pub fn get(
+        &self,
+        group_id: &str,
+    ) -> Result<Arc<Mutex<RateLimiterGroup>>, RateLimiterGroupError> {
+        self.groups
+            .iter()
+            .find(|group| group.lock().expect("Poisoned lock").id() == group_id)
+            .cloned()
+            .ok_or_else(|| RateLimiterGroupError::GroupNotFound(group_id.to_string()))
+    }
+
+    /// Returns all the rate limiter groups.
+    pub fn groups(&self) -> &[Arc<Mutex<RateLimiterGroup>>] {
+        &self.groups
+    }
+
+    /// Returns a vec with the structures used to configure the rate limiter groups.
+    pub fn configs(&self) -> Vec<RateLimiterGroupConfig> {
+        self.groups
+            .iter()
+            .map(|group| RateLimiterGroupConfig::from(&*group.lock().expect("Poisoned lock")))
+            .collect()
+    }
+
+    /// Returns the state of the rate limiter groups, to be saved in a snapshot.
+    pub fn save(&self) -> Vec<RateLimiterGroupState> {
+        self.groups
+            .iter()
+            .map(|group| group.lock().expect("Poisoned lock").save())
+            .collect()
+    }
+
+    /// Replaces the rate limiter groups with the ones saved in a snapshot.
+    pub fn restore(&mut self, states: &[RateLimiterGroupState]) -> Result<(), io::Error> {
+        self.groups = states
+            .iter()
+            .map(|state| Ok(Arc::new(Mutex::new(RateLimiterGroup::restore((), state)?))))
+            .collect::<Result<_, io::Error>>()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_config(group_id: &str, size: u64) -> RateLimiterGroupConfig {
+        RateLimiterGroupConfig {
+            group_id: group_id.to_string(),
+            bandwidth: Some(TokenBucketConfig {
+                size,
+                one_time_burst: None,
+                refill_time: 1000,
+            }),
+            ops: None,
+        }
+    }
+
+    #[test]
+    fn test_insert_and_update() {
+        let mut builder = RateLimiterGroupBuilder::new();
+        assert_eq!(
+            builder.get("grp0").unwrap_err(),
+            RateLimiterGroupError::GroupNotFound(String::from("grp0"))
+        );
+
+        builder.insert(group_config("grp0", 1000));
+        let group = builder.get("grp0").unwrap();
+        assert_eq!(builder.configs(), vec![group_config("grp0", 1000)]);
+
+        // Inserting an existing group replaces its buckets in place.
+        builder.insert(group_config("grp0", 2000));
+        assert!(Arc::ptr_eq(&group, &builder.get("grp0").unwrap()));
+        assert_eq!(builder.configs(), vec![group_config("grp0", 2000)]);
+
+        // Updates only touch the provided buckets.
+        let ops = Some(TokenBucketConfig {
+            size: 10,
+            one_time_burst: Some(5),
+            refill_time: 100,
+        });
+        builder
+            .update(&RateLimiterGroupConfig {
+                group_id: String::from("grp0"),
+                bandwidth: None,
+                ops,
+            })
+            .unwrap();
+        assert_eq!(
+            builder.configs(),
+            vec![RateLimiterGroupConfig {
+                ops,
+                ..group_config("grp0", 2000)
+            }]
+        );
+        assert_eq!(
+            builder.update(&group_config("grp1", 1000)).unwrap_err(),
+            RateLimiterGroupError::GroupNotFound(String::from("grp1"))
+        );
+    }
+
+    #[test]
+    fn test_save_restore() {
+        let mut builder = RateLimiterGroupBuilder::new();
+        builder.insert(group_config("grp0", 1000));
+        builder.insert(group_config("grp1", 2000));
+
+        let mut restored = RateLimiterGroupBuilder::new();
+        restored.restore(&builder.save()).unwrap();
+        assert_eq!(restored.configs(), builder.configs());
+    }
+}
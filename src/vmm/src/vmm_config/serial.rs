@@ -5,10 +5,59 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
+/// Errors associated with the serial console configuration.
+#[derive(Debug, thiserror::Error, displaydoc::Display, PartialEq, Eq)]
+pub enum SerialConfigError {
+    /// The serial console cannot use both an output path and a socket path.
+    ConflictingPaths,
+}
+
 /// The body of a PUT /serial request.
-#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct SerialConfig {
     /// Named pipe or file used as output for guest serial console.
     pub serial_out_path: Option<PathBuf>,
+    /// Unix domain socket on which an interactive client can attach to the guest serial console.
+    pub serial_socket_path: Option<PathBuf>,
+}
+
+impl SerialConfig {
+    /// Checks that at most one of the serial backends is selected.
+    pub fn validate(&self) -> Result<(), SerialConfigError> {
+        if self.serial_out_path.is_some() && self.serial_socket_path.is_some() {
+            return Err(SerialConfigError::ConflictingPaths);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        SerialConfig::default().validate().unwrap();
+        SerialConfig {
+            serial_out_path: Some(PathBuf::from("out")),
+            serial_socket_path: None,
+        }
+        .validate()
+        .unwrap();
+        SerialConfig {
+            serial_out_path: None,
+            serial_socket_path: Some(PathBuf::from("sock")),
+        }
+        .validate()
+        .unwrap();
+        assert_eq!(
+            SerialConfig {
+                serial_out_path: Some(PathBuf::from("out")),
+                serial_socket_path: Some(PathBuf::from("sock")),
+            }
+            .validate(),
+            Err(SerialConfigError::ConflictingPaths)
+        );
+    }
 }
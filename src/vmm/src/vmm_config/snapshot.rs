@@ -3,6 +3,7 @@
 
 //! Configurations used in the snapshotting context.
 
+use std::fmt;
 use std::path::PathBuf;
 
 /// For crates that depend on `vmm` we export.
@@ -33,6 +34,38 @@ pub enum MemBackendType {
     Uffd,
 }
 
+/// The compression algorithms available for the chunks of an encoded memory file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MemFileCompression {
+    /// Zstandard compression, for smaller memory files.
+    Zstd,
+    /// LZ4 compression, for faster snapshot creation and restore.
+    Lz4,
+}
+
+/// Base64 encoded 256-bit key used to encrypt memory files with AES-256-GCM.
+///
+/// The key is redacted from the `Debug` output, so that it never shows up in logs.
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct MemFileKey(pub String);
+
+impl fmt::Debug for MemFileKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MemFileKey(<redacted>)")
+    }
+}
+
+/// Encoding of the memory file of a snapshot, instead of a raw dump of the guest memory.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemFileEncoding {
+    /// Algorithm used to compress each chunk of guest memory.
+    pub compression: Option<MemFileCompression>,
+    /// Key used to encrypt each chunk of guest memory.
+    pub encryption_key: Option<MemFileKey>,
+}
+
 /// Stores the configuration that will be used for creating a snapshot.
 #[derive(Debug, PartialEq, Eq, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -45,6 +78,9 @@ pub struct CreateSnapshotParams {
     pub snapshot_path: PathBuf,
     /// Path to the file that will contain the guest memory.
     pub mem_file_path: PathBuf,
+    /// Encoding of the memory file. The guest memory is dumped as is when not specified.
+    #[serde(default)]
+    pub mem_file_encoding: Option<MemFileEncoding>,
 }
 
 /// Allows for changing the mapping between tap devices and host devices
@@ -57,6 +93,16 @@ pub struct NetworkOverride {
     pub host_dev_name: String,
 }
 
+/// A snapshot in the chain of parents of a diff snapshot.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SnapshotLayer {
+    /// Path to the file that contains the microVM state of the snapshot.
+    pub snapshot_path: PathBuf,
+    /// Path to the file that contains the guest memory of the snapshot.
+    pub mem_file_path: PathBuf,
+}
+
 /// Stores the configuration that will be used for loading a snapshot.
 #[derive(Debug, PartialEq, Eq)]
 pub struct LoadSnapshotParams {
@@ -72,6 +118,11 @@ pub struct LoadSnapshotParams {
     pub resume_vm: bool,
     /// The network devices to override on load.
     pub network_overrides: Vec<NetworkOverride>,
+    /// The parents of a diff snapshot, from the full snapshot at the base of the chain to the
+    /// direct parent of the snapshot.
+    pub parent_layers: Vec<SnapshotLayer>,
+    /// Key used to decrypt an encrypted memory file.
+    pub mem_file_encryption_key: Option<MemFileKey>,
 }
 
 /// Stores the configuration for loading a snapshot that is provided by the user.
@@ -101,6 +152,13 @@ pub struct LoadSnapshotConfig {
     /// The network devices to override on load.
     #[serde(default)]
     pub network_overrides: Vec<NetworkOverride>,
+    /// The parents of a diff snapshot, from the full snapshot at the base of the chain to the
+    /// direct parent of the snapshot.
+    #[serde(default)]
+    pub parent_layers: Vec<SnapshotLayer>,
+    /// Key used to decrypt an encrypted memory file.
+    #[serde(default)]
+    pub mem_file_encryption_key: Option<MemFileKey>,
 }
 
 /// Stores the configuration used for managing snapshot memory.
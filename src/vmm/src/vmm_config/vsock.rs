@@ -6,7 +6,10 @@ use std::sync::{Arc, Mutex};
 
 use serde::{Deserialize, Serialize};
 
-use crate::devices::virtio::vsock::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
+use crate::devices::virtio::vsock::{
+    Vsock, VsockError, VsockPortForwards, VsockUnixBackend, VsockUnixBackendError,
+    seqpacket_sock_path,
+};
 
 type MutexVsockUnix = Arc<Mutex<Vsock<VsockUnixBackend>>>;
 
@@ -32,6 +35,10 @@ pub struct VsockDeviceConfig {
     pub guest_cid: u32,
     /// Path to local unix socket.
     pub uds_path: String,
+    /// Forwarding of guest vsock ports to host TCP addresses, and vice versa.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "VsockPortForwards::is_empty")]
+    pub port_forwards: VsockPortForwards,
 }
 
 #[derive(Debug)]
@@ -47,6 +54,7 @@ impl From<&VsockAndUnixPath> for VsockDeviceConfig {
             vsock_id: None,
             guest_cid: u32::try_from(vsock_lock.cid()).unwrap(),
             uds_path: vsock.uds_path.clone(),
+            port_forwards: vsock_lock.backend().port_forwards().clone(),
         }
     }
 }
@@ -79,8 +87,10 @@ impl VsockBuilder {
     /// Inserts a Unix backend Vsock in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn insert(&mut self, cfg: VsockDeviceConfig) -> Result<(), VsockConfigError> {
-        // Make sure to drop the old one and remove the socket before creating a new one.
+        // Make sure to drop the old one and remove the sockets before creating a new one.
         if let Some(existing) = self.inner.take() {
+            std::fs::remove_file(seqpacket_sock_path(&existing.uds_path))
+                .map_err(VsockUnixBackendError::UnixBind)?;
             std::fs::remove_file(existing.uds_path).map_err(VsockUnixBackendError::UnixBind)?;
         }
         self.inner = Some(VsockAndUnixPath {
@@ -99,7 +109,8 @@ impl VsockBuilder {
     pub fn create_unixsock_vsock(
         cfg: VsockDeviceConfig,
     ) -> Result<Vsock<VsockUnixBackend>, VsockConfigError> {
-        let backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)?;
+        let backend =
+            VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path, cfg.port_forwards)?;
 
         Vsock::new(u64::from(cfg.guest_cid), backend).map_err(VsockConfigError::CreateVsockDevice)
     }
@@ -122,6 +133,7 @@ pub(crate) mod tests {
             vsock_id: None,
             guest_cid: 3,
             uds_path: tmp_sock_file.as_path().to_str().unwrap().to_string(),
+            port_forwards: VsockPortForwards::default(),
         }
     }
 
@@ -164,6 +176,27 @@ pub(crate) mod tests {
         assert_eq!(config.unwrap(), vsock_config);
     }
 
+    #[test]
+    fn test_vsock_port_forwards_config() {
+        let mut vsock_builder = VsockBuilder::new();
+        let mut tmp_sock_file = TempFile::new().unwrap();
+        tmp_sock_file.remove().unwrap();
+        let mut vsock_config = default_config(&tmp_sock_file);
+        // An empty forwarding table is not serialized.
+        assert!(
+            !serde_json::to_string(&vsock_config)
+                .unwrap()
+                .contains("port_forwards")
+        );
+
+        vsock_config.port_forwards = serde_json::from_str(
+            r#"{"host_to_guest": [{"host_addr": "127.0.0.1:0", "vsock_port": 22}]}"#,
+        )
+        .unwrap();
+        vsock_builder.insert(vsock_config.clone()).unwrap();
+        assert_eq!(vsock_builder.config().unwrap(), vsock_config);
+    }
+
     #[test]
     fn test_set_device() {
         let mut vsock_builder = VsockBuilder::new();
@@ -171,8 +204,12 @@ pub(crate) mod tests {
         tmp_sock_file.remove().unwrap();
         let vsock = Vsock::new(
             0,
-            VsockUnixBackend::new(1, tmp_sock_file.as_path().to_str().unwrap().to_string())
-                .unwrap(),
+            VsockUnixBackend::new(
+                1,
+                tmp_sock_file.as_path().to_str().unwrap().to_string(),
+                VsockPortForwards::default(),
+            )
+            .unwrap(),
         )
         .unwrap();
 
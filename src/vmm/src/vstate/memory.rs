@@ -7,6 +7,7 @@
 
 use std::fs::File;
 use std::io::SeekFrom;
+use std::os::fd::AsRawFd;
 use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
@@ -19,6 +20,7 @@ pub use vm_memory::{
 };
 use vm_memory::{Error as VmMemoryError, GuestMemoryError, WriteVolatile};
 use vmm_sys_util::errno;
+use vmm_sys_util::seek_hole::SeekHole;
 
 use crate::DirtyBitmap;
 use crate::utils::{get_page_size, u64_to_usize};
@@ -48,6 +50,8 @@ pub enum MemoryError {
     MemfdSetLen(std::io::Error),
     /// Total sum of memory regions exceeds largest possible file offset
     OffsetTooLarge,
+    /// Cannot map diff layer of memory file: {0}
+    DiffLayer(std::io::Error),
 }
 
 /// Creates a `Vec` of `GuestRegionMmap` with the given configuration
@@ -132,6 +136,55 @@ pub fn snapshot_file(
     create(regions, libc::MAP_PRIVATE, Some(file), track_dirty_pages)
 }
 
+/// Maps the data of the diff snapshot memory `file` over `regions`, which are laid out in the
+/// file like in [`snapshot_file`]. The holes of the file are not mapped, so the regions keep the
+/// contents previously mapped there. Pages are only read from the file when the guest accesses
+/// them.
+pub fn map_diff_layer(regions: &[GuestRegionMmap], file: &mut File) -> Result<(), MemoryError> {
+    let page_size = get_page_size().map_err(MemoryError::PageSize)? as u64;
+    let file_len = file.metadata().map_err(MemoryError::DiffLayer)?.len();
+
+    let mut region_offset = 0;
+    for region in regions {
+        let region_end = region_offset + region.len();
+        let mut cursor = region_offset;
+        while let Some(data_start) = file.seek_data(cursor).map_err(MemoryError::DiffLayer)? {
+            if data_start >= region_end {
+                break;
+            }
+            let data_end = file
+                .seek_hole(data_start)
+                .map_err(MemoryError::DiffLayer)?
+                .unwrap_or(file_len);
+            // Diff snapshots are written in whole pages.
+            let map_start = data_start - data_start % page_size;
+            let map_end = data_end.next_multiple_of(page_size).min(region_end);
+
+            // SAFETY: The mapping is within the bounds of `region`, which is only used by the guest
+            // once the guest memory is built, and the file is kept open by the mapping.
+            let addr = unsafe {
+                libc::mmap(
+                    region
+                        .as_ptr()
+                        .add(u64_to_usize(map_start - region_offset))
+                        .cast(),
+                    u64_to_usize(map_end - map_start),
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_FIXED | libc::MAP_NORESERVE,
+                    file.as_raw_fd(),
+                    i64::try_from(map_start).map_err(|_| MemoryError::OffsetTooLarge)?,
+                )
+            };
+            if addr == libc::MAP_FAILED {
+                return Err(MemoryError::DiffLayer(std::io::Error::last_os_error()));
+            }
+            cursor = map_end;
+        }
+        region_offset = region_end;
+    }
+    Ok(())
+}
+
 /// Defines the interface for snapshotting memory.
 pub trait GuestMemoryExtension
 where
@@ -345,7 +398,8 @@ mod tests {
     #![allow(clippy::undocumented_unsafe_blocks)]
 
     use std::collections::HashMap;
-    use std::io::{Read, Seek};
+    use std::io::{Read, Seek, Write};
+    use std::os::unix::fs::FileExt;
 
     use vmm_sys_util::tempfile::TempFile;
 
@@ -675,6 +729,65 @@ mod tests {
         assert_eq!(expected_first_region, diff_file_content);
     }
 
+    #[test]
+    fn test_map_diff_layer() {
+        let page_size = get_page_size().unwrap();
+
+        // Two regions of two pages each.
+        let region_size = page_size * 2;
+        let mem_regions = [
+            (GuestAddress(0), region_size),
+            (GuestAddress(page_size as u64 * 3), region_size),
+        ];
+        let memory_state = GuestMemoryState {
+            regions: mem_regions
+                .iter()
+                .map(|(addr, size)| GuestMemoryRegionState {
+                    base_address: addr.0,
+                    size: *size,
+                })
+                .collect(),
+        };
+
+        // The base file is filled with 1s.
+        let ones = vec![1u8; page_size];
+        let mut base_file = TempFile::new().unwrap().into_file();
+        base_file.write_all(&ones.repeat(4)).unwrap();
+
+        // The diff file has 2s on the second page of each region.
+        let twos = vec![2u8; page_size];
+        let mut diff_file = TempFile::new().unwrap().into_file();
+        diff_file.set_len(region_size as u64 * 2).unwrap();
+        diff_file.write_all_at(&twos, page_size as u64).unwrap();
+        diff_file.write_all_at(&twos, page_size as u64 * 3).unwrap();
+
+        let regions = snapshot_file(base_file, memory_state.regions(), false).unwrap();
+        map_diff_layer(&regions, &mut diff_file).unwrap();
+        let guest_memory = GuestMemoryMmap::from_regions(regions).unwrap();
+
+        let mut page = vec![0u8; page_size];
+        for (addr, expected) in [
+            (0, &ones),
+            (page_size, &twos),
+            (page_size * 3, &ones),
+            (page_size * 4, &twos),
+        ] {
+            guest_memory
+                .read(page.as_mut_slice(), GuestAddress(addr as u64))
+                .unwrap();
+            assert_eq!(&page, expected);
+        }
+
+        // Writes of the guest do not reach the diff file.
+        guest_memory
+            .write(&ones, GuestAddress(page_size as u64))
+            .unwrap();
+        diff_file
+            .read_exact_at(&mut page, page_size as u64)
+            .unwrap();
+        assert_eq!(page, twos);
+    }
+
     #[test]
     fn test_store_dirty_bitmap() {
         let page_size = get_page_size().unwrap();
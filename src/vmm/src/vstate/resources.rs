@@ -102,6 +102,18 @@ impl ResourceAllocator {
         allocate_many_ids(&mut self.gsi_msi_allocator, gsi_count)
     }
 
+    /// Free GSIs previously allocated for MSI
+    ///
+    /// # Arguments
+    ///
+    /// * `gsis` - The GSIs to free
+    pub fn free_gsi_msi(&mut self, gsis: &[u32]) -> Result<(), vm_allocator::Error> {
+        for gsi in gsis {
+            self.gsi_msi_allocator.free_id(*gsi)?;
+        }
+        Ok(())
+    }
+
     /// Allocate a memory range in 32-bit MMIO address space
     ///
     /// If it succeeds, it returns the first address of the allocated range
@@ -278,6 +290,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_free_gsi() {
+        let mut allocator = ResourceAllocator::new();
+        let gsis = allocator.allocate_gsi_msi(3).unwrap();
+        assert_eq!(
+            gsis,
+            (arch::GSI_MSI_START..arch::GSI_MSI_START + 3).collect::<Vec<_>>()
+        );
+
+        // Freed GSIs can be allocated again
+        allocator.free_gsi_msi(&gsis[..2]).unwrap();
+        assert_eq!(
+            allocator.allocate_gsi_msi(2),
+            Ok(vec![arch::GSI_MSI_START, arch::GSI_MSI_START + 1])
+        );
+
+        // GSIs that are not allocated cannot be freed
+        allocator
+            .free_gsi_msi(&[arch::GSI_MSI_START + 3])
+            .unwrap_err();
+    }
+
     fn clone_allocator(allocator: &ResourceAllocator) -> ResourceAllocator {
         let mut buf = vec![0u8; 1024];
         Snapshot::new(allocator.save())
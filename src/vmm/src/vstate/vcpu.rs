@@ -6,11 +6,13 @@
 // found in the THIRD-PARTY file.
 
 use std::cell::RefCell;
+use std::fs::File;
+use std::io::Write;
 #[cfg(feature = "gdb")]
 use std::os::fd::AsRawFd;
 use std::sync::atomic::{Ordering, fence};
 use std::sync::mpsc::{Receiver, Sender, TryRecvError, channel};
-use std::sync::{Arc, Barrier};
+use std::sync::{Arc, Barrier, OnceLock};
 use std::{fmt, io, thread};
 
 use kvm_bindings::{KVM_SYSTEM_EVENT_RESET, KVM_SYSTEM_EVENT_SHUTDOWN};
@@ -36,6 +38,10 @@ use crate::vstate::vm::Vm;
 /// Signal number (SIGRTMIN) used to kick Vcpus.
 pub const VCPU_RTSIG_OFFSET: i32 = 0;
 
+/// The `cgroup.threads` file of the cgroup that the vcpu threads move into when they start, if
+/// one was set up for them by the jailer.
+pub static VCPU_CGROUP_THREADS: OnceLock<File> = OnceLock::new();
+
 /// Errors associated with the wrappers over KVM ioctls.
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
 pub enum VcpuError {
@@ -69,6 +75,22 @@ pub struct VcpuConfig {
     pub cpu_config: CpuConfiguration,
 }
 
+/// Registers the signal handler used to kick the vcpu running on the current thread, if there
+/// is one.
+fn register_kick_signal_handler() {
+    extern "C" fn handle_signal(_: c_int, _: *mut siginfo_t, _: *mut c_void) {
+        TLS_VCPU_PTR.with(|cell| {
+            if let Some(kvm_run_ptr) = &mut *cell.borrow_mut() {
+                kvm_run_ptr.as_mut_ref().immediate_exit = 1;
+                fence(Ordering::Release);
+            }
+        })
+    }
+
+    register_signal_handler(sigrtmin() + VCPU_RTSIG_OFFSET, handle_signal)
+        .expect("Failed to register vcpu signal handler");
+}
+
 /// Error type for [`Vcpu::start_threaded`].
 #[derive(Debug, derive_more::From, thiserror::Error)]
 #[error("Failed to spawn vCPU thread: {0}")]
@@ -135,18 +157,7 @@ impl Vcpu {
     /// kick the vcpu running on the current thread, if there is one.
     fn register_kick_signal_handler(&mut self) {
         self.init_thread_local_data();
-
-        extern "C" fn handle_signal(_: c_int, _: *mut siginfo_t, _: *mut c_void) {
-            TLS_VCPU_PTR.with(|cell| {
-                if let Some(kvm_run_ptr) = &mut *cell.borrow_mut() {
-                    kvm_run_ptr.as_mut_ref().immediate_exit = 1;
-                    fence(Ordering::Release);
-                }
-            })
-        }
-
-        register_signal_handler(sigrtmin() + VCPU_RTSIG_OFFSET, handle_signal)
-            .expect("Failed to register vcpu signal handler");
+        register_kick_signal_handler();
     }
 
     /// Constructs a new VCPU for `vm`.
@@ -209,6 +220,7 @@ impl Vcpu {
             .name(format!("fc_vcpu {}", self.kvm_vcpu.index))
             .spawn(move || {
                 let filter = &*seccomp_filter;
+                join_vcpu_cgroup(self.kvm_vcpu.index);
                 self.register_kick_signal_handler();
                 // Synchronization to make sure thread local data is initialized.
                 barrier.wait();
@@ -228,15 +240,7 @@ impl Vcpu {
     /// Note that the state of the VCPU and associated VM must be setup first for this to do
     /// anything useful.
     pub fn run(&mut self, seccomp_filter: BpfProgramRef) {
-        // Load seccomp filters for this vCPU thread.
-        // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
-        // altogether is the desired behaviour.
-        if let Err(err) = crate::seccomp::apply_filter(seccomp_filter) {
-            panic!(
-                "Failed to set the requested seccomp filters on vCPU {}: Error: {}",
-                self.kvm_vcpu.index, err
-            );
-        }
+        apply_vcpu_filter(self.kvm_vcpu.index, seccomp_filter);
 
         // Start running the machine state in the `Paused` state.
         StateMachine::run(self, Self::paused);
@@ -638,6 +642,89 @@ pub struct VcpuHandle {
 #[error("Failed to signal vCPU: {0}")]
 pub struct VcpuSendEventError(pub vmm_sys_util::errno::Error);
 
+// Moves the thread of vCPU `index` into the vcpu cgroup, if there is one.
+fn join_vcpu_cgroup(index: u8) {
+    if let Some(mut threads_file) = VCPU_CGROUP_THREADS.get() {
+        // Writing 0 to `cgroup.threads` migrates the writing thread.
+        if let Err(err) = threads_file.write_all(b"0\n") {
+            METRICS.vcpu.failures.inc();
+            error!(
+                "Failed to move vCPU {} into the vcpu cgroup: {}",
+                index, err
+            );
+        }
+    }
+}
+
+// Loads the seccomp filters of the thread of vCPU `index`.
+fn apply_vcpu_filter(index: u8, seccomp_filter: BpfProgramRef) {
+    // Execution panics if filters cannot be loaded, use --no-seccomp if skipping filters
+    // altogether is the desired behaviour.
+    if let Err(err) = crate::seccomp::apply_filter(seccomp_filter) {
+        panic!(
+            "Failed to set the requested seccomp filters on vCPU {}: Error: {}",
+            index, err
+        );
+    }
+}
+
+/// Thread of a vcpu that can be hotplugged into the microVM.
+///
+/// Threads spawned by the VMM thread inherit its seccomp filters, which do not allow running
+/// vcpus. The threads of hotpluggable vcpus are thus started along with the boot vcpus, before the
+/// VMM thread filters are loaded, and wait with the vcpu filters loaded until a vcpu is handed to
+/// them.
+#[derive(Debug)]
+pub struct VcpuSlot {
+    vcpu_sender: Sender<(Vcpu, Arc<Barrier>)>,
+    vcpu_thread: thread::JoinHandle<()>,
+}
+
+impl VcpuSlot {
+    /// Starts the thread that will run the vcpu with index `index` once it is hotplugged.
+    pub fn start_threaded(
+        index: u8,
+        seccomp_filter: Arc<BpfProgram>,
+    ) -> Result<Self, StartThreadedError> {
+        let (vcpu_sender, vcpu_receiver) = channel::<(Vcpu, Arc<Barrier>)>();
+        let vcpu_thread = thread::Builder::new()
+            .name(format!("fc_vcpu {}", index))
+            .spawn(move || {
+                join_vcpu_cgroup(index);
+                // The signal handler is process wide, it only needs the thread local data of the
+                // vcpu to be initialized once it is received.
+                register_kick_signal_handler();
+                apply_vcpu_filter(index, &seccomp_filter);
+
+                // The sender is dropped without a vcpu if the microVM stops before the vcpu is
+                // hotplugged.
+                if let Ok((mut vcpu, barrier)) = vcpu_receiver.recv() {
+                    vcpu.init_thread_local_data();
+                    // Synchronization to make sure thread local data is initialized.
+                    barrier.wait();
+                    // Start running the machine state in the `Paused` state.
+                    StateMachine::run(&mut vcpu, Vcpu::paused);
+                }
+            })?;
+
+        Ok(VcpuSlot {
+            vcpu_sender,
+            vcpu_thread,
+        })
+    }
+
+    /// Hands `vcpu` over to the thread of this slot, and returns the handle of the running vcpu.
+    pub fn start_vcpu(self, mut vcpu: Vcpu, barrier: Arc<Barrier>) -> VcpuHandle {
+        let event_sender = vcpu.event_sender.take().expect("vCPU already started");
+        let response_receiver = vcpu.response_receiver.take().unwrap();
+        self.vcpu_sender
+            .send((vcpu, barrier))
+            .expect("vcpu sender channel closed on vcpu slot end.");
+
+        VcpuHandle::new(event_sender, response_receiver, self.vcpu_thread)
+    }
+}
+
 impl VcpuHandle {
     /// Creates a new [`VcpuHandle`].
     ///
@@ -1104,6 +1191,31 @@ pub(crate) mod tests {
         vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
     }
 
+    #[test]
+    fn test_vcpu_slot() {
+        let (_, _, vcpu) = setup_vcpu(0x1000);
+        let mut seccomp_filters = get_empty_filters();
+        let vcpu_filter = seccomp_filters.remove("vcpu").unwrap();
+
+        // A slot that never gets a vcpu stops when it is dropped.
+        let slot = VcpuSlot::start_threaded(1, vcpu_filter.clone()).unwrap();
+        let VcpuSlot {
+            vcpu_sender,
+            vcpu_thread,
+        } = slot;
+        drop(vcpu_sender);
+        vcpu_thread.join().unwrap();
+
+        // The vcpu handed to a slot runs in its thread, starting in the `Paused` state.
+        let slot = VcpuSlot::start_threaded(0, vcpu_filter).unwrap();
+        let barrier = Arc::new(Barrier::new(2));
+        let vcpu_handle = slot.start_vcpu(vcpu, barrier.clone());
+        barrier.wait();
+
+        queue_event_expect_response(&vcpu_handle, VcpuEvent::Pause, VcpuResponse::Paused);
+        vcpu_handle.send_event(VcpuEvent::Finish).unwrap();
+    }
+
     #[test]
     fn test_vcpu_save_state_events() {
         let (_vm, vcpu_handle, _vcpu_exit_evt) = vcpu_configured_for_boot();
@@ -6,8 +6,9 @@
 // found in the THIRD-PARTY file.
 
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard};
@@ -33,10 +34,12 @@ use crate::arch::{GSI_MSI_END, host_page_size};
 use crate::logger::info;
 use crate::persist::CreateSnapshotError;
 use crate::snapshot::Persist;
-use crate::utils::u64_to_usize;
-use crate::vmm_config::snapshot::SnapshotType;
+use crate::snapshot::mem_file::encode_guest_memory;
+use crate::utils::{u64_to_usize, usize_to_u64};
+use crate::vmm_config::snapshot::{MemFileEncoding, SnapshotType};
 use crate::vstate::memory::{
-    Address, GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion, GuestRegionMmap,
+    Address, GuestAddress, GuestMemory, GuestMemoryExtension, GuestMemoryMmap, GuestMemoryRegion,
+    GuestRegionMmap,
 };
 use crate::vstate::resources::ResourceAllocator;
 use crate::vstate::vcpu::VcpuError;
@@ -120,6 +123,28 @@ impl MsiVectorGroup {
         // `Vm::create_msix_group` where the argument for the number of `irq_routes` is a `u16`.
         u16::try_from(self.irq_routes.len()).unwrap()
     }
+
+    /// Releases the interrupts of the group, once the device using them has been removed
+    ///
+    /// The vectors are disabled, their routes are removed from KVM and their GSIs are freed.
+    pub fn release(&self) -> Result<(), InterruptError> {
+        {
+            let mut interrupts = self.vm.common.interrupts.lock().expect("Poisoned lock");
+            for route in &self.irq_routes {
+                route.disable(&self.vm.common.fd)?;
+                interrupts.remove(&route.gsi);
+            }
+        }
+        self.vm.set_gsi_routes()?;
+
+        let gsis = self
+            .irq_routes
+            .iter()
+            .map(|route| route.gsi)
+            .collect::<Vec<_>>();
+        self.vm.resource_allocator().free_gsi_msi(&gsis)?;
+        Ok(())
+    }
 }
 
 impl<'a> Persist<'a> for MsiVectorGroup {
@@ -243,6 +268,8 @@ pub struct VmCommon {
     max_memslots: u32,
     /// The guest memory of this Vm.
     pub guest_memory: GuestMemoryMmap,
+    /// Start address and size of the memory hotplug region, if any.
+    memory_hotplug_region: Option<(GuestAddress, u64)>,
     /// Interrupts used by Vm's devices
     pub interrupts: Mutex<HashMap<u32, RoutingEntry>>,
     /// Allocator for VM resources
@@ -322,6 +349,7 @@ impl Vm {
             fd,
             max_memslots: kvm.max_nr_memslots(),
             guest_memory: GuestMemoryMmap::default(),
+            memory_hotplug_region: None,
             interrupts: Mutex::new(HashMap::with_capacity(GSI_MSI_END as usize + 1)),
             resource_allocator: Mutex::new(ResourceAllocator::new()),
             mmio_bus: Arc::new(vm_device::Bus::new()),
@@ -399,6 +427,35 @@ impl Vm {
         Ok(())
     }
 
+    /// Register the region backing hotpluggable memory to this [`Vm`].
+    pub fn register_hotplug_memory_region(
+        &mut self,
+        region: GuestRegionMmap,
+    ) -> Result<(), VmError> {
+        let hotplug_region = (region.start_addr(), region.len());
+        self.register_memory_region(region)?;
+        self.common.memory_hotplug_region = Some(hotplug_region);
+        Ok(())
+    }
+
+    /// Start address and size of the memory hotplug region, if one was registered.
+    pub fn memory_hotplug_region(&self) -> Option<(GuestAddress, u64)> {
+        self.common.memory_hotplug_region
+    }
+
+    /// Gets the memory the guest boots with, i.e. the guest memory without the hotplug region.
+    pub fn boot_memory(&self) -> GuestMemoryMmap {
+        match self.common.memory_hotplug_region {
+            Some((start, len)) => {
+                self.guest_memory()
+                    .remove_region(start, len)
+                    .expect("Memory hotplug region is registered")
+                    .0
+            }
+            None => self.guest_memory().clone(),
+        }
+    }
+
     /// Gets a reference to the kvm file descriptor owned by this VM.
     pub fn fd(&self) -> &VmFd {
         &self.common.fd
@@ -451,10 +508,14 @@ impl Vm {
     /// If `snapshot_type` is [`SnapshotType::Diff`], and `mem_file_path` exists and is a snapshot
     /// file of matching size, then the diff snapshot will be directly merged into the existing
     /// snapshot. Otherwise, existing files are simply overwritten.
+    ///
+    /// The pages of `free_ranges` which the guest did not access since they were discarded are
+    /// not written.
     pub(crate) fn snapshot_memory_to_file(
         &self,
         mem_file_path: &Path,
         snapshot_type: SnapshotType,
+        free_ranges: &[(GuestAddress, u64)],
     ) -> Result<(), CreateSnapshotError> {
         use self::CreateSnapshotError::*;
 
@@ -497,14 +558,21 @@ impl Vm {
 
         match snapshot_type {
             SnapshotType::Diff => {
-                let dirty_bitmap = self.get_dirty_bitmap()?;
+                let mut dirty_bitmap = self.get_dirty_bitmap()?;
+                self.clear_free_pages(&mut dirty_bitmap, free_ranges)?;
                 self.guest_memory().dump_dirty(&mut file, &dirty_bitmap)?;
             }
-            SnapshotType::Full => {
+            SnapshotType::Full if free_ranges.is_empty() => {
                 self.guest_memory().dump(&mut file)?;
                 self.reset_dirty_bitmap();
                 self.guest_memory().reset_dirty();
             }
+            SnapshotType::Full => {
+                let mut bitmap = self.full_bitmap();
+                self.clear_free_pages(&mut bitmap, free_ranges)?;
+                self.guest_memory().dump_dirty(&mut file, &bitmap)?;
+                self.reset_dirty_bitmap();
+            }
         };
 
         file.flush()
@@ -513,6 +581,111 @@ impl Vm {
             .map_err(|err| MemoryBackingFile("sync_all", err))
     }
 
+    /// Returns a bitmap with all the pages of the guest memory set.
+    fn full_bitmap(&self) -> DirtyBitmap {
+        let page_size = host_page_size();
+        self.guest_memory()
+            .iter()
+            .zip(0u32..)
+            .map(|(region, slot)| {
+                let num_pages = u64_to_usize(region.len()) / page_size;
+                let mut bitmap = vec![u64::MAX; num_pages / 64];
+                if num_pages % 64 != 0 {
+                    bitmap.push((1u64 << (num_pages % 64)) - 1);
+                }
+                (slot, bitmap)
+            })
+            .collect()
+    }
+
+    /// Clears from `bitmap` the pages fully within `free_ranges` which are not resident. Free
+    /// ranges are discarded when the guest gives them away, so a resident page was accessed by the
+    /// guest since, and its contents matter again.
+    fn clear_free_pages(
+        &self,
+        bitmap: &mut DirtyBitmap,
+        free_ranges: &[(GuestAddress, u64)],
+    ) -> Result<(), VmError> {
+        if free_ranges.is_empty() {
+            return Ok(());
+        }
+        let page_size = usize_to_u64(host_page_size());
+        for (region, slot) in self.guest_memory().iter().zip(0u32..) {
+            let region_start = region.start_addr().0;
+            let region_end = region_start + region.len();
+            let mut resident = None;
+            for (addr, len) in free_ranges {
+                let start = addr.0.max(region_start).next_multiple_of(page_size);
+                let end = (addr.0 + len).min(region_end) / page_size * page_size;
+                if start >= end {
+                    continue;
+                }
+                if resident.is_none() {
+                    resident = Some(mincore_bitmap(region)?);
+                }
+                let resident = resident.as_ref().unwrap();
+                let Some(slot_bitmap) = bitmap.get_mut(&slot) else {
+                    continue;
+                };
+                for page in (start - region_start) / page_size..(end - region_start) / page_size {
+                    let (word, bit) = (u64_to_usize(page / 64), page % 64);
+                    if resident[word] & (1 << bit) == 0 {
+                        slot_bitmap[word] &= !(1 << bit);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves the guest memory of the virtual machine to `mem_file_path` as an encoded memory file.
+    ///
+    /// Unlike raw memory files, encoded memory files are rewritten from scratch, which is refused
+    /// when `mem_file_path` is the memory file this very microVM was loaded from.
+    pub(crate) fn snapshot_memory_to_encoded_file(
+        &self,
+        mem_file_path: &Path,
+        encoding: &MemFileEncoding,
+    ) -> Result<(), CreateSnapshotError> {
+        use self::CreateSnapshotError::*;
+
+        if mem_file_path.exists() {
+            let metadata = mem_file_path
+                .metadata()
+                .map_err(|err| MemoryBackingFile("get_metadata", err))?;
+            let backs_guest_memory = |file: &File| {
+                file.metadata().is_ok_and(|file_metadata| {
+                    file_metadata.dev() == metadata.dev() && file_metadata.ino() == metadata.ino()
+                })
+            };
+            if self
+                .guest_memory()
+                .iter()
+                .filter_map(|region| region.file_offset())
+                .any(|file_offset| backs_guest_memory(file_offset.file()))
+            {
+                return Err(MemFileInUse);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(mem_file_path)
+            .map_err(|err| MemoryBackingFile("open", err))?;
+        let file = encode_guest_memory(self.guest_memory(), BufWriter::new(file), encoding)?
+            .into_inner()
+            .map_err(|err| MemoryBackingFile("flush", err.into_error()))?;
+        file.sync_all()
+            .map_err(|err| MemoryBackingFile("sync_all", err))?;
+
+        // Like full snapshots, encoded memory files hold the whole guest memory.
+        self.reset_dirty_bitmap();
+        self.guest_memory().reset_dirty();
+        Ok(())
+    }
+
     /// Register a device IRQ
     pub fn register_irq(&self, fd: &EventFd, gsi: u32) -> Result<(), errno::Error> {
         self.common.fd.register_irqfd(fd, gsi)?;
@@ -679,7 +852,6 @@ impl DeviceRelocation for Vm {
 #[cfg(test)]
 pub(crate) mod tests {
     use vm_device::interrupt::{InterruptSourceConfig, LegacyIrqSourceConfig};
-    use vm_memory::GuestAddress;
     use vm_memory::mmap::MmapRegionBuilder;
 
     use super::*;
@@ -687,8 +859,9 @@ pub(crate) mod tests {
     use crate::snapshot::Snapshot;
     use crate::test_utils::single_region_mem_raw;
     use crate::utils::mib_to_bytes;
+    use crate::vmm_config::machine_config::HugePageConfig;
     use crate::vstate::kvm::Kvm;
-    use crate::vstate::memory::GuestRegionMmap;
+    use crate::vstate::memory::{Bytes, GuestRegionMmap};
 
     // Auxiliary function being used throughout the tests.
     pub(crate) fn setup_vm() -> (Kvm, Vm) {
@@ -730,6 +903,61 @@ pub(crate) mod tests {
         res.unwrap();
     }
 
+    #[test]
+    fn test_clear_free_pages() {
+        let page_size = host_page_size();
+        let (_, vm) = setup_vm_with_memory(16 * page_size);
+        // Only the first 4 pages are resident.
+        for page in 0..4 {
+            vm.guest_memory()
+                .write_obj(1u8, GuestAddress(usize_to_u64(page * page_size)))
+                .unwrap();
+        }
+
+        let mut bitmap = vm.full_bitmap();
+        assert_eq!(bitmap[&0], [0xffff]);
+
+        let page_size = usize_to_u64(page_size);
+        let free_ranges = [
+            // Pages 2 to 5, of which 4 and 5 are not resident.
+            (GuestAddress(2 * page_size), 4 * page_size),
+            // No whole page.
+            (GuestAddress(8 * page_size + 1), page_size),
+        ];
+        vm.clear_free_pages(&mut bitmap, &free_ranges).unwrap();
+        assert_eq!(bitmap[&0], [0xffcf]);
+    }
+
+    #[test]
+    fn test_register_hotplug_memory_region() {
+        let (_, mut vm) = setup_vm_with_memory(mib_to_bytes(128));
+        assert!(vm.memory_hotplug_region().is_none());
+        assert_eq!(vm.boot_memory().num_regions(), 1);
+
+        let hotplug_start = GuestAddress(1 << 30);
+        let region = crate::vstate::memory::anonymous(
+            [(hotplug_start, mib_to_bytes(64))].into_iter(),
+            false,
+            HugePageConfig::None,
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        vm.register_hotplug_memory_region(region).unwrap();
+
+        assert_eq!(
+            vm.memory_hotplug_region(),
+            Some((hotplug_start, mib_to_bytes(64) as u64))
+        );
+        assert_eq!(vm.guest_memory().num_regions(), 2);
+        let boot_memory = vm.boot_memory();
+        assert_eq!(boot_memory.num_regions(), 1);
+        assert_eq!(
+            boot_memory.last_addr(),
+            GuestAddress(mib_to_bytes(128) as u64 - 1)
+        );
+    }
+
     #[test]
     fn test_too_many_regions() {
         let (kvm, mut vm) = setup_vm();
@@ -1001,6 +1229,36 @@ pub(crate) mod tests {
         msix_group.set_gsi().unwrap();
     }
 
+    #[test]
+    fn test_msi_vector_group_release() {
+        let (_, mut vm) = setup_vm_with_memory(mib_to_bytes(128));
+        enable_irqchip(&mut vm);
+        let vm = Arc::new(vm);
+        let msix_group = create_msix_group(&vm);
+        let config = InterruptSourceConfig::MsiIrq(MsiIrqSourceConfig {
+            high_addr: 0x42,
+            low_addr: 0x12,
+            data: 0x12,
+            devid: 0xafa,
+        });
+        for i in 0..4 {
+            msix_group.update(i, config, false, true).unwrap();
+        }
+        assert_eq!(vm.common.interrupts.lock().unwrap().len(), 4);
+
+        msix_group.release().unwrap();
+        for route in &msix_group.irq_routes {
+            assert!(!route.enabled.load(Ordering::Acquire));
+        }
+        assert!(vm.common.interrupts.lock().unwrap().is_empty());
+
+        // The GSIs of the released group can be reused by a new one.
+        let new_group = create_msix_group(&vm);
+        for (route, new_route) in msix_group.irq_routes.iter().zip(&new_group.irq_routes) {
+            assert_eq!(route.gsi, new_route.gsi);
+        }
+    }
+
     #[test]
     fn test_msi_vector_group_persistence() {
         let (_, mut vm) = setup_vm_with_memory(mib_to_bytes(128));
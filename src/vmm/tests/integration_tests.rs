@@ -4,16 +4,20 @@
 #![allow(clippy::cast_possible_truncation, clippy::tests_outside_test_module)]
 
 use std::io::{Seek, SeekFrom};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use vmm::builder::build_and_boot_microvm;
 use vmm::devices::virtio::block::CacheType;
+use vmm::migration::SendMigrationError;
 use vmm::persist::{MicrovmState, MicrovmStateError, VmInfo, snapshot_state_sanity_check};
 use vmm::resources::VmResources;
 use vmm::rpc_interface::{
     LoadSnapshotError, PrebootApiController, RuntimeApiController, VmmAction, VmmActionError,
+    VmmData,
 };
 use vmm::seccomp::get_empty_filters;
 use vmm::snapshot::Snapshot;
@@ -26,12 +30,18 @@ use vmm::vmm_config::boot_source::BootSourceConfig;
 use vmm::vmm_config::drive::BlockDeviceConfig;
 use vmm::vmm_config::instance_info::{InstanceInfo, VmState};
 use vmm::vmm_config::machine_config::{MachineConfig, MachineConfigUpdate};
+use vmm::vmm_config::memory_hotplug::MemoryHotplugConfig;
+use vmm::vmm_config::migration::{
+    MigrationSocketConfig, MigrationSocketType, MigrationStatus, ReceiveMigrationParams,
+    SendMigrationParams,
+};
 use vmm::vmm_config::net::NetworkInterfaceConfig;
 use vmm::vmm_config::snapshot::{
     CreateSnapshotParams, LoadSnapshotParams, MemBackendConfig, MemBackendType, SnapshotType,
 };
 use vmm::vmm_config::vsock::VsockDeviceConfig;
 use vmm::{DumpCpuConfigError, EventManager, FcExitCode, Vmm};
+use vmm_sys_util::tempdir::TempDir;
 use vmm_sys_util::tempfile::TempFile;
 
 #[allow(unused_mut, unused_variables)]
@@ -226,6 +236,7 @@ fn verify_create_snapshot(is_diff: bool, pci_enabled: bool) -> (TempFile, TempFi
         snapshot_type,
         snapshot_path: snapshot_file.as_path().to_path_buf(),
         mem_file_path: memory_file.as_path().to_path_buf(),
+        mem_file_encoding: None,
     };
 
     controller
@@ -292,6 +303,8 @@ fn verify_load_snapshot(snapshot_file: TempFile, memory_file: TempFile) {
             track_dirty_pages: false,
             resume_vm: true,
             network_overrides: vec![],
+            parent_layers: vec![],
+            mem_file_encryption_key: None,
         }))
         .unwrap();
 
@@ -345,6 +358,190 @@ fn get_microvm_state_from_snapshot(pci_enabled: bool) -> MicrovmState {
     Snapshot::load(&mut snapshot_file.as_file()).unwrap().data
 }
 
+fn receive_migration(socket: MigrationSocketConfig) {
+    let mut event_manager = EventManager::new().unwrap();
+    let empty_seccomp_filters = get_empty_filters();
+    let mut vm_resources = VmResources::default();
+
+    let mut preboot_api_controller = PrebootApiController::new(
+        &empty_seccomp_filters,
+        InstanceInfo::default(),
+        &mut vm_resources,
+        &mut event_manager,
+    );
+
+    preboot_api_controller
+        .handle_preboot_request(VmmAction::ReceiveMigration(ReceiveMigrationParams {
+            socket,
+            track_dirty_pages: true,
+            resume_vm: true,
+        }))
+        .unwrap();
+
+    let vmm = preboot_api_controller.built_vmm.take().unwrap();
+    assert_eq!(vmm.lock().unwrap().instance_info.state, VmState::Running);
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+}
+
+/// Runs the event loop of the source microVM until the live migration ends.
+fn wait_migration_end(
+    event_manager: &mut EventManager,
+    controller: &mut RuntimeApiController,
+) -> MigrationStatus {
+    loop {
+        event_manager.run_with_timeout(100).unwrap();
+        match controller.handle_request(VmmAction::GetMigrationStatus) {
+            Ok(VmmData::MigrationStatus(MigrationStatus::InProgress)) => {}
+            Ok(VmmData::MigrationStatus(status)) => return status,
+            res => panic!("Unexpected migration status: {:?}", res),
+        }
+    }
+}
+
+#[test]
+fn test_live_migration() {
+    let tmp_dir = TempDir::new().unwrap();
+    let socket = MigrationSocketConfig {
+        socket_type: MigrationSocketType::Unix,
+        address: tmp_dir
+            .as_path()
+            .join("migration.sock")
+            .to_str()
+            .unwrap()
+            .to_string(),
+    };
+
+    let receiver_socket = socket.clone();
+    let receiver = thread::spawn(move || receive_migration(receiver_socket));
+
+    let (vmm, mut event_manager) = create_vmm(Some(NOISY_KERNEL_IMAGE), true, true, false);
+    let resources = VmResources {
+        machine_config: MachineConfig {
+            mem_size_mib: 1,
+            track_dirty_pages: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut controller = RuntimeApiController::new(resources, vmm.clone());
+
+    // Be sure that the microVM is running.
+    thread::sleep(Duration::from_millis(200));
+
+    // Wait for the destination to listen.
+    while !Path::new(&socket.address).exists() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    controller
+        .handle_request(VmmAction::SendMigration(SendMigrationParams {
+            socket,
+            max_iterations: 2,
+            dirty_threshold_mib: 0,
+        }))
+        .unwrap();
+    assert_eq!(
+        wait_migration_end(&mut event_manager, &mut controller),
+        MigrationStatus::Completed
+    );
+
+    // The source microVM is left paused once the destination took over.
+    assert_eq!(vmm.lock().unwrap().instance_info.state, VmState::Paused);
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+    receiver.join().unwrap();
+}
+
+#[test]
+fn test_live_migration_requires_running_destination() {
+    let tmp_dir = TempDir::new().unwrap();
+    let (vmm, _) = create_vmm(Some(NOISY_KERNEL_IMAGE), true, true, false);
+    let mut controller = RuntimeApiController::new(VmResources::default(), vmm.clone());
+
+    let res = controller.handle_request(VmmAction::SendMigration(SendMigrationParams {
+        socket: MigrationSocketConfig {
+            socket_type: MigrationSocketType::Unix,
+            address: tmp_dir
+                .as_path()
+                .join("migration.sock")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        },
+        max_iterations: 2,
+        dirty_threshold_mib: 0,
+    }));
+    assert!(
+        matches!(
+            res,
+            Err(VmmActionError::SendMigration(SendMigrationError::Connect(
+                _
+            )))
+        ),
+        "{:?}",
+        res
+    );
+
+    // The microVM keeps running when the migration cannot start.
+    assert_eq!(vmm.lock().unwrap().instance_info.state, VmState::Running);
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+}
+
+#[test]
+fn test_live_migration_keeps_api_responsive() {
+    let tmp_dir = TempDir::new().unwrap();
+    let params = || SendMigrationParams {
+        socket: MigrationSocketConfig {
+            socket_type: MigrationSocketType::Unix,
+            address: tmp_dir
+                .as_path()
+                .join("migration.sock")
+                .to_str()
+                .unwrap()
+                .to_string(),
+        },
+        max_iterations: 2,
+        dirty_threshold_mib: 0,
+    };
+    // The destination never reads from the stream, so the guest memory cannot be sent.
+    let listener = UnixListener::bind(&params().socket.address).unwrap();
+
+    let (vmm, mut event_manager) = create_vmm(Some(NOISY_KERNEL_IMAGE), true, true, false);
+    let mut controller = RuntimeApiController::new(VmResources::default(), vmm.clone());
+    controller
+        .handle_request(VmmAction::SendMigration(params()))
+        .unwrap();
+    let (destination, _) = listener.accept().unwrap();
+
+    // The API keeps answering while the guest memory is being sent.
+    match controller.handle_request(VmmAction::GetVmInstanceInfo) {
+        Ok(VmmData::InstanceInformation(info)) => assert_eq!(info.state, VmState::Running),
+        res => panic!("Unexpected instance info: {:?}", res),
+    }
+    assert!(matches!(
+        controller.handle_request(VmmAction::GetMigrationStatus),
+        Ok(VmmData::MigrationStatus(MigrationStatus::InProgress))
+    ));
+    let res = controller.handle_request(VmmAction::SendMigration(params()));
+    assert!(
+        matches!(
+            res,
+            Err(VmmActionError::SendMigration(
+                SendMigrationError::InProgress
+            ))
+        ),
+        "{:?}",
+        res
+    );
+
+    // The migration fails once the destination goes away, and the microVM keeps running.
+    drop(destination);
+    assert!(matches!(
+        wait_migration_end(&mut event_manager, &mut controller),
+        MigrationStatus::Failed { .. }
+    ));
+    assert_eq!(vmm.lock().unwrap().instance_info.state, VmState::Running);
+    vmm.lock().unwrap().stop(FcExitCode::Ok);
+}
+
 fn verify_load_snap_disallowed_after_boot_resources(res: VmmAction, res_name: &str) {
     let (snapshot_file, memory_file) = verify_create_snapshot(false, false);
 
@@ -371,6 +568,8 @@ fn verify_load_snap_disallowed_after_boot_resources(res: VmmAction, res_name: &s
         track_dirty_pages: false,
         resume_vm: false,
         network_overrides: vec![],
+        parent_layers: vec![],
+        mem_file_encryption_key: None,
     });
     let err = preboot_api_controller.handle_preboot_request(req);
     assert!(
@@ -403,7 +602,10 @@ fn test_preboot_load_snap_disallowed_after_boot_resources() {
         is_read_only: Some(false),
         path_on_host: Some(tmp_file),
         rate_limiter: None,
+        rate_limiter_group: None,
         file_engine_type: None,
+        discard: None,
+        format: None,
 
         socket: None,
     };
@@ -417,16 +619,26 @@ fn test_preboot_load_snap_disallowed_after_boot_resources() {
         guest_mac: None,
         rx_rate_limiter: None,
         tx_rate_limiter: None,
+        rx_rate_limiter_group: None,
+        tx_rate_limiter_group: None,
+        num_queues: None,
     });
     verify_load_snap_disallowed_after_boot_resources(req, "InsertNetworkDevice");
 
     let req = VmmAction::SetBalloonDevice(BalloonDeviceConfig::default());
     verify_load_snap_disallowed_after_boot_resources(req, "SetBalloonDevice");
 
+    let req = VmmAction::SetMemoryHotplugDevice(MemoryHotplugConfig {
+        total_size_mib: 1024,
+        block_size_mib: 2,
+    });
+    verify_load_snap_disallowed_after_boot_resources(req, "SetMemoryHotplugDevice");
+
     let req = VmmAction::SetVsockDevice(VsockDeviceConfig {
         vsock_id: Some(String::new()),
         guest_cid: 0,
         uds_path: String::new(),
+        port_forwards: Default::default(),
     });
     verify_load_snap_disallowed_after_boot_resources(req, "SetVsockDevice");
 
@@ -69,12 +69,15 @@ def get_example(name, *args, package="firecracker", **kwargs):
     return get_binary(package, *args, **kwargs, example=name)
 
 
-def run_seccompiler_bin(bpf_path, json_path=defs.SECCOMP_JSON_DIR, basic=False):
+def run_seccompiler_bin(
+    bpf_path, json_path=defs.SECCOMP_JSON_DIR, basic=False, learning=False
+):
     """
     Run seccompiler-bin.
 
     :param bpf_path: path to the output file
     :param json_path: optional path to json file
+    :param learning: compile the filters for the seccomp learning mode
     """
     # If no custom json filter, use the default one for the current target.
     if json_path == defs.SECCOMP_JSON_DIR:
@@ -85,10 +88,26 @@ def run_seccompiler_bin(bpf_path, json_path=defs.SECCOMP_JSON_DIR, basic=False):
     if basic:
         seccompiler_args += " --basic"
 
+    if learning:
+        seccompiler_args += " --learning"
+
     seccompiler = get_binary("seccompiler-bin")
     utils.check_output(f"{seccompiler} {seccompiler_args}")
 
 
+def run_seccompiler_learn(json_path, log_path):
+    """
+    Run `seccompiler-bin learn`, and return the generated rules.
+
+    :param json_path: path to the json file the log was recorded with
+    :param log_path: path to the seccomp learning log
+    """
+    seccompiler = get_binary("seccompiler-bin")
+    return utils.check_output(
+        f"{seccompiler} learn --input-file {json_path} --log-file {log_path}"
+    ).stdout
+
+
 def run_snap_editor_rebase(base_snap, diff_snap):
     """
     Run apply_diff_snap.
@@ -82,6 +82,8 @@ def validate_fc_metrics(metrics):
         "execute_fails",
         "invalid_reqs_count",
         "flush_count",
+        "discard_count",
+        "write_zeroes_count",
         "queue_event_count",
         "rate_limiter_event_count",
         "update_count",
@@ -127,6 +129,14 @@ def validate_fc_metrics(metrics):
         "tx_remaining_reqs_count",
         {"tap_write_agg": latency_agg_metrics_fields},
     ]
+    net_queue_metrics = [
+        "rx_bytes_count",
+        "rx_packets_count",
+        "rx_rate_limiter_throttled",
+        "tx_bytes_count",
+        "tx_packets_count",
+        "tx_rate_limiter_throttled",
+    ]
     firecracker_metrics = {
         "utc_timestamp_ms": "",
         "api_server": [
@@ -142,6 +152,14 @@ def validate_fc_metrics(metrics):
             "stats_update_fails",
             "deflate_count",
             "event_fails",
+            "free_page_hint_count",
+            "free_page_hint_bytes_count",
+            "free_page_hint_fails",
+            "free_page_report_count",
+            "free_page_report_bytes_count",
+            "free_page_report_fails",
+            "policy_inflate_count",
+            "policy_deflate_count",
         ],
         "block": block_metrics,
         "deprecated_api": [
@@ -151,6 +169,7 @@ def validate_fc_metrics(metrics):
         "get_api_requests": [
             "instance_info_count",
             "machine_cfg_count",
+            "metrics_count",
             "mmds_count",
             "vmm_version_count",
         ],
@@ -205,6 +224,8 @@ def validate_fc_metrics(metrics):
             "machine_cfg_fails",
             "mmds_count",
             "mmds_fails",
+            "rate_limiter_group_count",
+            "rate_limiter_group_fails",
         ],
         "put_api_requests": [
             "actions_count",
@@ -229,6 +250,8 @@ def validate_fc_metrics(metrics):
             "vsock_fails",
             "serial_count",
             "serial_fails",
+            "rate_limiter_group_count",
+            "rate_limiter_group_fails",
         ],
         "seccomp": [
             "num_faults",
@@ -297,6 +320,17 @@ def validate_fc_metrics(metrics):
             "entropy_rate_limiter_throttled",
             "rate_limiter_event_count",
         ],
+        "memory_hotplug": [
+            "activate_fails",
+            "event_fails",
+            "queue_event_count",
+            "plug_count",
+            "plug_fails",
+            "unplug_count",
+            "unplug_fails",
+            "unplug_all_count",
+            "state_count",
+        ],
     }
 
     # validate timestamp before jsonschema validation which some more time
@@ -330,8 +364,17 @@ def validate_fc_metrics(metrics):
             vhost_user_devices.append(metrics_name)
         if metrics_name.startswith("block_"):
             firecracker_metrics[metrics_name] = block_metrics
+        if metrics_name.startswith("rate_limiter_group_"):
+            firecracker_metrics[metrics_name] = [
+                "bytes_count",
+                "ops_count",
+                "throttled_count",
+            ]
         if metrics_name.startswith("net_"):
-            firecracker_metrics[metrics_name] = net_metrics
+            if "_queue" in metrics_name:
+                firecracker_metrics[metrics_name] = net_queue_metrics
+            else:
+                firecracker_metrics[metrics_name] = net_metrics
 
     firecracker_metrics_schema = create_metrics_schema_objects(firecracker_metrics)
 
@@ -296,6 +296,31 @@ def test_api_mmds_config(uvm_plain):
     test_microvm.api.mmds_config.put(**mmds_config)
     assert test_microvm.api.vm_config.get().json()["mmds-config"]["version"] == "V2"
 
+    # Invalid MMDS IPv6 address.
+    err_msg = "The MMDS IPv6 address is neither link local nor unique local."
+    with pytest.raises(RuntimeError, match=err_msg):
+        test_microvm.api.mmds_config.put(
+            ipv6_address="2001:db8::1", network_interfaces=["1"]
+        )
+
+    # Invalid MMDS policy path.
+    err_msg = (
+        "Invalid MMDS policy: The MMDS policy path `status` is not a JSON pointer "
+        "to a subtree of the data store."
+    )
+    with pytest.raises(RuntimeError, match=err_msg):
+        test_microvm.api.mmds_config.put(
+            policy={"status": "guest_writable"}, network_interfaces=["1"]
+        )
+
+    # Valid MMDS config specifying an IPv6 address.
+    mmds_config = {"ipv6_address": "fe80::a9fe:a9fe", "network_interfaces": ["1"]}
+    test_microvm.api.mmds_config.put(**mmds_config)
+    assert (
+        test_microvm.api.vm_config.get().json()["mmds-config"]["ipv6_address"]
+        == "fe80::a9fe:a9fe"
+    )
+
 
 # pylint: disable=too-many-statements
 def test_api_machine_config(uvm_plain):
@@ -865,7 +890,10 @@ def _drive_patch(test_microvm, io_engine):
             "is_read_only": True,
             "path_on_host": "/" + test_microvm.rootfs_file.name,
             "rate_limiter": None,
+            "rate_limiter_group": None,
             "io_engine": "Sync",
+            "discard": None,
+            "format": "Raw",
             "socket": None,
         },
         {
@@ -879,7 +907,10 @@ def _drive_patch(test_microvm, io_engine):
                 "bandwidth": {"size": 5000, "one_time_burst": None, "refill_time": 100},
                 "ops": {"size": 500, "one_time_burst": None, "refill_time": 100},
             },
+            "rate_limiter_group": None,
             "io_engine": io_engine,
+            "discard": None,
+            "format": "Raw",
             "socket": None,
         },
         {
@@ -890,7 +921,10 @@ def _drive_patch(test_microvm, io_engine):
             "is_read_only": None,
             "path_on_host": None,
             "rate_limiter": None,
+            "rate_limiter_group": None,
             "io_engine": None,
+            "discard": None,
+            "format": None,
             "socket": str(
                 Path("/")
                 / test_microvm.disks_vhost_user["scratch_vub"].socket_path.name
@@ -952,6 +986,26 @@ def test_api_vsock(uvm_nano):
     response = vm.api.vsock.put(vsock_id="vsock1", guest_cid=166, uds_path="vsock.sock")
     assert response.headers["deprecation"]
 
+    # Port forwarding rules must be valid.
+    port_forwards = {
+        "guest_to_host": [{"vsock_port": 52, "host_addr": "127.0.0.1:8080"}],
+        "host_to_guest": [{"host_addr": "127.0.0.1:2222", "vsock_port": 22}],
+    }
+    vm.api.vsock.put(guest_cid=15, uds_path="vsock.sock", port_forwards=port_forwards)
+    with pytest.raises(RuntimeError, match="is forwarded more than once"):
+        vm.api.vsock.put(
+            guest_cid=15,
+            uds_path="vsock.sock",
+            port_forwards={"guest_to_host": port_forwards["guest_to_host"] * 2},
+        )
+    with pytest.raises(RuntimeError):
+        vm.api.vsock.put(
+            guest_cid=15,
+            uds_path="vsock.sock",
+            port_forwards={"host_to_guest": [{"host_addr": "127.0.0.1"}]},
+        )
+    vm.api.vsock.put(guest_cid=166, uds_path="vsock.sock")
+
     # No other vsock action is allowed after booting the VM.
     vm.start()
 
@@ -1080,7 +1134,10 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
             "is_read_only": True,
             "path_on_host": f"/{uvm_nano.rootfs_file.name}",
             "rate_limiter": None,
+            "rate_limiter_group": None,
             "io_engine": "Sync",
+            "discard": None,
+            "format": "Raw",
             "socket": None,
         }
     ]
@@ -1097,6 +1154,7 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
     uvm_nano.api.vsock.put(guest_cid=15, uds_path="vsock.sock")
     setup_cfg["vsock"] = {"guest_cid": 15, "uds_path": "vsock.sock"}
 
+    setup_cfg["rate-limiter-groups"] = []
     setup_cfg["logger"] = None
     setup_cfg["metrics"] = None
     setup_cfg["mmds-config"] = {
@@ -1123,8 +1181,11 @@ def test_get_full_config_after_restoring_snapshot(microvm_factory, uvm_nano):
             "guest_mac": net_tools.mac_from_ip(net_iface.guest_ip),
             "iface_id": net_iface.dev_name,
             "host_dev_name": net_iface.tap_name,
+            "num_queues": 1,
             "rx_rate_limiter": None,
             "tx_rate_limiter": tx_rl,
+            "rx_rate_limiter_group": None,
+            "tx_rate_limiter_group": None,
         }
     ]
 
@@ -1191,7 +1252,10 @@ def test_get_full_config(uvm_plain):
             "is_read_only": True,
             "path_on_host": "/" + test_microvm.rootfs_file.name,
             "rate_limiter": None,
+            "rate_limiter_group": None,
             "io_engine": "Sync",
+            "discard": None,
+            "format": "Raw",
             "socket": None,
         }
     ]
@@ -1228,8 +1292,11 @@ def test_get_full_config(uvm_plain):
             "iface_id": iface_id,
             "host_dev_name": tap1.name,
             "guest_mac": "06:00:00:00:00:01",
+            "num_queues": 1,
             "rx_rate_limiter": None,
             "tx_rate_limiter": tx_rl,
+            "rx_rate_limiter_group": None,
+            "tx_rate_limiter_group": None,
         }
     ]
 
@@ -1242,6 +1309,7 @@ def test_get_full_config(uvm_plain):
     }
     response = test_microvm.api.mmds_config.put(**mmds_config)
 
+    expected_cfg["rate-limiter-groups"] = []
     expected_cfg["logger"] = None
     expected_cfg["metrics"] = None
     expected_cfg["mmds-config"] = {
@@ -397,6 +397,9 @@ def test_mmds_dummy(uvm_plain, version):
 def test_guest_mmds_hang(uvm_plain, version):
     """
     Test the MMDS json endpoint when Content-Length larger than actual length.
+
+    MMDS waits for the rest of the body, so the request times out on the guest
+    side, but MMDS keeps serving the following requests.
     """
     test_microvm = uvm_plain
     test_microvm.spawn()
@@ -415,35 +418,29 @@ def test_guest_mmds_hang(uvm_plain, version):
 
     run_guest_cmd(ssh_connection, f"ip route add {DEFAULT_IPV4} dev eth0", "")
 
-    get_cmd = "curl -m 2 -s"
-    get_cmd += " -X GET"
-    get_cmd += ' -H  "Content-Length: 100"'
-    get_cmd += ' -H "Accept: application/json"'
-    get_cmd += ' -d "some body"'
-    get_cmd += f" http://{DEFAULT_IPV4}/"
-
-    if version == "V1":
-        _, stdout, _ = ssh_connection.run(get_cmd)
-        assert "Invalid request" in stdout
-    else:
+    token = None
+    if version == "V2":
         # Generate token.
         token = generate_mmds_session_token(ssh_connection, DEFAULT_IPV4, token_ttl=60)
 
-        get_cmd += ' -H  "X-metadata-token: {}"'.format(token)
-        _, stdout, _ = ssh_connection.run(get_cmd)
-        assert "Invalid request" in stdout
-
-        # Do the same for a PUT request.
+    for method in ["GET", "PUT"]:
         cmd = "curl -m 2 -s"
-        cmd += " -X PUT"
+        cmd += f" -X {method}"
         cmd += ' -H  "Content-Length: 100"'
-        cmd += ' -H  "X-metadata-token: {}"'.format(token)
+        if token is not None:
+            cmd += f' -H  "X-metadata-token: {token}"'
         cmd += ' -H "Accept: application/json"'
         cmd += ' -d "some body"'
-        cmd += " http://{}/".format(DEFAULT_IPV4)
+        cmd += f" http://{DEFAULT_IPV4}/"
+
+        # curl exits with 28 when the operation times out.
+        rc, stdout, _ = ssh_connection.run(cmd)
+        assert rc == 28
+        assert stdout == ""
 
-        _, stdout, _ = ssh_connection.run(cmd)
-        assert "Invalid request" in stdout
+        # MMDS still serves requests.
+        cmd = generate_mmds_get_request(DEFAULT_IPV4, token=token)
+        run_guest_cmd(ssh_connection, cmd, data_store, use_json=True)
 
 
 @pytest.mark.parametrize("version", MMDS_VERSIONS)
@@ -717,6 +714,142 @@ def test_mmds_v2_negative(uvm_plain):
     )
 
 
+@pytest.mark.parametrize("version", MMDS_VERSIONS)
+def test_mmds_policy(uvm_plain, version):
+    """
+    Test the per-path access policy and the guest writable paths of MMDS.
+    """
+    test_microvm = uvm_plain
+    test_microvm.spawn()
+
+    test_microvm.add_net_iface()
+    test_microvm.api.mmds_config.put(
+        network_interfaces=["eth0"],
+        version=version,
+        policy={
+            "/latest/secrets": "token_required",
+            "/latest/internal": "hidden",
+            "/latest/status": "guest_writable",
+        },
+    )
+    data_store = {
+        "latest": {
+            "meta-data": {"ami-id": "ami-12345678"},
+            "secrets": {"key": "value"},
+            "internal": {"key": "value"},
+        }
+    }
+    populate_data_store(test_microvm, data_store)
+
+    test_microvm.basic_config(vcpu_count=1)
+    test_microvm.start()
+    ssh_connection = test_microvm.ssh
+
+    run_guest_cmd(ssh_connection, f"ip route add {DEFAULT_IPV4} dev eth0", "")
+
+    token = generate_mmds_session_token(ssh_connection, DEFAULT_IPV4, token_ttl=60)
+    get_cmd = generate_mmds_get_request(DEFAULT_IPV4, app_json=False)
+    get_with_token_cmd = generate_mmds_get_request(
+        DEFAULT_IPV4, token=token, app_json=False
+    )
+
+    # Hidden and token required paths are not listed without a token.
+    if version == "V1":
+        run_guest_cmd(ssh_connection, get_cmd + "latest", "meta-data/")
+        run_guest_cmd(
+            ssh_connection,
+            get_cmd + "latest/secrets/key",
+            "The MMDS resource requires a valid session token.",
+        )
+    run_guest_cmd(ssh_connection, get_with_token_cmd + "latest", "meta-data/\nsecrets/")
+    run_guest_cmd(ssh_connection, get_with_token_cmd + "latest/secrets/key", "value")
+    run_guest_cmd(
+        ssh_connection,
+        get_with_token_cmd + "latest/internal/key",
+        "Resource not found: /latest/internal/key.",
+    )
+
+    # The guest can only write to the guest writable paths.
+    write_cmd = "curl -m 2 -s"
+    write_cmd += " -X {}"
+    write_cmd += f' -H "X-metadata-token: {token}"'
+    write_cmd += " -H \"Content-Type: application/json\""
+    write_cmd += " -d '{}'"
+    write_cmd += f" http://{DEFAULT_IPV4}/"
+    run_guest_cmd(
+        ssh_connection,
+        write_cmd.format("PUT", '{"ready": false}') + "latest/status",
+        "",
+    )
+    run_guest_cmd(
+        ssh_connection,
+        write_cmd.format("PATCH", '{"ready": true}') + "latest/status",
+        "",
+    )
+    run_guest_cmd(
+        ssh_connection,
+        write_cmd.format("PUT", '"value"') + "latest/meta-data/ami-id",
+        "Resource not found: /latest/meta-data/ami-id.",
+    )
+
+    # Guest writes show up in the data store.
+    data_store["latest"]["status"] = {"ready": True}
+    assert test_microvm.api.mmds.get().json() == data_store
+
+
+@pytest.mark.parametrize("version", MMDS_VERSIONS)
+def test_mmds_wait(uvm_plain, version):
+    """
+    Test that guest requests can wait for the MMDS data store contents to change.
+    """
+    test_microvm = uvm_plain
+    test_microvm.spawn()
+
+    test_microvm.add_net_iface()
+    configure_mmds(test_microvm, iface_ids=["eth0"], version=version)
+    populate_data_store(test_microvm, {"latest": {"status": "pending"}})
+
+    test_microvm.basic_config(vcpu_count=1)
+    test_microvm.start()
+    ssh_connection = test_microvm.ssh
+
+    run_guest_cmd(ssh_connection, f"ip route add {DEFAULT_IPV4} dev eth0", "")
+
+    token = generate_mmds_session_token(ssh_connection, DEFAULT_IPV4, token_ttl=60)
+    get_cmd = f'curl -m 10 -s -H "X-metadata-token: {token}"'
+    get_cmd += f' "http://{DEFAULT_IPV4}/latest/status'
+
+    # The version of the data store contents is returned in the `ETag` header.
+    _, stdout, _ = ssh_connection.check_output(get_cmd + '" -i')
+    assert 'ETag: "1"' in stdout
+
+    # A waiting request is answered with the current contents when its timeout
+    # expires.
+    start = time.time()
+    run_guest_cmd(
+        ssh_connection, get_cmd + '?wait=true&version=1&timeout=1"', "pending"
+    )
+    assert time.time() - start >= 1
+
+    # A waiting request is answered as soon as the contents change.
+    ssh_connection.check_output(
+        f'nohup {get_cmd}?wait=true&version=1" > /tmp/mmds_wait 2>&1 &'
+    )
+    time.sleep(1)
+    run_guest_cmd(ssh_connection, "cat /tmp/mmds_wait", "")
+    test_microvm.api.mmds.patch(latest={"status": "ready"})
+    time.sleep(1)
+    run_guest_cmd(ssh_connection, "cat /tmp/mmds_wait", "ready")
+
+    # Requests for a different version are answered right away.
+    run_guest_cmd(ssh_connection, get_cmd + '?wait=true&version=1"', "ready")
+    run_guest_cmd(
+        ssh_connection,
+        get_cmd + '?wait=true"',
+        "The `wait` query parameter requires a `version` query parameter.",
+    )
+
+
 def test_deprecated_mmds_config(uvm_plain):
     """
     Test deprecated Mmds configs.
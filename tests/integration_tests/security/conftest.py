@@ -8,7 +8,7 @@ from pathlib import Path
 
 import pytest
 
-from host_tools.cargo_build import run_seccompiler_bin
+from host_tools.cargo_build import run_seccompiler_bin, run_seccompiler_learn
 
 
 @pytest.fixture()
@@ -18,12 +18,20 @@ def seccompiler(tmp_path):
     class Seccompiler:
         "A seccompiler helper class"
 
-        def compile(self, data: dict, basic=False) -> Path:
+        def compile(self, data: dict, basic=False, learning=False) -> Path:
             "Use seccompiler-bin to compile a filter from a dict"
             inp = tmp_path / "input.json"
             inp.write_text(json.dumps(data))
             bpf = tmp_path / "output.bpfmap"
-            run_seccompiler_bin(bpf_path=bpf, json_path=inp, basic=basic)
+            run_seccompiler_bin(
+                bpf_path=bpf, json_path=inp, basic=basic, learning=learning
+            )
             return bpf
 
+        def learn(self, data: dict, log_path: Path) -> dict:
+            "Use seccompiler-bin to generate the rules missing from a filter dict"
+            inp = tmp_path / "input.json"
+            inp.write_text(json.dumps(data))
+            return json.loads(run_seccompiler_learn(json_path=inp, log_path=log_path))
+
     return Seccompiler()
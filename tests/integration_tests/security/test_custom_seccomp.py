@@ -2,13 +2,16 @@
 # SPDX-License-Identifier: Apache-2.0
 """Tests that the --seccomp-filter parameter works as expected."""
 
+import json
 import platform
 import time
 from pathlib import Path
 
 import requests
+from tenacity import Retrying, stop_after_attempt, wait_fixed
 
-from framework import utils
+from framework import defs, utils
+from host_tools.cargo_build import DEFAULT_TARGET
 
 
 def install_filter(microvm, bpf_path):
@@ -104,6 +107,62 @@ def test_failing_filter(uvm_plain, seccompiler):
     test_microvm.mark_killed()
 
 
+def test_learning_mode(uvm_plain, seccompiler):
+    """Test --seccomp-learning-log, recording the syscalls a filter doesn't allow."""
+    json_path = defs.SECCOMP_JSON_DIR / f"{DEFAULT_TARGET}.json"
+    seccomp_filter = json.loads(json_path.read_text())
+
+    # Drop the rule allowing KVM_RUN from the vcpu filter.
+    kvm_run = 0xAE80
+    seccomp_filter["vcpu"]["filter"] = [
+        rule
+        for rule in seccomp_filter["vcpu"]["filter"]
+        if not (
+            rule["syscall"] == "ioctl"
+            and any(arg["val"] == kvm_run for arg in rule.get("args", []))
+        )
+    ]
+
+    bpf_path = seccompiler.compile(seccomp_filter, learning=True)
+    test_microvm = uvm_plain
+    install_filter(test_microvm, bpf_path)
+    log_path = Path(test_microvm.path) / "seccomp_learning.log"
+    log_path.touch()
+    test_microvm.create_jailed_resource(log_path)
+    test_microvm.jailer.extra_args.update({"seccomp-learning-log": log_path.name})
+    test_microvm.spawn()
+    test_microvm.basic_config()
+    # The vCPUs run, since the syscalls which are not allowed are only recorded.
+    test_microvm.start()
+
+    # The syscalls are recorded asynchronously.
+    ioctl_num = 16 if platform.machine() == "x86_64" else 29
+    jailed_log_path = Path(test_microvm.chroot()) / log_path.name
+    for attempt in Retrying(
+        stop=stop_after_attempt(10),
+        wait=wait_fixed(0.1),
+        reraise=True,
+    ):
+        with attempt:
+            records = [
+                json.loads(line) for line in jailed_log_path.read_text().splitlines()
+            ]
+            assert any(
+                record["thread"] == "vcpu"
+                and record["nr"] == ioctl_num
+                and record["args"][1] & 0xFFFFFFFF == kvm_run
+                for record in records
+            )
+
+    # The missing rule only allows KVM_RUN, like the other ioctl rules of the filter.
+    diff = seccompiler.learn(seccomp_filter, jailed_log_path)
+    assert {
+        "syscall": "ioctl",
+        "args": [{"index": 1, "op": "eq", "val": kvm_run, "type": "dword"}],
+        "comment": "Recorded in seccomp learning mode",
+    } in diff["vcpu"]["filter"]
+
+
 def test_invalid_bpf(uvm_plain):
     """Test that FC does not start, given an invalid binary filter."""
     test_microvm = uvm_plain
@@ -38,6 +38,7 @@ files_to_change=(
     "$FC_ROOT_DIR/src/seccompiler/Cargo.toml"
     "$FC_ROOT_DIR/src/cpu-template-helper/Cargo.toml"
     "$FC_ROOT_DIR/src/snapshot-editor/Cargo.toml"
+    "$FC_ROOT_DIR/src/uffd-handler/Cargo.toml"
 )
 say "Updating source files:"
 for file in "${files_to_change[@]}"; do